@@ -0,0 +1,44 @@
+//! Synthetic datasets shared by the benchmark suite.
+
+use shared::TradeRecord;
+
+/// Number of trades in the large synthetic dataset.
+pub const LARGE_DATASET_TRADES: usize = 1_000_000;
+
+/// Symbols trades are spread across, weighted towards the first.
+const SYMBOLS: [&str; 4] = ["ES.FUT", "ES.FUT", "NQ.FUT", "CL.FUT"];
+
+/// Generate `count` trades spanning roughly one trading session.
+///
+/// The generator is a seeded xorshift random walk, so every run benchmarks
+/// the exact same data. Timestamps advance by 1-40ms per trade, and prices
+/// move in whole 0.25 ticks around 5000.00.
+pub fn synthetic_trades(count: usize, seed: u64) -> Vec<TradeRecord> {
+    let mut state = seed.max(1);
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    const TICK: i64 = 250_000_000; // 0.25 in fixed-point 1e9
+    let mut ts: u64 = 1_704_096_000_000_000_000; // 2024-01-01T08:00:00Z
+    let mut price: i64 = 5_000_000_000_000;
+
+    (0..count)
+        .map(|_| {
+            let r = next();
+            ts += 1_000_000 + (r % 40) * 1_000_000;
+            price += ((r >> 8) % 5) as i64 * TICK - 2 * TICK;
+            TradeRecord {
+                ts_event_unix_ns: ts,
+                symbol: SYMBOLS[(r >> 16) as usize % SYMBOLS.len()].to_string(),
+                price_i64: price,
+                size_u32: 1 + ((r >> 24) % 50) as u32,
+                quote: None,
+                sequence_u32: None,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,166 @@
+//! Benchmarks for the data pipeline hot paths.
+//!
+//! Covers bar aggregation, LiveMessage serialization, historical response
+//! serialization per schema in both wire modes, downsampling, and mock data
+//! generation. Run with `cargo bench -p backend`.
+
+mod common;
+
+use backend::mock_service::MockService;
+use backend::service::MarketDataService;
+use common::{synthetic_trades, LARGE_DATASET_TRADES};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use shared::aggregate::{aggregate_trades, downsample_bars, NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use shared::{compact, HistoricalRequest, HistoricalResponse, LiveMessage, SymbolType};
+
+fn bench_aggregation(c: &mut Criterion) {
+    let trades = synthetic_trades(LARGE_DATASET_TRADES, 42);
+
+    let mut group = c.benchmark_group("aggregation");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(trades.len() as u64));
+    for (name, interval_ns) in [("1s", NANOS_PER_SECOND), ("1m", NANOS_PER_MINUTE)] {
+        group.bench_with_input(
+            BenchmarkId::new("trades_to_bars", name),
+            &interval_ns,
+            |b, &i| b.iter(|| aggregate_trades(black_box(&trades), i)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_serialization(c: &mut Criterion) {
+    let messages: Vec<LiveMessage> = synthetic_trades(10_000, 7)
+        .into_iter()
+        .map(|t| LiveMessage::Trade {
+            ts_event_unix_ns: t.ts_event_unix_ns,
+            symbol: t.symbol,
+            price_i64: t.price_i64,
+            size_u32: t.size_u32,
+        })
+        .collect();
+    let encoded: Vec<String> = messages
+        .iter()
+        .map(|m| serde_json::to_string(m).unwrap())
+        .collect();
+
+    let mut group = c.benchmark_group("live_message");
+    group.throughput(Throughput::Elements(messages.len() as u64));
+    group.bench_function("serialize", |b| {
+        b.iter(|| {
+            for msg in &messages {
+                black_box(serde_json::to_string(msg).unwrap());
+            }
+        })
+    });
+    group.bench_function("serialize_compact", |b| {
+        b.iter(|| {
+            for msg in &messages {
+                black_box(compact::live_to_string(msg).unwrap());
+            }
+        })
+    });
+    group.bench_function("deserialize", |b| {
+        b.iter(|| {
+            for json in &encoded {
+                black_box(serde_json::from_str::<LiveMessage>(json).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_wire_modes(c: &mut Criterion) {
+    let trades = synthetic_trades(100_000, 7);
+    let responses = [
+        (
+            "trades",
+            HistoricalResponse::Trades {
+                data: trades.clone(),
+            },
+        ),
+        (
+            "ohlcv-1s",
+            HistoricalResponse::Ohlcv1S {
+                data: aggregate_trades(&trades, NANOS_PER_SECOND),
+            },
+        ),
+        (
+            "ohlcv-1m",
+            HistoricalResponse::Ohlcv1M {
+                data: aggregate_trades(&trades, NANOS_PER_MINUTE),
+            },
+        ),
+    ];
+
+    // Throughput is the bytes written, so the report shows each mode's size
+    let mut group = c.benchmark_group("historical_response");
+    group.sample_size(10);
+    for (schema, response) in &responses {
+        let verbose = serde_json::to_string(response).unwrap().len();
+        group.throughput(Throughput::Bytes(verbose as u64));
+        group.bench_with_input(BenchmarkId::new("verbose", schema), response, |b, r| {
+            b.iter(|| serde_json::to_string(black_box(r)).unwrap())
+        });
+        let compact = compact::to_string(response).unwrap().len();
+        group.throughput(Throughput::Bytes(compact as u64));
+        group.bench_with_input(BenchmarkId::new("compact", schema), response, |b, r| {
+            b.iter(|| compact::to_string(black_box(r)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_downsampling(c: &mut Criterion) {
+    let bars = aggregate_trades(
+        &synthetic_trades(LARGE_DATASET_TRADES, 42),
+        NANOS_PER_SECOND,
+    );
+
+    let mut group = c.benchmark_group("downsampling");
+    group.throughput(Throughput::Elements(bars.len() as u64));
+    for max_points in [500, 2_000, 10_000] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(max_points),
+            &max_points,
+            |b, &n| b.iter(|| downsample_bars(black_box(&bars), n)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_mock_generation(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let service = MockService::new();
+
+    let mut group = c.benchmark_group("mock_generation");
+    for schema in ["trades", "ohlcv-1s", "ohlcv-1m"] {
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string(), "NQ.FUT".to_string()],
+            schema: schema.to_string(),
+            stype_in: SymbolType::Parent,
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T06:00:00Z".to_string(),
+            limit: 1000,
+            ..Default::default()
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(schema), &req, |b, req| {
+            b.iter(|| {
+                runtime
+                    .block_on(service.get_historical(black_box(req)))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_aggregation,
+    bench_serialization,
+    bench_wire_modes,
+    bench_downsampling,
+    bench_mock_generation
+);
+criterion_main!(benches);
@@ -0,0 +1,722 @@
+//! Live analytics layered onto a service's trade stream.
+
+use crate::paper::PaperDesk;
+use crate::profiles::ProfileTap;
+use crate::rollups::{self, RollupTap};
+use crate::service::LiveStream;
+use futures::StreamExt;
+use shared::aggregate::{BarFanout, ClosedBarFeed};
+use shared::book::BookSet;
+use shared::depth::{DepthBook, DepthUpdate};
+use shared::flow::ImbalanceTracker;
+use shared::roll::RollTracker;
+use shared::{LiveMessage, OhlcvRecord, TradeRecord};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Pass `stream` through unchanged, following each trade with order-flow
+/// imbalance `Metric` messages whenever `tracker` has an update due.
+pub fn with_order_flow_imbalance(
+    mut stream: LiveStream,
+    mut tracker: ImbalanceTracker,
+) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            let metrics = match &msg {
+                LiveMessage::Trade {
+                    ts_event_unix_ns,
+                    symbol,
+                    price_i64,
+                    size_u32,
+                } => tracker.push(*ts_event_unix_ns, symbol, *price_i64, *size_u32),
+                _ => Vec::new(),
+            };
+
+            yield msg;
+            for metric in metrics {
+                yield metric;
+            }
+        }
+    })
+}
+
+/// Pass `stream` through unchanged, following a trade with a `RollAlert`
+/// whenever it hands the lead in volume to a different contract month.
+pub fn with_roll_alerts(mut stream: LiveStream, mut tracker: RollTracker) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            let alert = match &msg {
+                LiveMessage::Trade {
+                    ts_event_unix_ns,
+                    symbol,
+                    size_u32,
+                    ..
+                } => tracker.push(*ts_event_unix_ns, symbol, *size_u32),
+                _ => None,
+            };
+
+            yield msg;
+            if let Some(alert) = alert {
+                yield alert;
+            }
+        }
+    })
+}
+
+/// Pass `stream` through unchanged, filling `key_id`'s paper orders against
+/// each trade. The desk publishes the resulting fills and positions.
+pub fn with_paper_fills(
+    mut stream: LiveStream,
+    desk: Arc<PaperDesk>,
+    key_id: String,
+) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            if let LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                ..
+            } = &msg
+            {
+                desk.on_trade(&key_id, *ts_event_unix_ns, symbol, *price_i64);
+            }
+            yield msg;
+        }
+    })
+}
+
+/// Pass `stream` through unchanged, feeding its trades into the rollups
+/// behind `tap`.
+pub fn with_rollups(mut stream: LiveStream, tap: RollupTap) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            if let LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+            } = &msg
+            {
+                tap.on_trade(*ts_event_unix_ns, symbol, *price_i64, *size_u32);
+            }
+            yield msg;
+        }
+    })
+}
+
+/// Pass `stream` through unchanged, feeding its trades into the volume
+/// profiles behind `tap`.
+pub fn with_profiles(mut stream: LiveStream, tap: ProfileTap) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            if let LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+            } = &msg
+            {
+                tap.on_trade(*ts_event_unix_ns, symbol, *price_i64, *size_u32);
+            }
+            yield msg;
+        }
+    })
+}
+
+/// Replace the trades in `stream` with the bars `feed` closes from them,
+/// flagged `bar_closed`. Other messages pass through; bars still open when
+/// the stream ends are sent last.
+pub fn with_closed_bars(mut stream: LiveStream, mut feed: ClosedBarFeed) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            let LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+            } = msg
+            else {
+                yield msg;
+                continue;
+            };
+            let dropped = feed.dropped();
+            let closed = feed.push(&TradeRecord {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+                quote: None,
+                sequence_u32: None,
+            });
+            if feed.dropped() > dropped {
+                debug!(ts_event_unix_ns, "Dropped trade for a closed bar");
+            }
+            for bar in closed {
+                yield closed_bar(bar);
+            }
+        }
+        for bar in feed.flush() {
+            yield closed_bar(bar);
+        }
+    })
+}
+
+/// Send the bars `fanout` builds, each flagged `bar_closed` with its width
+/// as `interval`: built from the trades in `stream`, which they replace, or
+/// rolled up from its bars, which pass through. Other messages pass
+/// through; bars still open when the stream ends are sent last.
+pub fn with_bar_fanout(mut stream: LiveStream, mut fanout: BarFanout) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            let closed = match msg {
+                LiveMessage::Trade {
+                    ts_event_unix_ns,
+                    symbol,
+                    price_i64,
+                    size_u32,
+                } => {
+                    let dropped = fanout.dropped();
+                    let closed = fanout.push_trade(&TradeRecord {
+                        ts_event_unix_ns,
+                        symbol,
+                        price_i64,
+                        size_u32,
+                        quote: None,
+                        sequence_u32: None,
+                    });
+                    if fanout.dropped() > dropped {
+                        debug!(ts_event_unix_ns, "Dropped trade for a closed bar");
+                    }
+                    closed
+                }
+                LiveMessage::Ohlcv {
+                    ts_event_unix_ns,
+                    ref symbol,
+                    open_i64,
+                    high_i64,
+                    low_i64,
+                    close_i64,
+                    volume_u64,
+                    ..
+                } => {
+                    let bar = OhlcvRecord {
+                        ts_event_unix_ns,
+                        symbol: symbol.clone(),
+                        open_i64,
+                        high_i64,
+                        low_i64,
+                        close_i64,
+                        volume_u64,
+                    };
+                    yield msg;
+                    fanout.push_bar(&bar)
+                }
+                msg => {
+                    yield msg;
+                    continue;
+                }
+            };
+            for (interval_ns, bar) in closed {
+                yield channel_bar(interval_ns, bar);
+            }
+        }
+        for (interval_ns, bar) in fanout.flush() {
+            yield channel_bar(interval_ns, bar);
+        }
+    })
+}
+
+/// Most trades in one `TradeBatch`; a fuller batch goes out early.
+pub const MAX_TRADE_BATCH: usize = 500;
+
+/// Gather the trades in `stream` that arrive within `window` of the first
+/// into one `TradeBatch`, sent when the window ends or the batch fills. Any
+/// other message sends the batch before it, so order is kept, and a trade
+/// alone in its window is sent as it came.
+pub fn with_trade_batches(mut stream: LiveStream, window: Duration) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        let mut batch: Vec<TradeRecord> = Vec::new();
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let next = match deadline {
+                Some(at) => tokio::select! {
+                    msg = stream.next() => Some(msg),
+                    _ = tokio::time::sleep_until(at) => None,
+                },
+                None => Some(stream.next().await),
+            };
+            match next {
+                // The window ended
+                None => {
+                    deadline = None;
+                    yield trade_batch(&mut batch);
+                }
+                Some(Some(LiveMessage::Trade {
+                    ts_event_unix_ns,
+                    symbol,
+                    price_i64,
+                    size_u32,
+                })) => {
+                    if batch.is_empty() {
+                        deadline = Some(Instant::now() + window);
+                    }
+                    batch.push(TradeRecord {
+                        ts_event_unix_ns,
+                        symbol,
+                        price_i64,
+                        size_u32,
+                        quote: None,
+                        sequence_u32: None,
+                    });
+                    if batch.len() >= MAX_TRADE_BATCH {
+                        deadline = None;
+                        yield trade_batch(&mut batch);
+                    }
+                }
+                Some(msg) => {
+                    if !batch.is_empty() {
+                        deadline = None;
+                        yield trade_batch(&mut batch);
+                    }
+                    match msg {
+                        Some(msg) => yield msg,
+                        None => break,
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// `batch`'s trades as one message, leaving it empty.
+fn trade_batch(batch: &mut Vec<TradeRecord>) -> LiveMessage {
+    let mut trades = std::mem::take(batch);
+    if trades.len() > 1 {
+        return LiveMessage::TradeBatch { trades };
+    }
+    let trade = trades.remove(0);
+    LiveMessage::Trade {
+        ts_event_unix_ns: trade.ts_event_unix_ns,
+        symbol: trade.symbol,
+        price_i64: trade.price_i64,
+        size_u32: trade.size_u32,
+    }
+}
+
+/// Replace the depth updates in `stream` with snapshots and diffs (see
+/// [`shared::depth`]). An error from upstream means updates may have been
+/// lost, so every book is snapshotted again on its next update. Other
+/// messages pass through.
+pub fn with_depth_book(mut stream: LiveStream) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        let mut book = DepthBook::new();
+        while let Some(msg) = stream.next().await {
+            match msg {
+                LiveMessage::Depth(update) => {
+                    let duplicates = book.duplicates();
+                    let sequence_u32 = update.sequence_u32;
+                    if let Some(msg) = book.push(update) {
+                        yield msg;
+                    } else if book.duplicates() > duplicates {
+                        debug!(sequence_u32, "Dropped a duplicate depth update");
+                    }
+                }
+                LiveMessage::Error { .. } => {
+                    book.invalidate();
+                    yield msg;
+                }
+                msg => yield msg,
+            }
+        }
+    })
+}
+
+/// Replace the order events in `stream` with snapshots and diffs of the top
+/// `depth` levels of the books they build (see [`shared::book`]), sent
+/// when a venue event completes and changed them. Books start from the
+/// provider's snapshot, so other subscribers' events aren't needed; an
+/// error from upstream snapshots every book again, as for depth.
+pub fn with_order_books(mut stream: LiveStream, depth: usize) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        let mut books = BookSet::new();
+        let mut views = DepthBook::new();
+        // Each symbol's completed events, numbered for the depth book
+        let mut events: HashMap<String, u32> = HashMap::new();
+        while let Some(msg) = stream.next().await {
+            match msg {
+                LiveMessage::Mbo(record) => {
+                    let book = books.apply(&record);
+                    if !record.last {
+                        continue;
+                    }
+                    let sequence_u32 = events.entry(record.symbol.clone()).or_default();
+                    *sequence_u32 = sequence_u32.wrapping_add(1);
+                    let update = DepthUpdate {
+                        ts_event_unix_ns: record.ts_event_unix_ns,
+                        levels: book.depth_levels(depth),
+                        symbol: record.symbol,
+                        sequence_u32: *sequence_u32,
+                        maybe_bad_book: record.maybe_bad_book,
+                    };
+                    if let Some(msg) = views.push(update) {
+                        yield msg;
+                    }
+                }
+                LiveMessage::Error { .. } => {
+                    views.invalidate();
+                    yield msg;
+                }
+                msg => yield msg,
+            }
+        }
+    })
+}
+
+/// Pass `stream` through unchanged, sending `snapshot` right after its
+/// `connected` message.
+pub fn with_snapshot(mut stream: LiveStream, snapshot: Vec<LiveMessage>) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        let mut snapshot = Some(snapshot);
+        while let Some(msg) = stream.next().await {
+            let connected = matches!(msg, LiveMessage::Connected { .. });
+            yield msg;
+            if let Some(snapshot) = snapshot.take_if(|_| connected) {
+                for msg in snapshot {
+                    yield msg;
+                }
+            }
+        }
+    })
+}
+
+/// `bar` as a live message, flagged `bar_closed` if it can no longer change.
+pub fn bar_message(bar: OhlcvRecord, bar_closed: bool) -> LiveMessage {
+    LiveMessage::Ohlcv {
+        ts_event_unix_ns: bar.ts_event_unix_ns,
+        symbol: bar.symbol,
+        open_i64: bar.open_i64,
+        high_i64: bar.high_i64,
+        low_i64: bar.low_i64,
+        close_i64: bar.close_i64,
+        volume_u64: bar.volume_u64,
+        bar_closed,
+        interval: None,
+    }
+}
+
+fn closed_bar(bar: OhlcvRecord) -> LiveMessage {
+    bar_message(bar, true)
+}
+
+/// A closed bar of a fan-out, labelled with its width.
+fn channel_bar(interval_ns: u64, bar: OhlcvRecord) -> LiveMessage {
+    LiveMessage::Ohlcv {
+        ts_event_unix_ns: bar.ts_event_unix_ns,
+        symbol: bar.symbol,
+        open_i64: bar.open_i64,
+        high_i64: bar.high_i64,
+        low_i64: bar.low_i64,
+        close_i64: bar.close_i64,
+        volume_u64: bar.volume_u64,
+        bar_closed: true,
+        interval: Some(rollups::label(interval_ns)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::{MessageRate, MockService};
+    use crate::service::MarketDataService;
+    use shared::aggregate::BarAlign;
+    use shared::SymbolType;
+
+    #[tokio::test]
+    async fn test_metrics_follow_trades() {
+        let service = MockService::new().with_rate(MessageRate::Fixed { hz: 20_000 });
+        let stream = service
+            .subscribe_live(
+                vec!["ESZ4".to_string()],
+                "trades".to_string(),
+                SymbolType::RawSymbol,
+            )
+            .await
+            .unwrap();
+        let tracker = ImbalanceTracker::from_spec("1s,5s").unwrap();
+
+        let messages: Vec<_> = with_order_flow_imbalance(stream, tracker)
+            .take(4)
+            .collect()
+            .await;
+
+        assert!(matches!(messages[0], LiveMessage::Connected { .. }));
+        assert!(matches!(messages[1], LiveMessage::Trade { .. }));
+        // The first trade always triggers a round of updates, one per window
+        match (&messages[2], &messages[3]) {
+            (
+                LiveMessage::Metric {
+                    window: first,
+                    value,
+                    ..
+                },
+                LiveMessage::Metric { window: second, .. },
+            ) => {
+                assert_eq!((first.as_str(), second.as_str()), ("1s", "5s"));
+                assert!((-1.0..=1.0).contains(value));
+            }
+            other => panic!("Expected metrics, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closed_bars_replace_trades() {
+        let trade = |ts_event_unix_ns, price_i64| LiveMessage::Trade {
+            ts_event_unix_ns,
+            symbol: "ES.FUT".to_string(),
+            price_i64,
+            size_u32: 2,
+        };
+        let stream: LiveStream = Box::pin(tokio_stream::iter(vec![
+            LiveMessage::Connected {
+                symbols: vec!["ES.FUT".to_string()],
+                schema: "trades".to_string(),
+                connection_id: None,
+            },
+            trade(1_000_000_000, 100),
+            trade(2_000_000_000, 104),
+            trade(61_000_000_000, 101),
+        ]));
+        let feed = ClosedBarFeed::new(60_000_000_000, BarAlign::Epoch);
+
+        let messages: Vec<_> = with_closed_bars(stream, feed).collect().await;
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], LiveMessage::Connected { .. }));
+        match &messages[1] {
+            LiveMessage::Ohlcv {
+                ts_event_unix_ns: 0,
+                high_i64: 104,
+                close_i64: 104,
+                volume_u64: 4,
+                bar_closed: true,
+                ..
+            } => {}
+            other => panic!("Expected the first minute's bar, got {:?}", other),
+        }
+        // The bar in progress is flushed when the stream ends
+        assert!(matches!(
+            messages[2],
+            LiveMessage::Ohlcv {
+                ts_event_unix_ns: 60_000_000_000,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bar_fanout_labels_channels() {
+        let bar = |ts_event_unix_ns, close_i64| LiveMessage::Ohlcv {
+            ts_event_unix_ns,
+            symbol: "ES.FUT".to_string(),
+            open_i64: close_i64,
+            high_i64: close_i64,
+            low_i64: close_i64,
+            close_i64,
+            volume_u64: 1,
+            bar_closed: false,
+            interval: None,
+        };
+        let stream: LiveStream = Box::pin(tokio_stream::iter(vec![
+            bar(0, 100),
+            bar(60_000_000_000, 101),
+            bar(300_000_000_000, 102),
+        ]));
+        let fanout = BarFanout::from_bars(
+            60_000_000_000,
+            &[60_000_000_000, 300_000_000_000],
+            BarAlign::Epoch,
+        )
+        .unwrap();
+
+        let messages: Vec<_> = with_bar_fanout(stream, fanout).collect().await;
+        let channels: Vec<_> = messages
+            .iter()
+            .map(|msg| match msg {
+                LiveMessage::Ohlcv {
+                    ts_event_unix_ns,
+                    interval,
+                    ..
+                } => (*ts_event_unix_ns, interval.as_deref()),
+                other => panic!("Expected bars, got {:?}", other),
+            })
+            .collect();
+        // Upstream bars pass through; the 5m bar closes when the next one
+        // starts, and the one in progress is flushed
+        assert_eq!(
+            channels,
+            vec![
+                (0, None),
+                (60_000_000_000, None),
+                (300_000_000_000, None),
+                (0, Some("5m")),
+                (300_000_000_000, Some("5m")),
+            ]
+        );
+        match &messages[3] {
+            LiveMessage::Ohlcv {
+                close_i64: 101,
+                volume_u64: 2,
+                bar_closed: true,
+                ..
+            } => {}
+            other => panic!("Expected the first 5m bar, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trade_batches() {
+        let trade = |price_i64| LiveMessage::Trade {
+            ts_event_unix_ns: 1,
+            symbol: "ES.FUT".to_string(),
+            price_i64,
+            size_u32: 1,
+        };
+        // A burst, a metric, a lone trade, then a burst the stream ends on
+        let stream: LiveStream = Box::pin(async_stream::stream! {
+            yield trade(100);
+            yield trade(101);
+            yield LiveMessage::Metric {
+                symbol: "ES.FUT".to_string(),
+                name: "order_flow_imbalance".to_string(),
+                value: 0.5,
+                window: "1s".to_string(),
+            };
+            yield trade(102);
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            yield trade(103);
+            yield trade(104);
+            yield trade(105);
+        });
+
+        let messages: Vec<_> = with_trade_batches(stream, Duration::from_millis(50))
+            .collect()
+            .await;
+        let shape: Vec<_> = messages
+            .iter()
+            .map(|msg| match msg {
+                LiveMessage::TradeBatch { trades } => trades.len(),
+                LiveMessage::Trade { .. } => 1,
+                _ => 0,
+            })
+            .collect();
+        assert_eq!(shape, vec![2, 0, 1, 3]);
+        assert!(matches!(
+            messages[2],
+            LiveMessage::Trade { price_i64: 102, .. }
+        ));
+        let LiveMessage::TradeBatch { trades } = &messages[3] else {
+            panic!("Expected a batch, got {:?}", messages[3]);
+        };
+        assert_eq!(
+            trades.iter().map(|t| t.price_i64).collect::<Vec<_>>(),
+            vec![103, 104, 105]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_depth_snapshot_then_diffs() {
+        let service = MockService::new().with_rate(MessageRate::Fixed { hz: 20_000 });
+        let stream = service
+            .subscribe_live(
+                vec!["ESZ4".to_string()],
+                "mbp-10".to_string(),
+                SymbolType::RawSymbol,
+            )
+            .await
+            .unwrap();
+
+        let messages: Vec<_> = with_depth_book(stream).skip(1).take(5).collect().await;
+        let LiveMessage::DepthSnapshot(snapshot) = &messages[0] else {
+            panic!("Expected a snapshot first, got {:?}", messages[0]);
+        };
+        assert_eq!(snapshot.levels.len(), shared::depth::DEPTH_LEVELS);
+        for (i, msg) in messages.iter().enumerate().skip(1) {
+            match msg {
+                LiveMessage::DepthDiff(diff) => assert_eq!(diff.sequence_u64, i as u64 + 1),
+                other => panic!("Expected diffs, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_order_books_from_mbo() {
+        let service = MockService::new().with_rate(MessageRate::Fixed { hz: 20_000 });
+        let stream = service
+            .subscribe_live(
+                vec!["ESZ4".to_string()],
+                "mbo".to_string(),
+                SymbolType::RawSymbol,
+            )
+            .await
+            .unwrap();
+
+        // The session's book snapshot makes one view, not one per order
+        let messages: Vec<_> = with_order_books(stream, 5).skip(1).take(5).collect().await;
+        let LiveMessage::DepthSnapshot(snapshot) = &messages[0] else {
+            panic!("Expected a snapshot first, got {:?}", messages[0]);
+        };
+        assert_eq!(snapshot.levels.len(), 5);
+        assert!(snapshot.levels[0].bid_px_i64 < snapshot.levels[0].ask_px_i64);
+        for (i, msg) in messages.iter().enumerate().skip(1) {
+            match msg {
+                LiveMessage::DepthDiff(diff) => assert_eq!(diff.sequence_u64, i as u64 + 1),
+                other => panic!("Expected diffs, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_follows_connected() {
+        let service = MockService::new().with_rate(MessageRate::Fixed { hz: 20_000 });
+        let stream = service
+            .subscribe_live(
+                vec!["ESZ4".to_string()],
+                "trades".to_string(),
+                SymbolType::RawSymbol,
+            )
+            .await
+            .unwrap();
+        let bar = OhlcvRecord {
+            ts_event_unix_ns: 60_000_000_000,
+            symbol: "ESZ4".to_string(),
+            open_i64: 100,
+            high_i64: 101,
+            low_i64: 99,
+            close_i64: 100,
+            volume_u64: 7,
+        };
+        let snapshot = vec![bar_message(bar.clone(), true), bar_message(bar, false)];
+
+        let messages: Vec<_> = with_snapshot(stream, snapshot).take(4).collect().await;
+        assert!(matches!(messages[0], LiveMessage::Connected { .. }));
+        assert!(matches!(
+            messages[1],
+            LiveMessage::Ohlcv {
+                bar_closed: true,
+                ..
+            }
+        ));
+        assert!(matches!(
+            messages[2],
+            LiveMessage::Ohlcv {
+                bar_closed: false,
+                volume_u64: 7,
+                ..
+            }
+        ));
+        assert!(matches!(messages[3], LiveMessage::Trade { .. }));
+    }
+}
@@ -0,0 +1,274 @@
+//! Live subscriptions held from startup.
+//!
+//! `AUTOSTART` names presets, e.g. `trades=ES.FUT,NQ.FUT;ohlcv-1m=CL.FUT`,
+//! each subscribed when the server starts and held for as long as it runs,
+//! resubscribing with backoff when its stream ends. Their trades feed the
+//! [rollups](crate::rollups) and [volume profiles](crate::profiles), so
+//! live bar snapshots, the leaderboard and profiles have data before any
+//! client connects. With recording on their messages
+//! are recorded too, and the recorder leaves the symbols and schemas they
+//! cover to them rather than subscribing twice; with `RECORD_DBN` they
+//! subscribe through the recorder's service so those symbols reach the DBN
+//! files as well.
+
+use crate::analytics::{with_profiles, with_rollups};
+use crate::egress::{data_symbol, EgressConfig, EgressFormat, Publisher};
+use crate::profiles::ProfileStore;
+use crate::recordings::Recordings;
+use crate::rollups::RollupStore;
+use crate::service::MarketDataService;
+use futures::StreamExt;
+use shared::{LiveMessage, Schema, SymbolType};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Delay before resubscribing after a stream ends or fails; doubled on each
+/// consecutive failure up to `MAX_RESUBSCRIBE_DELAY`.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(60);
+
+/// Symbols held subscribed to one schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    /// `trades`, `ohlcv-1s` or `ohlcv-1m`
+    pub schema: String,
+    pub symbols: Vec<String>,
+}
+
+impl Preset {
+    /// Whether the preset holds `symbol` in `schema`.
+    pub fn covers(&self, schema: &str, symbol: &str) -> bool {
+        self.schema == schema && self.symbols.iter().any(|s| s == symbol)
+    }
+}
+
+/// Parse `schema=SYMBOL,SYMBOL;...`. A schema named twice gets the symbols
+/// of both.
+pub fn parse(spec: &str) -> Result<Vec<Preset>, String> {
+    let mut presets: Vec<Preset> = Vec::new();
+    for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (schema, symbols) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("'{}' should be schema=SYMBOL,SYMBOL", entry))?;
+        let schema = schema.trim();
+        match schema.parse::<Schema>()? {
+            Schema::Trades | Schema::Ohlcv1S | Schema::Ohlcv1M => {}
+            _ => {
+                return Err(format!(
+                    "'{}' isn't a live schema: expected trades, ohlcv-1s or ohlcv-1m",
+                    schema
+                ))
+            }
+        }
+        let symbols: Vec<String> = symbols
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if symbols.is_empty() {
+            return Err(format!("'{}' names no symbols", entry));
+        }
+        let preset = match presets.iter_mut().find(|p| p.schema == schema) {
+            Some(preset) => preset,
+            None => {
+                presets.push(Preset {
+                    schema: schema.to_string(),
+                    symbols: Vec::new(),
+                });
+                presets.last_mut().expect("just pushed")
+            }
+        };
+        for symbol in symbols {
+            if !preset.symbols.contains(&symbol) {
+                preset.symbols.push(symbol);
+            }
+        }
+    }
+    Ok(presets)
+}
+
+/// The recorder's subscriptions with what `presets` hold taken out, one
+/// per schema that still has symbols of its own.
+pub fn uncovered(presets: &[Preset], config: &EgressConfig) -> Vec<EgressConfig> {
+    config
+        .schemas
+        .iter()
+        .filter_map(|schema| {
+            let symbols: Vec<String> = config
+                .symbols
+                .iter()
+                .filter(|symbol| !presets.iter().any(|p| p.covers(schema, symbol)))
+                .cloned()
+                .collect();
+            (!symbols.is_empty()).then(|| EgressConfig {
+                symbols,
+                schemas: vec![schema.clone()],
+                format: config.format,
+            })
+        })
+        .collect()
+}
+
+/// Hold each preset's subscription, feeding `rollups`, `profiles` and, if
+/// recording is on, `recordings`.
+pub fn spawn(
+    service: Arc<dyn MarketDataService>,
+    presets: Vec<Preset>,
+    rollups: Arc<RollupStore>,
+    profiles: Arc<ProfileStore>,
+    recordings: Option<Arc<Recordings>>,
+) -> Vec<JoinHandle<()>> {
+    presets
+        .into_iter()
+        .map(|preset| {
+            tokio::spawn(hold(
+                service.clone(),
+                preset,
+                rollups.clone(),
+                profiles.clone(),
+                recordings.clone(),
+            ))
+        })
+        .collect()
+}
+
+/// Hold one preset's subscription forever.
+async fn hold(
+    service: Arc<dyn MarketDataService>,
+    preset: Preset,
+    rollups: Arc<RollupStore>,
+    profiles: Arc<ProfileStore>,
+    recordings: Option<Arc<Recordings>>,
+) {
+    let Preset { schema, symbols } = preset;
+    let mut delay = RESUBSCRIBE_DELAY;
+    loop {
+        match service
+            .subscribe_live(symbols.clone(), schema.clone(), SymbolType::Parent)
+            .await
+        {
+            Ok(stream) => {
+                info!(schema = %schema, symbols = ?symbols, "Autostart subscribed");
+                delay = RESUBSCRIBE_DELAY;
+                let stream = match rollups.enabled() {
+                    true => with_rollups(stream, rollups.tap()),
+                    false => stream,
+                };
+                let mut stream = with_profiles(stream, profiles.tap());
+                while let Some(msg) = stream.next().await {
+                    if let LiveMessage::Error { message } = &msg {
+                        warn!(schema = %schema, "Autostart stream error: {}", message);
+                        continue;
+                    }
+                    let (Some(recordings), Some(symbol)) = (&recordings, data_symbol(&msg)) else {
+                        continue;
+                    };
+                    let recorded = match EgressFormat::Json.encode(&msg) {
+                        Ok(payload) => recordings.publish(&schema, symbol, payload).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = recorded {
+                        warn!(schema = %schema, "Autostart recording failed: {}", e);
+                    }
+                }
+                warn!(schema = %schema, "Autostart stream ended, resubscribing");
+            }
+            Err(e) => warn!(schema = %schema, "Autostart subscribe failed: {}", e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::MockService;
+    use shared::sessions::SessionSpec;
+
+    #[test]
+    fn test_parse() {
+        let presets = parse("trades=ES.FUT, NQ.FUT; ohlcv-1m=CL.FUT;trades=ES.FUT,GC.FUT").unwrap();
+        assert_eq!(
+            presets,
+            [
+                Preset {
+                    schema: "trades".to_string(),
+                    symbols: vec![
+                        "ES.FUT".to_string(),
+                        "NQ.FUT".to_string(),
+                        "GC.FUT".to_string()
+                    ],
+                },
+                Preset {
+                    schema: "ohlcv-1m".to_string(),
+                    symbols: vec!["CL.FUT".to_string()],
+                },
+            ]
+        );
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("ES.FUT").is_err());
+        assert!(parse("trades=").is_err());
+        assert!(parse("cvd-1m=ES.FUT").is_err());
+        assert!(parse("quotes=ES.FUT").is_err());
+
+        // The recorder keeps only what no preset holds
+        let record = EgressConfig {
+            symbols: vec!["ES.FUT".to_string(), "CL.FUT".to_string()],
+            schemas: vec!["trades".to_string(), "ohlcv-1m".to_string()],
+            format: EgressFormat::Json,
+        };
+        let left: Vec<(Vec<String>, Vec<String>)> = uncovered(&presets, &record)
+            .into_iter()
+            .map(|c| (c.schemas, c.symbols))
+            .collect();
+        assert_eq!(
+            left,
+            [
+                (vec!["trades".to_string()], vec!["CL.FUT".to_string()]),
+                (vec!["ohlcv-1m".to_string()], vec!["ES.FUT".to_string()]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_feeds_rollups_before_any_client() {
+        let dir = std::env::temp_dir().join(format!("sf-autostart-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let recordings = Arc::new(Recordings::open(&dir).unwrap());
+        let rollups = Arc::new(RollupStore::new(vec![1_000_000_000], 100));
+        let service: Arc<dyn MarketDataService> = Arc::new(MockService::new());
+        let presets = parse("trades=ES.FUT").unwrap();
+
+        let profiles = Arc::new(ProfileStore::new(SessionSpec::parse("cme").unwrap()));
+        let tasks = spawn(
+            service,
+            presets,
+            rollups.clone(),
+            profiles,
+            Some(recordings.clone()),
+        );
+        // Bars close once trades reach the next second
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while rollups
+            .series("ES.FUT", 1_000_000_000)
+            .is_none_or(|series| series.bars.is_empty())
+        {
+            assert!(tokio::time::Instant::now() < deadline, "no bars rolled up");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        for task in tasks {
+            task.abort();
+        }
+        recordings.flush();
+        assert!(recordings
+            .partitions()
+            .unwrap()
+            .iter()
+            .any(|p| p.symbol == "ES.FUT"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
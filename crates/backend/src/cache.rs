@@ -0,0 +1,370 @@
+//! Historical response cache shared between backend instances.
+//!
+//! [`CachedService`] wraps a [`MarketDataService`] and serves repeated
+//! historical requests from a [`HistoricalCache`], keyed by a hash of the
+//! request. Only ranges that ended at least [`SETTLE_TIME`] ago are cached,
+//! since more recent data may still be arriving upstream. Cache failures are
+//! logged and fall through to the wrapped service. [`observe`] reports
+//! whether a request was answered from the cache, and [`with_mode`] has a
+//! task's requests skip the cache or be served from it alone.
+//!
+//! Requests carry the upstream revision they're served at (see
+//! [`shared::DataVersion`]), so the key changes when the provider restates
+//! data, and a request pinned to an older revision still finds it here.
+
+use crate::service::{LiveStream, MarketDataService, MboStream, ServiceError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use sha2::{Digest, Sha256};
+use shared::querylog::CacheStatus;
+use shared::validation::DatasetRoute;
+use shared::{DataVersion, HistoricalRequest, HistoricalResponse, SymbolType};
+use std::cell::Cell;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Requests whose range ends more recently than this are not cached.
+pub const SETTLE_TIME: Duration = Duration::from_secs(15 * 60);
+
+/// Key prefix for cached historical responses.
+const KEY_PREFIX: &str = "sf:hist:";
+
+tokio::task_local! {
+    /// How the current task's last historical request met the cache.
+    static CACHE_STATUS: Cell<CacheStatus>;
+    /// How the current task's historical requests use the cache.
+    static CACHE_MODE: CacheMode;
+}
+
+/// How historical requests use the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Served from the cache if there, else fetched and cached
+    Use,
+    /// Fetched from the wrapped service, without reading or writing the
+    /// cache
+    Skip,
+    /// Served from the cache alone; anything not there is a
+    /// [`ServiceError::NotCached`]
+    Only,
+}
+
+/// Run `fut` with its historical requests using the cache as `mode` says.
+/// Outside it they [`Use`](CacheMode::Use) it.
+pub async fn with_mode<F: Future>(mode: CacheMode, fut: F) -> F::Output {
+    CACHE_MODE.scope(mode, fut).await
+}
+
+fn mode() -> CacheMode {
+    CACHE_MODE.try_with(|mode| *mode).unwrap_or(CacheMode::Use)
+}
+
+/// Run `fut`, returning its output and whether the historical request it
+/// made was a cache hit, a miss, or bypassed the cache.
+pub async fn observe<F: Future>(fut: F) -> (F::Output, CacheStatus) {
+    CACHE_STATUS
+        .scope(Cell::new(CacheStatus::Bypass), async {
+            let output = fut.await;
+            (output, CACHE_STATUS.with(Cell::get))
+        })
+        .await
+}
+
+fn report(status: CacheStatus) {
+    let _ = CACHE_STATUS.try_with(|cell| cell.set(status));
+}
+
+/// Error type for cache backends.
+#[derive(Debug, thiserror::Error)]
+#[error("Cache error: {0}")]
+pub struct CacheError(pub String);
+
+impl From<redis::RedisError> for CacheError {
+    fn from(e: redis::RedisError) -> Self {
+        CacheError(e.to_string())
+    }
+}
+
+/// Byte storage with expiry, shared across instances.
+#[async_trait]
+pub trait HistoricalCache: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError>;
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), CacheError>;
+}
+
+/// A [`HistoricalCache`] stored in Redis.
+pub struct RedisCache {
+    conn: ConnectionManager,
+    /// Prepended to every key, partitioning the cache
+    namespace: String,
+}
+
+impl RedisCache {
+    /// Wrap a connection; it reconnects on its own after failures.
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self {
+            conn,
+            namespace: String::new(),
+        }
+    }
+
+    /// Keep entries apart from caches with other namespaces, e.g. a
+    /// tenant's.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+}
+
+#[async_trait]
+impl HistoricalCache for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let key = format!("{}{}", self.namespace, key);
+        Ok(self.conn.clone().get(key).await?)
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), CacheError> {
+        let key = format!("{}{}", self.namespace, key);
+        let _: () = self
+            .conn
+            .clone()
+            .set_ex(key, value, ttl.as_secs().max(1))
+            .await?;
+        Ok(())
+    }
+}
+
+/// A service wrapper that caches settled historical responses.
+pub struct CachedService {
+    inner: Arc<dyn MarketDataService>,
+    cache: Arc<dyn HistoricalCache>,
+    ttl: Duration,
+}
+
+impl CachedService {
+    pub fn new(
+        inner: Arc<dyn MarketDataService>,
+        cache: Arc<dyn HistoricalCache>,
+        ttl: Duration,
+    ) -> Self {
+        Self { inner, cache, ttl }
+    }
+}
+
+/// Cache key for `req`, or `None` if its range hasn't settled.
+fn cache_key(req: &HistoricalRequest, now: DateTime<Utc>) -> Option<String> {
+    let end = DateTime::parse_from_rfc3339(&req.end_rfc3339).ok()?;
+    let settle = chrono::Duration::from_std(SETTLE_TIME).ok()?;
+    if end.with_timezone(&Utc) > now - settle {
+        return None;
+    }
+    let json = serde_json::to_vec(req).ok()?;
+    Some(format!(
+        "{}{}",
+        KEY_PREFIX,
+        hex::encode(Sha256::digest(json))
+    ))
+}
+
+#[async_trait]
+impl MarketDataService for CachedService {
+    async fn get_historical(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<HistoricalResponse, ServiceError> {
+        let mode = mode();
+        let key = match mode {
+            CacheMode::Skip => None,
+            CacheMode::Use | CacheMode::Only => cache_key(req, Utc::now()),
+        };
+        let Some(key) = key else {
+            if mode == CacheMode::Only {
+                return Err(ServiceError::NotCached(
+                    "the range ends too recently to be cached".to_string(),
+                ));
+            }
+            return self.inner.get_historical(req).await;
+        };
+
+        match self.cache.get(&key).await {
+            Ok(Some(bytes)) => match serde_json::from_slice(&bytes) {
+                Ok(resp) => {
+                    debug!(key = %key, "Historical cache hit");
+                    report(CacheStatus::Hit);
+                    return Ok(resp);
+                }
+                Err(e) => warn!(key = %key, "Discarding unreadable cache entry: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("{}", e),
+        }
+
+        report(CacheStatus::Miss);
+        if mode == CacheMode::Only {
+            return Err(ServiceError::NotCached(
+                "no response is cached for the request".to_string(),
+            ));
+        }
+        let resp = self.inner.get_historical(req).await?;
+        match serde_json::to_vec(&resp) {
+            Ok(bytes) => {
+                if let Err(e) = self.cache.put(&key, bytes, self.ttl).await {
+                    warn!("{}", e);
+                }
+            }
+            Err(e) => warn!("Not caching response: {}", e),
+        }
+        Ok(resp)
+    }
+
+    async fn subscribe_live(
+        &self,
+        symbols: Vec<String>,
+        schema: String,
+        stype_in: SymbolType,
+    ) -> Result<LiveStream, ServiceError> {
+        self.inner.subscribe_live(symbols, schema, stype_in).await
+    }
+
+    async fn count_records(&self, req: &HistoricalRequest) -> Result<u64, ServiceError> {
+        self.inner.count_records(req).await
+    }
+
+    async fn estimate_cost(&self, req: &HistoricalRequest) -> Result<f64, ServiceError> {
+        self.inner.estimate_cost(req).await
+    }
+
+    async fn unresolved_symbols(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<String>, ServiceError> {
+        self.inner.unresolved_symbols(req).await
+    }
+
+    async fn data_versions(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<DataVersion>, ServiceError> {
+        self.inner.data_versions(req).await
+    }
+
+    async fn replay_mbo(&self, req: &HistoricalRequest) -> Result<MboStream, ServiceError> {
+        self.inner.replay_mbo(req).await
+    }
+
+    fn datasets(&self, req: &HistoricalRequest) -> Result<Vec<DatasetRoute>, ServiceError> {
+        self.inner.datasets(req)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::MockService;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryCache(Mutex<HashMap<String, Vec<u8>>>);
+
+    #[async_trait]
+    impl HistoricalCache for MemoryCache {
+        async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        async fn put(&self, key: &str, value: Vec<u8>, _ttl: Duration) -> Result<(), CacheError> {
+            self.0.lock().unwrap().insert(key.to_string(), value);
+            Ok(())
+        }
+    }
+
+    fn request(end: &str) -> HistoricalRequest {
+        HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: end.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_only_settled_ranges_are_cached() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T01:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let key = cache_key(&request("2024-01-01T00:30:00Z"), now).unwrap();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(cache_key(&request("2024-01-01T00:30:00Z"), now), Some(key));
+        assert!(cache_key(&request("2024-01-01T00:50:00Z"), now).is_none());
+        assert!(cache_key(&request("not a time"), now).is_none());
+
+        // Data restated upstream is keyed apart from what was cached before
+        let revised = |revision: &str| HistoricalRequest {
+            revision: Some(revision.to_string()),
+            ..request("2024-01-01T00:30:00Z")
+        };
+        assert_ne!(
+            cache_key(&revised("GLBX.MDP3@2024-01-02"), now),
+            cache_key(&revised("GLBX.MDP3@2024-03-15"), now)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repeat_request_is_served_from_cache() {
+        let cache = Arc::new(MemoryCache::default());
+        let service = CachedService::new(
+            Arc::new(MockService::new()),
+            cache.clone(),
+            Duration::from_secs(60),
+        );
+        let req = request("2024-01-01T01:00:00Z");
+
+        let (first, status) = observe(service.get_historical(&req)).await;
+        assert_eq!(status, CacheStatus::Miss);
+        assert_eq!(cache.0.lock().unwrap().len(), 1);
+
+        // Mock data is random, so an identical second response proves a hit
+        let (second, status) = observe(service.get_historical(&req)).await;
+        assert_eq!(status, CacheStatus::Hit);
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(
+            serde_json::to_string(&first).unwrap(),
+            serde_json::to_string(&second).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_and_only_modes() {
+        let cache = Arc::new(MemoryCache::default());
+        let service = CachedService::new(
+            Arc::new(MockService::new()),
+            cache.clone(),
+            Duration::from_secs(60),
+        );
+        let req = request("2024-01-01T01:00:00Z");
+
+        let only = with_mode(CacheMode::Only, service.get_historical(&req)).await;
+        assert!(matches!(only, Err(ServiceError::NotCached(_))));
+        let (skipped, status) =
+            observe(with_mode(CacheMode::Skip, service.get_historical(&req))).await;
+        assert!(skipped.is_ok());
+        assert_eq!(status, CacheStatus::Bypass);
+        assert!(cache.0.lock().unwrap().is_empty());
+
+        service.get_historical(&req).await.unwrap();
+        let (only, status) =
+            observe(with_mode(CacheMode::Only, service.get_historical(&req))).await;
+        assert!(only.is_ok());
+        assert_eq!(status, CacheStatus::Hit);
+    }
+}
@@ -0,0 +1,148 @@
+//! Economic calendar integration.
+//!
+//! Loads release times from `CALENDAR_SOURCE`, a file path or an
+//! `http(s)://` URL serving iCalendar or JSON, and reloads it periodically.
+//! Alerts are broadcast to live sockets that asked for them, a lead time
+//! before each release and again at release time.
+
+use chrono::Utc;
+use shared::calendar::{self, CalendarEvent};
+use shared::LiveMessage;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often due alerts are checked for.
+const ALERT_TICK: Duration = Duration::from_secs(1);
+
+/// Error type for calendar loading.
+#[derive(Debug, thiserror::Error)]
+pub enum CalendarError {
+    #[error("Calendar fetch failed: {0}")]
+    Fetch(#[from] reqwest::Error),
+    #[error("Calendar read failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid calendar: {0}")]
+    Parse(String),
+}
+
+/// The loaded calendar and its alert broadcast.
+pub struct Calendar {
+    /// File path or URL; no calendar when unset
+    source: Option<String>,
+    /// How long before a release the `upcoming` alert goes out
+    lead: Duration,
+    events: RwLock<Vec<CalendarEvent>>,
+    alerts: broadcast::Sender<LiveMessage>,
+    http: reqwest::Client,
+}
+
+impl Calendar {
+    pub fn new(source: Option<String>, lead: Duration) -> Self {
+        let (alerts, _) = broadcast::channel(64);
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            source,
+            lead,
+            events: RwLock::new(Vec::new()),
+            alerts,
+            http,
+        }
+    }
+
+    /// Whether a calendar source is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Re-read the source, replacing the loaded events. Returns how many
+    /// were loaded.
+    pub async fn reload(&self) -> Result<usize, CalendarError> {
+        let Some(source) = &self.source else {
+            return Ok(0);
+        };
+        let text = if source.starts_with("http://") || source.starts_with("https://") {
+            self.http
+                .get(source)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        } else {
+            tokio::fs::read_to_string(source).await?
+        };
+        let events = calendar::parse(&text).map_err(CalendarError::Parse)?;
+        let count = events.len();
+        *self.events.write().unwrap_or_else(|e| e.into_inner()) = events;
+        Ok(count)
+    }
+
+    /// Events released in `[start_ns, end_ns)`, in time order.
+    pub fn events(&self, start_ns: u64, end_ns: u64) -> Vec<CalendarEvent> {
+        let events = self.events.read().unwrap_or_else(|e| e.into_inner());
+        events
+            .iter()
+            .filter(|e| e.ts_event_unix_ns >= start_ns && e.ts_event_unix_ns < end_ns)
+            .cloned()
+            .collect()
+    }
+
+    /// Receive every `calendar_event` alert.
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveMessage> {
+        self.alerts.subscribe()
+    }
+
+    /// Load the calendar, then keep reloading it every `refresh` and
+    /// broadcasting alerts as they fall due. Does nothing without a source.
+    pub fn start(self: &Arc<Self>, refresh: Duration) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let calendar = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(refresh);
+            loop {
+                ticks.tick().await;
+                match calendar.reload().await {
+                    Ok(count) => info!(events = count, "Loaded economic calendar"),
+                    Err(e) => warn!("Keeping previous economic calendar: {}", e),
+                }
+            }
+        });
+
+        let calendar = Arc::clone(self);
+        tokio::spawn(async move {
+            let lead_ns = calendar.lead.as_nanos() as u64;
+            let mut last = now_ns();
+            let mut ticks = tokio::time::interval(ALERT_TICK);
+            loop {
+                ticks.tick().await;
+                let now = now_ns();
+                let due = {
+                    let events = calendar.events.read().unwrap_or_else(|e| e.into_inner());
+                    calendar::due_alerts(&events, last, now, lead_ns)
+                };
+                last = now;
+                for alert in due {
+                    // No receivers just means no socket asked for alerts
+                    let _ = calendar.alerts.send(LiveMessage::CalendarEvent(alert));
+                }
+            }
+        });
+    }
+}
+
+fn now_ns() -> u64 {
+    Utc::now()
+        .timestamp_nanos_opt()
+        .and_then(|ns| u64::try_from(ns).ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,236 @@
+//! Cancelling work in flight.
+//!
+//! Each API request runs under a [`CancellationToken`] registered by its
+//! request id, the `X-Request-Id` header or a generated id echoed back in
+//! it. `DELETE /api/requests/:request_id` cancels the request if the same
+//! API key made it. Cancellation is cooperative: the token is set for the
+//! task serving the request, and the metered fetch, the per-symbol
+//! fallback and the provider's record decoding check it between steps
+//! (see [`checkpoint`]), so the upstream fetch stops and nothing is
+//! charged to the key's quotas. Scheduled job runs get a token of their
+//! own (see [`crate::scheduler`]).
+
+use crate::service::ServiceError;
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+pub use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    /// Token of the request or job run the current task serves.
+    static TOKEN: CancellationToken;
+}
+
+/// Run `fut` under `token`, which [`checkpoint`] and [`or_cancelled`]
+/// check.
+pub async fn with_cancellation<F: Future>(token: CancellationToken, fut: F) -> F::Output {
+    TOKEN.scope(token, fut).await
+}
+
+/// The current task's token, if running under [`with_cancellation`].
+pub fn current() -> Option<CancellationToken> {
+    TOKEN.try_with(CancellationToken::clone).ok()
+}
+
+/// Fail with [`ServiceError::Cancelled`] if the current task's work has
+/// been cancelled.
+pub fn checkpoint() -> Result<(), ServiceError> {
+    match TOKEN.try_with(CancellationToken::is_cancelled) {
+        Ok(true) => Err(ServiceError::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+/// Run `fut`, dropping it and failing with [`ServiceError::Cancelled`] as
+/// soon as the current task's work is cancelled.
+pub async fn or_cancelled<T, F>(fut: F) -> Result<T, ServiceError>
+where
+    F: Future<Output = Result<T, ServiceError>>,
+{
+    let Some(token) = current() else {
+        return fut.await;
+    };
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(ServiceError::Cancelled),
+        result = fut => result,
+    }
+}
+
+/// Error type for cancelling a request.
+#[derive(Debug, thiserror::Error)]
+pub enum CancelError {
+    /// Also returned for another key's request, so ids don't leak
+    #[error("No request '{0}' in flight")]
+    NotFound(String),
+}
+
+struct Entry {
+    key_id: String,
+    token: CancellationToken,
+    seq: u64,
+    started_at: String,
+    route: String,
+}
+
+/// A request in flight, as listed to its key.
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlightRequest {
+    pub request_id: String,
+    pub route: String,
+    pub started_at: String,
+}
+
+/// Requests in flight by request id.
+#[derive(Default)]
+pub struct InFlight {
+    requests: Mutex<HashMap<String, Entry>>,
+    seq: AtomicU64,
+}
+
+impl InFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn requests(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Register a request made by `key_id`, until the returned guard
+    /// drops. A request reusing an id in flight takes it over.
+    pub fn register(self: &Arc<Self>, request_id: &str, key_id: &str, route: &str) -> Registration {
+        let token = CancellationToken::new();
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        self.requests().insert(
+            request_id.to_string(),
+            Entry {
+                key_id: key_id.to_string(),
+                token: token.clone(),
+                seq,
+                started_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                route: route.to_string(),
+            },
+        );
+        Registration {
+            in_flight: self.clone(),
+            request_id: request_id.to_string(),
+            seq,
+            token,
+        }
+    }
+
+    /// Cancel `request_id` if `key_id` made it.
+    pub fn cancel(&self, request_id: &str, key_id: &str) -> Result<(), CancelError> {
+        match self.requests().get(request_id) {
+            Some(entry) if entry.key_id == key_id => {
+                entry.token.cancel();
+                Ok(())
+            }
+            _ => Err(CancelError::NotFound(request_id.to_string())),
+        }
+    }
+
+    /// `key_id`'s requests in flight, oldest first.
+    pub fn list(&self, key_id: &str) -> Vec<InFlightRequest> {
+        let requests = self.requests();
+        let mut listed: Vec<(u64, InFlightRequest)> = requests
+            .iter()
+            .filter(|(_, entry)| entry.key_id == key_id)
+            .map(|(id, entry)| {
+                (
+                    entry.seq,
+                    InFlightRequest {
+                        request_id: id.clone(),
+                        route: entry.route.clone(),
+                        started_at: entry.started_at.clone(),
+                    },
+                )
+            })
+            .collect();
+        listed.sort_by_key(|(seq, _)| *seq);
+        listed.into_iter().map(|(_, request)| request).collect()
+    }
+}
+
+/// A registered request, unregistered when dropped.
+pub struct Registration {
+    in_flight: Arc<InFlight>,
+    request_id: String,
+    seq: u64,
+    token: CancellationToken,
+}
+
+impl Registration {
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut requests = self.in_flight.requests();
+        // Unless a later request took the id over
+        if requests
+            .get(&self.request_id)
+            .is_some_and(|entry| entry.seq == self.seq)
+        {
+            requests.remove(&self.request_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_registry() {
+        let in_flight = Arc::new(InFlight::new());
+        let first = in_flight.register("r1", "abc", "/api/historical");
+        let _other = in_flight.register("r2", "def", "/api/historical");
+        assert_eq!(in_flight.list("abc").len(), 1);
+        assert!(matches!(
+            in_flight.cancel("r1", "def"),
+            Err(CancelError::NotFound(_))
+        ));
+        assert!(!first.token().is_cancelled());
+        in_flight.cancel("r1", "abc").unwrap();
+        assert!(first.token().is_cancelled());
+
+        // A reused id belongs to the latest request until it finishes
+        let second = in_flight.register("r1", "abc", "/api/historical/export");
+        drop(first);
+        assert_eq!(in_flight.list("abc")[0].route, "/api/historical/export");
+        drop(second);
+        assert!(in_flight.list("abc").is_empty());
+        assert!(in_flight.cancel("r1", "abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_or_cancelled() {
+        let token = CancellationToken::new();
+        let slow = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok::<_, ServiceError>(())
+        };
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            cancel.cancel();
+        });
+        let result = with_cancellation(token.clone(), or_cancelled(slow)).await;
+        assert!(matches!(result, Err(ServiceError::Cancelled)));
+        assert!(matches!(
+            with_cancellation(token, async { checkpoint() }).await,
+            Err(ServiceError::Cancelled)
+        ));
+        // Outside a cancellable task nothing is ever cancelled
+        assert!(checkpoint().is_ok());
+        assert!(or_cancelled(async { Ok(1) }).await.is_ok());
+    }
+}
@@ -0,0 +1,238 @@
+//! Guards against clock skew between the server and its data.
+//!
+//! Historical requests may not start in the future; an end time slightly
+//! ahead is clamped to now and one far ahead is rejected, so a chart can't
+//! ask for "data from tomorrow". Live trades and bars are compared with the
+//! server clock as they pass, with a warning when they run ahead of it.
+//! Both are counted in [`ClockSkewStats`] for `GET /api/metrics`.
+
+use crate::service::{LiveStream, ServiceError};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::StreamExt;
+use shared::metrics::ClockSkewStats;
+use shared::{HistoricalRequest, LiveMessage};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Shortest time between two skew warnings.
+const WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Tolerances for timestamps ahead of the server clock.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockLimits {
+    /// How far ahead a historical request's end may be before it is
+    /// rejected rather than clamped to now
+    pub max_future_end: Duration,
+    /// Live data further ahead than this is logged and counted
+    pub live_skew_warn: Duration,
+}
+
+impl Default for ClockLimits {
+    fn default() -> Self {
+        Self {
+            max_future_end: Duration::from_secs(24 * 60 * 60),
+            live_skew_warn: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Applies [`ClockLimits`] and keeps skew statistics.
+pub struct ClockGuard {
+    limits: ClockLimits,
+    stats: Mutex<ClockSkewStats>,
+    last_warning: Mutex<Option<Instant>>,
+}
+
+impl ClockGuard {
+    pub fn new(limits: ClockLimits) -> Self {
+        Self {
+            limits,
+            stats: Mutex::new(ClockSkewStats {
+                warn_threshold_ms: limits.live_skew_warn.as_millis() as u64,
+                ..Default::default()
+            }),
+            last_warning: Mutex::new(None),
+        }
+    }
+
+    fn stats_mut(&self) -> std::sync::MutexGuard<'_, ClockSkewStats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Current skew statistics.
+    pub fn stats(&self) -> ClockSkewStats {
+        self.stats_mut().clone()
+    }
+
+    /// Check `req`'s range against `now`, clamping a future end to `now`.
+    /// Unparseable times are left for the provider to reject.
+    pub fn check_request(
+        &self,
+        req: &mut HistoricalRequest,
+        now: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        let result = self.apply_limits(req, now);
+        match result {
+            Ok(true) => self.stats_mut().requests_clamped += 1,
+            Ok(false) => {}
+            Err(_) => self.stats_mut().requests_rejected += 1,
+        }
+        result.map(drop).map_err(ServiceError::InvalidTimeFormat)
+    }
+
+    /// [`check_request`](Self::check_request) for a request that won't be
+    /// served, leaving the statistics alone.
+    pub fn preview_request(
+        &self,
+        req: &mut HistoricalRequest,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        self.apply_limits(req, now).map(drop)
+    }
+
+    /// Apply the limits to `req`, returning whether its end was clamped.
+    fn apply_limits(
+        &self,
+        req: &mut HistoricalRequest,
+        now: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        let parse = |s: &str| DateTime::parse_from_rfc3339(s).ok();
+
+        if parse(&req.start_rfc3339).is_some_and(|start| start > now) {
+            return Err(format!(
+                "start {} is after the server's current time {}",
+                req.start_rfc3339,
+                now.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ));
+        }
+        let Some(end) = parse(&req.end_rfc3339) else {
+            return Ok(false);
+        };
+        if end <= now {
+            return Ok(false);
+        }
+        let ahead = (end.with_timezone(&Utc) - now).to_std().unwrap_or_default();
+        if ahead > self.limits.max_future_end {
+            return Err(format!(
+                "end {} is more than {}s after the server's current time",
+                req.end_rfc3339,
+                self.limits.max_future_end.as_secs()
+            ));
+        }
+        req.end_rfc3339 = now.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        Ok(true)
+    }
+
+    /// Record a live record's event time against `now_ns`.
+    pub fn observe(&self, ts_event_unix_ns: u64, now_ns: u64) {
+        let skew_ms = (ts_event_unix_ns as i128 - now_ns as i128) / 1_000_000;
+        let skew_ms = skew_ms.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        let ahead = skew_ms > 0 && skew_ms as u128 > self.limits.live_skew_warn.as_millis();
+        {
+            let mut stats = self.stats_mut();
+            stats.live_samples += 1;
+            stats.last_skew_ms = Some(skew_ms);
+            stats.max_skew_ms = Some(stats.max_skew_ms.map_or(skew_ms, |max| max.max(skew_ms)));
+            if ahead {
+                stats.ahead_samples += 1;
+            }
+        }
+
+        if ahead {
+            let mut last = self.last_warning.lock().unwrap_or_else(|e| e.into_inner());
+            if last.is_none_or(|at| at.elapsed() >= WARN_INTERVAL) {
+                *last = Some(Instant::now());
+                warn!(
+                    skew_ms,
+                    "Live data is timestamped ahead of server time; check the server clock"
+                );
+            }
+        }
+    }
+}
+
+impl Default for ClockGuard {
+    fn default() -> Self {
+        Self::new(ClockLimits::default())
+    }
+}
+
+/// Pass `stream` through unchanged, checking each trade and bar's
+/// timestamp against the server clock.
+pub fn with_skew_check(mut stream: LiveStream, guard: Arc<ClockGuard>) -> LiveStream {
+    Box::pin(async_stream::stream! {
+        while let Some(msg) = stream.next().await {
+            match &msg {
+                LiveMessage::Trade { ts_event_unix_ns, .. }
+                | LiveMessage::Ohlcv { ts_event_unix_ns, .. } => {
+                    let now_ns = Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX) as u64;
+                    guard.observe(*ts_event_unix_ns, now_ns);
+                }
+                _ => {}
+            }
+            yield msg;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(start: &str, end: &str) -> HistoricalRequest {
+        HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            start_rfc3339: start.to_string(),
+            end_rfc3339: end.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_future_end_is_clamped_or_rejected() {
+        let guard = ClockGuard::default();
+        let now = DateTime::parse_from_rfc3339("2024-07-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut req = request("2024-07-01T00:00:00Z", "2024-07-01T11:00:00Z");
+        guard.check_request(&mut req, now).unwrap();
+        assert_eq!(req.end_rfc3339, "2024-07-01T11:00:00Z");
+
+        let mut req = request("2024-07-01T00:00:00Z", "2024-07-01T23:59:59Z");
+        guard.check_request(&mut req, now).unwrap();
+        assert_eq!(req.end_rfc3339, "2024-07-01T12:00:00Z");
+
+        let mut req = request("2024-07-01T00:00:00Z", "2024-07-03T00:00:00Z");
+        assert!(guard.check_request(&mut req, now).is_err());
+        let mut req = request("2024-07-02T00:00:00Z", "2024-07-02T01:00:00Z");
+        assert!(guard.check_request(&mut req, now).is_err());
+
+        // Previews apply the same limits without counting
+        let mut req = request("2024-07-01T00:00:00Z", "2024-07-01T23:59:59Z");
+        guard.preview_request(&mut req, now).unwrap();
+        assert_eq!(req.end_rfc3339, "2024-07-01T12:00:00Z");
+        let mut req = request("2024-07-02T00:00:00Z", "2024-07-02T01:00:00Z");
+        assert!(guard.preview_request(&mut req, now).is_err());
+
+        let stats = guard.stats();
+        assert_eq!((stats.requests_clamped, stats.requests_rejected), (1, 2));
+    }
+
+    #[test]
+    fn test_live_skew_stats() {
+        let guard = ClockGuard::default();
+        let now = 1_700_000_000_000_000_000;
+        guard.observe(now - 150_000_000, now);
+        guard.observe(now + 5_000_000_000, now);
+        guard.observe(now - 20_000_000, now);
+
+        let stats = guard.stats();
+        assert_eq!(stats.live_samples, 3);
+        assert_eq!(stats.last_skew_ms, Some(-20));
+        assert_eq!(stats.max_skew_ms, Some(5_000));
+        assert_eq!(stats.ahead_samples, 1);
+        assert_eq!(stats.warn_threshold_ms, 2_000);
+    }
+}
@@ -0,0 +1,238 @@
+//! Previous session closes, for net and percent change.
+//!
+//! Day summaries, rollup bars and leaderboard movers carry a `day_change`
+//! against the close of the session before theirs (`PROFILE_SESSION`
+//! sessions, as for day summaries), so clients needn't fetch it with
+//! historical queries of their own. Each symbol's close is looked up once
+//! per session from the provider's minute bars (see
+//! [`shared::change::session_close`]) and kept while the server runs. A
+//! lookup that fails is retried after [`RETRY_AFTER`]; until then the
+//! change is left out.
+
+use crate::service::MarketDataService;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use shared::change::session_close;
+use shared::sessions::{Session, SessionSpec};
+use shared::{HistoricalRequest, HistoricalResponse, Schema, SymbolType};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How long a failed lookup waits before it's tried again.
+pub const RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Most minute bars fetched for one session's close (a day of every listed
+/// contract of a parent symbol).
+const CLOSE_BAR_LIMIT: u32 = 100_000;
+
+/// Closes kept before earlier sessions' are pruned.
+const PRUNE_AT: usize = 10_000;
+
+/// What's known of one symbol's close of one session.
+#[derive(Debug, Clone, Copy)]
+enum Lookup {
+    /// Being fetched in the background
+    Pending,
+    /// `None` when nothing traded
+    Done(Option<i64>),
+    Failed(Instant),
+}
+
+/// Previous session closes by symbol, looked up on first use.
+pub struct PreviousCloses {
+    service: Arc<dyn MarketDataService>,
+    session: SessionSpec,
+    closes: Mutex<HashMap<(String, NaiveDate), Lookup>>,
+}
+
+impl PreviousCloses {
+    /// Closes of `session`'s sessions, looked up from `service`.
+    pub fn new(service: Arc<dyn MarketDataService>, session: SessionSpec) -> Self {
+        Self {
+            service,
+            session,
+            closes: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<(String, NaiveDate), Lookup>> {
+        self.closes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The session before the one open at `ts_unix_ns`, or before the last
+    /// one to close if the market is shut.
+    fn previous_session(&self, ts_unix_ns: u64) -> Option<Session> {
+        let time = DateTime::from_timestamp_nanos(ts_unix_ns.min(i64::MAX as u64) as i64);
+        self.session.previous(&self.session.latest(time)?)
+    }
+
+    /// `symbol`'s close of the session before the one at `ts_unix_ns`,
+    /// fetched if it isn't known yet.
+    pub async fn close(&self, symbol: &str, ts_unix_ns: u64) -> Option<i64> {
+        let session = self.previous_session(ts_unix_ns)?;
+        let key = (symbol.to_string(), session.trading_day);
+        match self.lock().get(&key) {
+            Some(Lookup::Done(close)) => return *close,
+            Some(Lookup::Failed(at)) if at.elapsed() < RETRY_AFTER => return None,
+            _ => {}
+        }
+        self.fetch(key, session).await
+    }
+
+    /// `symbol`'s close of the session before the one at `ts_unix_ns` if
+    /// it's known, for callers that can't wait on the provider. An unknown
+    /// close is fetched in the background for the next call.
+    pub fn cached(self: &Arc<Self>, symbol: &str, ts_unix_ns: u64) -> Option<i64> {
+        let session = self.previous_session(ts_unix_ns)?;
+        let key = (symbol.to_string(), session.trading_day);
+        {
+            let mut closes = self.lock();
+            match closes.get(&key) {
+                Some(Lookup::Done(close)) => return *close,
+                Some(Lookup::Pending) => return None,
+                Some(Lookup::Failed(at)) if at.elapsed() < RETRY_AFTER => return None,
+                _ => {}
+            }
+            closes.insert(key.clone(), Lookup::Pending);
+        }
+        let closes = self.clone();
+        tokio::spawn(async move { closes.fetch(key, session).await });
+        None
+    }
+
+    async fn fetch(&self, key: (String, NaiveDate), session: Session) -> Option<i64> {
+        let rfc3339 = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let req = HistoricalRequest {
+            symbols: vec![key.0.clone()],
+            schema: Schema::Ohlcv1M.as_str().to_string(),
+            stype_in: stype_of(&key.0),
+            start_rfc3339: rfc3339(session.open),
+            end_rfc3339: rfc3339(session.close),
+            limit: CLOSE_BAR_LIMIT,
+            ..Default::default()
+        };
+        let lookup = match self.service.get_historical(&req).await {
+            Ok(HistoricalResponse::Ohlcv1M { data }) => Lookup::Done(session_close(&data)),
+            Ok(_) => Lookup::Done(None),
+            Err(e) => {
+                warn!(symbol = %key.0, trading_day = %key.1, "Previous close lookup failed: {}", e);
+                Lookup::Failed(Instant::now())
+            }
+        };
+
+        let mut closes = self.lock();
+        if closes.len() >= PRUNE_AT {
+            closes.retain(|(_, day), _| *day >= key.1);
+        }
+        closes.insert(key, lookup);
+        match lookup {
+            Lookup::Done(close) => close,
+            _ => None,
+        }
+    }
+}
+
+/// The symbol type `symbol` is written in: `ES.FUT` is a parent,
+/// `ES.c.0` continuous, `4916` an instrument ID and anything else a raw
+/// symbol, as live trades carry them.
+fn stype_of(symbol: &str) -> SymbolType {
+    if symbol.ends_with(".FUT") || symbol.ends_with(".OPT") {
+        SymbolType::Parent
+    } else if [".c.", ".n.", ".v."]
+        .iter()
+        .any(|rule| symbol.contains(rule))
+    {
+        SymbolType::Continuous
+    } else if !symbol.is_empty() && symbol.bytes().all(|b| b.is_ascii_digit()) {
+        SymbolType::InstrumentId
+    } else {
+        SymbolType::RawSymbol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::{LiveStream, ServiceError};
+    use async_trait::async_trait;
+    use shared::OhlcvRecord;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves one bar at the requested session's close, counting requests.
+    /// Live subscriptions are refused.
+    struct Closing(AtomicUsize);
+
+    #[async_trait]
+    impl MarketDataService for Closing {
+        async fn get_historical(
+            &self,
+            req: &HistoricalRequest,
+        ) -> Result<HistoricalResponse, ServiceError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(req.stype_in, SymbolType::Parent);
+            let end = DateTime::parse_from_rfc3339(&req.end_rfc3339).unwrap();
+            Ok(HistoricalResponse::Ohlcv1M {
+                data: vec![OhlcvRecord {
+                    ts_event_unix_ns: end.timestamp_nanos_opt().unwrap() as u64,
+                    symbol: "ESZ4".to_string(),
+                    open_i64: 100,
+                    high_i64: 100,
+                    low_i64: 100,
+                    close_i64: 100,
+                    volume_u64: 1,
+                }],
+            })
+        }
+
+        async fn subscribe_live(
+            &self,
+            _symbols: Vec<String>,
+            _schema: String,
+            _stype_in: SymbolType,
+        ) -> Result<LiveStream, ServiceError> {
+            Err(ServiceError::NotConfigured("live".to_string()))
+        }
+
+        fn name(&self) -> &'static str {
+            "Closing"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closes_looked_up_once_per_session() {
+        let service = Arc::new(Closing(AtomicUsize::new(0)));
+        let closes = Arc::new(PreviousCloses::new(
+            service.clone(),
+            SessionSpec::parse("01:00-23:00 UTC").unwrap(),
+        ));
+        // Tuesday 2024-06-04, after the session opens: Monday's close
+        let tuesday = DateTime::parse_from_rfc3339("2024-06-04T12:00:00Z")
+            .unwrap()
+            .timestamp_nanos_opt()
+            .unwrap() as u64;
+        assert_eq!(closes.close("ES.FUT", tuesday).await, Some(100));
+        assert_eq!(closes.cached("ES.FUT", tuesday), Some(100));
+        // Before Wednesday's open the latest session is still Tuesday's
+        let overnight = tuesday + 12 * 3_600_000_000_000;
+        assert_eq!(closes.close("ES.FUT", overnight).await, Some(100));
+        assert_eq!(service.0.load(Ordering::SeqCst), 1);
+
+        // Unknown closes are fetched in the background
+        assert_eq!(closes.cached("NQ.FUT", tuesday), None);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while closes.cached("NQ.FUT", tuesday).is_none() {
+            assert!(tokio::time::Instant::now() < deadline, "never looked up");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(service.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_stype_of() {
+        assert_eq!(stype_of("ES.FUT"), SymbolType::Parent);
+        assert_eq!(stype_of("ES.c.0"), SymbolType::Continuous);
+        assert_eq!(stype_of("ESZ4"), SymbolType::RawSymbol);
+        assert_eq!(stype_of("4916"), SymbolType::InstrumentId);
+    }
+}
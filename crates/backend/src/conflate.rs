@@ -0,0 +1,334 @@
+//! Adaptive conflation for live clients that fall behind.
+//!
+//! A subscription with `adaptive` set reads its stream ahead into a queue
+//! ([`queued`]), and each message is timed from being queued to being
+//! taken for sending. When that lag passes [`RAISE_AT`] the connection
+//! steps up to the next of [`LEVELS_MS`] and its trades, bars, day
+//! summaries and metrics are conflated (see [`Conflator`]) and sent once
+//! per interval; once the lag has stayed under [`LOWER_BELOW`] for
+//! [`CALM_FOR`] it steps back down. Each change is announced with a
+//! [`LiveMessage::RateAdjusted`].
+
+use crate::service::LiveStream;
+use futures::{Stream, StreamExt};
+use shared::LiveMessage;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// Conflation intervals a connection steps through, in milliseconds.
+pub const LEVELS_MS: [u64; 3] = [0, 100, 500];
+
+/// Lag that steps conflation up.
+pub const RAISE_AT: Duration = Duration::from_millis(250);
+
+/// Lag that, held for [`CALM_FOR`], steps conflation down.
+pub const LOWER_BELOW: Duration = Duration::from_millis(50);
+pub const CALM_FOR: Duration = Duration::from_secs(10);
+
+/// Shortest time between two steps up, so a step has time to take effect.
+const SETTLE_FOR: Duration = Duration::from_secs(2);
+
+/// Messages read ahead of the socket.
+pub const QUEUE_CAPACITY: usize = 4096;
+
+/// Live messages, each with when it was queued.
+pub type QueuedStream = Pin<Box<dyn Stream<Item = (Instant, LiveMessage)> + Send>>;
+
+/// `stream` read ahead by a task of its own into a queue of `capacity`
+/// messages, each with when it was queued. The task stops when the queue
+/// is dropped.
+pub fn queued(mut stream: LiveStream, capacity: usize) -> QueuedStream {
+    let (tx, rx) = mpsc::channel(capacity);
+    let reader = tokio::spawn(async move {
+        while let Some(msg) = stream.next().await {
+            if tx.send((Instant::now(), msg)).await.is_err() {
+                break;
+            }
+        }
+    });
+    Box::pin(Queue { rx, reader })
+}
+
+struct Queue {
+    rx: mpsc::Receiver<(Instant, LiveMessage)>,
+    reader: JoinHandle<()>,
+}
+
+impl Stream for Queue {
+    type Item = (Instant, LiveMessage);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for Queue {
+    // A quiet upstream would otherwise hold the reader, and the
+    // subscription, until its next message
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// A timer for flushing held messages every `every`, from `every` on.
+pub fn flush_every(every: Duration) -> Interval {
+    let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    timer
+}
+
+/// What a conflated message replaces: the same symbol's earlier trades,
+/// the same bar's earlier updates, and so on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Trade(String),
+    Bar(String, Option<String>, u64),
+    DaySummary(String),
+    Metric(String, String, String),
+}
+
+impl Key {
+    fn of(msg: &LiveMessage) -> Option<Key> {
+        match msg {
+            LiveMessage::Trade { symbol, .. } => Some(Key::Trade(symbol.clone())),
+            LiveMessage::Ohlcv {
+                symbol,
+                interval,
+                ts_event_unix_ns,
+                ..
+            } => Some(Key::Bar(
+                symbol.clone(),
+                interval.clone(),
+                *ts_event_unix_ns,
+            )),
+            LiveMessage::DaySummary(summary) => Some(Key::DaySummary(summary.symbol.clone())),
+            LiveMessage::Metric {
+                symbol,
+                name,
+                window,
+                ..
+            } => Some(Key::Metric(symbol.clone(), name.clone(), window.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Holds the latest of each conflatable message until it's flushed.
+///
+/// Trades of a symbol merge into one at the latest price and time, with
+/// their sizes summed; a bar, day summary or metric replaces the one
+/// before it. Other messages pass straight through.
+#[derive(Debug, Default)]
+pub struct Conflator {
+    /// In order of each key's first message since the last flush
+    pending: Vec<LiveMessage>,
+    slots: HashMap<Key, usize>,
+}
+
+impl Conflator {
+    /// Hold `msg` if it conflates, or hand it back to send now. A trade
+    /// batch is taken apart and its trades held.
+    pub fn push(&mut self, msg: LiveMessage) -> Option<LiveMessage> {
+        if let LiveMessage::TradeBatch { trades } = msg {
+            for trade in trades {
+                self.push(LiveMessage::Trade {
+                    ts_event_unix_ns: trade.ts_event_unix_ns,
+                    symbol: trade.symbol,
+                    price_i64: trade.price_i64,
+                    size_u32: trade.size_u32,
+                });
+            }
+            return None;
+        }
+        let Some(key) = Key::of(&msg) else {
+            return Some(msg);
+        };
+        match self.slots.get(&key) {
+            Some(&slot) => {
+                let held = &mut self.pending[slot];
+                let size = match (&*held, &msg) {
+                    (
+                        LiveMessage::Trade { size_u32: a, .. },
+                        LiveMessage::Trade { size_u32: b, .. },
+                    ) => Some(a.saturating_add(*b)),
+                    _ => None,
+                };
+                *held = msg;
+                if let (Some(size), LiveMessage::Trade { size_u32, .. }) = (size, held) {
+                    *size_u32 = size;
+                }
+            }
+            None => {
+                self.slots.insert(key, self.pending.len());
+                self.pending.push(msg);
+            }
+        }
+        None
+    }
+
+    /// Everything held, oldest first.
+    pub fn flush(&mut self) -> Vec<LiveMessage> {
+        self.slots.clear();
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// One connection's conflation level, adjusted to its lag.
+#[derive(Debug)]
+pub struct AdaptiveRate {
+    level: usize,
+    changed_at: Instant,
+    /// Since when the lag has been under [`LOWER_BELOW`]
+    calm_since: Option<Instant>,
+    conflator: Conflator,
+}
+
+impl AdaptiveRate {
+    /// Unconflated, as of `now`.
+    pub fn new(now: Instant) -> Self {
+        Self {
+            level: 0,
+            changed_at: now,
+            calm_since: None,
+            conflator: Conflator::default(),
+        }
+    }
+
+    /// How often held messages are flushed; `None` when nothing is held.
+    pub fn interval(&self) -> Option<Duration> {
+        match LEVELS_MS[self.level] {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Note that a message waited `lag` to be sent. Returns the
+    /// [`LiveMessage::RateAdjusted`] to send if the level changed; the
+    /// caller then flushes what's held and restarts its flush timer.
+    pub fn observe(&mut self, lag: Duration, now: Instant) -> Option<LiveMessage> {
+        let previous = self.level;
+        if lag > RAISE_AT {
+            self.calm_since = None;
+            if self.level + 1 < LEVELS_MS.len() && now >= self.changed_at + SETTLE_FOR {
+                self.level += 1;
+            }
+        } else if lag < LOWER_BELOW {
+            let calm_since = *self.calm_since.get_or_insert(now);
+            if self.level > 0 && now >= calm_since + CALM_FOR {
+                self.level -= 1;
+                self.calm_since = Some(now);
+            }
+        } else {
+            self.calm_since = None;
+        }
+        if self.level == previous {
+            return None;
+        }
+        self.changed_at = now;
+        Some(LiveMessage::RateAdjusted {
+            conflation_ms: LEVELS_MS[self.level],
+            previous_ms: LEVELS_MS[previous],
+            lag_ms: lag.as_millis() as u64,
+        })
+    }
+
+    /// Hold `msg` if this connection is conflating and it conflates, or
+    /// hand it back to send now.
+    pub fn push(&mut self, msg: LiveMessage) -> Option<LiveMessage> {
+        match self.level {
+            0 => Some(msg),
+            _ => self.conflator.push(msg),
+        }
+    }
+
+    /// Everything held, oldest first.
+    pub fn flush(&mut self) -> Vec<LiveMessage> {
+        self.conflator.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(symbol: &str, price_i64: i64, size_u32: u32) -> LiveMessage {
+        LiveMessage::Trade {
+            ts_event_unix_ns: price_i64 as u64,
+            symbol: symbol.to_string(),
+            price_i64,
+            size_u32,
+        }
+    }
+
+    #[test]
+    fn test_conflator() {
+        let mut conflator = Conflator::default();
+        assert!(conflator.push(trade("ESZ4", 100, 1)).is_none());
+        assert!(conflator.push(trade("NQZ4", 200, 1)).is_none());
+        assert!(conflator.push(trade("ESZ4", 101, 2)).is_none());
+        let batch = LiveMessage::TradeBatch {
+            trades: vec![shared::TradeRecord {
+                ts_event_unix_ns: 102,
+                symbol: "ESZ4".to_string(),
+                price_i64: 102,
+                size_u32: 1,
+                quote: None,
+                sequence_u32: None,
+            }],
+        };
+        assert!(conflator.push(batch).is_none());
+        let error = LiveMessage::Error {
+            message: "down".to_string(),
+        };
+        assert!(conflator.push(error).is_some());
+
+        let flushed = conflator.flush();
+        assert_eq!(flushed.len(), 2);
+        assert!(matches!(
+            &flushed[0],
+            LiveMessage::Trade { symbol, price_i64: 102, size_u32: 4, .. } if symbol == "ESZ4"
+        ));
+        assert!(conflator.flush().is_empty());
+    }
+
+    #[test]
+    fn test_steps_with_lag() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let slow = Duration::from_millis(400);
+        let fast = Duration::from_millis(5);
+        let mut rate = AdaptiveRate::new(start);
+        assert!(rate.push(trade("ESZ4", 100, 1)).is_some());
+
+        // Up one step, then not again until the first has settled
+        let Some(LiveMessage::RateAdjusted {
+            conflation_ms,
+            previous_ms,
+            lag_ms,
+        }) = rate.observe(slow, at(2))
+        else {
+            panic!("expected a step up");
+        };
+        assert_eq!((conflation_ms, previous_ms, lag_ms), (100, 0, 400));
+        assert_eq!(rate.interval(), Some(Duration::from_millis(100)));
+        assert!(rate.observe(slow, at(3)).is_none());
+        assert!(rate.observe(slow, at(4)).is_some());
+        assert!(rate.observe(slow, at(10)).is_none());
+        assert!(rate.push(trade("ESZ4", 100, 1)).is_none());
+
+        // Down a step per quiet spell, a slow message starting it over
+        assert!(rate.observe(fast, at(11)).is_none());
+        assert!(rate.observe(slow, at(15)).is_none());
+        assert!(rate.observe(fast, at(16)).is_none());
+        assert!(rate.observe(fast, at(26)).is_some());
+        assert!(rate.observe(fast, at(30)).is_none());
+        assert!(rate.observe(fast, at(36)).is_some());
+        assert_eq!(rate.interval(), None);
+        assert_eq!(rate.flush().len(), 1);
+    }
+}
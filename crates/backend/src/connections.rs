@@ -0,0 +1,185 @@
+//! Registry of open live WebSocket sessions.
+//!
+//! Each session gets an ID, sent to the client in its `connected` message,
+//! and records the `symbol_mapping` messages its stream carries. That is the
+//! same view the provider's point-in-time symbol map has, so
+//! `GET /api/connections/{id}/symbols` can show which concrete contracts a
+//! parent or continuous subscription currently resolves to.
+
+use chrono::{SecondsFormat, Utc};
+use shared::connections::{ConnectionSymbols, SymbolMapping};
+use shared::{LiveMessage, SymbolType};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+struct Session {
+    /// Usage key the session was opened with; only it may inspect the session
+    key_id: String,
+    symbols: Vec<String>,
+    schema: String,
+    stype_in: SymbolType,
+    connected_at: String,
+    mappings: BTreeMap<u32, SymbolMapping>,
+}
+
+/// Open live sessions, keyed by ID.
+#[derive(Default)]
+pub struct ConnectionRegistry {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, Session>>,
+}
+
+impl ConnectionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn sessions(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Register a session. It is removed when the returned handle drops.
+    pub fn open(
+        self: &Arc<Self>,
+        key_id: &str,
+        symbols: &[String],
+        schema: &str,
+        stype_in: SymbolType,
+    ) -> Connection {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sessions().insert(
+            id,
+            Session {
+                key_id: key_id.to_string(),
+                symbols: symbols.to_vec(),
+                schema: schema.to_string(),
+                stype_in,
+                connected_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                mappings: BTreeMap::new(),
+            },
+        );
+        Connection {
+            registry: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// Number of open sessions.
+    pub fn len(&self) -> usize {
+        self.sessions().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Symbol mappings of session `id`, if it is open and was opened with
+    /// `key_id`.
+    pub fn symbols(&self, id: u64, key_id: &str) -> Option<ConnectionSymbols> {
+        let sessions = self.sessions();
+        let session = sessions.get(&id).filter(|s| s.key_id == key_id)?;
+        Some(ConnectionSymbols {
+            id,
+            symbols: session.symbols.clone(),
+            schema: session.schema.clone(),
+            stype_in: session.stype_in,
+            connected_at: session.connected_at.clone(),
+            mappings: session.mappings.values().cloned().collect(),
+        })
+    }
+}
+
+/// Handle to a registered session.
+pub struct Connection {
+    registry: Arc<ConnectionRegistry>,
+    id: u64,
+}
+
+impl Connection {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Note a message sent on this session: tag `connected` with the
+    /// session ID and record symbol mappings.
+    pub fn observe(&self, msg: &mut LiveMessage) {
+        match msg {
+            LiveMessage::Connected { connection_id, .. } => *connection_id = Some(self.id),
+            LiveMessage::SymbolMapping {
+                instrument_id,
+                stype_in_symbol,
+                stype_out_symbol,
+            } => {
+                if let Some(session) = self.registry.sessions().get_mut(&self.id) {
+                    session.mappings.insert(
+                        *instrument_id,
+                        SymbolMapping {
+                            instrument_id: *instrument_id,
+                            stype_in_symbol: stype_in_symbol.clone(),
+                            stype_out_symbol: stype_out_symbol.clone(),
+                            updated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.registry.sessions().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(instrument_id: u32, raw: &str) -> LiveMessage {
+        LiveMessage::SymbolMapping {
+            instrument_id,
+            stype_in_symbol: "ES.FUT".to_string(),
+            stype_out_symbol: raw.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_session_tracks_mappings() {
+        let registry = Arc::new(ConnectionRegistry::new());
+        let conn = registry.open("k1", &["ES.FUT".to_string()], "trades", SymbolType::Parent);
+
+        let mut connected = LiveMessage::Connected {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "trades".to_string(),
+            connection_id: None,
+        };
+        conn.observe(&mut connected);
+        assert!(matches!(
+            connected,
+            LiveMessage::Connected { connection_id: Some(id), .. } if id == conn.id()
+        ));
+
+        conn.observe(&mut mapping(4916, "ESZ4"));
+        conn.observe(&mut mapping(1234, "ESH5"));
+        conn.observe(&mut mapping(4916, "ESZ4"));
+        let symbols = registry.symbols(conn.id(), "k1").unwrap();
+        assert_eq!(symbols.stype_in, SymbolType::Parent);
+        let raw: Vec<&str> = symbols
+            .mappings
+            .iter()
+            .map(|m| m.stype_out_symbol.as_str())
+            .collect();
+        assert_eq!(raw, ["ESH5", "ESZ4"]);
+
+        // Other keys can't see the session
+        assert!(registry.symbols(conn.id(), "k2").is_none());
+
+        let id = conn.id();
+        drop(conn);
+        assert!(registry.symbols(id, "k1").is_none());
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Encryption of secrets stored at rest.
+//!
+//! Secrets are sealed with ChaCha20-Poly1305 under a 32-byte master key and
+//! stored as hex `nonce || ciphertext`. The master key comes from the
+//! `SECRETS_KEY` environment variable or a key file created on first use;
+//! either way it must be kept apart from the files it protects.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::path::Path;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Error type for sealing and opening secrets.
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("Invalid master key: {0}")]
+    InvalidKey(String),
+    #[error("Failed to decrypt secret: {0}")]
+    Decrypt(String),
+    #[error("Master key file: {0}")]
+    Io(String),
+}
+
+/// Encrypts and decrypts secrets under one master key.
+#[derive(Clone)]
+pub struct Sealer {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Sealer {
+    /// A sealer using a random, unsaved master key (for tests).
+    pub fn ephemeral() -> Self {
+        Self::from_bytes(&rand::random::<[u8; KEY_LEN]>())
+    }
+
+    fn from_bytes(key: &[u8; KEY_LEN]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    /// A sealer using a master key given as 64 hex characters.
+    pub fn from_hex(hex_key: &str) -> Result<Self, CryptoError> {
+        let bytes =
+            hex::decode(hex_key.trim()).map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+        let key: [u8; KEY_LEN] = bytes.try_into().map_err(|_| {
+            CryptoError::InvalidKey(format!("expected {} hex characters", KEY_LEN * 2))
+        })?;
+        Ok(Self::from_bytes(&key))
+    }
+
+    /// Load the master key stored at `path`, generating it (readable only
+    /// by the current user) if the file doesn't exist.
+    pub fn load_or_create(path: &Path) -> Result<Self, CryptoError> {
+        let io_err = |e: std::io::Error| CryptoError::Io(format!("{}: {}", path.display(), e));
+        match std::fs::read_to_string(path) {
+            Ok(hex_key) => Self::from_hex(&hex_key),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = rand::random::<[u8; KEY_LEN]>();
+                write_private(path, &hex::encode(key)).map_err(io_err)?;
+                Ok(Self::from_bytes(&key))
+            }
+            Err(e) => Err(io_err(e)),
+        }
+    }
+
+    /// Encrypt `plaintext` under a fresh nonce.
+    pub fn seal(&self, plaintext: &str) -> String {
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        // Encryption only fails for inputs far beyond any secret's size
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .expect("secret too large to encrypt");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        hex::encode(sealed)
+    }
+
+    /// Decrypt a value produced by [`Sealer::seal`].
+    pub fn open(&self, sealed: &str) -> Result<String, CryptoError> {
+        let bytes = hex::decode(sealed).map_err(|e| CryptoError::Decrypt(e.to_string()))?;
+        if bytes.len() < NONCE_LEN {
+            return Err(CryptoError::Decrypt("value too short".to_string()));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::Decrypt("wrong master key or corrupted value".to_string()))?;
+        String::from_utf8(plaintext).map_err(|e| CryptoError::Decrypt(e.to_string()))
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_round_trip() {
+        let sealer = Sealer::ephemeral();
+        let sealed = sealer.seal("db-ABC123");
+        assert!(!sealed.contains("ABC123"));
+        // Fresh nonce each time
+        assert_ne!(sealed, sealer.seal("db-ABC123"));
+        assert_eq!(sealer.open(&sealed).unwrap(), "db-ABC123");
+
+        assert!(Sealer::ephemeral().open(&sealed).is_err());
+        assert!(sealer.open("00ff").is_err());
+    }
+
+    #[test]
+    fn test_key_file_is_reused() {
+        let path = std::env::temp_dir().join(format!("sf-secrets-{}.key", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let sealed = Sealer::load_or_create(&path).unwrap().seal("secret");
+        let reloaded = Sealer::load_or_create(&path).unwrap();
+        assert_eq!(reloaded.open(&sealed).unwrap(), "secret");
+
+        assert!(Sealer::from_hex("abcd").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,114 +3,294 @@
 //! This module provides the real DataBento API integration for
 //! historical and live market data.
 
-use crate::service::{LiveStream, MarketDataService, ServiceError};
+use crate::cancel;
+use crate::gateways::{GatewaySession, Gateways};
+use crate::latency::{PipelineLatency, Stage};
+use crate::routing::SymbolRouter;
+use crate::secrets::{Secrets, DATABENTO_API_KEY};
+use crate::service::{
+    checked_stype_in, historical_bars, historical_cvd, user_api_key, LiveStream, MarketDataService,
+    MboStream, ServiceError,
+};
+use crate::uploads::{DecodedFile, UploadError};
 use async_trait::async_trait;
 use databento::{
     dbn::{
-        decode::DbnMetadata, Dataset, OhlcvMsg, PitSymbolMap, SType, Schema as DbSchema,
-        SymbolIndex, TradeMsg,
+        decode::{DbnDecoder, DbnMetadata, DecodeRecord},
+        encode::{dbn::Encoder, DynWriter, EncodeRecord, EncodeRecordRef},
+        Compression, MappingInterval, MboMsg, Mbp10Msg, Mbp1Msg, Metadata, OhlcvMsg, PitSymbolMap,
+        Record, RecordRef, SType, Schema as DbSchema, SymbolIndex, SymbolMapping, SymbolMappingMsg,
+        TradeMsg, TsSymbolMap, UNDEF_PRICE,
+    },
+    historical::{
+        metadata::{GetCostParams, GetDatasetConditionParams, GetRecordCountParams},
+        symbology::ResolveParams,
+        timeseries::GetRangeParams,
     },
-    historical::timeseries::GetRangeParams,
     live::Subscription,
-    HistoricalClient, LiveClient,
+    HistoricalClient, LiveClient, Symbols,
+};
+use futures::StreamExt;
+use shared::aggregate::BarBuckets;
+use shared::book::{BookSide, MboAction, MboRecord};
+use shared::depth::{DepthLevel, DepthUpdate};
+use shared::validation::DatasetRoute;
+use shared::{
+    DataVersion, HistoricalRequest, HistoricalResponse, LiveMessage, OhlcvRecord, QuoteAtTrade,
+    Schema, SymbolType, TradeRecord,
 };
-use shared::{HistoricalRequest, HistoricalResponse, LiveMessage, OhlcvRecord, Schema, TradeRecord};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
 use std::num::NonZeroU64;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
-use tracing::{error, info, warn};
+use tracing::{error, info, instrument, warn, Span};
+
+/// How long a dataset's last modification date is reused before it's
+/// asked for again.
+const VERSION_TTL: Duration = Duration::from_secs(600);
+
+/// A dataset and a date range, end exclusive.
+type VersionKey = (String, time::Date, time::Date);
 
 /// DataBento service for real market data.
 ///
 /// # Configuration
-/// Requires a DataBento API key.
+/// Uses the requesting user's own DataBento key when they have registered
+/// one (see [`crate::users`]), otherwise the server's shared key from
+/// [`Secrets`], which can be rotated while running.
 ///
 /// # Supported Features
 /// - Historical trades and OHLCV data
 /// - Live streaming trades
+/// - Parent, continuous, raw and instrument-ID symbology (`stype_in`);
+///   instrument-ID requests skip symbol mapping and label records with the ID
+///
+/// # Datasets
+/// Symbols are routed to datasets by a [`SymbolRouter`] (e.g. `*.FUT` to
+/// `GLBX.MDP3`). Requests spanning several datasets are fetched per dataset
+/// and merged.
 ///
-/// # Dataset
-/// Default dataset: `GLBX.MDP3` (CME Globex)
+/// # Data versions
+/// DataBento reports when it last modified each day of a dataset, so a
+/// request's [`DataVersion`]s are the latest of those dates over its range.
+/// Only the latest revision can be fetched: a request pinned to an older
+/// one is refused.
+///
+/// # DBN tee
+/// With [`DatabentoService::with_dbn_tee`], every live session also writes
+/// its raw records back out as DBN (see [`DbnTee`]).
+///
+/// # Gateways
+/// With [`DatabentoService::with_gateways`], live sessions connect to the
+/// preferred of several gateways, failing over to the next (see
+/// [`crate::gateways`]); otherwise to DataBento's default for the dataset.
 pub struct DatabentoService {
-    api_key: String,
-    dataset: Dataset,
+    secrets: Arc<Secrets>,
+    router: SymbolRouter,
+    dbn_tee: Option<PathBuf>,
+    gateways: Option<Arc<Gateways>>,
+    /// Live records' decode times are recorded here when set
+    latency: Option<Arc<PipelineLatency>>,
+    /// Last modification dates looked up, and when
+    versions: Mutex<HashMap<VersionKey, (Instant, Option<time::Date>)>>,
 }
 
 impl DatabentoService {
-    /// Create a new DataBento service with the given API key.
-    pub fn new(api_key: String) -> Self {
-        assert!(!api_key.is_empty(), "API key cannot be empty");
-
-        info!("Initializing DataBento service with GLBX.MDP3 dataset");
+    /// Create a new DataBento service reading the shared API key from
+    /// `secrets`. Without one, only users with their own key can make
+    /// requests.
+    pub fn new(secrets: Arc<Secrets>) -> Self {
+        info!("Initializing DataBento service");
 
         Self {
-            api_key,
-            dataset: Dataset::GlbxMdp3, // CME Globex
+            secrets,
+            router: SymbolRouter::default(),
+            dbn_tee: None,
+            gateways: None,
+            latency: None,
+            versions: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Replace the symbol-to-dataset routing table.
+    pub fn with_router(mut self, router: SymbolRouter) -> Self {
+        self.router = router;
+        self
+    }
+
+    /// Write every live session's records to DBN files under `dir`.
+    pub fn with_dbn_tee(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dbn_tee = Some(dir.into());
+        self
+    }
+
+    /// Connect live sessions through `gateways`.
+    pub fn with_gateways(mut self, gateways: Arc<Gateways>) -> Self {
+        self.gateways = Some(gateways);
+        self
+    }
+
+    /// Record how long each live record takes to decode in `latency`.
+    pub fn with_latency(mut self, latency: Arc<PipelineLatency>) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
     /// Map our schema string to DataBento's Schema enum.
     fn map_schema(schema: &str) -> Result<DbSchema, ServiceError> {
         match schema {
             "trades" => Ok(DbSchema::Trades),
             "ohlcv-1s" => Ok(DbSchema::Ohlcv1S),
             "ohlcv-1m" => Ok(DbSchema::Ohlcv1M),
+            "mbp-10" => Ok(DbSchema::Mbp10),
+            "mbo" => Ok(DbSchema::Mbo),
             _ => Err(ServiceError::InvalidSchema(format!(
-                "Unknown schema: {}. Expected: trades, ohlcv-1s, or ohlcv-1m",
+                "Unknown schema: {}. Expected: trades, ohlcv-1s, ohlcv-1m, mbp-10, or mbo",
                 schema
             ))),
         }
     }
 
+    /// Map our symbol type to DataBento's.
+    fn map_stype(stype_in: SymbolType) -> SType {
+        match stype_in {
+            SymbolType::Parent => SType::Parent,
+            SymbolType::Continuous => SType::Continuous,
+            SymbolType::RawSymbol => SType::RawSymbol,
+            SymbolType::InstrumentId => SType::InstrumentId,
+        }
+    }
+
+    /// Symbols as sent upstream: instrument IDs go as numbers.
+    fn upstream_symbols(symbols: Vec<String>, stype_in: SymbolType) -> Symbols {
+        match stype_in {
+            // Already checked by `SymbolType::validate`
+            SymbolType::InstrumentId => {
+                Symbols::Ids(symbols.iter().filter_map(|s| s.parse().ok()).collect())
+            }
+            _ => Symbols::Symbols(symbols),
+        }
+    }
+
     /// Parse RFC3339 timestamp string to OffsetDateTime.
     fn parse_timestamp(ts: &str) -> Result<OffsetDateTime, ServiceError> {
         OffsetDateTime::parse(ts, &Rfc3339).map_err(|e| {
             ServiceError::InvalidTimeFormat(format!("Invalid RFC3339 timestamp '{}': {}", ts, e))
         })
     }
-}
-
-#[async_trait]
-impl MarketDataService for DatabentoService {
-    async fn get_historical(
-        &self,
-        req: &HistoricalRequest,
-    ) -> Result<HistoricalResponse, ServiceError> {
-        info!(
-            symbols = ?req.symbols,
-            schema = %req.schema,
-            start = %req.start_rfc3339,
-            end = %req.end_rfc3339,
-            limit = req.limit,
-            "DataBento historical request"
-        );
 
-        // Parse inputs
-        let db_schema = Self::map_schema(&req.schema)?;
-        let start = Self::parse_timestamp(&req.start_rfc3339)?;
-        let end = Self::parse_timestamp(&req.end_rfc3339)?;
+    /// The key to use for the current request: the user's, else the shared one.
+    fn api_key(&self) -> Result<String, ServiceError> {
+        user_api_key()
+            .or_else(|| self.secrets.get(DATABENTO_API_KEY))
+            .ok_or_else(|| {
+                ServiceError::NotConfigured(
+                    "no DataBento API key; register one with PUT /api/me/databento-key".to_string(),
+                )
+            })
+    }
 
-        // Parse our schema enum for response building
-        let schema: Schema = req
-            .schema
-            .parse()
-            .map_err(|e: String| ServiceError::InvalidSchema(e))?;
+    /// When DataBento last modified `dataset` from `start` until `end`,
+    /// reusing a lookup made within [`VERSION_TTL`].
+    async fn last_modified(
+        &self,
+        dataset: &str,
+        start: time::Date,
+        end: time::Date,
+    ) -> Result<Option<time::Date>, ServiceError> {
+        let key = (dataset.to_string(), start, end);
+        let cached = self.versions.lock().unwrap().get(&key).copied();
+        if let Some((_, modified)) = cached.filter(|(at, _)| at.elapsed() < VERSION_TTL) {
+            return Ok(modified);
+        }
+        let params = GetDatasetConditionParams::builder()
+            .dataset(dataset)
+            .date_range((start, end))
+            .build();
+        let conditions = self
+            .historical_client()?
+            .metadata()
+            .get_dataset_condition(&params)
+            .await
+            .map_err(|e| {
+                ServiceError::ApiError(format!("Dataset condition request failed: {}", e))
+            })?;
+        let modified = conditions
+            .iter()
+            .filter_map(|day| day.last_modified_date)
+            .max();
+        let mut versions = self.versions.lock().unwrap();
+        versions.retain(|_, (at, _)| at.elapsed() < VERSION_TTL);
+        versions.insert(key, (Instant::now(), modified));
+        Ok(modified)
+    }
 
-        // Build historical client
-        let mut client = HistoricalClient::builder()
-            .key(self.api_key.clone())
+    /// Build a historical API client.
+    fn historical_client(&self) -> Result<HistoricalClient, ServiceError> {
+        HistoricalClient::builder()
+            .key(self.api_key()?)
             .map_err(|e| ServiceError::ApiError(format!("Failed to create client: {}", e)))?
             .build()
-            .map_err(|e| ServiceError::ApiError(format!("Failed to build client: {}", e)))?;
+            .map_err(|e| ServiceError::ApiError(format!("Failed to build client: {}", e)))
+    }
 
+    /// Group symbols by dataset. An explicit dataset takes every symbol.
+    fn route(
+        &self,
+        symbols: &[String],
+        dataset: Option<&str>,
+    ) -> Result<Vec<(String, Vec<String>)>, ServiceError> {
+        match dataset {
+            Some(dataset) => Ok(vec![(dataset.to_string(), symbols.to_vec())]),
+            None => self
+                .router
+                .group(symbols)
+                .map_err(ServiceError::InvalidSymbol),
+        }
+    }
+
+    /// Fetch one dataset's share of a historical request.
+    ///
+    /// With `DbSchema::Mbp1`, trades are taken from the book feed so each
+    /// carries the BBO it printed against. The upstream limit can't be used
+    /// then, as it would count book updates rather than trades.
+    ///
+    /// Instrument-ID requests skip the symbol map: records are labelled
+    /// with their ID, so results don't depend on symbology resolution.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(
+        name = "decode",
+        skip_all,
+        fields(dataset = %dataset, schema = %schema.as_str(), records)
+    )]
+    async fn fetch_dataset(
+        client: &mut HistoricalClient,
+        dataset: &str,
+        symbols: Vec<String>,
+        stype_in: SymbolType,
+        schema: &Schema,
+        db_schema: DbSchema,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        limit: u32,
+    ) -> Result<HistoricalResponse, ServiceError> {
         // Build request parameters
         let params = GetRangeParams::builder()
-            .dataset(self.dataset)
+            .dataset(dataset)
             .date_time_range((start, end))
-            .symbols(req.symbols.clone())
+            .symbols(Self::upstream_symbols(symbols, stype_in))
             .schema(db_schema)
-            .stype_in(SType::Parent) // Using parent symbols like "ES.FUT"
-            .limit(NonZeroU64::new(req.limit as u64))
+            .stype_in(Self::map_stype(stype_in))
+            .limit(if db_schema == DbSchema::Mbp1 {
+                None
+            } else {
+                NonZeroU64::new(limit as u64)
+            })
             .build();
 
         // Fetch data
@@ -121,16 +301,72 @@ impl MarketDataService for DatabentoService {
             .map_err(|e| ServiceError::ApiError(format!("API request failed: {}", e)))?;
 
         // Get symbol map for resolving instrument IDs to symbols
-        let symbol_map = decoder
-            .metadata()
-            .symbol_map_for_date(start.date())
-            .map_err(|e| {
-                warn!("Failed to get symbol map: {}", e);
-                ServiceError::ApiError(format!("Symbol map error: {}", e))
-            })?;
+        let symbol_map = if stype_in == SymbolType::InstrumentId {
+            None
+        } else {
+            Some(
+                decoder
+                    .metadata()
+                    .symbol_map_for_date(start.date())
+                    .map_err(|e| {
+                        warn!("Failed to get symbol map: {}", e);
+                        ServiceError::ApiError(format!("Symbol map error: {}", e))
+                    })?,
+            )
+        };
+        let resolve = |instrument_id: u32| match &symbol_map {
+            Some(map) => map
+                .get(instrument_id)
+                .map(|s: &String| s.to_string())
+                .unwrap_or_else(|| format!("ID:{}", instrument_id)),
+            None => instrument_id.to_string(),
+        };
 
         // Process records based on schema
-        match schema {
+        let response = match schema {
+            Schema::Trades if db_schema == DbSchema::Mbp1 => {
+                let mut trades = Vec::new();
+
+                while let Some(record) = decoder
+                    .decode_record::<Mbp1Msg>()
+                    .await
+                    .map_err(|e| ServiceError::ApiError(format!("Decode error: {}", e)))?
+                {
+                    // Stops decoding, and the download, once cancelled
+                    cancel::checkpoint()?;
+                    // Only trade events; the rest are book updates
+                    if record.action as u8 != b'T' {
+                        continue;
+                    }
+
+                    let symbol = resolve(record.hd.instrument_id);
+
+                    // BBO is undefined when one side of the book is empty
+                    let level = &record.levels[0];
+                    let quote = (level.bid_px != UNDEF_PRICE && level.ask_px != UNDEF_PRICE)
+                        .then(|| QuoteAtTrade::classify(record.price, level.bid_px, level.ask_px));
+
+                    trades.push(TradeRecord {
+                        ts_event_unix_ns: record.hd.ts_event,
+                        symbol,
+                        price_i64: record.price,
+                        size_u32: record.size,
+                        quote,
+                        sequence_u32: Some(record.sequence),
+                    });
+
+                    if trades.len() >= limit as usize {
+                        break;
+                    }
+                }
+
+                info!(
+                    dataset,
+                    count = trades.len(),
+                    "Fetched quoted trades from DataBento"
+                );
+                Ok(HistoricalResponse::Trades { data: trades })
+            }
             Schema::Trades => {
                 let mut trades = Vec::new();
 
@@ -139,25 +375,29 @@ impl MarketDataService for DatabentoService {
                     .await
                     .map_err(|e| ServiceError::ApiError(format!("Decode error: {}", e)))?
                 {
+                    cancel::checkpoint()?;
                     // Resolve symbol from instrument ID
-                    let symbol = symbol_map
-                        .get(record.hd.instrument_id)
-                        .map(|s: &String| s.to_string())
-                        .unwrap_or_else(|| format!("ID:{}", record.hd.instrument_id));
+                    let symbol = resolve(record.hd.instrument_id);
 
                     trades.push(TradeRecord {
                         ts_event_unix_ns: record.hd.ts_event,
                         symbol,
                         price_i64: record.price,
                         size_u32: record.size,
+                        quote: None,
+                        sequence_u32: Some(record.sequence),
                     });
 
-                    if trades.len() >= req.limit as usize {
+                    if trades.len() >= limit as usize {
                         break;
                     }
                 }
 
-                info!(count = trades.len(), "Fetched trades from DataBento");
+                info!(
+                    dataset,
+                    count = trades.len(),
+                    "Fetched trades from DataBento"
+                );
                 Ok(HistoricalResponse::Trades { data: trades })
             }
             Schema::Ohlcv1S | Schema::Ohlcv1M => {
@@ -168,10 +408,8 @@ impl MarketDataService for DatabentoService {
                     .await
                     .map_err(|e| ServiceError::ApiError(format!("Decode error: {}", e)))?
                 {
-                    let symbol = symbol_map
-                        .get(record.hd.instrument_id)
-                        .map(|s: &String| s.to_string())
-                        .unwrap_or_else(|| format!("ID:{}", record.hd.instrument_id));
+                    cancel::checkpoint()?;
+                    let symbol = resolve(record.hd.instrument_id);
 
                     bars.push(OhlcvRecord {
                         ts_event_unix_ns: record.hd.ts_event,
@@ -183,12 +421,16 @@ impl MarketDataService for DatabentoService {
                         volume_u64: record.volume,
                     });
 
-                    if bars.len() >= req.limit as usize {
+                    if bars.len() >= limit as usize {
                         break;
                     }
                 }
 
-                info!(count = bars.len(), "Fetched OHLCV bars from DataBento");
+                info!(
+                    dataset,
+                    count = bars.len(),
+                    "Fetched OHLCV bars from DataBento"
+                );
 
                 match schema {
                     Schema::Ohlcv1S => Ok(HistoricalResponse::Ohlcv1S { data: bars }),
@@ -196,65 +438,141 @@ impl MarketDataService for DatabentoService {
                     _ => unreachable!(),
                 }
             }
+            Schema::Cvd1S | Schema::Cvd1M | Schema::Bars(_) => Err(ServiceError::InvalidSchema(
+                format!("{} is derived and has no upstream schema", schema.as_str()),
+            )),
+            Schema::Mbp10 | Schema::Mbo => Err(ServiceError::InvalidSchema(format!(
+                "{} is only streamed live",
+                schema.as_str()
+            ))),
+        };
+        if let Ok(response) = &response {
+            Span::current().record("records", response.len());
         }
+        response
     }
 
-    async fn subscribe_live(
-        &self,
+    /// Count trade events in one dataset's book feed without keeping the
+    /// records. With `buckets`, count the CVD bars they would form instead:
+    /// one per instrument and bar with a trade.
+    async fn count_book_trades(
+        client: &mut HistoricalClient,
+        dataset: &str,
         symbols: Vec<String>,
-        schema: String,
-    ) -> Result<LiveStream, ServiceError> {
-        info!(
-            symbols = ?symbols,
-            schema = %schema,
-            "DataBento live subscription request"
-        );
+        stype_in: SymbolType,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        mut buckets: Option<BarBuckets>,
+    ) -> Result<u64, ServiceError> {
+        let params = GetRangeParams::builder()
+            .dataset(dataset)
+            .date_time_range((start, end))
+            .symbols(Self::upstream_symbols(symbols, stype_in))
+            .schema(DbSchema::Mbp1)
+            .stype_in(Self::map_stype(stype_in))
+            .build();
+        let mut decoder = client
+            .timeseries()
+            .get_range(&params)
+            .await
+            .map_err(|e| ServiceError::ApiError(format!("API request failed: {}", e)))?;
 
-        let db_schema = Self::map_schema(&schema)?;
-        let api_key = self.api_key.clone();
-        let dataset = self.dataset;
-        let symbols_clone = symbols.clone();
+        let mut trades = 0u64;
+        let mut bars = HashSet::new();
+        while let Some(record) = decoder
+            .decode_record::<Mbp1Msg>()
+            .await
+            .map_err(|e| ServiceError::ApiError(format!("Decode error: {}", e)))?
+        {
+            cancel::checkpoint()?;
+            if record.action as u8 != b'T' {
+                continue;
+            }
+            match buckets.as_mut() {
+                Some(buckets) => {
+                    bars.insert((record.hd.instrument_id, buckets.start(record.hd.ts_event)));
+                }
+                None => trades += 1,
+            }
+        }
+        Ok(trades + bars.len() as u64)
+    }
 
-        // Create the live stream
-        let stream = async_stream::stream! {
-            // First emit connected message
-            yield LiveMessage::Connected {
-                symbols: symbols_clone.clone(),
-                schema: schema.clone(),
+    /// A live client for `dataset`, on the first of `gateways` that takes
+    /// the connection, or on DataBento's default gateway without any.
+    async fn connect_live(
+        api_key: &str,
+        dataset: &str,
+        gateways: Option<Arc<Gateways>>,
+    ) -> Result<(LiveClient, Option<GatewaySession>), String> {
+        let builder = || {
+            LiveClient::builder()
+                .key(api_key.to_string())
+                .map(|builder| builder.dataset(dataset.to_string()))
+                .map_err(|e| format!("Failed to set API key: {}", e))
+        };
+        let Some(gateways) = gateways else {
+            return match builder()?.build().await {
+                Ok(client) => Ok((client, None)),
+                Err(e) => Err(format!("Failed to connect to {}: {}", dataset, e)),
             };
+        };
 
-            // Build live client
-            let client_builder = match LiveClient::builder().key(api_key) {
-                Ok(b) => b,
+        let mut last_error = String::from("no gateways configured");
+        for gateway in gateways.candidates() {
+            let address = gateway.address_for(dataset);
+            let connected = match builder()?.addr(address.as_str()).await {
+                Ok(builder) => builder.build().await,
+                Err(e) => Err(e),
+            };
+            match connected {
+                Ok(client) => {
+                    info!(dataset = %dataset, gateway = %gateway.name, "Live session on {}", address);
+                    return Ok((client, Some(gateways.session(&gateway.name))));
+                }
                 Err(e) => {
-                    error!("Failed to set API key: {}", e);
-                    yield LiveMessage::Error {
-                        message: format!("Failed to set API key: {}", e),
-                    };
-                    return;
+                    warn!(dataset = %dataset, gateway = %gateway.name, "Failed to connect to {}: {}", address, e);
+                    gateways.failed(&gateway.name);
+                    last_error = format!("{}: {}", gateway.name, e);
                 }
-            };
-
-            // dataset() returns the builder directly (not a Result)
-            let client_builder = client_builder.dataset(dataset);
+            }
+        }
+        Err(format!(
+            "Failed to connect to {} on any gateway (last: {})",
+            dataset, last_error
+        ))
+    }
 
-            let mut client = match client_builder.build().await {
-                Ok(c) => c,
-                Err(e) => {
-                    error!("Failed to create live client: {}", e);
-                    yield LiveMessage::Error {
-                        message: format!("Failed to connect: {}", e),
-                    };
+    /// Live stream for the symbols of a single dataset.
+    #[allow(clippy::too_many_arguments)]
+    fn dataset_stream(
+        api_key: String,
+        dataset: String,
+        symbols: Vec<String>,
+        stype_in: SymbolType,
+        db_schema: DbSchema,
+        dbn_tee: Option<PathBuf>,
+        gateways: Option<Arc<Gateways>>,
+        latency: Option<Arc<PipelineLatency>>,
+    ) -> LiveStream {
+        let stream = async_stream::stream! {
+            let (mut client, session) = match Self::connect_live(&api_key, &dataset, gateways).await {
+                Ok(connected) => connected,
+                Err(message) => {
+                    error!(dataset = %dataset, "{}", message);
+                    yield LiveMessage::Error { message };
                     return;
                 }
             };
 
-            // Subscribe
-            let subscription = Subscription::builder()
-                .symbols(symbols_clone.clone())
+            // Subscribe; order feeds start from a book snapshot, so the
+            // books built from them are whole
+            let mut subscription = Subscription::builder()
+                .symbols(Self::upstream_symbols(symbols.clone(), stype_in))
                 .schema(db_schema)
-                .stype_in(SType::Parent)
+                .stype_in(Self::map_stype(stype_in))
                 .build();
+            subscription.use_snapshot = db_schema == DbSchema::Mbo;
 
             if let Err(e) = client.subscribe(subscription).await {
                 error!("Failed to subscribe: {}", e);
@@ -265,33 +583,80 @@ impl MarketDataService for DatabentoService {
             }
 
             // Start receiving
-            if let Err(e) = client.start().await {
-                error!("Failed to start stream: {}", e);
-                yield LiveMessage::Error {
-                    message: format!("Failed to start stream: {}", e),
-                };
-                return;
-            }
+            let metadata = match client.start().await {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    error!("Failed to start stream: {}", e);
+                    yield LiveMessage::Error {
+                        message: format!("Failed to start stream: {}", e),
+                    };
+                    return;
+                }
+            };
+            let mut tee = dbn_tee.map(|dir| {
+                DbnTee::new(dir, metadata, symbols, Self::map_stype(stype_in))
+            });
 
-            // Symbol map for resolving instrument IDs
+            // Symbol map for resolving instrument IDs (unused when the
+            // client subscribed by ID)
+            let by_id = stype_in == SymbolType::InstrumentId;
             let mut symbol_map = PitSymbolMap::new();
+            // Timed from a record arriving to its message leaving
+            let decoded = |received: Instant| {
+                if let Some(latency) = &latency {
+                    latency.record(Stage::Decode, received.elapsed());
+                }
+            };
 
             // Stream records
             loop {
                 match client.next_record().await {
                     Ok(Some(record)) => {
+                        let received = Instant::now();
+                        // Copy the raw record out; a tee that fails is
+                        // dropped rather than ending the session
+                        if let Some(writer) = &mut tee {
+                            if let Err(e) = writer.write(record) {
+                                error!(dataset = %dataset, "DBN tee stopped: {}", e);
+                                tee = None;
+                            }
+                        }
+
                         // Update symbol map
-                        if let Err(e) = symbol_map.on_record(record) {
-                            warn!("Symbol map update failed: {}", e);
+                        if !by_id {
+                            if let Err(e) = symbol_map.on_record(record) {
+                                warn!("Symbol map update failed: {}", e);
+                            }
+                        }
+
+                        // Report what each subscribed symbol resolved to
+                        if let Some(mapping) = record.get::<SymbolMappingMsg>() {
+                            match (mapping.stype_in_symbol(), mapping.stype_out_symbol()) {
+                                (Ok(stype_in_symbol), Ok(stype_out_symbol)) => {
+                                    yield LiveMessage::SymbolMapping {
+                                        instrument_id: mapping.hd.instrument_id,
+                                        stype_in_symbol: stype_in_symbol.to_string(),
+                                        stype_out_symbol: stype_out_symbol.to_string(),
+                                    };
+                                }
+                                (Err(e), _) | (_, Err(e)) => {
+                                    warn!("Unreadable symbol mapping: {}", e)
+                                }
+                            }
                         }
 
                         // Try to extract as TradeMsg
                         if let Some(trade) = record.get::<TradeMsg>() {
-                            let symbol = symbol_map
-                                .get_for_rec(trade)
-                                .map(|s| s.to_string())
-                                .unwrap_or_else(|| format!("ID:{}", trade.hd.instrument_id));
+                            let symbol = if by_id {
+                                trade.hd.instrument_id.to_string()
+                            } else {
+                                symbol_map
+                                    .get_for_rec(trade)
+                                    .map(|s| s.to_string())
+                                    .unwrap_or_else(|| format!("ID:{}", trade.hd.instrument_id))
+                            };
 
+                            decoded(received);
                             yield LiveMessage::Trade {
                                 ts_event_unix_ns: trade.hd.ts_event,
                                 symbol,
@@ -299,13 +664,46 @@ impl MarketDataService for DatabentoService {
                                 size_u32: trade.size,
                             };
                         }
+
+                        // Book depth, as the top levels after each update
+                        if let Some(book) = record.get::<Mbp10Msg>() {
+                            let symbol = if by_id {
+                                book.hd.instrument_id.to_string()
+                            } else {
+                                symbol_map
+                                    .get_for_rec(book)
+                                    .map(|s| s.to_string())
+                                    .unwrap_or_else(|| format!("ID:{}", book.hd.instrument_id))
+                            };
+                            let update = depth_update(book, symbol);
+                            decoded(received);
+                            yield LiveMessage::Depth(update);
+                        }
+
+                        // Order events, for books rebuilt downstream
+                        if let Some(order) = record.get::<MboMsg>() {
+                            let symbol = if by_id {
+                                order.hd.instrument_id.to_string()
+                            } else {
+                                symbol_map
+                                    .get_for_rec(order)
+                                    .map(|s| s.to_string())
+                                    .unwrap_or_else(|| format!("ID:{}", order.hd.instrument_id))
+                            };
+                            let order = mbo_record(order, symbol);
+                            decoded(received);
+                            yield LiveMessage::Mbo(order);
+                        }
                     }
                     Ok(None) => {
-                        info!("Live stream ended");
+                        info!(dataset = %dataset, "Live stream ended");
                         break;
                     }
                     Err(e) => {
                         error!("Stream error: {}", e);
+                        if let Some(session) = &session {
+                            session.failed();
+                        }
                         yield LiveMessage::Error {
                             message: format!("Stream error: {}", e),
                         };
@@ -315,9 +713,687 @@ impl MarketDataService for DatabentoService {
             }
         };
 
+        Box::pin(stream)
+    }
+}
+
+/// An mbp-10 record's book, with undefined prices on empty sides zeroed.
+fn depth_update(book: &Mbp10Msg, symbol: String) -> DepthUpdate {
+    let px = |px: i64| if px == UNDEF_PRICE { 0 } else { px };
+    DepthUpdate {
+        ts_event_unix_ns: book.hd.ts_event,
+        symbol,
+        sequence_u32: book.sequence,
+        maybe_bad_book: book.flags.is_maybe_bad_book(),
+        levels: book
+            .levels
+            .iter()
+            .map(|level| DepthLevel {
+                bid_px_i64: px(level.bid_px),
+                ask_px_i64: px(level.ask_px),
+                bid_sz_u32: level.bid_sz,
+                ask_sz_u32: level.ask_sz,
+                bid_ct_u32: level.bid_ct,
+                ask_ct_u32: level.ask_ct,
+            })
+            .collect(),
+    }
+}
+
+/// An MBO record as the order book engine takes it.
+fn mbo_record(order: &MboMsg, symbol: String) -> MboRecord {
+    MboRecord {
+        ts_event_unix_ns: order.hd.ts_event,
+        symbol,
+        order_id_u64: order.order_id,
+        price_i64: order.price,
+        size_u32: order.size,
+        action: match order.action as u8 {
+            b'A' => MboAction::Add,
+            b'C' => MboAction::Cancel,
+            b'M' => MboAction::Modify,
+            b'R' => MboAction::Clear,
+            b'T' => MboAction::Trade,
+            b'F' => MboAction::Fill,
+            _ => MboAction::None,
+        },
+        side: match order.side as u8 {
+            b'B' => BookSide::Bid,
+            b'A' => BookSide::Ask,
+            _ => BookSide::None,
+        },
+        sequence_u32: order.sequence,
+        last: order.flags.is_last(),
+        maybe_bad_book: order.flags.is_maybe_bad_book(),
+    }
+}
+
+/// Most DBN files opened for one dataset and schema in the same second.
+const MAX_TEE_FILES_PER_SECOND: u32 = 1_000;
+
+/// A live session's records written back out as zstd-compressed DBN, so
+/// recordings can be read by the `dbn` CLI and DataBento's client libraries.
+///
+/// One file is written per session and UTC day, as
+/// `{dir}/{day}/{dataset}.{schema}.{HHMMSS}.dbn.zst` from the time it was
+/// opened, with `-1`, `-2`, ... after the time when sessions for the same
+/// dataset and schema open in the same second. Each file's metadata is the session's, with the subscribed
+/// symbols and the mappings known when it was opened; the symbol mapping
+/// records are also written in the stream, and repeated at the head of a
+/// file opened at midnight, so every file can be read on its own.
+struct DbnTee {
+    dir: PathBuf,
+    metadata: Metadata,
+    /// Latest mapping per instrument ID
+    mappings: BTreeMap<u32, SymbolMappingMsg>,
+    file: Option<(time::Date, Encoder<DynWriter<'static, BufWriter<File>>>)>,
+}
+
+impl DbnTee {
+    fn new(dir: PathBuf, mut metadata: Metadata, symbols: Vec<String>, stype_in: SType) -> Self {
+        metadata.symbols = symbols;
+        metadata.stype_in = Some(stype_in);
+        Self {
+            dir,
+            metadata,
+            mappings: BTreeMap::new(),
+            file: None,
+        }
+    }
+
+    /// Write `record`. The first file is opened at the first record that
+    /// isn't a symbol mapping, so the mappings sent when a session starts
+    /// make it into the metadata.
+    fn write(&mut self, record: RecordRef) -> Result<(), String> {
+        let mapping = record.get::<SymbolMappingMsg>();
+        let now = OffsetDateTime::now_utc();
+        let opened = self.file.as_ref().map(|(day, _)| *day);
+        if opened != Some(now.date()) && (opened.is_some() || mapping.is_none()) {
+            // Dropping the previous encoder finishes its file
+            self.file = None;
+            let encoder = self.open(now)?;
+            self.file = Some((now.date(), encoder));
+        }
+        if let Some(mapping) = mapping {
+            self.mappings
+                .insert(mapping.hd.instrument_id, mapping.clone());
+        }
+        if let Some((_, encoder)) = &mut self.file {
+            encoder
+                .encode_record_ref(record)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn open(
+        &self,
+        now: OffsetDateTime,
+    ) -> Result<Encoder<DynWriter<'static, BufWriter<File>>>, String> {
+        let day = now.date();
+        let dir = self.dir.join(day.to_string());
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let mut metadata = self.metadata.clone();
+        metadata.start = now.unix_timestamp_nanos() as u64;
+        metadata.mappings = self.metadata_mappings(day);
+        let schema = metadata.schema.map(|s| s.as_str()).unwrap_or("mixed");
+        let stem = format!(
+            "{}.{}.{:02}{:02}{:02}",
+            metadata.dataset,
+            schema,
+            now.hour(),
+            now.minute(),
+            now.second()
+        );
+        // Never truncate another session's file
+        let mut attempt = 0;
+        let (path, file) = loop {
+            let path = match attempt {
+                0 => dir.join(format!("{}.dbn.zst", stem)),
+                n => dir.join(format!("{}-{}.dbn.zst", stem, n)),
+            };
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => break (path, file),
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::AlreadyExists
+                        && attempt < MAX_TEE_FILES_PER_SECOND =>
+                {
+                    attempt += 1
+                }
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            }
+        };
+        let failed = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let writer =
+            DynWriter::new(BufWriter::new(file), Compression::Zstd).map_err(|e| failed(&e))?;
+        let mut encoder = Encoder::new(writer, &metadata).map_err(|e| failed(&e))?;
+        for mapping in self.mappings.values() {
+            encoder.encode_record(mapping).map_err(|e| failed(&e))?;
+        }
+        info!(path = %path.display(), "Writing live data as DBN");
+        Ok(encoder)
+    }
+
+    /// The known mappings in metadata form, valid for `day`: each
+    /// instrument's raw symbol to its ID, as in a historical file.
+    fn metadata_mappings(&self, day: time::Date) -> Vec<SymbolMapping> {
+        let Some(end_date) = day.next_day() else {
+            return Vec::new();
+        };
+        self.mappings
+            .values()
+            .filter_map(|mapping| {
+                Some(SymbolMapping {
+                    raw_symbol: mapping.stype_out_symbol().ok()?.to_string(),
+                    intervals: vec![MappingInterval {
+                        start_date: day,
+                        end_date,
+                        symbol: mapping.hd.instrument_id.to_string(),
+                    }],
+                })
+            })
+            .collect()
+    }
+}
+
+/// Decode a DBN file, plain or zstd-compressed, such as a batch download.
+/// Trades, the trade events of an MBP-1 file (with the BBO they printed
+/// against) and 1s/1m OHLCV bars are read, labelled through the file's
+/// symbology or by instrument ID where it has none. Files of more than
+/// `max_records` records are refused.
+pub fn decode_file(bytes: &[u8], max_records: u64) -> Result<DecodedFile, UploadError> {
+    let invalid = |e: databento::dbn::Error| UploadError::Decode(e.to_string());
+    // Zstandard frames start with this magic number
+    if bytes.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+        decode_records(DbnDecoder::with_zstd(bytes).map_err(invalid)?, max_records)
+    } else {
+        decode_records(DbnDecoder::new(bytes).map_err(invalid)?, max_records)
+    }
+}
+
+fn decode_records<D: DecodeRecord + DbnMetadata>(
+    mut decoder: D,
+    max_records: u64,
+) -> Result<DecodedFile, UploadError> {
+    let invalid = |e: databento::dbn::Error| UploadError::Decode(e.to_string());
+    let metadata = decoder.metadata().clone();
+    let symbol_map: TsSymbolMap = metadata.symbol_map().map_err(invalid)?;
+    fn label<R: Record>(symbol_map: &TsSymbolMap, record: &R) -> String {
+        symbol_map
+            .get_for_rec(record)
+            .cloned()
+            .unwrap_or_else(|| format!("ID:{}", record.header().instrument_id))
+    }
+    let too_many = || {
+        UploadError::Invalid(format!(
+            "Files of more than {} records can't be viewed",
+            max_records
+        ))
+    };
+
+    let mut response = match metadata.schema {
+        Some(DbSchema::Trades) => {
+            let mut data = Vec::new();
+            while let Some(record) = decoder.decode_record::<TradeMsg>().map_err(invalid)? {
+                if data.len() as u64 >= max_records {
+                    return Err(too_many());
+                }
+                data.push(TradeRecord {
+                    ts_event_unix_ns: record.hd.ts_event,
+                    symbol: label(&symbol_map, record),
+                    price_i64: record.price,
+                    size_u32: record.size,
+                    quote: None,
+                    sequence_u32: Some(record.sequence),
+                });
+            }
+            HistoricalResponse::Trades { data }
+        }
+        Some(DbSchema::Mbp1) => {
+            let mut data = Vec::new();
+            while let Some(record) = decoder.decode_record::<Mbp1Msg>().map_err(invalid)? {
+                // Only trade events; the rest are book updates
+                if record.action as u8 != b'T' {
+                    continue;
+                }
+                if data.len() as u64 >= max_records {
+                    return Err(too_many());
+                }
+                let level = &record.levels[0];
+                let quote = (level.bid_px != UNDEF_PRICE && level.ask_px != UNDEF_PRICE)
+                    .then(|| QuoteAtTrade::classify(record.price, level.bid_px, level.ask_px));
+                data.push(TradeRecord {
+                    ts_event_unix_ns: record.hd.ts_event,
+                    symbol: label(&symbol_map, record),
+                    price_i64: record.price,
+                    size_u32: record.size,
+                    quote,
+                    sequence_u32: Some(record.sequence),
+                });
+            }
+            HistoricalResponse::Trades { data }
+        }
+        Some(db_schema @ (DbSchema::Ohlcv1S | DbSchema::Ohlcv1M)) => {
+            let mut data = Vec::new();
+            while let Some(record) = decoder.decode_record::<OhlcvMsg>().map_err(invalid)? {
+                if data.len() as u64 >= max_records {
+                    return Err(too_many());
+                }
+                data.push(OhlcvRecord {
+                    ts_event_unix_ns: record.hd.ts_event,
+                    symbol: label(&symbol_map, record),
+                    open_i64: record.open,
+                    high_i64: record.high,
+                    low_i64: record.low,
+                    close_i64: record.close,
+                    volume_u64: record.volume,
+                });
+            }
+            if db_schema == DbSchema::Ohlcv1S {
+                HistoricalResponse::Ohlcv1S { data }
+            } else {
+                HistoricalResponse::Ohlcv1M { data }
+            }
+        }
+        other => {
+            return Err(UploadError::Invalid(format!(
+                "DBN schema {} can't be viewed: expected trades, mbp-1, ohlcv-1s or ohlcv-1m",
+                other.map_or("(mixed)", |schema| schema.as_str())
+            )))
+        }
+    };
+    response.sort_by_time();
+    Ok(DecodedFile {
+        dataset: metadata.dataset,
+        response,
+    })
+}
+
+#[async_trait]
+impl MarketDataService for DatabentoService {
+    async fn get_historical(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<HistoricalResponse, ServiceError> {
+        info!(
+            symbols = ?req.symbols,
+            schema = %req.schema,
+            start = %req.start_rfc3339,
+            end = %req.end_rfc3339,
+            limit = req.limit,
+            "DataBento historical request"
+        );
+
+        if let Some(pinned) = &req.revision {
+            let current = DataVersion::revision(&self.data_versions(req).await?);
+            if let Some(current) = current.filter(|current| current != pinned) {
+                return Err(ServiceError::RevisionUnavailable(format!(
+                    "DataBento has restated the data since {}; only its latest revision, {}, \
+                     can be fetched",
+                    pinned, current
+                )));
+            }
+        }
+
+        // Parse our schema enum for response building
+        let schema: Schema = req
+            .schema
+            .parse()
+            .map_err(|e: String| ServiceError::InvalidSchema(e))?;
+
+        // Derived schemas are computed from quoted trades
+        if schema.cvd_interval_ns().is_some() {
+            return historical_cvd(self, req, schema).await;
+        }
+        if let Some(spec) = schema.bar_spec() {
+            return historical_bars(self, req, spec).await;
+        }
+
+        // Parse inputs
+        let mut db_schema = Self::map_schema(&req.schema)?;
+        let start = Self::parse_timestamp(&req.start_rfc3339)?;
+        let end = Self::parse_timestamp(&req.end_rfc3339)?;
+        let stype_in = checked_stype_in(req.stype_in, &req.symbols)?;
+        let groups = self.route(&req.symbols, req.dataset.as_deref())?;
+
+        // Quote-at-trade joins come from the mbp-1 book feed
+        if req.quote_at_trade {
+            if schema != Schema::Trades {
+                return Err(ServiceError::InvalidSchema(
+                    "quote_at_trade requires the trades schema".to_string(),
+                ));
+            }
+            db_schema = DbSchema::Mbp1;
+        }
+
+        let mut client = self.historical_client()?;
+
+        // The upstream limit is global, so with a per-symbol limit each
+        // symbol is fetched on its own and `limit` shared out afterwards
+        let per_symbol = req.limit_per_symbol;
+        let fetches: Vec<(String, Vec<String>)> = match per_symbol {
+            Some(_) => groups
+                .into_iter()
+                .flat_map(|(dataset, symbols)| {
+                    symbols
+                        .into_iter()
+                        .map(move |symbol| (dataset.clone(), vec![symbol]))
+                })
+                .collect(),
+            None => groups,
+        };
+        let fetch_limit = per_symbol.map_or(req.limit, |n| n.min(req.limit));
+
+        // Fetch each dataset's symbols and merge in time order
+        let merge = fetches.len() > 1;
+        let mut merged: Option<HistoricalResponse> = None;
+        for (dataset, symbols) in fetches {
+            let resp = Self::fetch_dataset(
+                &mut client,
+                &dataset,
+                symbols,
+                stype_in,
+                &schema,
+                db_schema,
+                start,
+                end,
+                fetch_limit,
+            )
+            .await?;
+
+            match merged.as_mut() {
+                Some(acc) => acc.append(resp),
+                None => merged = Some(resp),
+            }
+        }
+
+        let mut response = merged.unwrap_or_else(|| HistoricalResponse::empty(&schema));
+        if merge {
+            response.sort_by_time();
+        }
+        if merge || per_symbol.is_some() {
+            response.apply_limits(req.limit, per_symbol);
+        }
+        Ok(response)
+    }
+
+    async fn subscribe_live(
+        &self,
+        symbols: Vec<String>,
+        schema: String,
+        stype_in: SymbolType,
+    ) -> Result<LiveStream, ServiceError> {
+        info!(
+            symbols = ?symbols,
+            schema = %schema,
+            stype_in = stype_in.as_str(),
+            "DataBento live subscription request"
+        );
+
+        let db_schema = Self::map_schema(&schema)?;
+        stype_in
+            .validate(&symbols)
+            .map_err(ServiceError::InvalidSymbol)?;
+        let api_key = self.api_key()?;
+        let dataset_streams: Vec<LiveStream> = self
+            .route(&symbols, None)?
+            .into_iter()
+            .map(|(dataset, symbols)| {
+                info!(dataset = %dataset, symbols = ?symbols, "Routing live subscription");
+                Self::dataset_stream(
+                    api_key.clone(),
+                    dataset,
+                    symbols,
+                    stype_in,
+                    db_schema,
+                    self.dbn_tee.clone(),
+                    self.gateways.clone(),
+                    self.latency.clone(),
+                )
+            })
+            .collect();
+
+        // Create the live stream
+        let stream = async_stream::stream! {
+            // First emit connected message
+            yield LiveMessage::Connected {
+                symbols: symbols.clone(),
+                schema: schema.clone(),
+                connection_id: None,
+            };
+
+            // One upstream session per dataset, interleaved as records arrive
+            let mut merged = futures::stream::select_all(dataset_streams);
+            while let Some(msg) = merged.next().await {
+                yield msg;
+            }
+        };
+
         Ok(Box::pin(stream))
     }
 
+    /// Upstream schemas are counted by DataBento's metadata API. Quoted
+    /// trades and CVD bars come from the book feed, where metadata would
+    /// count book updates, so those are counted by decoding the feed. Tick,
+    /// volume and dollar bars can only be counted by building them.
+    async fn count_records(&self, req: &HistoricalRequest) -> Result<u64, ServiceError> {
+        let schema: Schema = req
+            .schema
+            .parse()
+            .map_err(|e: String| ServiceError::InvalidSchema(e))?;
+        let start = Self::parse_timestamp(&req.start_rfc3339)?;
+        let end = Self::parse_timestamp(&req.end_rfc3339)?;
+        let stype_in = checked_stype_in(req.stype_in, &req.symbols)?;
+        if req.quote_at_trade && schema != Schema::Trades {
+            return Err(ServiceError::InvalidSchema(
+                "quote_at_trade requires the trades schema".to_string(),
+            ));
+        }
+        if schema.bar_spec().is_some() {
+            let unlimited = HistoricalRequest {
+                limit: u32::MAX,
+                ..req.clone()
+            };
+            return Ok(self.get_historical(&unlimited).await?.len() as u64);
+        }
+        let from_book = req.quote_at_trade || schema.cvd_interval_ns().is_some();
+        let align = req.bar_align().map_err(ServiceError::InvalidTimeFormat)?;
+        let db_schema = if from_book {
+            DbSchema::Mbp1
+        } else {
+            Self::map_schema(&req.schema)?
+        };
+
+        let mut client = self.historical_client()?;
+        let mut total = 0;
+        for (dataset, symbols) in self.route(&req.symbols, req.dataset.as_deref())? {
+            total += if from_book {
+                Self::count_book_trades(
+                    &mut client,
+                    &dataset,
+                    symbols,
+                    stype_in,
+                    start,
+                    end,
+                    schema
+                        .cvd_interval_ns()
+                        .map(|interval| BarBuckets::new(interval, align)),
+                )
+                .await?
+            } else {
+                let params = GetRecordCountParams::builder()
+                    .dataset(dataset)
+                    .date_time_range((start, end))
+                    .symbols(Self::upstream_symbols(symbols, stype_in))
+                    .schema(db_schema)
+                    .stype_in(Self::map_stype(stype_in))
+                    .build();
+                client
+                    .metadata()
+                    .get_record_count(&params)
+                    .await
+                    .map_err(|e| ServiceError::ApiError(format!("Count request failed: {}", e)))?
+            };
+        }
+        Ok(total)
+    }
+
+    async fn estimate_cost(&self, req: &HistoricalRequest) -> Result<f64, ServiceError> {
+        let schema: Schema = req
+            .schema
+            .parse()
+            .map_err(|e: String| ServiceError::InvalidSchema(e))?;
+        // Mirror the upstream schema get_historical would query
+        let db_schema = if req.quote_at_trade || schema.cvd_interval_ns().is_some() {
+            DbSchema::Mbp1
+        } else if schema.bar_spec().is_some() {
+            DbSchema::Trades
+        } else {
+            Self::map_schema(&req.schema)?
+        };
+        let start = Self::parse_timestamp(&req.start_rfc3339)?;
+        let end = Self::parse_timestamp(&req.end_rfc3339)?;
+        let stype_in = checked_stype_in(req.stype_in, &req.symbols)?;
+
+        let mut client = self.historical_client()?;
+        let mut total = 0.0;
+        for (dataset, symbols) in self.route(&req.symbols, req.dataset.as_deref())? {
+            let params = GetCostParams::builder()
+                .dataset(dataset)
+                .date_time_range((start, end))
+                .symbols(Self::upstream_symbols(symbols, stype_in))
+                .schema(db_schema)
+                .stype_in(Self::map_stype(stype_in))
+                .build();
+            total += client
+                .metadata()
+                .get_cost(&params)
+                .await
+                .map_err(|e| ServiceError::ApiError(format!("Cost request failed: {}", e)))?;
+        }
+        Ok(total)
+    }
+
+    /// DataBento's order data starts each UTC day with a snapshot of every
+    /// book, so a replay from midnight builds whole books.
+    async fn replay_mbo(&self, req: &HistoricalRequest) -> Result<MboStream, ServiceError> {
+        let start = Self::parse_timestamp(&req.start_rfc3339)?;
+        let end = Self::parse_timestamp(&req.end_rfc3339)?;
+        let stype_in = checked_stype_in(req.stype_in, &req.symbols)?;
+        let routes = self.route(&req.symbols, req.dataset.as_deref())?;
+        let limit = u64::from(req.limit);
+        // Built now, while the request's user key is in scope
+        let mut client = self.historical_client()?;
+        Ok(Box::pin(async_stream::try_stream! {
+            let mut remaining = limit;
+            'datasets: for (dataset, symbols) in routes {
+                let params = GetRangeParams::builder()
+                    .dataset(dataset.as_str())
+                    .date_time_range((start, end))
+                    .symbols(Self::upstream_symbols(symbols, stype_in))
+                    .schema(DbSchema::Mbo)
+                    .stype_in(Self::map_stype(stype_in))
+                    .limit(NonZeroU64::new(remaining))
+                    .build();
+                let mut decoder = client
+                    .timeseries()
+                    .get_range(&params)
+                    .await
+                    .map_err(|e| ServiceError::ApiError(format!("API request failed: {}", e)))?;
+                let symbol_map = match stype_in {
+                    SymbolType::InstrumentId => None,
+                    _ => Some(
+                        decoder
+                            .metadata()
+                            .symbol_map_for_date(start.date())
+                            .map_err(|e| ServiceError::ApiError(format!("Symbol map error: {}", e)))?,
+                    ),
+                };
+                while let Some(order) = decoder
+                    .decode_record::<MboMsg>()
+                    .await
+                    .map_err(|e| ServiceError::ApiError(format!("Decode error: {}", e)))?
+                {
+                    cancel::checkpoint()?;
+                    let symbol = match &symbol_map {
+                        Some(map) => map
+                            .get(order.hd.instrument_id)
+                            .map(|s: &String| s.to_string())
+                            .unwrap_or_else(|| format!("ID:{}", order.hd.instrument_id)),
+                        None => order.hd.instrument_id.to_string(),
+                    };
+                    let record = mbo_record(order, symbol);
+                    yield record;
+                    remaining = remaining.saturating_sub(1);
+                    if remaining == 0 {
+                        break 'datasets;
+                    }
+                }
+            }
+        }))
+    }
+
+    async fn unresolved_symbols(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<String>, ServiceError> {
+        let stype_in = checked_stype_in(req.stype_in, &req.symbols)?;
+        // Records are labelled by ID, so any ID is served (possibly empty)
+        if stype_in == SymbolType::InstrumentId {
+            return Ok(Vec::new());
+        }
+        let start = Self::parse_timestamp(&req.start_rfc3339)?;
+        let end = Self::parse_timestamp(&req.end_rfc3339)?;
+        // Symbology resolves whole days; the end date is exclusive
+        let end_date = end.date().next_day().unwrap_or(end.date());
+
+        let mut client = self.historical_client()?;
+        let mut unresolved = Vec::new();
+        for (dataset, symbols) in self.route(&req.symbols, req.dataset.as_deref())? {
+            let params = ResolveParams::builder()
+                .dataset(dataset)
+                .symbols(Self::upstream_symbols(symbols, stype_in))
+                .stype_in(Self::map_stype(stype_in))
+                .stype_out(SType::InstrumentId)
+                .date_range((start.date(), end_date))
+                .build();
+            let resolution =
+                client.symbology().resolve(&params).await.map_err(|e| {
+                    ServiceError::ApiError(format!("Symbology request failed: {}", e))
+                })?;
+            unresolved.extend(resolution.not_found);
+        }
+        Ok(unresolved)
+    }
+
+    async fn data_versions(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<DataVersion>, ServiceError> {
+        let start = Self::parse_timestamp(&req.start_rfc3339)?;
+        let end = Self::parse_timestamp(&req.end_rfc3339)?;
+        let end_date = end.date().next_day().unwrap_or(end.date());
+        let mut versions = Vec::new();
+        for (dataset, _) in self.route(&req.symbols, req.dataset.as_deref())? {
+            let modified = self.last_modified(&dataset, start.date(), end_date).await?;
+            if let Some(modified) = modified {
+                versions.push(DataVersion {
+                    dataset,
+                    last_modified: modified.to_string(),
+                });
+            }
+        }
+        Ok(versions)
+    }
+
+    fn datasets(&self, req: &HistoricalRequest) -> Result<Vec<DatasetRoute>, ServiceError> {
+        Ok(self
+            .route(&req.symbols, req.dataset.as_deref())?
+            .into_iter()
+            .map(|(dataset, symbols)| DatasetRoute { dataset, symbols })
+            .collect())
+    }
+
     fn name(&self) -> &'static str {
         "DatabentoService"
     }
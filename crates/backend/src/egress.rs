@@ -0,0 +1,431 @@
+//! Republishing live data to a message bus.
+//!
+//! The egress holds its own live subscription per schema and forwards each
+//! trade or bar to a [`Publisher`], so downstream systems can consume the
+//! normalized feed without speaking the WebSocket protocol. Control
+//! messages (`connected`, `error`, ...) are not published.
+//!
+//! Publishers are behind cargo features: `nats` publishes to
+//! `{prefix}.{schema}.{symbol}` subjects, `kafka` to `{prefix}.{schema}`
+//! topics keyed by symbol.
+
+use crate::service::MarketDataService;
+use async_trait::async_trait;
+use futures::StreamExt;
+use shared::{LiveMessage, SymbolType};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Delay before resubscribing after the live stream ends or fails;
+/// doubled on each consecutive failure up to `MAX_RESUBSCRIBE_DELAY`.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(60);
+
+/// Error type for egress operations.
+#[derive(Debug, thiserror::Error)]
+pub enum EgressError {
+    #[error("Invalid egress config: {0}")]
+    Config(String),
+    #[error("Serialization failed: {0}")]
+    Encode(String),
+    #[error("Publish failed: {0}")]
+    Publish(String),
+}
+
+/// Wire encoding of published messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EgressFormat {
+    /// The WebSocket JSON representation
+    #[default]
+    Json,
+    /// MessagePack with named fields (same shape as the JSON)
+    Msgpack,
+}
+
+impl std::str::FromStr for EgressFormat {
+    type Err = EgressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(EgressFormat::Json),
+            "msgpack" => Ok(EgressFormat::Msgpack),
+            _ => Err(EgressError::Config(format!(
+                "unknown format '{}': expected json or msgpack",
+                s
+            ))),
+        }
+    }
+}
+
+impl EgressFormat {
+    pub fn encode(&self, msg: &LiveMessage) -> Result<Vec<u8>, EgressError> {
+        match self {
+            EgressFormat::Json => {
+                serde_json::to_vec(msg).map_err(|e| EgressError::Encode(e.to_string()))
+            }
+            EgressFormat::Msgpack => {
+                rmp_serde::to_vec_named(msg).map_err(|e| EgressError::Encode(e.to_string()))
+            }
+        }
+    }
+}
+
+/// A message bus that live messages are republished to.
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// Publish one encoded message for `symbol` under `schema`.
+    async fn publish(
+        &self,
+        schema: &str,
+        symbol: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), EgressError>;
+
+    /// Name of the bus (for logging).
+    fn name(&self) -> &'static str;
+}
+
+/// What the egress subscribes to and how it encodes messages.
+#[derive(Debug, Clone)]
+pub struct EgressConfig {
+    pub symbols: Vec<String>,
+    /// One live subscription is held per schema
+    pub schemas: Vec<String>,
+    pub format: EgressFormat,
+}
+
+/// Connect to the bus named by `url`: `nats://host:4222` or
+/// `kafka://broker1:9092,broker2:9092`. `prefix` starts every subject or
+/// topic name.
+pub async fn connect(url: &str, prefix: &str) -> Result<Arc<dyn Publisher>, EgressError> {
+    let (scheme, _) = url
+        .split_once("://")
+        .ok_or_else(|| EgressError::Config(format!("'{}' has no scheme", url)))?;
+    match scheme {
+        "nats" | "tls" => connect_nats(url, prefix).await,
+        "kafka" => connect_kafka(url.trim_start_matches("kafka://"), prefix),
+        _ => Err(EgressError::Config(format!(
+            "unsupported scheme '{}': expected nats:// or kafka://",
+            scheme
+        ))),
+    }
+}
+
+#[cfg(feature = "nats")]
+async fn connect_nats(url: &str, prefix: &str) -> Result<Arc<dyn Publisher>, EgressError> {
+    Ok(Arc::new(nats::NatsPublisher::connect(url, prefix).await?))
+}
+
+#[cfg(not(feature = "nats"))]
+async fn connect_nats(_url: &str, _prefix: &str) -> Result<Arc<dyn Publisher>, EgressError> {
+    Err(not_compiled("nats"))
+}
+
+#[cfg(feature = "kafka")]
+fn connect_kafka(brokers: &str, prefix: &str) -> Result<Arc<dyn Publisher>, EgressError> {
+    Ok(Arc::new(kafka::KafkaPublisher::new(brokers, prefix)?))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn connect_kafka(_brokers: &str, _prefix: &str) -> Result<Arc<dyn Publisher>, EgressError> {
+    Err(not_compiled("kafka"))
+}
+
+#[allow(dead_code)] // Unused when both features are enabled
+fn not_compiled(feature: &str) -> EgressError {
+    EgressError::Config(format!(
+        "{} support is not compiled in; rebuild with `--features {}`",
+        feature, feature
+    ))
+}
+
+/// Start forwarding live data to `publisher`, one task per schema.
+pub fn spawn(
+    service: Arc<dyn MarketDataService>,
+    publisher: Arc<dyn Publisher>,
+    config: EgressConfig,
+) -> Vec<JoinHandle<()>> {
+    config
+        .schemas
+        .iter()
+        .map(|schema| {
+            tokio::spawn(forward(
+                service.clone(),
+                publisher.clone(),
+                config.symbols.clone(),
+                schema.clone(),
+                config.format,
+            ))
+        })
+        .collect()
+}
+
+/// Symbol of a data message; `None` for control messages.
+pub fn data_symbol(msg: &LiveMessage) -> Option<&str> {
+    match msg {
+        LiveMessage::Trade { symbol, .. } | LiveMessage::Ohlcv { symbol, .. } => Some(symbol),
+        LiveMessage::Depth(update) => Some(&update.symbol),
+        LiveMessage::DepthSnapshot(snapshot) => Some(&snapshot.symbol),
+        LiveMessage::DepthDiff(diff) => Some(&diff.symbol),
+        LiveMessage::Mbo(record) => Some(&record.symbol),
+        _ => None,
+    }
+}
+
+/// Forward one schema's live stream forever, resubscribing when it ends.
+async fn forward(
+    service: Arc<dyn MarketDataService>,
+    publisher: Arc<dyn Publisher>,
+    symbols: Vec<String>,
+    schema: String,
+    format: EgressFormat,
+) {
+    let mut delay = RESUBSCRIBE_DELAY;
+    let mut failed: u64 = 0;
+    loop {
+        match service
+            .subscribe_live(symbols.clone(), schema.clone(), SymbolType::Parent)
+            .await
+        {
+            Ok(mut stream) => {
+                info!(schema = %schema, bus = publisher.name(), "Egress subscribed");
+                delay = RESUBSCRIBE_DELAY;
+                while let Some(msg) = stream.next().await {
+                    if let LiveMessage::Error { message } = &msg {
+                        warn!(schema = %schema, "Egress stream error: {}", message);
+                        continue;
+                    }
+                    let Some(symbol) = data_symbol(&msg) else {
+                        continue;
+                    };
+                    let result = match format.encode(&msg) {
+                        Ok(payload) => publisher.publish(&schema, symbol, payload).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        failed += 1;
+                        // Log the first failure, then back off as they pile up
+                        if failed.is_power_of_two() {
+                            warn!(schema = %schema, failed, "Egress dropped message: {}", e);
+                        }
+                    }
+                }
+                warn!(schema = %schema, "Egress stream ended, resubscribing");
+            }
+            Err(e) => warn!(schema = %schema, "Egress subscribe failed: {}", e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use super::{EgressError, Publisher};
+    use async_trait::async_trait;
+
+    /// Publishes to `{prefix}.{schema}.{symbol}` subjects.
+    pub struct NatsPublisher {
+        client: async_nats::Client,
+        prefix: String,
+    }
+
+    impl NatsPublisher {
+        pub async fn connect(url: &str, prefix: &str) -> Result<Self, EgressError> {
+            let client = async_nats::connect(url)
+                .await
+                .map_err(|e| EgressError::Config(format!("NATS {}: {}", url, e)))?;
+            Ok(Self {
+                client,
+                prefix: prefix.to_string(),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Publisher for NatsPublisher {
+        async fn publish(
+            &self,
+            schema: &str,
+            symbol: &str,
+            payload: Vec<u8>,
+        ) -> Result<(), EgressError> {
+            let subject = format!("{}.{}.{}", self.prefix, schema, symbol);
+            self.client
+                .publish(subject, payload.into())
+                .await
+                .map_err(|e| EgressError::Publish(e.to_string()))
+        }
+
+        fn name(&self) -> &'static str {
+            "NATS"
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use super::{EgressError, Publisher};
+    use async_trait::async_trait;
+    use rdkafka::config::ClientConfig;
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+    use std::time::Duration;
+
+    /// Publishes to `{prefix}.{schema}` topics, keyed by symbol so each
+    /// symbol stays ordered within its partition.
+    pub struct KafkaPublisher {
+        producer: FutureProducer,
+        prefix: String,
+    }
+
+    impl KafkaPublisher {
+        pub fn new(brokers: &str, prefix: &str) -> Result<Self, EgressError> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers)
+                .set("message.timeout.ms", "5000")
+                .create()
+                .map_err(|e| EgressError::Config(format!("Kafka {}: {}", brokers, e)))?;
+            Ok(Self {
+                producer,
+                prefix: prefix.to_string(),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl Publisher for KafkaPublisher {
+        async fn publish(
+            &self,
+            schema: &str,
+            symbol: &str,
+            payload: Vec<u8>,
+        ) -> Result<(), EgressError> {
+            let topic = format!("{}.{}", self.prefix, schema);
+            let record = FutureRecord::to(&topic).key(symbol).payload(&payload);
+            self.producer
+                .send(record, Duration::ZERO)
+                .await
+                .map(|_| ())
+                .map_err(|(e, _)| EgressError::Publish(e.to_string()))
+        }
+
+        fn name(&self) -> &'static str {
+            "Kafka"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::{MessageRate, MockService};
+    use tokio::sync::mpsc;
+
+    /// Records what would have been published.
+    struct Capture(mpsc::UnboundedSender<(String, String, Vec<u8>)>);
+
+    #[async_trait]
+    impl Publisher for Capture {
+        async fn publish(
+            &self,
+            schema: &str,
+            symbol: &str,
+            payload: Vec<u8>,
+        ) -> Result<(), EgressError> {
+            let _ = self
+                .0
+                .send((schema.to_string(), symbol.to_string(), payload));
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            "capture"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwards_data_messages() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let service = Arc::new(MockService::new().with_rate(MessageRate::Fixed { hz: 1000 }));
+        let tasks = spawn(
+            service,
+            Arc::new(Capture(tx)),
+            EgressConfig {
+                symbols: vec!["ES.FUT".to_string()],
+                schemas: vec!["trades".to_string()],
+                format: EgressFormat::Msgpack,
+            },
+        );
+
+        let (schema, symbol, payload) = rx.recv().await.unwrap();
+        assert_eq!(schema, "trades");
+        assert_eq!(symbol, "ES.FUT");
+        let msg: LiveMessage = rmp_serde::from_slice(&payload).unwrap();
+        assert!(matches!(msg, LiveMessage::Trade { .. }));
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwards_depth_messages() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let service = Arc::new(MockService::new().with_rate(MessageRate::Fixed { hz: 1000 }));
+        let tasks = spawn(
+            service,
+            Arc::new(Capture(tx)),
+            EgressConfig {
+                symbols: vec!["ES.FUT".to_string()],
+                schemas: vec!["mbp-10".to_string()],
+                format: EgressFormat::Json,
+            },
+        );
+
+        let (schema, symbol, payload) = rx.recv().await.unwrap();
+        assert_eq!(schema, "mbp-10");
+        assert_eq!(symbol, "ES.FUT");
+        let msg: LiveMessage = serde_json::from_slice(&payload).unwrap();
+        assert!(matches!(msg, LiveMessage::Depth(_)));
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwards_order_messages() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let service = Arc::new(MockService::new().with_rate(MessageRate::Fixed { hz: 1000 }));
+        let tasks = spawn(
+            service,
+            Arc::new(Capture(tx)),
+            EgressConfig {
+                symbols: vec!["ES.FUT".to_string()],
+                schemas: vec!["mbo".to_string()],
+                format: EgressFormat::Msgpack,
+            },
+        );
+
+        let (schema, symbol, payload) = rx.recv().await.unwrap();
+        assert_eq!(schema, "mbo");
+        assert_eq!(symbol, "ES.FUT");
+        let msg: LiveMessage = rmp_serde::from_slice(&payload).unwrap();
+        assert!(matches!(msg, LiveMessage::Mbo(_)));
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejects_unknown_scheme() {
+        assert!(matches!(
+            connect("amqp://localhost", "marketdata").await,
+            Err(EgressError::Config(_))
+        ));
+        assert!("xml".parse::<EgressFormat>().is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Entitlements: which data each API key may fetch.
+//!
+//! Every historical fetch and live subscription is put to an [`Authorizer`]
+//! first, once per symbol, with the upstream dataset the symbol routes to.
+//! The default, [`AllowAll`], lets everything through. Deployments bound by
+//! exchange licensing can plug in their own, or set `ENTITLEMENTS` to grant
+//! datasets per key with [`DatasetGrants`], e.g.:
+//!
+//! ```text
+//! ENTITLEMENTS="3fa1b2c3d4e5f607=GLBX.MDP3,OPRA.PILLAR;*=GLBX.MDP3"
+//! ```
+
+use crate::service::MarketDataService;
+use async_trait::async_trait;
+use shared::HistoricalRequest;
+use std::collections::HashMap;
+use std::fmt;
+
+/// How data is being asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Historical,
+    Live,
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessKind::Historical => write!(f, "historical"),
+            AccessKind::Live => write!(f, "live"),
+        }
+    }
+}
+
+/// One symbol a key asks for.
+#[derive(Debug, Clone, Copy)]
+pub struct Access<'a> {
+    /// The caller's usage account (see [`crate::usage::key_id`])
+    pub key_id: &'a str,
+    pub kind: AccessKind,
+    pub schema: &'a str,
+    pub symbol: &'a str,
+    /// Upstream dataset the symbol is fetched from; `None` for providers
+    /// without datasets, such as mock mode
+    pub dataset: Option<&'a str>,
+}
+
+/// Decides whether a key may have a symbol's data.
+#[async_trait]
+pub trait Authorizer: Send + Sync {
+    /// `Ok` to allow, or why not.
+    async fn authorize(&self, access: &Access<'_>) -> Result<(), String>;
+
+    /// Whether decisions hang on the dataset, so a request whose dataset
+    /// can't be determined must be refused rather than put to
+    /// [`Authorizer::authorize`] without one.
+    fn needs_dataset(&self) -> bool {
+        false
+    }
+}
+
+/// Allows everything; the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllowAll;
+
+#[async_trait]
+impl Authorizer for AllowAll {
+    async fn authorize(&self, _access: &Access<'_>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Datasets granted to each key, from `key=DATASET,DATASET;...`. `*` as a
+/// key covers keys not listed; `*` as a dataset grants all of them. Keys
+/// matching no entry get nothing. Symbols from providers without datasets
+/// are always allowed, but requests the provider can't route are refused.
+#[derive(Debug, Clone, Default)]
+pub struct DatasetGrants {
+    grants: HashMap<String, Vec<String>>,
+}
+
+impl DatasetGrants {
+    /// Parse a `key=DATASET,DATASET;...` table.
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let mut grants: HashMap<String, Vec<String>> = HashMap::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (key, datasets) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("'{}' should be key=DATASET,DATASET", entry))?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(format!("'{}' names no key", entry));
+            }
+            grants.entry(key.to_string()).or_default().extend(
+                datasets
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|d| !d.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        Ok(Self { grants })
+    }
+
+    /// Whether `key_id` may fetch from `dataset`.
+    pub fn allows(&self, key_id: &str, dataset: &str) -> bool {
+        self.grants
+            .get(key_id)
+            .or_else(|| self.grants.get("*"))
+            .is_some_and(|datasets| datasets.iter().any(|d| d == "*" || d == dataset))
+    }
+}
+
+#[async_trait]
+impl Authorizer for DatasetGrants {
+    async fn authorize(&self, access: &Access<'_>) -> Result<(), String> {
+        match access.dataset {
+            Some(dataset) if !self.allows(access.key_id, dataset) => Err(format!(
+                "this key may not access {} ({})",
+                dataset, access.symbol
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn needs_dataset(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EntitlementError {
+    #[error("Not entitled: {0}")]
+    Denied(String),
+    #[error("Not entitled: can't determine the dataset: {0}")]
+    Unresolved(String),
+}
+
+/// Put each of `req`'s symbols to `authorizer`, stopping at the first
+/// refused.
+pub async fn check(
+    authorizer: &dyn Authorizer,
+    service: &dyn MarketDataService,
+    key_id: &str,
+    kind: AccessKind,
+    req: &HistoricalRequest,
+) -> Result<(), EntitlementError> {
+    let routes = match service.datasets(req) {
+        Ok(routes) => routes,
+        Err(e) if authorizer.needs_dataset() => {
+            return Err(EntitlementError::Unresolved(e.to_string()))
+        }
+        // Otherwise a request the provider can't route fails when fetched
+        Err(_) => Vec::new(),
+    };
+    for symbol in &req.symbols {
+        let dataset = routes
+            .iter()
+            .find(|route| route.symbols.contains(symbol))
+            .map(|route| route.dataset.as_str());
+        let access = Access {
+            key_id,
+            kind,
+            schema: &req.schema,
+            symbol,
+            dataset,
+        };
+        authorizer
+            .authorize(&access)
+            .await
+            .map_err(EntitlementError::Denied)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::MockService;
+    use crate::service::{LiveStream, ServiceError};
+    use shared::validation::DatasetRoute;
+    use shared::{HistoricalResponse, SymbolType};
+
+    #[tokio::test]
+    async fn test_dataset_grants() {
+        let grants = DatasetGrants::from_spec("abc=GLBX.MDP3, OPRA.PILLAR; *=GLBX.MDP3").unwrap();
+        assert!(grants.allows("abc", "OPRA.PILLAR"));
+        assert!(grants.allows("def", "GLBX.MDP3"));
+        assert!(!grants.allows("def", "OPRA.PILLAR"));
+        let any = DatasetGrants::from_spec("abc=*").unwrap();
+        assert!(any.allows("abc", "XNAS.ITCH"));
+        assert!(!any.allows("def", "XNAS.ITCH"));
+        assert!(DatasetGrants::from_spec("abc").is_err());
+        assert!(DatasetGrants::from_spec("=GLBX.MDP3").is_err());
+
+        let access = |dataset| Access {
+            key_id: "def",
+            kind: AccessKind::Live,
+            schema: "trades",
+            symbol: "ES.FUT",
+            dataset,
+        };
+        assert!(grants.authorize(&access(Some("GLBX.MDP3"))).await.is_ok());
+        assert_eq!(
+            grants.authorize(&access(Some("OPRA.PILLAR"))).await,
+            Err("this key may not access OPRA.PILLAR (ES.FUT)".to_string())
+        );
+        assert!(grants.authorize(&access(None)).await.is_ok());
+    }
+
+    /// Refuses one symbol to everyone.
+    struct NoCrude;
+
+    #[async_trait]
+    impl Authorizer for NoCrude {
+        async fn authorize(&self, access: &Access<'_>) -> Result<(), String> {
+            match access.symbol {
+                "CL.FUT" => Err(format!("no {} {} data", access.kind, access.symbol)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let service = MockService::new();
+        let req = |symbols: &[&str]| HistoricalRequest {
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+            schema: "trades".to_string(),
+            ..Default::default()
+        };
+        let historical = AccessKind::Historical;
+        assert!(
+            check(&NoCrude, &service, "abc", historical, &req(&["ES.FUT"]))
+                .await
+                .is_ok()
+        );
+        let err = check(
+            &NoCrude,
+            &service,
+            "abc",
+            historical,
+            &req(&["ES.FUT", "CL.FUT"]),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Not entitled: no historical CL.FUT data");
+        assert!(
+            check(&AllowAll, &service, "abc", historical, &req(&["CL.FUT"]))
+                .await
+                .is_ok()
+        );
+    }
+
+    /// Can't route, fetch or subscribe to anything.
+    struct Unroutable;
+
+    #[async_trait]
+    impl MarketDataService for Unroutable {
+        async fn get_historical(
+            &self,
+            req: &HistoricalRequest,
+        ) -> Result<HistoricalResponse, ServiceError> {
+            Err(ServiceError::InvalidSymbol(req.symbols.join(",")))
+        }
+
+        async fn subscribe_live(
+            &self,
+            symbols: Vec<String>,
+            _schema: String,
+            _stype_in: SymbolType,
+        ) -> Result<LiveStream, ServiceError> {
+            Err(ServiceError::InvalidSymbol(symbols.join(",")))
+        }
+
+        fn datasets(&self, req: &HistoricalRequest) -> Result<Vec<DatasetRoute>, ServiceError> {
+            Err(ServiceError::InvalidSymbol(req.symbols.join(",")))
+        }
+
+        fn name(&self) -> &'static str {
+            "Unroutable"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_unroutable() {
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "trades".to_string(),
+            ..Default::default()
+        };
+        let live = AccessKind::Live;
+        let grants = DatasetGrants::from_spec("*=*").unwrap();
+        let err = check(&grants, &Unroutable, "abc", live, &req)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, EntitlementError::Unresolved(_)));
+        assert!(check(&AllowAll, &Unroutable, "abc", live, &req)
+            .await
+            .is_ok());
+    }
+}
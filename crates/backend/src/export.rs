@@ -0,0 +1,468 @@
+//! Writing historical responses to files.
+//!
+//! Parquet and JSON carry the raw integer fields under their JSON names, so
+//! exported files round-trip without losing fixed-point precision. CSV is
+//! meant for spreadsheets: prices are decimals at the instrument's tick
+//! precision (see [`TickTable`]). Quote columns on trades are empty (null)
+//! when the trade has no quote.
+
+use arrow_array::{
+    Array, ArrayRef, Int64Array, RecordBatch, StringArray, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use sha2::{Digest, Sha256};
+use shared::jobs::ExportFormat;
+use shared::price::{format_price_to_tick, PRICE_SCALE};
+use shared::ticks::TickTable;
+use shared::{HistoricalResponse, OhlcvRecord, QuoteAtTrade, Schema, TradeRecord, TradeSide};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Error type for file exports.
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Missing or mistyped column '{0}'")]
+    Column(String),
+}
+
+const TRADE_COLUMNS: [&str; 7] = [
+    "ts_event_unix_ns",
+    "symbol",
+    "price_i64",
+    "size_u32",
+    "bid_px_i64",
+    "ask_px_i64",
+    "side",
+];
+const OHLCV_COLUMNS: [&str; 7] = [
+    "ts_event_unix_ns",
+    "symbol",
+    "open_i64",
+    "high_i64",
+    "low_i64",
+    "close_i64",
+    "volume_u64",
+];
+const CSV_TRADE_COLUMNS: [&str; 7] = [
+    "ts_event_unix_ns",
+    "symbol",
+    "price",
+    "size",
+    "bid_px",
+    "ask_px",
+    "side",
+];
+const CSV_OHLCV_COLUMNS: [&str; 7] = [
+    "ts_event_unix_ns",
+    "symbol",
+    "open",
+    "high",
+    "low",
+    "close",
+    "volume",
+];
+
+/// Write `resp` to `path` in `format`, formatting CSV prices with `ticks`.
+/// Returns the number of records written.
+pub fn write_response(
+    resp: &HistoricalResponse,
+    path: &Path,
+    format: ExportFormat,
+    ticks: &TickTable,
+) -> Result<usize, ExportError> {
+    let mut w = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Parquet => write_parquet(w, resp, None)?,
+        ExportFormat::Csv => {
+            write_csv(&mut w, resp, ticks)?;
+            w.flush()?;
+        }
+        ExportFormat::Json => {
+            serde_json::to_writer(&mut w, resp)?;
+            w.flush()?;
+        }
+    }
+    Ok(resp.len())
+}
+
+/// SHA-256 of the file at `path`, hex-encoded.
+pub fn file_sha256(path: &Path) -> Result<String, ExportError> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// SHA-256 of `resp`'s records as JSON, hex-encoded: the same for the same
+/// records whichever format they're written in.
+pub fn records_sha256(resp: &HistoricalResponse) -> Result<String, ExportError> {
+    let mut hasher = Sha256::new();
+    serde_json::to_writer(&mut hasher, resp)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Write `resp` to `path` as Snappy-compressed Parquet, for files kept on
+/// disk rather than handed out. Returns the number of records written.
+pub fn write_compressed_parquet(
+    resp: &HistoricalResponse,
+    path: &Path,
+) -> Result<usize, ExportError> {
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    write_parquet(BufWriter::new(File::create(path)?), resp, Some(props))?;
+    Ok(resp.len())
+}
+
+fn write_parquet(
+    w: impl Write + Send,
+    resp: &HistoricalResponse,
+    props: Option<WriterProperties>,
+) -> Result<(), ExportError> {
+    let batch = record_batch(resp)?;
+    let mut writer = ArrowWriter::try_new(w, batch.schema(), props)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Read a Parquet file written by this module back as `schema`'s records.
+pub fn read_parquet(path: &Path, schema: &Schema) -> Result<HistoricalResponse, ExportError> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+    let mut resp = HistoricalResponse::empty(schema);
+    for batch in reader {
+        let batch = batch?;
+        let data = match schema {
+            Schema::Trades => HistoricalResponse::Trades {
+                data: read_trades(&batch)?,
+            },
+            _ => HistoricalResponse::from_bars(schema, read_bars(&batch)?)
+                .unwrap_or(HistoricalResponse::Trades { data: Vec::new() }),
+        };
+        resp.append(data);
+    }
+    Ok(resp)
+}
+
+fn column<'a, T: Array + 'static>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a T, ExportError> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_any().downcast_ref::<T>())
+        .ok_or_else(|| ExportError::Column(name.to_string()))
+}
+
+fn read_trades(batch: &RecordBatch) -> Result<Vec<TradeRecord>, ExportError> {
+    let ts = column::<UInt64Array>(batch, TRADE_COLUMNS[0])?;
+    let symbol = column::<StringArray>(batch, TRADE_COLUMNS[1])?;
+    let price = column::<Int64Array>(batch, TRADE_COLUMNS[2])?;
+    let size = column::<UInt32Array>(batch, TRADE_COLUMNS[3])?;
+    let bid = column::<Int64Array>(batch, TRADE_COLUMNS[4])?;
+    let ask = column::<Int64Array>(batch, TRADE_COLUMNS[5])?;
+    let side = column::<StringArray>(batch, TRADE_COLUMNS[6])?;
+    let quote = |i: usize| {
+        if bid.is_null(i) || ask.is_null(i) || side.is_null(i) {
+            return None;
+        }
+        let side = match side.value(i) {
+            "bid" => TradeSide::Bid,
+            "ask" => TradeSide::Ask,
+            _ => TradeSide::Mid,
+        };
+        Some(QuoteAtTrade {
+            bid_px_i64: bid.value(i),
+            ask_px_i64: ask.value(i),
+            side,
+        })
+    };
+    Ok((0..batch.num_rows())
+        .map(|i| TradeRecord {
+            ts_event_unix_ns: ts.value(i),
+            symbol: symbol.value(i).to_string(),
+            price_i64: price.value(i),
+            size_u32: size.value(i),
+            quote: quote(i),
+            sequence_u32: None,
+        })
+        .collect())
+}
+
+fn read_bars(batch: &RecordBatch) -> Result<Vec<OhlcvRecord>, ExportError> {
+    let ts = column::<UInt64Array>(batch, OHLCV_COLUMNS[0])?;
+    let symbol = column::<StringArray>(batch, OHLCV_COLUMNS[1])?;
+    let open = column::<Int64Array>(batch, OHLCV_COLUMNS[2])?;
+    let high = column::<Int64Array>(batch, OHLCV_COLUMNS[3])?;
+    let low = column::<Int64Array>(batch, OHLCV_COLUMNS[4])?;
+    let close = column::<Int64Array>(batch, OHLCV_COLUMNS[5])?;
+    let volume = column::<UInt64Array>(batch, OHLCV_COLUMNS[6])?;
+    Ok((0..batch.num_rows())
+        .map(|i| OhlcvRecord {
+            ts_event_unix_ns: ts.value(i),
+            symbol: symbol.value(i).to_string(),
+            open_i64: open.value(i),
+            high_i64: high.value(i),
+            low_i64: low.value(i),
+            close_i64: close.value(i),
+            volume_u64: volume.value(i),
+        })
+        .collect())
+}
+
+fn side_str(side: TradeSide) -> &'static str {
+    match side {
+        TradeSide::Bid => "bid",
+        TradeSide::Ask => "ask",
+        TradeSide::Mid => "mid",
+    }
+}
+
+fn write_csv(
+    w: &mut impl Write,
+    resp: &HistoricalResponse,
+    ticks: &TickTable,
+) -> std::io::Result<()> {
+    match resp {
+        HistoricalResponse::Trades { data } => {
+            writeln!(w, "{}", CSV_TRADE_COLUMNS.join(","))?;
+            for t in data {
+                let (bid, ask, side) = match &t.quote {
+                    Some(q) => (
+                        ticks.format_price(&t.symbol, q.bid_px_i64),
+                        ticks.format_price(&t.symbol, q.ask_px_i64),
+                        side_str(q.side),
+                    ),
+                    None => (String::new(), String::new(), ""),
+                };
+                writeln!(
+                    w,
+                    "{},{},{},{},{},{},{}",
+                    t.ts_event_unix_ns,
+                    t.symbol,
+                    ticks.format_price(&t.symbol, t.price_i64),
+                    t.size_u32,
+                    bid,
+                    ask,
+                    side
+                )?;
+            }
+        }
+        HistoricalResponse::Ohlcv1S { data }
+        | HistoricalResponse::Ohlcv1M { data }
+        | HistoricalResponse::Bars { data, .. } => {
+            write_csv_bars(w, data, |b, v| ticks.format_price(&b.symbol, v))?;
+        }
+        // CVD bars hold whole contracts, not prices
+        HistoricalResponse::Cvd1S { data } | HistoricalResponse::Cvd1M { data } => {
+            write_csv_bars(w, data, |_, v| format_price_to_tick(v, PRICE_SCALE))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_csv_bars(
+    w: &mut impl Write,
+    data: &[OhlcvRecord],
+    format: impl Fn(&OhlcvRecord, i64) -> String,
+) -> std::io::Result<()> {
+    writeln!(w, "{}", CSV_OHLCV_COLUMNS.join(","))?;
+    for b in data {
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{}",
+            b.ts_event_unix_ns,
+            b.symbol,
+            format(b, b.open_i64),
+            format(b, b.high_i64),
+            format(b, b.low_i64),
+            format(b, b.close_i64),
+            b.volume_u64
+        )?;
+    }
+    Ok(())
+}
+
+fn record_batch(resp: &HistoricalResponse) -> Result<RecordBatch, ExportError> {
+    match resp {
+        HistoricalResponse::Trades { data } => trades_batch(data),
+        HistoricalResponse::Ohlcv1S { data }
+        | HistoricalResponse::Ohlcv1M { data }
+        | HistoricalResponse::Cvd1S { data }
+        | HistoricalResponse::Cvd1M { data }
+        | HistoricalResponse::Bars { data, .. } => ohlcv_batch(data),
+    }
+}
+
+fn trades_batch(data: &[TradeRecord]) -> Result<RecordBatch, ExportError> {
+    let schema = ArrowSchema::new(vec![
+        Field::new(TRADE_COLUMNS[0], DataType::UInt64, false),
+        Field::new(TRADE_COLUMNS[1], DataType::Utf8, false),
+        Field::new(TRADE_COLUMNS[2], DataType::Int64, false),
+        Field::new(TRADE_COLUMNS[3], DataType::UInt32, false),
+        Field::new(TRADE_COLUMNS[4], DataType::Int64, true),
+        Field::new(TRADE_COLUMNS[5], DataType::Int64, true),
+        Field::new(TRADE_COLUMNS[6], DataType::Utf8, true),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            data.iter().map(|t| t.ts_event_unix_ns),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            data.iter().map(|t| t.symbol.as_str()),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            data.iter().map(|t| t.price_i64),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            data.iter().map(|t| t.size_u32),
+        )),
+        Arc::new(Int64Array::from_iter(
+            data.iter().map(|t| t.quote.map(|q| q.bid_px_i64)),
+        )),
+        Arc::new(Int64Array::from_iter(
+            data.iter().map(|t| t.quote.map(|q| q.ask_px_i64)),
+        )),
+        Arc::new(StringArray::from_iter(
+            data.iter().map(|t| t.quote.map(|q| side_str(q.side))),
+        )),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+fn ohlcv_batch(data: &[OhlcvRecord]) -> Result<RecordBatch, ExportError> {
+    let mut fields = vec![
+        Field::new(OHLCV_COLUMNS[0], DataType::UInt64, false),
+        Field::new(OHLCV_COLUMNS[1], DataType::Utf8, false),
+    ];
+    fields.extend(
+        OHLCV_COLUMNS[2..6]
+            .iter()
+            .map(|name| Field::new(*name, DataType::Int64, false)),
+    );
+    fields.push(Field::new(OHLCV_COLUMNS[6], DataType::UInt64, false));
+
+    let prices = |f: fn(&OhlcvRecord) -> i64| -> ArrayRef {
+        Arc::new(Int64Array::from_iter_values(data.iter().map(f)))
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            data.iter().map(|b| b.ts_event_unix_ns),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            data.iter().map(|b| b.symbol.as_str()),
+        )),
+        prices(|b| b.open_i64),
+        prices(|b| b.high_i64),
+        prices(|b| b.low_i64),
+        prices(|b| b.close_i64),
+        Arc::new(UInt64Array::from_iter_values(
+            data.iter().map(|b| b.volume_u64),
+        )),
+    ];
+    Ok(RecordBatch::try_new(
+        Arc::new(ArrowSchema::new(fields)),
+        columns,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use shared::QuoteAtTrade;
+
+    fn trades() -> HistoricalResponse {
+        let trade = |ts, quote| TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ES.FUT".to_string(),
+            price_i64: 5_000_250_000_001,
+            size_u32: 3,
+            quote,
+            sequence_u32: None,
+        };
+        HistoricalResponse::Trades {
+            data: vec![
+                trade(1, None),
+                trade(
+                    2,
+                    Some(QuoteAtTrade::classify(
+                        5_000_250_000_000,
+                        5_000_000_000_000,
+                        5_000_250_000_000,
+                    )),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let path = std::env::temp_dir().join(format!("sf-export-{}.csv", std::process::id()));
+        assert_eq!(
+            write_response(
+                &trades(),
+                &path,
+                ExportFormat::Csv,
+                &TickTable::with_defaults()
+            )
+            .unwrap(),
+            2
+        );
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_TRADE_COLUMNS.join(","));
+        assert_eq!(lines[1], "1,ES.FUT,5000.25,3,,,");
+        assert_eq!(lines[2], "2,ES.FUT,5000.25,3,5000.00,5000.25,ask");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let path = std::env::temp_dir().join(format!("sf-export-{}.parquet", std::process::id()));
+        write_response(
+            &trades(),
+            &path,
+            ExportFormat::Parquet,
+            &TickTable::default(),
+        )
+        .unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_columns(), TRADE_COLUMNS.len());
+        let bid = batch.column_by_name("bid_px_i64").unwrap();
+        assert_eq!(bid.null_count(), 1);
+
+        write_compressed_parquet(&trades(), &path).unwrap();
+        let HistoricalResponse::Trades { data } = read_parquet(&path, &Schema::Trades).unwrap()
+        else {
+            panic!("expected trades");
+        };
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].price_i64, 5_000_250_000_001);
+        assert!(data[0].quote.is_none());
+        assert_eq!(data[1].quote.unwrap().side, TradeSide::Ask);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,407 @@
+//! Live data fan-out through Redis pub/sub for multi-instance deployments.
+//!
+//! Clients on any instance read live data from Redis channels
+//! (`sf:live:{schema}:{stype_in}:{symbol}`) instead of holding their own
+//! upstream subscriptions, so WebSockets need no sticky routing. Each
+//! channel is fed by exactly one instance: whichever holds the matching
+//! `sf:feeder:...` lock. The feeder renews the lock while the channel has subscribers
+//! anywhere in the cluster; if it dies, the lock expires and an instance
+//! with local subscribers takes over.
+
+use crate::service::{
+    user_api_key, with_user_api_key, LiveStream, MarketDataService, MboStream, ServiceError,
+};
+use async_trait::async_trait;
+use futures::StreamExt;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use shared::validation::DatasetRoute;
+use shared::{DataVersion, HistoricalRequest, HistoricalResponse, LiveMessage, Schema, SymbolType};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How long a feeder lock survives without renewal.
+pub const LOCK_TTL: Duration = Duration::from_secs(10);
+
+/// How often feeders renew their lock and idle instances retry claiming.
+const CLAIM_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Release the lock only if this instance still holds it.
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Extend the lock only if this instance still holds it.
+const RENEW_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Pub/sub channel carrying live messages for one symbol.
+pub fn channel(schema: &str, stype_in: SymbolType, symbol: &str) -> String {
+    format!("sf:live:{}:{}:{}", schema, stype_in.as_str(), symbol)
+}
+
+fn lock_key(schema: &str, stype_in: SymbolType, symbol: &str) -> String {
+    format!("sf:feeder:{}:{}:{}", schema, stype_in.as_str(), symbol)
+}
+
+/// A `(schema, stype_in, symbol)` live feed.
+type Feed = (String, SymbolType, String);
+
+#[derive(Default)]
+struct FeedState {
+    /// Open client streams on this instance
+    subscribers: usize,
+    /// Whether a claim task is running for this feed
+    claiming: bool,
+}
+
+/// State shared with claim and feed tasks.
+struct Shared {
+    inner: Arc<dyn MarketDataService>,
+    conn: ConnectionManager,
+    instance_id: String,
+    /// Prepended to channel and lock names, partitioning the feeds
+    namespace: String,
+    feeds: Mutex<HashMap<Feed, FeedState>>,
+}
+
+/// A service wrapper that serves live data through Redis pub/sub.
+/// Historical requests pass straight through.
+pub struct RedisFanout {
+    client: redis::Client,
+    shared: Arc<Shared>,
+}
+
+impl RedisFanout {
+    pub fn new(
+        inner: Arc<dyn MarketDataService>,
+        client: redis::Client,
+        conn: ConnectionManager,
+    ) -> Self {
+        let instance_id = format!("{}-{:016x}", std::process::id(), rand::random::<u64>());
+        Self {
+            client,
+            shared: Arc::new(Shared {
+                inner,
+                conn,
+                instance_id,
+                namespace: String::new(),
+                feeds: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Keep feeds apart from fan-outs with other namespaces, e.g. a
+    /// tenant's, which subscribe upstream with their own provider keys.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        if let Some(shared) = Arc::get_mut(&mut self.shared) {
+            shared.namespace = namespace.into();
+        }
+        self
+    }
+}
+
+fn connection_error(e: redis::RedisError) -> ServiceError {
+    ServiceError::ConnectionError(format!("Redis: {}", e))
+}
+
+impl Shared {
+    fn feeds(&self) -> std::sync::MutexGuard<'_, HashMap<Feed, FeedState>> {
+        self.feeds.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn channel(&self, (schema, stype_in, symbol): &Feed) -> String {
+        format!("{}{}", self.namespace, channel(schema, *stype_in, symbol))
+    }
+
+    fn lock_key(&self, (schema, stype_in, symbol): &Feed) -> String {
+        format!("{}{}", self.namespace, lock_key(schema, *stype_in, symbol))
+    }
+
+    /// Count a local subscriber, starting a claim task if none is running.
+    /// The claim task feeds the channel with the subscribing user's
+    /// provider key.
+    fn attach(self: &Arc<Self>, feed: &Feed) {
+        let mut feeds = self.feeds();
+        let state = feeds.entry(feed.clone()).or_default();
+        state.subscribers += 1;
+        if !state.claiming {
+            state.claiming = true;
+            tokio::spawn(with_user_api_key(
+                user_api_key(),
+                Arc::clone(self).claim(feed.clone()),
+            ));
+        }
+    }
+
+    fn detach(&self, feed: &Feed) {
+        if let Some(state) = self.feeds().get_mut(feed) {
+            state.subscribers = state.subscribers.saturating_sub(1);
+        }
+    }
+
+    /// Try to become the feed's feeder until it has no local subscribers.
+    async fn claim(self: Arc<Self>, feed: Feed) {
+        loop {
+            {
+                let mut feeds = self.feeds();
+                if feeds.get(&feed).is_none_or(|s| s.subscribers == 0) {
+                    feeds.remove(&feed);
+                    return;
+                }
+            }
+
+            match self.try_lock(&feed).await {
+                Ok(true) => self.feed(&feed).await,
+                Ok(false) => {}
+                Err(e) => warn!(feed = ?feed, "Feeder lock failed: {}", e),
+            }
+            tokio::time::sleep(CLAIM_INTERVAL).await;
+        }
+    }
+
+    async fn try_lock(&self, feed: &Feed) -> redis::RedisResult<bool> {
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(self.lock_key(feed))
+            .arg(&self.instance_id)
+            .arg("NX")
+            .arg("PX")
+            .arg(LOCK_TTL.as_millis() as u64)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(acquired.is_some())
+    }
+
+    /// Whether the lock is still ours after extending it.
+    async fn renew_lock(&self, feed: &Feed) -> redis::RedisResult<bool> {
+        let renewed: i64 = redis::Script::new(RENEW_SCRIPT)
+            .key(self.lock_key(feed))
+            .arg(&self.instance_id)
+            .arg(LOCK_TTL.as_millis() as u64)
+            .invoke_async(&mut self.conn.clone())
+            .await?;
+        Ok(renewed == 1)
+    }
+
+    async fn release_lock(&self, feed: &Feed) {
+        let (schema, _, symbol) = feed;
+        let released: redis::RedisResult<i64> = redis::Script::new(RELEASE_SCRIPT)
+            .key(self.lock_key(feed))
+            .arg(&self.instance_id)
+            .invoke_async(&mut self.conn.clone())
+            .await;
+        if let Err(e) = released {
+            warn!(schema = %schema, symbol = %symbol, "Feeder unlock failed: {}", e);
+        }
+    }
+
+    /// Clients subscribed to `channel` across all instances.
+    async fn subscriber_count(&self, channel: &str) -> redis::RedisResult<u64> {
+        let (_, count): (String, u64) = redis::cmd("PUBSUB")
+            .arg("NUMSUB")
+            .arg(channel)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(count)
+    }
+
+    async fn publish(&self, channel: &str, msg: &LiveMessage) {
+        let payload = match serde_json::to_string(msg) {
+            Ok(payload) => payload,
+            Err(e) => return warn!("Failed to serialize live message: {}", e),
+        };
+        let published: redis::RedisResult<()> = self.conn.clone().publish(channel, payload).await;
+        if let Err(e) = published {
+            warn!(channel = %channel, "Publish failed: {}", e);
+        }
+    }
+
+    /// Relay the upstream feed into its channel while anyone listens and
+    /// the lock stays ours. Releases the lock on return.
+    async fn feed(&self, feed: &Feed) {
+        let (schema, stype_in, symbol) = feed;
+        let channel = self.channel(feed);
+        info!(channel = %channel, "Feeding live channel");
+
+        match self
+            .inner
+            .subscribe_live(vec![symbol.clone()], schema.clone(), *stype_in)
+            .await
+        {
+            Ok(mut upstream) => {
+                let mut renew = tokio::time::interval(CLAIM_INTERVAL);
+                renew.tick().await;
+                loop {
+                    tokio::select! {
+                        msg = upstream.next() => match msg {
+                            // Each client stream sends its own
+                            Some(LiveMessage::Connected { .. }) => {}
+                            Some(msg) => self.publish(&channel, &msg).await,
+                            None => break,
+                        },
+                        _ = renew.tick() => {
+                            match self.renew_lock(feed).await {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                    warn!(channel = %channel, "Lost feeder lock");
+                                    return;
+                                }
+                                Err(e) => warn!(channel = %channel, "Lock renewal failed: {}", e),
+                            }
+                            if self.subscriber_count(&channel).await.is_ok_and(|n| n == 0) {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let message = e.to_string();
+                self.publish(&channel, &LiveMessage::Error { message })
+                    .await;
+            }
+        }
+
+        info!(channel = %channel, "Stopped feeding live channel");
+        self.release_lock(feed).await;
+    }
+}
+
+/// Detaches a client stream's feeds when the stream is dropped.
+struct SubscriberGuard {
+    shared: Arc<Shared>,
+    feeds: Vec<Feed>,
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        for feed in &self.feeds {
+            self.shared.detach(feed);
+        }
+    }
+}
+
+#[async_trait]
+impl MarketDataService for RedisFanout {
+    async fn get_historical(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<HistoricalResponse, ServiceError> {
+        self.shared.inner.get_historical(req).await
+    }
+
+    async fn subscribe_live(
+        &self,
+        symbols: Vec<String>,
+        schema: String,
+        stype_in: SymbolType,
+    ) -> Result<LiveStream, ServiceError> {
+        schema
+            .parse::<Schema>()
+            .map_err(ServiceError::InvalidSchema)?;
+        stype_in
+            .validate(&symbols)
+            .map_err(ServiceError::InvalidSymbol)?;
+
+        // Subscribe before claiming so no early messages are missed
+        let mut pubsub = self
+            .client
+            .get_async_pubsub()
+            .await
+            .map_err(connection_error)?;
+        let feeds: Vec<Feed> = symbols
+            .iter()
+            .map(|symbol| (schema.clone(), stype_in, symbol.clone()))
+            .collect();
+        for feed in &feeds {
+            pubsub
+                .subscribe(self.shared.channel(feed))
+                .await
+                .map_err(connection_error)?;
+        }
+        for feed in &feeds {
+            self.shared.attach(feed);
+        }
+        let guard = SubscriberGuard {
+            shared: Arc::clone(&self.shared),
+            feeds,
+        };
+
+        Ok(Box::pin(async_stream::stream! {
+            let _guard = guard;
+            yield LiveMessage::Connected {
+                symbols,
+                schema,
+                connection_id: None,
+            };
+
+            let mut messages = pubsub.into_on_message();
+            while let Some(msg) = messages.next().await {
+                match serde_json::from_slice::<LiveMessage>(msg.get_payload_bytes()) {
+                    Ok(msg) => yield msg,
+                    Err(e) => warn!("Ignoring malformed live message: {}", e),
+                }
+            }
+        }))
+    }
+
+    async fn count_records(&self, req: &HistoricalRequest) -> Result<u64, ServiceError> {
+        self.shared.inner.count_records(req).await
+    }
+
+    async fn estimate_cost(&self, req: &HistoricalRequest) -> Result<f64, ServiceError> {
+        self.shared.inner.estimate_cost(req).await
+    }
+
+    async fn unresolved_symbols(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<String>, ServiceError> {
+        self.shared.inner.unresolved_symbols(req).await
+    }
+
+    async fn data_versions(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<DataVersion>, ServiceError> {
+        self.shared.inner.data_versions(req).await
+    }
+
+    async fn replay_mbo(&self, req: &HistoricalRequest) -> Result<MboStream, ServiceError> {
+        self.shared.inner.replay_mbo(req).await
+    }
+
+    fn datasets(&self, req: &HistoricalRequest) -> Result<Vec<DatasetRoute>, ServiceError> {
+        self.shared.inner.datasets(req)
+    }
+
+    fn name(&self) -> &'static str {
+        self.shared.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_names() {
+        assert_eq!(
+            channel("trades", SymbolType::Parent, "ES.FUT"),
+            "sf:live:trades:parent:ES.FUT"
+        );
+        assert_eq!(
+            lock_key("ohlcv-1s", SymbolType::InstrumentId, "4916"),
+            "sf:feeder:ohlcv-1s:instrument_id:4916"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Serving the web frontend from the backend.
+//!
+//! Built with `--features embed-ui`, the files of `ui/dist` (run
+//! `npm run build` in `ui` first) are compiled into the binary, so one
+//! executable serves both the API and the UI on one port. `UI_DIR` serves
+//! a directory from disk instead, with or without the feature.
+//!
+//! The frontend is the router's fallback: API routes match first. Paths
+//! that aren't files get `index.html`, for the frontend's own routes,
+//! except under `assets/`, where a missing file is a 404, and under `/api`,
+//! `/ws` and `/t`, so an unknown API route is still a 404.
+
+use axum::extract::State;
+use axum::http::{header, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "embed-ui")]
+#[derive(rust_embed::RustEmbed)]
+#[folder = "../../ui/dist"]
+#[allow_missing = true]
+struct Dist;
+
+/// Where the frontend's files come from.
+#[derive(Debug, Clone)]
+pub enum Assets {
+    /// Compiled in from `ui/dist`
+    #[cfg(feature = "embed-ui")]
+    Embedded,
+    /// Read from a directory as requested
+    Dir(PathBuf),
+}
+
+impl Assets {
+    /// The frontend compiled into the binary, if it was built with
+    /// `embed-ui` and `ui/dist` held a build at the time.
+    pub fn embedded() -> Option<Self> {
+        #[cfg(feature = "embed-ui")]
+        if Dist::get(INDEX).is_some() {
+            return Some(Assets::Embedded);
+        }
+        None
+    }
+
+    /// Contents of the file at `path`, relative to the frontend's root.
+    fn get(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        match self {
+            #[cfg(feature = "embed-ui")]
+            Assets::Embedded => Dist::get(path).map(|file| file.data),
+            Assets::Dir(dir) => {
+                let relative = Path::new(path);
+                if !relative
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_)))
+                {
+                    return None;
+                }
+                std::fs::read(dir.join(relative)).ok().map(Cow::Owned)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Assets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "embed-ui")]
+            Assets::Embedded => write!(f, "the binary"),
+            Assets::Dir(dir) => write!(f, "{}", dir.display()),
+        }
+    }
+}
+
+/// The frontend's entry point.
+const INDEX: &str = "index.html";
+
+/// Directory of the build's fingerprinted files.
+const ASSETS: &str = "assets/";
+
+/// Leading path segments that belong to the server, never the frontend.
+const SERVER_PREFIXES: [&str; 3] = ["api", "ws", "t"];
+
+/// `Content-Type` for a file, by extension.
+fn content_type(path: &str) -> &'static str {
+    let extension = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "html" => "text/html; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The response for `path` of the frontend.
+pub fn respond(assets: &Assets, method: &Method, path: &str) -> Response {
+    if method != Method::GET && method != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    let path = path.trim_start_matches('/');
+    let first = path.split('/').next().unwrap_or_default();
+    if SERVER_PREFIXES.contains(&first) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let path = if path.is_empty() { INDEX } else { path };
+
+    let (path, data) = match assets.get(path) {
+        Some(data) => (path, data),
+        // A frontend route rather than a missing file
+        None if !path.starts_with(ASSETS) => match assets.get(INDEX) {
+            Some(data) => (INDEX, data),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    // Vite fingerprints everything under assets/, so only index.html and
+    // unhashed files need checking for changes
+    let cache = if path.starts_with(ASSETS) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    (
+        [
+            (header::CONTENT_TYPE, content_type(path)),
+            (header::CACHE_CONTROL, cache),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+async fn serve(State(assets): State<Arc<Assets>>, method: Method, uri: Uri) -> Response {
+    respond(&assets, &method, uri.path())
+}
+
+/// A router serving `assets` at every path, to be the API router's
+/// fallback.
+pub fn router(assets: Assets) -> Router {
+    Router::new().fallback(serve).with_state(Arc::new(assets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_respond() {
+        let dir = std::env::temp_dir().join(format!("sf-frontend-{}", std::process::id()));
+        let dist = dir.join("dist");
+        std::fs::create_dir_all(dist.join("assets")).unwrap();
+        std::fs::write(dist.join(INDEX), "<html>").unwrap();
+        std::fs::write(dist.join("assets/index-1a2b.js"), "js").unwrap();
+        std::fs::write(dir.join("secret.txt"), "").unwrap();
+        let assets = Assets::Dir(dist);
+        let get = |path| respond(&assets, &Method::GET, path);
+
+        let index = get("/");
+        assert_eq!(
+            index.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(index.headers()[header::CACHE_CONTROL], "no-cache");
+        assert_eq!(body(index).await, "<html>");
+
+        let script = get("/assets/index-1a2b.js");
+        assert_eq!(
+            script.headers()[header::CONTENT_TYPE],
+            "text/javascript; charset=utf-8"
+        );
+        assert!(script.headers()[header::CACHE_CONTROL]
+            .to_str()
+            .unwrap()
+            .contains("immutable"));
+
+        // Frontend routes get the entry point; missing files and server
+        // paths don't
+        assert_eq!(body(get("/charts/ES.FUT")).await, "<html>");
+        assert_eq!(get("/assets/missing.js").status(), StatusCode::NOT_FOUND);
+        assert_eq!(get("/api/nope").status(), StatusCode::NOT_FOUND);
+        assert_eq!(get("/t/acme/api/health").status(), StatusCode::NOT_FOUND);
+        assert_eq!(body(get("/../secret.txt")).await, "<html>");
+        assert_eq!(
+            respond(&assets, &Method::POST, "/").status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
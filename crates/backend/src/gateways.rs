@@ -0,0 +1,336 @@
+//! Choosing among DataBento live gateways.
+//!
+//! `LIVE_GATEWAYS` names the gateways live sessions may connect to, e.g.
+//! `primary={dataset}.lsg.databento.com:13000;dr=10.20.0.5:13000`, where
+//! `{dataset}` stands for the dataset's host name (`GLBX.MDP3` becomes
+//! `glbx-mdp3`). Each is probed at startup by timing a TCP connect.
+//!
+//! A session tries the gateways in order of preference, fastest first with
+//! `LIVE_GATEWAY_PREFERENCE=latency` (the default) or as listed with
+//! `order`, moving on to the next when one can't be connected to. A gateway
+//! that failed, to connect or mid-session, goes to the back of the line for
+//! [`FAILOVER_COOLDOWN`], so a reconnecting client lands elsewhere. Which
+//! gateway served how many sessions is reported by `GET /api/metrics`.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use shared::metrics::GatewayStats;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// How long a failed gateway is tried after the others.
+pub const FAILOVER_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Longest a startup probe waits for a connection.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A gateway's address as configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gateway {
+    pub name: String,
+    /// `host:port`, with `{dataset}` replaced per dataset
+    pub address: String,
+}
+
+impl Gateway {
+    /// Address to reach `dataset` at, e.g. `glbx-mdp3.lsg.databento.com:13000`.
+    pub fn address_for(&self, dataset: &str) -> String {
+        let host = dataset.to_ascii_lowercase().replace(['.', '_'], "-");
+        self.address.replace("{dataset}", &host)
+    }
+}
+
+/// Parse `name=host:port;...`.
+pub fn parse(spec: &str) -> Result<Vec<Gateway>, String> {
+    let mut gateways: Vec<Gateway> = Vec::new();
+    for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, address) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("'{}' should be name=host:port", entry))?;
+        let (name, address) = (name.trim(), address.trim());
+        if name.is_empty() || !address.contains(':') {
+            return Err(format!("'{}' should be name=host:port", entry));
+        }
+        if gateways.iter().any(|g| g.name == name) {
+            return Err(format!("Gateway '{}' is named twice", name));
+        }
+        gateways.push(Gateway {
+            name: name.to_string(),
+            address: address.to_string(),
+        });
+    }
+    if gateways.is_empty() {
+        return Err("No gateways listed".to_string());
+    }
+    Ok(gateways)
+}
+
+/// How gateways are ranked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GatewayPreference {
+    /// Fastest probe first; unreachable gateways last
+    #[default]
+    Latency,
+    /// As listed in `LIVE_GATEWAYS`
+    Order,
+}
+
+impl FromStr for GatewayPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latency" => Ok(GatewayPreference::Latency),
+            "order" => Ok(GatewayPreference::Order),
+            other => Err(format!(
+                "Unknown gateway preference '{}': expected latency or order",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    latency: Option<Duration>,
+    last_failure: Option<(Instant, DateTime<Utc>)>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    gateway: Gateway,
+    health: Mutex<Health>,
+    sessions: AtomicU64,
+    active: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl Entry {
+    fn health(&self) -> std::sync::MutexGuard<'_, Health> {
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn cooling_down(&self, now: Instant) -> bool {
+        self.health()
+            .last_failure
+            .is_some_and(|(at, _)| now.duration_since(at) < FAILOVER_COOLDOWN)
+    }
+}
+
+/// The configured gateways and how each has fared.
+#[derive(Debug)]
+pub struct Gateways {
+    entries: Vec<Entry>,
+    preference: GatewayPreference,
+}
+
+impl Gateways {
+    pub fn new(gateways: Vec<Gateway>, preference: GatewayPreference) -> Self {
+        Self {
+            entries: gateways
+                .into_iter()
+                .map(|gateway| Entry {
+                    gateway,
+                    health: Mutex::default(),
+                    sessions: AtomicU64::new(0),
+                    active: AtomicU64::new(0),
+                    failures: AtomicU64::new(0),
+                })
+                .collect(),
+            preference,
+        }
+    }
+
+    /// Time a TCP connect to each gateway for each of `datasets`, keeping
+    /// each gateway's fastest.
+    pub async fn probe(&self, datasets: &[String]) {
+        let probes = self.entries.iter().map(|entry| async move {
+            let mut fastest: Option<Duration> = None;
+            for dataset in datasets {
+                let address = entry.gateway.address_for(dataset);
+                let started = Instant::now();
+                match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(&address)).await {
+                    Ok(Ok(_)) => {
+                        let elapsed = started.elapsed();
+                        fastest = Some(fastest.map_or(elapsed, |f| f.min(elapsed)));
+                    }
+                    Ok(Err(e)) => {
+                        warn!(gateway = %entry.gateway.name, "Probe of {} failed: {}", address, e)
+                    }
+                    Err(_) => {
+                        warn!(gateway = %entry.gateway.name, "Probe of {} timed out", address)
+                    }
+                }
+            }
+            match fastest {
+                Some(latency) => info!(
+                    gateway = %entry.gateway.name,
+                    latency_ms = latency.as_secs_f64() * 1000.0,
+                    "Probed live gateway"
+                ),
+                None => warn!(gateway = %entry.gateway.name, "Live gateway unreachable"),
+            }
+            entry.health().latency = fastest;
+        });
+        futures::future::join_all(probes).await;
+    }
+
+    /// Gateways in the order a new session should try them.
+    pub fn candidates(&self) -> Vec<&Gateway> {
+        let now = Instant::now();
+        let mut ranked: Vec<(bool, Option<Duration>, usize, &Entry)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.cooling_down(now), entry.health().latency, i, entry))
+            .collect();
+        ranked.sort_by_key(|(cooling, latency, i, _)| match self.preference {
+            // None sorts before Some, so unreachable ones are keyed last
+            GatewayPreference::Latency => (*cooling, latency.is_none(), *latency, *i),
+            GatewayPreference::Order => (*cooling, false, None, *i),
+        });
+        ranked
+            .into_iter()
+            .map(|(_, _, _, entry)| &entry.gateway)
+            .collect()
+    }
+
+    fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.gateway.name == name)
+    }
+
+    /// Note that `name` couldn't be connected to, or failed mid-session.
+    pub fn failed(&self, name: &str) {
+        if let Some(entry) = self.entry(name) {
+            entry.failures.fetch_add(1, Ordering::Relaxed);
+            entry.health().last_failure = Some((Instant::now(), Utc::now()));
+        }
+    }
+
+    /// Count a session served by `name` until the returned guard drops.
+    pub fn session(self: &Arc<Self>, name: &str) -> GatewaySession {
+        if let Some(entry) = self.entry(name) {
+            entry.sessions.fetch_add(1, Ordering::Relaxed);
+            entry.active.fetch_add(1, Ordering::Relaxed);
+        }
+        GatewaySession {
+            gateways: self.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    /// Each gateway's statistics, in the order sessions would try them.
+    pub fn stats(&self) -> Vec<GatewayStats> {
+        self.candidates()
+            .into_iter()
+            .filter_map(|gateway| self.entry(&gateway.name))
+            .map(|entry| {
+                let health = entry.health();
+                GatewayStats {
+                    name: entry.gateway.name.clone(),
+                    address: entry.gateway.address.clone(),
+                    latency_ms: health.latency.map(|l| l.as_secs_f64() * 1000.0),
+                    sessions: entry.sessions.load(Ordering::Relaxed),
+                    active_sessions: entry.active.load(Ordering::Relaxed),
+                    failures: entry.failures.load(Ordering::Relaxed),
+                    last_failure_at: health
+                        .last_failure
+                        .map(|(_, at)| at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A live session on one gateway, counted as active until dropped.
+pub struct GatewaySession {
+    gateways: Arc<Gateways>,
+    name: String,
+}
+
+impl GatewaySession {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Note that the session ended on an error.
+    pub fn failed(&self) {
+        self.gateways.failed(&self.name);
+    }
+}
+
+impl Drop for GatewaySession {
+    fn drop(&mut self) {
+        if let Some(entry) = self.gateways.entry(&self.name) {
+            entry.active.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(gateways: &Gateways) -> Vec<&str> {
+        gateways
+            .candidates()
+            .into_iter()
+            .map(|g| g.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let gateways =
+            parse("primary={dataset}.lsg.databento.com:13000; dr=10.20.0.5:13000").unwrap();
+        assert_eq!(gateways.len(), 2);
+        assert_eq!(
+            gateways[0].address_for("GLBX.MDP3"),
+            "glbx-mdp3.lsg.databento.com:13000"
+        );
+        assert_eq!(gateways[1].address_for("GLBX.MDP3"), "10.20.0.5:13000");
+        assert!(parse("").is_err());
+        assert!(parse("primary").is_err());
+        assert!(parse("primary=localhost").is_err());
+        assert!(parse("a=h:1;a=h:2").is_err());
+        assert_eq!("order".parse(), Ok(GatewayPreference::Order));
+        assert!("nearest".parse::<GatewayPreference>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_and_failover() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Nothing listens on a port just freed
+        let closed = {
+            let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            l.local_addr().unwrap().port()
+        };
+        let spec = format!("down=127.0.0.1:{};up=127.0.0.1:{}", closed, port);
+        let gateways = Arc::new(Gateways::new(
+            parse(&spec).unwrap(),
+            GatewayPreference::Latency,
+        ));
+        gateways.probe(&["GLBX.MDP3".to_string()]).await;
+        assert_eq!(names(&gateways), ["up", "down"]);
+
+        let session = gateways.session("up");
+        assert_eq!(gateways.stats()[0].active_sessions, 1);
+        session.failed();
+        drop(session);
+        // A failed gateway is tried last until it cools down
+        assert_eq!(names(&gateways), ["down", "up"]);
+        let stats = gateways.stats();
+        assert_eq!((stats[1].sessions, stats[1].active_sessions), (1, 0));
+        assert_eq!(stats[1].failures, 1);
+        assert!(stats[1].latency_ms.is_some() && stats[1].last_failure_at.is_some());
+        assert_eq!(stats[0].latency_ms, None);
+
+        let ordered = Gateways::new(parse(&spec).unwrap(), GatewayPreference::Order);
+        ordered.probe(&["GLBX.MDP3".to_string()]).await;
+        assert_eq!(names(&ordered), ["down", "up"]);
+    }
+}
@@ -1,24 +1,200 @@
 //! HTTP and WebSocket handlers for the market data API.
 
-use crate::service::{MarketDataService, ServiceError};
+use crate::analytics::{
+    bar_message, with_bar_fanout, with_closed_bars, with_depth_book, with_order_books,
+    with_order_flow_imbalance, with_paper_fills, with_profiles, with_roll_alerts, with_rollups,
+    with_snapshot, with_trade_batches,
+};
+use crate::cache::{self, CacheMode};
+use crate::calendar::Calendar;
+use crate::cancel::{self, CancelError, InFlight, InFlightRequest};
+use crate::clock::{with_skew_check, ClockGuard};
+use crate::closes::PreviousCloses;
+use crate::conflate::{self, flush_every, AdaptiveRate, QueuedStream};
+use crate::connections::ConnectionRegistry;
+use crate::databento_service;
+use crate::entitlements::{self, AccessKind, Authorizer, EntitlementError};
+use crate::gateways::Gateways;
+use crate::imports::{self, ImportError};
+use crate::latency::{PipelineLatency, Stage, TransformTimer};
+use crate::monitor::MonitoredService;
+use crate::notify::{Notifier, WebhookError};
+use crate::paper::{PaperDesk, PaperError};
+use crate::positions::{PositionBook, PositionError};
+use crate::profiles::ProfileStore;
+use crate::queries::{QueryError, QueryOverrides, QueryStore};
+use crate::querylog::QueryLog;
+use crate::queue::FairQueue;
+use crate::ranges::RangePolicy;
+use crate::recordings::{RecordingError, Recordings};
+use crate::retention::Retention;
+use crate::rollups::{self, RollupStore};
+use crate::scenarios::{ScenarioError, Scenarios};
+use crate::scheduler::{JobError, Scheduler};
+use crate::secrets::{Secrets, SecretsError, DATABENTO_API_KEY};
+use crate::service::{
+    checked_stype_in, historical_partial, user_api_key, with_user_api_key, MarketDataService,
+    ServiceError,
+};
+use crate::store::StoreError;
+use crate::symbols::{SymbolResolver, UnknownSymbols};
+use crate::telemetry;
+use crate::uploads::{UploadError, Uploads, MAX_UPLOAD_RECORDS};
+use crate::usage::{self, Quota, UsageError, UsageTracker};
+use crate::users::{UserDirectory, UserError};
+use crate::versions::{ApiVersion, Deprecations};
 use axum::{
+    async_trait,
+    body::{Body, Bytes},
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Query, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Extension, FromRequest, FromRequestParts, Multipart, Path, Query, Request, State,
     },
-    http::StatusCode,
+    http::{header, request::Parts, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, NaiveTime, SecondsFormat, TimeDelta, Utc};
 use futures::{SinkExt, StreamExt};
 use serde::Deserialize;
-use shared::{ErrorResponse, HistoricalRequest};
+use shared::activity::{OrderActivity, OrderActivityReport, OrderActivityRequest};
+use shared::aggregate::{
+    aggregate_trades, resample_bars, BarAlign, BarFanout, ClosedBarFeed, NANOS_PER_MINUTE,
+    NANOS_PER_SECOND,
+};
+use shared::book::{BookResponse, BookSampleRequest, BookSampler, BookSet, MboRecord};
+use shared::calendar::CalendarEvent;
+use shared::change::DayChange;
+use shared::close;
+use shared::connections::ConnectionSymbols;
+use shared::depth::DEPTH_LEVELS;
+use shared::diff::{self, DiffReport, DiffRequest, DiffSource};
+use shared::flow::ImbalanceTracker;
+use shared::imports::{ImportReport, ImportSpec};
+use shared::integrity::{self, IntegrityReport};
+use shared::jobs::{JobManifest, JobRun, JobSpec};
+use shared::metrics::{ServerMetrics, StageLatency};
+use shared::paper::{NewPaperOrder, PaperCommand, PaperOrder, PaperPosition};
+use shared::positions::{PnlMarker, Position};
+use shared::price::PricesAs;
+use shared::profile::VolumeProfile;
+use shared::projection::{LiveProjection, ProjectedResponse, Projection};
+use shared::querylog::{CacheStatus, QueryLogFilter, QueryRecord};
+use shared::recordings::{CompactionReport, RecordingPartition};
+use shared::roll::{rank_contracts, FrontMonth, RollTracker};
+use shared::rollups::{Leaderboard, LeaderboardRank, RollupBars};
+use shared::scenarios::{Scenario, ScenarioRequest};
+use shared::stats::{
+    self, correlation, intraday_profiles, parse_bar_interval, CorrelationMatrix,
+    CorrelationRequest, IntradayProfile, IntradayProfileRequest, ReturnsReport, ReturnsRequest,
+    StatsRange,
+};
+use shared::status::StatusReport;
+use shared::storage::StorageReport;
+use shared::summary::DaySummary;
+use shared::tape::{self, Tape, TapeSource};
+use shared::tca::{TcaReport, TcaRequest};
+use shared::ticks::{TickSize, TickTable};
+use shared::timeexpr::{self, parse_lookback};
+use shared::uploads::UploadInfo;
+use shared::usage::UsageReport;
+use shared::users::{DatabentoKey, NewUser, UserInfo};
+use shared::validation::{self, RequestValidation, ValidationIssue};
+use shared::webhooks::{Webhook, WebhookEvent};
+use shared::wire::{self, VersionInfo};
+use shared::{
+    compact, fragment, BatchResult, CombinedRequest, CombinedResponse, DataSource, DataVersion,
+    ErrorResponse, HistoricalRequest, HistoricalResponse, LiveMessage, NormalizedResponse,
+    OhlcvRecord, RecordCount, RecordOrder, ResponseMeta, SavedQuery, Schema, SymbolError,
+    SymbolType,
+};
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::{error, info, warn};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{error, info, instrument, warn, Span};
 
 /// Application state shared across handlers.
 pub struct AppState {
     pub service: Arc<dyn MarketDataService>,
+    /// Whether `service` caches historical responses (see [`cache`])
+    pub cached: bool,
+    /// Consulted before each historical fetch and live subscription
+    pub authorizer: Arc<dyn Authorizer>,
+    /// DataBento live gateways; `None` unless `LIVE_GATEWAYS` is set
+    pub gateways: Option<Arc<Gateways>>,
+    /// Upstream historical fetches wait here for a turn
+    pub historical_queue: Arc<FairQueue>,
+    /// Requests being served, for cancelling
+    pub in_flight: Arc<InFlight>,
+    pub ws_limits: WsLimits,
+    pub queries: QueryStore,
+    pub scheduler: Arc<Scheduler>,
+    pub notifier: Arc<Notifier>,
+    pub usage: Arc<UsageTracker>,
+    pub users: Arc<UserDirectory>,
+    pub secrets: Arc<Secrets>,
+    pub ticks: Arc<TickTable>,
+    pub connections: Arc<ConnectionRegistry>,
+    pub clock: Arc<ClockGuard>,
+    /// Latency of each stage of the live pipeline
+    pub latency: Arc<PipelineLatency>,
+    pub paper: Arc<PaperDesk>,
+    pub positions: Arc<PositionBook>,
+    pub calendar: Arc<Calendar>,
+    pub monitor: Arc<MonitoredService>,
+    pub query_log: QueryLog,
+    pub ranges: RangePolicy,
+    pub symbols: SymbolResolver,
+    pub rollups: Arc<RollupStore>,
+    /// Developing volume profiles of live symbols
+    pub profiles: Arc<ProfileStore>,
+    /// Previous session closes, for day changes
+    pub closes: Arc<PreviousCloses>,
+    /// Recorded live data; `None` unless `RECORD_DIR` is set
+    pub recordings: Option<Arc<Recordings>>,
+    /// Scenarios the mock service follows; `None` unless in mock mode
+    pub scenarios: Option<Arc<Scenarios>>,
+    /// Directory imports may name files under; `None` unless `IMPORT_DIR`
+    /// is set
+    pub import_dir: Option<PathBuf>,
+    /// DBN files uploaded for viewing, by API key
+    pub uploads: Arc<Uploads>,
+    pub retention: Arc<Retention>,
+    /// Sunset dates of deprecated API versions
+    pub deprecations: Deprecations,
+}
+
+/// Size limits for WebSocket traffic.
+#[derive(Debug, Clone, Copy)]
+pub struct WsLimits {
+    /// Outbound messages larger than this are sent as `fragment` parts
+    pub max_frame_bytes: usize,
+    /// Inbound client messages larger than this are rejected with an error
+    pub max_inbound_bytes: usize,
+    /// Most completed bars sent ahead of a live bar subscription's updates
+    pub snapshot_bars: usize,
+}
+
+impl WsLimits {
+    /// Inbound messages past this many multiples of the soft limit are
+    /// refused at the protocol level, closing the connection.
+    const INBOUND_HARD_LIMIT_FACTOR: usize = 4;
+}
+
+impl Default for WsLimits {
+    fn default() -> Self {
+        Self {
+            // Stay under the common 1MB proxy frame cap
+            max_frame_bytes: 1_000_000,
+            max_inbound_bytes: 64 * 1024,
+            snapshot_bars: 100,
+        }
+    }
 }
 
 /// Health check endpoint.
@@ -26,19 +202,273 @@ pub async fn health() -> &'static str {
     "ok"
 }
 
+/// GET /api/metrics - Server-side statistics: clock skew between the
+/// server and the data it serves, the historical fetch queue, how the live
+/// gateways have served, and the live pipeline's latency by stage.
+pub async fn metrics(State(state): State<Arc<AppState>>) -> Json<ServerMetrics> {
+    Json(ServerMetrics {
+        clock_skew: state.clock.stats(),
+        historical_queue: state.historical_queue.stats(),
+        gateways: state
+            .gateways
+            .as_ref()
+            .map(|gateways| gateways.stats())
+            .unwrap_or_default(),
+        pipeline: state.latency.summary(),
+    })
+}
+
+/// Query parameters for `GET /api/debug/latency`.
+#[derive(Debug, Deserialize)]
+pub struct LatencyParams {
+    /// Clear the histograms once read, to time from now on (admin)
+    #[serde(default)]
+    pub reset: bool,
+}
+
+/// GET /api/debug/latency - Histograms of how long each stage of the live
+/// pipeline takes per message: decoding provider records, a subscription's
+/// transforms, serializing and sending to the socket.
+pub async fn debug_latency(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+    Query(params): Query<LatencyParams>,
+) -> Result<Json<Vec<StageLatency>>, UserError> {
+    if params.reset {
+        state.users.check_admin(token.as_deref())?;
+    }
+    let stats = state.latency.histograms();
+    if params.reset {
+        state.latency.reset();
+    }
+    Ok(Json(stats))
+}
+
+impl IntoResponse for ScenarioError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ScenarioError::Disabled | ScenarioError::NotFound(_) => StatusCode::NOT_FOUND,
+            ScenarioError::Invalid(_) => StatusCode::BAD_REQUEST,
+            ScenarioError::Full => StatusCode::CONFLICT,
+        };
+        status_error((status, self.to_string()))
+    }
+}
+
+/// The mock's scenarios, if the server runs in mock mode.
+fn scenarios(state: &AppState) -> Result<Arc<Scenarios>, ScenarioError> {
+    state.scenarios.clone().ok_or(ScenarioError::Disabled)
+}
+
+/// GET /api/mock/scenarios - Scheduled mock scenarios, soonest first.
+pub async fn list_scenarios(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Scenario>>, ScenarioError> {
+    Ok(Json(scenarios(&state)?.list()))
+}
+
+/// POST /api/mock/scenarios - Schedule a volatility spike, flash crash or
+/// trend day in mock data.
+pub async fn schedule_scenario(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ScenarioRequest>,
+) -> Result<impl IntoResponse, ScenarioError> {
+    let scenario = scenarios(&state)?.schedule(&req)?;
+    info!(id = scenario.id, kind = ?scenario.kind, "Scheduled mock scenario");
+
+    Ok((StatusCode::CREATED, Json(scenario)))
+}
+
+/// DELETE /api/mock/scenarios/:id - Remove a scheduled scenario.
+pub async fn delete_scenario(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> Result<StatusCode, ScenarioError> {
+    scenarios(&state)?.remove(id)?;
+    info!(id, "Removed mock scenario");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/mock/scenarios - Remove every scheduled scenario.
+pub async fn clear_scenarios(
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ScenarioError> {
+    let removed = scenarios(&state)?.clear();
+    info!(removed, "Cleared mock scenarios");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/version - Server build and wire format version, with what each
+/// wire format version changed and the API versions served.
+pub async fn version(State(state): State<Arc<AppState>>) -> Json<VersionInfo> {
+    Json(VersionInfo::new(
+        env!("CARGO_PKG_VERSION"),
+        state.deprecations.versions(),
+    ))
+}
+
+/// GET /api/status - Upstream provider health and recent incidents.
+pub async fn status(State(state): State<Arc<AppState>>) -> Json<StatusReport> {
+    Json(state.monitor.status())
+}
+
+/// HTTP status a ServiceError is reported with.
+fn service_status(error: &ServiceError) -> StatusCode {
+    match error {
+        ServiceError::InvalidSchema(_) => StatusCode::BAD_REQUEST,
+        ServiceError::InvalidTimeFormat(_) => StatusCode::BAD_REQUEST,
+        ServiceError::InvalidSymbol(_) => StatusCode::BAD_REQUEST,
+        ServiceError::ApiError(_) => StatusCode::BAD_GATEWAY,
+        ServiceError::ConnectionError(_) => StatusCode::BAD_GATEWAY,
+        ServiceError::NotConfigured(_) => StatusCode::UNAUTHORIZED,
+        ServiceError::RevisionUnavailable(_) => StatusCode::CONFLICT,
+        ServiceError::NotCached(_) => StatusCode::NOT_FOUND,
+        ServiceError::TooManyRecords(_) => StatusCode::BAD_REQUEST,
+        // The status nginx logs for a request the client gave up on
+        ServiceError::Cancelled => StatusCode::from_u16(499).unwrap_or(StatusCode::BAD_REQUEST),
+    }
+}
+
 /// Convert ServiceError to HTTP response.
 impl IntoResponse for ServiceError {
     fn into_response(self) -> Response {
-        let (status, message) = match &self {
-            ServiceError::InvalidSchema(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ServiceError::InvalidTimeFormat(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            ServiceError::ApiError(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
-            ServiceError::ConnectionError(_) => (StatusCode::BAD_GATEWAY, self.to_string()),
-            ServiceError::NotConfigured(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
+        let status = service_status(&self);
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// The caller's usage account, identified by the `X-API-Key` header or,
+/// for clients that can't set headers (browser WebSockets), the `api_key`
+/// query parameter.
+pub struct ApiKey(pub String);
+
+#[derive(Deserialize)]
+struct ApiKeyParam {
+    api_key: Option<String>,
+}
+
+/// The raw key a request was made with, if any.
+fn request_api_key(parts: &Parts) -> Option<String> {
+    let header = parts
+        .headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    header.or_else(|| {
+        Query::<ApiKeyParam>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|Query(p)| p.api_key)
+    })
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiKey {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ApiKey(usage::key_id(request_api_key(parts).as_deref())))
+    }
+}
+
+/// The user a request's API key belongs to. Handlers taking this reject
+/// requests without a valid user token.
+#[derive(Debug, Clone)]
+pub struct AuthUser(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = UserError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthUser>()
+            .cloned()
+            .ok_or(UserError::Unauthorized)
+    }
+}
+
+/// Middleware resolving the request's user, if its API key is a user
+/// token, and serving the request with that user's DataBento key.
+pub async fn user_context(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let user = request_api_key(&parts).and_then(|key| state.users.authenticate(&key));
+    let Some(name) = user else {
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+
+    let api_key = match state.users.databento_key(&name) {
+        Ok(api_key) => api_key,
+        Err(e) => {
+            error!(user = %name, "Failed to load DataBento key: {}", e);
+            return e.into_response();
+        }
+    };
+    parts.extensions.insert(AuthUser(name));
+    with_user_api_key(api_key, next.run(Request::from_parts(parts, body))).await
+}
+
+/// Middleware serving each request under a cancellation token registered
+/// by its request id and API key (see [`crate::cancel`]).
+pub async fn cancellable(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let Some(request_id) = parts
+        .headers
+        .get(telemetry::REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    else {
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+    let key_id = usage::key_id(request_api_key(&parts).as_deref());
+    let registration = state
+        .in_flight
+        .register(&request_id, &key_id, parts.uri.path());
+    let response = cancel::with_cancellation(
+        registration.token(),
+        next.run(Request::from_parts(parts, body)),
+    )
+    .await;
+    drop(registration);
+    response
+}
+
+/// Convert CancelError to HTTP response.
+impl IntoResponse for CancelError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            CancelError::NotFound(_) => StatusCode::NOT_FOUND,
         };
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
 
+/// Convert EntitlementError to HTTP response.
+impl IntoResponse for EntitlementError {
+    fn into_response(self) -> Response {
+        let status = StatusCode::FORBIDDEN;
         let body = Json(ErrorResponse {
-            error: message,
+            error: self.to_string(),
             code: status.as_u16(),
         });
 
@@ -46,146 +476,3559 @@ impl IntoResponse for ServiceError {
     }
 }
 
-/// POST /api/historical - Fetch historical market data.
-pub async fn historical(
-    State(state): State<Arc<AppState>>,
-    Json(req): Json<HistoricalRequest>,
-) -> Result<impl IntoResponse, ServiceError> {
-    info!(
-        symbols = ?req.symbols,
-        schema = %req.schema,
-        start = %req.start_rfc3339,
-        end = %req.end_rfc3339,
-        "Fetching historical data"
-    );
+/// Convert UsageError to HTTP response.
+impl IntoResponse for UsageError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            UsageError::QuotaExceeded(Quota::CostUsd) => StatusCode::PAYMENT_REQUIRED,
+            UsageError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            UsageError::InvalidQuotas(_) | UsageError::Storage(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
 
-    let response = state.service.get_historical(&req).await?;
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
 
-    Ok(Json(response))
+        (status, body).into_response()
+    }
 }
 
-/// Query parameters for WebSocket connection.
-#[derive(Debug, Deserialize)]
-pub struct LiveParams {
-    /// Comma-separated list of symbols (default: "ES.FUT")
-    #[serde(default = "default_symbols")]
-    pub symbols: String,
-    /// Schema type (default: "trades")
-    #[serde(default = "default_schema")]
-    pub schema: String,
-    /// Symbol type input (default: "parent") - reserved for DataBento integration
-    #[serde(default = "default_stype_in")]
-    #[allow(dead_code)]
-    pub stype_in: String,
+/// `req` with relative start and end times resolved and the clock limits
+/// applied.
+fn checked_range(
+    state: &AppState,
+    req: &HistoricalRequest,
+) -> Result<HistoricalRequest, ServiceError> {
+    let now = Utc::now();
+    let mut req = req.clone();
+    timeexpr::resolve_request(&mut req, now).map_err(ServiceError::InvalidTimeFormat)?;
+    state.clock.check_request(&mut req, now)?;
+    Ok(req)
 }
 
-fn default_symbols() -> String {
-    "ES.FUT".to_string()
+/// Check that `req`'s symbols resolve before anything is fetched. With
+/// `partial`, unknown symbols are dropped from `req` and returned as symbol
+/// errors as long as one is left; otherwise any fail the request. If the
+/// symbols can't be resolved at all, the request goes ahead unchecked.
+async fn checked_symbols(
+    state: &AppState,
+    req: &mut HistoricalRequest,
+    partial: bool,
+) -> Result<Vec<SymbolError>, UnknownSymbols> {
+    let unknown = match state.symbols.unknown(state.service.as_ref(), req).await {
+        Ok(unknown) => unknown,
+        Err(e) => {
+            warn!("Symbol check failed, fetching unchecked: {}", e);
+            return Ok(Vec::new());
+        }
+    };
+    if unknown.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !partial || unknown.len() == req.symbols.len() {
+        return Err(UnknownSymbols(unknown));
+    }
+    req.symbols
+        .retain(|symbol| !unknown.iter().any(|u| &u.symbol == symbol));
+    Ok(unknown
+        .into_iter()
+        .map(|u| SymbolError {
+            symbol: u.symbol.clone(),
+            error: UnknownSymbols(vec![u]).to_string(),
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        })
+        .collect())
 }
 
-fn default_schema() -> String {
-    "trades".to_string()
+/// Fetch historical data for `key_id`, enforcing its quotas and counting
+/// the records served and the estimated upstream cost. Relative times are
+/// resolved and a future end time is clamped to now first. The fetch is
+/// added to the query log.
+async fn metered_historical(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+) -> Result<HistoricalResponse, Response> {
+    Ok(metered_fetch(state, key_id, req, false).await?.response)
 }
 
-fn default_stype_in() -> String {
-    "parent".to_string()
+/// A metered fetch: the records, the request as fetched and where the
+/// records came from.
+struct Fetched {
+    response: HistoricalResponse,
+    /// The request with its times resolved and clamped
+    request: HistoricalRequest,
+    source: DataSource,
+    /// Symbols left out of a partial fetch
+    symbol_errors: Vec<SymbolError>,
+    /// The provider's current version of each dataset over the range
+    data_versions: Vec<DataVersion>,
 }
 
-/// GET /ws/live - WebSocket endpoint for live market data.
-pub async fn live_ws(
-    ws: WebSocketUpgrade,
-    Query(params): Query<LiveParams>,
-    State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    let symbols: Vec<String> = params
-        .symbols
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
+/// [`metered_historical`], also reporting how the records were fetched.
+/// With `partial`, symbols that fail are left out rather than failing the
+/// fetch (see [`historical_partial`]).
+#[instrument(
+    name = "historical",
+    skip_all,
+    fields(key_id = %key_id, symbols = ?req.symbols, schema = %req.schema, records, cache)
+)]
+async fn metered_fetch(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+    partial: bool,
+) -> Result<Fetched, Response> {
+    let (received_at, started) = (Utc::now(), Instant::now());
+    let mut req = checked_range(state, req).map_err(IntoResponse::into_response)?;
+    // Field names and number formats only change how records are sent,
+    // not which are fetched
+    req.compact = false;
+    req.prices_as = PricesAs::Number;
+    state
+        .ranges
+        .check(&req)
+        .map_err(IntoResponse::into_response)?;
+    let unknown = checked_symbols(state, &mut req, partial)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    entitlements::check(
+        state.authorizer.as_ref(),
+        state.service.as_ref(),
+        key_id,
+        AccessKind::Historical,
+        &req,
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    // An unpinned request is pinned to the current revision, which keys it
+    // in the cache, so data restated upstream is fetched again
+    let data_versions = match state.service.data_versions(&req).await {
+        Ok(versions) => versions,
+        Err(e) => {
+            warn!("Data version lookup failed: {}", e);
+            Vec::new()
+        }
+    };
+    if req.revision.is_none() {
+        req.revision = DataVersion::revision(&data_versions);
+    }
+    let req = &req;
 
-    info!(
-        symbols = ?symbols,
-        schema = %params.schema,
-        "WebSocket connection request"
-    );
+    state
+        .usage
+        .check_historical(key_id, 0.0)
+        .map_err(IntoResponse::into_response)?;
+
+    // Only a cost quota needs the estimate, which is a call upstream
+    let cost = match state.usage.quotas().cost_usd {
+        Some(_) => state
+            .service
+            .estimate_cost(req)
+            .await
+            .map_err(IntoResponse::into_response)?,
+        None => 0.0,
+    };
+    state
+        .usage
+        .check_historical(key_id, cost)
+        .map_err(IntoResponse::into_response)?;
+
+    let fetch = async {
+        let _turn = state.historical_queue.acquire(key_id).await;
+        match partial {
+            true => historical_partial(state.service.as_ref(), req).await,
+            false => Ok((state.service.get_historical(req).await?, Vec::new())),
+        }
+    };
+    // Cancelling drops the fetch, ending the upstream download
+    let (result, cache) = cache::observe(cancel::or_cancelled(fetch)).await;
+    let span = Span::current();
+    span.record("cache", cache.as_str());
+    // Responses served from the cache cost nothing upstream
+    let cost = if cache == CacheStatus::Hit { 0.0 } else { cost };
+    if let Ok((response, _)) = &result {
+        span.record("records", response.len());
+    }
+    state.query_log.record(QueryRecord {
+        id: 0,
+        at: received_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        key_id: key_id.to_string(),
+        symbols: req.symbols.clone(),
+        schema: req.schema.clone(),
+        start_rfc3339: req.start_rfc3339.clone(),
+        end_rfc3339: req.end_rfc3339.clone(),
+        dataset: req.dataset.clone(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        records: result.as_ref().map_or(0, |(r, _)| r.len() as u64),
+        cache,
+        cost_usd: cost,
+        error: result.as_ref().err().map(ToString::to_string),
+    });
+    let (response, failed) = result.map_err(IntoResponse::into_response)?;
+    state.rollups.ingest_historical(req, &response);
+    state
+        .usage
+        .record_historical(key_id, response.len() as u64, cost);
 
-    ws.on_upgrade(move |socket| handle_live_socket(socket, state, symbols, params.schema))
+    let failed = failed.into_iter().map(|(symbol, e)| SymbolError {
+        symbol,
+        code: service_status(&e).as_u16(),
+        error: e.to_string(),
+    });
+    let symbol_errors: Vec<SymbolError> = unknown.into_iter().chain(failed).collect();
+    for e in &symbol_errors {
+        warn!(symbol = %e.symbol, "Left symbol out of historical response: {}", e.error);
+    }
+    Ok(Fetched {
+        response,
+        request: req.clone(),
+        source: cache.into(),
+        symbol_errors,
+        data_versions,
+    })
 }
 
-/// Handle an active WebSocket connection.
-async fn handle_live_socket(
-    socket: WebSocket,
-    state: Arc<AppState>,
-    symbols: Vec<String>,
-    schema: String,
-) {
-    let (mut sender, mut receiver) = socket.split();
+/// Fetch `req` for `key_id` as `/api/historical` would, only to warm the
+/// caches on the way (see [`crate::prefetch`]). Returns how many records
+/// were fetched and where they came from.
+pub async fn prefetch(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+) -> Result<(usize, DataSource), ErrorResponse> {
+    match metered_fetch(state, key_id, req, false).await {
+        Ok(fetched) => Ok((fetched.response.len(), fetched.source)),
+        Err(response) => Err(error_body(response).await),
+    }
+}
 
-    // Subscribe to live data
-    let stream = match state
+/// Count the records `req` matches for `key_id` without fetching them.
+/// The key must be within its quotas, but nothing is recorded as served.
+async fn counted_historical(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+) -> Result<RecordCount, Response> {
+    let mut req = checked_range(state, req).map_err(IntoResponse::into_response)?;
+    checked_symbols(state, &mut req, false)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    state
+        .usage
+        .check_historical(key_id, 0.0)
+        .map_err(IntoResponse::into_response)?;
+
+    let count = state
         .service
-        .subscribe_live(symbols.clone(), schema.clone())
+        .count_records(&req)
         .await
-    {
-        Ok(stream) => stream,
-        Err(e) => {
-            error!("Failed to subscribe: {}", e);
-            let error_msg = serde_json::to_string(&shared::LiveMessage::Error {
-                message: e.to_string(),
-            })
-            .unwrap_or_else(|_| r#"{"type":"error","message":"Unknown error"}"#.to_string());
-            let _ = sender.send(Message::Text(error_msg)).await;
-            return;
+        .map_err(IntoResponse::into_response)?;
+    Ok(RecordCount {
+        schema: req.schema,
+        count,
+    })
+}
+
+/// A served historical request, in any of its shapes.
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+enum Served {
+    Count(RecordCount),
+    Records(NormalizedResponse),
+    Projected(ProjectedResponse),
+}
+
+/// Serve `req`: its record count when `count_only` is set, otherwise the
+/// sorted and deduplicated records, transformed if asked and cut down to
+/// `fields` if given, along with what API v2 reports about them under
+/// `meta`. With `partial`, symbols that fail are reported there instead of
+/// failing the request.
+async fn serve_historical(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+    partial: bool,
+) -> Result<(Served, Option<ResponseMeta>), Response> {
+    if req.count_only {
+        let count = counted_historical(state, key_id, req).await?;
+        return Ok((Served::Count(count), None));
+    }
+    let started = Instant::now();
+    let (limit, auto) = (req.limit, req.auto_resolution);
+    let (req, resolution, limited) = match req.auto_resolution {
+        true => {
+            let (req, resolution, limited) = auto_resolution(state, key_id, req).await?;
+            (req, Some(resolution), limited)
         }
+        false => (req.clone(), None, false),
     };
 
-    info!(symbols = ?symbols, schema = %schema, "WebSocket connected");
+    // Resolve fields and the transform up front so a typo fails before
+    // anything is fetched or billed; the fetch itself always asks for whole
+    // records
+    let schema: Schema = req
+        .schema
+        .parse()
+        .map_err(|e| ServiceError::InvalidSchema(e).into_response())?;
+    let projection = Projection::new(&schema, &req.fields).map_err(bad_request)?;
+    let transform = req.bar_transform().map_err(bad_request)?;
+    if transform.is_some() && schema == Schema::Trades {
+        return Err(bad_request(
+            "transform applies to bar schemas, not trades".to_string(),
+        ));
+    }
+    // Ordering is applied to the served records; the fetch is always
+    // time-ordered, which keeps it shareable in the cache
+    let (order, fields) = (req.order, req.fields.clone());
+    let (compact, prices_as) = (req.compact, req.prices_as);
+    let req = HistoricalRequest {
+        fields: Vec::new(),
+        order: RecordOrder::Time,
+        ..req.clone()
+    };
 
-    // Spawn a task to forward messages from the stream to the WebSocket
-    let mut stream = stream;
-    let send_task = tokio::spawn(async move {
-        while let Some(msg) = stream.next().await {
-            match serde_json::to_string(&msg) {
-                Ok(json) => {
-                    if sender.send(Message::Text(json)).await.is_err() {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to serialize message: {}", e);
-                }
-            }
+    let fetched = metered_fetch(state, key_id, &req, partial).await?;
+    let mut truncated = fetched
+        .response
+        .reached_limits(req.limit, req.limit_per_symbol);
+    let mut served = normalized(fetched.response);
+    if resolution.as_deref() == Some(HOURLY_RESOLUTION) {
+        if let Some(bars) = served.response.bars_mut() {
+            *bars = resample_bars(bars, 3_600 * NANOS_PER_SECOND);
+            // The minute bars fetched are all the range holds unless limited
+            truncated = limited || bars.len() > limit as usize;
+            bars.truncate(limit as usize);
         }
-    });
+    }
+    served.resolution = resolution;
+    served.limited = limited;
+    if let Some(transform) = transform {
+        if let Some(bars) = served.response.bars_mut() {
+            *bars = transform.apply(bars, &state.ticks).map_err(bad_request)?;
+            truncated |= bars.len() > limit as usize;
+            bars.truncate(limit as usize);
+        }
+        served.transform = Some(transform.to_string());
+    }
+    served.response.order_by(order);
 
-    // Handle incoming messages (for future use, e.g., ping/pong or resubscription)
-    let recv_task = tokio::spawn(async move {
-        while let Some(Ok(msg)) = receiver.next().await {
-            match msg {
-                Message::Close(_) => break,
-                Message::Ping(data) => {
-                    // Ping is handled automatically by axum
-                    tracing::trace!("Received ping: {:?}", data);
-                }
-                Message::Text(text) => {
-                    // Could handle subscription changes here in the future
-                    tracing::debug!("Received text: {}", text);
-                }
-                _ => {}
-            }
+    let revision = fetched.request.revision.clone();
+    let meta = ResponseMeta {
+        truncated,
+        symbol_errors: fetched.symbol_errors,
+        query: Some(HistoricalRequest {
+            limit,
+            auto_resolution: auto,
+            fields,
+            order,
+            compact,
+            prices_as,
+            ..fetched.request
+        }),
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        source: Some(fetched.source),
+        data_versions: fetched.data_versions,
+        revision,
+        ..ResponseMeta::from(&served)
+    };
+    let served = match projection {
+        Some(projection) => Served::Projected(ProjectedResponse {
+            response: served,
+            projection,
+        }),
+        None => Served::Records(served),
+    };
+    Ok((served, Some(meta)))
+}
+
+/// Schemas `auto_resolution` chooses between, finest first.
+const AUTO_SCHEMAS: [Schema; 3] = [Schema::Trades, Schema::Ohlcv1S, Schema::Ohlcv1M];
+
+/// Resolution reported when minute bars are merged into hourly ones.
+const HOURLY_RESOLUTION: &str = "ohlcv-1h";
+
+/// Most minute bars fetched to merge into hourly ones.
+const MAX_RESAMPLED_BARS: u64 = 1_000_000;
+
+/// The request `auto_resolution` serves for `req` and the resolution it
+/// picked (see [`pick_resolution`]). Counts aren't billed like fetches.
+async fn auto_resolution(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+) -> Result<(HistoricalRequest, String, bool), Response> {
+    let schema: Schema = req
+        .schema
+        .parse()
+        .map_err(|e| ServiceError::InvalidSchema(e).into_response())?;
+    let Some(finest) = AUTO_SCHEMAS.iter().position(|s| *s == schema) else {
+        return Err(bad_request(format!(
+            "auto_resolution starts from trades, ohlcv-1s or ohlcv-1m, not {}",
+            schema
+        )));
+    };
+    pick_resolution(req, &AUTO_SCHEMAS[finest..], |candidate| async move {
+        let allowed = checked_range(state, &candidate)
+            .is_ok_and(|resolved| state.ranges.check(&resolved).is_ok());
+        if !allowed {
+            return Ok(None);
         }
-    });
+        Ok(Some(
+            counted_historical(state, key_id, &candidate).await?.count,
+        ))
+    })
+    .await
+}
 
-    // Wait for either task to complete
-    tokio::select! {
-        _ = send_task => {
-            info!("Send task completed");
+/// The finest of `schemas` whose record count fits in `req.limit`, as
+/// `count` gives it (`None` when the schema's range isn't allowed), else
+/// minute bars to be merged into hourly ones. Then the resolution picked,
+/// and whether there were more minute bars than [`MAX_RESAMPLED_BARS`].
+async fn pick_resolution<E, F, Fut>(
+    req: &HistoricalRequest,
+    schemas: &[Schema],
+    mut count: F,
+) -> Result<(HistoricalRequest, String, bool), E>
+where
+    F: FnMut(HistoricalRequest) -> Fut,
+    Fut: Future<Output = Result<Option<u64>, E>>,
+{
+    // Unknown when minute bars' range isn't allowed, which fetching them
+    // then reports
+    let mut minute_bars = None;
+    for schema in schemas {
+        let candidate = HistoricalRequest {
+            schema: schema.as_str().into_owned(),
+            auto_resolution: false,
+            ..req.clone()
+        };
+        let Some(records) = count(candidate.clone()).await? else {
+            continue;
+        };
+        if records <= u64::from(req.limit) {
+            return Ok((candidate, schema.as_str().into_owned(), false));
         }
-        _ = recv_task => {
-            info!("Receive task completed (client disconnected)");
+        if *schema == Schema::Ohlcv1M {
+            minute_bars = Some(records);
         }
     }
 
-    info!(symbols = ?symbols, "WebSocket disconnected");
+    let minute_bars = minute_bars.unwrap_or(MAX_RESAMPLED_BARS);
+    let minutes = HistoricalRequest {
+        schema: Schema::Ohlcv1M.as_str().into_owned(),
+        limit: minute_bars.min(MAX_RESAMPLED_BARS) as u32,
+        auto_resolution: false,
+        ..req.clone()
+    };
+    let limited = minute_bars > MAX_RESAMPLED_BARS;
+    Ok((minutes, HOURLY_RESOLUTION.to_string(), limited))
+}
+
+/// A 400 response carrying `error`.
+fn bad_request(error: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error,
+            code: StatusCode::BAD_REQUEST.as_u16(),
+        }),
+    )
+        .into_response()
+}
+
+/// Sort and deduplicate a fetched response before serving it.
+fn normalized(response: HistoricalResponse) -> NormalizedResponse {
+    let served = NormalizedResponse::from(response);
+    if served.duplicates_dropped > 0 {
+        info!(
+            dropped = served.duplicates_dropped,
+            "Dropped duplicate records"
+        );
+    }
+    served
+}
+
+/// POST /api/historical - Fetch historical market data.
+pub async fn historical(
+    State(state): State<Arc<AppState>>,
+    Extension(version): Extension<ApiVersion>,
+    ApiKey(key_id): ApiKey,
+    Json(req): Json<HistoricalRequest>,
+) -> Result<Response, Response> {
+    info!(
+        symbols = ?req.symbols,
+        schema = %req.schema,
+        start = %req.start_rfc3339,
+        end = %req.end_rfc3339,
+        key_id = %key_id,
+        "Fetching historical data"
+    );
+
+    let partial = version.serves_partial();
+    let (served, meta) = serve_historical(&state, &key_id, &req, partial).await?;
+    let body = shaped(version.historical(&served, meta), &req);
+    Ok(Json(body).into_response())
+}
+
+/// `body` with its records written as `req` asks: prices and volumes as
+/// `prices_as`, then fields renamed if `compact` is set.
+fn shaped(mut body: serde_json::Value, req: &HistoricalRequest) -> serde_json::Value {
+    req.prices_as.apply_response(&mut body);
+    if req.compact {
+        compact::compact_response(&mut body);
+    }
+    body
+}
+
+/// A live message's JSON with its record, if it is one, written as the
+/// subscription asks (see [`shaped`]).
+fn live_json(
+    msg: &LiveMessage,
+    fields: Option<&LiveProjection>,
+    compact: bool,
+    prices_as: PricesAs,
+) -> serde_json::Result<String> {
+    let records = matches!(
+        msg,
+        LiveMessage::Trade { .. } | LiveMessage::TradeBatch { .. } | LiveMessage::Ohlcv { .. }
+    );
+    if !records || fields.is_none() && !compact && prices_as.is_number() {
+        return serde_json::to_string(msg);
+    }
+    let mut json = serde_json::to_value(msg)?;
+    compact::each_live_record(&mut json, |record| {
+        if let Some(fields) = fields {
+            fields.apply(record);
+        }
+        prices_as.apply_record(record);
+        if compact {
+            compact::compact_record(record);
+        }
+    });
+    serde_json::to_string(&json)
+}
+
+/// Most requests in one batch.
+const MAX_BATCH_REQUESTS: usize = 20;
+
+/// Requests of a batch served at a time.
+const BATCH_CONCURRENCY: usize = 4;
+
+/// POST /api/historical/batch - Serve several historical requests in one
+/// round trip, a few at a time. Each is metered on its own and its result
+/// or error is returned in request order.
+pub async fn historical_batch(
+    State(state): State<Arc<AppState>>,
+    Extension(version): Extension<ApiVersion>,
+    ApiKey(key_id): ApiKey,
+    Json(reqs): Json<Vec<HistoricalRequest>>,
+) -> Result<Response, Response> {
+    info!(requests = reqs.len(), key_id = %key_id, "Fetching historical batch");
+    let (state, key_id) = (&state, &key_id);
+    let results = serve_batch(reqs, |req| async move {
+        let (served, meta) =
+            serve_historical(state, key_id, &req, version.serves_partial()).await?;
+        Ok(shaped(version.historical(&served, meta), &req))
+    })
+    .await?;
+    Ok(Json(results).into_response())
+}
+
+/// The results of `reqs`, each served by `serve` with a few at a time, in
+/// request order; one failing leaves the others be.
+async fn serve_batch<F, Fut>(
+    reqs: Vec<HistoricalRequest>,
+    serve: F,
+) -> Result<Vec<BatchResult<serde_json::Value>>, Response>
+where
+    F: Fn(HistoricalRequest) -> Fut,
+    Fut: Future<Output = Result<serde_json::Value, Response>>,
+{
+    if reqs.len() > MAX_BATCH_REQUESTS {
+        return Err(bad_request(format!(
+            "Batch of {} requests exceeds the limit of {}",
+            reqs.len(),
+            MAX_BATCH_REQUESTS
+        )));
+    }
+    Ok(futures::stream::iter(reqs)
+        .map(|req| {
+            let served = serve(req);
+            async move {
+                match served.await {
+                    Ok(body) => BatchResult::Ok(body),
+                    Err(response) => BatchResult::Error(error_body(response).await),
+                }
+            }
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .await)
+}
+
+/// Most schemas in one combined request.
+const MAX_COMBINED_SCHEMAS: usize = 6;
+
+/// POST /api/historical/combined - Serve several schemas of the same
+/// symbols and range at once, keyed by schema. Each schema is fetched and
+/// metered on its own, so one failing doesn't fail the others.
+pub async fn historical_combined(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(combined): Json<CombinedRequest>,
+) -> Result<Response, Response> {
+    let reqs = combined.requests();
+    if reqs.is_empty() || reqs.len() > MAX_COMBINED_SCHEMAS {
+        return Err(bad_request(format!(
+            "Expected 1 to {} schemas, got {}",
+            MAX_COMBINED_SCHEMAS,
+            reqs.len()
+        )));
+    }
+    info!(
+        symbols = ?combined.request.symbols,
+        schemas = ?combined.schemas,
+        start = %combined.request.start_rfc3339,
+        end = %combined.request.end_rfc3339,
+        key_id = %key_id,
+        "Fetching combined historical data"
+    );
+
+    let (state, key_id) = (&state, &key_id);
+    let results = futures::future::join_all(reqs.into_iter().map(|req| async move {
+        let result = match serve_historical(state, key_id, &req, false).await {
+            Ok((served, _)) => {
+                let body = serde_json::to_value(&served).unwrap_or_default();
+                BatchResult::Ok(shaped(body, &req))
+            }
+            Err(response) => BatchResult::Error(error_body(response).await),
+        };
+        (req.schema, result)
+    }))
+    .await;
+    let response: CombinedResponse<serde_json::Value> = results.into_iter().collect();
+    Ok(Json(response).into_response())
+}
+
+/// The error carried by an error response.
+async fn error_body(response: Response) -> ErrorResponse {
+    let code = response.status().as_u16();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    serde_json::from_slice(&body).unwrap_or_else(|_| ErrorResponse {
+        error: String::from_utf8_lossy(&body).into_owned(),
+        code,
+    })
+}
+
+/// POST /api/validate - Fetch historical market data and report integrity
+/// problems in it instead of returning the records.
+pub async fn validate(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(req): Json<HistoricalRequest>,
+) -> Result<Json<IntegrityReport>, Response> {
+    info!(
+        symbols = ?req.symbols,
+        schema = %req.schema,
+        start = %req.start_rfc3339,
+        end = %req.end_rfc3339,
+        key_id = %key_id,
+        "Validating historical data"
+    );
+
+    let response = metered_historical(&state, &key_id, &req).await?;
+    Ok(Json(integrity::check(&response)))
+}
+
+/// POST /api/diff - Fetch one historical range from two sources and report
+/// how their records differ: those missing from either side and those
+/// whose prices or sizes don't match.
+pub async fn diff(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(mut req): Json<DiffRequest>,
+) -> Result<Json<DiffReport>, Response> {
+    info!(
+        symbols = ?req.request.symbols,
+        schema = %req.request.schema,
+        left = ?req.left,
+        right = ?req.right,
+        key_id = %key_id,
+        "Diffing historical data"
+    );
+
+    // Once, so relative times mean the same range on both sides
+    timeexpr::resolve_request(&mut req.request, Utc::now()).map_err(bad_request)?;
+    let (left, right) = tokio::try_join!(
+        diff_side(&state, &key_id, &req.request, &req.left),
+        diff_side(&state, &key_id, &req.request, &req.right),
+    )?;
+    let (limit, per_symbol) = (req.request.limit, req.request.limit_per_symbol);
+    let limited = left.reached_limits(limit, per_symbol) || right.reached_limits(limit, per_symbol);
+    let report = compute(move || diff::diff(&req, left, right, limited))
+        .await?
+        .map_err(bad_request)?;
+    Ok(Json(report))
+}
+
+/// `req` as served by `source`, for a diff.
+async fn diff_side(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+    source: &DiffSource,
+) -> Result<HistoricalResponse, Response> {
+    match source {
+        DiffSource::Provider { dataset } => {
+            let req = HistoricalRequest {
+                dataset: dataset.clone().or_else(|| req.dataset.clone()),
+                ..req.clone()
+            };
+            cache::with_mode(CacheMode::Skip, metered_historical(state, key_id, &req)).await
+        }
+        DiffSource::Cache if !state.cached => Err(status_error((
+            StatusCode::BAD_REQUEST,
+            "No historical cache is configured".to_string(),
+        ))),
+        DiffSource::Cache => {
+            cache::with_mode(CacheMode::Only, metered_historical(state, key_id, req)).await
+        }
+        DiffSource::Recording => {
+            let recordings = recordings(state).map_err(IntoResponse::into_response)?;
+            let req = req.clone();
+            compute(move || recordings.query(&req))
+                .await?
+                .map_err(IntoResponse::into_response)
+        }
+    }
+}
+
+/// POST /api/historical/validate - Check a historical request without
+/// fetching anything: every problem with it, or the request as it would be
+/// served and the datasets it would be fetched from.
+pub async fn validate_request(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(req): Json<HistoricalRequest>,
+) -> Json<RequestValidation> {
+    let now = Utc::now();
+    let (mut req, mut errors) = validation::check(&req, now);
+    if let Err(e) = state.clock.preview_request(&mut req, now) {
+        errors.push(ValidationIssue::new("end_rfc3339", e));
+    }
+    // auto_resolution falls back to a schema whose range is allowed
+    if !req.count_only && !req.auto_resolution {
+        if let Err(e) = state.ranges.check(&req) {
+            errors.push(ValidationIssue::new("end_rfc3339", e.to_string()));
+        }
+    }
+    if errors.is_empty() {
+        if let Err(e) = checked_symbols(&state, &mut req, false).await {
+            errors.push(ValidationIssue::new("symbols", e.to_string()));
+        }
+    }
+    if errors.is_empty() {
+        let authorizer = state.authorizer.as_ref();
+        let kind = AccessKind::Historical;
+        if let Err(e) =
+            entitlements::check(authorizer, state.service.as_ref(), &key_id, kind, &req).await
+        {
+            errors.push(ValidationIssue::new("symbols", e.to_string()));
+        }
+    }
+    let datasets = match state.service.datasets(&req) {
+        Ok(datasets) => datasets,
+        Err(e) => {
+            let field = if req.dataset.is_some() {
+                "dataset"
+            } else {
+                "symbols"
+            };
+            errors.push(ValidationIssue::new(field, e.to_string()));
+            Vec::new()
+        }
+    };
+    Json(RequestValidation::new(req, datasets, errors))
+}
+
+/// GET /api/usage - The caller's usage this month and the quotas it counts
+/// against.
+pub async fn get_usage(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+) -> Json<UsageReport> {
+    Json(state.usage.report(&key_id))
+}
+
+/// GET /api/requests - The caller's requests in flight, oldest first.
+pub async fn list_requests(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+) -> Json<Vec<InFlightRequest>> {
+    Json(state.in_flight.list(&key_id))
+}
+
+/// DELETE /api/requests/:request_id - Cancel one of the caller's requests
+/// in flight. The cancelled request fails with status 499 and isn't
+/// charged to the caller's quotas.
+pub async fn cancel_request(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Path(request_id): Path<String>,
+) -> Result<StatusCode, CancelError> {
+    state.in_flight.cancel(&request_id, &key_id)?;
+    info!(request_id = %request_id, "Cancelled request");
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// GET /api/ticks - Configured tick sizes, ordered by symbol root.
+pub async fn list_ticks(State(state): State<Arc<AppState>>) -> Json<Vec<TickSize>> {
+    Json(state.ticks.entries())
+}
+
+/// GET /api/ticks/:symbol - Tick size that applies to a symbol.
+pub async fn get_tick(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<Json<TickSize>, (StatusCode, Json<ErrorResponse>)> {
+    state.ticks.entry(&symbol).map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: format!("No tick size known for {}", symbol),
+                code: StatusCode::NOT_FOUND.as_u16(),
+            }),
+        )
+    })
+}
+
+/// Most minute bars fetched when ranking contract months (a day is 1,440
+/// bars per listed contract).
+const FRONT_MONTH_BAR_LIMIT: u32 = 200_000;
+
+/// Query parameters for `GET /api/front-month/{symbol}`.
+#[derive(Debug, Deserialize)]
+pub struct FrontMonthParams {
+    /// Lookback the volumes are summed over (default: "1d")
+    #[serde(default = "default_front_month_last")]
+    pub last: String,
+}
+
+fn default_front_month_last() -> String {
+    "1d".to_string()
+}
+
+/// GET /api/front-month/{symbol} - The contract month of a parent symbol
+/// (e.g. ES.FUT) with the most volume over a lookback.
+pub async fn front_month(
+    ApiKey(key_id): ApiKey,
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<FrontMonthParams>,
+) -> Result<Json<FrontMonth>, Response> {
+    let error = |status: StatusCode, error: String| {
+        (
+            status,
+            Json(ErrorResponse {
+                error,
+                code: status.as_u16(),
+            }),
+        )
+            .into_response()
+    };
+
+    let lookback = parse_lookback(&params.last).map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+    let end = Utc::now();
+    let start = end.checked_sub_signed(lookback).ok_or_else(|| {
+        error(
+            StatusCode::BAD_REQUEST,
+            format!("last '{}' out of range", params.last),
+        )
+    })?;
+    let req = HistoricalRequest {
+        symbols: vec![symbol.clone()],
+        schema: Schema::Ohlcv1M.as_str().to_string(),
+        start_rfc3339: start.to_rfc3339_opts(SecondsFormat::Secs, true),
+        end_rfc3339: end.to_rfc3339_opts(SecondsFormat::Secs, true),
+        limit: FRONT_MONTH_BAR_LIMIT,
+        ..Default::default()
+    };
+    info!(symbol = %symbol, last = %params.last, key_id = %key_id, "Ranking contract months");
+
+    let contracts = match metered_historical(&state, &key_id, &req).await? {
+        HistoricalResponse::Ohlcv1M { data } => {
+            rank_contracts(data.iter().map(|bar| (bar.symbol.as_str(), bar.volume_u64)))
+        }
+        _ => Vec::new(),
+    };
+    let front = contracts.first().map(|c| c.symbol.clone()).ok_or_else(|| {
+        error(
+            StatusCode::NOT_FOUND,
+            format!(
+                "No contract volume for {} in the last {}",
+                symbol, params.last
+            ),
+        )
+    })?;
+
+    Ok(Json(FrontMonth {
+        parent: symbol,
+        front,
+        window: params.last,
+        contracts,
+    }))
+}
+
+/// Most minute bars fetched for a statistics request (about two years of
+/// a contract that trades around the clock).
+const STATS_BAR_LIMIT: u32 = 1_000_000;
+
+/// Fetch the range's minute bars for a statistics request, sorted and
+/// deduplicated.
+async fn stats_bars(
+    state: &AppState,
+    key_id: &str,
+    range: &StatsRange,
+) -> Result<Vec<OhlcvRecord>, Response> {
+    let req = range.bars_request(Schema::Ohlcv1M, STATS_BAR_LIMIT);
+    let mut response = metered_historical(state, key_id, &req).await?;
+    response.normalize();
+    Ok(match response {
+        HistoricalResponse::Ohlcv1M { data } => data,
+        _ => Vec::new(),
+    })
+}
+
+/// Run a statistics computation off the async runtime.
+async fn compute<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, Response> {
+    tokio::task::spawn_blocking(f).await.map_err(|e| {
+        error!("Statistics computation failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })
+}
+
+/// POST /api/stats/intraday-profile - Average volume and volatility by
+/// time of day across the sessions in a range.
+pub async fn intraday_profile(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(req): Json<IntradayProfileRequest>,
+) -> Result<Json<Vec<IntradayProfile>>, Response> {
+    let (bucket_secs, tz) = req.buckets().map_err(bad_request)?;
+    info!(
+        symbols = ?req.range.symbols,
+        bucket = %req.bucket,
+        tz = %tz,
+        key_id = %key_id,
+        "Computing intraday profile"
+    );
+
+    let bars = stats_bars(&state, &key_id, &req.range).await?;
+    let profiles = compute(move || intraday_profiles(&bars, bucket_secs, tz)).await?;
+    Ok(Json(profiles))
+}
+
+/// POST /api/stats/returns - Log returns, realized volatility and maximum
+/// drawdown per symbol over a range.
+pub async fn returns(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(req): Json<ReturnsRequest>,
+) -> Result<Json<ReturnsReport>, Response> {
+    let interval_ns = parse_bar_interval(&req.interval).map_err(bad_request)?;
+    info!(
+        symbols = ?req.range.symbols,
+        interval = %req.interval,
+        estimator = ?req.estimator,
+        key_id = %key_id,
+        "Computing returns"
+    );
+
+    let bars = stats_bars(&state, &key_id, &req.range).await?;
+    let estimator = req.estimator;
+    let symbols = compute(move || {
+        let bars = match interval_ns {
+            NANOS_PER_MINUTE => bars,
+            _ => resample_bars(&bars, interval_ns),
+        };
+        stats::returns(&bars, estimator)
+    })
+    .await?;
+    Ok(Json(ReturnsReport {
+        interval_secs: interval_ns / NANOS_PER_SECOND,
+        estimator,
+        symbols,
+    }))
+}
+
+/// POST /api/stats/correlation - Pairwise correlation of bar returns
+/// between symbols over a range.
+pub async fn correlation_matrix(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(req): Json<CorrelationRequest>,
+) -> Result<Json<CorrelationMatrix>, Response> {
+    let interval_ns = parse_bar_interval(&req.interval).map_err(bad_request)?;
+    info!(
+        symbols = ?req.range.symbols,
+        interval = %req.interval,
+        missing = ?req.missing,
+        key_id = %key_id,
+        "Computing correlation matrix"
+    );
+
+    let bars = stats_bars(&state, &key_id, &req.range).await?;
+    let missing = req.missing;
+    let matrix = compute(move || correlation(&bars, interval_ns, missing)).await?;
+    Ok(Json(matrix))
+}
+
+/// POST /api/tca - Benchmark fills against arrival price, interval VWAP
+/// and the day's close, with slippage per fill and in aggregate.
+pub async fn tca(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(req): Json<TcaRequest>,
+) -> Result<Json<TcaReport>, Response> {
+    let plan = req.plan().map_err(bad_request)?;
+    info!(
+        fills = plan.len(),
+        symbols = ?plan.range.symbols,
+        key_id = %key_id,
+        "Computing TCA report"
+    );
+
+    let bars = stats_bars(&state, &key_id, &plan.range).await?;
+    let report = compute(move || plan.analyze(&bars)).await?;
+    Ok(Json(report))
+}
+
+/// HTTP status for a store failure.
+fn store_status(e: &StoreError) -> StatusCode {
+    match e {
+        StoreError::InvalidName(_) => StatusCode::BAD_REQUEST,
+        StoreError::NotFound(_) => StatusCode::NOT_FOUND,
+        StoreError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Convert QueryError to HTTP response.
+impl IntoResponse for QueryError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            QueryError::Service(e) => return e.into_response(),
+            QueryError::Store(ref e) => store_status(e),
+            QueryError::InvalidOverride(_) => StatusCode::BAD_REQUEST,
+        };
+
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// POST /api/queries - Save a historical request template under a name.
+pub async fn save_query(
+    State(state): State<Arc<AppState>>,
+    Json(query): Json<SavedQuery>,
+) -> Result<impl IntoResponse, QueryError> {
+    query
+        .request
+        .schema
+        .parse::<Schema>()
+        .map_err(ServiceError::InvalidSchema)?;
+    state.queries.insert(&query.name, query.request.clone())?;
+    info!(name = %query.name, "Saved query");
+
+    Ok((StatusCode::CREATED, Json(query)))
+}
+
+/// GET /api/queries - List saved queries.
+pub async fn list_queries(State(state): State<Arc<AppState>>) -> Json<Vec<SavedQuery>> {
+    let queries = state
+        .queries
+        .list()
+        .into_iter()
+        .map(|(name, request)| SavedQuery { name, request })
+        .collect();
+    Json(queries)
+}
+
+/// GET /api/queries/:name/run - Execute a saved query, with optional
+/// overrides given as query parameters.
+pub async fn run_query(
+    State(state): State<Arc<AppState>>,
+    Extension(version): Extension<ApiVersion>,
+    ApiKey(key_id): ApiKey,
+    Path(name): Path<String>,
+    Query(overrides): Query<QueryOverrides>,
+) -> Result<Response, Response> {
+    let req = state
+        .queries
+        .get(&name)
+        .map_err(QueryError::from)
+        .and_then(|template| overrides.apply(&template))
+        .map_err(IntoResponse::into_response)?;
+    info!(
+        name = %name,
+        symbols = ?req.symbols,
+        schema = %req.schema,
+        start = %req.start_rfc3339,
+        end = %req.end_rfc3339,
+        key_id = %key_id,
+        "Running saved query"
+    );
+
+    let partial = version.serves_partial();
+    let (served, meta) = serve_historical(&state, &key_id, &req, partial).await?;
+    let body = shaped(version.historical(&served, meta), &req);
+    Ok(Json(body).into_response())
+}
+
+/// Convert JobError to HTTP response.
+impl IntoResponse for JobError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            JobError::Service(e) => return e.into_response(),
+            JobError::Store(ref e) => store_status(e),
+            JobError::InvalidSpec(_) => StatusCode::BAD_REQUEST,
+            JobError::Export(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            JobError::NoManifest(_) => StatusCode::NOT_FOUND,
+            JobError::NotRunning(_) => StatusCode::CONFLICT,
+        };
+
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// POST /api/jobs - Register a recurring job, replacing any with the same name.
+pub async fn add_job(
+    State(state): State<Arc<AppState>>,
+    Json(spec): Json<JobSpec>,
+) -> Result<impl IntoResponse, JobError> {
+    state.scheduler.add(spec.clone())?;
+    info!(name = %spec.name, cron = %spec.cron, timezone = %spec.timezone, "Scheduled job");
+
+    Ok((StatusCode::CREATED, Json(spec)))
+}
+
+/// GET /api/jobs - List registered jobs.
+pub async fn list_jobs(State(state): State<Arc<AppState>>) -> Json<Vec<JobSpec>> {
+    Json(state.scheduler.list())
+}
+
+/// DELETE /api/jobs/:name - Unregister a job, cancelling any run in
+/// progress.
+pub async fn delete_job(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, JobError> {
+    state.scheduler.remove(&name)?;
+    info!(name = %name, "Removed job");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/jobs/:name/run - Cancel a job's run in progress, leaving
+/// the job scheduled. The run is recorded as cancelled.
+pub async fn cancel_job_run(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, JobError> {
+    state.scheduler.cancel(&name)?;
+    info!(name = %name, "Cancelled job run");
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// POST /api/jobs/:name/run - Run a job now. A failed run is still
+/// returned with status 200; its `status` and `error` describe the failure.
+pub async fn run_job(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<JobRun>, JobError> {
+    Ok(Json(state.scheduler.run_now(&name).await?))
+}
+
+/// GET /api/jobs/:name/runs - Recent runs of a job, newest first.
+pub async fn job_runs(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<JobRun>>, JobError> {
+    Ok(Json(state.scheduler.history(&name)?))
+}
+
+/// GET /api/jobs/:name/manifest - Manifest of a job's latest output: the
+/// request as run, provider and versions, and the file's checksum.
+pub async fn job_manifest(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<JobManifest>, JobError> {
+    Ok(Json(state.scheduler.manifest(&name)?))
+}
+
+/// Convert WebhookError to HTTP response.
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            WebhookError::Store(e) => store_status(e),
+            WebhookError::InvalidSpec(_) => StatusCode::BAD_REQUEST,
+            WebhookError::Delivery(_) => StatusCode::BAD_GATEWAY,
+            WebhookError::Crypto(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// Hide a webhook's secret before returning it to clients.
+fn redacted(hook: Webhook) -> Webhook {
+    Webhook {
+        secret: "********".to_string(),
+        ..hook
+    }
+}
+
+/// POST /api/webhooks - Register a webhook, replacing any with the same name.
+pub async fn add_webhook(
+    State(state): State<Arc<AppState>>,
+    Json(hook): Json<Webhook>,
+) -> Result<impl IntoResponse, WebhookError> {
+    state.notifier.add(hook.clone())?;
+    info!(name = %hook.name, events = ?hook.events, "Registered webhook");
+
+    Ok((StatusCode::CREATED, Json(redacted(hook))))
+}
+
+/// GET /api/webhooks - List webhooks, with secrets redacted.
+pub async fn list_webhooks(State(state): State<Arc<AppState>>) -> Json<Vec<Webhook>> {
+    Json(state.notifier.list().into_iter().map(redacted).collect())
+}
+
+/// DELETE /api/webhooks/:name - Remove a webhook.
+pub async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, WebhookError> {
+    state.notifier.remove(&name)?;
+    info!(name = %name, "Removed webhook");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/webhooks/:name/test - Send a test alert to a webhook and
+/// report whether it was accepted.
+pub async fn test_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, WebhookError> {
+    let event = WebhookEvent::Alert {
+        name: "test".to_string(),
+        message: "Test notification from the market data server".to_string(),
+    };
+    state.notifier.send_to(&name, &event).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Convert PaperError to HTTP response.
+impl IntoResponse for PaperError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            PaperError::InvalidOrder(_) => StatusCode::BAD_REQUEST,
+            PaperError::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// POST /api/paper/orders - Submit a paper order for the caller's API key.
+/// It fills against the trades of that key's live subscriptions.
+pub async fn submit_paper_order(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(order): Json<NewPaperOrder>,
+) -> Result<impl IntoResponse, PaperError> {
+    let opened = state.paper.submit(&key_id, order)?;
+    info!(key_id = %key_id, order_id = opened.order_id, "Submitted paper order");
+
+    Ok((StatusCode::CREATED, Json(opened)))
+}
+
+/// GET /api/paper/orders - The caller's paper orders, oldest first.
+pub async fn list_paper_orders(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+) -> Json<Vec<PaperOrder>> {
+    Json(state.paper.orders(&key_id))
+}
+
+/// DELETE /api/paper/orders/:id - Cancel an open paper order.
+pub async fn cancel_paper_order(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Path(order_id): Path<u64>,
+) -> Result<Json<PaperOrder>, PaperError> {
+    Ok(Json(state.paper.cancel(&key_id, order_id)?))
+}
+
+/// GET /api/paper/positions - The caller's paper positions and PnL.
+pub async fn paper_positions(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+) -> Json<Vec<PaperPosition>> {
+    Json(state.paper.positions(&key_id))
+}
+
+/// Convert PositionError to HTTP response.
+impl IntoResponse for PositionError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            PositionError::Invalid(_) => StatusCode::BAD_REQUEST,
+        };
+
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// POST /api/positions - Register positions to mark to market, replacing
+/// any in the same symbols. Returns every position the caller holds.
+pub async fn register_positions(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Json(positions): Json<Vec<Position>>,
+) -> Result<Json<Vec<Position>>, PositionError> {
+    let held = state.positions.register(&key_id, positions)?;
+    info!(key_id = %key_id, positions = held.len(), "Registered positions");
+
+    Ok(Json(held))
+}
+
+/// GET /api/positions - The caller's registered positions.
+pub async fn list_positions(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+) -> Json<Vec<Position>> {
+    Json(state.positions.list(&key_id))
+}
+
+/// DELETE /api/positions - Remove all of the caller's positions.
+pub async fn clear_positions(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+) -> StatusCode {
+    state.positions.clear(&key_id);
+    StatusCode::NO_CONTENT
+}
+
+/// Convert UserError to HTTP response.
+impl IntoResponse for UserError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            UserError::Store(e) => store_status(e),
+            UserError::Exists(_) => StatusCode::CONFLICT,
+            UserError::Unauthorized => StatusCode::UNAUTHORIZED,
+            UserError::AdminDisabled => StatusCode::FORBIDDEN,
+            UserError::InvalidKey(_) => StatusCode::BAD_REQUEST,
+            UserError::Crypto(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// The bearer token of an admin request, checked against `ADMIN_TOKEN`.
+pub struct AdminToken(Option<String>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminToken {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::to_string);
+        Ok(AdminToken(token))
+    }
+}
+
+/// POST /api/users - Create a user (admin). The response holds the user's
+/// API token, which is not shown again.
+pub async fn create_user(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+    Json(user): Json<NewUser>,
+) -> Result<impl IntoResponse, UserError> {
+    state.users.check_admin(token.as_deref())?;
+    let created = state.users.create(&user.name)?;
+    info!(user = %user.name, "Created user");
+
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// GET /api/users - List users (admin).
+pub async fn list_users(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+) -> Result<Json<Vec<UserInfo>>, UserError> {
+    state.users.check_admin(token.as_deref())?;
+    Ok(Json(state.users.list()))
+}
+
+/// DELETE /api/users/:name - Remove a user and their DataBento key (admin).
+pub async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+    Path(name): Path<String>,
+) -> Result<StatusCode, UserError> {
+    state.users.check_admin(token.as_deref())?;
+    state.users.remove(&name)?;
+    info!(user = %name, "Removed user");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Convert SecretsError to HTTP response.
+impl IntoResponse for SecretsError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            SecretsError::InvalidValue(_) => StatusCode::BAD_REQUEST,
+            SecretsError::Backend(_) | SecretsError::Crypto(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = Json(ErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+        });
+
+        (status, body).into_response()
+    }
+}
+
+/// GET /api/admin/queries - Logged historical queries (admin), newest,
+/// slowest or costliest first, optionally for one usage account.
+pub async fn admin_queries(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+    Query(filter): Query<QueryLogFilter>,
+) -> Result<Json<Vec<QueryRecord>>, UserError> {
+    state.users.check_admin(token.as_deref())?;
+    Ok(Json(state.query_log.select(&filter)))
+}
+
+/// GET /api/admin/storage - Disk used by each subsystem, its retention
+/// policy and what it has evicted (admin).
+pub async fn storage_report(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+) -> Result<Json<StorageReport>, Response> {
+    state
+        .users
+        .check_admin(token.as_deref())
+        .map_err(IntoResponse::into_response)?;
+    let retention = state.retention.clone();
+    Ok(Json(compute(move || retention.report()).await?))
+}
+
+/// PUT /api/admin/databento-key - Rotate the server's shared DataBento key
+/// (admin). New requests use it immediately; open live streams keep the
+/// key they connected with. A server in mock mode stores the key but only
+/// uses it once restarted, and says so with a 409.
+pub async fn rotate_databento_key(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+    Json(key): Json<DatabentoKey>,
+) -> Result<StatusCode, Response> {
+    state
+        .users
+        .check_admin(token.as_deref())
+        .map_err(IntoResponse::into_response)?;
+    state
+        .secrets
+        .set(DATABENTO_API_KEY, &key.api_key)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    info!(
+        backend = state.secrets.backend_name(),
+        "Rotated shared DataBento key"
+    );
+
+    // Only mock mode has scenarios, and the provider is chosen at startup
+    if state.scenarios.is_some() {
+        return Err(status_error((
+            StatusCode::CONFLICT,
+            "DataBento key stored, but the server is running in mock mode: restart it to \
+             use the key"
+                .to_string(),
+        )));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/me - The calling user.
+pub async fn me(
+    State(state): State<Arc<AppState>>,
+    AuthUser(name): AuthUser,
+) -> Result<Json<UserInfo>, UserError> {
+    Ok(Json(state.users.info(&name)?))
+}
+
+/// PUT /api/me/databento-key - Register the calling user's own DataBento key.
+pub async fn set_databento_key(
+    State(state): State<Arc<AppState>>,
+    AuthUser(name): AuthUser,
+    Json(key): Json<DatabentoKey>,
+) -> Result<StatusCode, UserError> {
+    state.users.set_databento_key(&name, &key.api_key)?;
+    info!(user = %name, "Registered DataBento key");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/me/databento-key - Forget the calling user's DataBento key.
+pub async fn clear_databento_key(
+    State(state): State<Arc<AppState>>,
+    AuthUser(name): AuthUser,
+) -> Result<StatusCode, UserError> {
+    state.users.clear_databento_key(&name)?;
+    info!(user = %name, "Removed DataBento key");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/connections/:id/symbols - What an open live session's symbols
+/// currently resolve to. Only visible to the API key that opened it.
+pub async fn connection_symbols(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Path(id): Path<u64>,
+) -> Result<Json<ConnectionSymbols>, (StatusCode, Json<ErrorResponse>)> {
+    state
+        .connections
+        .symbols(id, &key_id)
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: format!("No open live connection {}", id),
+                    code: StatusCode::NOT_FOUND.as_u16(),
+                }),
+            )
+        })
+}
+
+/// Query parameters for WebSocket connection.
+#[derive(Debug, Deserialize)]
+pub struct LiveParams {
+    /// Comma-separated list of symbols (default: "ES.FUT")
+    #[serde(default = "default_symbols")]
+    pub symbols: String,
+    /// Schema type (default: "trades")
+    #[serde(default = "default_schema")]
+    pub schema: String,
+    /// Symbol type input (default: "parent"); "instrument_id" takes numeric
+    /// IDs and labels records with them
+    #[serde(default)]
+    pub stype_in: SymbolType,
+    /// Comma-separated order-flow imbalance windows (e.g. "5s,1m"); no
+    /// metrics are sent when unset
+    #[serde(default)]
+    pub imbalance_windows: Option<String>,
+    /// Window over which contract volume is compared for roll alerts
+    /// (e.g. "15m"); no alerts are sent when unset
+    #[serde(default)]
+    pub roll_window: Option<String>,
+    /// Send only closed bars of these comma-separated widths (e.g.
+    /// "1m,5m,15m") built from the trades, instead of the trades
+    /// themselves; with an ohlcv-1s or ohlcv-1m schema, also send the
+    /// coarser widths rolled up from its bars. With several widths, or over
+    /// bars, each closed bar carries its width as `interval`
+    #[serde(default)]
+    pub bar_feed: Option<String>,
+    /// Where `bar_feed` bars start, as a historical request's `align`
+    #[serde(default)]
+    pub bar_align: Option<String>,
+    /// Also send economic calendar alerts
+    #[serde(default)]
+    pub calendar: bool,
+    /// Also send `profile_delta` messages as the symbols' developing volume
+    /// profiles change (see [`crate::profiles`])
+    #[serde(default)]
+    pub profile: bool,
+    /// Also send `day_summary` messages with the symbols' open, high, low,
+    /// volume and VWAP this session as they trade (see [`crate::profiles`])
+    #[serde(default)]
+    pub day_summary: bool,
+    /// Levels per side of the books an `mbo` subscription sends (default:
+    /// 10, most: `MAX_BOOK_DEPTH`)
+    #[serde(default)]
+    pub book_depth: Option<usize>,
+    /// Completed bars an `ohlcv-1s` or `ohlcv-1m` subscription starts with,
+    /// before the bar in progress (default and most: `LIVE_SNAPSHOT_BARS`)
+    #[serde(default)]
+    pub snapshot_bars: Option<usize>,
+    /// Comma-separated fields to send of each trade and bar (e.g.
+    /// "ts_event,price"), named as a historical request's `fields`; whole
+    /// records when unset (see [`LiveProjection`])
+    #[serde(default)]
+    pub fields: Option<String>,
+    /// Send trades and bars with short field names (see [`compact`])
+    #[serde(default)]
+    pub compact: bool,
+    /// Write prices and volumes as `number` (default), `string` or `decimal`
+    #[serde(default)]
+    pub prices_as: PricesAs,
+    /// Wire version the client speaks; message types introduced after it
+    /// aren't sent (see [`wire::LIVE_MESSAGES`])
+    #[serde(default)]
+    pub wire_version: Option<u32>,
+    /// Send trades arriving within this many milliseconds of each other as
+    /// one `trade_batch` (most: `MAX_TRADE_BATCH_MS`); off when unset or 0
+    #[serde(default)]
+    pub trade_batch_ms: Option<u64>,
+    /// Conflate trades, bars, day summaries and metrics when the client
+    /// falls behind, announcing each change with `rate_adjusted` (see
+    /// [`crate::conflate`])
+    #[serde(default)]
+    pub adaptive: bool,
+}
+
+fn default_symbols() -> String {
+    "ES.FUT".to_string()
+}
+
+fn default_schema() -> String {
+    "trades".to_string()
+}
+
+/// Most widths one subscription's `bar_feed` can ask for.
+const MAX_BAR_FEED_WIDTHS: usize = 8;
+
+/// The closed bars a subscription asks for with `bar_feed`.
+enum BarFeed {
+    /// One width from trades, sent untagged as before
+    Closed(ClosedBarFeed),
+    /// Several widths from trades, or coarser ones from upstream bars, each
+    /// tagged with its width
+    Fanout(BarFanout),
+}
+
+/// The closed-bar feed a subscription asks for with `bar_feed`, if any.
+/// Coarser widths are derived from the finest inside the feed, so one
+/// upstream subscription serves them all.
+fn closed_bar_feed(params: &LiveParams) -> Result<Option<BarFeed>, String> {
+    let Some(spec) = params.bar_feed.as_deref() else {
+        return Ok(None);
+    };
+    let mut widths = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|width| !width.is_empty())
+        .map(|width| {
+            parse_lookback(width)
+                .map_err(|e| format!("bar_feed: {}", e))?
+                .num_nanoseconds()
+                .and_then(|ns| u64::try_from(ns).ok())
+                .filter(|ns| *ns >= NANOS_PER_SECOND)
+                .ok_or_else(|| format!("bar_feed '{}' must be at least 1s", width))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    widths.sort_unstable();
+    widths.dedup();
+    if widths.is_empty() {
+        return Err("bar_feed needs at least one width".to_string());
+    }
+    if widths.len() > MAX_BAR_FEED_WIDTHS {
+        return Err(format!(
+            "bar_feed takes at most {} widths",
+            MAX_BAR_FEED_WIDTHS
+        ));
+    }
+    let align = params
+        .bar_align
+        .as_deref()
+        .map_or(Ok(BarAlign::Epoch), str::parse)?;
+    let feed = match params.schema.parse() {
+        Ok(Schema::Trades) if widths.len() == 1 => {
+            BarFeed::Closed(ClosedBarFeed::new(widths[0], align))
+        }
+        Ok(Schema::Trades) => BarFeed::Fanout(BarFanout::from_trades(&widths, align)?),
+        Ok(Schema::Ohlcv1S) => {
+            BarFeed::Fanout(BarFanout::from_bars(NANOS_PER_SECOND, &widths, align)?)
+        }
+        Ok(Schema::Ohlcv1M) => {
+            BarFeed::Fanout(BarFanout::from_bars(NANOS_PER_MINUTE, &widths, align)?)
+        }
+        _ => {
+            return Err(
+                "bar_feed builds bars from trades or ohlcv-1s/ohlcv-1m bars; subscribe with one of those schemas"
+                    .to_string(),
+            )
+        }
+    };
+    Ok(Some(feed))
+}
+
+/// Most trades fetched to build the bar in progress for a snapshot.
+const SNAPSHOT_MAX_TRADES: u32 = 100_000;
+
+/// The last `bars` completed bars of a live bar subscription as of `now`,
+/// flagged `bar_closed`, followed by the bar in progress built from its
+/// trades so far, so a chart isn't blank until the next bar closes. Empty
+/// for other schemas. Completed bars come from `rollups` when they cover
+/// them, else from `service`.
+async fn bar_snapshot(
+    service: &dyn MarketDataService,
+    rollups: &RollupStore,
+    symbols: &[String],
+    schema: &str,
+    stype_in: SymbolType,
+    bars: usize,
+    now: DateTime<Utc>,
+) -> Result<Vec<LiveMessage>, ServiceError> {
+    let interval_ns = match schema.parse() {
+        Ok(Schema::Ohlcv1S) => NANOS_PER_SECOND,
+        Ok(Schema::Ohlcv1M) => NANOS_PER_MINUTE,
+        _ => return Ok(Vec::new()),
+    };
+    let now_ns = now.timestamp_nanos_opt().unwrap_or(0).max(0) as u64;
+    let bar_start = now_ns - now_ns % interval_ns;
+    let request = |schema: &str, start_ns: u64, end_ns: u64, limit: u32| {
+        let rfc3339 = |ns: u64| {
+            DateTime::from_timestamp_nanos(ns as i64).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        };
+        HistoricalRequest {
+            symbols: symbols.to_vec(),
+            schema: schema.to_string(),
+            stype_in,
+            start_rfc3339: rfc3339(start_ns),
+            end_rfc3339: rfc3339(end_ns),
+            limit,
+            ..Default::default()
+        }
+    };
+
+    let mut snapshot = Vec::new();
+    if let Some(completed) = rollups.recent(symbols, interval_ns, bar_start, bars) {
+        snapshot.extend(completed.into_iter().map(|bar| bar_message(bar, true)));
+    } else if bars > 0 {
+        let start_ns = bar_start.saturating_sub(interval_ns.saturating_mul(bars as u64));
+        let limit = u32::try_from(bars.saturating_mul(symbols.len())).unwrap_or(u32::MAX);
+        let mut completed = service
+            .get_historical(&request(schema, start_ns, bar_start, limit))
+            .await?;
+        completed.sort_by_time();
+        if let Some(completed) = completed.bars_mut() {
+            snapshot.extend(completed.drain(..).map(|bar| bar_message(bar, true)));
+        }
+    }
+    if now_ns > bar_start {
+        let trades = service
+            .get_historical(&request("trades", bar_start, now_ns, SNAPSHOT_MAX_TRADES))
+            .await?;
+        if let HistoricalResponse::Trades { data } = trades {
+            snapshot.extend(
+                aggregate_trades(&data, interval_ns)
+                    .into_iter()
+                    .filter(|bar| bar.ts_event_unix_ns == bar_start)
+                    .map(|bar| bar_message(bar, false)),
+            );
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Data messages are counted against the live quota in batches of this size.
+const LIVE_USAGE_BATCH: u64 = 100;
+
+/// Longest `trade_batch_ms` window; longer ones are cut to it.
+const MAX_TRADE_BATCH_MS: u64 = 100;
+
+/// GET /ws/live - WebSocket endpoint for live market data.
+pub async fn live_ws(
+    ws: WebSocketUpgrade,
+    ApiKey(key_id): ApiKey,
+    Query(params): Query<LiveParams>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let symbols: Vec<String> = params
+        .symbols
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    info!(
+        symbols = ?symbols,
+        schema = %params.schema,
+        key_id = %key_id,
+        "WebSocket connection request"
+    );
+
+    let stype_in = match checked_stype_in(params.stype_in, &symbols) {
+        Ok(stype_in) => stype_in,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = state.usage.check_live(&key_id) {
+        return e.into_response();
+    }
+    let trackers = match live_trackers(&params) {
+        Ok(trackers) => trackers,
+        Err(e) => return bad_request(e),
+    };
+
+    // The socket outlives this request, so carry the user's key over
+    let api_key = user_api_key();
+    let hard_limit = state.ws_limits.max_inbound_bytes * WsLimits::INBOUND_HARD_LIMIT_FACTOR;
+    ws.max_message_size(hard_limit)
+        .max_frame_size(hard_limit)
+        .on_upgrade(move |socket| {
+            with_user_api_key(
+                api_key,
+                handle_live_socket(socket, state, key_id, symbols, stype_in, params, trackers),
+            )
+        })
+        .into_response()
+}
+
+/// What a live subscription's trades are tracked for, besides being sent.
+struct LiveTrackers {
+    imbalance: Option<ImbalanceTracker>,
+    roll: Option<RollTracker>,
+    bars: Option<BarFeed>,
+}
+
+/// The trackers `params` ask for, or why not, naming the malformed
+/// parameter.
+fn live_trackers(params: &LiveParams) -> Result<LiveTrackers, String> {
+    let imbalance = params
+        .imbalance_windows
+        .as_deref()
+        .map(ImbalanceTracker::from_spec)
+        .transpose()
+        .map_err(|e| format!("imbalance_windows: {}", e))?;
+    let roll = params
+        .roll_window
+        .as_deref()
+        .map(RollTracker::from_spec)
+        .transpose()
+        .map_err(|e| format!("roll_window: {}", e))?;
+    Ok(LiveTrackers {
+        imbalance,
+        roll,
+        bars: closed_bar_feed(params)?,
+    })
+}
+
+/// Handle an active WebSocket connection.
+async fn handle_live_socket(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    key_id: String,
+    symbols: Vec<String>,
+    stype_in: SymbolType,
+    params: LiveParams,
+    trackers: LiveTrackers,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let schema = params.schema.clone();
+
+    if let Err(e) = live_entitled(&state, &key_id, &symbols, &schema).await {
+        warn!(key_id = %key_id, "Refusing live subscription: {}", e);
+        close_with_error(&mut sender, close::NOT_ENTITLED, e.to_string()).await;
+        return;
+    }
+
+    let fields: Vec<String> = params
+        .fields
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    let fields = match LiveProjection::new(&fields) {
+        Ok(fields) => fields,
+        Err(e) => {
+            close_with_error(&mut sender, close::BAD_PARAMS, e).await;
+            return;
+        }
+    };
+
+    let LiveTrackers {
+        imbalance,
+        roll,
+        bars,
+    } = trackers;
+
+    // Subscribe to live data
+    let snapshot_bars = params
+        .snapshot_bars
+        .map_or(state.ws_limits.snapshot_bars, |n| {
+            n.min(state.ws_limits.snapshot_bars)
+        });
+    let subscribed = state
+        .service
+        .subscribe_live(symbols.clone(), schema.clone(), stype_in)
+        .await
+        .map(|stream| {
+            let timer = TransformTimer::new(state.latency.clone());
+            let stream = with_skew_check(timer.input(stream), state.clock.clone());
+            let stream = match schema.parse() {
+                Ok(Schema::Mbp10) => with_depth_book(stream),
+                Ok(Schema::Mbo) => with_order_books(
+                    stream,
+                    params
+                        .book_depth
+                        .unwrap_or(DEPTH_LEVELS)
+                        .min(MAX_BOOK_DEPTH),
+                ),
+                _ => stream,
+            };
+            let stream = match imbalance {
+                Some(tracker) => with_order_flow_imbalance(stream, tracker),
+                None => stream,
+            };
+            let stream = match roll {
+                Some(tracker) => with_roll_alerts(stream, tracker),
+                None => stream,
+            };
+            let stream = with_paper_fills(stream, state.paper.clone(), key_id.clone());
+            let stream = match state.rollups.enabled() {
+                true => with_rollups(stream, state.rollups.tap()),
+                false => stream,
+            };
+            let stream = with_profiles(stream, state.profiles.tap());
+            let stream = match bars {
+                Some(BarFeed::Closed(feed)) => with_closed_bars(stream, feed),
+                Some(BarFeed::Fanout(fanout)) => with_bar_fanout(stream, fanout),
+                None => stream,
+            };
+            // Last, as the transforms above take trades one at a time
+            let stream = match params.trade_batch_ms.filter(|ms| *ms > 0) {
+                Some(ms) => {
+                    with_trade_batches(stream, Duration::from_millis(ms.min(MAX_TRADE_BATCH_MS)))
+                }
+                None => stream,
+            };
+            timer.output(stream)
+        });
+    let stream = match subscribed {
+        Ok(stream) => {
+            // Subscribed first so no bar closes unseen between the two
+            let snapshot = bar_snapshot(
+                state.service.as_ref(),
+                &state.rollups,
+                &symbols,
+                &schema,
+                stype_in,
+                snapshot_bars,
+                Utc::now(),
+            )
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to fetch the bar snapshot: {}", e);
+                Vec::new()
+            });
+            with_snapshot(stream, snapshot)
+        }
+        Err(e) => {
+            error!("Failed to subscribe: {}", e);
+            close_with_error(&mut sender, service_close_code(&e), e.to_string()).await;
+            return;
+        }
+    };
+
+    let connection = state.connections.open(&key_id, &symbols, &schema, stype_in);
+    info!(
+        symbols = ?symbols,
+        schema = %schema,
+        connection_id = connection.id(),
+        "WebSocket connected"
+    );
+
+    let limits = state.ws_limits;
+    let (compact, prices_as) = (params.compact, params.prices_as);
+    let wire_version = params.wire_version;
+
+    // Replies generated by the receive task are sent alongside stream data
+    let (reply_tx, mut reply_rx) = mpsc::channel::<LiveMessage>(16);
+    let mut paper_events = state.paper.subscribe();
+    let mut calendar_alerts = params.calendar.then(|| state.calendar.subscribe());
+    let (send_profiles, send_summaries) = (params.profile, params.day_summary);
+    let mut session_updates = (send_profiles || send_summaries).then(|| state.profiles.subscribe());
+    // Sessions are kept by the symbols trades carry, which can be the
+    // contracts the subscribed symbols map to
+    let mut session_symbols: HashSet<String> = symbols.iter().cloned().collect();
+    let mut status_changes = state.monitor.subscribe();
+    let paper = state.paper.clone();
+    let paper_key = key_id.clone();
+
+    // Adaptive subscriptions read ahead, so the wait to be sent shows
+    // how far behind the client is
+    let mut stream: QueuedStream = match params.adaptive {
+        true => conflate::queued(stream, conflate::QUEUE_CAPACITY),
+        false => Box::pin(stream.map(|msg| (Instant::now(), msg))),
+    };
+    let mut rate = params.adaptive.then(|| AdaptiveRate::new(Instant::now()));
+
+    // Spawn a task to forward messages from the stream to the WebSocket
+    let usage = state.usage.clone();
+    let latency = state.latency.clone();
+    let send_task = tokio::spawn(async move {
+        let mut next_fragment_id = 0u64;
+        let mut uncounted = 0u64;
+        // An error the stream ends on is what it ended because of
+        let mut last_error = None;
+        // Conflated messages due out, and when they're next flushed
+        let mut flushed = VecDeque::new();
+        let mut flush_timer: Option<tokio::time::Interval> = None;
+        loop {
+            let mut msg = if let Some(msg) = flushed.pop_front() {
+                msg
+            } else {
+                tokio::select! {
+                    Some(msg) = reply_rx.recv() => msg,
+                    event = paper_events.recv() => match event {
+                        Ok((event_key, msg)) if event_key == key_id => msg,
+                        Ok(_) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                            warn!(skipped = n, "Live socket fell behind on paper events");
+                            continue;
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => continue,
+                    },
+                    change = status_changes.recv() => match change {
+                        Ok(msg) => msg,
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => continue,
+                    },
+                    alert = async { calendar_alerts.as_mut().unwrap().recv().await },
+                        if calendar_alerts.is_some() => match alert {
+                        Ok(msg) => msg,
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                            warn!(skipped = n, "Live socket fell behind on calendar alerts");
+                            continue;
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                            calendar_alerts = None;
+                            continue;
+                        }
+                    },
+                    update = async { session_updates.as_mut().unwrap().recv().await },
+                        if session_updates.is_some() => match update {
+                        Ok(LiveMessage::ProfileDelta(delta))
+                            if send_profiles && session_symbols.contains(&delta.symbol) =>
+                        {
+                            LiveMessage::ProfileDelta(delta)
+                        }
+                        Ok(LiveMessage::DaySummary(summary))
+                            if send_summaries && session_symbols.contains(&summary.symbol) =>
+                        {
+                            LiveMessage::DaySummary(summary)
+                        }
+                        Ok(_) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                            warn!(skipped = n, "Live socket fell behind on session updates");
+                            continue;
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                            session_updates = None;
+                            continue;
+                        }
+                    },
+                    _ = async { flush_timer.as_mut().unwrap().tick().await },
+                        if flush_timer.is_some() => {
+                        flushed.extend(rate.as_mut().map(AdaptiveRate::flush).unwrap_or_default());
+                        continue;
+                    },
+                    msg = stream.next() => match msg {
+                        Some((queued_at, msg)) => {
+                            last_error = match &msg {
+                                LiveMessage::Error { message } => Some(message.clone()),
+                                _ => None,
+                            };
+                            match &mut rate {
+                                None => msg,
+                                Some(rate) => {
+                                    let now = Instant::now();
+                                    if let Some(notice) = rate.observe(now - queued_at, now) {
+                                        info!(
+                                            connection_id = connection.id(),
+                                            "Live rate adjusted: {:?}", notice
+                                        );
+                                        flushed.extend(rate.flush());
+                                        flushed.push_back(notice);
+                                        flush_timer = rate.interval().map(flush_every);
+                                    }
+                                    flushed.extend(rate.push(msg));
+                                    continue;
+                                }
+                            }
+                        }
+                        None => {
+                            // Send what's held before closing
+                            let held = rate.as_mut().map(AdaptiveRate::flush).unwrap_or_default();
+                            if !held.is_empty() {
+                                flushed.extend(held);
+                                continue;
+                            }
+                            let frame = match &last_error {
+                                Some(message) => close_frame(close::UPSTREAM_DOWN, message),
+                                None => close_frame(close::NORMAL, "Stream ended"),
+                            };
+                            let _ = sender.send(frame).await;
+                            break;
+                        }
+                    },
+                }
+            };
+
+            if session_updates.is_some() {
+                let symbols: Vec<&String> = match &msg {
+                    LiveMessage::Trade { symbol, .. } => vec![symbol],
+                    LiveMessage::TradeBatch { trades } => {
+                        trades.iter().map(|trade| &trade.symbol).collect()
+                    }
+                    LiveMessage::SymbolMapping {
+                        stype_out_symbol, ..
+                    } => vec![stype_out_symbol],
+                    _ => Vec::new(),
+                };
+                for symbol in symbols {
+                    if !session_symbols.contains(symbol) {
+                        session_symbols.insert(symbol.clone());
+                    }
+                }
+            }
+            connection.observe(&mut msg);
+            if wire_version.is_some_and(|v| !wire::knows_live_message(v, msg.type_name())) {
+                continue;
+            }
+            let started = Instant::now();
+            let json = match live_json(&msg, fields.as_ref(), compact, prices_as) {
+                Ok(json) => json,
+                Err(e) => {
+                    warn!("Failed to serialize message: {}", e);
+                    continue;
+                }
+            };
+            latency.record(Stage::Serialize, started.elapsed());
+
+            let started = Instant::now();
+            if send_frames(
+                &mut sender,
+                json,
+                limits.max_frame_bytes,
+                &mut next_fragment_id,
+            )
+            .await
+            .is_err()
+            {
+                break;
+            }
+            latency.record(Stage::Send, started.elapsed());
+
+            uncounted += match &msg {
+                LiveMessage::Trade { .. } | LiveMessage::Ohlcv { .. } => 1,
+                LiveMessage::TradeBatch { trades } => trades.len() as u64,
+                _ => 0,
+            };
+            if uncounted >= LIVE_USAGE_BATCH {
+                let counted = std::mem::take(&mut uncounted);
+                if let Err(e) = usage.record_live(&key_id, counted) {
+                    info!(key_id = %key_id, "Closing live stream: {}", e);
+                    close_with_error(&mut sender, close::QUOTA_EXCEEDED, e.to_string()).await;
+                    break;
+                }
+            }
+        }
+        // Quota is already enforced; only the count matters now
+        let _ = usage.record_live(&key_id, uncounted);
+    });
+
+    // Handle incoming messages: paper trading commands, and pings
+    let recv_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = receiver.next().await {
+            match msg {
+                Message::Close(_) => break,
+                Message::Ping(data) => {
+                    // Ping is handled automatically by axum
+                    tracing::trace!("Received ping: {:?}", data);
+                }
+                Message::Text(text) if text.len() > limits.max_inbound_bytes => {
+                    warn!(bytes = text.len(), "Rejecting oversized client message");
+                    let reply = LiveMessage::Error {
+                        message: format!(
+                            "Message too large: {} bytes (max {})",
+                            text.len(),
+                            limits.max_inbound_bytes
+                        ),
+                    };
+                    if reply_tx.send(reply).await.is_err() {
+                        break;
+                    }
+                }
+                Message::Text(text) => {
+                    let result = match serde_json::from_str::<PaperCommand>(&text) {
+                        Ok(PaperCommand::SubmitOrder(order)) => paper
+                            .submit(&paper_key, order)
+                            .map(drop)
+                            .map_err(|e| e.to_string()),
+                        Ok(PaperCommand::CancelOrder { order_id }) => paper
+                            .cancel(&paper_key, order_id)
+                            .map(drop)
+                            .map_err(|e| e.to_string()),
+                        Err(e) => Err(format!("Unrecognized client message: {}", e)),
+                    };
+                    // Accepted commands are answered by their paper_order event
+                    if let Err(message) = result {
+                        if reply_tx.send(LiveMessage::Error { message }).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    // Wait for either task to complete
+    tokio::select! {
+        _ = send_task => {
+            info!("Send task completed");
+        }
+        _ = recv_task => {
+            info!("Receive task completed (client disconnected)");
+        }
+    }
+
+    info!(symbols = ?symbols, "WebSocket disconnected");
+}
+
+/// Query parameters for `GET /api/calendar`.
+#[derive(Debug, Deserialize)]
+pub struct CalendarParams {
+    /// `START..END`, each RFC3339 or a relative expression (default:
+    /// `today..now+7d`)
+    #[serde(default = "default_calendar_range")]
+    pub range: String,
+}
+
+fn default_calendar_range() -> String {
+    "today..now+7d".to_string()
+}
+
+/// GET /api/calendar?range=START..END - Economic calendar events released
+/// in a range, for chart annotations. Empty when no calendar is configured.
+pub async fn calendar_events(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CalendarParams>,
+) -> Result<Json<Vec<CalendarEvent>>, Response> {
+    let (start, end) = params.range.split_once("..").ok_or_else(|| {
+        bad_request(format!(
+            "Invalid range '{}': expected START..END, e.g. today..now+7d",
+            params.range
+        ))
+    })?;
+    let now = Utc::now();
+    let to_ns = |expr: &str| {
+        timeexpr::resolve(expr, now).map(|t| t.timestamp_nanos_opt().unwrap_or(0).max(0) as u64)
+    };
+    let start_ns = to_ns(start).map_err(bad_request)?;
+    let end_ns = to_ns(end).map_err(bad_request)?;
+    Ok(Json(state.calendar.events(start_ns, end_ns)))
+}
+
+/// Most bars `GET /api/rollups/:symbol` returns by default.
+const DEFAULT_ROLLUP_BARS: usize = 500;
+
+/// Query parameters for `GET /api/rollups/:symbol`.
+#[derive(Debug, Deserialize)]
+pub struct RollupParams {
+    /// Bar width, one of `ROLLUP_INTERVALS` (default: the narrowest)
+    pub interval: Option<String>,
+    /// How many of the latest bars to return (default 500)
+    pub bars: Option<usize>,
+}
+
+/// An error body with `status`.
+fn status_error((status, error): (StatusCode, String)) -> Response {
+    (
+        status,
+        Json(ErrorResponse {
+            error,
+            code: status.as_u16(),
+        }),
+    )
+        .into_response()
+}
+
+/// The configured rollup width `interval` names, or the narrowest.
+fn rollup_interval(state: &AppState, interval: Option<&str>) -> Result<u64, (StatusCode, String)> {
+    if !state.rollups.enabled() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "Rollups are off; set ROLLUP_INTERVALS to keep them".to_string(),
+        ));
+    }
+    let configured = state.rollups.intervals();
+    let Some(interval) = interval else {
+        return Ok(configured[0]);
+    };
+    rollups::parse_intervals(interval)
+        .ok()
+        .and_then(|parsed| parsed.first().copied())
+        .filter(|ns| configured.contains(ns))
+        .ok_or_else(|| {
+            let kept: Vec<String> = configured.iter().map(|ns| rollups::label(*ns)).collect();
+            (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "No {} rollups; kept widths are {}",
+                    interval,
+                    kept.join(", ")
+                ),
+            )
+        })
+}
+
+/// GET /api/rollups/:symbol?interval=1m&bars=500 - The latest bars kept
+/// for a symbol, served without calling the provider.
+pub async fn rollup_bars(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<RollupParams>,
+) -> Result<Json<RollupBars>, Response> {
+    let interval_ns = rollup_interval(&state, params.interval.as_deref()).map_err(status_error)?;
+    let series = state.rollups.series(&symbol, interval_ns).ok_or_else(|| {
+        status_error((
+            StatusCode::NOT_FOUND,
+            format!("No rollups kept for {}", symbol),
+        ))
+    })?;
+    let bars = series.last(params.bars.unwrap_or(DEFAULT_ROLLUP_BARS));
+    let day_change = match bars.last() {
+        Some(bar) => state
+            .closes
+            .close(&symbol, bar.ts_event_unix_ns)
+            .await
+            .map(|close| DayChange::new(close, bar.close_i64)),
+        None => None,
+    };
+    Ok(Json(RollupBars {
+        interval: rollups::label(interval_ns),
+        covered_from_unix_ns: series.covered.map(|(from, _)| from),
+        covered_to_unix_ns: series.covered.map(|(_, to)| to),
+        bars,
+        day_change,
+        symbol,
+    }))
+}
+
+/// Query parameters for `GET /api/leaderboard`.
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardParams {
+    /// Bar width ranked over, one of `ROLLUP_INTERVALS` (default: the
+    /// narrowest)
+    pub interval: Option<String>,
+    /// How far back to rank, e.g. `1h` (default)
+    #[serde(default = "default_leaderboard_window")]
+    pub window: String,
+    #[serde(default)]
+    pub rank: LeaderboardRank,
+    /// Most symbols listed (default 10)
+    #[serde(default = "default_leaderboard_limit")]
+    pub limit: usize,
+}
+
+fn default_leaderboard_window() -> String {
+    "1h".to_string()
+}
+
+fn default_leaderboard_limit() -> usize {
+    10
+}
+
+/// GET /api/leaderboard?window=1h&rank=change - Symbols ranked by how much
+/// they moved, or traded, over a recent window of their rollups.
+pub async fn leaderboard(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LeaderboardParams>,
+) -> Result<Json<Leaderboard>, Response> {
+    let interval_ns = rollup_interval(&state, params.interval.as_deref()).map_err(status_error)?;
+    let window = parse_lookback(&params.window).map_err(bad_request)?;
+    let since = Utc::now()
+        .checked_sub_signed(window)
+        .and_then(|since| since.timestamp_nanos_opt())
+        .unwrap_or(0)
+        .max(0) as u64;
+    let movers = state.rollups.movers(interval_ns, since);
+    let mut movers = shared::rollups::leaderboard(movers, params.rank, params.limit);
+    let now = Utc::now().timestamp_nanos_opt().unwrap_or(0).max(0) as u64;
+    let closes = futures::future::join_all(
+        movers
+            .iter()
+            .map(|mover| state.closes.close(&mover.symbol, now)),
+    )
+    .await;
+    for (mover, close) in movers.iter_mut().zip(closes) {
+        mover.day_change = close.map(|close| DayChange::new(close, mover.close_i64));
+    }
+    Ok(Json(Leaderboard {
+        interval: rollups::label(interval_ns),
+        since_unix_ns: since,
+        rank: params.rank,
+        movers,
+    }))
+}
+
+/// How far back `GET /api/tape` reads: the whole tape on a first call, and
+/// the oldest `since_ns` it honors.
+const TAPE_LOOKBACK: Duration = Duration::from_secs(15 * 60);
+
+/// Most trades one `GET /api/tape` call reads from the provider.
+const TAPE_FETCH_LIMIT: u32 = 50_000;
+
+/// Most trades one `GET /api/tape` call sends.
+const TAPE_MAX_LIMIT: usize = 1_000;
+
+/// Query parameters for `GET /api/tape`.
+#[derive(Debug, Deserialize)]
+pub struct TapeParams {
+    pub symbol: String,
+    /// Symbol type of `symbol` when read from the provider (default:
+    /// "parent")
+    #[serde(default)]
+    pub stype_in: SymbolType,
+    /// Smallest trade size sent, e.g. to show only block trades (default:
+    /// every trade)
+    #[serde(default)]
+    pub min_size: u32,
+    /// Only trades after this time, in nanoseconds since the epoch: the
+    /// previous response's `latest_unix_ns` when polling
+    pub since_ns: Option<u64>,
+    /// Most trades sent, newest first (default 100, at most 1,000)
+    #[serde(default = "default_tape_limit")]
+    pub limit: usize,
+}
+
+fn default_tape_limit() -> usize {
+    100
+}
+
+/// GET /api/tape?symbol=ES.FUT&min_size=50&since_ns=..&limit=100 - A
+/// symbol's latest trades, newest first, for a time and sales panel. Read
+/// from the live recording when the symbol's trades are being recorded,
+/// otherwise fetched from the provider like a historical request.
+pub async fn tape(
+    ApiKey(key_id): ApiKey,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TapeParams>,
+) -> Result<Json<Tape>, Response> {
+    let now = Utc::now();
+    let oldest = (now - TimeDelta::from_std(TAPE_LOOKBACK).unwrap_or_default())
+        .timestamp_nanos_opt()
+        .unwrap_or(0)
+        .max(0) as u64;
+    let start_ns = params
+        .since_ns
+        .map_or(oldest, |since| since.saturating_add(1).max(oldest));
+    let req = HistoricalRequest {
+        symbols: vec![params.symbol.clone()],
+        schema: Schema::Trades.as_str().to_string(),
+        stype_in: params.stype_in,
+        start_rfc3339: DateTime::from_timestamp_nanos(start_ns.min(i64::MAX as u64) as i64)
+            .to_rfc3339_opts(SecondsFormat::Nanos, true),
+        end_rfc3339: now.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        limit: TAPE_FETCH_LIMIT,
+        ..Default::default()
+    };
+
+    let recordings = state.recordings.clone();
+    let (response, source, more) = match recordings {
+        Some(recordings) if recordings.is_recording(&req.schema, &params.symbol) => {
+            // Read from disk, so there's no need to stop short
+            let req = HistoricalRequest {
+                limit: u32::MAX,
+                ..req
+            };
+            let response = compute(move || recordings.query(&req))
+                .await?
+                .map_err(IntoResponse::into_response)?;
+            (response, TapeSource::Recording, false)
+        }
+        _ => {
+            let fetched = metered_fetch(&state, &key_id, &req, false).await?;
+            let more = fetched.response.reached_limits(req.limit, None);
+            (fetched.response, fetched.source.into(), more)
+        }
+    };
+    let HistoricalResponse::Trades { data: trades } = response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    let latest_unix_ns = trades
+        .last()
+        .map(|trade| trade.ts_event_unix_ns)
+        .or(params.since_ns);
+    let limit = params.limit.min(TAPE_MAX_LIMIT);
+    let (trades, skipped) = tape::select(trades, params.min_size, params.since_ns, limit);
+    Ok(Json(Tape {
+        symbol: params.symbol,
+        source,
+        trades,
+        latest_unix_ns,
+        skipped,
+        more,
+    }))
+}
+
+/// GET /api/profile/:symbol - A symbol's developing volume profile this
+/// session, while a live subscription to it is open.
+pub async fn volume_profile(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<Json<VolumeProfile>, Response> {
+    state.profiles.profile(&symbol).map(Json).ok_or_else(|| {
+        status_error((
+            StatusCode::NOT_FOUND,
+            format!(
+                "No volume profile for {}; profiles are kept while a live subscription to the symbol is open",
+                symbol
+            ),
+        ))
+    })
+}
+
+/// Query parameters for day summaries.
+#[derive(Debug, Deserialize)]
+pub struct DaySummaryParams {
+    /// Comma-separated symbols (default: every symbol with one)
+    #[serde(default)]
+    pub symbols: String,
+}
+
+/// GET /api/day-summary - Open, high, low, volume and VWAP this session of
+/// the symbols a live subscription feeds, with their change against the
+/// previous session's close.
+pub async fn day_summary(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DaySummaryParams>,
+) -> Json<Vec<DaySummary>> {
+    let symbols: Vec<String> = params
+        .symbols
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let mut summaries = state.profiles.summaries(&symbols);
+    let closes = futures::future::join_all(
+        summaries
+            .iter()
+            .map(|summary| state.closes.close(&summary.symbol, summary.last_unix_ns)),
+    )
+    .await;
+    for (summary, close) in summaries.iter_mut().zip(closes) {
+        summary.day_change = close.map(|close| DayChange::new(close, summary.last_i64));
+    }
+    Json(summaries)
+}
+
+/// Most levels per side of a book, rebuilt or live.
+const MAX_BOOK_DEPTH: usize = 50;
+
+/// Most order events replayed for one `GET /api/book`; a busy contract
+/// sees a few million in a day.
+const MAX_BOOK_EVENTS: u32 = 50_000_000;
+
+/// Query parameters for `GET /api/book`.
+#[derive(Debug, Deserialize)]
+pub struct BookParams {
+    pub symbol: String,
+    /// The time of the book, as a historical request's `end` (default:
+    /// "now")
+    #[serde(default = "default_book_at")]
+    pub at: String,
+    /// Levels per side (default 10, at most 50)
+    #[serde(default)]
+    pub depth: Option<usize>,
+    /// Symbol type of `symbol` (default: "parent")
+    #[serde(default)]
+    pub stype_in: SymbolType,
+}
+
+fn default_book_at() -> String {
+    "now".to_string()
+}
+
+/// GET /api/book?symbol=ESZ4&at=2024-06-04T14:30:00Z&depth=10 - The order
+/// book of each of a symbol's instruments at a time, rebuilt from the day's
+/// market-by-order (MBO) events: the provider's snapshot at UTC midnight,
+/// then every event up to `at`.
+pub async fn book(
+    ApiKey(key_id): ApiKey,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<BookParams>,
+) -> Result<Json<BookResponse>, Response> {
+    let bad_request = |e: String| status_error((StatusCode::BAD_REQUEST, e));
+    let at = timeexpr::resolve(&params.at, Utc::now()).map_err(bad_request)?;
+    let midnight = at.date_naive().and_time(NaiveTime::MIN).and_utc();
+    let req = HistoricalRequest {
+        symbols: vec![params.symbol.clone()],
+        schema: Schema::Mbo.as_str().to_string(),
+        stype_in: params.stype_in,
+        start_rfc3339: midnight.to_rfc3339_opts(SecondsFormat::Secs, true),
+        end_rfc3339: at.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        limit: MAX_BOOK_EVENTS,
+        ..Default::default()
+    };
+    // The end may be clamped to what the provider has published
+    let req = checked_range(&state, &req).map_err(IntoResponse::into_response)?;
+    let at_unix_ns = DateTime::parse_from_rfc3339(&req.end_rfc3339)
+        .ok()
+        .and_then(|end| end.timestamp_nanos_opt())
+        .unwrap_or(0)
+        .max(0) as u64;
+    info!(symbol = %params.symbol, at = %req.end_rfc3339, key_id = %key_id, "Rebuilding order books");
+
+    let mut books = BookSet::new();
+    replay_orders(&state, &key_id, &req, |record| {
+        books.apply(record);
+    })
+    .await?;
+
+    Ok(Json(BookResponse {
+        symbol: params.symbol,
+        at_unix_ns,
+        events_u64: books.events(),
+        books: books.views(params.depth.unwrap_or(DEPTH_LEVELS).min(MAX_BOOK_DEPTH)),
+    }))
+}
+
+/// Replay `req`'s order events through `apply`, as the key's historical
+/// access and usage allow, failing if there are more than
+/// `MAX_BOOK_EVENTS` of them.
+async fn replay_orders(
+    state: &AppState,
+    key_id: &str,
+    req: &HistoricalRequest,
+    mut apply: impl FnMut(&MboRecord) + Send,
+) -> Result<(), Response> {
+    entitlements::check(
+        state.authorizer.as_ref(),
+        state.service.as_ref(),
+        key_id,
+        AccessKind::Historical,
+        req,
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    state
+        .usage
+        .check_historical(key_id, 0.0)
+        .map_err(IntoResponse::into_response)?;
+
+    let mut events = 0;
+    let replay = async {
+        let _turn = state.historical_queue.acquire(key_id).await;
+        let mut records = state.service.replay_mbo(req).await?;
+        while let Some(record) = records.next().await {
+            apply(&record?);
+            events += 1;
+        }
+        Ok(())
+    };
+    let result = cancel::or_cancelled(replay).await;
+    state.usage.record_historical(key_id, events, 0.0);
+    result.map_err(IntoResponse::into_response)?;
+    if events >= u64::from(MAX_BOOK_EVENTS) {
+        return Err(status_error((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "More than {} order events from {} to {}; ask for an earlier or shorter range",
+                MAX_BOOK_EVENTS, req.start_rfc3339, req.end_rfc3339
+            ),
+        )));
+    }
+    Ok(())
+}
+
+/// `time`, an RFC 3339 time as [`checked_range`] leaves it, in UTC.
+fn parse_utc(time: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("Invalid time '{}': {}", time, e))
+}
+
+/// Most samples one `POST /api/book/sample` sends (a day at one a
+/// second).
+const MAX_BOOK_SAMPLES: u64 = 86_400;
+
+/// POST /api/book/sample - A symbol's books every `interval` over a range,
+/// for liquidity studies, as NDJSON: one `GET /api/book` response a line,
+/// sent as the events are replayed. The replay starts at the UTC midnight
+/// before `start`. A replay that fails part way ends with an error line.
+pub async fn book_sample(
+    ApiKey(key_id): ApiKey,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<BookSampleRequest>,
+) -> Result<Response, Response> {
+    let bad_request = |e: String| status_error((StatusCode::BAD_REQUEST, e));
+    let interval_ns = parse_lookback(&body.interval)
+        .map_err(bad_request)?
+        .num_nanoseconds()
+        .filter(|ns| *ns > 0)
+        .ok_or_else(|| bad_request(format!("interval '{}' must be positive", body.interval)))?
+        as u64;
+    let req = HistoricalRequest {
+        symbols: vec![body.symbol.clone()],
+        schema: Schema::Mbo.as_str().to_string(),
+        stype_in: body.stype_in,
+        start_rfc3339: body.start_rfc3339.clone(),
+        end_rfc3339: body.end_rfc3339.clone(),
+        limit: MAX_BOOK_EVENTS,
+        ..Default::default()
+    };
+    let mut req = checked_range(&state, &req).map_err(IntoResponse::into_response)?;
+    let start = parse_utc(&req.start_rfc3339).map_err(bad_request)?;
+    let end = parse_utc(&req.end_rfc3339).map_err(bad_request)?;
+    let unix_ns = |time: DateTime<Utc>| time.timestamp_nanos_opt().unwrap_or(0).max(0) as u64;
+    let (start_unix_ns, end_unix_ns) = (unix_ns(start), unix_ns(end));
+    if end_unix_ns < start_unix_ns {
+        return Err(bad_request("end is before start".to_string()));
+    }
+    let samples = (end_unix_ns - start_unix_ns) / interval_ns + 1;
+    if samples > MAX_BOOK_SAMPLES {
+        return Err(bad_request(format!(
+            "{} samples requested; at most {} are sent, so widen the interval or narrow the range",
+            samples, MAX_BOOK_SAMPLES
+        )));
+    }
+    req.start_rfc3339 = start
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    entitlements::check(
+        state.authorizer.as_ref(),
+        state.service.as_ref(),
+        &key_id,
+        AccessKind::Historical,
+        &req,
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    state
+        .usage
+        .check_historical(&key_id, 0.0)
+        .map_err(IntoResponse::into_response)?;
+    info!(
+        symbol = %body.symbol,
+        start = %req.start_rfc3339,
+        end = %req.end_rfc3339,
+        interval = %body.interval,
+        key_id = %key_id,
+        "Sampling order books"
+    );
+
+    let turn = state.historical_queue.acquire(&key_id).await;
+    let mut events = cancel::or_cancelled(state.service.replay_mbo(&req))
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let mut sampler = BookSampler::new(
+        &body.symbol,
+        start_unix_ns,
+        end_unix_ns,
+        interval_ns,
+        body.depth.unwrap_or(DEPTH_LEVELS).min(MAX_BOOK_DEPTH),
+    );
+    // The body outlives the request's task, so check its token as it goes
+    let token = cancel::current();
+    let lines = async_stream::stream! {
+        let _turn = turn;
+        let mut failed = None;
+        while let Some(record) = events.next().await {
+            if token.as_ref().is_some_and(cancel::CancellationToken::is_cancelled) {
+                failed = Some(ServiceError::Cancelled);
+                break;
+            }
+            match record {
+                Ok(record) => {
+                    for sample in sampler.apply(&record) {
+                        yield ndjson(&sample);
+                    }
+                }
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+        state.usage.record_historical(&key_id, sampler.events(), 0.0);
+        let error = match failed {
+            Some(e) => ErrorResponse {
+                error: e.to_string(),
+                code: service_status(&e).as_u16(),
+            },
+            None if sampler.events() >= u64::from(MAX_BOOK_EVENTS) => ErrorResponse {
+                error: format!(
+                    "More than {} order events before the end; sample a shorter range",
+                    MAX_BOOK_EVENTS
+                ),
+                code: StatusCode::BAD_REQUEST.as_u16(),
+            },
+            None => {
+                for sample in sampler.finish() {
+                    yield ndjson(&sample);
+                }
+                return;
+            }
+        };
+        warn!(key_id = %key_id, "Book sampling stopped: {}", error.error);
+        yield ndjson(&error);
+    };
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines.map(Ok::<_, Infallible>)),
+    )
+        .into_response())
+}
+
+/// `value` as a line of NDJSON.
+fn ndjson<T: serde::Serialize>(value: &T) -> String {
+    let mut line = serde_json::to_string(value).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+/// POST /api/book/activity - Adds, cancels and trades per price level of a
+/// symbol's instruments over a range, with how long orders rested and the
+/// cancel-to-trade ratio (see [`shared::activity`]), from the day's
+/// market-by-order events.
+pub async fn book_activity(
+    ApiKey(key_id): ApiKey,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<OrderActivityRequest>,
+) -> Result<Json<OrderActivityReport>, Response> {
+    let bad_request = |e: String| status_error((StatusCode::BAD_REQUEST, e));
+    let req = HistoricalRequest {
+        symbols: vec![body.symbol.clone()],
+        schema: Schema::Mbo.as_str().to_string(),
+        stype_in: body.stype_in,
+        start_rfc3339: body.start_rfc3339.clone(),
+        end_rfc3339: body.end_rfc3339.clone(),
+        limit: MAX_BOOK_EVENTS,
+        ..Default::default()
+    };
+    let mut req = checked_range(&state, &req).map_err(IntoResponse::into_response)?;
+    let start = parse_utc(&req.start_rfc3339).map_err(bad_request)?;
+    let end = parse_utc(&req.end_rfc3339).map_err(bad_request)?;
+    if end < start {
+        return Err(bad_request("end is before start".to_string()));
+    }
+    // Orders resting at the start are known from the day's snapshot
+    req.start_rfc3339 = start
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    info!(
+        symbol = %body.symbol,
+        start = %body.start_rfc3339,
+        end = %req.end_rfc3339,
+        key_id = %key_id,
+        "Measuring order activity"
+    );
+
+    let unix_ns = |time: DateTime<Utc>| time.timestamp_nanos_opt().unwrap_or(0).max(0) as u64;
+    let mut activity = OrderActivity::new(unix_ns(start));
+    replay_orders(&state, &key_id, &req, |record| activity.apply(record)).await?;
+    Ok(Json(activity.report(
+        &body.symbol,
+        unix_ns(start),
+        unix_ns(end),
+    )))
+}
+
+impl IntoResponse for RecordingError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            RecordingError::Disabled => StatusCode::NOT_FOUND,
+            RecordingError::Query(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        status_error((status, self.to_string()))
+    }
+}
+
+/// The recordings, if recording is on.
+fn recordings(state: &AppState) -> Result<Arc<Recordings>, RecordingError> {
+    state.recordings.clone().ok_or(RecordingError::Disabled)
+}
+
+/// Query parameters for `GET /api/recordings`.
+#[derive(Debug, Deserialize)]
+pub struct RecordingFilter {
+    pub schema: Option<String>,
+    pub symbol: Option<String>,
+}
+
+/// GET /api/recordings?schema=trades&symbol=ES.FUT - Recorded partitions:
+/// compacted Parquet ones from the index, then NDJSON still being written.
+pub async fn list_recordings(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<RecordingFilter>,
+) -> Result<Json<Vec<RecordingPartition>>, Response> {
+    let recordings = recordings(&state).map_err(IntoResponse::into_response)?;
+    let partitions = compute(move || recordings.partitions())
+        .await?
+        .map_err(IntoResponse::into_response)?;
+    Ok(Json(
+        partitions
+            .into_iter()
+            .filter(|p| filter.schema.as_ref().is_none_or(|s| &p.schema == s))
+            .filter(|p| filter.symbol.as_ref().is_none_or(|s| &p.symbol == s))
+            .collect(),
+    ))
+}
+
+/// POST /api/recordings/query - Recorded records in a historical request's
+/// schema, symbols, range and limits, read from disk without calling the
+/// provider.
+pub async fn query_recordings(
+    State(state): State<Arc<AppState>>,
+    Json(mut req): Json<HistoricalRequest>,
+) -> Result<Json<HistoricalResponse>, Response> {
+    let recordings = recordings(&state).map_err(IntoResponse::into_response)?;
+    timeexpr::resolve_request(&mut req, Utc::now()).map_err(bad_request)?;
+    let response = compute(move || recordings.query(&req))
+        .await?
+        .map_err(IntoResponse::into_response)?;
+    Ok(Json(response))
+}
+
+/// POST /api/admin/recordings/compact - Compact finished days now rather
+/// than at the next hourly run (admin).
+pub async fn compact_recordings(
+    State(state): State<Arc<AppState>>,
+    AdminToken(token): AdminToken,
+) -> Result<Json<CompactionReport>, Response> {
+    state
+        .users
+        .check_admin(token.as_deref())
+        .map_err(IntoResponse::into_response)?;
+    let recordings = recordings(&state).map_err(IntoResponse::into_response)?;
+    let today = Utc::now().date_naive();
+    Ok(Json(compute(move || recordings.compact(today)).await?))
+}
+
+impl IntoResponse for ImportError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ImportError::Recording(e) => return e.into_response(),
+            ImportError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        status_error((status, self.to_string()))
+    }
+}
+
+/// The `spec` and `file` parts of an import upload.
+async fn import_upload(
+    mut multipart: Multipart,
+) -> Result<(ImportSpec, Option<String>, Bytes), ImportError> {
+    let invalid = |e: axum::extract::multipart::MultipartError| ImportError::Invalid(e.body_text());
+    let (mut spec, mut file) = (None, None);
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        match field.name() {
+            Some("spec") => {
+                let json = field.bytes().await.map_err(invalid)?;
+                spec =
+                    Some(serde_json::from_slice::<ImportSpec>(&json).map_err(|e| {
+                        ImportError::Invalid(format!("Invalid import spec: {}", e))
+                    })?);
+            }
+            Some("file") => {
+                let name = field.file_name().map(str::to_string);
+                file = Some((name, field.bytes().await.map_err(invalid)?));
+            }
+            _ => {}
+        }
+    }
+    let missing = |part: &str| ImportError::Invalid(format!("Missing the '{}' part", part));
+    let spec = spec.ok_or_else(|| missing("spec"))?;
+    let (name, bytes) = file.ok_or_else(|| missing("file"))?;
+    Ok((spec, name, bytes))
+}
+
+/// POST /api/import - Import a CSV or Parquet file into the recordings
+/// under a symbol and schema of the caller's choosing: either a multipart
+/// upload of `spec` (an [`ImportSpec`] as JSON) and `file`, or a JSON spec
+/// whose `path` names a file under `IMPORT_DIR`.
+pub async fn import_file(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+) -> Result<Json<ImportReport>, Response> {
+    let recordings = recordings(&state).map_err(IntoResponse::into_response)?;
+    let multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("multipart/form-data"));
+    let (spec, name, bytes) = if multipart {
+        let upload = Multipart::from_request(request, &())
+            .await
+            .map_err(IntoResponse::into_response)?;
+        import_upload(upload)
+            .await
+            .map_err(IntoResponse::into_response)?
+    } else {
+        let Json(spec) = Json::<ImportSpec>::from_request(request, &())
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let dir = state
+            .import_dir
+            .as_deref()
+            .ok_or(ImportError::PathsDisabled)
+            .map_err(IntoResponse::into_response)?;
+        let path = spec.path.clone().ok_or_else(|| {
+            bad_request("Send the file as a multipart upload or name it in 'path'".to_string())
+        })?;
+        let resolved = imports::resolve(dir, &path).map_err(IntoResponse::into_response)?;
+        let bytes = tokio::fs::read(&resolved)
+            .await
+            .map_err(|e| ImportError::Io(e).into_response())?;
+        (spec, Some(path), Bytes::from(bytes))
+    };
+    let report = compute(move || imports::import(&recordings, &spec, name.as_deref(), bytes))
+        .await?
+        .map_err(IntoResponse::into_response)?;
+    info!(
+        symbol = %report.symbol,
+        schema = %report.schema,
+        records = report.records,
+        skipped = report.skipped,
+        "Imported data"
+    );
+    Ok(Json(report))
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            UploadError::NotFound(_) => StatusCode::NOT_FOUND,
+            UploadError::Decode(_) | UploadError::Invalid(_) => StatusCode::BAD_REQUEST,
+        };
+        status_error((status, self.to_string()))
+    }
+}
+
+/// The `file` part of a DBN upload, with its name.
+async fn dbn_upload(mut multipart: Multipart) -> Result<(String, Bytes), UploadError> {
+    let invalid = |e: axum::extract::multipart::MultipartError| UploadError::Invalid(e.body_text());
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        if field.name() == Some("file") {
+            let name = field.file_name().unwrap_or("upload.dbn").to_string();
+            return Ok((name, field.bytes().await.map_err(invalid)?));
+        }
+    }
+    Err(UploadError::Invalid("Missing the 'file' part".to_string()))
+}
+
+/// POST /api/uploads - Upload a `.dbn` or `.dbn.zst` file as a multipart
+/// `file` part to view it. It's decoded and held for the caller's API key
+/// until it expires, and served by `POST /api/uploads/:id/historical`.
+pub async fn upload_dbn(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<UploadInfo>), Response> {
+    let (name, bytes) = dbn_upload(multipart)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let size = bytes.len() as u64;
+    let decoded = compute(move || databento_service::decode_file(&bytes, MAX_UPLOAD_RECORDS))
+        .await?
+        .map_err(IntoResponse::into_response)?;
+    let info = state
+        .uploads
+        .insert(&key_id, &name, size, decoded, Utc::now());
+    info!(
+        key_id = %key_id,
+        upload = %info.id,
+        file = %info.file_name,
+        records = info.records,
+        "Decoded DBN upload"
+    );
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+/// GET /api/uploads - The caller's uploads, oldest first.
+pub async fn list_uploads(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+) -> Json<Vec<UploadInfo>> {
+    Json(state.uploads.list(&key_id))
+}
+
+/// POST /api/uploads/:id/historical - Records of an upload for a historical
+/// request body, as its provider would serve them. A request naming no
+/// symbols gets all of the file's.
+pub async fn upload_historical(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Path(id): Path<String>,
+    Json(mut req): Json<HistoricalRequest>,
+) -> Result<Json<HistoricalResponse>, Response> {
+    let upload = state
+        .uploads
+        .get(&key_id, &id)
+        .map_err(IntoResponse::into_response)?;
+    timeexpr::resolve_request(&mut req, Utc::now()).map_err(bad_request)?;
+    let response = upload
+        .get_historical(&req)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    Ok(Json(response))
+}
+
+/// DELETE /api/uploads/:id - Drop one of the caller's uploads now.
+pub async fn delete_upload(
+    State(state): State<Arc<AppState>>,
+    ApiKey(key_id): ApiKey,
+    Path(id): Path<String>,
+) -> Result<Json<UploadInfo>, UploadError> {
+    Ok(Json(state.uploads.remove(&key_id, &id)?))
+}
+
+/// Query parameters for the positions stream.
+#[derive(Debug, Deserialize)]
+pub struct PositionsParams {
+    /// Symbol type of the registered symbols (default: "parent")
+    #[serde(default)]
+    pub stype_in: SymbolType,
+    /// Least trade time between marks of one symbol (default: "1s"; "0s"
+    /// marks every trade)
+    #[serde(default = "default_mark_interval")]
+    pub interval: String,
+}
+
+fn default_mark_interval() -> String {
+    "1s".to_string()
+}
+
+/// GET /ws/positions - Stream PnL of the caller's registered positions,
+/// marked against live trades in their symbols.
+pub async fn positions_ws(
+    ws: WebSocketUpgrade,
+    ApiKey(key_id): ApiKey,
+    Query(params): Query<PositionsParams>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let symbols: Vec<String> = state
+        .positions
+        .list(&key_id)
+        .into_iter()
+        .map(|position| position.symbol)
+        .collect();
+    if symbols.is_empty() {
+        return bad_request("No positions registered; POST /api/positions first".to_string());
+    }
+    let stype_in = match checked_stype_in(params.stype_in, &symbols) {
+        Ok(stype_in) => stype_in,
+        Err(e) => return e.into_response(),
+    };
+    let interval_ns = match parse_lookback(&params.interval).map(|d| d.num_nanoseconds()) {
+        Ok(Some(ns)) if ns >= 0 => ns as u64,
+        Ok(_) => return bad_request(format!("Invalid interval '{}'", params.interval)),
+        Err(e) => return bad_request(e),
+    };
+    if let Err(e) = state.usage.check_live(&key_id) {
+        return e.into_response();
+    }
+
+    info!(symbols = ?symbols, key_id = %key_id, "Positions stream request");
+    let api_key = user_api_key();
+    ws.on_upgrade(move |socket| {
+        with_user_api_key(
+            api_key,
+            handle_positions_socket(
+                socket,
+                state,
+                key_id,
+                symbols,
+                stype_in,
+                PnlMarker::new(interval_ns),
+            ),
+        )
+    })
+    .into_response()
+}
+
+/// Mark positions against the trades of their symbols until either side
+/// closes. Positions changed while connected are picked up on their next
+/// trade; symbols registered later need a new connection.
+async fn handle_positions_socket(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    key_id: String,
+    symbols: Vec<String>,
+    stype_in: SymbolType,
+    mut marker: PnlMarker,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    if let Err(e) = live_entitled(&state, &key_id, &symbols, &Schema::Trades.as_str()).await {
+        warn!(key_id = %key_id, "Refusing positions subscription: {}", e);
+        close_with_error(&mut sender, close::NOT_ENTITLED, e.to_string()).await;
+        return;
+    }
+    let mut stream = match state
+        .service
+        .subscribe_live(
+            symbols.clone(),
+            Schema::Trades.as_str().to_string(),
+            stype_in,
+        )
+        .await
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Failed to subscribe: {}", e);
+            close_with_error(&mut sender, service_close_code(&e), e.to_string()).await;
+            return;
+        }
+    };
+
+    loop {
+        let msg = tokio::select! {
+            msg = stream.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            incoming = receiver.next() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let reply = match msg {
+            LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                ..
+            } => state
+                .positions
+                .get(&key_id, &symbol)
+                .and_then(|position| marker.mark(&position, ts_event_unix_ns, price_i64)),
+            LiveMessage::Error { .. } => Some(msg),
+            _ => None,
+        };
+        let Some(reply) = reply else {
+            continue;
+        };
+        let json = match serde_json::to_string(&reply) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Failed to serialize message: {}", e);
+                continue;
+            }
+        };
+        if sender.send(Message::Text(json)).await.is_err() {
+            break;
+        }
+    }
+
+    info!(symbols = ?symbols, "Positions stream disconnected");
+}
+
+/// Check `key_id` may subscribe to `symbols` live.
+async fn live_entitled(
+    state: &AppState,
+    key_id: &str,
+    symbols: &[String],
+    schema: &str,
+) -> Result<(), EntitlementError> {
+    let req = HistoricalRequest {
+        symbols: symbols.to_vec(),
+        schema: schema.to_string(),
+        ..Default::default()
+    };
+    entitlements::check(
+        state.authorizer.as_ref(),
+        state.service.as_ref(),
+        key_id,
+        AccessKind::Live,
+        &req,
+    )
+    .await
+}
+
+/// Close code for a live socket ended by `error`: see [`shared::close`].
+fn service_close_code(error: &ServiceError) -> u16 {
+    close::from_status(service_status(error).as_u16())
+}
+
+/// A close frame with `code`, its reason cut to fit.
+fn close_frame(code: u16, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: close::reason(reason).to_string().into(),
+    }))
+}
+
+/// Send `message` as a `LiveMessage::Error`, then close the socket with
+/// `code`.
+async fn close_with_error<S>(sender: &mut S, code: u16, message: String)
+where
+    S: futures::Sink<Message> + Unpin,
+{
+    let frame = close_frame(code, &message);
+    let json = serde_json::to_string(&LiveMessage::Error { message }).unwrap_or_default();
+    let _ = sender.send(Message::Text(json)).await;
+    let _ = sender.send(frame).await;
+}
+
+/// Send serialized JSON as one frame, or as `fragment` parts if it exceeds
+/// `max_frame_bytes`.
+async fn send_frames<S>(
+    sender: &mut S,
+    json: String,
+    max_frame_bytes: usize,
+    next_fragment_id: &mut u64,
+) -> Result<(), S::Error>
+where
+    S: futures::Sink<Message> + Unpin,
+{
+    if json.len() <= max_frame_bytes {
+        return sender.send(Message::Text(json)).await;
+    }
+
+    let id = *next_fragment_id;
+    *next_fragment_id += 1;
+
+    let parts = fragment::split(&json, id, max_frame_bytes);
+    tracing::debug!(
+        bytes = json.len(),
+        parts = parts.len(),
+        "Fragmenting message"
+    );
+    for part in parts {
+        // Fragments are plain strings and numbers; serialization cannot fail
+        let frame = serde_json::to_string(&part).unwrap_or_default();
+        sender.send(Message::Text(frame)).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::MockService;
+
+    #[tokio::test]
+    async fn test_serve_batch() {
+        let service = MockService::new();
+        let req = |symbol: &str, schema: &str, limit: u32| HistoricalRequest {
+            symbols: vec![symbol.to_string()],
+            schema: schema.to_string(),
+            start_rfc3339: "2024-06-04T00:00:00Z".to_string(),
+            end_rfc3339: "2024-06-05T00:00:00Z".to_string(),
+            limit,
+            ..Default::default()
+        };
+        let serve = |req: HistoricalRequest| {
+            let service = &service;
+            async move {
+                let served = service
+                    .get_historical(&req)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                Ok(serde_json::to_value(served).unwrap())
+            }
+        };
+
+        let results = serve_batch(
+            vec![
+                req("ES.FUT", "ohlcv-1m", 3),
+                req("ES.FUT", "ticks", 3),
+                req("CL.FUT", "trades", 5),
+            ],
+            serve,
+        )
+        .await
+        .unwrap();
+        let served = |result: &BatchResult| match result {
+            BatchResult::Ok(body) => (
+                body["schema"].as_str().unwrap().to_string(),
+                body["data"][0]["symbol"].as_str().unwrap().to_string(),
+                body["data"].as_array().unwrap().len(),
+            ),
+            BatchResult::Error(e) => panic!("Expected data, got {:?}", e),
+        };
+        // Results keep request order, the failed one in its place
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            served(&results[0]),
+            ("ohlcv-1m".to_string(), "ES.FUT".to_string(), 3)
+        );
+        match &results[1] {
+            BatchResult::Error(e) => assert_eq!(e.code, 400),
+            BatchResult::Ok(_) => panic!("Expected an error for an unknown schema"),
+        }
+        assert_eq!(
+            served(&results[2]),
+            ("trades".to_string(), "CL.FUT".to_string(), 5)
+        );
+
+        let too_many = vec![req("ES.FUT", "trades", 1); MAX_BATCH_REQUESTS + 1];
+        let response = serve_batch(too_many, serve).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_live_trackers_refuse_malformed_windows() {
+        let params = |query: &str| {
+            let uri: axum::http::Uri = format!("/ws/live?{}", query).parse().unwrap();
+            Query::<LiveParams>::try_from_uri(&uri).unwrap().0
+        };
+        assert!(live_trackers(&params("imbalance_windows=5s,1m&roll_window=15m")).is_ok());
+
+        for (query, named) in [
+            ("imbalance_windows=5x", "imbalance_windows"),
+            ("imbalance_windows=2h", "imbalance_windows"),
+            ("roll_window=soon", "roll_window"),
+        ] {
+            let response = match live_trackers(&params(query)) {
+                Ok(_) => panic!("{} was accepted", query),
+                Err(e) => bad_request(e),
+            };
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+            assert!(error.error.starts_with(named), "{}", error.error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pick_resolution() {
+        let service = MockService::new();
+        let req = |start: &str, end: &str, limit: u32| HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "trades".to_string(),
+            start_rfc3339: start.to_string(),
+            end_rfc3339: end.to_string(),
+            limit,
+            auto_resolution: true,
+            ..Default::default()
+        };
+        let pick = |req: HistoricalRequest, allowed: &'static [Schema]| {
+            let service = &service;
+            async move {
+                pick_resolution(&req, &AUTO_SCHEMAS, |candidate| async move {
+                    let schema: Schema = candidate.schema.parse().unwrap();
+                    match allowed.contains(&schema) {
+                        true => service.count_records(&candidate).await.map(Some),
+                        false => Ok(None),
+                    }
+                })
+                .await
+                .unwrap()
+            }
+        };
+        let all = &AUTO_SCHEMAS;
+
+        // The mock serves 1,000 trades over any range
+        let (served, resolution, _) = pick(
+            req("2024-06-04T14:00:00Z", "2024-06-04T14:05:00Z", 1_000),
+            all,
+        )
+        .await;
+        assert_eq!(
+            (served.schema.as_str(), resolution.as_str()),
+            ("trades", "trades")
+        );
+        let (_, resolution, _) = pick(
+            req("2024-06-04T14:00:00Z", "2024-06-04T14:05:00Z", 500),
+            all,
+        )
+        .await;
+        assert_eq!(resolution, "ohlcv-1s");
+        let (_, resolution, _) = pick(
+            req("2024-06-04T14:00:00Z", "2024-06-04T15:00:00Z", 500),
+            all,
+        )
+        .await;
+        assert_eq!(resolution, "ohlcv-1m");
+
+        // A day's 1,440 minute bars are merged into hourly ones
+        let (served, resolution, limited) = pick(
+            req("2024-06-04T00:00:00Z", "2024-06-05T00:00:00Z", 500),
+            all,
+        )
+        .await;
+        assert_eq!(
+            (served.schema.as_str(), resolution.as_str()),
+            ("ohlcv-1m", "ohlcv-1h")
+        );
+        assert_eq!((served.limit, limited), (1_440, false));
+
+        // Minute bars are fetched by their own count, not a finer schema's
+        let (served, _, _) = pick(
+            req("2024-06-04T00:00:00Z", "2024-06-05T00:00:00Z", 500),
+            &[Schema::Trades],
+        )
+        .await;
+        assert_eq!(served.limit as u64, MAX_RESAMPLED_BARS);
+
+        // Years of minute bars are more than are merged
+        let (served, _, limited) = pick(
+            req("2020-01-01T00:00:00Z", "2024-01-01T00:00:00Z", 500),
+            &[Schema::Ohlcv1M],
+        )
+        .await;
+        assert_eq!((served.limit as u64, limited), (MAX_RESAMPLED_BARS, true));
+    }
 }
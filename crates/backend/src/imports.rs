@@ -0,0 +1,277 @@
+//! Importing third-party CSV and Parquet files into the recordings.
+//!
+//! `POST /api/import` takes a file, uploaded or named under `IMPORT_DIR`,
+//! and an [`ImportSpec`] mapping its columns onto a schema's records. The
+//! records are stored under the spec's symbol as Parquet partitions beside
+//! the recorded ones, so `POST /api/recordings/query` serves them the same
+//! way.
+
+use crate::recordings::{RecordingError, Recordings};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    Date32Type, Decimal128Type, DecimalType, Float32Type, Float64Type, Int16Type, Int32Type,
+    Int64Type, Int8Type, TimestampMicrosecondType, TimestampMillisecondType,
+    TimestampNanosecondType, TimestampSecondType, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow_array::{Array, ArrowPrimitiveType};
+use arrow_schema::{DataType, TimeUnit};
+use axum::body::Bytes;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use shared::imports::{parse_csv, to_records, ImportFormat, ImportReport, ImportSpec, Table};
+use std::path::{Path, PathBuf};
+
+/// Largest file accepted as an upload; bigger files can be named under
+/// `IMPORT_DIR` instead.
+pub const MAX_UPLOAD_BYTES: usize = 512 * 1024 * 1024;
+
+/// Error type for imports.
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Importing files by path is off; set IMPORT_DIR or upload the file")]
+    PathsDisabled,
+    #[error("Import path '{0}' isn't a file under the import directory")]
+    Path(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[error(transparent)]
+    Recording(#[from] RecordingError),
+}
+
+/// `path` resolved under `dir`, refusing anything outside it.
+pub fn resolve(dir: &Path, path: &str) -> Result<PathBuf, ImportError> {
+    let outside = || ImportError::Path(path.to_string());
+    let dir = dir.canonicalize()?;
+    let resolved = dir.join(path).canonicalize().map_err(|_| outside())?;
+    if !resolved.starts_with(&dir) || !resolved.is_file() {
+        return Err(outside());
+    }
+    Ok(resolved)
+}
+
+/// The cells of a primitive column as text, with nulls empty.
+fn cells<T: ArrowPrimitiveType>(
+    column: &dyn Array,
+    format: impl Fn(T::Native) -> String,
+) -> Vec<String> {
+    column
+        .as_primitive::<T>()
+        .iter()
+        .map(|v| v.map(&format).unwrap_or_default())
+        .collect()
+}
+
+/// The cells of a Parquet column as text, `None` for types that can't be
+/// imported. Timestamps become nanoseconds since the epoch and dates
+/// `YYYY-MM-DD`.
+fn column_text(column: &dyn Array) -> Option<Vec<String>> {
+    let nanos = |scale: i64| move |v: i64| v.saturating_mul(scale).to_string();
+    Some(match column.data_type() {
+        DataType::Int8 => cells::<Int8Type>(column, |v| v.to_string()),
+        DataType::Int16 => cells::<Int16Type>(column, |v| v.to_string()),
+        DataType::Int32 => cells::<Int32Type>(column, |v| v.to_string()),
+        DataType::Int64 => cells::<Int64Type>(column, |v| v.to_string()),
+        DataType::UInt8 => cells::<UInt8Type>(column, |v| v.to_string()),
+        DataType::UInt16 => cells::<UInt16Type>(column, |v| v.to_string()),
+        DataType::UInt32 => cells::<UInt32Type>(column, |v| v.to_string()),
+        DataType::UInt64 => cells::<UInt64Type>(column, |v| v.to_string()),
+        DataType::Float32 => cells::<Float32Type>(column, |v| v.to_string()),
+        DataType::Float64 => cells::<Float64Type>(column, |v| v.to_string()),
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            cells::<TimestampSecondType>(column, nanos(1_000_000_000))
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            cells::<TimestampMillisecondType>(column, nanos(1_000_000))
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            cells::<TimestampMicrosecondType>(column, nanos(1_000))
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            cells::<TimestampNanosecondType>(column, nanos(1))
+        }
+        DataType::Date32 => cells::<Date32Type>(column, |days| {
+            chrono::DateTime::from_timestamp(i64::from(days) * 86_400, 0)
+                .map(|t| t.date_naive().to_string())
+                .unwrap_or_default()
+        }),
+        DataType::Decimal128(precision, scale) => {
+            let (precision, scale) = (*precision, *scale);
+            cells::<Decimal128Type>(column, |v| {
+                Decimal128Type::format_decimal(v, precision, scale)
+            })
+        }
+        DataType::Utf8 => column
+            .as_string::<i32>()
+            .iter()
+            .map(|v| v.unwrap_or_default().to_string())
+            .collect(),
+        DataType::LargeUtf8 => column
+            .as_string::<i64>()
+            .iter()
+            .map(|v| v.unwrap_or_default().to_string())
+            .collect(),
+        _ => return None,
+    })
+}
+
+/// Read a Parquet file as text cells. Columns of types that can't be
+/// imported, such as lists, are left empty rather than failing the file,
+/// as they needn't be mapped.
+pub fn parquet_table(bytes: Bytes) -> Result<Table, ImportError> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+    let columns: Vec<String> = builder
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.name().clone())
+        .collect();
+    let mut table = Table {
+        columns,
+        rows: Vec::new(),
+    };
+    for batch in builder.build()? {
+        let batch = batch?;
+        let cells: Vec<Vec<String>> = batch
+            .columns()
+            .iter()
+            .map(|column| column_text(column.as_ref()).unwrap_or_default())
+            .collect();
+        for row in 0..batch.num_rows() {
+            table.rows.push(
+                cells
+                    .iter()
+                    .map(|column| column.get(row).cloned().unwrap_or_default())
+                    .collect(),
+            );
+        }
+    }
+    Ok(table)
+}
+
+/// Import `bytes`, a file named `file_name`, into `recordings` as `spec`
+/// says.
+pub fn import(
+    recordings: &Recordings,
+    spec: &ImportSpec,
+    file_name: Option<&str>,
+    bytes: Bytes,
+) -> Result<ImportReport, ImportError> {
+    let format = spec
+        .format
+        .or_else(|| file_name.and_then(ImportFormat::from_file_name))
+        .ok_or_else(|| {
+            ImportError::Invalid(
+                "Unknown file format: name a .csv or .parquet file or set 'format'".to_string(),
+            )
+        })?;
+    let table = match format {
+        ImportFormat::Csv => {
+            let text = std::str::from_utf8(&bytes)
+                .map_err(|e| ImportError::Invalid(format!("CSV isn't UTF-8: {}", e)))?;
+            parse_csv(text, spec.delimiter.unwrap_or(',')).map_err(ImportError::Invalid)?
+        }
+        ImportFormat::Parquet => parquet_table(bytes)?,
+    };
+    let imported = to_records(&table, spec).map_err(ImportError::Invalid)?;
+    let records = imported.response.len() as u64;
+    let partitions = recordings.store(imported.response)?;
+    Ok(ImportReport {
+        symbol: spec.symbol.clone(),
+        schema: spec.schema.clone(),
+        records,
+        skipped: imported.skipped,
+        errors: imported.errors,
+        partitions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::write_compressed_parquet;
+    use shared::imports::ColumnMapping;
+    use shared::{HistoricalRequest, HistoricalResponse, TradeRecord};
+
+    #[test]
+    fn test_import() {
+        let dir = std::env::temp_dir().join(format!("sf-imports-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let recordings = Recordings::open(dir.join("recordings")).unwrap();
+        let spec = ImportSpec {
+            symbol: "MYDATA".to_string(),
+            schema: "trades".to_string(),
+            path: None,
+            format: None,
+            columns: ColumnMapping {
+                ts_event: "time".to_string(),
+                ..ColumnMapping::default()
+            },
+            time_format: Default::default(),
+            fixed_point: false,
+            delimiter: None,
+        };
+        let csv = "time,price,size\n\
+                   2024-01-02T23:59:59Z,4500.25,2\n\
+                   2024-01-03T00:00:00Z,4500.5,1\n";
+        let report = import(&recordings, &spec, Some("ticks.csv"), Bytes::from(csv)).unwrap();
+        assert_eq!(report.records, 2);
+        assert_eq!(report.partitions.len(), 2);
+        assert_eq!(
+            report.partitions[0].path,
+            "trades/2024-01-02/MYDATA.imported.parquet"
+        );
+        assert_eq!(report.partitions[0].symbol, "MYDATA");
+
+        // Importing a day again replaces its records in the imported span
+        let report = import(&recordings, &spec, Some("ticks.csv"), Bytes::from(csv)).unwrap();
+        assert_eq!(report.partitions[1].records, Some(1));
+        let query = HistoricalRequest {
+            symbols: vec!["MYDATA".to_string()],
+            schema: "trades".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-04T00:00:00Z".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(recordings.query(&query).unwrap().len(), 2);
+
+        // Parquet files from elsewhere map their own column names
+        let parquet = dir.join("other.parquet");
+        let trades = HistoricalResponse::Trades {
+            data: vec![TradeRecord {
+                ts_event_unix_ns: 1_704_067_200_000_000_000,
+                symbol: "ignored".to_string(),
+                price_i64: 4_400_000_000_000,
+                size_u32: 5,
+                quote: None,
+                sequence_u32: None,
+            }],
+        };
+        write_compressed_parquet(&trades, &parquet).unwrap();
+        let mapped = ImportSpec {
+            columns: ColumnMapping {
+                ts_event: "ts_event_unix_ns".to_string(),
+                price: "price_i64".to_string(),
+                size: "size_u32".to_string(),
+                ..ColumnMapping::default()
+            },
+            fixed_point: true,
+            ..spec.clone()
+        };
+        let bytes = Bytes::from(std::fs::read(&parquet).unwrap());
+        let report = import(&recordings, &mapped, Some("other.parquet"), bytes).unwrap();
+        assert_eq!(report.records, 1);
+        assert_eq!(recordings.query(&query).unwrap().len(), 3);
+
+        // Paths stay under the import directory
+        assert!(resolve(&dir, "other.parquet").is_ok());
+        assert!(resolve(&dir.join("recordings"), "../other.parquet").is_err());
+        assert!(import(&recordings, &spec, Some("ticks.xlsx"), Bytes::new()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+//! Latency of each stage of the live pipeline.
+//!
+//! Live messages are timed through four stages (see [`Stage`]), each into a
+//! histogram of its own, so a slow feed can be put down to the provider
+//! side, a subscription's transforms or the client's socket. Decoding is
+//! timed by the DataBento service, from a record arriving to its message
+//! leaving the session; the rest by each live socket. Histograms cover
+//! every session since startup or the last [`PipelineLatency::reset`], and
+//! are served summarised by `GET /api/metrics` and whole by
+//! `GET /api/debug/latency`.
+
+use crate::service::LiveStream;
+use futures::StreamExt;
+use shared::metrics::{LatencyBucket, StageLatency};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the histogram buckets, in microseconds; slower samples
+/// fall in one more bucket past the last.
+const BOUNDS_US: [u64; 16] = [
+    10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000,
+    500_000, 1_000_000,
+];
+
+/// A stage of the live pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// A provider record to a live message
+    Decode,
+    /// A subscription's transforms, from a message in to one out
+    Transform,
+    /// A message to JSON
+    Serialize,
+    /// JSON to the socket, fragments included
+    Send,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::Decode,
+        Stage::Transform,
+        Stage::Serialize,
+        Stage::Send,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Transform => "transform",
+            Stage::Serialize => "serialize",
+            Stage::Send => "send",
+        }
+    }
+}
+
+/// One stage's samples.
+struct Histogram {
+    counts: [AtomicU64; BOUNDS_US.len() + 1],
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+            total_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, elapsed: Duration) {
+        let us = elapsed.as_micros().min(u128::from(u64::MAX)) as u64;
+        let bucket = BOUNDS_US.partition_point(|bound| *bound < us);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for count in &self.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.total_us.store(0, Ordering::Relaxed);
+        self.max_us.store(0, Ordering::Relaxed);
+    }
+
+    fn stats(&self, stage: Stage, with_buckets: bool) -> StageLatency {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        let samples: u64 = counts.iter().sum();
+        let max_us = self.max_us.load(Ordering::Relaxed);
+        let mut cumulative = 0;
+        let buckets: Vec<LatencyBucket> = BOUNDS_US
+            .iter()
+            .zip(&counts)
+            .map(|(bound, count)| {
+                cumulative += count;
+                LatencyBucket {
+                    le_us: *bound,
+                    count: cumulative,
+                }
+            })
+            .collect();
+        // The first bound with the quantile's share of samples under it
+        let quantile = |q: f64| {
+            let wanted = (samples as f64 * q).ceil() as u64;
+            buckets
+                .iter()
+                .find(|bucket| bucket.count >= wanted)
+                .map_or(max_us, |bucket| bucket.le_us.min(max_us))
+        };
+        StageLatency {
+            stage: stage.as_str().to_string(),
+            samples,
+            mean_us: match samples {
+                0 => 0.0,
+                n => self.total_us.load(Ordering::Relaxed) as f64 / n as f64,
+            },
+            p50_us: quantile(0.5),
+            p90_us: quantile(0.9),
+            p99_us: quantile(0.99),
+            max_us,
+            buckets: if with_buckets { buckets } else { Vec::new() },
+        }
+    }
+}
+
+/// A latency histogram per [`Stage`], shared by every live session.
+pub struct PipelineLatency {
+    stages: [Histogram; Stage::ALL.len()],
+}
+
+impl Default for PipelineLatency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineLatency {
+    pub fn new() -> Self {
+        Self {
+            stages: std::array::from_fn(|_| Histogram::new()),
+        }
+    }
+
+    fn histogram(&self, stage: Stage) -> &Histogram {
+        &self.stages[stage as usize]
+    }
+
+    /// Count one message taking `elapsed` in `stage`.
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        self.histogram(stage).record(elapsed);
+    }
+
+    /// Each stage's percentiles, for `GET /api/metrics`.
+    pub fn summary(&self) -> Vec<StageLatency> {
+        Stage::ALL
+            .iter()
+            .map(|stage| self.histogram(*stage).stats(*stage, false))
+            .collect()
+    }
+
+    /// Each stage's percentiles and histogram.
+    pub fn histograms(&self) -> Vec<StageLatency> {
+        Stage::ALL
+            .iter()
+            .map(|stage| self.histogram(*stage).stats(*stage, true))
+            .collect()
+    }
+
+    /// Drop every sample, to time from now on.
+    pub fn reset(&self) {
+        for histogram in &self.stages {
+            histogram.reset();
+        }
+    }
+}
+
+/// Times the transforms between [`input`](Self::input) and
+/// [`output`](Self::output) of one subscription: each message out is timed
+/// from the latest message in. Messages the transforms send on a timer,
+/// with nothing new in, aren't timed, and of several out of one message in
+/// only the first is.
+pub struct TransformTimer {
+    latency: Arc<PipelineLatency>,
+    arrived: Arc<Mutex<Option<Instant>>>,
+}
+
+impl TransformTimer {
+    pub fn new(latency: Arc<PipelineLatency>) -> Self {
+        Self {
+            latency,
+            arrived: Arc::default(),
+        }
+    }
+
+    /// `stream`, noting when each message leaves it for the transforms.
+    pub fn input(&self, stream: LiveStream) -> LiveStream {
+        let arrived = self.arrived.clone();
+        Box::pin(stream.inspect(move |_| {
+            *arrived.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        }))
+    }
+
+    /// `stream`, the transforms' output, timing each message.
+    pub fn output(self, stream: LiveStream) -> LiveStream {
+        let TransformTimer { latency, arrived } = self;
+        Box::pin(stream.inspect(move |_| {
+            let at = arrived.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if let Some(at) = at {
+                latency.record(Stage::Transform, at.elapsed());
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::LiveMessage;
+
+    #[test]
+    fn test_histograms() {
+        let latency = PipelineLatency::new();
+        for us in [5, 40, 40, 80, 3_000_000] {
+            latency.record(Stage::Send, Duration::from_micros(us));
+        }
+        latency.record(Stage::Decode, Duration::from_micros(7));
+
+        let stats = latency.histograms();
+        assert_eq!(
+            stats.iter().map(|s| s.stage.as_str()).collect::<Vec<_>>(),
+            ["decode", "transform", "serialize", "send"]
+        );
+        let send = &stats[3];
+        assert_eq!((send.samples, send.max_us), (5, 3_000_000));
+        assert_eq!(send.mean_us, 600_033.0);
+        // 5 <= 10, both 40s <= 50, 80 <= 100; the slowest is over every bound
+        assert_eq!((send.p50_us, send.p90_us), (50, 3_000_000));
+        assert_eq!(
+            send.buckets[0],
+            LatencyBucket {
+                le_us: 10,
+                count: 1
+            }
+        );
+        assert_eq!(send.buckets[3].count, 4);
+        assert_eq!(send.buckets.last().unwrap().count, 4);
+        // A bound past the largest sample is capped at it
+        assert_eq!(stats[0].p99_us, 7);
+        assert!(latency.summary()[3].buckets.is_empty());
+
+        latency.reset();
+        assert_eq!(latency.summary()[3].samples, 0);
+    }
+
+    #[tokio::test]
+    async fn test_transform_timer() {
+        let latency = Arc::new(PipelineLatency::new());
+        let timer = TransformTimer::new(latency.clone());
+        let message = || LiveMessage::Error {
+            message: String::new(),
+        };
+        let messages = futures::stream::iter(vec![message(), message(), message()]);
+        // Keeps every other message, sending an extra one first
+        let input = timer.input(Box::pin(messages));
+        let transformed = futures::stream::iter(vec![message()]).chain(
+            input
+                .enumerate()
+                .filter_map(|(i, msg)| async move { (i % 2 == 0).then_some(msg) }),
+        );
+        let output: Vec<_> = timer.output(Box::pin(transformed)).collect().await;
+
+        assert_eq!(output.len(), 3);
+        assert_eq!(latency.summary()[1].samples, 2);
+    }
+}
@@ -0,0 +1,53 @@
+//! Market Data Viewer backend library.
+//!
+//! Holds the service layer and HTTP handlers. The `backend` binary wires
+//! these into an Axum server; benches and tests use them directly.
+
+pub mod analytics;
+pub mod autostart;
+pub mod cache;
+pub mod calendar;
+pub mod cancel;
+pub mod clock;
+pub mod closes;
+pub mod conflate;
+pub mod connections;
+pub mod crypto;
+pub mod databento_service;
+pub mod egress;
+pub mod entitlements;
+pub mod export;
+pub mod fanout;
+pub mod frontend;
+pub mod gateways;
+pub mod handlers;
+pub mod imports;
+pub mod latency;
+pub mod mock_service;
+pub mod monitor;
+pub mod notify;
+pub mod paper;
+pub mod positions;
+pub mod prefetch;
+pub mod profiles;
+pub mod queries;
+pub mod querylog;
+pub mod queue;
+pub mod ranges;
+pub mod recordings;
+pub mod retention;
+pub mod rollups;
+pub mod routing;
+pub mod scenarios;
+pub mod scheduler;
+pub mod secrets;
+pub mod selftest;
+pub mod service;
+pub mod store;
+pub mod symbols;
+pub mod telemetry;
+pub mod tenants;
+pub mod uploads;
+pub mod usage;
+pub mod users;
+pub mod versions;
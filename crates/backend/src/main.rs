@@ -3,71 +3,1240 @@
 //! Axum server providing REST and WebSocket APIs for market data.
 //! Supports both mock mode (no API key) and live DataBento mode.
 
-mod databento_service;
-mod handlers;
-mod mock_service;
-mod service;
-
 use axum::{
-    routing::{get, post},
-    Router,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
-use databento_service::DatabentoService;
-use handlers::AppState;
-use mock_service::MockService;
-use service::MarketDataService;
-use std::{net::SocketAddr, sync::Arc};
+use backend::autostart::{self, Preset};
+use backend::cache::{CachedService, HistoricalCache, RedisCache};
+use backend::calendar::Calendar;
+use backend::cancel::InFlight;
+use backend::clock::{ClockGuard, ClockLimits};
+use backend::closes::PreviousCloses;
+use backend::connections::ConnectionRegistry;
+use backend::crypto::Sealer;
+use backend::databento_service::DatabentoService;
+use backend::egress::{self, EgressConfig, EgressFormat};
+use backend::entitlements::{AllowAll, Authorizer, DatasetGrants};
+use backend::fanout::RedisFanout;
+use backend::frontend::{self, Assets};
+use backend::gateways::{self, Gateway, GatewayPreference, Gateways};
+use backend::handlers::{self, AppState, WsLimits};
+use backend::imports;
+use backend::latency::PipelineLatency;
+use backend::mock_service::{Chaos, MessageRate, MockService, MAX_RATE_HZ};
+use backend::monitor::MonitoredService;
+use backend::notify::{Notifier, WebhookStore};
+use backend::paper::PaperDesk;
+use backend::positions::PositionBook;
+use backend::prefetch::{self, Prefetch};
+use backend::profiles::ProfileStore;
+use backend::queries::QueryStore;
+use backend::querylog::QueryLog;
+use backend::queue::FairQueue;
+use backend::ranges::RangePolicy;
+use backend::recordings::{recordable, Recordings, DBN_DIR};
+use backend::retention::{self, Directory, Files, Retention};
+use backend::rollups::{self, RollupStore};
+use backend::routing::SymbolRouter;
+use backend::scenarios::Scenarios;
+use backend::scheduler::{JobStore, Scheduler};
+use backend::secrets::{EncryptedFile, SecretBackend, Secrets, DATABENTO_API_KEY};
+use backend::selftest;
+use backend::service::MarketDataService;
+use backend::symbols::SymbolResolver;
+use backend::telemetry::{self, LogFormat, OtlpConfig};
+use backend::tenants::{self, TenantConfig};
+use backend::uploads::{self, Uploads};
+use backend::usage::{self, UsageTracker};
+use backend::users::{UserDirectory, UserStore};
+use backend::versions::{self, ApiVersion, Deprecations, VersionPolicy};
+use shared::fragment;
+use shared::sessions::SessionSpec;
+use shared::storage::RetentionPolicy;
+use shared::ticks::TickTable;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use tower_http::cors::{Any, CorsLayer};
-use tracing::{info, warn};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tower_http::trace::TraceLayer;
+use tracing::{error, info, warn};
 
 /// Server configuration.
+#[derive(Clone)]
 struct Config {
     host: String,
     port: u16,
+    /// Legacy shared key; imported into the secret store on first start
     databento_api_key: Option<String>,
+    /// Use DataBento even without a shared key, serving only users who
+    /// registered their own
+    databento_per_user: bool,
+    /// Symbol-to-dataset routing table (`pattern=DATASET;...`)
+    symbol_routes: Option<String>,
+    /// DataBento live gateways to choose among (`name=host:port;...`);
+    /// unset uses DataBento's default
+    live_gateways: Option<Vec<Gateway>>,
+    /// How live gateways are ranked: `latency` or `order`
+    live_gateway_preference: GatewayPreference,
+    /// Datasets each API key may fetch (`key=DATASET,DATASET;...`); unset
+    /// allows everything
+    entitlements: Option<String>,
+    /// Mock live message rate (msgs/sec); unset keeps the realistic pacing
+    mock_rate_hz: Option<u32>,
+    /// Mock burst rate (msgs/sec) layered on top of `mock_rate_hz`
+    mock_burst_hz: Option<u32>,
+    /// How long each mock burst lasts
+    mock_burst_ms: u64,
+    /// How often a mock burst starts
+    mock_burst_every_ms: u64,
+    /// Trading hours mock historical data is confined to
+    mock_session: Option<SessionSpec>,
+    /// Delays, drops, duplicates and error events injected into mock data
+    mock_chaos: Option<Chaos>,
+    /// WebSocket frame and inbound message size limits
+    ws_limits: WsLimits,
+    /// File where saved queries are persisted
+    queries_path: String,
+    /// File where scheduled jobs are persisted
+    jobs_path: String,
+    /// File where webhooks are persisted
+    webhooks_path: String,
+    /// Message bus live data is republished to (`nats://...` or `kafka://...`)
+    egress_url: Option<String>,
+    /// Subject/topic name prefix for egress
+    egress_prefix: String,
+    /// Comma-separated symbols to republish
+    egress_symbols: String,
+    /// Comma-separated schemas to republish
+    egress_schemas: String,
+    /// Egress serialization (`json` or `msgpack`)
+    egress_format: String,
+    /// Redis for the shared historical cache and live fan-out
+    redis_url: Option<String>,
+    /// How long cached historical responses live in Redis
+    redis_cache_ttl: Duration,
+    /// Directory scheduled jobs write their output under
+    data_dir: PathBuf,
+    /// File where per-key usage counters are persisted
+    usage_path: String,
+    /// File historical queries are logged to
+    query_log_path: String,
+    /// Monthly per-key quotas (`records_served=N,live_messages=N,cost_usd=X`)
+    usage_quotas: Option<String>,
+    /// File where user accounts are persisted
+    users_path: String,
+    /// Bearer token for user management endpoints; unset disables them
+    admin_token: Option<String>,
+    /// Master key (64 hex chars) sealing stored secrets
+    secrets_key: Option<String>,
+    /// Master key file, created on first use when `secrets_key` is unset
+    secrets_key_path: PathBuf,
+    /// Where server secrets are stored (`file` or `keychain`)
+    secrets_backend: String,
+    /// Encrypted secrets file for the `file` backend
+    secrets_path: PathBuf,
+    /// Keychain service name secrets are stored under
+    #[cfg_attr(not(feature = "keychain"), allow(dead_code))]
+    keychain_service: String,
+    /// Tenants file; each tenant is served under `/t/{name}`
+    tenants_path: Option<String>,
+    /// Directory holding each tenant's state
+    tenants_dir: PathBuf,
+    /// Prefix for this API's Redis keys; empty outside tenants
+    namespace: String,
+    /// Per-schema range limit overrides (`schema=span;...`)
+    max_ranges: Option<String>,
+    /// Sunset dates of deprecated API versions (`v1=2025-06-30;...`)
+    api_sunsets: Deprecations,
+    /// Tick size overrides (`ROOT=TICK;...;*=TICK`)
+    tick_sizes: Option<String>,
+    /// How long a symbol's resolution is cached
+    symbol_cache_ttl: Duration,
+    /// Directory live data is recorded to; unset disables recording
+    record_dir: Option<PathBuf>,
+    /// Comma-separated symbols to record
+    record_symbols: String,
+    /// Comma-separated schemas to record
+    record_schemas: String,
+    /// Also write the recorder's DataBento sessions out as DBN
+    record_dbn: bool,
+    /// Live subscriptions held from startup
+    autostart: Vec<Preset>,
+    /// Historical queries fetched before serving, to warm the caches
+    prefetch: Vec<Prefetch>,
+    /// Longest startup waits for the prefetches
+    prefetch_timeout: Duration,
+    /// Most upstream historical fetches run at once, across tenants
+    historical_concurrency: usize,
+    /// Directory imports may name files under; unset allows uploads only
+    import_dir: Option<PathBuf>,
+    /// Built frontend to serve instead of the one compiled in, if any
+    ui_dir: Option<PathBuf>,
+    /// How long uploaded DBN files are held
+    upload_ttl: Duration,
+    /// Limits on recorded data
+    record_retention: Option<RetentionPolicy>,
+    /// Limits on scheduled job output
+    data_retention: Option<RetentionPolicy>,
+    /// Bar widths rolled up per symbol; empty disables rollups
+    rollup_intervals: Vec<u64>,
+    /// Bars kept per symbol and width
+    rollup_max_bars: usize,
+    /// Where rollups are stored between restarts
+    rollups_path: String,
+    /// Session hours volume profiles and day summaries are kept over
+    profile_session: SessionSpec,
+    /// Tolerances for timestamps ahead of the server clock
+    clock_limits: ClockLimits,
+    /// Ticks paper market orders fill away from the trade price
+    paper_slippage_ticks: u32,
+    /// Economic calendar file or URL (iCalendar or JSON)
+    calendar_source: Option<String>,
+    /// How long before a release its `upcoming` alert is sent
+    calendar_lead: Duration,
+    /// How often the calendar is reloaded
+    calendar_refresh: Duration,
+    /// Live silence (seconds) that marks the provider degraded; 0 disables
+    live_silence_secs: u64,
+    /// Log line format (`text` or `json`)
+    log_format: LogFormat,
+    /// OTLP collector spans are exported to
+    otlp: Option<OtlpConfig>,
 }
 
 impl Config {
     fn from_env() -> Self {
         Self {
             host: std::env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
-            port: std::env::var("PORT")
-                .ok()
-                .and_then(|p| p.parse().ok())
-                .unwrap_or(3001),
+            port: env_parse("PORT").unwrap_or(3001),
             databento_api_key: std::env::var("DATABENTO_API_KEY").ok(),
+            databento_per_user: env_parse("DATABENTO_PER_USER").unwrap_or(false),
+            symbol_routes: std::env::var("SYMBOL_ROUTES").ok(),
+            entitlements: std::env::var("ENTITLEMENTS").ok(),
+            live_gateways: std::env::var("LIVE_GATEWAYS").ok().map(|spec| {
+                gateways::parse(&spec).unwrap_or_else(|e| panic!("Invalid LIVE_GATEWAYS: {}", e))
+            }),
+            live_gateway_preference: std::env::var("LIVE_GATEWAY_PREFERENCE")
+                .map(|p| {
+                    p.parse()
+                        .unwrap_or_else(|e| panic!("Invalid LIVE_GATEWAY_PREFERENCE: {}", e))
+                })
+                .unwrap_or_default(),
+            mock_rate_hz: env_parse("MOCK_RATE_HZ"),
+            mock_burst_hz: env_parse("MOCK_BURST_HZ"),
+            mock_burst_ms: env_parse("MOCK_BURST_MS").unwrap_or(500),
+            mock_burst_every_ms: env_parse("MOCK_BURST_EVERY_MS").unwrap_or(5_000),
+            mock_session: std::env::var("MOCK_SESSION").ok().map(|spec| {
+                spec.parse()
+                    .unwrap_or_else(|e| panic!("Invalid MOCK_SESSION: {}", e))
+            }),
+            mock_chaos: std::env::var("MOCK_CHAOS").ok().map(|spec| {
+                spec.parse()
+                    .unwrap_or_else(|e| panic!("Invalid MOCK_CHAOS: {}", e))
+            }),
+            ws_limits: WsLimits {
+                max_frame_bytes: env_parse("WS_MAX_FRAME_BYTES")
+                    .unwrap_or(WsLimits::default().max_frame_bytes)
+                    .max(fragment::MIN_FRAME_BYTES),
+                max_inbound_bytes: env_parse("WS_MAX_INBOUND_BYTES")
+                    .unwrap_or(WsLimits::default().max_inbound_bytes),
+                snapshot_bars: env_parse("LIVE_SNAPSHOT_BARS")
+                    .unwrap_or(WsLimits::default().snapshot_bars),
+            },
+            queries_path: std::env::var("QUERIES_PATH")
+                .unwrap_or_else(|_| "saved_queries.json".to_string()),
+            jobs_path: std::env::var("JOBS_PATH").unwrap_or_else(|_| "jobs.json".to_string()),
+            webhooks_path: std::env::var("WEBHOOKS_PATH")
+                .unwrap_or_else(|_| "webhooks.json".to_string()),
+            egress_url: std::env::var("EGRESS_URL").ok(),
+            egress_prefix: std::env::var("EGRESS_PREFIX")
+                .unwrap_or_else(|_| "marketdata".to_string()),
+            egress_symbols: std::env::var("EGRESS_SYMBOLS")
+                .unwrap_or_else(|_| "ES.FUT".to_string()),
+            egress_schemas: std::env::var("EGRESS_SCHEMAS")
+                .unwrap_or_else(|_| "trades".to_string()),
+            egress_format: std::env::var("EGRESS_FORMAT").unwrap_or_else(|_| "json".to_string()),
+            redis_url: std::env::var("REDIS_URL").ok(),
+            redis_cache_ttl: Duration::from_secs(env_parse("REDIS_CACHE_TTL_SECS").unwrap_or(3600)),
+            data_dir: std::env::var("DATA_DIR")
+                .unwrap_or_else(|_| "data".to_string())
+                .into(),
+            usage_path: std::env::var("USAGE_PATH").unwrap_or_else(|_| "usage.json".to_string()),
+            usage_quotas: std::env::var("USAGE_QUOTAS").ok(),
+            query_log_path: std::env::var("QUERY_LOG_PATH")
+                .unwrap_or_else(|_| "query_log.jsonl".to_string()),
+            users_path: std::env::var("USERS_PATH").unwrap_or_else(|_| "users.json".to_string()),
+            admin_token: std::env::var("ADMIN_TOKEN").ok(),
+            secrets_key: std::env::var("SECRETS_KEY").ok(),
+            secrets_key_path: std::env::var("SECRETS_KEY_PATH")
+                .unwrap_or_else(|_| "secrets.key".to_string())
+                .into(),
+            secrets_backend: std::env::var("SECRETS_BACKEND")
+                .unwrap_or_else(|_| "file".to_string()),
+            secrets_path: std::env::var("SECRETS_PATH")
+                .unwrap_or_else(|_| "secrets.json".to_string())
+                .into(),
+            keychain_service: "signalfoundry".to_string(),
+            tenants_path: std::env::var("TENANTS_PATH").ok(),
+            tenants_dir: std::env::var("TENANTS_DIR")
+                .unwrap_or_else(|_| "tenants".to_string())
+                .into(),
+            namespace: String::new(),
+            max_ranges: std::env::var("MAX_RANGES").ok(),
+            api_sunsets: std::env::var("API_SUNSET")
+                .map(|spec| {
+                    Deprecations::parse(&spec)
+                        .unwrap_or_else(|e| panic!("Invalid API_SUNSET: {}", e))
+                })
+                .unwrap_or_default(),
+            tick_sizes: std::env::var("TICK_SIZES").ok(),
+            symbol_cache_ttl: Duration::from_secs(
+                env_parse("SYMBOL_CACHE_TTL_SECS").unwrap_or(86_400),
+            ),
+            record_dir: std::env::var("RECORD_DIR").ok().map(PathBuf::from),
+            record_symbols: std::env::var("RECORD_SYMBOLS")
+                .unwrap_or_else(|_| "ES.FUT".to_string()),
+            record_schemas: std::env::var("RECORD_SCHEMAS")
+                .unwrap_or_else(|_| "trades".to_string()),
+            record_dbn: env_parse("RECORD_DBN").unwrap_or(false),
+            autostart: std::env::var("AUTOSTART")
+                .map(|spec| {
+                    autostart::parse(&spec).unwrap_or_else(|e| panic!("Invalid AUTOSTART: {}", e))
+                })
+                .unwrap_or_default(),
+            prefetch: std::env::var("PREFETCH")
+                .map(|spec| {
+                    prefetch::parse(&spec).unwrap_or_else(|e| panic!("Invalid PREFETCH: {}", e))
+                })
+                .unwrap_or_default(),
+            prefetch_timeout: Duration::from_secs(env_parse("PREFETCH_TIMEOUT_SECS").unwrap_or(60)),
+            historical_concurrency: env_parse("HISTORICAL_CONCURRENCY").unwrap_or(8),
+            import_dir: std::env::var("IMPORT_DIR").ok().map(PathBuf::from),
+            ui_dir: std::env::var("UI_DIR").ok().map(PathBuf::from),
+            upload_ttl: Duration::from_secs(env_parse("UPLOAD_TTL_SECS").unwrap_or(3600)),
+            record_retention: std::env::var("RECORD_RETENTION").ok().map(|spec| {
+                retention::parse_policy(&spec)
+                    .unwrap_or_else(|e| panic!("Invalid RECORD_RETENTION: {}", e))
+            }),
+            data_retention: std::env::var("DATA_RETENTION").ok().map(|spec| {
+                retention::parse_policy(&spec)
+                    .unwrap_or_else(|e| panic!("Invalid DATA_RETENTION: {}", e))
+            }),
+            rollup_intervals: std::env::var("ROLLUP_INTERVALS")
+                .map(|spec| {
+                    rollups::parse_intervals(&spec)
+                        .unwrap_or_else(|e| panic!("Invalid ROLLUP_INTERVALS: {}", e))
+                })
+                .unwrap_or_default(),
+            rollup_max_bars: env_parse("ROLLUP_MAX_BARS").unwrap_or(1440),
+            rollups_path: std::env::var("ROLLUPS_PATH")
+                .unwrap_or_else(|_| "rollups.json".to_string()),
+            profile_session: std::env::var("PROFILE_SESSION")
+                .unwrap_or_else(|_| "cme".to_string())
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid PROFILE_SESSION: {}", e)),
+            clock_limits: ClockLimits {
+                max_future_end: env_parse("MAX_FUTURE_END_SECS")
+                    .map(Duration::from_secs)
+                    .unwrap_or(ClockLimits::default().max_future_end),
+                live_skew_warn: env_parse("CLOCK_SKEW_WARN_MS")
+                    .map(Duration::from_millis)
+                    .unwrap_or(ClockLimits::default().live_skew_warn),
+            },
+            paper_slippage_ticks: env_parse("PAPER_SLIPPAGE_TICKS").unwrap_or(0),
+            calendar_source: std::env::var("CALENDAR_SOURCE").ok(),
+            calendar_lead: Duration::from_secs(env_parse("CALENDAR_LEAD_SECS").unwrap_or(300)),
+            calendar_refresh: Duration::from_secs(
+                env_parse("CALENDAR_REFRESH_SECS").unwrap_or(3600).max(1),
+            ),
+            live_silence_secs: env_parse("LIVE_SILENCE_SECS").unwrap_or(60),
+            log_format: std::env::var("LOG_FORMAT")
+                .map(|format| {
+                    format
+                        .parse()
+                        .unwrap_or_else(|e| panic!("Invalid LOG_FORMAT {}", e))
+                })
+                .unwrap_or_default(),
+            otlp: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .ok()
+                .filter(|endpoint| !endpoint.is_empty())
+                .map(|endpoint| OtlpConfig {
+                    endpoint,
+                    service_name: std::env::var("OTEL_SERVICE_NAME")
+                        .unwrap_or_else(|_| "signalfoundry".to_string()),
+                }),
+        }
+    }
+
+    /// Settings for `tenant`'s API: its state under its own directory, its
+    /// overrides, and the rest as configured. The shared DataBento key and
+    /// egress stay with the default API.
+    fn for_tenant(&self, tenant: &TenantConfig) -> Self {
+        let dir = tenant.dir(&self.tenants_dir);
+        let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+        Self {
+            databento_api_key: None,
+            databento_per_user: tenant.databento_per_user,
+            queries_path: path("saved_queries.json"),
+            jobs_path: path("jobs.json"),
+            webhooks_path: path("webhooks.json"),
+            egress_url: None,
+            record_dir: None,
+            autostart: Vec::new(),
+            prefetch: Vec::new(),
+            import_dir: None,
+            data_dir: dir.join("data"),
+            usage_path: path("usage.json"),
+            query_log_path: path("query_log.jsonl"),
+            rollups_path: path("rollups.json"),
+            usage_quotas: tenant.usage_quotas.clone(),
+            users_path: path("users.json"),
+            admin_token: tenant.admin_token.clone(),
+            secrets_path: dir.join("secrets.json"),
+            keychain_service: format!("signalfoundry-{}", tenant.name),
+            max_ranges: tenant
+                .max_ranges
+                .clone()
+                .or_else(|| self.max_ranges.clone()),
+            namespace: tenant.namespace(),
+            ..self.clone()
+        }
+    }
+
+    /// Master key for secrets stored at rest.
+    fn sealer(&self) -> Sealer {
+        match &self.secrets_key {
+            Some(hex_key) => {
+                Sealer::from_hex(hex_key).unwrap_or_else(|e| panic!("Invalid SECRETS_KEY: {}", e))
+            }
+            None => Sealer::load_or_create(&self.secrets_key_path)
+                .unwrap_or_else(|e| panic!("Failed to load secrets key: {}", e)),
+        }
+    }
+
+    /// Storage for server secrets such as the shared DataBento key.
+    fn secret_backend(&self, sealer: &Sealer) -> Arc<dyn SecretBackend> {
+        match self.secrets_backend.as_str() {
+            "file" => Arc::new(EncryptedFile::new(&self.secrets_path, sealer.clone())),
+            #[cfg(feature = "keychain")]
+            "keychain" => Arc::new(backend::secrets::Keychain::new(&self.keychain_service)),
+            #[cfg(not(feature = "keychain"))]
+            "keychain" => panic!(
+                "Invalid SECRETS_BACKEND: {}",
+                backend::secrets::keychain_unavailable()
+            ),
+            other => panic!(
+                "Invalid SECRETS_BACKEND '{}': expected file or keychain",
+                other
+            ),
+        }
+    }
+
+    /// Range limits: the defaults plus `MAX_RANGES` overrides.
+    fn range_policy(&self) -> RangePolicy {
+        let mut policy = RangePolicy::default();
+        if let Some(spec) = &self.max_ranges {
+            policy
+                .apply_spec(spec)
+                .unwrap_or_else(|e| panic!("Invalid MAX_RANGES: {}", e));
+        }
+        policy
+    }
+
+    /// Tick sizes: the built-in CME table plus `TICK_SIZES` overrides.
+    fn tick_table(&self) -> TickTable {
+        let mut table = TickTable::with_defaults();
+        if let Some(spec) = &self.tick_sizes {
+            table
+                .apply_spec(spec)
+                .unwrap_or_else(|e| panic!("Invalid TICK_SIZES: {}", e));
+        }
+        table
+    }
+
+    /// Who may fetch what: `ENTITLEMENTS` grants, or everything to
+    /// everyone.
+    fn authorizer(&self) -> Arc<dyn Authorizer> {
+        match &self.entitlements {
+            Some(spec) => Arc::new(
+                DatasetGrants::from_spec(spec)
+                    .unwrap_or_else(|e| panic!("Invalid ENTITLEMENTS: {}", e)),
+            ),
+            None => Arc::new(AllowAll),
+        }
+    }
+
+    /// Dataset routing for the DataBento service.
+    fn symbol_router(&self) -> SymbolRouter {
+        match &self.symbol_routes {
+            Some(spec) => SymbolRouter::from_spec(spec)
+                .unwrap_or_else(|e| panic!("Invalid SYMBOL_ROUTES: {}", e)),
+            None => SymbolRouter::default(),
+        }
+    }
+
+    /// What the live data egress subscribes to.
+    fn egress_config(&self) -> EgressConfig {
+        EgressConfig {
+            symbols: list(&self.egress_symbols),
+            schemas: list(&self.egress_schemas),
+            format: self
+                .egress_format
+                .parse::<EgressFormat>()
+                .unwrap_or_else(|e| panic!("Invalid EGRESS_FORMAT: {}", e)),
+        }
+    }
+
+    /// What the recorder subscribes to; messages are recorded as JSON.
+    fn record_config(&self) -> EgressConfig {
+        EgressConfig {
+            symbols: list(&self.record_symbols),
+            schemas: list(&self.record_schemas),
+            format: EgressFormat::Json,
+        }
+    }
+
+    /// Live message pacing for the mock service.
+    fn mock_rate(&self) -> MessageRate {
+        match (self.mock_rate_hz, self.mock_burst_hz) {
+            (base, Some(burst_hz)) => MessageRate::Burst {
+                base_hz: base.unwrap_or(0),
+                burst_hz,
+                burst_ms: self.mock_burst_ms,
+                every_ms: self.mock_burst_every_ms,
+            },
+            (Some(hz), None) => MessageRate::Fixed { hz },
+            (None, None) => MessageRate::Realistic,
         }
     }
 }
 
-#[tokio::main]
-async fn main() {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "backend=info,tower_http=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+/// Items of a comma-separated list, trimmed.
+fn list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
 
-    let config = Config::from_env();
+/// Read and parse an environment variable, ignoring unset or malformed values.
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok().and_then(|v| v.parse().ok())
+}
+
+/// A provider service with what wraps and watches it.
+struct Provider {
+    secrets: Arc<Secrets>,
+    notifier: Arc<Notifier>,
+    monitor: Arc<MonitoredService>,
+    /// The monitored service, behind Redis when configured
+    service: Arc<dyn MarketDataService>,
+    cache: Option<Arc<dyn HistoricalCache>>,
+    recordings: Option<Arc<Recordings>>,
+    /// The recorder's DBN-writing service, which autostart presets use
+    dbn_tee: Option<Arc<dyn MarketDataService>>,
+    /// DataBento live gateways, when several are configured
+    gateways: Option<Arc<Gateways>>,
+    /// Live pipeline latency, decoding timed by the service
+    latency: Arc<PipelineLatency>,
+    /// Scenarios the mock service follows, in mock mode
+    scenarios: Option<Arc<Scenarios>>,
+}
+
+/// Open the secret store and the market data service it selects, with
+/// monitoring, webhooks, egress and the Redis cache and fan-out.
+async fn open_provider(
+    config: &Config,
+    sealer: &Sealer,
+    redis: Option<&(redis::Client, redis::aio::ConnectionManager)>,
+    self_test: bool,
+) -> Provider {
+    let secrets = Arc::new(
+        Secrets::open(config.secret_backend(sealer))
+            .await
+            .unwrap_or_else(|e| panic!("Failed to load secrets: {}", e)),
+    );
+    info!("Loaded secrets from {}", secrets.backend_name());
+
+    // Move a key given the old way into the secret store
+    if let Some(env_key) = &config.databento_api_key {
+        match secrets.get(DATABENTO_API_KEY) {
+            None => {
+                secrets
+                    .set(DATABENTO_API_KEY, env_key)
+                    .await
+                    .unwrap_or_else(|e| panic!("Failed to store DATABENTO_API_KEY: {}", e));
+                warn!(
+                    "Imported DATABENTO_API_KEY into the {}; remove it from the environment",
+                    secrets.backend_name()
+                );
+            }
+            Some(stored) if stored != *env_key => warn!(
+                "Ignoring DATABENTO_API_KEY: the {} holds a different key; \
+                 use PUT /api/admin/databento-key to rotate it",
+                secrets.backend_name()
+            ),
+            Some(_) => warn!(
+                "DATABENTO_API_KEY is already in the {}; remove it from the environment",
+                secrets.backend_name()
+            ),
+        }
+    }
 
     // Select service based on API key presence
-    let service: Arc<dyn MarketDataService> = if let Some(api_key) = config.databento_api_key {
+    let has_shared_key = secrets.get(DATABENTO_API_KEY).is_some();
+    let uses_databento = has_shared_key || config.databento_per_user;
+    let mut gateways = None;
+    let mut scenarios = None;
+    let latency = Arc::new(PipelineLatency::new());
+    let service: Arc<dyn MarketDataService> = if uses_databento {
         // Use DataBento service when API key is available
-        info!("DATABENTO_API_KEY is set - using DataBento service");
-        Arc::new(DatabentoService::new(api_key))
+        if has_shared_key {
+            info!("DataBento API key found - using DataBento service");
+        } else {
+            info!("DATABENTO_PER_USER is set - using users' own DataBento keys");
+        }
+        let router = config.symbol_router();
+        for route in router.routes() {
+            info!("Routing {} -> {}", route.pattern, route.dataset);
+        }
+        let mut databento = DatabentoService::new(secrets.clone())
+            .with_router(router.clone())
+            .with_latency(latency.clone());
+        if let Some(list) = &config.live_gateways {
+            let probed = Arc::new(Gateways::new(list.clone(), config.live_gateway_preference));
+            let mut datasets: Vec<String> =
+                router.routes().iter().map(|r| r.dataset.clone()).collect();
+            datasets.sort();
+            datasets.dedup();
+            if !self_test {
+                probed.probe(&datasets).await;
+            }
+            databento = databento.with_gateways(probed.clone());
+            gateways = Some(probed);
+        }
+        Arc::new(databento)
     } else {
-        info!("DATABENTO_API_KEY not set - running in MOCK mode");
-        info!("Set DATABENTO_API_KEY environment variable to enable live data");
-        Arc::new(MockService::new())
+        info!("No DataBento API key stored - running in MOCK mode");
+        info!("Set DATABENTO_API_KEY once to store a key and enable live data");
+
+        let rate = config.mock_rate();
+        if rate != MessageRate::Realistic {
+            info!(?rate, max_hz = MAX_RATE_HZ, "Mock load-test rate enabled");
+        }
+        let mut mock = MockService::new().with_rate(rate);
+        if let Some(sessions) = config.mock_session {
+            info!(%sessions, "Mock historical data follows session hours");
+            mock = mock.with_sessions(sessions);
+        }
+        if let Some(chaos) = config.mock_chaos {
+            warn!(
+                ?chaos,
+                "Mock chaos mode: data will be delayed, dropped and duplicated"
+            );
+            mock = mock.with_chaos(chaos);
+        }
+        let scheduled = Arc::new(Scenarios::new());
+        scenarios = Some(scheduled.clone());
+        Arc::new(mock.with_scenarios(scheduled))
     };
 
     info!("Using service: {}", service.name());
 
-    let state = Arc::new(AppState { service });
+    let webhooks = WebhookStore::open(&config.webhooks_path)
+        .unwrap_or_else(|e| panic!("Failed to load webhooks: {}", e));
+    info!(
+        "Loaded {} webhooks from {}",
+        webhooks.len(),
+        config.webhooks_path
+    );
+    let notifier = Arc::new(Notifier::new(webhooks, sealer.clone()));
+    match notifier.seal_plaintext() {
+        Ok(0) => {}
+        Ok(sealed) => info!("Sealed {} webhook secrets stored in plaintext", sealed),
+        Err(e) => panic!("Failed to seal webhook secrets: {}", e),
+    }
+    let mut monitor = MonitoredService::new(service, notifier.clone());
+    if config.live_silence_secs > 0 {
+        monitor = monitor.with_silence_threshold(Duration::from_secs(config.live_silence_secs));
+    }
+    let monitor = Arc::new(monitor);
+    monitor.start();
+    let service: Arc<dyn MarketDataService> = monitor.clone();
+
+    if let Some(url) = config.egress_url.as_ref().filter(|_| !self_test) {
+        let egress_config = config.egress_config();
+        match egress::connect(url, &config.egress_prefix).await {
+            Ok(publisher) => {
+                info!(
+                    "Republishing {:?} {:?} to {} ({})",
+                    egress_config.schemas,
+                    egress_config.symbols,
+                    url,
+                    publisher.name()
+                );
+                egress::spawn(service.clone(), publisher, egress_config);
+            }
+            Err(e) => error!("Live data egress disabled: {}", e),
+        }
+    }
+
+    // Presets subscribe through the DBN tee so their symbols are written out,
+    // since the recorder leaves them to the presets
+    let mut dbn_tee: Option<Arc<dyn MarketDataService>> = None;
+    let recordings = config
+        .record_dir
+        .as_ref()
+        .filter(|_| !self_test)
+        .map(|dir| {
+            let recordings = Arc::new(
+                Recordings::open(dir)
+                    .unwrap_or_else(|e| panic!("Failed to open recordings: {}", e)),
+            );
+            let record_config = config.record_config();
+            // Books would be dropped at compaction, so they aren't recorded
+            for schema in &record_config.schemas {
+                if !schema.parse().is_ok_and(|schema| recordable(&schema)) {
+                    panic!(
+                        "RECORD_SCHEMAS: '{}' can't be recorded: expected trades, ohlcv-1s or ohlcv-1m",
+                        schema
+                    );
+                }
+            }
+            info!(
+                "Recording {:?} {:?} to {}",
+                record_config.schemas,
+                record_config.symbols,
+                dir.display()
+            );
+            // The DBN tee gets sessions of its own, so only the recorder's
+            // are written out
+            let recorder: Arc<dyn MarketDataService> = match config.record_dbn {
+                true if uses_databento => {
+                    let router = config.symbol_router();
+                    let tee = dir.join(DBN_DIR);
+                    info!(
+                        "Writing recorded DataBento sessions as DBN to {}",
+                        tee.display()
+                    );
+                    let mut databento = DatabentoService::new(secrets.clone())
+                        .with_router(router)
+                        .with_dbn_tee(tee);
+                    if let Some(gateways) = &gateways {
+                        databento = databento.with_gateways(gateways.clone());
+                    }
+                    let databento: Arc<dyn MarketDataService> = Arc::new(databento);
+                    dbn_tee = Some(databento.clone());
+                    databento
+                }
+                true => {
+                    warn!("RECORD_DBN needs DataBento live data; not writing DBN");
+                    service.clone()
+                }
+                false => service.clone(),
+            };
+            // Symbols held by autostart presets are recorded from theirs
+            for record_config in autostart::uncovered(&config.autostart, &record_config) {
+                egress::spawn(recorder.clone(), recordings.clone(), record_config);
+            }
+            recordings.spawn_flusher();
+            recordings.spawn_compactor();
+            recordings
+        });
+
+    // Share the historical cache and live feeds with other instances
+    let mut cache: Option<Arc<dyn HistoricalCache>> = None;
+    let service: Arc<dyn MarketDataService> = match redis {
+        Some((client, conn)) => {
+            let fanout = RedisFanout::new(service, client.clone(), conn.clone())
+                .with_namespace(&config.namespace);
+            let redis_cache: Arc<dyn HistoricalCache> =
+                Arc::new(RedisCache::new(conn.clone()).with_namespace(&config.namespace));
+            cache = Some(redis_cache.clone());
+            Arc::new(CachedService::new(
+                Arc::new(fanout),
+                redis_cache,
+                config.redis_cache_ttl,
+            ))
+        }
+        None => service,
+    };
+
+    Provider {
+        secrets,
+        notifier,
+        monitor,
+        service,
+        cache,
+        recordings,
+        dbn_tee,
+        gateways,
+        latency,
+        scenarios,
+    }
+}
+
+/// Load the stores an API serves from and start its scheduler.
+fn open_state(
+    config: Config,
+    provider: Provider,
+    sealer: Sealer,
+    ticks: Arc<TickTable>,
+    calendar: Arc<Calendar>,
+    historical_queue: Arc<FairQueue>,
+) -> Arc<AppState> {
+    let Provider {
+        secrets,
+        notifier,
+        monitor,
+        service,
+        cache,
+        recordings,
+        dbn_tee,
+        gateways,
+        latency,
+        scenarios,
+        ..
+    } = provider;
+
+    let queries = QueryStore::open(&config.queries_path)
+        .unwrap_or_else(|e| panic!("Failed to load saved queries: {}", e));
+    info!(
+        "Loaded {} saved queries from {}",
+        queries.len(),
+        config.queries_path
+    );
+
+    let user_store = UserStore::open(&config.users_path)
+        .unwrap_or_else(|e| panic!("Failed to load users: {}", e));
+    info!(
+        "Loaded {} users from {}",
+        user_store.len(),
+        config.users_path
+    );
+    if config.admin_token.is_none() {
+        info!("ADMIN_TOKEN not set - user management disabled");
+    }
+    let users = Arc::new(UserDirectory::new(
+        user_store,
+        sealer,
+        config.admin_token.clone(),
+    ));
+
+    let jobs = JobStore::open(&config.jobs_path)
+        .unwrap_or_else(|e| panic!("Failed to load scheduled jobs: {}", e));
+    info!(
+        "Loaded {} scheduled jobs from {}, writing to {}",
+        jobs.len(),
+        config.jobs_path,
+        config.data_dir.display()
+    );
+    let ranges = config.range_policy();
+    let scheduler = Arc::new(
+        Scheduler::new(service.clone(), jobs, config.data_dir.clone()).with_ticks(ticks.clone()),
+    );
+    scheduler.start();
+    notifier.forward_job_runs(scheduler.subscribe());
+
+    let quotas = match &config.usage_quotas {
+        Some(spec) => usage::parse_quotas(spec).unwrap_or_else(|e| panic!("{}", e)),
+        None => Default::default(),
+    };
+    info!(?quotas, "Per-key monthly quotas");
+    let usage = Arc::new(
+        UsageTracker::open(&config.usage_path, quotas)
+            .unwrap_or_else(|e| panic!("Failed to load usage: {}", e)),
+    );
+    usage.spawn_flusher();
+
+    let query_log = QueryLog::open(&config.query_log_path)
+        .unwrap_or_else(|e| panic!("Failed to load query log: {}", e));
+    info!(
+        "Loaded {} logged queries from {}",
+        query_log.len(),
+        config.query_log_path
+    );
+
+    let rollups = match config.rollup_intervals.is_empty() {
+        true => Arc::new(RollupStore::disabled()),
+        false => {
+            let store = RollupStore::open(
+                &config.rollups_path,
+                config.rollup_intervals.clone(),
+                config.rollup_max_bars,
+            )
+            .unwrap_or_else(|e| panic!("Failed to load rollups: {}", e));
+            info!(
+                "Keeping {} rollups in {}",
+                config
+                    .rollup_intervals
+                    .iter()
+                    .map(|ns| rollups::label(*ns))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                config.rollups_path
+            );
+            let store = Arc::new(store);
+            store.spawn_flusher();
+            store
+        }
+    };
+    let closes = Arc::new(PreviousCloses::new(service.clone(), config.profile_session));
+    let profiles = Arc::new(ProfileStore::new(config.profile_session));
+    profiles.spawn_publisher(closes.clone());
+    if !config.autostart.is_empty() {
+        for preset in &config.autostart {
+            info!(
+                "Holding {} {:?} from startup",
+                preset.schema, preset.symbols
+            );
+        }
+        autostart::spawn(
+            dbn_tee.unwrap_or_else(|| service.clone()),
+            config.autostart.clone(),
+            rollups.clone(),
+            profiles.clone(),
+            recordings.clone(),
+        );
+    }
+
+    let mut retention = Retention::new();
+    if let Some(recordings) = &recordings {
+        retention = retention.with_subsystem(
+            "recordings",
+            recordings.dir().display().to_string(),
+            recordings.clone(),
+            config.record_retention,
+        );
+    }
+    let mut state_files = vec![
+        &config.queries_path,
+        &config.jobs_path,
+        &config.webhooks_path,
+        &config.usage_path,
+        &config.query_log_path,
+        &config.users_path,
+    ];
+    if rollups.enabled() {
+        state_files.push(&config.rollups_path);
+    }
+    let retention = Arc::new(
+        retention
+            .with_subsystem(
+                "exports",
+                config.data_dir.display().to_string(),
+                Arc::new(Directory(config.data_dir.clone())),
+                config.data_retention,
+            )
+            .with_subsystem(
+                "state",
+                state_files
+                    .iter()
+                    .map(|path| path.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                Arc::new(Files(state_files.iter().map(PathBuf::from).collect())),
+                None,
+            ),
+    );
+    retention.spawn();
+    let uploads = Arc::new(Uploads::new(config.upload_ttl));
+    uploads.spawn_expiry();
+
+    Arc::new(AppState {
+        service,
+        cached: cache.is_some(),
+        authorizer: config.authorizer(),
+        gateways,
+        historical_queue,
+        in_flight: Arc::new(InFlight::new()),
+        ws_limits: config.ws_limits,
+        queries,
+        scheduler,
+        notifier,
+        usage,
+        users,
+        secrets,
+        ticks: ticks.clone(),
+        connections: Arc::new(ConnectionRegistry::new()),
+        clock: Arc::new(ClockGuard::new(config.clock_limits)),
+        latency,
+        paper: Arc::new(PaperDesk::new(config.paper_slippage_ticks, ticks.clone())),
+        positions: Arc::new(PositionBook::new()),
+        calendar,
+        monitor,
+        query_log,
+        ranges,
+        symbols: SymbolResolver::new(ticks, config.symbol_cache_ttl),
+        rollups,
+        profiles,
+        closes,
+        recordings,
+        scenarios,
+        import_dir: config.import_dir.clone(),
+        uploads,
+        retention,
+        deprecations: config.api_sunsets.clone(),
+    })
+}
+
+/// The REST and WebSocket API served from `state`, with the REST API under
+/// each version's prefix.
+fn api_router(state: Arc<AppState>) -> Router {
+    let mut router = Router::new();
+    for version in ApiVersion::ALL {
+        let policy = VersionPolicy {
+            version,
+            sunset: state.deprecations.sunset(version),
+        };
+        let routes = rest_routes()
+            .layer(Extension(version))
+            .layer(middleware::from_fn_with_state(
+                policy,
+                versions::deprecation,
+            ));
+        router = router.nest(&version.prefix(), routes);
+    }
+    router
+        .route("/ws/live", get(handlers::live_ws))
+        .route("/ws/positions", get(handlers::positions_ws))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::user_context,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::cancellable,
+        ))
+        .with_state(state)
+}
+
+/// The REST API of one version, relative to its prefix.
+fn rest_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/health", get(handlers::health))
+        .route("/metrics", get(handlers::metrics))
+        .route("/debug/latency", get(handlers::debug_latency))
+        .route(
+            "/mock/scenarios",
+            get(handlers::list_scenarios)
+                .post(handlers::schedule_scenario)
+                .delete(handlers::clear_scenarios),
+        )
+        .route("/mock/scenarios/:id", delete(handlers::delete_scenario))
+        .route("/status", get(handlers::status))
+        .route("/version", get(handlers::version))
+        .route("/historical", post(handlers::historical))
+        .route("/historical/batch", post(handlers::historical_batch))
+        .route("/historical/combined", post(handlers::historical_combined))
+        .route("/historical/validate", post(handlers::validate_request))
+        .route("/validate", post(handlers::validate))
+        .route("/diff", post(handlers::diff))
+        .route("/usage", get(handlers::get_usage))
+        .route("/ticks", get(handlers::list_ticks))
+        .route("/ticks/:symbol", get(handlers::get_tick))
+        .route("/front-month/:symbol", get(handlers::front_month))
+        .route("/stats/intraday-profile", post(handlers::intraday_profile))
+        .route("/stats/returns", post(handlers::returns))
+        .route("/stats/correlation", post(handlers::correlation_matrix))
+        .route("/tca", post(handlers::tca))
+        .route("/calendar", get(handlers::calendar_events))
+        .route("/rollups/:symbol", get(handlers::rollup_bars))
+        .route("/leaderboard", get(handlers::leaderboard))
+        .route("/tape", get(handlers::tape))
+        .route("/profile/:symbol", get(handlers::volume_profile))
+        .route("/day-summary", get(handlers::day_summary))
+        .route("/book", get(handlers::book))
+        .route("/book/sample", post(handlers::book_sample))
+        .route("/book/activity", post(handlers::book_activity))
+        .route("/recordings", get(handlers::list_recordings))
+        .route("/recordings/query", post(handlers::query_recordings))
+        .route(
+            "/uploads",
+            get(handlers::list_uploads)
+                .post(handlers::upload_dbn)
+                .layer(DefaultBodyLimit::max(uploads::MAX_UPLOAD_BYTES)),
+        )
+        .route("/uploads/:id", delete(handlers::delete_upload))
+        .route("/uploads/:id/historical", post(handlers::upload_historical))
+        .route(
+            "/import",
+            post(handlers::import_file).layer(DefaultBodyLimit::max(imports::MAX_UPLOAD_BYTES)),
+        )
+        .route(
+            "/paper/orders",
+            get(handlers::list_paper_orders).post(handlers::submit_paper_order),
+        )
+        .route("/paper/orders/:id", delete(handlers::cancel_paper_order))
+        .route("/paper/positions", get(handlers::paper_positions))
+        .route(
+            "/positions",
+            get(handlers::list_positions)
+                .post(handlers::register_positions)
+                .delete(handlers::clear_positions),
+        )
+        .route(
+            "/users",
+            get(handlers::list_users).post(handlers::create_user),
+        )
+        .route("/users/:name", delete(handlers::delete_user))
+        .route("/admin/queries", get(handlers::admin_queries))
+        .route("/admin/storage", get(handlers::storage_report))
+        .route(
+            "/admin/recordings/compact",
+            post(handlers::compact_recordings),
+        )
+        .route("/admin/databento-key", put(handlers::rotate_databento_key))
+        .route("/me", get(handlers::me))
+        .route("/requests", get(handlers::list_requests))
+        .route("/requests/:request_id", delete(handlers::cancel_request))
+        .route(
+            "/me/databento-key",
+            put(handlers::set_databento_key).delete(handlers::clear_databento_key),
+        )
+        .route(
+            "/queries",
+            get(handlers::list_queries).post(handlers::save_query),
+        )
+        .route("/queries/:name/run", get(handlers::run_query))
+        .route("/jobs", get(handlers::list_jobs).post(handlers::add_job))
+        .route("/jobs/:name", delete(handlers::delete_job))
+        .route(
+            "/jobs/:name/run",
+            post(handlers::run_job).delete(handlers::cancel_job_run),
+        )
+        .route("/jobs/:name/runs", get(handlers::job_runs))
+        .route("/jobs/:name/manifest", get(handlers::job_manifest))
+        .route(
+            "/webhooks",
+            get(handlers::list_webhooks).post(handlers::add_webhook),
+        )
+        .route("/webhooks/:name", delete(handlers::delete_webhook))
+        .route("/webhooks/:name/test", post(handlers::test_webhook))
+        .route(
+            "/connections/:id/symbols",
+            get(handlers::connection_symbols),
+        )
+}
+
+#[tokio::main]
+async fn main() {
+    let config = Config::from_env();
+    // Initialize tracing
+    let telemetry = telemetry::init(config.log_format, config.otlp.as_ref());
+    if let Some(otlp) = config.otlp.as_ref().filter(|_| telemetry.is_exporting()) {
+        info!(
+            "Exporting spans to {} as {}",
+            otlp.endpoint, otlp.service_name
+        );
+    }
+    // Validate the deployment and exit instead of serving
+    let self_test = std::env::args().skip(1).any(|arg| arg == "--self-test");
+
+    let redis = match &config.redis_url {
+        Some(url) => {
+            let client = redis::Client::open(url.as_str())
+                .unwrap_or_else(|e| panic!("Invalid REDIS_URL: {}", e));
+            let conn = redis::aio::ConnectionManager::new(client.clone())
+                .await
+                .unwrap_or_else(|e| panic!("Failed to connect to Redis at {}: {}", url, e));
+            info!(
+                "Using Redis at {} for historical cache and live fan-out",
+                url
+            );
+            Some((client, conn))
+        }
+        None => None,
+    };
+
+    let sealer = config.sealer();
+    let provider = open_provider(&config, &sealer, redis.as_ref(), self_test).await;
+
+    if self_test {
+        let report = selftest::run(provider.service, provider.cache).await;
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => error!("Failed to serialize self-test report: {}", e),
+        }
+        std::process::exit(if report.passed { 0 } else { 1 });
+    }
+
+    let ticks = Arc::new(config.tick_table());
+    info!(
+        "Loaded tick sizes for {} symbol roots",
+        ticks.entries().len()
+    );
+    let calendar = Arc::new(Calendar::new(
+        config.calendar_source.clone(),
+        config.calendar_lead,
+    ));
+    calendar.start(config.calendar_refresh);
+    // Shared by the tenants, so the limit holds for the whole server
+    let historical_queue = Arc::new(FairQueue::new(config.historical_concurrency));
+
+    let tenants = match &config.tenants_path {
+        Some(path) => {
+            tenants::load(path).unwrap_or_else(|e| panic!("Failed to load tenants: {}", e))
+        }
+        None => Vec::new(),
+    };
+    let addr: SocketAddr = format!("{}:{}", config.host, config.port)
+        .parse()
+        .expect("Invalid address");
+
+    let state = open_state(
+        config.clone(),
+        provider,
+        sealer.clone(),
+        ticks.clone(),
+        calendar.clone(),
+        historical_queue.clone(),
+    );
+    // Warm the caches before anyone can ask
+    if !config.prefetch.is_empty() {
+        info!("Prefetching {} queries", config.prefetch.len());
+        let warm = prefetch::run(&state, &config.prefetch);
+        if tokio::time::timeout(config.prefetch_timeout, warm)
+            .await
+            .is_err()
+        {
+            warn!(
+                "Prefetching didn't finish in {:?}, serving anyway",
+                config.prefetch_timeout
+            );
+        }
+    }
+    let mut app = api_router(state);
+    for tenant in &tenants {
+        let tenant_config = config.for_tenant(tenant);
+        let dir = tenant.dir(&config.tenants_dir);
+        std::fs::create_dir_all(&dir)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {}", dir.display(), e));
+        info!(tenant = %tenant.name, "Serving tenant from {}", dir.display());
+
+        let provider = open_provider(&tenant_config, &sealer, redis.as_ref(), false).await;
+        let state = open_state(
+            tenant_config,
+            provider,
+            sealer.clone(),
+            ticks.clone(),
+            calendar.clone(),
+            historical_queue.clone(),
+        );
+        app = app.nest(&format!("/t/{}", tenant.name), api_router(state));
+    }
+
+    // The frontend answers whatever the APIs don't
+    let assets = config
+        .ui_dir
+        .clone()
+        .map(Assets::Dir)
+        .or_else(Assets::embedded);
+    if let Some(assets) = assets {
+        info!("Serving the frontend from {}", assets);
+        app = app.fallback_service(frontend::router(assets));
+    }
 
     // Configure CORS for local development
     let cors = CorsLayer::new()
@@ -75,23 +1244,31 @@ async fn main() {
         .allow_methods(Any)
         .allow_headers(Any);
 
-    // Build router
+    let app = app
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(telemetry::request_span)
+                .on_response(telemetry::log_response),
+        )
+        .layer(middleware::from_fn(telemetry::request_id))
+        .layer(cors);
+    // Unversioned API paths are rewritten to a version before routing
     let app = Router::new()
-        .route("/api/health", get(handlers::health))
-        .route("/api/historical", post(handlers::historical))
-        .route("/ws/live", get(handlers::live_ws))
-        .layer(cors)
-        .with_state(state);
-
-    let addr: SocketAddr = format!("{}:{}", config.host, config.port)
-        .parse()
-        .expect("Invalid address");
+        .fallback_service(app)
+        .layer(middleware::from_fn(versions::negotiate));
 
     info!("Starting server on http://{}", addr);
     info!("Health check: http://{}/api/health", addr);
     info!("Historical API: POST http://{}/api/historical", addr);
     info!("Live WebSocket: ws://{}/ws/live", addr);
+    for tenant in &tenants {
+        info!(
+            "Tenant {}: http://{}/t/{}/api",
+            tenant.name, addr, tenant.name
+        );
+    }
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
+    telemetry.shutdown();
 }
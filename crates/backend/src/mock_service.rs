@@ -1,68 +1,669 @@
 //! Mock implementation of MarketDataService for development without API key.
 
-use crate::service::{LiveStream, MarketDataService, ServiceError};
+use crate::scenarios::Scenarios;
+use crate::service::{
+    checked_stype_in, historical_bars, historical_cvd, LiveStream, MarketDataService, MboStream,
+    ServiceError,
+};
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Utc};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
+use shared::aggregate::{BarAlign, ClosedBarFeed, NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use shared::book::{BookSide, MboAction, MboRecord};
+use shared::depth::{DepthLevel, DepthUpdate, DEPTH_LEVELS};
+use shared::sessions::SessionSpec;
+use shared::ticks::{symbol_root, TickTable};
 use shared::{
-    HistoricalRequest, HistoricalResponse, LiveMessage, OhlcvRecord, Schema, TradeRecord,
+    HistoricalRequest, HistoricalResponse, LiveMessage, OhlcvRecord, QuoteAtTrade, Schema,
+    SymbolType, TradeRecord,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+
+/// Upper bound on the configurable live message rate.
+pub const MAX_RATE_HZ: u32 = 100_000;
+
+/// Pacing tick used when emitting at a configured rate.
+const PACING_TICK: Duration = Duration::from_millis(1);
+
+/// Instrument ID of the first subscribed symbol's mock front month.
+const MOCK_INSTRUMENT_ID: u32 = 5_000;
+
+/// Mock contract a parent or continuous symbol resolves to as of `now`:
+/// the quarterly contract of its root expiring next, e.g. `ES.FUT` to
+/// `ESZ4` in October 2024.
+fn mock_front_month(symbol: &str, now: DateTime<Utc>) -> String {
+    let root = symbol.split('.').next().unwrap_or(symbol);
+    let quarter = (now.month0() / 3) as usize;
+    format!(
+        "{}{}{}",
+        root,
+        ["H", "M", "U", "Z"][quarter],
+        now.year() % 10
+    )
+}
+
+/// When a mock symbol trades.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MockHours {
+    /// The service's sessions, if any
+    Configured,
+    /// A market's sessions, by [`SessionSpec`] name
+    Market(&'static str),
+    /// Around the clock, weekends included
+    Always,
+}
+
+/// How a kind of instrument trades in mock data: its price, tick and how
+/// far it moves, picked per symbol by [`MockProfile::of`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MockProfile {
+    base_price_i64: i64,
+    /// Also the quoted spread and the gap between book levels
+    tick_i64: i64,
+    /// Largest move between trades, in ticks
+    step_ticks: i64,
+    /// Largest move from a bar's open to its high or low, in ticks
+    bar_ticks: i64,
+    max_size_u32: u32,
+    hours: MockHours,
+    /// Whether parent and continuous symbols map to a front month
+    futures: bool,
+}
+
+/// ES-like, for futures roots other than treasuries.
+const INDEX_FUTURE: MockProfile = MockProfile {
+    base_price_i64: 5_000_000_000_000, // 5000.00
+    tick_i64: 250_000_000,
+    step_ticks: 2,
+    bar_ticks: 8,
+    max_size_u32: 50,
+    hours: MockHours::Configured,
+    futures: true,
+};
+
+/// ZN-like: a low-volatility treasury future on a 1/64 tick.
+const TREASURY: MockProfile = MockProfile {
+    base_price_i64: 110_500_000_000, // 110.50
+    tick_i64: 15_625_000,
+    step_ticks: 1,
+    bar_ticks: 4,
+    max_size_u32: 200,
+    hours: MockHours::Configured,
+    futures: true,
 };
-use std::time::Duration;
+
+/// AAPL-like, trading NYSE hours.
+const EQUITY: MockProfile = MockProfile {
+    base_price_i64: 190_000_000_000, // 190.00
+    tick_i64: 10_000_000,
+    step_ticks: 5,
+    bar_ticks: 25,
+    max_size_u32: 500,
+    hours: MockHours::Market("nyse"),
+    futures: false,
+};
+
+/// BTC-like, trading around the clock in large moves of a fine tick.
+const CRYPTO: MockProfile = MockProfile {
+    base_price_i64: 65_000_000_000_000, // 65000.00
+    tick_i64: 10_000_000,
+    step_ticks: 2_000,
+    bar_ticks: 10_000,
+    max_size_u32: 10,
+    hours: MockHours::Always,
+    futures: false,
+};
+
+/// Treasury futures roots on a tick the [`TREASURY`] tick is a multiple of.
+const TREASURY_ROOTS: [&str; 3] = ["ZT", "ZF", "ZN"];
+
+/// Coins and the quote currencies they pair with, as in `BTC`, `BTCUSD`
+/// or `BTC-USDT`.
+const CRYPTO_BASES: [&str; 3] = ["BTC", "ETH", "SOL"];
+const CRYPTO_QUOTES: [&str; 4] = ["", "USD", "USDT", "USDC"];
+
+impl MockProfile {
+    /// The profile `symbol` trades with: crypto pairs, then treasury
+    /// roots, then tickers of one to five letters (with an optional class,
+    /// `BRK.B`) that aren't futures roots; anything else is an index future.
+    fn of(symbol: &str) -> MockProfile {
+        let symbol = symbol.trim().to_ascii_uppercase();
+        let pair = symbol.replace(['-', '/'], "");
+        let is_crypto = CRYPTO_BASES.iter().any(|base| {
+            pair.strip_prefix(base)
+                .is_some_and(|quote| CRYPTO_QUOTES.contains(&quote))
+        });
+        if is_crypto {
+            return CRYPTO;
+        }
+        if TREASURY_ROOTS.contains(&symbol_root(&symbol)) {
+            return TREASURY;
+        }
+        let (ticker, class) = symbol.split_once('.').unwrap_or((&symbol, ""));
+        let is_ticker = (1..=5).contains(&ticker.len())
+            && ticker.bytes().all(|b| b.is_ascii_uppercase())
+            && class.len() <= 1
+            && class.bytes().all(|b| b.is_ascii_uppercase())
+            && !is_futures_root(ticker);
+        match is_ticker {
+            true => EQUITY,
+            false => INDEX_FUTURE,
+        }
+    }
+
+    /// A random move of up to [`step_ticks`](Self::step_ticks), scaled
+    /// by `volatility`, from `price`, no lower than a tick.
+    fn step(&self, rng: &mut impl Rng, price: i64, volatility: f64) -> i64 {
+        let reach = self.scaled(self.step_ticks, volatility);
+        let ticks = rng.gen_range(-reach..=reach);
+        (price + ticks * self.tick_i64).max(self.tick_i64)
+    }
+
+    /// `ticks` scaled by `volatility`, at least one.
+    fn scaled(&self, ticks: i64, volatility: f64) -> i64 {
+        ((ticks as f64 * volatility).round() as i64).max(1)
+    }
+
+    /// `price` moved by the `shift` fraction of itself, on a tick.
+    fn shifted(&self, price: i64, shift: f64) -> i64 {
+        if shift == 0.0 {
+            return price;
+        }
+        let ticks = (price as f64 * (1.0 + shift) / self.tick_i64 as f64).round() as i64;
+        (ticks * self.tick_i64).max(self.tick_i64)
+    }
+
+    fn size(&self, rng: &mut impl Rng) -> u32 {
+        rng.gen_range(1..=self.max_size_u32)
+    }
+}
+
+fn is_futures_root(root: &str) -> bool {
+    TickTable::with_defaults()
+        .entries()
+        .iter()
+        .any(|entry| entry.root == root)
+}
+
+/// Width of the bars a live subscription to `schema` streams, `None` for
+/// trades, depth and orders. Derived schemas aren't streamed live, as with
+/// DataBento.
+fn live_interval_ns(schema: &Schema) -> Result<Option<u64>, ServiceError> {
+    match schema {
+        Schema::Trades | Schema::Mbp10 | Schema::Mbo => Ok(None),
+        Schema::Ohlcv1S => Ok(Some(NANOS_PER_SECOND)),
+        Schema::Ohlcv1M => Ok(Some(NANOS_PER_MINUTE)),
+        other => Err(ServiceError::InvalidSchema(format!(
+            "Unknown schema: {}. Expected: trades, ohlcv-1s, ohlcv-1m, mbp-10, or mbo",
+            other
+        ))),
+    }
+}
+
+/// A mock book around `price`: levels a tick apart on each side, with
+/// random sizes.
+fn mock_depth(
+    rng: &mut StdRng,
+    ts_event_unix_ns: u64,
+    symbol: String,
+    sequence_u32: u32,
+    price: i64,
+    tick: i64,
+) -> DepthUpdate {
+    let levels = (0..DEPTH_LEVELS as i64)
+        .map(|i| DepthLevel {
+            bid_px_i64: price - tick * (i + 1),
+            ask_px_i64: price + tick * i,
+            bid_sz_u32: rng.gen_range(1..=200),
+            ask_sz_u32: rng.gen_range(1..=200),
+            bid_ct_u32: rng.gen_range(1..=20),
+            ask_ct_u32: rng.gen_range(1..=20),
+        })
+        .collect();
+    DepthUpdate {
+        ts_event_unix_ns,
+        symbol,
+        sequence_u32,
+        maybe_bad_book: false,
+        levels,
+    }
+}
+
+/// Orders resting in one symbol's mock book, for mock order events.
+struct MockOrders {
+    symbol: String,
+    tick: i64,
+    next_id: u64,
+    sequence_u32: u32,
+    resting: Vec<(u64, BookSide, i64)>,
+}
+
+impl MockOrders {
+    fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            tick: MockProfile::of(symbol).tick_i64,
+            next_id: 1,
+            sequence_u32: 0,
+            resting: Vec::new(),
+        }
+    }
+
+    fn record(
+        &mut self,
+        ts_event_unix_ns: u64,
+        action: MboAction,
+        (order_id_u64, side, price_i64): (u64, BookSide, i64),
+        size_u32: u32,
+    ) -> MboRecord {
+        self.sequence_u32 += 1;
+        MboRecord {
+            ts_event_unix_ns,
+            symbol: self.symbol.clone(),
+            order_id_u64,
+            price_i64,
+            size_u32,
+            action,
+            side,
+            sequence_u32: self.sequence_u32,
+            last: true,
+            maybe_bad_book: false,
+        }
+    }
+
+    /// A clear, then orders on the ten levels each side of `price`, as a
+    /// day or a live session starts.
+    fn snapshot(&mut self, rng: &mut impl Rng, ts: u64, price: i64) -> Vec<MboRecord> {
+        self.resting.clear();
+        let mut records = vec![self.record(ts, MboAction::Clear, (0, BookSide::None, 0), 0)];
+        while self.resting.len() < 4 * DEPTH_LEVELS {
+            records.push(self.add(rng, ts, price));
+        }
+        // One venue event: the book is whole after the last record
+        let last = records.len() - 1;
+        for record in &mut records[..last] {
+            record.last = false;
+        }
+        records
+    }
+
+    fn add(&mut self, rng: &mut impl Rng, ts: u64, price: i64) -> MboRecord {
+        let level = rng.gen_range(0..DEPTH_LEVELS as i64);
+        let order = match rng.gen_bool(0.5) {
+            true => (self.next_id, BookSide::Bid, price - self.tick * (level + 1)),
+            false => (self.next_id, BookSide::Ask, price + self.tick * level),
+        };
+        self.next_id += 1;
+        self.resting.push(order);
+        self.record(ts, MboAction::Add, order, rng.gen_range(1..=50))
+    }
+
+    /// One random add, cancel or modify near `price`.
+    fn step(&mut self, rng: &mut impl Rng, ts: u64, price: i64) -> MboRecord {
+        if self.resting.len() < 2 * DEPTH_LEVELS || rng.gen_bool(0.5) {
+            return self.add(rng, ts, price);
+        }
+        let order = self
+            .resting
+            .swap_remove(rng.gen_range(0..self.resting.len()));
+        if rng.gen_bool(0.7) {
+            return self.record(ts, MboAction::Cancel, order, u32::MAX);
+        }
+        // Re-priced a tick toward the touch
+        let moved = match order.1 {
+            BookSide::Bid => (order.0, order.1, order.2 + self.tick),
+            _ => (order.0, order.1, order.2 - self.tick),
+        };
+        self.resting.push(moved);
+        self.record(ts, MboAction::Modify, moved, rng.gen_range(1..=50))
+    }
+}
+
+/// How fast the mock live stream emits messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageRate {
+    /// Random 100-500ms gaps between trades, resembling a quiet market.
+    Realistic,
+    /// A steady rate in messages per second.
+    Fixed { hz: u32 },
+    /// A steady `base_hz`, with a burst at `burst_hz` lasting `burst_ms`
+    /// at the start of every `every_ms` period. Mimics opening-bell spikes.
+    Burst {
+        base_hz: u32,
+        burst_hz: u32,
+        burst_ms: u64,
+        every_ms: u64,
+    },
+}
+
+impl MessageRate {
+    /// Messages per second due at `elapsed` since the stream started.
+    /// Returns `None` for the realistic (randomly delayed) mode.
+    pub fn rate_at(&self, elapsed: Duration) -> Option<f64> {
+        match *self {
+            MessageRate::Realistic => None,
+            MessageRate::Fixed { hz } => Some(hz.min(MAX_RATE_HZ) as f64),
+            MessageRate::Burst {
+                base_hz,
+                burst_hz,
+                burst_ms,
+                every_ms,
+            } => {
+                let phase_ms = elapsed.as_millis() as u64 % every_ms.max(1);
+                let hz = if phase_ms < burst_ms {
+                    burst_hz
+                } else {
+                    base_hz
+                };
+                Some(hz.min(MAX_RATE_HZ) as f64)
+            }
+        }
+    }
+}
+
+/// Error messages chaos mode sends mid-stream, resembling a feed that
+/// misbehaves rather than the server's own errors.
+const CHAOS_ERRORS: [&str; 4] = [
+    "Decode error: unexpected end of record (rtype 0x00, length 0)",
+    "\u{fffd}\u{fffd}gateway: \u{0}",
+    "",
+    "Gap detected: sequence 48213 after 48190",
+];
+
+/// Misbehaviour injected into mock data, so clients' handling of a feed
+/// that stalls, drops, repeats itself and reports odd errors can be tested
+/// on demand. Each rate is a probability per live message, or per
+/// historical fetch for `fail`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Chaos {
+    /// Longest random delay before a live message or historical response
+    pub max_delay_ms: u64,
+    /// A live stream ends with an error, as a dropped connection would
+    pub drop: f64,
+    /// A malformed-looking error event is sent; the stream carries on
+    pub error: f64,
+    /// A live message is sent twice
+    pub duplicate: f64,
+    /// A historical fetch fails with a connection error
+    pub fail: f64,
+}
+
+impl Chaos {
+    /// What `MOCK_CHAOS=on` enables.
+    pub const PRESET: Chaos = Chaos {
+        max_delay_ms: 250,
+        drop: 0.001,
+        error: 0.01,
+        duplicate: 0.01,
+        fail: 0.05,
+    };
+
+    /// Wait out a random delay, then maybe fail, before a historical fetch.
+    async fn disrupt_fetch(&self) -> Result<(), ServiceError> {
+        let (delay, fail) = {
+            let mut rng = rand::thread_rng();
+            (
+                rng.gen_range(0..=self.max_delay_ms),
+                rng.gen_bool(self.fail.clamp(0.0, 1.0)),
+            )
+        };
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        match fail {
+            true => Err(ServiceError::ConnectionError(
+                "Mock chaos: connection reset by peer".to_string(),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// `stream` with delays, duplicates, error events and drops mixed in.
+    /// The first message, `Connected`, always arrives untouched.
+    fn disrupt(self, mut stream: LiveStream) -> LiveStream {
+        Box::pin(async_stream::stream! {
+            let mut rng = StdRng::from_entropy();
+            if let Some(first) = stream.next().await {
+                yield first;
+            }
+            while let Some(message) = stream.next().await {
+                let delay = rng.gen_range(0..=self.max_delay_ms);
+                if delay > 0 {
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                }
+                if rng.gen_bool(self.drop.clamp(0.0, 1.0)) {
+                    yield LiveMessage::Error {
+                        message: "Mock chaos: connection dropped".to_string(),
+                    };
+                    return;
+                }
+                if rng.gen_bool(self.error.clamp(0.0, 1.0)) {
+                    let message = CHAOS_ERRORS[rng.gen_range(0..CHAOS_ERRORS.len())];
+                    yield LiveMessage::Error { message: message.to_string() };
+                }
+                if rng.gen_bool(self.duplicate.clamp(0.0, 1.0)) {
+                    yield message.clone();
+                }
+                yield message;
+            }
+        })
+    }
+}
+
+impl FromStr for Chaos {
+    type Err = String;
+
+    /// `on` for [`Chaos::PRESET`], or settings such as
+    /// `delay=500,drop=0.01,error=0.05,duplicate=0.1,fail=0.2`, those left
+    /// out being off.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "on" {
+            return Ok(Chaos::PRESET);
+        }
+        let mut chaos = Chaos::default();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("'{}' should be name=value", setting))?;
+            let value = value.trim();
+            let rate = || match value.parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                _ => Err(format!("{} should be a probability from 0 to 1", name)),
+            };
+            match name.trim() {
+                "delay" => {
+                    chaos.max_delay_ms = value
+                        .parse()
+                        .map_err(|_| format!("delay should be milliseconds, not '{}'", value))?
+                }
+                "drop" => chaos.drop = rate()?,
+                "error" => chaos.error = rate()?,
+                "duplicate" => chaos.duplicate = rate()?,
+                "fail" => chaos.fail = rate()?,
+                other => {
+                    return Err(format!(
+                    "Unknown chaos setting '{}': expected delay, drop, error, duplicate or fail",
+                    other
+                ))
+                }
+            }
+        }
+        Ok(chaos)
+    }
+}
 
 /// Mock service that generates realistic market data without external API.
+///
+/// Each symbol trades to a profile picked by its pattern: index futures
+/// (ES-like) by default, with treasury futures (ZN-like), equities
+/// (AAPL-like, NYSE hours) and crypto pairs (BTC-like, around the clock)
+/// priced, ticked and sized as such.
 pub struct MockService {
-    /// Base price for mock data generation (ES futures ~4500-5500 range)
-    base_price: i64,
+    /// Live message pacing
+    rate: MessageRate,
+    /// Trading hours historical data is confined to; `None` trades around
+    /// the clock
+    sessions: Option<SessionSpec>,
+    /// Misbehaviour injected for resilience testing
+    chaos: Option<Chaos>,
+    /// Scripted regimes prices follow
+    scenarios: Arc<Scenarios>,
 }
 
 impl MockService {
     pub fn new() -> Self {
-        // Base price in fixed-point 1e9 format (e.g., 5000.00 = 5000 * 1e9)
         Self {
-            base_price: 5_000_000_000_000, // 5000.00
+            rate: MessageRate::Realistic,
+            sessions: None,
+            chaos: None,
+            scenarios: Arc::new(Scenarios::new()),
         }
     }
 
-    /// Generate mock trade data for the given time range.
+    /// Set the live message rate, e.g. for soak and load testing.
+    pub fn with_rate(mut self, rate: MessageRate) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Confine historical data to a market's sessions, leaving weekends and
+    /// daily breaks empty as they would be upstream.
+    pub fn with_sessions(mut self, sessions: SessionSpec) -> Self {
+        self.sessions = Some(sessions);
+        self
+    }
+
+    /// Make the feed misbehave, to test how clients cope.
+    pub fn with_chaos(mut self, chaos: Chaos) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Price data to the regimes of `scenarios`, as they're scheduled.
+    pub fn with_scenarios(mut self, scenarios: Arc<Scenarios>) -> Self {
+        self.scenarios = scenarios;
+        self
+    }
+
+    /// The sessions `profile` trades in; `None` trades around the clock.
+    fn sessions(&self, profile: &MockProfile) -> Option<SessionSpec> {
+        match profile.hours {
+            MockHours::Configured => self.sessions,
+            MockHours::Market(market) => market.parse().ok(),
+            MockHours::Always => None,
+        }
+    }
+
+    /// The parts of `[start, end)` the mock market for `profile` is open.
+    fn open_spans(
+        &self,
+        profile: &MockProfile,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        match self.sessions(profile) {
+            Some(sessions) => sessions.open_spans(start, end),
+            None if start < end => vec![(start, end)],
+            None => Vec::new(),
+        }
+    }
+
+    /// Indexes of the whole `bar_secs` bars, counted from `start`, that
+    /// fit within the open parts of `[start, end)` for `profile`.
+    fn bar_indexes(
+        &self,
+        profile: &MockProfile,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bar_secs: i64,
+    ) -> impl Iterator<Item = i64> {
+        self.open_spans(profile, start, end)
+            .into_iter()
+            .flat_map(move |(open, close)| {
+                let first = ((open - start).num_seconds() + bar_secs - 1) / bar_secs;
+                first..(close - start).num_seconds() / bar_secs
+            })
+    }
+
+    /// Generate mock trade data for the given time range, optionally with
+    /// a synthetic BBO around each trade.
     fn generate_trades(
         &self,
         symbols: &[String],
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         limit: u32,
+        quote_at_trade: bool,
     ) -> Vec<TradeRecord> {
         let mut rng = rand::thread_rng();
         let mut trades = Vec::new();
-        let mut current_price = self.base_price;
-
-        let duration_ns = (end - start).num_nanoseconds().unwrap_or(0) as u64;
         let num_trades = std::cmp::min(limit as usize, 1000);
 
-        for i in 0..num_trades {
-            // Random walk for price
-            let price_change: i64 = rng.gen_range(-500_000_000..=500_000_000); // ±0.50
-            current_price = (current_price + price_change).max(self.base_price - 50_000_000_000); // Don't go too low
+        // Symbols open in the range share the trades out evenly, each
+        // spreading its share across its own open hours
+        let trading: Vec<_> = symbols
+            .iter()
+            .map(|symbol| {
+                let profile = MockProfile::of(symbol);
+                let spans = self.open_spans(&profile, start, end);
+                (symbol.clone(), profile, spans)
+            })
+            .filter(|(_, _, spans)| !spans.is_empty())
+            .collect();
+        for (k, (symbol, profile, spans)) in trading.iter().enumerate() {
+            let span_ns: Vec<u64> = spans
+                .iter()
+                .map(|(open, close)| (*close - *open).num_nanoseconds().unwrap_or(0) as u64)
+                .collect();
+            let duration_ns: u64 = span_ns.iter().sum();
+            let count = (num_trades + trading.len() - 1 - k) / trading.len();
+            let mut walk = profile.base_price_i64;
 
-            // Spread trades across the time range
-            let time_offset = if num_trades > 1 {
-                (duration_ns * i as u64) / (num_trades as u64 - 1)
-            } else {
-                0
-            };
-            let ts = start.timestamp_nanos_opt().unwrap_or(0) as u64 + time_offset;
+            for i in 0..count {
+                let mut time_offset = if count > 1 {
+                    (duration_ns * i as u64) / (count as u64 - 1)
+                } else {
+                    0
+                };
+                let mut ts = 0;
+                for ((open, _), len) in spans.iter().zip(&span_ns) {
+                    ts = open.timestamp_nanos_opt().unwrap_or(0) as u64 + time_offset;
+                    if time_offset <= *len {
+                        break;
+                    }
+                    time_offset -= len;
+                }
 
-            // Pick a random symbol from the list
-            let symbol = symbols[i % symbols.len()].clone();
+                let regime = self.scenarios.regime(symbol, ts);
+                walk = profile.step(&mut rng, walk, regime.volatility);
+                let current_price = profile.shifted(walk, regime.shift);
 
-            trades.push(TradeRecord {
-                ts_event_unix_ns: ts,
-                symbol,
-                price_i64: current_price,
-                size_u32: rng.gen_range(1..=50),
-            });
+                // Mostly lift the offer or hit the bid, occasionally print inside
+                let spread = profile.tick_i64;
+                let quote = quote_at_trade.then(|| {
+                    let bid = match rng.gen_range(0..10) {
+                        0..=4 => current_price - spread,
+                        5..=8 => current_price,
+                        _ => current_price - spread / 2,
+                    };
+                    QuoteAtTrade::classify(current_price, bid, bid + spread)
+                });
+
+                trades.push(TradeRecord {
+                    ts_event_unix_ns: ts,
+                    symbol: symbol.clone(),
+                    price_i64: current_price,
+                    size_u32: profile.size(&mut rng),
+                    quote,
+                    sequence_u32: None,
+                });
+            }
         }
 
+        // Stable, so symbols trading at once keep their order
+        trades.sort_by_key(|trade| trade.ts_event_unix_ns);
         trades
     }
 
@@ -77,34 +678,42 @@ impl MockService {
     ) -> Vec<OhlcvRecord> {
         let mut rng = rand::thread_rng();
         let mut bars = Vec::new();
-        let mut current_price = self.base_price;
 
-        let duration_secs = (end - start).num_seconds();
-        let num_bars = std::cmp::min((duration_secs / bar_duration_secs) as usize, limit as usize);
+        for symbol in symbols {
+            let profile = MockProfile::of(symbol);
+            let tick = profile.tick_i64;
+            let bar_ns = bar_duration_secs as u64 * 1_000_000_000;
+            let mut current_price = profile.base_price_i64;
+            let bars_at = self
+                .bar_indexes(&profile, start, end, bar_duration_secs)
+                .take(limit as usize);
 
-        for i in 0..num_bars {
-            for symbol in symbols {
-                let bar_start = start + chrono::Duration::seconds(i as i64 * bar_duration_secs);
+            for i in bars_at {
+                let bar_start = start + chrono::Duration::seconds(i * bar_duration_secs);
                 let ts = bar_start.timestamp_nanos_opt().unwrap_or(0) as u64;
 
                 let open = current_price;
 
-                // Generate realistic intrabar movement
-                let high_delta: i64 = rng.gen_range(0..=2_000_000_000); // Up to +2.00
-                let low_delta: i64 = rng.gen_range(0..=2_000_000_000); // Up to -2.00
-                let close_delta: i64 = rng.gen_range(-1_000_000_000..=1_000_000_000);
-
-                let high = open + high_delta;
-                let low = open - low_delta;
-                let close = (open + close_delta).clamp(low, high);
+                // Generate realistic intrabar movement, in whole ticks
+                let (from, to) = (
+                    self.scenarios.regime(symbol, ts),
+                    self.scenarios.regime(symbol, ts + bar_ns),
+                );
+                let volatility = from.volatility.max(to.volatility);
+                let reach = profile.scaled(profile.bar_ticks, volatility);
+                let high = open + rng.gen_range(0..=reach) * tick;
+                let low = (open - rng.gen_range(0..=reach) * tick).max(tick).min(open);
+                let close = (open + rng.gen_range(-reach / 2..=reach / 2) * tick).clamp(low, high);
 
+                // Shifted as of each end of the bar, the extremes by the
+                // larger and smaller shift so they still bound it
                 bars.push(OhlcvRecord {
                     ts_event_unix_ns: ts,
                     symbol: symbol.clone(),
-                    open_i64: open,
-                    high_i64: high,
-                    low_i64: low,
-                    close_i64: close,
+                    open_i64: profile.shifted(open, from.shift),
+                    high_i64: profile.shifted(high, from.shift.max(to.shift)),
+                    low_i64: profile.shifted(low, from.shift.min(to.shift)),
+                    close_i64: profile.shifted(close, to.shift),
                     volume_u64: rng.gen_range(100..=10000),
                 });
 
@@ -113,10 +722,39 @@ impl MockService {
             }
         }
 
+        // Stable, so bars of the same time keep the symbols' order
+        bars.sort_by_key(|bar| bar.ts_event_unix_ns);
         bars
     }
 }
 
+/// Whether the mock knows `symbol`. Equity tickers and crypto pairs
+/// resolve as they are; otherwise its root must be one of the builtin
+/// futures roots, and a raw symbol must name a contract month (`ESZ4`).
+/// Instrument IDs always resolve.
+fn mock_resolves(symbol: &str, stype_in: SymbolType) -> bool {
+    let root = symbol_root(symbol);
+    match stype_in {
+        SymbolType::InstrumentId => true,
+        _ if !MockProfile::of(symbol).futures => true,
+        SymbolType::RawSymbol if root == symbol => false,
+        _ => is_futures_root(root),
+    }
+}
+
+/// Parse a request's start and end times.
+fn parse_range(req: &HistoricalRequest) -> Result<(DateTime<Utc>, DateTime<Utc>), ServiceError> {
+    let start = DateTime::parse_from_rfc3339(&req.start_rfc3339)
+        .map_err(|e| ServiceError::InvalidTimeFormat(format!("start_rfc3339: {}", e)))?
+        .with_timezone(&Utc);
+
+    let end = DateTime::parse_from_rfc3339(&req.end_rfc3339)
+        .map_err(|e| ServiceError::InvalidTimeFormat(format!("end_rfc3339: {}", e)))?
+        .with_timezone(&Utc);
+
+    Ok((start, end))
+}
+
 impl Default for MockService {
     fn default() -> Self {
         Self::new()
@@ -129,88 +767,296 @@ impl MarketDataService for MockService {
         &self,
         req: &HistoricalRequest,
     ) -> Result<HistoricalResponse, ServiceError> {
+        if let Some(chaos) = &self.chaos {
+            chaos.disrupt_fetch().await?;
+        }
         // Parse schema
         let schema: Schema = req
             .schema
             .parse()
             .map_err(|e: String| ServiceError::InvalidSchema(e))?;
+        checked_stype_in(req.stype_in, &req.symbols)?;
+        let (start, end) = parse_range(req)?;
 
-        // Parse timestamps
-        let start = DateTime::parse_from_rfc3339(&req.start_rfc3339)
-            .map_err(|e| ServiceError::InvalidTimeFormat(format!("start_rfc3339: {}", e)))?
-            .with_timezone(&Utc);
+        if req.quote_at_trade && schema != Schema::Trades && schema.cvd_interval_ns().is_none() {
+            return Err(ServiceError::InvalidSchema(
+                "quote_at_trade requires the trades schema".to_string(),
+            ));
+        }
 
-        let end = DateTime::parse_from_rfc3339(&req.end_rfc3339)
-            .map_err(|e| ServiceError::InvalidTimeFormat(format!("end_rfc3339: {}", e)))?
-            .with_timezone(&Utc);
+        // Trades are shared evenly between symbols and bars generated per
+        // symbol; with a per-symbol limit, generate that many of each
+        // and share out `limit` afterwards
+        let per_symbol = req.limit_per_symbol;
+        let trades_limit =
+            per_symbol.map_or(req.limit, |n| n.saturating_mul(req.symbols.len() as u32));
+        let bars_limit = per_symbol.unwrap_or(req.limit);
 
         // Generate mock data based on schema
-        match schema {
+        let mut response = match schema {
             Schema::Trades => {
-                let data = self.generate_trades(&req.symbols, start, end, req.limit);
-                Ok(HistoricalResponse::Trades { data })
+                let data = self.generate_trades(
+                    &req.symbols,
+                    start,
+                    end,
+                    trades_limit,
+                    req.quote_at_trade,
+                );
+                HistoricalResponse::Trades { data }
             }
             Schema::Ohlcv1S => {
-                let data = self.generate_ohlcv(&req.symbols, start, end, 1, req.limit);
-                Ok(HistoricalResponse::Ohlcv1S { data })
+                let data = self.generate_ohlcv(&req.symbols, start, end, 1, bars_limit);
+                HistoricalResponse::Ohlcv1S { data }
             }
             Schema::Ohlcv1M => {
-                let data = self.generate_ohlcv(&req.symbols, start, end, 60, req.limit);
-                Ok(HistoricalResponse::Ohlcv1M { data })
+                let data = self.generate_ohlcv(&req.symbols, start, end, 60, bars_limit);
+                HistoricalResponse::Ohlcv1M { data }
             }
+            Schema::Cvd1S | Schema::Cvd1M => return historical_cvd(self, req, schema).await,
+            Schema::Bars(spec) => return historical_bars(self, req, spec).await,
+            Schema::Mbp10 | Schema::Mbo => {
+                return Err(ServiceError::InvalidSchema(format!(
+                    "{} is only streamed live",
+                    schema.as_str()
+                )))
+            }
+        };
+        if per_symbol.is_some() {
+            response.apply_limits(req.limit, per_symbol);
         }
+        Ok(response)
+    }
+
+    async fn count_records(&self, req: &HistoricalRequest) -> Result<u64, ServiceError> {
+        // Bars cover the whole range, so count them rather than generate
+        // them; other schemas are capped at 1,000 trades and cheap to build
+        let bar_secs = match req.schema.parse() {
+            Ok(Schema::Ohlcv1S) => 1,
+            Ok(Schema::Ohlcv1M) => 60,
+            _ => {
+                let unlimited = HistoricalRequest {
+                    limit: u32::MAX,
+                    ..req.clone()
+                };
+                return Ok(self.get_historical(&unlimited).await?.len() as u64);
+            }
+        };
+        checked_stype_in(req.stype_in, &req.symbols)?;
+        let (start, end) = parse_range(req)?;
+        Ok(req
+            .symbols
+            .iter()
+            .map(|symbol| {
+                let profile = MockProfile::of(symbol);
+                self.bar_indexes(&profile, start, end, bar_secs).count() as u64
+            })
+            .sum())
+    }
+
+    /// Each symbol's book starts from a snapshot at the start of the
+    /// range, followed by up to 1000 random order events spread across it.
+    async fn replay_mbo(&self, req: &HistoricalRequest) -> Result<MboStream, ServiceError> {
+        checked_stype_in(req.stype_in, &req.symbols)?;
+        let (start, end) = parse_range(req)?;
+        let start_ns = start.timestamp_nanos_opt().unwrap_or(0).max(0) as u64;
+        let span_ns = (end - start).num_nanoseconds().unwrap_or(0).max(0) as u64;
+        let mut rng = rand::thread_rng();
+        let mut orders: Vec<MockOrders> = req.symbols.iter().map(|s| MockOrders::new(s)).collect();
+        let mut records = Vec::new();
+        let prices: Vec<i64> = req
+            .symbols
+            .iter()
+            .map(|symbol| MockProfile::of(symbol).base_price_i64)
+            .collect();
+        for (book, price) in orders.iter_mut().zip(&prices) {
+            records.extend(book.snapshot(&mut rng, start_ns, *price));
+        }
+        if !orders.is_empty() {
+            let events = (req.limit as usize).saturating_sub(records.len()).min(1000) as u64;
+            for i in 0..events {
+                let ts = start_ns + span_ns * i / events;
+                let k = i as usize % orders.len();
+                let shift = self.scenarios.regime(&req.symbols[k], ts).shift;
+                let price = MockProfile::of(&req.symbols[k]).shifted(prices[k], shift);
+                records.push(orders[k].step(&mut rng, ts, price));
+            }
+        }
+        records.truncate(req.limit as usize);
+        Ok(Box::pin(futures::stream::iter(records.into_iter().map(Ok))))
+    }
+
+    async fn unresolved_symbols(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<String>, ServiceError> {
+        let stype_in = checked_stype_in(req.stype_in, &req.symbols)?;
+        Ok(req
+            .symbols
+            .iter()
+            .filter(|symbol| !mock_resolves(symbol, stype_in))
+            .cloned()
+            .collect())
     }
 
     async fn subscribe_live(
         &self,
         symbols: Vec<String>,
         schema: String,
+        stype_in: SymbolType,
     ) -> Result<LiveStream, ServiceError> {
-        // Validate schema
-        let _schema: Schema = schema
+        // Validate schema and symbols
+        let parsed: Schema = schema
             .parse()
             .map_err(|e: String| ServiceError::InvalidSchema(e))?;
+        let interval_ns = live_interval_ns(&parsed)?;
+        let depth = parsed == Schema::Mbp10;
+        let orders = parsed == Schema::Mbo;
+        stype_in
+            .validate(&symbols)
+            .map_err(ServiceError::InvalidSymbol)?;
 
-        let base_price = self.base_price;
+        let rate = self.rate;
+        let scenarios = self.scenarios.clone();
         let symbols_clone = symbols.clone();
+        let profiles: Vec<MockProfile> = symbols.iter().map(|s| MockProfile::of(s)).collect();
 
-        // Create a stream that emits mock trades at random intervals
+        // Create a stream that emits mock trades at random intervals, or the
+        // bars they make up for bar schemas
         // Use StdRng which is Send-safe (unlike thread_rng)
         let stream = async_stream::stream! {
             let mut rng = StdRng::from_entropy();
-            let mut current_price = base_price;
+            let mut prices: Vec<i64> = profiles.iter().map(|p| p.base_price_i64).collect();
             let mut symbol_idx = 0;
+            let mut bars = interval_ns.map(|ns| ClosedBarFeed::new(ns, BarAlign::Epoch));
+            let mut sequence_u32 = 0;
+            let mut books: Vec<MockOrders> = match orders {
+                true => symbols_clone.iter().map(|symbol| MockOrders::new(symbol)).collect(),
+                false => Vec::new(),
+            };
 
             // First, emit a connected message
             yield LiveMessage::Connected {
                 symbols: symbols_clone.clone(),
                 schema: schema.clone(),
+                connection_id: None,
             };
 
-            loop {
-                // Random delay between 100-500ms
-                let delay_ms = rng.gen_range(100..=500);
-                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
-
-                // Random price movement
-                let price_change: i64 = rng.gen_range(-250_000_000..=250_000_000); // ±0.25
-                current_price = (current_price + price_change).max(base_price - 50_000_000_000);
+            // Resolve parent and continuous symbols to a front month, as
+            // DataBento does when a subscription starts
+            if matches!(stype_in, SymbolType::Parent | SymbolType::Continuous) {
+                let now = Utc::now();
+                for (i, symbol) in symbols_clone.iter().enumerate() {
+                    if !profiles[i].futures {
+                        continue;
+                    }
+                    yield LiveMessage::SymbolMapping {
+                        instrument_id: MOCK_INSTRUMENT_ID + i as u32,
+                        stype_in_symbol: symbol.clone(),
+                        stype_out_symbol: mock_front_month(symbol, now),
+                    };
+                }
+            }
 
-                let symbol = symbols_clone[symbol_idx % symbols_clone.len()].clone();
-                symbol_idx += 1;
+            // Order feeds start from each book's snapshot
+            let now = Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64;
+            for (book, price) in books.iter_mut().zip(&prices) {
+                for record in book.snapshot(&mut rng, now, *price) {
+                    yield LiveMessage::Mbo(record);
+                }
+            }
 
-                let ts = Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64;
+            let started = Instant::now();
+            let mut last_tick = started;
+            let mut credit = 0.0_f64;
+            let mut ticker = tokio::time::interval(PACING_TICK);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
-                yield LiveMessage::Trade {
-                    ts_event_unix_ns: ts,
-                    symbol,
-                    price_i64: current_price,
-                    size_u32: rng.gen_range(1..=25),
+            loop {
+                // Work out how many trades are due before the next pause
+                let due = match rate.rate_at(started.elapsed()) {
+                    None => {
+                        // Random delay between 100-500ms
+                        let delay_ms = rng.gen_range(100..=500);
+                        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                        1
+                    }
+                    Some(hz) => {
+                        ticker.tick().await;
+                        let now = Instant::now();
+                        // Cap catch-up at 100ms worth so a stalled consumer
+                        // doesn't receive one enormous burst on recovery
+                        let dt = (now - last_tick).as_secs_f64().min(0.1);
+                        last_tick = now;
+                        credit += hz * dt;
+                        let due = credit.floor();
+                        credit -= due;
+                        due as usize
+                    }
                 };
+
+                for _ in 0..due {
+                    // Each symbol walks its own price
+                    let k = symbol_idx % symbols_clone.len();
+                    symbol_idx += 1;
+                    let profile = &profiles[k];
+                    let symbol = symbols_clone[k].clone();
+
+                    let ts = Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64;
+                    let regime = scenarios.regime(&symbol, ts);
+                    prices[k] = profile.step(&mut rng, prices[k], regime.volatility);
+                    let current_price = profile.shifted(prices[k], regime.shift);
+                    let size_u32 = profile.size(&mut rng);
+
+                    if orders {
+                        yield LiveMessage::Mbo(books[k].step(&mut rng, ts, current_price));
+                        continue;
+                    }
+                    if depth {
+                        sequence_u32 += 1;
+                        let tick = profile.tick_i64;
+                        let update =
+                            mock_depth(&mut rng, ts, symbol, sequence_u32, current_price, tick);
+                        yield LiveMessage::Depth(update);
+                        continue;
+                    }
+                    let Some(feed) = bars.as_mut() else {
+                        yield LiveMessage::Trade {
+                            ts_event_unix_ns: ts,
+                            symbol,
+                            price_i64: current_price,
+                            size_u32,
+                        };
+                        continue;
+                    };
+                    let closed = feed.push(&TradeRecord {
+                        ts_event_unix_ns: ts,
+                        symbol,
+                        price_i64: current_price,
+                        size_u32,
+                        quote: None,
+                        sequence_u32: None,
+                    });
+                    for bar in closed {
+                        yield LiveMessage::Ohlcv {
+                            ts_event_unix_ns: bar.ts_event_unix_ns,
+                            symbol: bar.symbol,
+                            open_i64: bar.open_i64,
+                            high_i64: bar.high_i64,
+                            low_i64: bar.low_i64,
+                            close_i64: bar.close_i64,
+                            volume_u64: bar.volume_u64,
+                            bar_closed: false,
+                            interval: None,
+                        };
+                    }
+                }
             }
         };
 
-        Ok(Box::pin(stream))
+        Ok(match self.chaos {
+            Some(chaos) => chaos.disrupt(Box::pin(stream)),
+            None => Box::pin(stream),
+        })
     }
 
     fn name(&self) -> &'static str {
@@ -221,7 +1067,9 @@ impl MarketDataService for MockService {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio_stream::StreamExt;
+    use crate::service::historical_partial;
+    use shared::price::PricesAs;
+    use shared::RecordOrder;
 
     #[tokio::test]
     async fn test_mock_historical_trades() {
@@ -229,10 +1077,22 @@ mod tests {
         let req = HistoricalRequest {
             symbols: vec!["ES.FUT".to_string()],
             schema: "trades".to_string(),
-            stype_in: "parent".to_string(),
+            stype_in: SymbolType::Parent,
             start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
             end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
             limit: 100,
+            limit_per_symbol: None,
+            dataset: None,
+            quote_at_trade: false,
+            count_only: false,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            order: RecordOrder::Time,
+            compact: false,
+            prices_as: PricesAs::Number,
+            auto_resolution: false,
+            revision: None,
         };
 
         let resp = service.get_historical(&req).await.unwrap();
@@ -246,16 +1106,66 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_mock_limit_per_symbol() {
+        let service = MockService::new();
+        let symbols = |resp: HistoricalResponse| -> Vec<String> {
+            match resp {
+                HistoricalResponse::Trades { data } => data.into_iter().map(|r| r.symbol).collect(),
+                HistoricalResponse::Ohlcv1M { data } => {
+                    data.into_iter().map(|r| r.symbol).collect()
+                }
+                _ => panic!("Unexpected schema"),
+            }
+        };
+        let count =
+            |symbols: &[String], symbol: &str| symbols.iter().filter(|s| *s == symbol).count();
+
+        for schema in ["trades", "ohlcv-1m"] {
+            let req = HistoricalRequest {
+                symbols: vec!["ES.FUT".to_string(), "NQ.FUT".to_string()],
+                schema: schema.to_string(),
+                start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+                end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+                limit: 8,
+                limit_per_symbol: Some(5),
+                ..Default::default()
+            };
+            let served = symbols(service.get_historical(&req).await.unwrap());
+            assert_eq!((count(&served, "ES.FUT"), count(&served, "NQ.FUT")), (4, 4));
+
+            let req = HistoricalRequest {
+                limit: 100,
+                limit_per_symbol: Some(3),
+                ..req
+            };
+            let served = symbols(service.get_historical(&req).await.unwrap());
+            assert_eq!((count(&served, "ES.FUT"), count(&served, "NQ.FUT")), (3, 3));
+        }
+    }
+
     #[tokio::test]
     async fn test_mock_historical_ohlcv() {
         let service = MockService::new();
         let req = HistoricalRequest {
             symbols: vec!["ES.FUT".to_string()],
             schema: "ohlcv-1m".to_string(),
-            stype_in: "parent".to_string(),
+            stype_in: SymbolType::Parent,
             start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
             end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
             limit: 100,
+            limit_per_symbol: None,
+            dataset: None,
+            quote_at_trade: false,
+            count_only: false,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            order: RecordOrder::Time,
+            compact: false,
+            prices_as: PricesAs::Number,
+            auto_resolution: false,
+            revision: None,
         };
 
         let resp = service.get_historical(&req).await.unwrap();
@@ -283,60 +1193,564 @@ mod tests {
         let req = HistoricalRequest {
             symbols: vec!["ES.FUT".to_string()],
             schema: "invalid".to_string(),
-            stype_in: "parent".to_string(),
+            stype_in: SymbolType::Parent,
             start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
             end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
             limit: 100,
+            limit_per_symbol: None,
+            dataset: None,
+            quote_at_trade: false,
+            count_only: false,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            order: RecordOrder::Time,
+            compact: false,
+            prices_as: PricesAs::Number,
+            auto_resolution: false,
+            revision: None,
         };
 
         let result = service.get_historical(&req).await;
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_mock_count_ignores_limit() {
+        let service = MockService::new();
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string(), "NQ.FUT".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            limit: 10,
+            count_only: true,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            ..Default::default()
+        };
+        assert_eq!(service.count_records(&req).await.unwrap(), 120);
+
+        let req = HistoricalRequest {
+            schema: "trades".to_string(),
+            ..req
+        };
+        assert_eq!(service.count_records(&req).await.unwrap(), 1000);
+    }
+
+    #[tokio::test]
+    async fn test_mock_sessions() {
+        let service = MockService::new().with_sessions("cme".parse().unwrap());
+        // Friday 15:00 CT to Sunday 18:00 CT: an hour before the close and
+        // an hour after the open
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            start_rfc3339: "2024-03-08T21:00:00Z".to_string(),
+            end_rfc3339: "2024-03-10T23:00:00Z".to_string(),
+            limit: 1000,
+            ..Default::default()
+        };
+        assert_eq!(service.count_records(&req).await.unwrap(), 120);
+        let bars = service.get_historical(&req).await.unwrap();
+        assert_eq!(bars.len(), 120);
+
+        let req = HistoricalRequest {
+            schema: "trades".to_string(),
+            ..req
+        };
+        let HistoricalResponse::Trades { data } = service.get_historical(&req).await.unwrap()
+        else {
+            panic!("expected trades");
+        };
+        let friday_close = 1_709_935_200_000_000_000; // 2024-03-08T22:00:00Z
+        let sunday_open = 1_710_108_000_000_000_000; // 2024-03-10T22:00:00Z
+        assert!(data
+            .iter()
+            .all(|t| t.ts_event_unix_ns <= friday_close || t.ts_event_unix_ns >= sunday_open));
+        assert!(data.iter().any(|t| t.ts_event_unix_ns > sunday_open));
+    }
+
+    #[tokio::test]
+    async fn test_mock_profiles() {
+        for (symbol, profile) in [
+            ("ES.FUT", INDEX_FUTURE),
+            ("ESZ4", INDEX_FUTURE),
+            ("ZN.c.0", TREASURY),
+            ("AAPL", EQUITY),
+            ("BRK.B", EQUITY),
+            ("BTC-USD", CRYPTO),
+            ("ethusdt", CRYPTO),
+            ("ES", INDEX_FUTURE),
+        ] {
+            assert_eq!(MockProfile::of(symbol), profile, "{}", symbol);
+        }
+        assert!(mock_resolves("AAPL", SymbolType::RawSymbol));
+        assert!(!mock_resolves("ES", SymbolType::RawSymbol));
+
+        // Saturday: crypto trades, equities don't, and each walks its tick;
+        // the open symbol gets the whole limit
+        let service = MockService::new();
+        let req = HistoricalRequest {
+            symbols: vec!["AAPL".to_string(), "BTCUSD".to_string()],
+            schema: "trades".to_string(),
+            stype_in: SymbolType::RawSymbol,
+            start_rfc3339: "2024-03-09T14:00:00Z".to_string(),
+            end_rfc3339: "2024-03-09T15:00:00Z".to_string(),
+            limit: 100,
+            ..Default::default()
+        };
+        let HistoricalResponse::Trades { data } = service.get_historical(&req).await.unwrap()
+        else {
+            panic!("expected trades");
+        };
+        assert_eq!(data.len(), 100);
+        assert!(data.iter().all(|t| t.symbol == "BTCUSD"));
+        assert!(data.iter().all(|t| t.price_i64 % CRYPTO.tick_i64 == 0));
+
+        let req = HistoricalRequest {
+            symbols: vec!["ZN.FUT".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            stype_in: SymbolType::Parent,
+            start_rfc3339: "2024-03-11T14:00:00Z".to_string(),
+            end_rfc3339: "2024-03-11T15:00:00Z".to_string(),
+            ..req
+        };
+        let HistoricalResponse::Ohlcv1M { data } = service.get_historical(&req).await.unwrap()
+        else {
+            panic!("expected bars");
+        };
+        assert_eq!(data.len(), 60);
+        let near = |price: i64| (price - TREASURY.base_price_i64).abs() < 100_000_000_000;
+        assert!(data
+            .iter()
+            .all(|bar| near(bar.low_i64) && near(bar.high_i64)));
+        assert!(data
+            .iter()
+            .all(|bar| bar.close_i64 % TREASURY.tick_i64 == 0));
+    }
+
+    #[tokio::test]
+    async fn test_mock_scenarios() {
+        use shared::scenarios::{ScenarioKind, ScenarioRequest};
+
+        let scenarios = Arc::new(Scenarios::new());
+        let service = MockService::new().with_scenarios(scenarios.clone());
+        scenarios
+            .schedule(&ScenarioRequest {
+                kind: ScenarioKind::TrendDay,
+                at: "2024-01-01T00:00:00Z".to_string(),
+                duration_secs: Some(3600),
+                magnitude: Some(-0.5),
+                symbols: vec!["ES.FUT".to_string()],
+            })
+            .unwrap();
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string(), "NQ.FUT".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            limit: 1000,
+            ..Default::default()
+        };
+        let HistoricalResponse::Ohlcv1M { data } = service.get_historical(&req).await.unwrap()
+        else {
+            panic!("expected bars");
+        };
+        let closes = |symbol: &str| -> Vec<i64> {
+            data.iter()
+                .filter(|bar| bar.symbol == symbol)
+                .map(|bar| bar.close_i64)
+                .collect()
+        };
+        // ES trends down by half over the hour; NQ is left alone
+        let es = closes("ES.FUT");
+        assert!(es[0] > 4_500_000_000_000);
+        assert!(*es.last().unwrap() < 3_000_000_000_000);
+        assert!(closes("NQ.FUT")
+            .iter()
+            .all(|close| *close > 4_000_000_000_000));
+        for bar in &data {
+            assert!(bar.low_i64 <= bar.open_i64.min(bar.close_i64));
+            assert!(bar.high_i64 >= bar.open_i64.max(bar.close_i64));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_instrument_ids() {
+        let service = MockService::new();
+        let mut req = HistoricalRequest {
+            symbols: vec!["4916".to_string()],
+            schema: "trades".to_string(),
+            stype_in: SymbolType::InstrumentId,
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            limit: 10,
+            ..Default::default()
+        };
+        assert!(service.get_historical(&req).await.is_ok());
+
+        req.symbols.push("ES.FUT".to_string());
+        assert!(matches!(
+            service.get_historical(&req).await,
+            Err(ServiceError::InvalidSymbol(_))
+        ));
+        assert!(service
+            .subscribe_live(
+                req.symbols.clone(),
+                "trades".to_string(),
+                SymbolType::InstrumentId
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_partial_symbols() {
+        let service = MockService::new();
+        let mut req = HistoricalRequest {
+            symbols: vec!["4916".to_string(), "ES.FUT".to_string(), "5002".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            stype_in: SymbolType::InstrumentId,
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            limit: 10,
+            ..Default::default()
+        };
+        let (response, failed) = historical_partial(&service, &req).await.unwrap();
+        assert_eq!(response.len(), 10);
+        assert_eq!(response.records_by_symbol().len(), 2);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, "ES.FUT");
+        assert!(matches!(failed[0].1, ServiceError::InvalidSymbol(_)));
+
+        // Nothing to serve fails as before
+        req.symbols = vec!["ES.FUT".to_string(), "NQ.FUT".to_string()];
+        assert!(matches!(
+            historical_partial(&service, &req).await,
+            Err(ServiceError::InvalidSymbol(_))
+        ));
+    }
+
     #[tokio::test]
     async fn test_mock_invalid_time_format() {
         let service = MockService::new();
         let req = HistoricalRequest {
             symbols: vec!["ES.FUT".to_string()],
             schema: "trades".to_string(),
-            stype_in: "parent".to_string(),
+            stype_in: SymbolType::Parent,
             start_rfc3339: "invalid-time".to_string(),
             end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
             limit: 100,
+            limit_per_symbol: None,
+            dataset: None,
+            quote_at_trade: false,
+            count_only: false,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            order: RecordOrder::Time,
+            compact: false,
+            prices_as: PricesAs::Number,
+            auto_resolution: false,
+            revision: None,
         };
 
         let result = service.get_historical(&req).await;
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_mock_quote_at_trade() {
+        let service = MockService::new();
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            limit: 100,
+            quote_at_trade: true,
+            ..Default::default()
+        };
+
+        match service.get_historical(&req).await.unwrap() {
+            HistoricalResponse::Trades { data } => {
+                for trade in &data {
+                    let quote = trade.quote.expect("trade should carry a quote");
+                    assert!(quote.bid_px_i64 <= trade.price_i64);
+                    assert!(quote.ask_px_i64 >= trade.price_i64);
+                }
+            }
+            _ => panic!("Expected trades response"),
+        }
+
+        let bars = HistoricalRequest {
+            schema: "ohlcv-1m".to_string(),
+            ..req
+        };
+        assert!(service.get_historical(&bars).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_historical_cvd() {
+        let service = MockService::new();
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "cvd-1m".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            limit: 30,
+            ..Default::default()
+        };
+
+        match service.get_historical(&req).await.unwrap() {
+            HistoricalResponse::Cvd1M { data } => {
+                assert_eq!(data.len(), 30);
+                // Each bar opens where the previous one closed
+                for pair in data.windows(2) {
+                    assert_eq!(pair[1].open_i64, pair[0].close_i64);
+                }
+            }
+            _ => panic!("Expected cvd-1m response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_historical_tick_bars() {
+        let service = MockService::new();
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "tickbar-10".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            ..Default::default()
+        };
+
+        let resp = service.get_historical(&req).await.unwrap();
+        assert_eq!(resp.schema().as_str(), "tickbar-10");
+        // 1,000 mock trades make 100 bars of 10
+        assert_eq!(resp.len(), 100);
+        let json = serde_json::to_string(&resp).unwrap();
+        assert!(json.starts_with(r#"{"schema":"tickbar-10","data":["#));
+    }
+
     #[tokio::test]
     async fn test_mock_live_stream() {
         let service = MockService::new();
         let stream = service
-            .subscribe_live(vec!["ES.FUT".to_string()], "trades".to_string())
+            .subscribe_live(
+                vec!["ES.FUT".to_string()],
+                "trades".to_string(),
+                SymbolType::Parent,
+            )
             .await
             .unwrap();
 
-        // Take first 3 messages (connected + 2 trades)
+        // Take first 3 messages (connected + mapping + 1 trade)
         let messages: Vec<_> = stream.take(3).collect().await;
 
         assert_eq!(messages.len(), 3);
 
         // First message should be Connected
         match &messages[0] {
-            LiveMessage::Connected { symbols, schema } => {
+            LiveMessage::Connected {
+                symbols, schema, ..
+            } => {
                 assert_eq!(symbols, &vec!["ES.FUT".to_string()]);
                 assert_eq!(schema, "trades");
             }
             _ => panic!("Expected Connected message first"),
         }
 
-        // Subsequent messages should be trades
+        // Then the parent symbol's front month, followed by trades
         match &messages[1] {
+            LiveMessage::SymbolMapping {
+                stype_in_symbol,
+                stype_out_symbol,
+                ..
+            } => {
+                assert_eq!(stype_in_symbol, "ES.FUT");
+                assert!(stype_out_symbol.starts_with("ES"));
+            }
+            _ => panic!("Expected SymbolMapping message"),
+        }
+        match &messages[2] {
             LiveMessage::Trade { symbol, .. } => {
                 assert_eq!(symbol, "ES.FUT");
             }
             _ => panic!("Expected Trade message"),
         }
     }
+
+    #[tokio::test]
+    async fn test_mock_live_bars() {
+        let service = MockService::new().with_rate(MessageRate::Fixed { hz: 20_000 });
+        let stream = service
+            .subscribe_live(
+                vec!["ESZ4".to_string(), "NQZ4".to_string()],
+                "ohlcv-1s".to_string(),
+                SymbolType::RawSymbol,
+            )
+            .await
+            .unwrap();
+
+        // Raw symbols need no mapping; bars close on the second
+        let messages: Vec<_> = stream.take(3).collect().await;
+        assert!(matches!(messages[0], LiveMessage::Connected { .. }));
+        for msg in &messages[1..] {
+            match msg {
+                LiveMessage::Ohlcv {
+                    ts_event_unix_ns,
+                    high_i64,
+                    low_i64,
+                    volume_u64,
+                    ..
+                } => {
+                    assert_eq!(ts_event_unix_ns % 1_000_000_000, 0);
+                    assert!(high_i64 >= low_i64 && *volume_u64 > 0);
+                }
+                other => panic!("Expected a bar, got {:?}", other),
+            }
+        }
+
+        let result = service
+            .subscribe_live(
+                vec!["ES.FUT".to_string()],
+                "cvd-1m".to_string(),
+                SymbolType::Parent,
+            )
+            .await;
+        assert!(matches!(result, Err(ServiceError::InvalidSchema(_))));
+    }
+
+    #[test]
+    fn test_mock_front_month() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        assert_eq!(
+            mock_front_month("ES.FUT", at("2024-10-15T00:00:00Z")),
+            "ESZ4"
+        );
+        assert_eq!(
+            mock_front_month("CL.c.0", at("2025-01-02T00:00:00Z")),
+            "CLH5"
+        );
+    }
+
+    #[test]
+    fn test_burst_rate_pattern() {
+        let rate = MessageRate::Burst {
+            base_hz: 100,
+            burst_hz: 20_000,
+            burst_ms: 500,
+            every_ms: 5_000,
+        };
+
+        assert_eq!(rate.rate_at(Duration::from_millis(100)), Some(20_000.0));
+        assert_eq!(rate.rate_at(Duration::from_millis(1_000)), Some(100.0));
+        assert_eq!(rate.rate_at(Duration::from_millis(5_200)), Some(20_000.0));
+        assert_eq!(MessageRate::Realistic.rate_at(Duration::ZERO), None);
+        assert_eq!(
+            MessageRate::Fixed { hz: u32::MAX }.rate_at(Duration::ZERO),
+            Some(MAX_RATE_HZ as f64)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_live_stream_fixed_rate() {
+        let service = MockService::new().with_rate(MessageRate::Fixed { hz: 20_000 });
+        let stream = service
+            .subscribe_live(
+                vec!["ES.FUT".to_string()],
+                "trades".to_string(),
+                SymbolType::Parent,
+            )
+            .await
+            .unwrap();
+
+        let started = Instant::now();
+        let messages: Vec<_> = stream.take(2_001).collect().await;
+
+        assert_eq!(messages.len(), 2_001);
+        // 2000 trades at 20kHz take ~100ms; the realistic mode would take minutes
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_chaos() {
+        assert_eq!("on".parse(), Ok(Chaos::PRESET));
+        let parsed: Chaos = "delay=20, duplicate=0.5".parse().unwrap();
+        assert_eq!(
+            (parsed.max_delay_ms, parsed.duplicate, parsed.drop),
+            (20, 0.5, 0.0)
+        );
+        assert!("drop=2".parse::<Chaos>().is_err());
+        assert!("stall=0.1".parse::<Chaos>().is_err());
+
+        let live = |chaos: Chaos| async move {
+            MockService::new()
+                .with_rate(MessageRate::Fixed { hz: 20_000 })
+                .with_chaos(chaos)
+                .subscribe_live(
+                    vec!["ESZ4".to_string()],
+                    "trades".to_string(),
+                    SymbolType::RawSymbol,
+                )
+                .await
+                .unwrap()
+        };
+
+        // Every message after the first sent twice
+        let doubled: Vec<_> = live(Chaos {
+            duplicate: 1.0,
+            ..Chaos::default()
+        })
+        .await
+        .take(5)
+        .collect()
+        .await;
+        assert!(matches!(doubled[0], LiveMessage::Connected { .. }));
+        assert_eq!(
+            serde_json::to_string(&doubled[1]).unwrap(),
+            serde_json::to_string(&doubled[2]).unwrap()
+        );
+
+        // A dropped connection reports an error and ends the stream
+        let dropped: Vec<_> = live(Chaos {
+            drop: 1.0,
+            ..Chaos::default()
+        })
+        .await
+        .collect()
+        .await;
+        assert_eq!(dropped.len(), 2);
+        assert!(matches!(dropped[1], LiveMessage::Error { .. }));
+
+        let errors: Vec<_> = live(Chaos {
+            error: 1.0,
+            ..Chaos::default()
+        })
+        .await
+        .take(3)
+        .collect()
+        .await;
+        assert!(matches!(errors[1], LiveMessage::Error { .. }));
+        assert!(matches!(errors[2], LiveMessage::Trade { .. }));
+
+        let failing = MockService::new().with_chaos(Chaos {
+            fail: 1.0,
+            ..Chaos::default()
+        });
+        let req = HistoricalRequest {
+            symbols: vec!["ESZ4".to_string()],
+            schema: "trades".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            limit: 10,
+            ..Default::default()
+        };
+        assert!(matches!(
+            failing.get_historical(&req).await,
+            Err(ServiceError::ConnectionError(_))
+        ));
+    }
 }
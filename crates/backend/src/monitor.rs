@@ -0,0 +1,494 @@
+//! Provider outage detection and health status.
+//!
+//! [`MonitoredService`] wraps a [`MarketDataService`] and watches for
+//! upstream failures (`ApiError`, `ConnectionError`). After
+//! [`OUTAGE_THRESHOLD`] consecutive failures the provider is considered
+//! down and a `provider_outage` webhook fires; the next success fires
+//! `provider_recovered`. Client errors such as a bad schema don't count.
+//!
+//! It also tracks the upstream error rate over [`ERROR_WINDOW`] and how
+//! long open live streams have gone without a message. Either marks the
+//! provider degraded. Every change of health is recorded as an incident,
+//! served by `GET /api/status`, and broadcast as a `provider_status`
+//! message.
+
+use crate::notify::Notifier;
+use crate::service::{LiveStream, MarketDataService, MboStream, ServiceError};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::StreamExt;
+use shared::status::{Incident, ProviderHealth, ProviderStatus, StatusChange, StatusReport};
+use shared::validation::DatasetRoute;
+use shared::webhooks::WebhookEvent;
+use shared::{DataVersion, HistoricalRequest, HistoricalResponse, LiveMessage, SymbolType};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{error, info, instrument, warn};
+
+/// Consecutive upstream failures before a provider is reported down.
+pub const OUTAGE_THRESHOLD: u32 = 3;
+
+/// Window the upstream error rate is measured over.
+pub const ERROR_WINDOW: Duration = Duration::from_secs(300);
+
+/// Share of failed requests in the window that marks a provider degraded,
+/// once it has seen at least [`ERROR_RATE_MIN_REQUESTS`].
+const DEGRADED_ERROR_RATE: f64 = 0.25;
+const ERROR_RATE_MIN_REQUESTS: usize = 4;
+
+/// Incidents kept for `GET /api/status`.
+const INCIDENT_HISTORY: usize = 100;
+
+/// How often health is re-evaluated without any traffic.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+fn now_ns() -> u64 {
+    Utc::now()
+        .timestamp_nanos_opt()
+        .and_then(|ns| u64::try_from(ns).ok())
+        .unwrap_or_default()
+}
+
+fn rfc3339(ts_ns: u64) -> String {
+    DateTime::from_timestamp_nanos(ts_ns as i64).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Live stream activity, shared with the streams themselves.
+#[derive(Default)]
+struct LiveActivity {
+    streams: AtomicUsize,
+    /// Last message or subscription, Unix nanoseconds
+    last_ns: AtomicU64,
+}
+
+/// Decrements the open stream count when a stream is dropped.
+struct StreamGuard(Arc<LiveActivity>);
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0.streams.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct Health {
+    health: ProviderHealth,
+    reason: String,
+    since_ns: u64,
+    /// Recent upstream requests: (time, failed)
+    calls: VecDeque<(u64, bool)>,
+    last_error: Option<String>,
+    incidents: VecDeque<Incident>,
+    next_incident: u64,
+}
+
+/// A service wrapper that reports provider outages to webhooks.
+pub struct MonitoredService {
+    inner: Arc<dyn MarketDataService>,
+    notifier: Arc<Notifier>,
+    failures: AtomicU32,
+    down: AtomicBool,
+    /// Live silence that marks the provider degraded; never when unset
+    silence_after: Option<Duration>,
+    live: Arc<LiveActivity>,
+    health: Mutex<Health>,
+    changes: broadcast::Sender<LiveMessage>,
+}
+
+impl MonitoredService {
+    pub fn new(inner: Arc<dyn MarketDataService>, notifier: Arc<Notifier>) -> Self {
+        let (changes, _) = broadcast::channel(16);
+        Self {
+            inner,
+            notifier,
+            failures: AtomicU32::new(0),
+            down: AtomicBool::new(false),
+            silence_after: None,
+            live: Arc::new(LiveActivity::default()),
+            health: Mutex::new(Health {
+                health: ProviderHealth::Up,
+                reason: "ok".to_string(),
+                since_ns: now_ns(),
+                calls: VecDeque::new(),
+                last_error: None,
+                incidents: VecDeque::new(),
+                next_incident: 0,
+            }),
+            changes,
+        }
+    }
+
+    /// Mark the provider degraded when open live streams go this long
+    /// without a message.
+    pub fn with_silence_threshold(mut self, silence_after: Duration) -> Self {
+        self.silence_after = Some(silence_after);
+        self
+    }
+
+    /// Whether the provider is currently considered down.
+    pub fn is_down(&self) -> bool {
+        self.down.load(Ordering::Relaxed)
+    }
+
+    fn health(&self) -> MutexGuard<'_, Health> {
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Receive a `provider_status` message on every change of health.
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveMessage> {
+        self.changes.subscribe()
+    }
+
+    /// Re-evaluate health every second, so live silence and an aging error
+    /// rate are noticed without new requests.
+    pub fn start(self: &Arc<Self>) {
+        let monitor = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                ticks.tick().await;
+                monitor.evaluate(now_ns());
+            }
+        });
+    }
+
+    fn record<T>(&self, result: &Result<T, ServiceError>) {
+        match result {
+            Ok(_) => {
+                self.failures.store(0, Ordering::Relaxed);
+                if self.down.swap(false, Ordering::Relaxed) {
+                    info!("Provider {} recovered", self.inner.name());
+                    self.notifier.notify(WebhookEvent::ProviderRecovered {
+                        provider: self.inner.name().to_string(),
+                    });
+                }
+                self.health().calls.push_back((now_ns(), false));
+            }
+            Err(e @ (ServiceError::ApiError(_) | ServiceError::ConnectionError(_))) => {
+                let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                {
+                    let mut health = self.health();
+                    health.calls.push_back((now_ns(), true));
+                    health.last_error = Some(e.to_string());
+                }
+                if failures >= OUTAGE_THRESHOLD && !self.down.swap(true, Ordering::Relaxed) {
+                    error!(failures, "Provider {} is down: {}", self.inner.name(), e);
+                    self.notifier.notify(WebhookEvent::ProviderOutage {
+                        provider: self.inner.name().to_string(),
+                        consecutive_failures: failures,
+                        error: e.to_string(),
+                    });
+                }
+            }
+            Err(_) => return,
+        }
+        self.evaluate(now_ns());
+    }
+
+    /// Work out the provider's health at `now`, recording and broadcasting
+    /// any change.
+    fn evaluate(&self, now: u64) {
+        let mut health = self.health();
+        let window_start = now.saturating_sub(ERROR_WINDOW.as_nanos() as u64);
+        while health
+            .calls
+            .front()
+            .is_some_and(|(at, _)| *at < window_start)
+        {
+            health.calls.pop_front();
+        }
+        let requests = health.calls.len();
+        let failed = health.calls.iter().filter(|(_, failed)| *failed).count();
+
+        let streams = self.live.streams.load(Ordering::Relaxed);
+        let quiet_ns = now.saturating_sub(self.live.last_ns.load(Ordering::Relaxed));
+        let silent = self
+            .silence_after
+            .filter(|limit| streams > 0 && quiet_ns > limit.as_nanos() as u64);
+
+        let (state, reason) = if self.is_down() {
+            (
+                ProviderHealth::Down,
+                format!(
+                    "{} consecutive upstream failures: {}",
+                    self.failures.load(Ordering::Relaxed),
+                    health.last_error.as_deref().unwrap_or("unknown error")
+                ),
+            )
+        } else if let Some(limit) = silent {
+            (
+                ProviderHealth::Degraded,
+                format!("no live data for over {}s", limit.as_secs()),
+            )
+        } else if requests >= ERROR_RATE_MIN_REQUESTS
+            && failed as f64 / requests as f64 >= DEGRADED_ERROR_RATE
+        {
+            (
+                ProviderHealth::Degraded,
+                format!("{} of {} recent upstream requests failed", failed, requests),
+            )
+        } else {
+            (ProviderHealth::Up, "ok".to_string())
+        };
+        if state == health.health {
+            return;
+        }
+
+        let provider = self.inner.name().to_string();
+        let at = rfc3339(now);
+        if let Some(open) = health
+            .incidents
+            .front_mut()
+            .filter(|incident| incident.resolved_at.is_none())
+        {
+            open.resolved_at = Some(at.clone());
+        }
+        if state != ProviderHealth::Up {
+            warn!(?state, "Provider {}: {}", provider, reason);
+            health.next_incident += 1;
+            let incident = Incident {
+                id: health.next_incident,
+                provider: provider.clone(),
+                health: state,
+                reason: reason.clone(),
+                started_at: at.clone(),
+                resolved_at: None,
+            };
+            health.incidents.push_front(incident);
+            health.incidents.truncate(INCIDENT_HISTORY);
+        } else {
+            info!("Provider {} is healthy again", provider);
+        }
+        health.health = state;
+        health.reason = reason.clone();
+        health.since_ns = now;
+
+        // No receivers just means no live socket is open
+        let _ = self.changes.send(LiveMessage::ProviderStatus(StatusChange {
+            provider,
+            health: state,
+            reason,
+            at,
+        }));
+    }
+
+    /// Current health and recent incidents.
+    pub fn status(&self) -> StatusReport {
+        let health = self.health();
+        let last_ns = self.live.last_ns.load(Ordering::Relaxed);
+        StatusReport {
+            providers: vec![ProviderStatus {
+                provider: self.inner.name().to_string(),
+                health: health.health,
+                reason: health.reason.clone(),
+                since: rfc3339(health.since_ns),
+                recent_requests: health.calls.len() as u64,
+                recent_failures: health.calls.iter().filter(|(_, failed)| *failed).count() as u64,
+                live_streams: self.live.streams.load(Ordering::Relaxed),
+                last_live_at: (last_ns > 0).then(|| rfc3339(last_ns)),
+            }],
+            incidents: health.incidents.iter().cloned().collect(),
+        }
+    }
+
+    /// Count `stream` as open until it is dropped, noting each message.
+    fn watch_live(&self, mut stream: LiveStream) -> LiveStream {
+        let live = self.live.clone();
+        live.streams.fetch_add(1, Ordering::Relaxed);
+        live.last_ns.store(now_ns(), Ordering::Relaxed);
+        let guard = StreamGuard(live.clone());
+        Box::pin(async_stream::stream! {
+            let _guard = guard;
+            while let Some(msg) = stream.next().await {
+                live.last_ns.store(now_ns(), Ordering::Relaxed);
+                yield msg;
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl MarketDataService for MonitoredService {
+    #[instrument(name = "provider", skip_all, fields(provider = self.inner.name()))]
+    async fn get_historical(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<HistoricalResponse, ServiceError> {
+        let result = self.inner.get_historical(req).await;
+        self.record(&result);
+        result
+    }
+
+    async fn subscribe_live(
+        &self,
+        symbols: Vec<String>,
+        schema: String,
+        stype_in: SymbolType,
+    ) -> Result<LiveStream, ServiceError> {
+        let result = self.inner.subscribe_live(symbols, schema, stype_in).await;
+        self.record(&result);
+        result.map(|stream| self.watch_live(stream))
+    }
+
+    async fn count_records(&self, req: &HistoricalRequest) -> Result<u64, ServiceError> {
+        let result = self.inner.count_records(req).await;
+        self.record(&result);
+        result
+    }
+
+    async fn estimate_cost(&self, req: &HistoricalRequest) -> Result<f64, ServiceError> {
+        let result = self.inner.estimate_cost(req).await;
+        self.record(&result);
+        result
+    }
+
+    async fn unresolved_symbols(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<String>, ServiceError> {
+        let result = self.inner.unresolved_symbols(req).await;
+        self.record(&result);
+        result
+    }
+
+    async fn data_versions(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<DataVersion>, ServiceError> {
+        let result = self.inner.data_versions(req).await;
+        self.record(&result);
+        result
+    }
+
+    async fn replay_mbo(&self, req: &HistoricalRequest) -> Result<MboStream, ServiceError> {
+        let result = self.inner.replay_mbo(req).await;
+        self.record(&result);
+        result
+    }
+
+    fn datasets(&self, req: &HistoricalRequest) -> Result<Vec<DatasetRoute>, ServiceError> {
+        self.inner.datasets(req)
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Sealer;
+    use crate::notify::WebhookStore;
+
+    /// Fails with a connection error while `failing` is set. Live
+    /// subscriptions are refused while failing and silent otherwise.
+    struct Flaky {
+        failing: AtomicBool,
+    }
+
+    #[async_trait]
+    impl MarketDataService for Flaky {
+        async fn get_historical(
+            &self,
+            req: &HistoricalRequest,
+        ) -> Result<HistoricalResponse, ServiceError> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(ServiceError::ConnectionError("refused".to_string()));
+            }
+            let schema = req.schema.parse().map_err(ServiceError::InvalidSchema)?;
+            Ok(HistoricalResponse::empty(&schema))
+        }
+
+        async fn subscribe_live(
+            &self,
+            _symbols: Vec<String>,
+            _schema: String,
+            _stype_in: SymbolType,
+        ) -> Result<LiveStream, ServiceError> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(ServiceError::NotConfigured("live".to_string()));
+            }
+            Ok(Box::pin(futures::stream::pending()))
+        }
+
+        fn name(&self) -> &'static str {
+            "Flaky"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outage_after_consecutive_failures() {
+        let flaky = Arc::new(Flaky {
+            failing: AtomicBool::new(true),
+        });
+        let notifier = Arc::new(Notifier::new(
+            WebhookStore::in_memory(),
+            Sealer::ephemeral(),
+        ));
+        let service = MonitoredService::new(flaky.clone(), notifier);
+        let req = HistoricalRequest {
+            schema: "trades".to_string(),
+            ..Default::default()
+        };
+
+        for _ in 1..OUTAGE_THRESHOLD {
+            assert!(service.get_historical(&req).await.is_err());
+        }
+        assert!(!service.is_down());
+        // Client errors neither count nor reset the streak
+        assert!(service
+            .subscribe_live(vec![], String::new(), SymbolType::Parent)
+            .await
+            .is_err());
+        assert!(service.get_historical(&req).await.is_err());
+        assert!(service.is_down());
+
+        flaky.failing.store(false, Ordering::Relaxed);
+        assert!(service.get_historical(&req).await.is_ok());
+        assert!(!service.is_down());
+    }
+
+    #[tokio::test]
+    async fn test_status_incidents_and_live_silence() {
+        let flaky = Arc::new(Flaky {
+            failing: AtomicBool::new(false),
+        });
+        let notifier = Arc::new(Notifier::new(
+            WebhookStore::in_memory(),
+            Sealer::ephemeral(),
+        ));
+        let service =
+            MonitoredService::new(flaky, notifier).with_silence_threshold(Duration::from_secs(30));
+        let mut changes = service.subscribe();
+        let stream = service
+            .subscribe_live(vec![], String::new(), SymbolType::Parent)
+            .await
+            .unwrap();
+        assert_eq!(service.status().providers[0].live_streams, 1);
+
+        // A minute without live data
+        let later = now_ns() + 60_000_000_000;
+        service.evaluate(later);
+        let report = service.status();
+        assert_eq!(report.providers[0].health, ProviderHealth::Degraded);
+        assert!(report.incidents[0].resolved_at.is_none());
+        assert!(matches!(
+            changes.try_recv(),
+            Ok(LiveMessage::ProviderStatus(StatusChange {
+                health: ProviderHealth::Degraded,
+                ..
+            }))
+        ));
+
+        // Closing the only stream ends the silence
+        drop(stream);
+        service.evaluate(later);
+        let report = service.status();
+        assert_eq!(report.providers[0].live_streams, 0);
+        assert_eq!(report.providers[0].health, ProviderHealth::Up);
+        assert!(report.incidents[0].resolved_at.is_some());
+    }
+}
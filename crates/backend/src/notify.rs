@@ -0,0 +1,329 @@
+//! Outbound webhook delivery.
+//!
+//! Each delivery is a JSON POST signed with the webhook's secret:
+//! `X-Webhook-Signature: sha256=<hex>` is the HMAC-SHA256 of
+//! `"{timestamp}.{body}"`, where `timestamp` is the Unix time in seconds
+//! sent in `X-Webhook-Timestamp`. Receivers should recompute the signature
+//! and reject stale timestamps. Secrets are sealed with the server's master
+//! key before being written to `WEBHOOKS_PATH`.
+
+use crate::crypto::{CryptoError, Sealer};
+use crate::store::{NamedStore, StoreError};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use shared::jobs::JobRun;
+use shared::webhooks::{PayloadFormat, Webhook, WebhookEvent};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+/// Attempts per delivery before giving up.
+const MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry; doubled for each later one.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Marks a stored secret as sealed; webhooks saved before secrets were
+/// sealed hold theirs in plaintext.
+const SEALED_PREFIX: &str = "sealed:";
+
+/// Error type for webhook operations.
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Invalid webhook: {0}")]
+    InvalidSpec(String),
+    #[error("Webhook: {0}")]
+    Store(#[from] StoreError),
+    #[error("Delivery failed: {0}")]
+    Delivery(String),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// Registered webhooks, keyed by name, with their secrets sealed.
+pub type WebhookStore = NamedStore<Webhook>;
+
+/// Fans events out to every webhook subscribed to them.
+pub struct Notifier {
+    hooks: WebhookStore,
+    sealer: Sealer,
+    http: reqwest::Client,
+}
+
+impl Notifier {
+    /// Deliver to `hooks`, whose secrets are sealed with `sealer`.
+    pub fn new(hooks: WebhookStore, sealer: Sealer) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            hooks,
+            sealer,
+            http,
+        }
+    }
+
+    /// Seal the secrets of webhooks saved before secrets were sealed,
+    /// returning how many there were.
+    pub fn seal_plaintext(&self) -> Result<usize, WebhookError> {
+        let mut sealed = 0;
+        for (name, hook) in self.hooks.list() {
+            if !hook.secret.starts_with(SEALED_PREFIX) {
+                self.hooks.insert(&name, self.sealed(hook))?;
+                sealed += 1;
+            }
+        }
+        Ok(sealed)
+    }
+
+    /// `hook` with its secret sealed for storage.
+    fn sealed(&self, hook: Webhook) -> Webhook {
+        Webhook {
+            secret: format!("{}{}", SEALED_PREFIX, self.sealer.seal(&hook.secret)),
+            ..hook
+        }
+    }
+
+    /// A stored `hook` with its secret opened for signing.
+    fn opened(&self, hook: Webhook) -> Result<Webhook, WebhookError> {
+        let secret = match hook.secret.strip_prefix(SEALED_PREFIX) {
+            Some(sealed) => self.sealer.open(sealed)?,
+            None => hook.secret.clone(),
+        };
+        Ok(Webhook { secret, ..hook })
+    }
+
+    /// Registered webhooks, ordered by name, with their secrets as stored.
+    pub fn list(&self) -> Vec<Webhook> {
+        self.hooks
+            .list()
+            .into_iter()
+            .map(|(_, hook)| hook)
+            .collect()
+    }
+
+    /// Register `hook`, replacing any webhook with the same name.
+    pub fn add(&self, hook: Webhook) -> Result<(), WebhookError> {
+        let scheme_ok = reqwest::Url::parse(&hook.url)
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+        if !scheme_ok {
+            return Err(WebhookError::InvalidSpec(format!(
+                "url '{}' must be an http(s) URL",
+                hook.url
+            )));
+        }
+        if hook.secret.is_empty() {
+            return Err(WebhookError::InvalidSpec("secret is required".to_string()));
+        }
+        self.hooks.insert(&hook.name.clone(), self.sealed(hook))?;
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<Webhook, WebhookError> {
+        Ok(self.hooks.remove(name)?)
+    }
+
+    /// Deliver `event` to every interested webhook in the background.
+    pub fn notify(&self, event: WebhookEvent) {
+        let event = Arc::new(event);
+        for hook in self.list() {
+            if !hook.accepts(event.kind()) {
+                continue;
+            }
+            let hook = match self.opened(hook) {
+                Ok(hook) => hook,
+                Err(e) => {
+                    warn!("Not delivering to a webhook: {}", e);
+                    continue;
+                }
+            };
+            let http = self.http.clone();
+            let event = Arc::clone(&event);
+            tokio::spawn(async move {
+                let mut delay = RETRY_DELAY;
+                for attempt in 1..=MAX_ATTEMPTS {
+                    match deliver(&http, &hook, &event).await {
+                        Ok(()) => return,
+                        Err(e) if attempt == MAX_ATTEMPTS => {
+                            warn!(webhook = %hook.name, "Giving up on delivery: {}", e)
+                        }
+                        Err(e) => {
+                            warn!(webhook = %hook.name, attempt, "Delivery failed, retrying: {}", e);
+                            tokio::time::sleep(delay).await;
+                            delay *= 2;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// Deliver `event` to the named webhook once, waiting for the result.
+    pub async fn send_to(&self, name: &str, event: &WebhookEvent) -> Result<(), WebhookError> {
+        let hook = self.opened(self.hooks.get(name)?)?;
+        deliver(&self.http, &hook, event).await
+    }
+
+    /// Notify about every job run received on `runs`.
+    pub fn forward_job_runs(self: &Arc<Self>, mut runs: broadcast::Receiver<JobRun>) {
+        let notifier = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                match runs.recv().await {
+                    Ok(run) => notifier.notify(WebhookEvent::JobCompleted { run }),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Skipped notifications for {} job runs", n)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
+
+/// POST one signed event to `hook`.
+async fn deliver(
+    http: &reqwest::Client,
+    hook: &Webhook,
+    event: &WebhookEvent,
+) -> Result<(), WebhookError> {
+    let body = match hook.format {
+        PayloadFormat::Json => serde_json::to_string(event),
+        PayloadFormat::Slack => {
+            serde_json::to_string(&serde_json::json!({ "text": event.summary() }))
+        }
+        PayloadFormat::Discord => {
+            serde_json::to_string(&serde_json::json!({ "content": event.summary() }))
+        }
+    }
+    .map_err(|e| WebhookError::Delivery(e.to_string()))?;
+    let timestamp = chrono::Utc::now().timestamp();
+
+    let resp = http
+        .post(&hook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, sign(&hook.secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| WebhookError::Delivery(e.to_string()))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(WebhookError::Delivery(format!(
+            "{} returned {}",
+            hook.url, status
+        )));
+    }
+    info!(webhook = %hook.name, event = ?event.kind(), "Delivered webhook");
+    Ok(())
+}
+
+/// Signature header value for `body` sent at `timestamp`.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, routing::post, Router};
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_sign() {
+        // Matches `printf '1700000000.{}' | openssl dgst -sha256 -hmac secret`
+        assert_eq!(
+            sign("secret", 1_700_000_000, "{}"),
+            "sha256=b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_signed() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| {
+                let tx = tx.clone();
+                async move {
+                    let header = |name: &str| headers[name].to_str().unwrap().to_string();
+                    tx.send((header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER), body))
+                        .unwrap();
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let notifier = Notifier::new(WebhookStore::in_memory(), Sealer::ephemeral());
+        notifier
+            .add(Webhook {
+                name: "ops".to_string(),
+                url: format!("http://{}/hook", addr),
+                secret: "secret".to_string(),
+                events: Vec::new(),
+                format: PayloadFormat::Slack,
+            })
+            .unwrap();
+
+        let event = WebhookEvent::ProviderRecovered {
+            provider: "DataBento".to_string(),
+        };
+        notifier.send_to("ops", &event).await.unwrap();
+
+        let (timestamp, signature, body) = rx.recv().await.unwrap();
+        assert_eq!(body, r#"{"text":"Provider DataBento recovered"}"#);
+        assert_eq!(signature, sign("secret", timestamp.parse().unwrap(), &body));
+    }
+
+    #[test]
+    fn test_secrets_are_sealed() {
+        let hooks = WebhookStore::in_memory();
+        let hook = Webhook {
+            name: "ops".to_string(),
+            url: "https://example.com/hook".to_string(),
+            secret: "secret".to_string(),
+            events: Vec::new(),
+            format: PayloadFormat::Json,
+        };
+        // Saved before secrets were sealed
+        hooks.insert("old", hook.clone()).unwrap();
+        let notifier = Notifier::new(hooks, Sealer::ephemeral());
+        notifier.add(hook).unwrap();
+        assert_eq!(notifier.seal_plaintext().unwrap(), 1);
+        assert_eq!(notifier.seal_plaintext().unwrap(), 0);
+
+        for stored in notifier.list() {
+            assert!(!stored.secret.contains("secret"));
+            assert_eq!(notifier.opened(stored).unwrap().secret, "secret");
+        }
+    }
+
+    #[test]
+    fn test_add_validates_url() {
+        let notifier = Notifier::new(WebhookStore::in_memory(), Sealer::ephemeral());
+        let hook = Webhook {
+            name: "ops".to_string(),
+            url: "ftp://example.com".to_string(),
+            secret: "secret".to_string(),
+            events: Vec::new(),
+            format: PayloadFormat::Json,
+        };
+        assert!(matches!(
+            notifier.add(hook),
+            Err(WebhookError::InvalidSpec(_))
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! Paper trading desk: one simulated account per API key.
+//!
+//! Orders are submitted over REST or a live socket and filled against the
+//! trades of that key's live subscriptions. Order updates, fills and
+//! position marks are broadcast with the key they belong to, and each live
+//! socket forwards its own key's events.
+
+use chrono::{SecondsFormat, Utc};
+use shared::paper::{NewPaperOrder, PaperAccount, PaperOrder, PaperPosition};
+use shared::ticks::TickTable;
+use shared::LiveMessage;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast;
+
+/// Error type for paper trading operations.
+#[derive(Debug, thiserror::Error)]
+pub enum PaperError {
+    #[error("Invalid paper order: {0}")]
+    InvalidOrder(String),
+    #[error("No paper order {0}")]
+    NotFound(u64),
+}
+
+/// Paper accounts, keyed by API key.
+pub struct PaperDesk {
+    accounts: Mutex<HashMap<String, PaperAccount>>,
+    /// Ticks market orders fill away from the trade price
+    slippage_ticks: u32,
+    ticks: Arc<TickTable>,
+    events: broadcast::Sender<(String, LiveMessage)>,
+}
+
+impl PaperDesk {
+    pub fn new(slippage_ticks: u32, ticks: Arc<TickTable>) -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self {
+            accounts: Mutex::new(HashMap::new()),
+            slippage_ticks,
+            ticks,
+            events,
+        }
+    }
+
+    fn accounts(&self) -> MutexGuard<'_, HashMap<String, PaperAccount>> {
+        self.accounts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn publish(&self, key_id: &str, msg: LiveMessage) {
+        // No receivers just means no live socket is open
+        let _ = self.events.send((key_id.to_string(), msg));
+    }
+
+    /// Open an order for `key_id`.
+    pub fn submit(&self, key_id: &str, order: NewPaperOrder) -> Result<PaperOrder, PaperError> {
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let opened = self
+            .accounts()
+            .entry(key_id.to_string())
+            .or_default()
+            .submit(order, &now)
+            .map_err(PaperError::InvalidOrder)?;
+        self.publish(key_id, LiveMessage::PaperOrder(opened.clone()));
+        Ok(opened)
+    }
+
+    /// Cancel one of `key_id`'s open orders.
+    pub fn cancel(&self, key_id: &str, order_id: u64) -> Result<PaperOrder, PaperError> {
+        let cancelled = {
+            let mut accounts = self.accounts();
+            let account = accounts
+                .get_mut(key_id)
+                .filter(|account| account.order(order_id).is_some())
+                .ok_or(PaperError::NotFound(order_id))?;
+            account.cancel(order_id).map_err(PaperError::InvalidOrder)?
+        };
+        self.publish(key_id, LiveMessage::PaperOrder(cancelled.clone()));
+        Ok(cancelled)
+    }
+
+    /// Every order of `key_id`, oldest first.
+    pub fn orders(&self, key_id: &str) -> Vec<PaperOrder> {
+        self.accounts()
+            .get(key_id)
+            .map(PaperAccount::orders)
+            .unwrap_or_default()
+    }
+
+    /// Every position of `key_id`, by symbol.
+    pub fn positions(&self, key_id: &str) -> Vec<PaperPosition> {
+        self.accounts()
+            .get(key_id)
+            .map(PaperAccount::positions)
+            .unwrap_or_default()
+    }
+
+    /// Fill `key_id`'s orders against a trade seen on one of its live
+    /// subscriptions, and publish what changed.
+    pub fn on_trade(&self, key_id: &str, ts_event_unix_ns: u64, symbol: &str, price_i64: i64) {
+        let messages = {
+            let mut accounts = self.accounts();
+            let Some(account) = accounts.get_mut(key_id) else {
+                return;
+            };
+            // Without a known tick, market orders fill at the trade price
+            let slippage = self
+                .ticks
+                .tick_size(symbol)
+                .map_or(0, |tick| tick * self.slippage_ticks as i64);
+            account.on_trade(ts_event_unix_ns, symbol, price_i64, slippage)
+        };
+        for msg in messages {
+            self.publish(key_id, msg);
+        }
+    }
+
+    /// Receive every account's events, tagged with their API key.
+    pub fn subscribe(&self) -> broadcast::Receiver<(String, LiveMessage)> {
+        self.events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::paper::OrderSide;
+
+    #[tokio::test]
+    async fn test_events_are_tagged_by_key() {
+        let desk = PaperDesk::new(2, Arc::new(TickTable::with_defaults()));
+        let mut events = desk.subscribe();
+        let order = NewPaperOrder {
+            symbol: "ES.FUT".to_string(),
+            side: OrderSide::Sell,
+            qty: 1,
+            limit_price_i64: None,
+        };
+        desk.submit("alice", order).unwrap();
+        assert!(matches!(
+            desk.cancel("bob", 1),
+            Err(PaperError::NotFound(1))
+        ));
+
+        // Bob's trades don't touch Alice's orders
+        desk.on_trade("bob", 1, "ES.FUT", 5_000_000_000_000);
+        desk.on_trade("alice", 2, "ES.FUT", 5_000_000_000_000);
+
+        let mut fill = None;
+        while let Ok((key_id, msg)) = events.try_recv() {
+            assert_eq!(key_id, "alice");
+            if let LiveMessage::PaperFill(f) = msg {
+                fill = Some(f);
+            }
+        }
+        // Two ES ticks of 0.25 below the trade
+        assert_eq!(fill.unwrap().price_i64, 4_999_500_000_000);
+        assert_eq!(desk.positions("alice")[0].qty, -1);
+        assert!(desk.orders("bob").is_empty());
+    }
+}
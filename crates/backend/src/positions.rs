@@ -0,0 +1,80 @@
+//! Positions registered for mark-to-market, per API key.
+//!
+//! Kept in memory only; clients re-register after a restart.
+
+use shared::positions::Position;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+/// Error type for position registration.
+#[derive(Debug, thiserror::Error)]
+pub enum PositionError {
+    #[error("Invalid position: {0}")]
+    Invalid(String),
+}
+
+/// Each API key's positions, by symbol.
+#[derive(Default)]
+pub struct PositionBook {
+    positions: Mutex<HashMap<String, BTreeMap<String, Position>>>,
+}
+
+impl PositionBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn positions(&self) -> MutexGuard<'_, HashMap<String, BTreeMap<String, Position>>> {
+        self.positions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Register `positions` for `key_id`, replacing any in the same
+    /// symbols; a zero quantity removes the symbol. Returns every position
+    /// the key now holds.
+    pub fn register(
+        &self,
+        key_id: &str,
+        positions: Vec<Position>,
+    ) -> Result<Vec<Position>, PositionError> {
+        for position in &positions {
+            if position.symbol.trim().is_empty() {
+                return Err(PositionError::Invalid("symbol is empty".to_string()));
+            }
+            if position.qty != 0 && position.entry_price_i64 <= 0 {
+                return Err(PositionError::Invalid(format!(
+                    "{}: entry price must be positive",
+                    position.symbol
+                )));
+            }
+        }
+
+        let mut book = self.positions();
+        let held = book.entry(key_id.to_string()).or_default();
+        for position in positions {
+            if position.qty == 0 {
+                held.remove(&position.symbol);
+            } else {
+                held.insert(position.symbol.clone(), position);
+            }
+        }
+        Ok(held.values().cloned().collect())
+    }
+
+    /// Every position `key_id` holds, by symbol.
+    pub fn list(&self, key_id: &str) -> Vec<Position> {
+        self.positions()
+            .get(key_id)
+            .map(|held| held.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// `key_id`'s position in `symbol`, if any.
+    pub fn get(&self, key_id: &str, symbol: &str) -> Option<Position> {
+        self.positions().get(key_id)?.get(symbol).cloned()
+    }
+
+    /// Remove every position `key_id` holds.
+    pub fn clear(&self, key_id: &str) {
+        self.positions().remove(key_id);
+    }
+}
@@ -0,0 +1,144 @@
+//! Warming the caches before the server starts listening.
+//!
+//! `PREFETCH` lists historical queries to fetch at startup, e.g.
+//! `ohlcv-1m=ES.FUT,NQ.FUT@today;trades=CL.FUT@yesterday..today;morning-es`.
+//! An entry is either `schema=SYMBOL,SYMBOL@START` or `...@START..END`, with
+//! times as anywhere else (see [`shared::timeexpr`]) and END `now` by
+//! default, or the name of a saved query.
+//!
+//! Each is fetched the way `/api/historical` would fetch it, under its own
+//! usage account, so it leaves the same traces a user's request would: the
+//! symbol checks and data revisions are looked up, the bars feed the
+//! [rollups](crate::rollups) and a settled range lands in the historical
+//! cache. Failures are logged and never stop the server from starting.
+
+use crate::handlers::{self, AppState};
+use shared::timeexpr::resolve;
+use shared::{HistoricalRequest, Schema};
+use std::fmt;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Usage account prefetches are counted under.
+pub const PREFETCH_KEY: &str = "prefetch";
+
+/// One query fetched at startup.
+#[derive(Debug, Clone)]
+pub enum Prefetch {
+    /// Written out in `PREFETCH`
+    Request(Box<HistoricalRequest>),
+    /// A saved query, by name
+    Saved(String),
+}
+
+impl fmt::Display for Prefetch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Prefetch::Request(req) => write!(
+                f,
+                "{}={}@{}..{}",
+                req.schema,
+                req.symbols.join(","),
+                req.start_rfc3339,
+                req.end_rfc3339
+            ),
+            Prefetch::Saved(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Parse `PREFETCH`: entries separated by `;`.
+pub fn parse(spec: &str) -> Result<Vec<Prefetch>, String> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(parse_entry)
+        .collect()
+}
+
+fn parse_entry(entry: &str) -> Result<Prefetch, String> {
+    let Some((schema, rest)) = entry.split_once('=') else {
+        return Ok(Prefetch::Saved(entry.to_string()));
+    };
+    let (symbols, range) = rest
+        .split_once('@')
+        .ok_or_else(|| format!("'{}' should be schema=SYMBOL,SYMBOL@START[..END]", entry))?;
+    let schema: Schema = schema.trim().parse()?;
+    let symbols: Vec<String> = symbols
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if symbols.is_empty() {
+        return Err(format!("'{}' names no symbols", entry));
+    }
+    let (start, end) = range.split_once("..").unwrap_or((range, "now"));
+    let (start, end) = (start.trim(), end.trim());
+    // Times are resolved on each fetch; this only checks they would be
+    let now = chrono::Utc::now();
+    resolve(start, now)?;
+    resolve(end, now)?;
+    Ok(Prefetch::Request(Box::new(HistoricalRequest {
+        symbols,
+        schema: schema.as_str().into_owned(),
+        start_rfc3339: start.to_string(),
+        end_rfc3339: end.to_string(),
+        ..Default::default()
+    })))
+}
+
+/// Fetch each entry in turn, logging how it went.
+pub async fn run(state: &AppState, entries: &[Prefetch]) {
+    for entry in entries {
+        let req = match entry {
+            Prefetch::Request(req) => req.as_ref().clone(),
+            Prefetch::Saved(name) => match state.queries.get(name) {
+                Ok(req) => req,
+                Err(e) => {
+                    warn!(entry = %entry, "Prefetch skipped: {}", e);
+                    continue;
+                }
+            },
+        };
+        let started = Instant::now();
+        match handlers::prefetch(state, PREFETCH_KEY, &req).await {
+            Ok((records, source)) => info!(
+                entry = %entry,
+                records,
+                source = ?source,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "Prefetched"
+            ),
+            Err(e) => warn!(entry = %entry, "Prefetch failed: {}", e.error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entries =
+            parse("ohlcv-1m=ES.FUT, NQ.FUT@today; trades=CL.FUT@yesterday..today ;morning-es")
+                .unwrap();
+        assert_eq!(entries.len(), 3);
+        let Prefetch::Request(req) = &entries[0] else {
+            panic!("expected a request, got {:?}", entries[0]);
+        };
+        assert_eq!(req.symbols, ["ES.FUT", "NQ.FUT"]);
+        assert_eq!(
+            (req.start_rfc3339.as_str(), req.end_rfc3339.as_str()),
+            ("today", "now")
+        );
+        assert_eq!(entries[1].to_string(), "trades=CL.FUT@yesterday..today");
+        assert!(matches!(&entries[2], Prefetch::Saved(name) if name == "morning-es"));
+
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("trades=ES.FUT").is_err());
+        assert!(parse("trades=@today").is_err());
+        assert!(parse("candles=ES.FUT@today").is_err());
+        assert!(parse("trades=ES.FUT@someday").is_err());
+    }
+}
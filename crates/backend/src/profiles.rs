@@ -0,0 +1,279 @@
+//! Developing volume profiles and day summaries of live symbols.
+//!
+//! Every live trade stream passing through the server feeds a
+//! [`ProfileStore`], which counts each symbol's volume by price, and its
+//! open, high, low, volume and VWAP, over its current session
+//! (`PROFILE_SESSION`, CME Globex hours by default), starting over when the
+//! next session opens. Like the rollups, a symbol's trades are taken from
+//! one subscription at a time, so two sockets watching it don't count them
+//! twice; its session is dropped when that subscription ends, and the next
+//! one to see a trade starts it afresh.
+//!
+//! `GET /api/profile/:symbol` and `GET /api/day-summary` serve them so far.
+//! Every [`PUBLISH_INTERVAL`], subscriptions opened with `profile=true` get
+//! `profile_delta` messages with the levels that traded, and those opened
+//! with `day_summary=true` get the `day_summary` of each symbol that
+//! traded, with its change against the previous session's close once
+//! [that's known](crate::closes).
+
+use crate::closes::PreviousCloses;
+use chrono::DateTime;
+use shared::change::DayChange;
+use shared::profile::{DevelopingProfile, VolumeProfile};
+use shared::sessions::{Session, SessionSpec};
+use shared::summary::{DaySummary, DaySummaryBuilder};
+use shared::LiveMessage;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// How often what traded is sent as profile deltas and day summaries.
+pub const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Messages held for subscribers that fall behind.
+const UPDATE_BUFFER: usize = 1024;
+
+/// One symbol's session so far.
+struct Tracked {
+    session: Session,
+    profile: DevelopingProfile,
+    summary: DaySummaryBuilder,
+    /// Whether it traded since the last publish
+    traded: bool,
+}
+
+#[derive(Default)]
+struct State {
+    /// The subscription feeding each symbol
+    owners: HashMap<String, u64>,
+    sessions: HashMap<String, Tracked>,
+}
+
+/// Developing volume profiles and day summaries by symbol.
+pub struct ProfileStore {
+    session: SessionSpec,
+    state: Mutex<State>,
+    next_tap: AtomicU64,
+    updates: broadcast::Sender<LiveMessage>,
+}
+
+impl ProfileStore {
+    /// A store profiling each trading day's `session`.
+    pub fn new(session: SessionSpec) -> Self {
+        Self {
+            session,
+            state: Mutex::default(),
+            next_tap: AtomicU64::new(0),
+            updates: broadcast::channel(UPDATE_BUFFER).0,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A feed for one live subscription's trades.
+    pub fn tap(self: &Arc<Self>) -> ProfileTap {
+        ProfileTap {
+            store: Arc::clone(self),
+            id: self.next_tap.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    fn on_trade(&self, tap: u64, ts: u64, symbol: &str, price: i64, size: u32) {
+        let mut state = self.lock();
+        if *state.owners.entry(symbol.to_string()).or_insert(tap) != tap {
+            return;
+        }
+        let time = DateTime::from_timestamp_nanos(ts.min(i64::MAX as u64) as i64);
+        let current = state.sessions.get(symbol).map(|tracked| tracked.session);
+        if !current.is_some_and(|session| session.contains(time)) {
+            // Trades between sessions, or late for an earlier one, aren't
+            // counted
+            let Some(session) = self.session.containing(time) else {
+                return;
+            };
+            if current.is_some_and(|current| session.trading_day <= current.trading_day) {
+                return;
+            }
+            let day = session.trading_day.to_string();
+            state.sessions.insert(
+                symbol.to_string(),
+                Tracked {
+                    session,
+                    profile: DevelopingProfile::new(symbol, &day),
+                    summary: DaySummaryBuilder::new(symbol, &day),
+                    traded: false,
+                },
+            );
+        }
+        if let Some(tracked) = state.sessions.get_mut(symbol) {
+            tracked.profile.add(ts, price, size);
+            tracked.summary.add(ts, price, size);
+            tracked.traded = true;
+        }
+    }
+
+    /// Drop the sessions `tap` fed.
+    fn release(&self, tap: u64) {
+        let mut state = self.lock();
+        let State { owners, sessions } = &mut *state;
+        owners.retain(|symbol, owner| {
+            let keep = *owner != tap;
+            if !keep {
+                sessions.remove(symbol);
+            }
+            keep
+        });
+    }
+
+    /// `symbol`'s profile so far this session, if a subscription feeds it.
+    pub fn profile(&self, symbol: &str) -> Option<VolumeProfile> {
+        self.lock()
+            .sessions
+            .get(symbol)
+            .map(|tracked| tracked.profile.snapshot())
+    }
+
+    /// The day summaries of `symbols` that a subscription feeds, or of
+    /// every such symbol when `symbols` is empty, by symbol.
+    pub fn summaries(&self, symbols: &[String]) -> Vec<DaySummary> {
+        let state = self.lock();
+        let mut summaries: Vec<DaySummary> = state
+            .sessions
+            .iter()
+            .filter(|(symbol, _)| symbols.is_empty() || symbols.contains(symbol))
+            .filter_map(|(_, tracked)| tracked.summary.summary().cloned())
+            .collect();
+        summaries.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        summaries
+    }
+
+    /// Receive every symbol's profile deltas and day summaries.
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveMessage> {
+        self.updates.subscribe()
+    }
+
+    /// Send what traded since the last call: each symbol's profile delta
+    /// and day summary, its change set from `previous_close`.
+    pub fn publish(&self, previous_close: impl Fn(&DaySummary) -> Option<i64>) {
+        let mut updates = Vec::new();
+        for tracked in self.lock().sessions.values_mut() {
+            if !std::mem::take(&mut tracked.traded) {
+                continue;
+            }
+            if let Some(delta) = tracked.profile.take_delta() {
+                updates.push(LiveMessage::ProfileDelta(delta));
+            }
+            if let Some(summary) = tracked.summary.summary() {
+                updates.push(LiveMessage::DaySummary(summary.clone()));
+            }
+        }
+        for update in &mut updates {
+            if let LiveMessage::DaySummary(summary) = update {
+                summary.day_change =
+                    previous_close(summary).map(|close| DayChange::new(close, summary.last_i64));
+            }
+        }
+        for update in updates {
+            // Nobody listening is fine
+            let _ = self.updates.send(update);
+        }
+    }
+
+    /// Publish every [`PUBLISH_INTERVAL`], with the previous closes known
+    /// to `closes`.
+    pub fn spawn_publisher(self: &Arc<Self>, closes: Arc<PreviousCloses>) -> JoinHandle<()> {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(PUBLISH_INTERVAL);
+            loop {
+                ticker.tick().await;
+                store.publish(|summary| closes.cached(&summary.symbol, summary.last_unix_ns));
+            }
+        })
+    }
+}
+
+/// Feeds one live subscription's trades into a [`ProfileStore`]. The
+/// symbols it feeds are released for other subscriptions when dropped.
+pub struct ProfileTap {
+    store: Arc<ProfileStore>,
+    id: u64,
+}
+
+impl ProfileTap {
+    pub fn on_trade(&self, ts_event_unix_ns: u64, symbol: &str, price_i64: i64, size_u32: u32) {
+        self.store
+            .on_trade(self.id, ts_event_unix_ns, symbol, price_i64, size_u32);
+    }
+}
+
+impl Drop for ProfileTap {
+    fn drop(&mut self) {
+        self.store.release(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3_600_000_000_000;
+
+    #[test]
+    fn test_sessions_and_owners() {
+        // Sessions run 01:00-23:00 UTC on weekdays
+        let store = Arc::new(ProfileStore::new(
+            SessionSpec::parse("01:00-23:00 UTC").unwrap(),
+        ));
+        let mut updates = store.subscribe();
+        let (first, second) = (store.tap(), store.tap());
+        // Monday 1970-01-05 and Tuesday
+        let (day, next) = (4 * 24 * HOUR, 5 * 24 * HOUR);
+
+        first.on_trade(day + 2 * HOUR, "ES.FUT", 100, 3);
+        // Another subscription's copy of the same trade isn't counted
+        second.on_trade(day + 2 * HOUR, "ES.FUT", 100, 3);
+        // Between sessions
+        first.on_trade(day + 23 * HOUR + 1, "ES.FUT", 100, 50);
+        let profile = store.profile("ES.FUT").unwrap();
+        assert_eq!(
+            (profile.trading_day.as_str(), profile.volume_u64),
+            ("1970-01-05", 3)
+        );
+
+        store.publish(|_| Some(99));
+        let LiveMessage::ProfileDelta(delta) = updates.try_recv().unwrap() else {
+            panic!("expected a profile delta");
+        };
+        assert!(delta.reset);
+        let LiveMessage::DaySummary(summary) = updates.try_recv().unwrap() else {
+            panic!("expected a day summary");
+        };
+        assert_eq!((summary.open_i64, summary.volume_u64), (100, 3));
+        assert_eq!(summary.day_change.unwrap().net_change_i64, 1);
+        // Nothing traded since
+        store.publish(|_| None);
+        assert!(updates.try_recv().is_err());
+
+        // The next session starts over, and an earlier one's trade is late
+        first.on_trade(next + 2 * HOUR, "ES.FUT", 101, 1);
+        first.on_trade(day + 3 * HOUR, "ES.FUT", 100, 7);
+        let profile = store.profile("ES.FUT").unwrap();
+        assert_eq!(
+            (profile.trading_day.as_str(), profile.volume_u64),
+            ("1970-01-06", 1)
+        );
+        assert_eq!(store.summaries(&[])[0].open_i64, 101);
+
+        // Dropped with its feed; the other subscription starts it afresh
+        drop(first);
+        assert!(store.profile("ES.FUT").is_none());
+        second.on_trade(next + 4 * HOUR, "ES.FUT", 102, 2);
+        assert_eq!(store.profile("ES.FUT").unwrap().volume_u64, 2);
+    }
+}
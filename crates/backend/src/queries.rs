@@ -0,0 +1,122 @@
+//! Named historical query templates, persisted to a local JSON file.
+//!
+//! A saved query is a `HistoricalRequest` stored under a name. Running it
+//! re-issues the request, optionally with some fields overridden, so
+//! recurring pulls don't need the full JSON body each time. Start and end may
+//! be relative (`now-6h`, see [`shared::timeexpr`]), which makes a saved
+//! query a rolling window that is resolved on every run.
+
+use crate::service::ServiceError;
+use crate::store::{NamedStore, StoreError};
+use chrono::{SecondsFormat, Utc};
+use serde::Deserialize;
+use shared::timeexpr::parse_lookback;
+use shared::HistoricalRequest;
+
+/// Error type for saved query operations.
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    #[error("Saved query: {0}")]
+    Store(#[from] StoreError),
+    #[error("Invalid override: {0}")]
+    InvalidOverride(String),
+    #[error(transparent)]
+    Service(#[from] ServiceError),
+}
+
+/// Overrides applied to a saved query when it is run.
+#[derive(Debug, Default, Deserialize)]
+pub struct QueryOverrides {
+    /// Comma-separated symbols
+    pub symbols: Option<String>,
+    pub start_rfc3339: Option<String>,
+    pub end_rfc3339: Option<String>,
+    /// Look back this far from now (e.g. 30m, 2h, 1d); replaces start/end
+    pub last: Option<String>,
+    pub limit: Option<u32>,
+    pub dataset: Option<String>,
+}
+
+impl QueryOverrides {
+    /// Apply the overrides to a copy of `template`.
+    pub fn apply(&self, template: &HistoricalRequest) -> Result<HistoricalRequest, QueryError> {
+        let mut req = template.clone();
+
+        if let Some(symbols) = &self.symbols {
+            req.symbols = symbols
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Some(start) = &self.start_rfc3339 {
+            req.start_rfc3339 = start.clone();
+        }
+        if let Some(end) = &self.end_rfc3339 {
+            req.end_rfc3339 = end.clone();
+        }
+        if let Some(last) = &self.last {
+            let end = Utc::now();
+            let start = end
+                .checked_sub_signed(parse_lookback(last).map_err(QueryError::InvalidOverride)?)
+                .ok_or_else(|| {
+                    QueryError::InvalidOverride(format!("last '{}' out of range", last))
+                })?;
+            req.start_rfc3339 = start.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+            req.end_rfc3339 = end.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        }
+        if let Some(limit) = self.limit {
+            req.limit = limit;
+        }
+        if let Some(dataset) = &self.dataset {
+            req.dataset = Some(dataset.clone());
+        }
+
+        Ok(req)
+    }
+}
+
+/// Saved query templates, keyed by name.
+pub type QueryStore = NamedStore<HistoricalRequest>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> HistoricalRequest {
+        HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-01T01:00:00Z".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_overrides() {
+        let overrides = QueryOverrides {
+            symbols: Some("CL.FUT, NQ.FUT".to_string()),
+            limit: Some(10),
+            ..Default::default()
+        };
+        let req = overrides.apply(&template()).unwrap();
+        assert_eq!(req.symbols, vec!["CL.FUT", "NQ.FUT"]);
+        assert_eq!(req.limit, 10);
+        assert_eq!(req.start_rfc3339, "2024-01-01T00:00:00Z");
+
+        let req = QueryOverrides {
+            last: Some("2h".to_string()),
+            ..Default::default()
+        }
+        .apply(&template())
+        .unwrap();
+        assert_ne!(req.start_rfc3339, "2024-01-01T00:00:00Z");
+
+        let bad = QueryOverrides {
+            last: Some("2w".to_string()),
+            ..Default::default()
+        };
+        assert!(bad.apply(&template()).is_err());
+    }
+}
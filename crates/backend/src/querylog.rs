@@ -0,0 +1,229 @@
+//! Log of served historical queries, for finding slow and costly ones.
+//!
+//! Every historical fetch is recorded with its duration, record count,
+//! cache outcome and estimated upstream cost. Entries are appended to a
+//! JSON Lines file; the newest [`CAPACITY`] are kept in memory and the file
+//! is cut down to them whenever the log is opened.
+
+use shared::querylog::{QueryLogFilter, QueryRecord, QuerySort};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tracing::warn;
+
+/// Queries kept, newest first.
+pub const CAPACITY: usize = 10_000;
+
+/// Entries returned when a filter sets no limit.
+pub const DEFAULT_LIMIT: usize = 50;
+
+/// Error type for the query log.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to persist query log: {0}")]
+pub struct QueryLogError(pub String);
+
+struct Entries {
+    next_id: u64,
+    records: VecDeque<QueryRecord>,
+    file: Option<File>,
+}
+
+/// Recent historical queries, optionally persisted.
+pub struct QueryLog {
+    path: Option<PathBuf>,
+    entries: Mutex<Entries>,
+}
+
+impl QueryLog {
+    /// A log that is not persisted.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            entries: Mutex::new(Entries {
+                next_id: 1,
+                records: VecDeque::new(),
+                file: None,
+            }),
+        }
+    }
+
+    /// Open the log at `path`, loading the newest entries saved there.
+    /// Unreadable lines, such as one cut short by a crash, are skipped.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, QueryLogError> {
+        let path = path.into();
+        let storage = |e: std::io::Error| QueryLogError(format!("{}: {}", path.display(), e));
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(storage(e)),
+        };
+
+        let mut records = VecDeque::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str::<QueryRecord>(line) {
+                Ok(record) => {
+                    records.push_back(record);
+                    if records.len() > CAPACITY {
+                        records.pop_front();
+                    }
+                }
+                Err(e) => warn!("Skipping query log entry in {}: {}", path.display(), e),
+            }
+        }
+        rewrite(&path, &records).map_err(storage)?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(storage)?;
+
+        Ok(Self {
+            entries: Mutex::new(Entries {
+                next_id: records.back().map_or(1, |r| r.id + 1),
+                records,
+                file: Some(file),
+            }),
+            path: Some(path),
+        })
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a query, assigning its id. A failed write is logged rather than
+    /// failing the query it describes.
+    pub fn record(&self, mut record: QueryRecord) {
+        let mut entries = self.entries();
+        record.id = entries.next_id;
+        entries.next_id += 1;
+        if let Some(file) = &mut entries.file {
+            let written = serde_json::to_string(&record)
+                .map_err(std::io::Error::other)
+                .and_then(|line| writeln!(file, "{}", line));
+            if let Err(e) = written {
+                let path = self.path.as_deref().unwrap_or(Path::new(""));
+                warn!("Failed to append to query log {}: {}", path.display(), e);
+            }
+        }
+        entries.records.push_back(record);
+        if entries.records.len() > CAPACITY {
+            entries.records.pop_front();
+        }
+    }
+
+    /// Queries matching `filter`, in its order.
+    pub fn select(&self, filter: &QueryLogFilter) -> Vec<QueryRecord> {
+        let entries = self.entries();
+        let mut matching: Vec<&QueryRecord> = entries
+            .records
+            .iter()
+            .rev()
+            .filter(|r| filter.key_id.as_ref().is_none_or(|k| &r.key_id == k))
+            .filter(|r| filter.min_duration_ms.is_none_or(|d| r.duration_ms >= d))
+            .collect();
+        // Stable sorts keep the newest first among equals
+        match filter.sort {
+            QuerySort::Recent => {}
+            QuerySort::Slowest => matching.sort_by_key(|r| std::cmp::Reverse(r.duration_ms)),
+            QuerySort::Costliest => matching.sort_by(|a, b| b.cost_usd.total_cmp(&a.cost_usd)),
+        }
+        matching
+            .into_iter()
+            .take(filter.limit.unwrap_or(DEFAULT_LIMIT))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Replace the file at `path` with `records`, via a temporary file so a
+/// crash never leaves a truncated log behind.
+fn rewrite(path: &Path, records: &VecDeque<QueryRecord>) -> std::io::Result<()> {
+    let mut text = String::new();
+    for record in records {
+        text.push_str(&serde_json::to_string(record).map_err(std::io::Error::other)?);
+        text.push('\n');
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, text)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::querylog::CacheStatus;
+
+    fn query(key_id: &str, duration_ms: u64, cost_usd: f64) -> QueryRecord {
+        QueryRecord {
+            id: 0,
+            at: "2024-07-01T00:00:00Z".to_string(),
+            key_id: key_id.to_string(),
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "trades".to_string(),
+            start_rfc3339: "2024-06-30T00:00:00Z".to_string(),
+            end_rfc3339: "2024-07-01T00:00:00Z".to_string(),
+            dataset: None,
+            duration_ms,
+            records: 10,
+            cache: CacheStatus::Miss,
+            cost_usd,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_filters_and_persistence() {
+        let dir = std::env::temp_dir().join(format!("sf-querylog-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("queries.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let log = QueryLog::open(&path).unwrap();
+        log.record(query("a", 100, 0.5));
+        log.record(query("b", 900, 0.1));
+        log.record(query("a", 300, 2.0));
+
+        let ids = |filter: QueryLogFilter| -> Vec<u64> {
+            log.select(&filter).into_iter().map(|r| r.id).collect()
+        };
+        assert_eq!(ids(QueryLogFilter::default()), [3, 2, 1]);
+        let slowest = QueryLogFilter {
+            sort: QuerySort::Slowest,
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(ids(slowest), [2, 3]);
+        let costliest_a = QueryLogFilter {
+            sort: QuerySort::Costliest,
+            key_id: Some("a".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(costliest_a), [3, 1]);
+        let slow = QueryLogFilter {
+            min_duration_ms: Some(300),
+            ..Default::default()
+        };
+        assert_eq!(ids(slow), [3, 2]);
+
+        // A torn last line is skipped and ids continue after a reopen
+        drop(log);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"id\":4,\"at\"").unwrap();
+        let reopened = QueryLog::open(&path).unwrap();
+        assert_eq!(reopened.len(), 3);
+        reopened.record(query("c", 1, 0.0));
+        assert_eq!(reopened.select(&QueryLogFilter::default())[0].id, 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,200 @@
+//! Fair queuing of upstream historical fetches.
+//!
+//! At most `HISTORICAL_CONCURRENCY` historical fetches run at once. Past
+//! that, fetches wait in a queue per API key, and a finishing fetch hands
+//! its slot to the next key in turn rather than the next request, so a key
+//! with a 20-request batch waiting gets one slot in every round, not all of
+//! them, and a key asking for one chart waits behind at most one request
+//! from each other key.
+
+use shared::metrics::HistoricalQueueStats;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+#[derive(Default)]
+struct Inner {
+    running: usize,
+    /// Waiting requests by key, oldest first
+    waiting: HashMap<String, VecDeque<oneshot::Sender<Permit>>>,
+    /// Keys with waiting requests, in the order they get a slot
+    turns: VecDeque<String>,
+    queued_total: u64,
+}
+
+impl Inner {
+    fn waiting(&self) -> usize {
+        self.waiting.values().map(VecDeque::len).sum()
+    }
+
+    /// The next key's oldest waiter, its key moved to the back of the line
+    /// if it has more.
+    fn next_waiter(&mut self) -> Option<oneshot::Sender<Permit>> {
+        let key = self.turns.pop_front()?;
+        let queue = self.waiting.get_mut(&key)?;
+        let waiter = queue.pop_front();
+        if queue.is_empty() {
+            self.waiting.remove(&key);
+        } else {
+            self.turns.push_back(key);
+        }
+        waiter
+    }
+}
+
+/// Limits concurrent historical fetches, taking turns between keys.
+pub struct FairQueue {
+    max_concurrent: usize,
+    inner: Mutex<Inner>,
+}
+
+impl FairQueue {
+    /// A queue running at most `max_concurrent` fetches at once (at least
+    /// one).
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            max_concurrent: max_concurrent.max(1),
+            inner: Mutex::default(),
+        }
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for a slot for `key_id`, held until the permit drops.
+    pub async fn acquire(self: &Arc<Self>, key_id: &str) -> Permit {
+        let receiver = {
+            let mut inner = self.inner();
+            if inner.running < self.max_concurrent && inner.turns.is_empty() {
+                inner.running += 1;
+                return Permit {
+                    queue: self.clone(),
+                };
+            }
+            let (sender, receiver) = oneshot::channel();
+            match inner.waiting.get_mut(key_id) {
+                Some(queue) => queue.push_back(sender),
+                None => {
+                    inner
+                        .waiting
+                        .insert(key_id.to_string(), VecDeque::from([sender]));
+                    inner.turns.push_back(key_id.to_string());
+                }
+            }
+            inner.queued_total += 1;
+            receiver
+        };
+        // Senders are only dropped after sending
+        receiver.await.expect("fair queue waiter dropped")
+    }
+
+    /// Pass a finished fetch's slot on, or free it if nobody waits.
+    fn release(self: &Arc<Self>) {
+        let waiter = {
+            let mut inner = self.inner();
+            match inner.next_waiter() {
+                Some(waiter) => waiter,
+                None => {
+                    inner.running -= 1;
+                    return;
+                }
+            }
+        };
+        // A waiter that gave up hands the permit back, and dropping it
+        // passes the slot on to the next
+        let _ = waiter.send(Permit {
+            queue: self.clone(),
+        });
+    }
+
+    pub fn stats(&self) -> HistoricalQueueStats {
+        let inner = self.inner();
+        HistoricalQueueStats {
+            max_concurrent: self.max_concurrent,
+            running: inner.running,
+            waiting: inner.waiting(),
+            waiting_keys: inner.turns.len(),
+            queued_total: inner.queued_total,
+        }
+    }
+}
+
+/// A slot in the [`FairQueue`], handed to the next waiter when dropped.
+pub struct Permit {
+    queue: Arc<FairQueue>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn until_waiting(queue: &FairQueue, n: usize) {
+        while queue.stats().waiting < n {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_takes_turns_between_keys() {
+        let queue = Arc::new(FairQueue::new(1));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let first = queue.acquire("a").await;
+
+        let mut tasks = Vec::new();
+        for (i, (key, label)) in [("a", "a2"), ("a", "a3"), ("a", "a4"), ("b", "b1")]
+            .into_iter()
+            .enumerate()
+        {
+            let (waiter, order) = (queue.clone(), order.clone());
+            tasks.push(tokio::spawn(async move {
+                let _permit = waiter.acquire(key).await;
+                order.lock().unwrap().push(label);
+            }));
+            until_waiting(&queue, i + 1).await;
+        }
+        let stats = queue.stats();
+        assert_eq!(
+            (stats.running, stats.waiting, stats.waiting_keys),
+            (1, 4, 2)
+        );
+
+        drop(first);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["a2", "b1", "a3", "a4"]);
+        let stats = queue.stats();
+        assert_eq!(
+            (stats.running, stats.waiting, stats.queued_total),
+            (0, 0, 4)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skips_waiters_that_gave_up() {
+        let queue = Arc::new(FairQueue::new(1));
+        let first = queue.acquire("a").await;
+        let gave_up = {
+            let queue = queue.clone();
+            tokio::spawn(async move {
+                let _permit = queue.acquire("b").await;
+            })
+        };
+        until_waiting(&queue, 1).await;
+        gave_up.abort();
+        let _ = gave_up.await;
+
+        drop(first);
+        assert_eq!(queue.stats().running, 0);
+        let _again = queue.acquire("c").await;
+        assert_eq!(queue.stats().running, 1);
+    }
+}
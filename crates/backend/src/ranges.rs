@@ -0,0 +1,209 @@
+//! Per-schema limits on how long a historical range may be.
+//!
+//! A month of trades is millions of records and won't come back in one
+//! response, so each schema has a longest range it serves. A request over
+//! it is rejected up front with the limit and the coarsest-needed schema
+//! that would cover the range, or a pointer to scheduled jobs (which pull
+//! into files and aren't limited) when none would. Limits can be changed
+//! with the `MAX_RANGES` environment variable, e.g.:
+//!
+//! ```text
+//! MAX_RANGES="trades=1d;ohlcv-1m=none"
+//! ```
+//!
+//! Derived schemas built from trades (`cvd-1m`, `tickbar-500`, ...) use the
+//! `trades` limit unless given their own.
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Duration};
+use shared::timeexpr::parse_lookback;
+use shared::{HistoricalRequest, RangeErrorResponse, Schema};
+
+/// Schemas a too-long range can fall back to, finest first.
+const COARSER: [Schema; 2] = [Schema::Ohlcv1S, Schema::Ohlcv1M];
+
+/// A range longer than its schema allows.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Range of {} exceeds the {} allowed for {}; {}",
+    format_span(*requested),
+    format_span(*max),
+    schema,
+    suggestion(suggested_schema.as_deref())
+)]
+pub struct RangeError {
+    pub schema: String,
+    pub requested: Duration,
+    pub max: Duration,
+    /// Coarser schema whose limit covers the range, if any
+    pub suggested_schema: Option<String>,
+}
+
+impl IntoResponse for RangeError {
+    fn into_response(self) -> Response {
+        let status = StatusCode::BAD_REQUEST;
+        let body = RangeErrorResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+            requested_secs: self.requested.num_seconds(),
+            max_secs: self.max.num_seconds(),
+            suggest_job: self.suggested_schema.is_none(),
+            suggested_schema: self.suggested_schema,
+            schema: self.schema,
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+fn suggestion(schema: Option<&str>) -> String {
+    match schema {
+        Some(schema) => format!("request {} instead or split the range", schema),
+        None => "split the range or pull it with a scheduled job (POST /api/jobs)".to_string(),
+    }
+}
+
+/// `span` in the largest whole unit, e.g. `3d`, `36h` or `90m`.
+fn format_span(span: Duration) -> String {
+    let secs = span.num_seconds();
+    [(86_400, "d"), (3_600, "h"), (60, "m")]
+        .iter()
+        .find(|(unit, _)| secs % unit == 0 && secs >= *unit)
+        .map_or_else(
+            || format!("{}s", secs),
+            |(unit, suffix)| format!("{}{}", secs / unit, suffix),
+        )
+}
+
+/// Longest range served per schema.
+#[derive(Debug, Clone)]
+pub struct RangePolicy {
+    /// Schema name and its limit; `None` is unlimited
+    limits: Vec<(String, Option<Duration>)>,
+}
+
+impl Default for RangePolicy {
+    fn default() -> Self {
+        Self {
+            limits: vec![
+                ("trades".to_string(), Some(Duration::days(3))),
+                ("ohlcv-1s".to_string(), Some(Duration::days(30))),
+                ("ohlcv-1m".to_string(), Some(Duration::days(730))),
+            ],
+        }
+    }
+}
+
+impl RangePolicy {
+    /// Apply `schema=span;...` overrides, where a span is a lookback such
+    /// as `3d` or `12h`, or `none` to lift the schema's limit.
+    pub fn apply_spec(&mut self, spec: &str) -> Result<(), String> {
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let (schema, span) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid range limit '{}': expected schema=span", rule))?;
+            let schema = schema.trim();
+            schema.parse::<Schema>()?;
+            let limit = match span.trim() {
+                "none" => None,
+                span => Some(parse_lookback(span)?),
+            };
+            match self.limits.iter_mut().find(|(s, _)| s == schema) {
+                Some(entry) => entry.1 = limit,
+                None => self.limits.push((schema.to_string(), limit)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Longest range served for `schema`, or `None` if unlimited.
+    pub fn max_range(&self, schema: &Schema) -> Option<Duration> {
+        let lookup = |name: &str| self.limits.iter().find(|(s, _)| s == name).map(|e| e.1);
+        match lookup(&schema.as_str()) {
+            Some(limit) => limit,
+            None if schema.cvd_interval_ns().is_some() || schema.bar_spec().is_some() => {
+                lookup("trades").flatten()
+            }
+            None => None,
+        }
+    }
+
+    /// Check `req`'s range against its schema's limit. Requests whose
+    /// schema or times don't parse are left for the provider to reject.
+    pub fn check(&self, req: &HistoricalRequest) -> Result<(), RangeError> {
+        let Ok(schema) = req.schema.parse::<Schema>() else {
+            return Ok(());
+        };
+        let (Ok(start), Ok(end)) = (
+            DateTime::parse_from_rfc3339(&req.start_rfc3339),
+            DateTime::parse_from_rfc3339(&req.end_rfc3339),
+        ) else {
+            return Ok(());
+        };
+        let requested = end - start;
+        let Some(max) = self.max_range(&schema).filter(|max| requested > *max) else {
+            return Ok(());
+        };
+
+        let finer = COARSER.iter().position(|s| *s == schema);
+        let suggested_schema = COARSER
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| finer.is_none_or(|f| *i > f))
+            .find(|(_, s)| self.max_range(s).is_none_or(|m| requested <= m))
+            .map(|(_, s)| s.as_str().into_owned());
+        Err(RangeError {
+            schema: schema.as_str().into_owned(),
+            requested,
+            max,
+            suggested_schema,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(schema: &str, days: i64) -> HistoricalRequest {
+        let start = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap();
+        HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: schema.to_string(),
+            start_rfc3339: start.to_rfc3339(),
+            end_rfc3339: (start + Duration::days(days)).to_rfc3339(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_suggests_coarser_schema_or_job() {
+        let policy = RangePolicy::default();
+        assert!(policy.check(&request("trades", 3)).is_ok());
+
+        let e = policy.check(&request("trades", 10)).unwrap_err();
+        assert_eq!(e.suggested_schema.as_deref(), Some("ohlcv-1s"));
+        assert_eq!(
+            e.to_string(),
+            "Range of 10d exceeds the 3d allowed for trades; \
+             request ohlcv-1s instead or split the range"
+        );
+        // Derived schemas fall back to the trades limit
+        let e = policy.check(&request("tickbar-500", 90)).unwrap_err();
+        assert_eq!(e.suggested_schema.as_deref(), Some("ohlcv-1m"));
+
+        let e = policy.check(&request("ohlcv-1m", 1000)).unwrap_err();
+        assert_eq!(e.suggested_schema, None);
+    }
+
+    #[test]
+    fn test_spec_overrides() {
+        let mut policy = RangePolicy::default();
+        policy.apply_spec("trades=12h; ohlcv-1m=none").unwrap();
+        assert_eq!(policy.max_range(&Schema::Trades), Some(Duration::hours(12)));
+        assert!(policy.check(&request("ohlcv-1m", 5000)).is_ok());
+        assert!(policy.apply_spec("trades").is_err());
+        assert!(policy.apply_spec("ticks=1d").is_err());
+    }
+}
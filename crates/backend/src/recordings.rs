@@ -0,0 +1,834 @@
+//! Live data recorded to disk, compacted into Parquet as it ages.
+//!
+//! With `RECORD_DIR` set, the server holds its own live subscription for
+//! `RECORD_SYMBOLS` and `RECORD_SCHEMAS`, the way the egress does, and
+//! appends each trade or bar as its WebSocket JSON to a hot NDJSON file per
+//! schema, UTC day and symbol: `{dir}/{schema}/{day}/{symbol}.ndjson`.
+//!
+//! Once a day is over, the compaction job rewrites each of its files as a
+//! Snappy-compressed Parquet partition of the schema's historical records
+//! (`{symbol}.parquet` beside it), adds the partition to `{dir}/index.json`
+//! with its record count and time span, and only then deletes the NDJSON.
+//! A run interrupted part way leaves either the NDJSON to compact again or
+//! a Parquet file the next start indexes. Queries read the indexed
+//! partitions overlapping their range and the hot files not yet compacted.
+//!
+//! With `RECORD_DBN` the recorder's DataBento sessions are also written
+//! out as DBN under `{dir}/dbn/{day}/`; those files are only kept within
+//! the retention limits, never indexed or queried.
+
+use crate::egress::{EgressError, Publisher};
+use crate::export::{read_parquet, write_compressed_parquet, ExportError};
+use crate::retention::{remove_empty_parents, Storage, StoredFile};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use shared::recordings::{CompactionReport, PartitionFormat, RecordingPartition};
+use shared::{
+    HistoricalRequest, HistoricalResponse, LiveMessage, OhlcvRecord, Schema, TradeRecord,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// How often hot files are flushed to disk.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often finished days are compacted.
+pub const COMPACT_INTERVAL: Duration = Duration::from_secs(3600);
+
+const INDEX_FILE: &str = "index.json";
+const HOT_EXTENSION: &str = "ndjson";
+const COLD_EXTENSION: &str = "parquet";
+/// Directory under the recordings the DBN tee writes to, by UTC day
+pub const DBN_DIR: &str = "dbn";
+/// Suffix of imported partitions' file stems, `{symbol}.imported.parquet`,
+/// so they never collide with a recorded day being compacted
+const IMPORTED_SUFFIX: &str = ".imported";
+
+/// Error type for recordings.
+#[derive(Debug, thiserror::Error)]
+pub enum RecordingError {
+    #[error("Recording is off; set RECORD_DIR to record live data")]
+    Disabled,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Export(#[from] ExportError),
+    #[error("Invalid recording index: {0}")]
+    Index(String),
+    #[error("{0}")]
+    Query(String),
+}
+
+/// A day's NDJSON file being appended to.
+struct HotFile {
+    day: NaiveDate,
+    writer: BufWriter<File>,
+}
+
+/// Recorded live data under one directory.
+pub struct Recordings {
+    dir: PathBuf,
+    /// Open hot files by schema and symbol
+    hot: Mutex<HashMap<(String, String), HotFile>>,
+    /// Compacted partitions by path
+    index: Mutex<BTreeMap<String, RecordingPartition>>,
+    /// Held for a compaction run, so runs don't overlap
+    compacting: Mutex<()>,
+}
+
+/// Name of `symbol`'s partition files: symbols are used as they are,
+/// except for characters a file name can't safely hold.
+fn file_stem(symbol: &str) -> String {
+    symbol
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Nanoseconds since the epoch of an RFC3339 time.
+fn parse_ns(ts: &str) -> Result<u64, RecordingError> {
+    DateTime::parse_from_rfc3339(ts)
+        .ok()
+        .and_then(|t| t.timestamp_nanos_opt())
+        .map(|ns| ns.max(0) as u64)
+        .ok_or_else(|| RecordingError::Query(format!("invalid time '{}'", ts)))
+}
+
+/// Whether messages of `schema` can be recorded, compacted and queried.
+pub fn recordable(schema: &Schema) -> bool {
+    matches!(schema, Schema::Trades | Schema::Ohlcv1S | Schema::Ohlcv1M)
+}
+
+/// UTC day of a nanosecond timestamp.
+fn day_of(ns: u64) -> NaiveDate {
+    DateTime::from_timestamp_nanos(ns.min(i64::MAX as u64) as i64).date_naive()
+}
+
+/// The records of `schema` in an NDJSON recording, for `keep` symbols and
+/// timestamps. Lines that don't parse, such as one cut short by a crash,
+/// are counted and skipped.
+fn read_hot(
+    path: &Path,
+    schema: &Schema,
+    keep: impl Fn(&str, u64) -> bool,
+) -> Result<(HistoricalResponse, usize), RecordingError> {
+    let mut trades = Vec::new();
+    let mut bars = Vec::new();
+    let mut skipped = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        match serde_json::from_str::<LiveMessage>(&line?) {
+            Ok(LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+            }) if keep(&symbol, ts_event_unix_ns) => trades.push(TradeRecord {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+                quote: None,
+                sequence_u32: None,
+            }),
+            Ok(LiveMessage::Ohlcv {
+                ts_event_unix_ns,
+                symbol,
+                open_i64,
+                high_i64,
+                low_i64,
+                close_i64,
+                volume_u64,
+                ..
+            }) if keep(&symbol, ts_event_unix_ns) => bars.push(OhlcvRecord {
+                ts_event_unix_ns,
+                symbol,
+                open_i64,
+                high_i64,
+                low_i64,
+                close_i64,
+                volume_u64,
+            }),
+            Ok(_) => {}
+            Err(_) => skipped += 1,
+        }
+    }
+    let mut resp = match schema {
+        Schema::Trades => HistoricalResponse::Trades { data: trades },
+        _ => HistoricalResponse::from_bars(schema, bars)
+            .unwrap_or(HistoricalResponse::Trades { data: Vec::new() }),
+    };
+    resp.sort_by_time();
+    Ok((resp, skipped))
+}
+
+/// `resp`'s records by UTC day and symbol.
+fn by_day(resp: HistoricalResponse) -> BTreeMap<(NaiveDate, String), HistoricalResponse> {
+    fn group<T>(
+        data: Vec<T>,
+        key: impl Fn(&T) -> (u64, &str),
+    ) -> BTreeMap<(NaiveDate, String), Vec<T>> {
+        let mut days: BTreeMap<(NaiveDate, String), Vec<T>> = BTreeMap::new();
+        for record in data {
+            let (ts, symbol) = key(&record);
+            let day = (day_of(ts), symbol.to_string());
+            days.entry(day).or_default().push(record);
+        }
+        days
+    }
+    let schema = resp.schema();
+    match resp {
+        HistoricalResponse::Trades { data } => group(data, |r| (r.ts_event_unix_ns, &r.symbol))
+            .into_iter()
+            .map(|(day, data)| (day, HistoricalResponse::Trades { data }))
+            .collect(),
+        HistoricalResponse::Ohlcv1S { data }
+        | HistoricalResponse::Ohlcv1M { data }
+        | HistoricalResponse::Cvd1S { data }
+        | HistoricalResponse::Cvd1M { data }
+        | HistoricalResponse::Bars { data, .. } => group(data, |r| (r.ts_event_unix_ns, &r.symbol))
+            .into_iter()
+            .filter_map(|(day, data)| Some((day, HistoricalResponse::from_bars(&schema, data)?)))
+            .collect(),
+    }
+}
+
+impl Recordings {
+    /// Open the recordings under `dir`, creating it if needed. Parquet
+    /// partitions missing from the index, left by a compaction that
+    /// stopped part way, are indexed; entries whose file is gone are
+    /// dropped.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, RecordingError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let index_path = dir.join(INDEX_FILE);
+        let saved: Vec<RecordingPartition> = match std::fs::read_to_string(&index_path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| RecordingError::Index(format!("{}: {}", index_path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let recordings = Self {
+            dir,
+            hot: Mutex::new(HashMap::new()),
+            index: Mutex::new(saved.into_iter().map(|p| (p.path.clone(), p)).collect()),
+            compacting: Mutex::new(()),
+        };
+
+        let on_disk = recordings.scan(COLD_EXTENSION)?;
+        let mut index = recordings.index.lock().unwrap();
+        let mut changed = false;
+        index.retain(|path, _| {
+            let kept = on_disk.iter().any(|p| &p.path == path);
+            changed |= !kept;
+            kept
+        });
+        for partition in on_disk {
+            if !index.contains_key(&partition.path) {
+                let partition = recordings.describe_cold(partition)?;
+                index.insert(partition.path.clone(), partition);
+                changed = true;
+            }
+        }
+        if changed {
+            recordings.persist(&index)?;
+        }
+        drop(index);
+        Ok(recordings)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Append a live message's JSON to `symbol`'s hot file of `schema` for
+    /// `day`, opening it if needed.
+    fn append(
+        &self,
+        schema: &str,
+        symbol: &str,
+        day: NaiveDate,
+        line: &[u8],
+    ) -> std::io::Result<()> {
+        let mut hot = self.hot.lock().unwrap();
+        let key = (schema.to_string(), symbol.to_string());
+        let file = match hot.get_mut(&key) {
+            Some(file) if file.day == day => file,
+            _ => {
+                let dir = self.dir.join(schema).join(day.to_string());
+                std::fs::create_dir_all(&dir)?;
+                let path = dir.join(format!("{}.{}", file_stem(symbol), HOT_EXTENSION));
+                let writer =
+                    BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+                // The previous day's file, if any, is flushed as it's dropped
+                if let Some(mut previous) = hot.insert(key.clone(), HotFile { day, writer }) {
+                    previous.writer.flush()?;
+                }
+                hot.get_mut(&key).expect("just inserted")
+            }
+        };
+        file.writer.write_all(line)?;
+        file.writer.write_all(b"\n")
+    }
+
+    /// Whether live `schema` messages for `symbol` are being recorded: its
+    /// hot file for the day is open.
+    pub fn is_recording(&self, schema: &str, symbol: &str) -> bool {
+        self.hot
+            .lock()
+            .unwrap()
+            .contains_key(&(schema.to_string(), symbol.to_string()))
+    }
+
+    /// Write buffered messages to the hot files.
+    pub fn flush(&self) {
+        for ((schema, symbol), file) in self.hot.lock().unwrap().iter_mut() {
+            if let Err(e) = file.writer.flush() {
+                warn!(schema = %schema, symbol = %symbol, "Failed to flush recording: {}", e);
+            }
+        }
+    }
+
+    /// Flush and close the hot files of days before `day`.
+    fn close_before(&self, day: NaiveDate) {
+        self.hot.lock().unwrap().retain(|(schema, symbol), file| {
+            if file.day >= day {
+                return true;
+            }
+            if let Err(e) = file.writer.flush() {
+                warn!(schema = %schema, symbol = %symbol, "Failed to flush recording: {}", e);
+            }
+            false
+        });
+    }
+
+    /// Flush hot files every [`FLUSH_INTERVAL`].
+    pub fn spawn_flusher(self: &Arc<Self>) -> JoinHandle<()> {
+        let recordings = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                ticker.tick().await;
+                recordings.flush();
+            }
+        })
+    }
+
+    /// Compact finished days now and every [`COMPACT_INTERVAL`].
+    pub fn spawn_compactor(self: &Arc<Self>) -> JoinHandle<()> {
+        let recordings = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(COMPACT_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let recordings = recordings.clone();
+                let today = Utc::now().date_naive();
+                let report = tokio::task::spawn_blocking(move || recordings.compact(today)).await;
+                match report {
+                    Ok(report) if !report.compacted.is_empty() || !report.failed.is_empty() => {
+                        info!(
+                            compacted = report.compacted.len(),
+                            bytes_before = report.bytes_before,
+                            bytes_after = report.bytes_after,
+                            failed = report.failed.len(),
+                            "Compacted recordings"
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Compaction task failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Partition files with `extension` on disk, as
+    /// `{schema}/{day}/{stem}.{extension}`.
+    fn scan(&self, extension: &str) -> std::io::Result<Vec<RecordingPartition>> {
+        let mut found = Vec::new();
+        let dirs = |path: &Path| -> std::io::Result<Vec<(String, PathBuf)>> {
+            let mut dirs = Vec::new();
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    dirs.push((
+                        entry.file_name().to_string_lossy().into_owned(),
+                        entry.path(),
+                    ));
+                }
+            }
+            Ok(dirs)
+        };
+        for (schema, schema_dir) in dirs(&self.dir)? {
+            for (day, day_dir) in dirs(&schema_dir)? {
+                if day.parse::<NaiveDate>().is_err() {
+                    continue;
+                }
+                for entry in std::fs::read_dir(&day_dir)? {
+                    let path = entry?.path();
+                    if path.extension().and_then(|e| e.to_str()) != Some(extension) {
+                        continue;
+                    }
+                    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                        continue;
+                    };
+                    found.push(RecordingPartition {
+                        schema: schema.clone(),
+                        symbol: stem.to_string(),
+                        day: day.clone(),
+                        format: match extension {
+                            COLD_EXTENSION => PartitionFormat::Parquet,
+                            _ => PartitionFormat::Ndjson,
+                        },
+                        path: format!("{}/{}/{}.{}", schema, day, stem, extension),
+                        bytes: std::fs::metadata(&path)?.len(),
+                        records: None,
+                        start_unix_ns: None,
+                        end_unix_ns: None,
+                    });
+                }
+            }
+        }
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(found)
+    }
+
+    /// `partition` with its records and time span, read from its Parquet
+    /// file.
+    fn describe_cold(
+        &self,
+        partition: RecordingPartition,
+    ) -> Result<RecordingPartition, RecordingError> {
+        let schema = partition
+            .schema
+            .parse::<Schema>()
+            .map_err(RecordingError::Index)?;
+        let resp = read_parquet(&self.dir.join(&partition.path), &schema)?;
+        let span = resp.time_span();
+        Ok(RecordingPartition {
+            symbol: resp
+                .records_by_symbol()
+                .into_keys()
+                .next()
+                .unwrap_or(partition.symbol.clone()),
+            records: Some(resp.len() as u64),
+            start_unix_ns: span.map(|(start, _)| start),
+            end_unix_ns: span.map(|(_, end)| end),
+            ..partition
+        })
+    }
+
+    /// Every partition: compacted ones from the index, then hot files.
+    pub fn partitions(&self) -> Result<Vec<RecordingPartition>, RecordingError> {
+        self.flush();
+        let mut partitions: Vec<RecordingPartition> =
+            self.index.lock().unwrap().values().cloned().collect();
+        partitions.extend(self.scan(HOT_EXTENSION)?);
+        Ok(partitions)
+    }
+
+    /// Write the index via a temporary file.
+    fn persist(&self, index: &BTreeMap<String, RecordingPartition>) -> Result<(), RecordingError> {
+        let path = self.dir.join(INDEX_FILE);
+        let partitions: Vec<&RecordingPartition> = index.values().collect();
+        let json = serde_json::to_string_pretty(&partitions)
+            .map_err(|e| RecordingError::Index(e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Compact the hot files of days before `today` into Parquet.
+    pub fn compact(&self, today: NaiveDate) -> CompactionReport {
+        let _running = self.compacting.lock().unwrap();
+        self.close_before(today);
+        let mut report = CompactionReport::default();
+        let hot = match self.scan(HOT_EXTENSION) {
+            Ok(hot) => hot,
+            Err(e) => {
+                report.failed.push(format!("{}: {}", self.dir.display(), e));
+                return report;
+            }
+        };
+        for partition in hot {
+            if partition
+                .day
+                .parse::<NaiveDate>()
+                .map_or(true, |day| day >= today)
+            {
+                continue;
+            }
+            let bytes_before = partition.bytes;
+            let path = partition.path.clone();
+            match self.compact_partition(partition) {
+                Ok(compacted) => {
+                    report.bytes_before += bytes_before;
+                    report.bytes_after += compacted.bytes;
+                    report.compacted.push(compacted);
+                }
+                Err(e) => report.failed.push(format!("{}: {}", path, e)),
+            }
+        }
+        report
+    }
+
+    /// Rewrite one hot file as Parquet, index it and delete the file.
+    fn compact_partition(
+        &self,
+        hot: RecordingPartition,
+    ) -> Result<RecordingPartition, RecordingError> {
+        let schema = hot
+            .schema
+            .parse::<Schema>()
+            .map_err(RecordingError::Query)?;
+        let hot_path = self.dir.join(&hot.path);
+        let relative = format!(
+            "{}/{}/{}.{}",
+            hot.schema, hot.day, hot.symbol, COLD_EXTENSION
+        );
+        let cold_path = self.dir.join(&relative);
+
+        // A Parquet file already there was written by a run that stopped
+        // before deleting the NDJSON
+        if !cold_path.exists() {
+            let (resp, skipped) = read_hot(&hot_path, &schema, |_, _| true)?;
+            if skipped > 0 {
+                warn!(path = %hot.path, skipped, "Skipped unreadable recorded lines");
+            }
+            let tmp = cold_path.with_extension("parquet.tmp");
+            write_compressed_parquet(&resp, &tmp)?;
+            std::fs::rename(&tmp, &cold_path)?;
+        }
+        let cold = self.describe_cold(RecordingPartition {
+            format: PartitionFormat::Parquet,
+            bytes: std::fs::metadata(&cold_path)?.len(),
+            path: relative,
+            ..hot
+        })?;
+
+        let mut index = self.index.lock().unwrap();
+        index.insert(cold.path.clone(), cold.clone());
+        self.persist(&index)?;
+        drop(index);
+        std::fs::remove_file(&hot_path)?;
+        Ok(cold)
+    }
+
+    /// Store imported records as Parquet partitions, one per UTC day,
+    /// beside the recorded ones. A day imported before has its records in
+    /// the newly imported span replaced, so importing a file twice doesn't
+    /// duplicate it.
+    pub fn store(
+        &self,
+        resp: HistoricalResponse,
+    ) -> Result<Vec<RecordingPartition>, RecordingError> {
+        let schema = resp.schema();
+        let days = by_day(resp);
+
+        let _running = self.compacting.lock().unwrap();
+        let mut stored = Vec::new();
+        for ((day, symbol), mut records) in days {
+            let relative = format!(
+                "{}/{}/{}{}.{}",
+                schema.as_str(),
+                day,
+                file_stem(&symbol),
+                IMPORTED_SUFFIX,
+                COLD_EXTENSION
+            );
+            let path = self.dir.join(&relative);
+            if path.exists() {
+                let (first, last) = records.time_span().expect("a day has records");
+                let mut existing = read_parquet(&path, &schema)?;
+                existing.retain(|_, ts| ts < first || ts > last);
+                records.append(existing);
+                records.sort_by_time();
+            } else {
+                std::fs::create_dir_all(path.parent().expect("partition in a day directory"))?;
+            }
+            let tmp = path.with_extension("parquet.tmp");
+            write_compressed_parquet(&records, &tmp)?;
+            std::fs::rename(&tmp, &path)?;
+            let partition = self.describe_cold(RecordingPartition {
+                schema: schema.as_str().into_owned(),
+                symbol,
+                day: day.to_string(),
+                format: PartitionFormat::Parquet,
+                path: relative,
+                bytes: std::fs::metadata(&path)?.len(),
+                records: None,
+                start_unix_ns: None,
+                end_unix_ns: None,
+            })?;
+            let mut index = self.index.lock().unwrap();
+            index.insert(partition.path.clone(), partition.clone());
+            self.persist(&index)?;
+            stored.push(partition);
+        }
+        Ok(stored)
+    }
+
+    /// Recorded records matching `req`: its schema, symbols, range (RFC3339
+    /// times) and limits.
+    pub fn query(&self, req: &HistoricalRequest) -> Result<HistoricalResponse, RecordingError> {
+        let schema = req
+            .schema
+            .parse::<Schema>()
+            .map_err(RecordingError::Query)?;
+        if !recordable(&schema) {
+            return Err(RecordingError::Query(format!(
+                "Schema '{}' isn't recorded: expected trades, ohlcv-1s or ohlcv-1m",
+                req.schema
+            )));
+        }
+        let start_ns = parse_ns(&req.start_rfc3339)?;
+        let end_ns = parse_ns(&req.end_rfc3339)?;
+        let stems: Vec<String> = req.symbols.iter().map(|s| file_stem(s)).collect();
+        let keep = |symbol: &str, ts: u64| {
+            req.symbols.iter().any(|s| s == symbol) && (start_ns..end_ns).contains(&ts)
+        };
+
+        let mut resp = HistoricalResponse::empty(&schema);
+        for partition in self.index.lock().unwrap().values() {
+            if partition.schema == req.schema
+                && req.symbols.contains(&partition.symbol)
+                && partition.overlaps(start_ns, end_ns)
+            {
+                let mut cold = read_parquet(&self.dir.join(&partition.path), &schema)?;
+                cold.retain(|symbol, ts| keep(symbol, ts));
+                resp.append(cold);
+            }
+        }
+        // Messages are filed by the day they arrived, at or after their
+        // timestamps, so hot files from the range's first day on can hold it
+        let (first_day, last_day) = (day_of(start_ns), day_of(end_ns).succ_opt());
+        self.flush();
+        for partition in self.scan(HOT_EXTENSION)? {
+            let in_days = partition
+                .day
+                .parse::<NaiveDate>()
+                .is_ok_and(|day| day >= first_day && last_day.is_none_or(|last| day <= last));
+            if partition.schema == req.schema && in_days && stems.contains(&partition.symbol) {
+                let (hot, _) = read_hot(&self.dir.join(&partition.path), &schema, keep)?;
+                resp.append(hot);
+            }
+        }
+        resp.sort_by_time();
+        resp.apply_limits(req.limit, req.limit_per_symbol);
+        Ok(resp)
+    }
+
+    /// The DBN tee's files, `{dir}/dbn/{day}/*.dbn.zst`, dated like
+    /// recorded partitions.
+    fn dbn_files(&self, today: NaiveDate) -> std::io::Result<Vec<StoredFile>> {
+        let mut found = Vec::new();
+        let days = match std::fs::read_dir(self.dir.join(DBN_DIR)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(found),
+            days => days?,
+        };
+        for entry in days {
+            let entry = entry?;
+            let Some(day) = entry
+                .file_name()
+                .to_str()
+                .and_then(|d| d.parse::<NaiveDate>().ok())
+            else {
+                continue;
+            };
+            let Some(time) = day.succ_opt().and_then(|d| d.and_hms_opt(0, 0, 0)) else {
+                continue;
+            };
+            for file in std::fs::read_dir(entry.path())? {
+                let file = file?;
+                let metadata = file.metadata()?;
+                if metadata.is_file() {
+                    found.push(StoredFile {
+                        path: file.path(),
+                        bytes: metadata.len(),
+                        time: time.and_utc(),
+                        evictable: day < today,
+                    });
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// Partitions, dated by the end of their day. Today's are still being
+/// written, so retention leaves them. Imported partitions are dated by when
+/// they were imported, as their data can be of any age.
+impl Storage for Recordings {
+    fn files(&self) -> std::io::Result<Vec<StoredFile>> {
+        let today = Utc::now().date_naive();
+        let partitions = self.partitions().map_err(std::io::Error::other)?;
+        Ok(partitions
+            .into_iter()
+            .filter_map(|partition| {
+                let day = partition.day.parse::<NaiveDate>().ok()?;
+                let path = self.dir.join(&partition.path);
+                let imported = partition
+                    .path
+                    .ends_with(&format!("{}.{}", IMPORTED_SUFFIX, COLD_EXTENSION));
+                let time = if imported {
+                    std::fs::metadata(&path).ok()?.modified().ok()?.into()
+                } else {
+                    day.succ_opt()?.and_hms_opt(0, 0, 0)?.and_utc()
+                };
+                Some(StoredFile {
+                    path,
+                    bytes: partition.bytes,
+                    time,
+                    evictable: imported || day < today,
+                })
+            })
+            .chain(self.dbn_files(today)?)
+            .collect())
+    }
+
+    fn remove(&self, file: &StoredFile) -> std::io::Result<()> {
+        let _running = self.compacting.lock().unwrap();
+        self.close_before(Utc::now().date_naive());
+        if let Ok(relative) = file.path.strip_prefix(&self.dir) {
+            let relative = relative.to_string_lossy();
+            let mut index = self.index.lock().unwrap();
+            if index.remove(relative.as_ref()).is_some() {
+                self.persist(&index).map_err(std::io::Error::other)?;
+            }
+        }
+        std::fs::remove_file(&file.path)?;
+        remove_empty_parents(&file.path, &self.dir);
+        Ok(())
+    }
+}
+
+/// Records each live message published to it.
+#[async_trait]
+impl Publisher for Recordings {
+    async fn publish(
+        &self,
+        schema: &str,
+        symbol: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), EgressError> {
+        self.append(schema, symbol, Utc::now().date_naive(), &payload)
+            .map_err(|e| EgressError::Publish(e.to_string()))
+    }
+
+    fn name(&self) -> &'static str {
+        "recordings"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_NS: u64 = 86_400 * 1_000_000_000;
+
+    fn trade(ts: u64, symbol: &str) -> Vec<u8> {
+        serde_json::to_vec(&LiveMessage::Trade {
+            ts_event_unix_ns: ts,
+            symbol: symbol.to_string(),
+            price_i64: 5_000_000_000_000,
+            size_u32: 1,
+        })
+        .unwrap()
+    }
+
+    fn request(start: &str, end: &str) -> HistoricalRequest {
+        HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "trades".to_string(),
+            start_rfc3339: start.to_string(),
+            end_rfc3339: end.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compaction() {
+        let dir = std::env::temp_dir().join(format!("sf-recordings-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let recordings = Recordings::open(&dir).unwrap();
+
+        // 2024-01-02 and 2024-01-03, recorded on those days
+        let jan2 = day_of(19_724 * DAY_NS);
+        let jan3 = jan2.succ_opt().unwrap();
+        for i in 0..3 {
+            let ts = 19_724 * DAY_NS + i;
+            recordings
+                .append("trades", "ES.FUT", jan2, &trade(ts, "ES.FUT"))
+                .unwrap();
+            recordings
+                .append("trades", "NQ.FUT", jan2, &trade(ts, "NQ.FUT"))
+                .unwrap();
+        }
+        recordings
+            .append("trades", "ES.FUT", jan3, &trade(19_725 * DAY_NS, "ES.FUT"))
+            .unwrap();
+        recordings.flush();
+        // A line cut short by a crash is skipped
+        let jan2_es = dir.join("trades/2024-01-02/ES.FUT.ndjson");
+        let mut file = OpenOptions::new().append(true).open(&jan2_es).unwrap();
+        file.write_all(b"{\"type\":\"tra").unwrap();
+
+        let report = recordings.compact(jan3);
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(report.compacted.len(), 2);
+        let es = &report.compacted[0];
+        assert_eq!(es.path, "trades/2024-01-02/ES.FUT.parquet");
+        assert_eq!(es.records, Some(3));
+        assert_eq!(es.end_unix_ns, Some(19_724 * DAY_NS + 2));
+        assert!(!jan2_es.exists());
+        assert!(dir.join("trades/2024-01-03/ES.FUT.ndjson").exists());
+
+        // Queries span compacted and hot partitions
+        let all = request("2024-01-02T00:00:00Z", "2024-01-04T00:00:00Z");
+        assert_eq!(recordings.query(&all).unwrap().len(), 4);
+        let first = request("2024-01-02T00:00:00Z", "2024-01-02T00:00:00.000000002Z");
+        assert_eq!(recordings.query(&first).unwrap().len(), 2);
+        assert!(recordings
+            .query(&HistoricalRequest {
+                schema: "cvd-1m".to_string(),
+                ..all.clone()
+            })
+            .is_err());
+
+        // An index lost after the Parquet was written is rebuilt
+        std::fs::remove_file(dir.join(INDEX_FILE)).unwrap();
+        let reopened = Recordings::open(&dir).unwrap();
+        let partitions = reopened.partitions().unwrap();
+        assert_eq!(partitions.len(), 3);
+        assert_eq!(partitions[0].records, Some(3));
+        assert_eq!(partitions[2].format, PartitionFormat::Ndjson);
+        assert_eq!(reopened.query(&all).unwrap().len(), 4);
+
+        // Evicting a partition drops it from the index too
+        let files = reopened.files().unwrap();
+        assert!(files.iter().all(|f| f.evictable));
+        reopened.remove(&files[0]).unwrap();
+        assert_eq!(reopened.partitions().unwrap().len(), 2);
+        assert_eq!(reopened.query(&all).unwrap().len(), 1);
+
+        // DBN tee files count toward retention but aren't partitions
+        let dbn = dir.join(DBN_DIR).join("2024-01-02");
+        std::fs::create_dir_all(&dbn).unwrap();
+        std::fs::write(dbn.join("GLBX.MDP3.trades.143000.dbn.zst"), b"DBN").unwrap();
+        let files = reopened.files().unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(reopened.partitions().unwrap().len(), 2);
+        reopened.remove(&files[2]).unwrap();
+        assert!(!dir.join(DBN_DIR).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,388 @@
+//! Disk usage limits for what the server keeps on local disk.
+//!
+//! Recordings (`RECORD_DIR`) and scheduled job output (`DATA_DIR`) grow for
+//! as long as the server runs. Each can be given a retention policy such as
+//! `RECORD_RETENTION=50GB,30d`: every [`EVICTION_INTERVAL`] files holding
+//! data older than the age are deleted, then the oldest of the rest until
+//! the subsystem fits the size. Files still being written, such as today's
+//! recordings, are counted but never deleted. The server's own state files
+//! are only reported. `GET /api/admin/storage` shows what each subsystem
+//! uses and has evicted.
+
+use chrono::{DateTime, Utc};
+use shared::storage::{RetentionPolicy, StorageReport, SubsystemUsage};
+use shared::timeexpr::parse_lookback;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// How often retention policies are enforced.
+pub const EVICTION_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Error type for retention settings.
+#[derive(Debug, thiserror::Error)]
+pub enum RetentionError {
+    #[error("Invalid retention '{0}': expected a size and/or an age, e.g. 50GB,30d")]
+    InvalidPolicy(String),
+}
+
+/// Parse `50GB,30d` into a policy. Sizes take `KB`, `MB`, `GB` or `TB`
+/// (powers of 1000) and may be fractional; ages take `s`, `m`, `h` or `d`.
+pub fn parse_policy(spec: &str) -> Result<RetentionPolicy, RetentionError> {
+    let invalid = || RetentionError::InvalidPolicy(spec.to_string());
+    let mut policy = RetentionPolicy::default();
+    for limit in spec.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+        let upper = limit.to_ascii_uppercase();
+        let unit = [("TB", 1e12), ("GB", 1e9), ("MB", 1e6), ("KB", 1e3)]
+            .into_iter()
+            .find_map(|(suffix, scale)| Some((upper.strip_suffix(suffix)?, scale)));
+        match unit {
+            Some((amount, scale)) => {
+                let amount: f64 = amount.trim().parse().map_err(|_| invalid())?;
+                if amount.is_nan() || amount <= 0.0 {
+                    return Err(invalid());
+                }
+                policy.max_bytes = Some((amount * scale) as u64);
+            }
+            None => {
+                let age = parse_lookback(limit).map_err(|_| invalid())?;
+                policy.max_age_secs =
+                    Some(u64::try_from(age.num_seconds()).map_err(|_| invalid())?);
+            }
+        }
+    }
+    if policy == RetentionPolicy::default() {
+        return Err(invalid());
+    }
+    Ok(policy)
+}
+
+/// A file a subsystem keeps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredFile {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// When its newest data was written
+    pub time: DateTime<Utc>,
+    /// Whether retention may delete it
+    pub evictable: bool,
+}
+
+/// Files on disk that a subsystem keeps.
+pub trait Storage: Send + Sync {
+    /// Every file kept.
+    fn files(&self) -> std::io::Result<Vec<StoredFile>>;
+
+    /// Delete `file`.
+    fn remove(&self, file: &StoredFile) -> std::io::Result<()>;
+}
+
+/// Every file under a directory, dated by modification time. Directories
+/// left empty by a deletion are removed.
+pub struct Directory(pub PathBuf);
+
+impl Directory {
+    fn walk(dir: &Path, found: &mut Vec<StoredFile>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                Self::walk(&entry.path(), found)?;
+            } else {
+                found.push(StoredFile {
+                    path: entry.path(),
+                    bytes: metadata.len(),
+                    time: metadata.modified()?.into(),
+                    evictable: true,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for Directory {
+    fn files(&self) -> std::io::Result<Vec<StoredFile>> {
+        let mut found = Vec::new();
+        match Self::walk(&self.0, &mut found) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            result => result.map(|_| found),
+        }
+    }
+
+    fn remove(&self, file: &StoredFile) -> std::io::Result<()> {
+        std::fs::remove_file(&file.path)?;
+        remove_empty_parents(&file.path, &self.0);
+        Ok(())
+    }
+}
+
+/// Remove the directories between `path` and `root` that are now empty.
+pub fn remove_empty_parents(path: &Path, root: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == root || !dir.starts_with(root) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+/// Individual files that are reported but never deleted.
+pub struct Files(pub Vec<PathBuf>);
+
+impl Storage for Files {
+    fn files(&self) -> std::io::Result<Vec<StoredFile>> {
+        let mut found = Vec::new();
+        for path in &self.0 {
+            match std::fs::metadata(path) {
+                Ok(metadata) => found.push(StoredFile {
+                    path: path.clone(),
+                    bytes: metadata.len(),
+                    time: metadata.modified()?.into(),
+                    evictable: false,
+                }),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(found)
+    }
+
+    fn remove(&self, _file: &StoredFile) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The files `policy` evicts from `files` at `now`: those older than the
+/// age, then the oldest until the total fits the size.
+pub fn expired(
+    mut files: Vec<StoredFile>,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Vec<StoredFile> {
+    files.sort_by_key(|f| f.time);
+    let mut total: u64 = files.iter().map(|f| f.bytes).sum();
+    let cutoff = policy
+        .max_age_secs
+        .and_then(|secs| i64::try_from(secs).ok())
+        .map(|secs| now - chrono::Duration::seconds(secs));
+    let mut evicted = Vec::new();
+    for file in files.into_iter().filter(|f| f.evictable) {
+        let too_old = cutoff.is_some_and(|cutoff| file.time < cutoff);
+        let too_big = policy.max_bytes.is_some_and(|max| total > max);
+        if !too_old && !too_big {
+            continue;
+        }
+        total -= file.bytes;
+        evicted.push(file);
+    }
+    evicted
+}
+
+/// One subsystem's storage and limits.
+struct Subsystem {
+    name: &'static str,
+    path: String,
+    storage: Arc<dyn Storage>,
+    policy: Option<RetentionPolicy>,
+    evicted_files: AtomicU64,
+    evicted_bytes: AtomicU64,
+}
+
+/// Disk usage of the server's subsystems, and the task keeping them
+/// within their retention policies.
+#[derive(Default)]
+pub struct Retention {
+    subsystems: Vec<Subsystem>,
+}
+
+impl Retention {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report `storage` as `name`, kept within `policy` if given.
+    pub fn with_subsystem(
+        mut self,
+        name: &'static str,
+        path: impl Into<String>,
+        storage: Arc<dyn Storage>,
+        policy: Option<RetentionPolicy>,
+    ) -> Self {
+        self.subsystems.push(Subsystem {
+            name,
+            path: path.into(),
+            storage,
+            policy,
+            evicted_files: AtomicU64::new(0),
+            evicted_bytes: AtomicU64::new(0),
+        });
+        self
+    }
+
+    /// What each subsystem uses now.
+    pub fn report(&self) -> StorageReport {
+        let subsystems: Vec<SubsystemUsage> = self
+            .subsystems
+            .iter()
+            .map(|subsystem| {
+                let files = subsystem.storage.files().unwrap_or_else(|e| {
+                    warn!(subsystem = subsystem.name, "Failed to list files: {}", e);
+                    Vec::new()
+                });
+                SubsystemUsage {
+                    name: subsystem.name.to_string(),
+                    path: subsystem.path.clone(),
+                    bytes: files.iter().map(|f| f.bytes).sum(),
+                    files: files.len() as u64,
+                    oldest_unix_ns: files
+                        .iter()
+                        .map(|f| f.time)
+                        .min()
+                        .and_then(|t| t.timestamp_nanos_opt())
+                        .map(|ns| ns.max(0) as u64),
+                    retention: subsystem.policy,
+                    evicted_files: subsystem.evicted_files.load(Ordering::Relaxed),
+                    evicted_bytes: subsystem.evicted_bytes.load(Ordering::Relaxed),
+                }
+            })
+            .collect();
+        StorageReport {
+            total_bytes: subsystems.iter().map(|s| s.bytes).sum(),
+            subsystems,
+        }
+    }
+
+    /// Delete what each policy evicts at `now`. Returns the files and
+    /// bytes deleted.
+    pub fn enforce(&self, now: DateTime<Utc>) -> (u64, u64) {
+        let (mut files, mut bytes) = (0, 0);
+        for subsystem in &self.subsystems {
+            let Some(policy) = &subsystem.policy else {
+                continue;
+            };
+            let kept = match subsystem.storage.files() {
+                Ok(kept) => kept,
+                Err(e) => {
+                    warn!(subsystem = subsystem.name, "Failed to list files: {}", e);
+                    continue;
+                }
+            };
+            for file in expired(kept, policy, now) {
+                if let Err(e) = subsystem.storage.remove(&file) {
+                    warn!(subsystem = subsystem.name, path = %file.path.display(), "Failed to evict: {}", e);
+                    continue;
+                }
+                subsystem.evicted_files.fetch_add(1, Ordering::Relaxed);
+                subsystem
+                    .evicted_bytes
+                    .fetch_add(file.bytes, Ordering::Relaxed);
+                files += 1;
+                bytes += file.bytes;
+            }
+        }
+        (files, bytes)
+    }
+
+    /// Enforce retention now and every [`EVICTION_INTERVAL`].
+    pub fn spawn(self: &Arc<Self>) -> JoinHandle<()> {
+        let retention = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(EVICTION_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let retention = retention.clone();
+                match tokio::task::spawn_blocking(move || retention.enforce(Utc::now())).await {
+                    Ok((0, _)) => {}
+                    Ok((files, bytes)) => info!(files, bytes, "Evicted files past retention"),
+                    Err(e) => warn!("Retention task failed: {}", e),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(
+            parse_policy("50GB, 30d").unwrap(),
+            RetentionPolicy {
+                max_bytes: Some(50_000_000_000),
+                max_age_secs: Some(30 * 86_400),
+            }
+        );
+        assert_eq!(parse_policy("0.5gb").unwrap().max_bytes, Some(500_000_000));
+        assert_eq!(parse_policy("12h").unwrap().max_bytes, None);
+        assert!(parse_policy("").is_err());
+        assert!(parse_policy("-1GB").is_err());
+        assert!(parse_policy("lots").is_err());
+    }
+
+    #[test]
+    fn test_expired() {
+        let now = Utc::now();
+        let file = |name: &str, days: i64, bytes, evictable| StoredFile {
+            path: name.into(),
+            bytes,
+            time: now - chrono::Duration::days(days),
+            evictable,
+        };
+        let files = vec![
+            file("new", 0, 40, false),
+            file("mid", 2, 40, true),
+            file("old", 10, 40, true),
+            file("older", 20, 40, true),
+        ];
+
+        let by_age = RetentionPolicy {
+            max_age_secs: Some(7 * 86_400),
+            ..Default::default()
+        };
+        let names = |evicted: Vec<StoredFile>| -> Vec<PathBuf> {
+            evicted.into_iter().map(|f| f.path).collect()
+        };
+        assert_eq!(
+            names(expired(files.clone(), &by_age, now)),
+            vec![PathBuf::from("older"), PathBuf::from("old")]
+        );
+
+        // Files still being written count towards the size but stay
+        let by_size = RetentionPolicy {
+            max_bytes: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(expired(files, &by_size, now).len(), 3);
+    }
+
+    #[test]
+    fn test_directory_eviction() {
+        let dir = std::env::temp_dir().join(format!("sf-retention-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("job")).unwrap();
+        std::fs::write(dir.join("job/a.csv"), "0123456789").unwrap();
+
+        let retention = Retention::new().with_subsystem(
+            "exports",
+            dir.display().to_string(),
+            Arc::new(Directory(dir.clone())),
+            Some(RetentionPolicy {
+                max_bytes: Some(5),
+                max_age_secs: None,
+            }),
+        );
+        assert_eq!(retention.report().total_bytes, 10);
+        assert_eq!(retention.enforce(Utc::now()), (1, 10));
+        assert!(!dir.join("job").exists());
+        let report = retention.report();
+        assert_eq!(report.subsystems[0].files, 0);
+        assert_eq!(report.subsystems[0].evicted_bytes, 10);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,488 @@
+//! Minute-level rollups kept on ingest.
+//!
+//! With `ROLLUP_INTERVALS` set (e.g. `1m,5m`), every historical fetch and
+//! live trade stream passing through the server extends rolling per-symbol
+//! bar series at those widths, persisted to `ROLLUPS_PATH`. Live bar
+//! snapshots, `GET /api/rollups/:symbol` ("the last 500 bars") and
+//! `GET /api/leaderboard` are then served from them without calling the
+//! provider.
+//!
+//! Only data known to cover whole buckets is kept (see
+//! [`shared::rollups`]): a fetch cut short by its limit adds nothing, and a
+//! live stream's first bar, joined part way through, is left out. Each
+//! symbol's live trades are taken from one subscription at a time, so two
+//! sockets watching the same symbol don't count its trades twice.
+
+use shared::aggregate::{aggregate_trades, resample_bars, NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use shared::rollups::{Mover, RollupSeries};
+use shared::timeexpr::parse_lookback;
+use shared::{HistoricalRequest, HistoricalResponse, OhlcvRecord, SymbolType};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// How often changed rollups are written to disk.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Error type for rollup storage.
+#[derive(Debug, thiserror::Error)]
+pub enum RollupError {
+    #[error("Invalid rollup interval '{0}': expected e.g. 1m or 5m, a whole number of seconds")]
+    InvalidInterval(String),
+    #[error("Rollup storage failed: {0}")]
+    Storage(String),
+}
+
+/// Parse `1m,5m` into bar widths in nanoseconds.
+pub fn parse_intervals(spec: &str) -> Result<Vec<u64>, RollupError> {
+    let mut intervals = Vec::new();
+    for interval in spec.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let ns = parse_lookback(interval)
+            .ok()
+            .filter(|d| d.num_seconds() > 0)
+            .and_then(|d| d.num_nanoseconds())
+            .and_then(|ns| u64::try_from(ns).ok())
+            .ok_or_else(|| RollupError::InvalidInterval(interval.to_string()))?;
+        if !intervals.contains(&ns) {
+            intervals.push(ns);
+        }
+    }
+    intervals.sort_unstable();
+    Ok(intervals)
+}
+
+/// A bar width written the way `ROLLUP_INTERVALS` takes it: `5m`, `1h`.
+pub fn label(interval_ns: u64) -> String {
+    let seconds = interval_ns / NANOS_PER_SECOND;
+    match seconds {
+        s if s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s % 3_600 == 0 => format!("{}h", s / 3_600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// A bar being built from one subscription's live trades.
+struct LiveBar {
+    bar: OhlcvRecord,
+    /// Whether the subscription saw the bucket from its start
+    complete: bool,
+}
+
+#[derive(Default)]
+struct LiveState {
+    /// Subscription each symbol's trades are taken from
+    owners: HashMap<String, u64>,
+    building: HashMap<(String, u64), LiveBar>,
+}
+
+/// Rolling bar series per symbol and width.
+pub struct RollupStore {
+    intervals: Vec<u64>,
+    max_bars: usize,
+    path: Option<PathBuf>,
+    series: Mutex<HashMap<(String, u64), RollupSeries>>,
+    live: Mutex<LiveState>,
+    next_tap: AtomicU64,
+    dirty: AtomicBool,
+}
+
+impl RollupStore {
+    /// A store that keeps nothing.
+    pub fn disabled() -> Self {
+        Self::new(Vec::new(), 0)
+    }
+
+    /// An in-memory store keeping up to `max_bars` bars per symbol at each
+    /// of `intervals`.
+    pub fn new(intervals: Vec<u64>, max_bars: usize) -> Self {
+        Self {
+            intervals,
+            max_bars,
+            path: None,
+            series: Mutex::new(HashMap::new()),
+            live: Mutex::new(LiveState::default()),
+            next_tap: AtomicU64::new(1),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// A store persisted at `path`, loading the rollups saved there.
+    /// Series at widths no longer configured are dropped.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        intervals: Vec<u64>,
+        max_bars: usize,
+    ) -> Result<Self, RollupError> {
+        let path = path.into();
+        let saved: Vec<RollupSeries> = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| RollupError::Storage(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(RollupError::Storage(format!("{}: {}", path.display(), e))),
+        };
+        let store = Self {
+            path: Some(path),
+            ..Self::new(intervals, max_bars)
+        };
+        store.lock_series().extend(
+            saved
+                .into_iter()
+                .filter(|s| store.intervals.contains(&s.interval_ns))
+                .map(|s| ((s.symbol.clone(), s.interval_ns), s)),
+        );
+        Ok(store)
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.intervals.is_empty()
+    }
+
+    /// Configured bar widths in nanoseconds, narrowest first.
+    pub fn intervals(&self) -> &[u64] {
+        &self.intervals
+    }
+
+    fn lock_series(&self) -> MutexGuard<'_, HashMap<(String, u64), RollupSeries>> {
+        self.series.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_live(&self) -> MutexGuard<'_, LiveState> {
+        self.live.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record `bars` as every bar of `symbol` at `interval_ns` from `from`
+    /// to `to`.
+    fn insert(&self, symbol: &str, interval_ns: u64, bars: &[OhlcvRecord], from: u64, to: u64) {
+        self.lock_series()
+            .entry((symbol.to_string(), interval_ns))
+            .or_insert_with(|| RollupSeries::new(symbol, interval_ns))
+            .insert(bars, from, to, self.max_bars);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Add what a served historical fetch of `req` says about whole
+    /// buckets. Fetches that hit their limit, and schemas that aren't
+    /// trades or time bars dividing a rollup width, add nothing.
+    pub fn ingest_historical(&self, req: &HistoricalRequest, response: &HistoricalResponse) {
+        if !self.enabled() || response.reached_limits(req.limit, req.limit_per_symbol) {
+            return;
+        }
+        let (Ok(start), Ok(end)) = (
+            chrono::DateTime::parse_from_rfc3339(&req.start_rfc3339),
+            chrono::DateTime::parse_from_rfc3339(&req.end_rfc3339),
+        ) else {
+            return;
+        };
+        let (Some(start), Some(end)) = (start.timestamp_nanos_opt(), end.timestamp_nanos_opt())
+        else {
+            return;
+        };
+        let (start, end) = (start.max(0) as u64, end.max(0) as u64);
+
+        // Symbols the records are labelled with; raw symbols are known to be
+        // labelled as requested even when they traded nothing
+        let mut symbols: Vec<String> = response.records_by_symbol().into_keys().collect();
+        if req.stype_in == SymbolType::RawSymbol {
+            for symbol in &req.symbols {
+                if !symbols.contains(symbol) {
+                    symbols.push(symbol.clone());
+                }
+            }
+        }
+
+        for &interval_ns in &self.intervals {
+            let bars = match response {
+                HistoricalResponse::Trades { data } => aggregate_trades(data, interval_ns),
+                HistoricalResponse::Ohlcv1S { data } if interval_ns % NANOS_PER_SECOND == 0 => {
+                    resample_bars(data, interval_ns)
+                }
+                HistoricalResponse::Ohlcv1M { data } if interval_ns % NANOS_PER_MINUTE == 0 => {
+                    resample_bars(data, interval_ns)
+                }
+                _ => continue,
+            };
+            // Whole buckets inside the range
+            let from = start.div_ceil(interval_ns) * interval_ns;
+            let to = end - end % interval_ns;
+            if from >= to {
+                continue;
+            }
+            for symbol in &symbols {
+                self.insert(symbol, interval_ns, &bars, from, to);
+            }
+        }
+    }
+
+    /// A handle for feeding one subscription's live trades.
+    pub fn tap(self: &Arc<Self>) -> RollupTap {
+        RollupTap {
+            store: Arc::clone(self),
+            id: self.next_tap.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    fn on_trade(&self, tap: u64, ts: u64, symbol: &str, price: i64, size: u32) {
+        let mut live = self.lock_live();
+        let owner = *live.owners.entry(symbol.to_string()).or_insert(tap);
+        if owner != tap {
+            return;
+        }
+        for &interval_ns in &self.intervals {
+            let start = ts - ts % interval_ns;
+            let bar = OhlcvRecord {
+                ts_event_unix_ns: start,
+                symbol: symbol.to_string(),
+                open_i64: price,
+                high_i64: price,
+                low_i64: price,
+                close_i64: price,
+                volume_u64: u64::from(size),
+            };
+            let key = (symbol.to_string(), interval_ns);
+            let Some(current) = live.building.get_mut(&key) else {
+                // Joined part way through this bucket
+                live.building.insert(
+                    key,
+                    LiveBar {
+                        bar,
+                        complete: false,
+                    },
+                );
+                continue;
+            };
+            let current_start = current.bar.ts_event_unix_ns;
+            if start == current_start {
+                let b = &mut current.bar;
+                b.high_i64 = b.high_i64.max(price);
+                b.low_i64 = b.low_i64.min(price);
+                b.close_i64 = price;
+                b.volume_u64 += u64::from(size);
+            } else if start > current_start {
+                // Buckets up to this trade's are over, the empty ones too
+                let closed = std::mem::replace(
+                    current,
+                    LiveBar {
+                        bar,
+                        complete: true,
+                    },
+                );
+                match closed.complete {
+                    true => self.insert(symbol, interval_ns, &[closed.bar], current_start, start),
+                    false => {
+                        self.insert(symbol, interval_ns, &[], current_start + interval_ns, start)
+                    }
+                }
+            }
+        }
+    }
+
+    fn release(&self, tap: u64) {
+        let mut live = self.lock_live();
+        let LiveState { owners, building } = &mut *live;
+        owners.retain(|symbol, owner| {
+            let keep = *owner != tap;
+            if !keep {
+                building.retain(|(s, _), _| s != symbol);
+            }
+            keep
+        });
+    }
+
+    /// The bars of each of `symbols` at `interval_ns` in the `count` buckets
+    /// before `end`, if every symbol's series covers them.
+    pub fn recent(
+        &self,
+        symbols: &[String],
+        interval_ns: u64,
+        end: u64,
+        count: usize,
+    ) -> Option<Vec<OhlcvRecord>> {
+        let series = self.lock_series();
+        let mut bars = Vec::new();
+        for symbol in symbols {
+            bars.extend(
+                series
+                    .get(&(symbol.clone(), interval_ns))?
+                    .recent(end, count)?,
+            );
+        }
+        bars.sort_by(|a, b| {
+            a.ts_event_unix_ns
+                .cmp(&b.ts_event_unix_ns)
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        Some(bars)
+    }
+
+    /// `symbol`'s series at `interval_ns`, if any.
+    pub fn series(&self, symbol: &str, interval_ns: u64) -> Option<RollupSeries> {
+        self.lock_series()
+            .get(&(symbol.to_string(), interval_ns))
+            .cloned()
+    }
+
+    /// How every symbol with bars at `interval_ns` moved since `since`.
+    pub fn movers(&self, interval_ns: u64, since: u64) -> Vec<Mover> {
+        self.lock_series()
+            .values()
+            .filter(|s| s.interval_ns == interval_ns)
+            .filter_map(|s| s.mover(since))
+            .collect()
+    }
+
+    /// Write rollups to disk if anything changed since the last flush.
+    pub fn flush(&self) -> Result<(), RollupError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let series: Vec<RollupSeries> = self.lock_series().values().cloned().collect();
+        let json =
+            serde_json::to_string(&series).map_err(|e| RollupError::Storage(e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .map_err(|e| {
+                self.dirty.store(true, Ordering::Relaxed);
+                RollupError::Storage(format!("{}: {}", path.display(), e))
+            })
+    }
+
+    /// Flush every [`FLUSH_INTERVAL`] in the background.
+    pub fn spawn_flusher(self: &Arc<Self>) -> JoinHandle<()> {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = store.flush() {
+                    warn!("{}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Feeds one live subscription's trades into a [`RollupStore`]. The
+/// symbols it feeds are released for other subscriptions when dropped.
+pub struct RollupTap {
+    store: Arc<RollupStore>,
+    id: u64,
+}
+
+impl RollupTap {
+    pub fn on_trade(&self, ts_event_unix_ns: u64, symbol: &str, price_i64: i64, size_u32: u32) {
+        self.store
+            .on_trade(self.id, ts_event_unix_ns, symbol, price_i64, size_u32);
+    }
+}
+
+impl Drop for RollupTap {
+    fn drop(&mut self) {
+        self.store.release(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::TradeRecord;
+
+    const MIN: u64 = 60_000_000_000;
+
+    fn trade(ts: u64, price: i64) -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ESZ4".to_string(),
+            price_i64: price,
+            size_u32: 1,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_intervals() {
+        assert_eq!(parse_intervals("5m, 1m,1m").unwrap(), vec![MIN, 5 * MIN]);
+        assert!(parse_intervals("0m").is_err());
+        assert!(parse_intervals("1x").is_err());
+        assert_eq!(label(5 * MIN), "5m");
+        assert_eq!(label(60 * MIN), "1h");
+    }
+
+    #[test]
+    fn test_ingest_historical() {
+        let store = RollupStore::new(vec![MIN, 5 * MIN], 100);
+        let req = HistoricalRequest {
+            symbols: vec!["ESZ4".to_string()],
+            schema: "trades".to_string(),
+            stype_in: SymbolType::RawSymbol,
+            // 00:00:30 to 00:07:30: minutes 1-6 are whole, 5m bucket 0 isn't
+            start_rfc3339: "1970-01-01T00:00:30Z".to_string(),
+            end_rfc3339: "1970-01-01T00:07:30Z".to_string(),
+            limit: 100,
+            ..Default::default()
+        };
+        let data = (0..7)
+            .map(|m| trade(m * MIN + MIN / 2, 100 + m as i64))
+            .collect();
+        let response = HistoricalResponse::Trades { data };
+        store.ingest_historical(&req, &response);
+
+        let minutes = store.series("ESZ4", MIN).unwrap();
+        assert_eq!(minutes.covered, Some((MIN, 7 * MIN)));
+        assert_eq!(minutes.bars.len(), 6);
+        assert!(store.series("ESZ4", 5 * MIN).is_none());
+        assert_eq!(
+            store
+                .recent(&["ESZ4".to_string()], MIN, 7 * MIN, 3)
+                .unwrap()[0]
+                .close_i64,
+            104
+        );
+
+        // A fetch cut short by its limit adds nothing
+        let store = RollupStore::new(vec![MIN], 100);
+        store.ingest_historical(&HistoricalRequest { limit: 7, ..req }, &response);
+        assert!(store.series("ESZ4", MIN).is_none());
+    }
+
+    #[test]
+    fn test_live_taps() {
+        let store = Arc::new(RollupStore::new(vec![MIN], 100));
+        let first = store.tap();
+        let second = store.tap();
+        for t in [
+            trade(MIN / 2, 100),
+            trade(MIN + 1, 101),
+            trade(MIN + 2, 102),
+        ] {
+            first.on_trade(t.ts_event_unix_ns, &t.symbol, t.price_i64, t.size_u32);
+            // The same trades through another socket aren't counted again
+            second.on_trade(t.ts_event_unix_ns, &t.symbol, t.price_i64, t.size_u32);
+        }
+        // Minute 0 was joined part way; minute 1 is still open
+        assert_eq!(store.series("ESZ4", MIN).unwrap().covered, Some((MIN, MIN)));
+        first.on_trade(3 * MIN, "ESZ4", 103, 1);
+        let series = store.series("ESZ4", MIN).unwrap();
+        assert_eq!(series.covered, Some((MIN, 3 * MIN)));
+        assert_eq!(series.bars.len(), 1);
+        assert_eq!(series.bars[0].volume_u64, 2);
+
+        // Once the first socket goes, the second takes over from scratch
+        drop(first);
+        second.on_trade(4 * MIN, "ESZ4", 104, 1);
+        assert_eq!(
+            store.series("ESZ4", MIN).unwrap().covered,
+            Some((MIN, 3 * MIN))
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Symbol-to-dataset routing.
+//!
+//! Maps symbols to upstream datasets by naming convention so requests don't
+//! need an explicit dataset. Rules are checked in order and the first match
+//! wins. The table can be replaced with the `SYMBOL_ROUTES` environment
+//! variable, e.g.:
+//!
+//! ```text
+//! SYMBOL_ROUTES="*.FUT=GLBX.MDP3;*-USD=CRYPTO.DATASET;@ticker=XNAS.ITCH;*=GLBX.MDP3"
+//! ```
+
+use std::fmt;
+
+/// How a rule matches symbols.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolPattern {
+    /// Glob pattern: `*` matches any run of characters, `?` exactly one.
+    Glob(String),
+    /// A plain equity ticker: 1-5 uppercase letters with an optional
+    /// one-letter share class (`AAPL`, `BRK.B`). Written as `@ticker`.
+    Ticker,
+}
+
+impl SymbolPattern {
+    /// Parse a pattern from its configuration form.
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "@ticker" => SymbolPattern::Ticker,
+            glob => SymbolPattern::Glob(glob.to_string()),
+        }
+    }
+
+    pub fn matches(&self, symbol: &str) -> bool {
+        match self {
+            SymbolPattern::Glob(glob) => glob_match(glob.as_bytes(), symbol.as_bytes()),
+            SymbolPattern::Ticker => is_plain_ticker(symbol),
+        }
+    }
+}
+
+impl fmt::Display for SymbolPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolPattern::Glob(glob) => f.write_str(glob),
+            SymbolPattern::Ticker => f.write_str("@ticker"),
+        }
+    }
+}
+
+/// A single routing rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub pattern: SymbolPattern,
+    pub dataset: String,
+}
+
+/// Ordered table of routing rules.
+#[derive(Debug, Clone)]
+pub struct SymbolRouter {
+    routes: Vec<Route>,
+}
+
+impl SymbolRouter {
+    pub fn new(routes: Vec<Route>) -> Self {
+        Self { routes }
+    }
+
+    /// Parse a `pattern=DATASET;pattern=DATASET` table.
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let routes = spec
+            .split(';')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| {
+                let (pattern, dataset) = rule
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid route '{}': expected pattern=DATASET", rule))?;
+                let (pattern, dataset) = (pattern.trim(), dataset.trim());
+                if pattern.is_empty() || dataset.is_empty() {
+                    return Err(format!(
+                        "Invalid route '{}': empty pattern or dataset",
+                        rule
+                    ));
+                }
+                Ok(Route {
+                    pattern: SymbolPattern::parse(pattern),
+                    dataset: dataset.to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if routes.is_empty() {
+            return Err("Routing table is empty".to_string());
+        }
+        Ok(Self { routes })
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// Dataset for `symbol`, or `None` if no rule matches.
+    pub fn dataset_for(&self, symbol: &str) -> Option<&str> {
+        self.routes
+            .iter()
+            .find(|route| route.pattern.matches(symbol))
+            .map(|route| route.dataset.as_str())
+    }
+
+    /// Group symbols by dataset, preserving first-seen order of datasets.
+    /// Fails with the first symbol no rule matches.
+    pub fn group(&self, symbols: &[String]) -> Result<Vec<(String, Vec<String>)>, String> {
+        let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+        for symbol in symbols {
+            let dataset = self
+                .dataset_for(symbol)
+                .ok_or_else(|| format!("No dataset route matches symbol '{}'", symbol))?;
+            match groups.iter_mut().find(|(d, _)| d == dataset) {
+                Some((_, group)) => group.push(symbol.clone()),
+                None => groups.push((dataset.to_string(), vec![symbol.clone()])),
+            }
+        }
+        Ok(groups)
+    }
+}
+
+impl Default for SymbolRouter {
+    /// CME futures and options (parent, continuous) go to Globex, plain
+    /// tickers to Nasdaq, and anything else falls back to Globex raw symbols.
+    fn default() -> Self {
+        let route = |pattern: &str, dataset: &str| Route {
+            pattern: SymbolPattern::parse(pattern),
+            dataset: dataset.to_string(),
+        };
+        Self::new(vec![
+            route("*.FUT", "GLBX.MDP3"),
+            route("*.OPT", "GLBX.MDP3"),
+            route("*.c.*", "GLBX.MDP3"),
+            route("*.n.*", "GLBX.MDP3"),
+            route("*.v.*", "GLBX.MDP3"),
+            route("@ticker", "XNAS.ITCH"),
+            route("*", "GLBX.MDP3"),
+        ])
+    }
+}
+
+/// Match `text` against a glob with `*` and `?` wildcards.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it is currently absorbing up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` absorb one more character and retry
+                Some((sp, st)) => {
+                    p = sp + 1;
+                    t = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn is_plain_ticker(symbol: &str) -> bool {
+    let (root, class) = match symbol.split_once('.') {
+        Some((root, class)) => (root, Some(class)),
+        None => (symbol, None),
+    };
+    let upper = |s: &str| s.bytes().all(|b| b.is_ascii_uppercase());
+
+    (1..=5).contains(&root.len()) && upper(root) && class.is_none_or(|c| c.len() == 1 && upper(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.FUT", b"ES.FUT"));
+        assert!(!glob_match(b"*.FUT", b"ES.OPT"));
+        assert!(glob_match(b"*-USD", b"BTC-USD"));
+        assert!(glob_match(b"ES?4", b"ESZ4"));
+        assert!(glob_match(b"*.c.*", b"ES.c.0"));
+        assert!(glob_match(b"*", b""));
+        assert!(!glob_match(b"?", b""));
+    }
+
+    #[test]
+    fn test_default_routes() {
+        let router = SymbolRouter::default();
+        assert_eq!(router.dataset_for("ES.FUT"), Some("GLBX.MDP3"));
+        assert_eq!(router.dataset_for("ES.c.0"), Some("GLBX.MDP3"));
+        assert_eq!(router.dataset_for("ESZ4"), Some("GLBX.MDP3"));
+        assert_eq!(router.dataset_for("AAPL"), Some("XNAS.ITCH"));
+        assert_eq!(router.dataset_for("BRK.B"), Some("XNAS.ITCH"));
+    }
+
+    #[test]
+    fn test_spec_parsing_and_grouping() {
+        let router =
+            SymbolRouter::from_spec("*.FUT=GLBX.MDP3; *-USD=CRYPTO; @ticker=XNAS.ITCH").unwrap();
+        let symbols: Vec<String> = ["ES.FUT", "AAPL", "CL.FUT", "BTC-USD"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let groups = router.group(&symbols).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(
+            groups[0],
+            (
+                "GLBX.MDP3".to_string(),
+                vec!["ES.FUT".to_string(), "CL.FUT".to_string()]
+            )
+        );
+        assert_eq!(groups[1].0, "XNAS.ITCH");
+        assert_eq!(groups[2].0, "CRYPTO");
+
+        assert!(router.group(&["esz4".to_string()]).is_err());
+        assert!(SymbolRouter::from_spec("*.FUT").is_err());
+        assert!(SymbolRouter::from_spec("").is_err());
+    }
+}
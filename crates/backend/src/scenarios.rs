@@ -0,0 +1,136 @@
+//! Scheduled market scenarios for mock mode.
+//!
+//! [`Scenarios`] holds what `POST /api/mock/scenarios` schedules; the mock
+//! service looks up the [`Regime`] they make for each symbol as it prices
+//! live and historical data. Scenarios are kept until removed, so a range
+//! fetched again later shows them the same way.
+
+use chrono::Utc;
+use shared::scenarios::{self, Regime, Scenario, ScenarioRequest};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Most scenarios scheduled at once.
+pub const MAX_SCENARIOS: usize = 100;
+
+/// Error type for scheduling scenarios.
+#[derive(Debug, thiserror::Error)]
+pub enum ScenarioError {
+    #[error("Scenarios are only run in mock mode")]
+    Disabled,
+    #[error("Invalid scenario: {0}")]
+    Invalid(String),
+    #[error("At most {MAX_SCENARIOS} scenarios can be scheduled")]
+    Full,
+    #[error("Scenario not found: {0}")]
+    NotFound(u64),
+}
+
+/// The scenarios scheduled for the mock service.
+#[derive(Debug)]
+pub struct Scenarios {
+    next_id: AtomicU64,
+    scheduled: RwLock<Vec<Scenario>>,
+}
+
+impl Default for Scenarios {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scenarios {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            scheduled: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Schedule `req`, its start resolved against the server's clock.
+    pub fn schedule(&self, req: &ScenarioRequest) -> Result<Scenario, ScenarioError> {
+        let mut scheduled = self.scheduled.write().unwrap_or_else(|e| e.into_inner());
+        if scheduled.len() >= MAX_SCENARIOS {
+            return Err(ScenarioError::Full);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let scenario = req
+            .schedule(id, Utc::now())
+            .map_err(ScenarioError::Invalid)?;
+        scheduled.push(scenario.clone());
+        Ok(scenario)
+    }
+
+    /// Every scheduled scenario, soonest first.
+    pub fn list(&self) -> Vec<Scenario> {
+        let mut list = self
+            .scheduled
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        list.sort_by_key(|scenario| (scenario.start_unix_ns, scenario.id));
+        list
+    }
+
+    /// Remove scenario `id`.
+    pub fn remove(&self, id: u64) -> Result<(), ScenarioError> {
+        let mut scheduled = self.scheduled.write().unwrap_or_else(|e| e.into_inner());
+        let before = scheduled.len();
+        scheduled.retain(|scenario| scenario.id != id);
+        match scheduled.len() < before {
+            true => Ok(()),
+            false => Err(ScenarioError::NotFound(id)),
+        }
+    }
+
+    /// Remove every scenario, returning how many there were.
+    pub fn clear(&self) -> usize {
+        let mut scheduled = self.scheduled.write().unwrap_or_else(|e| e.into_inner());
+        std::mem::take(&mut *scheduled).len()
+    }
+
+    /// The regime scheduled scenarios make for `symbol` at `ts_unix_ns`.
+    pub fn regime(&self, symbol: &str, ts_unix_ns: u64) -> Regime {
+        let scheduled = self.scheduled.read().unwrap_or_else(|e| e.into_inner());
+        scenarios::regime_at(&scheduled, symbol, ts_unix_ns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::scenarios::ScenarioKind;
+
+    #[test]
+    fn test_schedule_and_remove() {
+        let scenarios = Scenarios::new();
+        let req = |at: &str| ScenarioRequest {
+            kind: ScenarioKind::FlashCrash,
+            at: at.to_string(),
+            duration_secs: Some(600),
+            magnitude: None,
+            symbols: Vec::new(),
+        };
+        let later = scenarios.schedule(&req("now+1h")).unwrap();
+        let now = scenarios.schedule(&req("now-1m")).unwrap();
+        assert!(matches!(
+            scenarios.schedule(&req("someday")),
+            Err(ScenarioError::Invalid(_))
+        ));
+        assert_eq!(
+            scenarios.list().iter().map(|s| s.id).collect::<Vec<_>>(),
+            [now.id, later.id]
+        );
+        let ts = Utc::now().timestamp_nanos_opt().unwrap() as u64;
+        assert!(scenarios.regime("ES.FUT", ts).shift < 0.0);
+
+        scenarios.remove(now.id).unwrap();
+        assert!(matches!(
+            scenarios.remove(now.id),
+            Err(ScenarioError::NotFound(_))
+        ));
+        assert_eq!(scenarios.regime("ES.FUT", ts), Regime::default());
+        assert_eq!(scenarios.clear(), 1);
+        assert!(scenarios.list().is_empty());
+    }
+}
@@ -0,0 +1,576 @@
+//! Recurring historical data pulls.
+//!
+//! Each registered job runs on its own task, sleeping until the next time
+//! its cron expression fires, then fetching its request and writing the
+//! result under the data directory, with a [`JobManifest`] beside it
+//! recording the request as run and checksums of what was written.
+//! Finished runs are kept in a short in-memory history and broadcast to
+//! subscribers. A run in progress can be cancelled; it stops at its next
+//! [`crate::cancel`] checkpoint, without writing output, and is recorded
+//! as cancelled.
+
+use crate::cancel::{self, CancellationToken};
+use crate::export::{file_sha256, records_sha256, write_response, ExportError};
+use crate::service::{MarketDataService, ServiceError};
+use crate::store::{NamedStore, StoreError};
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use shared::jobs::{JobManifest, JobRange, JobRun, JobSpec, JobStatus};
+use shared::ticks::TickTable;
+use shared::timeexpr::parse_lookback;
+use shared::wire::WIRE_VERSION;
+use shared::{HistoricalRequest, Schema};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Runs kept per job.
+const HISTORY_LEN: usize = 50;
+
+/// Appended to an output's file name to name its manifest.
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+/// Error type for scheduler operations.
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error("Invalid job: {0}")]
+    InvalidSpec(String),
+    #[error("Job: {0}")]
+    Store(#[from] StoreError),
+    #[error(transparent)]
+    Service(#[from] ServiceError),
+    #[error("Export failed: {0}")]
+    Export(#[from] ExportError),
+    #[error("Job '{0}' has no manifest; it hasn't written any output")]
+    NoManifest(String),
+    #[error("Job '{0}' isn't running")]
+    NotRunning(String),
+}
+
+/// Registered jobs, keyed by name.
+pub type JobStore = NamedStore<JobSpec>;
+
+/// Owns the job store and one timer task per job.
+pub struct Scheduler {
+    service: Arc<dyn MarketDataService>,
+    data_dir: PathBuf,
+    ticks: Arc<TickTable>,
+    jobs: JobStore,
+    tasks: Mutex<HashMap<String, JoinHandle<()>>>,
+    history: Mutex<HashMap<String, VecDeque<JobRun>>>,
+    /// Token shared by a job's runs in progress, and how many there are
+    running: Mutex<HashMap<String, (CancellationToken, usize)>>,
+    events: broadcast::Sender<JobRun>,
+}
+
+impl Scheduler {
+    pub fn new(service: Arc<dyn MarketDataService>, jobs: JobStore, data_dir: PathBuf) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            service,
+            data_dir,
+            ticks: Arc::new(TickTable::with_defaults()),
+            jobs,
+            tasks: Mutex::new(HashMap::new()),
+            history: Mutex::new(HashMap::new()),
+            running: Mutex::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// Tick sizes used to format CSV exports.
+    pub fn with_ticks(mut self, ticks: Arc<TickTable>) -> Self {
+        self.ticks = ticks;
+        self
+    }
+
+    /// Start timers for all stored jobs. Jobs that no longer validate are
+    /// logged and left unscheduled.
+    pub fn start(self: &Arc<Self>) {
+        for (name, spec) in self.jobs.list() {
+            match validate(&spec) {
+                Ok(schedule) => self.spawn(spec, schedule),
+                Err(e) => error!(job = %name, "Not scheduling job: {}", e),
+            }
+        }
+    }
+
+    /// Registered jobs, ordered by name.
+    pub fn list(&self) -> Vec<JobSpec> {
+        self.jobs.list().into_iter().map(|(_, spec)| spec).collect()
+    }
+
+    /// Register `spec`, replacing any job with the same name.
+    pub fn add(self: &Arc<Self>, spec: JobSpec) -> Result<(), JobError> {
+        let schedule = validate(&spec)?;
+        self.jobs.insert(&spec.name, spec.clone())?;
+        self.spawn(spec, schedule);
+        Ok(())
+    }
+
+    /// Unregister a job, stop its timer and cancel any run in progress.
+    /// Its run history is dropped.
+    pub fn remove(&self, name: &str) -> Result<JobSpec, JobError> {
+        let spec = self.jobs.remove(name)?;
+        if let Some(task) = lock(&self.tasks).remove(name) {
+            task.abort();
+        }
+        let _ = self.cancel(name);
+        lock(&self.history).remove(name);
+        Ok(spec)
+    }
+
+    /// Run a job immediately, outside its schedule.
+    pub async fn run_now(&self, name: &str) -> Result<JobRun, JobError> {
+        let spec = self.jobs.get(name)?;
+        Ok(self.execute(&spec).await)
+    }
+
+    /// Cancel a job's runs in progress.
+    pub fn cancel(&self, name: &str) -> Result<(), JobError> {
+        match lock(&self.running).get(name) {
+            Some((token, _)) => {
+                token.cancel();
+                Ok(())
+            }
+            None => Err(JobError::NotRunning(name.to_string())),
+        }
+    }
+
+    /// Recent runs of a job, newest first.
+    pub fn history(&self, name: &str) -> Result<Vec<JobRun>, JobError> {
+        self.jobs.get(name)?;
+        Ok(lock(&self.history)
+            .get(name)
+            .map(|runs| runs.iter().rev().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    /// Manifest of a job's latest output still on disk.
+    pub fn manifest(&self, name: &str) -> Result<JobManifest, JobError> {
+        let spec = self.jobs.get(name)?;
+        let dir = self.data_dir.join(output_dir(&spec));
+        let prefix = format!("{}-", spec.name);
+        let entries = match std::fs::read_dir(&dir) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(JobError::NoManifest(spec.name))
+            }
+            entries => entries.map_err(ExportError::from)?,
+        };
+        // File names end with the run time, so the greatest is the latest
+        let latest = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|file| file.starts_with(&prefix) && file.ends_with(MANIFEST_SUFFIX))
+            .max()
+            .ok_or_else(|| JobError::NoManifest(spec.name.clone()))?;
+        let json = std::fs::read(dir.join(latest)).map_err(ExportError::from)?;
+        Ok(serde_json::from_slice(&json).map_err(ExportError::from)?)
+    }
+
+    /// Receive every finished run, successful or not.
+    pub fn subscribe(&self) -> broadcast::Receiver<JobRun> {
+        self.events.subscribe()
+    }
+
+    fn spawn(self: &Arc<Self>, spec: JobSpec, schedule: Schedule) {
+        let name = spec.name.clone();
+        let scheduler = Arc::clone(self);
+        let task = tokio::spawn(async move {
+            // Validated before spawning
+            let tz: Tz = spec.timezone.parse().unwrap_or(Tz::UTC);
+            let mut after = Utc::now();
+            loop {
+                let Some(next) = schedule.after(&after.with_timezone(&tz)).next() else {
+                    info!(job = %spec.name, "Schedule has no further runs");
+                    return;
+                };
+                let next = next.with_timezone(&Utc);
+                let wait = (next - Utc::now()).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+
+                scheduler.execute(&spec).await;
+                // Runs that fall due while a pull is in progress are skipped
+                after = next.max(Utc::now());
+            }
+        });
+
+        if let Some(previous) = lock(&self.tasks).insert(name, task) {
+            previous.abort();
+        }
+    }
+
+    /// Run a job once, recording and broadcasting the outcome.
+    async fn execute(&self, spec: &JobSpec) -> JobRun {
+        let token = {
+            let mut running = lock(&self.running);
+            let (token, runs) = running
+                .entry(spec.name.clone())
+                .or_insert_with(|| (CancellationToken::new(), 0));
+            *runs += 1;
+            token.clone()
+        };
+        let started = Utc::now();
+        let result = cancel::with_cancellation(token, self.pull(spec, started)).await;
+        let finished = Utc::now();
+        {
+            let mut running = lock(&self.running);
+            if let Some((_, runs)) = running.get_mut(&spec.name) {
+                *runs -= 1;
+                if *runs == 0 {
+                    running.remove(&spec.name);
+                }
+            }
+        }
+
+        let mut run = JobRun {
+            job: spec.name.clone(),
+            started_at: started.to_rfc3339_opts(SecondsFormat::Millis, true),
+            finished_at: finished.to_rfc3339_opts(SecondsFormat::Millis, true),
+            status: JobStatus::Succeeded,
+            records: 0,
+            output: None,
+            manifest: None,
+            error: None,
+        };
+        match result {
+            Ok(manifest) => {
+                info!(
+                    job = %spec.name,
+                    records = manifest.records,
+                    output = %manifest.output,
+                    sha256 = %manifest.sha256,
+                    "Job succeeded"
+                );
+                run.records = manifest.records;
+                run.manifest = Some(format!("{}{}", manifest.output, MANIFEST_SUFFIX));
+                run.output = Some(manifest.output);
+            }
+            Err(JobError::Service(ServiceError::Cancelled)) => {
+                info!(job = %spec.name, "Job cancelled");
+                run.status = JobStatus::Cancelled;
+                run.error = Some(ServiceError::Cancelled.to_string());
+            }
+            Err(e) => {
+                error!(job = %spec.name, "Job failed: {}", e);
+                run.status = JobStatus::Failed;
+                run.error = Some(e.to_string());
+            }
+        }
+
+        {
+            let mut history = lock(&self.history);
+            let runs = history.entry(spec.name.clone()).or_default();
+            if runs.len() == HISTORY_LEN {
+                runs.pop_front();
+            }
+            runs.push_back(run.clone());
+        }
+        // No subscribers is fine
+        let _ = self.events.send(run.clone());
+        run
+    }
+
+    /// Fetch and write a job's data and its manifest, which is returned.
+    async fn pull(&self, spec: &JobSpec, now: DateTime<Utc>) -> Result<JobManifest, JobError> {
+        let req = resolve_request(spec, now)?;
+        let mut resp = cancel::or_cancelled(self.service.get_historical(&req)).await?;
+        // A run cancelled as the fetch finished writes nothing
+        cancel::checkpoint()?;
+        let dropped = resp.normalize();
+        if dropped > 0 {
+            info!(job = %spec.name, dropped, "Dropped duplicate records");
+        }
+
+        let relative = output_dir(spec).join(format!(
+            "{}-{}.{}",
+            spec.name,
+            now.format("%Y%m%dT%H%M%SZ"),
+            spec.format.extension()
+        ));
+        let path = self.data_dir.join(&relative);
+        let ticks = Arc::clone(&self.ticks);
+        let mut manifest = JobManifest {
+            job: spec.name.clone(),
+            started_at: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            request: req,
+            provider: self.service.name().to_string(),
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            wire_version: WIRE_VERSION,
+            format: spec.format,
+            output: relative.display().to_string(),
+            records: 0,
+            bytes: 0,
+            sha256: String::new(),
+            records_sha256: String::new(),
+        };
+        let manifest = tokio::task::spawn_blocking(move || {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            manifest.records = write_response(&resp, &path, manifest.format, &ticks)?;
+            manifest.bytes = std::fs::metadata(&path)?.len();
+            manifest.sha256 = file_sha256(&path)?;
+            manifest.records_sha256 = records_sha256(&resp)?;
+            let mut manifest_path = path.into_os_string();
+            manifest_path.push(MANIFEST_SUFFIX);
+            std::fs::write(manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+            Ok::<_, ExportError>(manifest)
+        })
+        .await
+        .map_err(|e| ExportError::Io(std::io::Error::other(e)))??;
+
+        Ok(manifest)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Check a job spec, returning its parsed schedule.
+fn validate(spec: &JobSpec) -> Result<Schedule, JobError> {
+    crate::store::validate_name(&spec.name)?;
+    let schedule = Schedule::from_str(&spec.cron)
+        .map_err(|e| JobError::InvalidSpec(format!("cron '{}': {}", spec.cron, e)))?;
+    parse_timezone(&spec.timezone)?;
+    spec.request
+        .schema
+        .parse::<Schema>()
+        .map_err(ServiceError::InvalidSchema)?;
+    if let JobRange::Last(lookback) = &spec.range {
+        let span = parse_lookback(lookback).map_err(JobError::InvalidSpec)?;
+        if Utc::now().checked_sub_signed(span).is_none() {
+            return Err(JobError::InvalidSpec(format!(
+                "last '{}' out of range",
+                lookback
+            )));
+        }
+    }
+    if let Some(dir) = &spec.output_dir {
+        let relative = Path::new(dir)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if dir.is_empty() || !relative {
+            return Err(JobError::InvalidSpec(format!(
+                "output_dir '{}' must be a relative path inside the data directory",
+                dir
+            )));
+        }
+    }
+    Ok(schedule)
+}
+
+fn parse_timezone(tz: &str) -> Result<Tz, JobError> {
+    tz.parse()
+        .map_err(|_| JobError::InvalidSpec(format!("unknown timezone '{}'", tz)))
+}
+
+fn output_dir(spec: &JobSpec) -> PathBuf {
+    PathBuf::from(spec.output_dir.as_deref().unwrap_or(&spec.name))
+}
+
+/// The job's request with start and end set from its range at `now`.
+fn resolve_request(spec: &JobSpec, now: DateTime<Utc>) -> Result<HistoricalRequest, JobError> {
+    let start = match &spec.range {
+        JobRange::Today => {
+            let tz = parse_timezone(&spec.timezone)?;
+            now.with_timezone(&tz)
+                .date_naive()
+                .and_hms_opt(0, 0, 0)
+                .and_then(|midnight| midnight.and_local_timezone(tz).earliest())
+                .map(|midnight| midnight.with_timezone(&Utc))
+                .ok_or_else(|| JobError::InvalidSpec(format!("no midnight in {} on {}", tz, now)))?
+        }
+        JobRange::Last(lookback) => now
+            .checked_sub_signed(parse_lookback(lookback).map_err(JobError::InvalidSpec)?)
+            .ok_or_else(|| JobError::InvalidSpec(format!("last '{}' out of range", lookback)))?,
+    };
+
+    let mut req = spec.request.clone();
+    req.start_rfc3339 = start.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+    req.end_rfc3339 = now.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+    Ok(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::MockService;
+    use crate::service::LiveStream;
+    use async_trait::async_trait;
+    use shared::jobs::ExportFormat;
+    use shared::{HistoricalResponse, SymbolType};
+
+    fn spec() -> JobSpec {
+        JobSpec {
+            name: "es-close".to_string(),
+            cron: "0 0 17 * * Mon-Fri".to_string(),
+            timezone: "America/Chicago".to_string(),
+            request: HistoricalRequest {
+                symbols: vec!["ES.FUT".to_string()],
+                schema: "ohlcv-1m".to_string(),
+                limit: 5,
+                ..Default::default()
+            },
+            range: JobRange::Today,
+            output_dir: None,
+            format: ExportFormat::Csv,
+        }
+    }
+
+    #[test]
+    fn test_today_starts_at_local_midnight() {
+        // 17:00 CDT is 22:00 UTC
+        let now = DateTime::parse_from_rfc3339("2024-07-01T22:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let req = resolve_request(&spec(), now).unwrap();
+        assert_eq!(req.start_rfc3339, "2024-07-01T05:00:00Z");
+        assert_eq!(req.end_rfc3339, "2024-07-01T22:00:00Z");
+
+        let last = JobSpec {
+            range: JobRange::Last("2h".to_string()),
+            ..spec()
+        };
+        let req = resolve_request(&last, now).unwrap();
+        assert_eq!(req.start_rfc3339, "2024-07-01T20:00:00Z");
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_specs() {
+        assert!(validate(&spec()).is_ok());
+
+        let bad = [
+            JobSpec {
+                cron: "every day".to_string(),
+                ..spec()
+            },
+            JobSpec {
+                timezone: "Mars/Olympus".to_string(),
+                ..spec()
+            },
+            JobSpec {
+                output_dir: Some("../etc".to_string()),
+                ..spec()
+            },
+            JobSpec {
+                output_dir: Some("/data".to_string()),
+                ..spec()
+            },
+            JobSpec {
+                range: JobRange::Last("2w".to_string()),
+                ..spec()
+            },
+        ];
+        for spec in bad {
+            assert!(validate(&spec).is_err(), "accepted {:?}", spec);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_now_writes_output_and_history() {
+        let data_dir = std::env::temp_dir().join(format!("sf-jobs-{}", std::process::id()));
+        let scheduler = Arc::new(Scheduler::new(
+            Arc::new(MockService::new()),
+            JobStore::in_memory(),
+            data_dir.clone(),
+        ));
+        let mut events = scheduler.subscribe();
+        scheduler.add(spec()).unwrap();
+
+        let run = scheduler.run_now("es-close").await.unwrap();
+        assert_eq!(run.status, JobStatus::Succeeded, "{:?}", run.error);
+        let output = run.output.clone().unwrap();
+        assert!(output.starts_with("es-close"));
+        assert!(data_dir.join(&output).exists());
+
+        // The manifest checks out against the file it describes
+        let manifest = scheduler.manifest("es-close").unwrap();
+        assert_eq!(Some(&manifest.output), run.output.as_ref());
+        assert_eq!(manifest.records, run.records);
+        assert_eq!(manifest.provider, "MockService");
+        assert!(!manifest.request.start_rfc3339.is_empty());
+        let path = data_dir.join(&output);
+        assert_eq!(manifest.sha256, file_sha256(&path).unwrap());
+        assert_eq!(manifest.bytes, std::fs::metadata(&path).unwrap().len());
+        assert!(data_dir.join(run.manifest.clone().unwrap()).exists());
+        assert!(matches!(
+            scheduler.manifest("missing"),
+            Err(JobError::Store(StoreError::NotFound(_)))
+        ));
+
+        assert_eq!(events.recv().await.unwrap().output, run.output);
+        assert_eq!(scheduler.history("es-close").unwrap().len(), 1);
+        assert!(matches!(
+            scheduler.run_now("missing").await,
+            Err(JobError::Store(StoreError::NotFound(_)))
+        ));
+
+        scheduler.remove("es-close").unwrap();
+        assert!(scheduler.list().is_empty());
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    /// Never answers a historical fetch.
+    struct Stalled;
+
+    #[async_trait]
+    impl MarketDataService for Stalled {
+        async fn get_historical(
+            &self,
+            _req: &HistoricalRequest,
+        ) -> Result<HistoricalResponse, ServiceError> {
+            futures::future::pending().await
+        }
+
+        async fn subscribe_live(
+            &self,
+            _symbols: Vec<String>,
+            _schema: String,
+            _stype_in: SymbolType,
+        ) -> Result<LiveStream, ServiceError> {
+            Err(ServiceError::NotConfigured("live".to_string()))
+        }
+
+        fn name(&self) -> &'static str {
+            "Stalled"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_run_in_progress() {
+        let data_dir = std::env::temp_dir().join(format!("sf-jobs-cancel-{}", std::process::id()));
+        let scheduler = Arc::new(Scheduler::new(
+            Arc::new(Stalled),
+            JobStore::in_memory(),
+            data_dir.clone(),
+        ));
+        scheduler.add(spec()).unwrap();
+        assert!(matches!(
+            scheduler.cancel("es-close"),
+            Err(JobError::NotRunning(_))
+        ));
+
+        let run = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.run_now("es-close").await }
+        });
+        while scheduler.cancel("es-close").is_err() {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        let run = run.await.unwrap().unwrap();
+        assert_eq!(run.status, JobStatus::Cancelled);
+        assert_eq!(run.output, None);
+        assert_eq!(
+            scheduler.history("es-close").unwrap()[0].status,
+            JobStatus::Cancelled
+        );
+        assert!(scheduler.cancel("es-close").is_err());
+        assert!(!data_dir.exists());
+    }
+}
@@ -0,0 +1,239 @@
+//! Storage for the server's provider credentials.
+//!
+//! Secrets live in a [`SecretBackend`]: an [`EncryptedFile`] sealed with the
+//! master key (see [`crate::crypto`]), or, with the `keychain` feature, the
+//! OS keychain (macOS Keychain, Windows Credential Manager, Secret Service
+//! on Linux). [`Secrets`] caches them in memory so providers can read them
+//! on every request, and writes through on rotation so new values take
+//! effect without a restart.
+
+use crate::crypto::{CryptoError, Sealer};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// Name of the shared DataBento API key.
+pub const DATABENTO_API_KEY: &str = "databento_api_key";
+
+/// Secrets loaded at startup.
+const KNOWN_SECRETS: &[&str] = &[DATABENTO_API_KEY];
+
+/// Error type for secret storage.
+#[derive(Debug, thiserror::Error)]
+pub enum SecretsError {
+    #[error("Invalid secret: {0}")]
+    InvalidValue(String),
+    #[error("Secret storage: {0}")]
+    Backend(String),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// Where secrets are persisted. Calls may block, so [`Secrets`] runs them
+/// on the blocking thread pool.
+pub trait SecretBackend: Send + Sync {
+    fn get(&self, name: &str) -> Result<Option<String>, SecretsError>;
+    fn set(&self, name: &str, value: &str) -> Result<(), SecretsError>;
+
+    /// Name of the backend (for logging).
+    fn name(&self) -> &'static str;
+}
+
+/// Secrets sealed individually in a JSON file.
+pub struct EncryptedFile {
+    path: PathBuf,
+    sealer: Sealer,
+}
+
+impl EncryptedFile {
+    pub fn new(path: impl Into<PathBuf>, sealer: Sealer) -> Self {
+        Self {
+            path: path.into(),
+            sealer,
+        }
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, String>, SecretsError> {
+        let backend_err = |e: &dyn std::fmt::Display| {
+            SecretsError::Backend(format!("{}: {}", self.path.display(), e))
+        };
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| backend_err(&e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(backend_err(&e)),
+        }
+    }
+}
+
+impl SecretBackend for EncryptedFile {
+    fn get(&self, name: &str) -> Result<Option<String>, SecretsError> {
+        match self.read()?.get(name) {
+            Some(sealed) => Ok(Some(self.sealer.open(sealed)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), SecretsError> {
+        let backend_err =
+            |e: std::io::Error| SecretsError::Backend(format!("{}: {}", self.path.display(), e));
+
+        let mut sealed = self.read()?;
+        sealed.insert(name.to_string(), self.sealer.seal(value));
+        let json = serde_json::to_string_pretty(&sealed)
+            .map_err(|e| SecretsError::Backend(e.to_string()))?;
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, json).map_err(backend_err)?;
+        std::fs::rename(&tmp, &self.path).map_err(backend_err)
+    }
+
+    fn name(&self) -> &'static str {
+        "encrypted file"
+    }
+}
+
+/// Secrets held in the OS keychain under one service name.
+#[cfg(feature = "keychain")]
+pub struct Keychain {
+    service: String,
+}
+
+#[cfg(feature = "keychain")]
+impl Keychain {
+    pub fn new(service: &str) -> Self {
+        Self {
+            service: service.to_string(),
+        }
+    }
+
+    fn entry(&self, name: &str) -> Result<keyring::Entry, SecretsError> {
+        keyring::Entry::new(&self.service, name).map_err(|e| SecretsError::Backend(e.to_string()))
+    }
+}
+
+#[cfg(feature = "keychain")]
+impl SecretBackend for Keychain {
+    fn get(&self, name: &str) -> Result<Option<String>, SecretsError> {
+        match self.entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(SecretsError::Backend(e.to_string())),
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> Result<(), SecretsError> {
+        self.entry(name)?
+            .set_password(value)
+            .map_err(|e| SecretsError::Backend(e.to_string()))
+    }
+
+    fn name(&self) -> &'static str {
+        "OS keychain"
+    }
+}
+
+/// Keychain support is not compiled in; rebuild with `--features keychain`.
+#[cfg(not(feature = "keychain"))]
+pub fn keychain_unavailable() -> SecretsError {
+    SecretsError::Backend(
+        "keychain support is not compiled in; rebuild with `--features keychain`".to_string(),
+    )
+}
+
+/// Server secrets, cached in memory over a [`SecretBackend`].
+pub struct Secrets {
+    backend: Arc<dyn SecretBackend>,
+    cache: RwLock<HashMap<String, String>>,
+}
+
+impl Secrets {
+    /// Load the known secrets from `backend`.
+    pub async fn open(backend: Arc<dyn SecretBackend>) -> Result<Self, SecretsError> {
+        let loader = Arc::clone(&backend);
+        let cache = tokio::task::spawn_blocking(move || {
+            let mut cache = HashMap::new();
+            for name in KNOWN_SECRETS {
+                if let Some(value) = loader.get(name)? {
+                    cache.insert(name.to_string(), value);
+                }
+            }
+            Ok::<_, SecretsError>(cache)
+        })
+        .await
+        .map_err(|e| SecretsError::Backend(e.to_string()))??;
+
+        Ok(Self {
+            backend,
+            cache: RwLock::new(cache),
+        })
+    }
+
+    /// Name of the backend (for logging).
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// Store a new value for `name`; readers see it once it is persisted.
+    pub async fn set(&self, name: &str, value: &str) -> Result<(), SecretsError> {
+        let value = value.trim();
+        if value.is_empty() || value.chars().any(char::is_whitespace) {
+            return Err(SecretsError::InvalidValue(
+                "must be non-empty with no whitespace".to_string(),
+            ));
+        }
+
+        let backend = Arc::clone(&self.backend);
+        let (key, stored) = (name.to_string(), value.to_string());
+        tokio::task::spawn_blocking(move || backend.set(&key, &stored))
+            .await
+            .map_err(|e| SecretsError::Backend(e.to_string()))??;
+
+        self.cache
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_encrypted_file_rotation() {
+        let path = std::env::temp_dir().join(format!("sf-secrets-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sealer = Sealer::ephemeral();
+
+        let backend = Arc::new(EncryptedFile::new(&path, sealer.clone()));
+        let secrets = Secrets::open(backend).await.unwrap();
+        assert_eq!(secrets.get(DATABENTO_API_KEY), None);
+
+        secrets.set(DATABENTO_API_KEY, "db-old").await.unwrap();
+        secrets.set(DATABENTO_API_KEY, "db-new").await.unwrap();
+        assert_eq!(secrets.get(DATABENTO_API_KEY).as_deref(), Some("db-new"));
+        assert!(secrets.set(DATABENTO_API_KEY, " ").await.is_err());
+
+        let on_disk = std::fs::read_to_string(&path).unwrap();
+        assert!(!on_disk.contains("db-new"));
+
+        let reopened = Secrets::open(Arc::new(EncryptedFile::new(&path, sealer)))
+            .await
+            .unwrap();
+        assert_eq!(reopened.get(DATABENTO_API_KEY).as_deref(), Some("db-new"));
+
+        // The file is useless without its master key
+        let wrong_key = Arc::new(EncryptedFile::new(&path, Sealer::ephemeral()));
+        assert!(Secrets::open(wrong_key).await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
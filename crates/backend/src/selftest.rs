@@ -0,0 +1,228 @@
+//! Startup self-test for deployment validation.
+//!
+//! `backend --self-test` runs each check against the configured provider
+//! and cache, prints a JSON [`SelfTestReport`], and exits non-zero if any
+//! check failed. Against mock mode it makes a quick CI smoke test.
+
+use crate::cache::HistoricalCache;
+use crate::service::MarketDataService;
+use chrono::{SecondsFormat, Utc};
+use futures::StreamExt;
+use serde::Serialize;
+use shared::{HistoricalRequest, LiveMessage, SymbolType};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Longest any single check may take.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Symbol used by the provider checks.
+const TEST_SYMBOL: &str = "ES.FUT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// Not applicable to this deployment (e.g. no cache configured)
+    Skipped,
+}
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub duration_ms: u64,
+    pub detail: String,
+}
+
+/// Outcome of a full self-test run.
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub provider: &'static str,
+    pub started_at: String,
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+/// Run `check` with a timeout, timing it.
+async fn timed<F>(name: &'static str, check: F) -> CheckResult
+where
+    F: Future<Output = Result<String, String>>,
+{
+    let started = Instant::now();
+    let (status, detail) = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok(detail)) => (CheckStatus::Passed, detail),
+        Ok(Err(detail)) => (CheckStatus::Failed, detail),
+        Err(_) => (
+            CheckStatus::Failed,
+            format!("timed out after {}s", CHECK_TIMEOUT.as_secs()),
+        ),
+    };
+    CheckResult {
+        name,
+        status,
+        duration_ms: started.elapsed().as_millis() as u64,
+        detail,
+    }
+}
+
+/// A small request over a settled window, a day back.
+fn sample_request() -> HistoricalRequest {
+    let end = Utc::now() - chrono::Duration::days(1);
+    let start = end - chrono::Duration::minutes(5);
+    HistoricalRequest {
+        symbols: vec![TEST_SYMBOL.to_string()],
+        schema: "trades".to_string(),
+        start_rfc3339: start.to_rfc3339_opts(SecondsFormat::Secs, true),
+        end_rfc3339: end.to_rfc3339_opts(SecondsFormat::Secs, true),
+        limit: 10,
+        ..Default::default()
+    }
+}
+
+/// The provider accepts our credentials (via its metadata/cost API).
+async fn provider_auth(service: &dyn MarketDataService) -> Result<String, String> {
+    let cost = service
+        .estimate_cost(&sample_request())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(format!("credentials accepted (sample cost ${:.4})", cost))
+}
+
+async fn historical_pull(service: &dyn MarketDataService) -> Result<String, String> {
+    let response = service
+        .get_historical(&sample_request())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(format!("{} records", response.len()))
+}
+
+/// Subscribe, wait for the `connected` acknowledgement, then disconnect.
+async fn live_connect(service: &dyn MarketDataService) -> Result<String, String> {
+    let mut stream = service
+        .subscribe_live(
+            vec![TEST_SYMBOL.to_string()],
+            "trades".to_string(),
+            SymbolType::Parent,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    loop {
+        match stream.next().await {
+            Some(LiveMessage::Connected { .. }) => break,
+            Some(LiveMessage::Error { message }) => return Err(message),
+            Some(_) => {}
+            None => return Err("stream ended before connecting".to_string()),
+        }
+    }
+    drop(stream);
+    Ok("connected and disconnected".to_string())
+}
+
+/// Round-trip a value through the cache.
+async fn cache_round_trip(cache: &dyn HistoricalCache) -> Result<String, String> {
+    let key = format!("sf:selftest:{:016x}", rand::random::<u64>());
+    let value = b"self-test".to_vec();
+    cache
+        .put(&key, value.clone(), Duration::from_secs(60))
+        .await
+        .map_err(|e| e.to_string())?;
+    match cache.get(&key).await.map_err(|e| e.to_string())? {
+        Some(read) if read == value => Ok("write and read back".to_string()),
+        Some(_) => Err("read back a different value".to_string()),
+        None => Err("value missing after write".to_string()),
+    }
+}
+
+/// Run every check. `cache` is the shared historical cache, if configured.
+pub async fn run(
+    service: Arc<dyn MarketDataService>,
+    cache: Option<Arc<dyn HistoricalCache>>,
+) -> SelfTestReport {
+    let started_at = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut checks = vec![
+        timed("provider_auth", provider_auth(service.as_ref())).await,
+        timed("historical_pull", historical_pull(service.as_ref())).await,
+        timed("live_connect", live_connect(service.as_ref())).await,
+    ];
+    checks.push(match &cache {
+        Some(cache) => timed("cache_read_write", cache_round_trip(cache.as_ref())).await,
+        None => CheckResult {
+            name: "cache_read_write",
+            status: CheckStatus::Skipped,
+            duration_ms: 0,
+            detail: "no cache configured (REDIS_URL unset)".to_string(),
+        },
+    });
+
+    SelfTestReport {
+        provider: service.name(),
+        started_at,
+        passed: checks.iter().all(|c| c.status != CheckStatus::Failed),
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheError;
+    use crate::mock_service::MockService;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryCache(Mutex<HashMap<String, Vec<u8>>>);
+
+    #[async_trait]
+    impl HistoricalCache for MemoryCache {
+        async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        async fn put(&self, key: &str, value: Vec<u8>, _ttl: Duration) -> Result<(), CacheError> {
+            self.0.lock().unwrap().insert(key.to_string(), value);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_mode_passes() {
+        let report = run(Arc::new(MockService::new()), None).await;
+        assert!(report.passed, "{:?}", report);
+        assert_eq!(report.checks.len(), 4);
+        assert_eq!(report.checks[3].status, CheckStatus::Skipped);
+
+        let cache: Arc<dyn HistoricalCache> = Arc::new(MemoryCache::default());
+        let report = run(Arc::new(MockService::new()), Some(cache)).await;
+        assert!(report
+            .checks
+            .iter()
+            .all(|c| c.status == CheckStatus::Passed));
+    }
+
+    #[tokio::test]
+    async fn test_failing_cache_fails_report() {
+        struct Broken;
+
+        #[async_trait]
+        impl HistoricalCache for Broken {
+            async fn get(&self, _key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+                Ok(None)
+            }
+
+            async fn put(&self, _: &str, _: Vec<u8>, _: Duration) -> Result<(), CacheError> {
+                Err(CacheError("connection refused".to_string()))
+            }
+        }
+
+        let report = run(Arc::new(MockService::new()), Some(Arc::new(Broken))).await;
+        assert!(!report.passed);
+        assert_eq!(report.checks[3].status, CheckStatus::Failed);
+        assert!(report.checks[3].detail.contains("connection refused"));
+    }
+}
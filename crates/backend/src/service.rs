@@ -1,7 +1,11 @@
 //! MarketDataService trait defining the interface for market data providers.
 
 use async_trait::async_trait;
-use shared::{HistoricalRequest, HistoricalResponse, LiveMessage};
+use shared::aggregate::{cumulative_delta, threshold_bars, BarSpec};
+use shared::book::MboRecord;
+use shared::validation::DatasetRoute;
+use shared::{DataVersion, HistoricalRequest, HistoricalResponse, LiveMessage, Schema, SymbolType};
+use std::future::Future;
 use std::pin::Pin;
 use tokio_stream::Stream;
 
@@ -13,17 +17,54 @@ pub enum ServiceError {
     InvalidSchema(String),
     #[error("Invalid time format: {0}")]
     InvalidTimeFormat(String),
+    #[error("Invalid symbol: {0}")]
+    InvalidSymbol(String),
     #[error("API error: {0}")]
     ApiError(String),
     #[error("Connection error: {0}")]
     ConnectionError(String),
     #[error("Not configured: {0}")]
     NotConfigured(String),
+    #[error("Revision unavailable: {0}")]
+    RevisionUnavailable(String),
+    #[error("Not cached: {0}")]
+    NotCached(String),
+    #[error("Too many records: {0}")]
+    TooManyRecords(String),
+    #[error("Request cancelled")]
+    Cancelled,
 }
 
+tokio::task_local! {
+    /// Provider API key of the user the current task is serving.
+    static USER_API_KEY: Option<String>;
+}
+
+/// Run `fut` on behalf of a user whose own provider key is `api_key`.
+/// Providers use it instead of the server's key, if set.
+pub async fn with_user_api_key<F: Future>(api_key: Option<String>, fut: F) -> F::Output {
+    USER_API_KEY.scope(api_key, fut).await
+}
+
+/// The current user's provider key, if running under [`with_user_api_key`].
+pub fn user_api_key() -> Option<String> {
+    USER_API_KEY.try_with(|key| key.clone()).ok().flatten()
+}
+
+/// Most trades a CVD series is derived from; a range with more fails
+/// rather than carry a delta that stops short.
+pub const MAX_CVD_TRADES: u32 = 1_000_000;
+
+/// Most trades tick, volume or dollar bars are built from; a range with
+/// more fails rather than end early.
+pub const MAX_BAR_TRADES: u32 = 1_000_000;
+
 /// A stream of live market data messages.
 pub type LiveStream = Pin<Box<dyn Stream<Item = LiveMessage> + Send>>;
 
+/// Type alias for a replay of order events, which can fail part way.
+pub type MboStream = Pin<Box<dyn Stream<Item = Result<MboRecord, ServiceError>> + Send>>;
+
 /// Trait defining the interface for market data services.
 /// Implemented by both MockService and DatabentoService.
 #[async_trait]
@@ -34,14 +75,305 @@ pub trait MarketDataService: Send + Sync {
         req: &HistoricalRequest,
     ) -> Result<HistoricalResponse, ServiceError>;
 
-    /// Subscribe to live market data.
+    /// Subscribe to live market data, with `symbols` interpreted per
+    /// `stype_in`.
     /// Returns a stream of LiveMessage that can be forwarded to WebSocket clients.
     async fn subscribe_live(
         &self,
         symbols: Vec<String>,
         schema: String,
+        stype_in: SymbolType,
     ) -> Result<LiveStream, ServiceError>;
 
+    /// Count the records `req` matches, ignoring its limit. The default
+    /// fetches them without a limit and counts what comes back; providers
+    /// with a cheaper way to count override it.
+    async fn count_records(&self, req: &HistoricalRequest) -> Result<u64, ServiceError> {
+        let unlimited = HistoricalRequest {
+            limit: u32::MAX,
+            ..req.clone()
+        };
+        Ok(self.get_historical(&unlimited).await?.len() as u64)
+    }
+
+    /// Estimate the upstream cost in USD of serving `req`. Providers that
+    /// don't bill per request report zero.
+    async fn estimate_cost(&self, _req: &HistoricalRequest) -> Result<f64, ServiceError> {
+        Ok(0.0)
+    }
+
+    /// Which of `req`'s symbols don't resolve for its range and `stype_in`,
+    /// checked without fetching or billing any data. Providers that can't
+    /// tell report none.
+    async fn unresolved_symbols(
+        &self,
+        _req: &HistoricalRequest,
+    ) -> Result<Vec<String>, ServiceError> {
+        Ok(Vec::new())
+    }
+
+    /// When the provider last modified the data `req` covers, per dataset,
+    /// which tells when it restates data. Providers that don't version
+    /// their data report none.
+    async fn data_versions(
+        &self,
+        _req: &HistoricalRequest,
+    ) -> Result<Vec<DataVersion>, ServiceError> {
+        Ok(Vec::new())
+    }
+
+    /// The order events (`mbo`) of `req`'s symbols and range, oldest first
+    /// and at most `req.limit` of them, streamed as they're downloaded.
+    /// Providers without order data refuse.
+    async fn replay_mbo(&self, _req: &HistoricalRequest) -> Result<MboStream, ServiceError> {
+        Err(ServiceError::InvalidSchema(format!(
+            "{} has no order (mbo) data",
+            self.name()
+        )))
+    }
+
+    /// Upstream datasets `req`'s symbols would be fetched from. Providers
+    /// without datasets report none.
+    fn datasets(&self, _req: &HistoricalRequest) -> Result<Vec<DatasetRoute>, ServiceError> {
+        Ok(Vec::new())
+    }
+
     /// Get the name of this service (for logging).
     fn name(&self) -> &'static str;
 }
+
+/// `stype_in`, once `symbols` are checked to be well-formed for it.
+pub fn checked_stype_in(
+    stype_in: SymbolType,
+    symbols: &[String],
+) -> Result<SymbolType, ServiceError> {
+    stype_in
+        .validate(symbols)
+        .map_err(ServiceError::InvalidSymbol)?;
+    Ok(stype_in)
+}
+
+/// Whether `error` may come from only some of a request's symbols, so the
+/// others could still be served.
+fn symbol_specific(error: &ServiceError) -> bool {
+    matches!(
+        error,
+        ServiceError::InvalidSymbol(_) | ServiceError::ApiError(_)
+    )
+}
+
+/// Fetch `req` through `service`, serving the symbols that can be when
+/// some can't. If a multi-symbol fetch fails in a way one symbol could
+/// cause, such as a symbol that doesn't resolve upstream, each symbol is
+/// fetched on its own and those that work are merged under `req.limit` and
+/// `req.limit_per_symbol`. Returns the records and the error of each symbol
+/// left out; fails with the original error when no symbol can be served.
+pub async fn historical_partial<S: MarketDataService + ?Sized>(
+    service: &S,
+    req: &HistoricalRequest,
+) -> Result<(HistoricalResponse, Vec<(String, ServiceError)>), ServiceError> {
+    let error = match service.get_historical(req).await {
+        Ok(response) => return Ok((response, Vec::new())),
+        Err(e) if req.symbols.len() > 1 && symbol_specific(&e) => e,
+        Err(e) => return Err(e),
+    };
+    // Not worth a fetch per symbol for a request no longer wanted
+    crate::cancel::checkpoint()?;
+
+    let results = futures::future::join_all(req.symbols.iter().map(|symbol| async move {
+        let single = HistoricalRequest {
+            symbols: vec![symbol.clone()],
+            ..req.clone()
+        };
+        (symbol.clone(), service.get_historical(&single).await)
+    }))
+    .await;
+    let mut merged: Option<HistoricalResponse> = None;
+    let mut failed = Vec::new();
+    for (symbol, result) in results {
+        match (result, merged.as_mut()) {
+            (Ok(response), Some(acc)) => acc.append(response),
+            (Ok(response), None) => merged = Some(response),
+            (Err(e), _) => failed.push((symbol, e)),
+        }
+    }
+    let Some(mut response) = merged else {
+        return Err(error);
+    };
+    response.sort_by_time();
+    response.apply_limits(req.limit, req.limit_per_symbol);
+    Ok((response, failed))
+}
+
+/// Serve a derived CVD request (`cvd-1s`, `cvd-1m`) from side-tagged trades
+/// fetched through `service`. `req.limit` and `req.limit_per_symbol` cap
+/// the bars returned.
+pub async fn historical_cvd<S: MarketDataService + ?Sized>(
+    service: &S,
+    req: &HistoricalRequest,
+    schema: Schema,
+) -> Result<HistoricalResponse, ServiceError> {
+    let interval_ns = schema.cvd_interval_ns().ok_or_else(|| {
+        ServiceError::InvalidSchema(format!("{} is not a CVD schema", schema.as_str()))
+    })?;
+    let align = req.bar_align().map_err(ServiceError::InvalidTimeFormat)?;
+
+    let trades_req = HistoricalRequest {
+        schema: Schema::Trades.as_str().to_string(),
+        // One over the cap tells a range with too many apart
+        limit: MAX_CVD_TRADES + 1,
+        limit_per_symbol: None,
+        quote_at_trade: true,
+        ..req.clone()
+    };
+    let mut response = service.get_historical(&trades_req).await?;
+    check_trade_cap(&response, MAX_CVD_TRADES, req, &schema.as_str())?;
+    // Duplicated trades would count twice towards the delta
+    response.normalize();
+    let trades = match response {
+        HistoricalResponse::Trades { data } => data,
+        other => {
+            return Err(ServiceError::ApiError(format!(
+                "Expected trades for CVD, got {}",
+                other.schema().as_str()
+            )))
+        }
+    };
+
+    let data = cumulative_delta(&trades, interval_ns, align);
+    let mut response = match schema {
+        Schema::Cvd1S => HistoricalResponse::Cvd1S { data },
+        _ => HistoricalResponse::Cvd1M { data },
+    };
+    response.apply_limits(req.limit, req.limit_per_symbol);
+    Ok(response)
+}
+
+/// Fail unless `response`, trades fetched with a limit one over `cap`, is
+/// within it: a derived series missing the rest would be silently wrong.
+fn check_trade_cap(
+    response: &HistoricalResponse,
+    cap: u32,
+    req: &HistoricalRequest,
+    derived: &str,
+) -> Result<(), ServiceError> {
+    if response.len() > cap as usize {
+        return Err(ServiceError::TooManyRecords(format!(
+            "More than {} trades from {} to {} to derive {} from; ask for a shorter range",
+            cap, req.start_rfc3339, req.end_rfc3339, derived
+        )));
+    }
+    Ok(())
+}
+
+/// Serve a tick, volume or dollar bar request (`tickbar-500`, ...) from
+/// trades fetched through `service`. `req.limit` and `req.limit_per_symbol`
+/// cap the bars returned.
+pub async fn historical_bars<S: MarketDataService + ?Sized>(
+    service: &S,
+    req: &HistoricalRequest,
+    spec: BarSpec,
+) -> Result<HistoricalResponse, ServiceError> {
+    if req.quote_at_trade {
+        return Err(ServiceError::InvalidSchema(
+            "quote_at_trade requires the trades schema".to_string(),
+        ));
+    }
+    let trades_req = HistoricalRequest {
+        schema: Schema::Trades.as_str().to_string(),
+        // One over the cap tells a range with too many apart
+        limit: MAX_BAR_TRADES + 1,
+        limit_per_symbol: None,
+        ..req.clone()
+    };
+    let mut response = service.get_historical(&trades_req).await?;
+    check_trade_cap(&response, MAX_BAR_TRADES, req, &spec.to_string())?;
+    // A duplicated trade would count towards the threshold twice
+    response.normalize();
+    let HistoricalResponse::Trades { data: trades } = response else {
+        return Err(ServiceError::ApiError(format!(
+            "Expected trades for {}, got {}",
+            spec,
+            response.schema()
+        )));
+    };
+
+    let mut response = HistoricalResponse::Bars {
+        spec,
+        data: threshold_bars(&trades, spec),
+    };
+    response.apply_limits(req.limit, req.limit_per_symbol);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::TradeRecord;
+
+    /// Serves as many trades as asked for, a millisecond apart, whatever
+    /// the range. Live subscriptions are refused.
+    struct Firehose;
+
+    #[async_trait]
+    impl MarketDataService for Firehose {
+        async fn get_historical(
+            &self,
+            req: &HistoricalRequest,
+        ) -> Result<HistoricalResponse, ServiceError> {
+            let data = (0..u64::from(req.limit))
+                .map(|i| TradeRecord {
+                    ts_event_unix_ns: 1_704_067_200_000_000_000 + i * 1_000_000,
+                    symbol: String::new(),
+                    price_i64: 5_000_000_000_000,
+                    size_u32: 1,
+                    quote: None,
+                    sequence_u32: None,
+                })
+                .collect();
+            Ok(HistoricalResponse::Trades { data })
+        }
+
+        async fn subscribe_live(
+            &self,
+            _symbols: Vec<String>,
+            _schema: String,
+            _stype_in: SymbolType,
+        ) -> Result<LiveStream, ServiceError> {
+            Err(ServiceError::NotConfigured("live".to_string()))
+        }
+
+        fn name(&self) -> &'static str {
+            "Firehose"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cvd_fails_past_trade_cap() {
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "cvd-1m".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-02T00:00:00Z".to_string(),
+            ..Default::default()
+        };
+
+        let result = historical_cvd(&Firehose, &req, Schema::Cvd1M).await;
+        assert!(matches!(result, Err(ServiceError::TooManyRecords(_))));
+    }
+
+    #[tokio::test]
+    async fn test_bars_fail_past_trade_cap() {
+        let req = HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "volbar-100".to_string(),
+            start_rfc3339: "2024-01-01T00:00:00Z".to_string(),
+            end_rfc3339: "2024-01-02T00:00:00Z".to_string(),
+            ..Default::default()
+        };
+        let spec = "volbar-100".parse().unwrap();
+
+        let result = historical_bars(&Firehose, &req, spec).await;
+        assert!(matches!(result, Err(ServiceError::TooManyRecords(_))));
+    }
+}
@@ -0,0 +1,194 @@
+//! Small named-item stores persisted to a local JSON file.
+//!
+//! Used for saved queries and scheduled jobs: a handful of user-defined
+//! items that must survive restarts but don't justify a database.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{RwLock, RwLockReadGuard};
+
+/// Longest accepted item name.
+const MAX_NAME_LEN: usize = 64;
+
+/// Error type for store operations.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("Invalid name '{0}': use 1-64 letters, digits, '-' or '_'")]
+    InvalidName(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Failed to persist: {0}")]
+    Storage(String),
+}
+
+/// Items keyed by name, kept in memory and written through to `path`.
+pub struct NamedStore<T> {
+    path: Option<PathBuf>,
+    items: RwLock<BTreeMap<String, T>>,
+}
+
+impl<T: Clone + Serialize + DeserializeOwned> NamedStore<T> {
+    /// A store that is not persisted.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            items: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Open the store at `path`, loading any items already saved there.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let path = path.into();
+        let items = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| StoreError::Storage(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(StoreError::Storage(format!("{}: {}", path.display(), e))),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            items: RwLock::new(items),
+        })
+    }
+
+    /// Number of stored items.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Names and items, ordered by name.
+    pub fn list(&self) -> Vec<(String, T)> {
+        self.read()
+            .iter()
+            .map(|(name, item)| (name.clone(), item.clone()))
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Result<T, StoreError> {
+        self.read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(name.to_string()))
+    }
+
+    /// Store `item` under `name`, replacing any existing item.
+    pub fn insert(&self, name: &str, item: T) -> Result<(), StoreError> {
+        validate_name(name)?;
+
+        let mut items = self.items.write().unwrap_or_else(|e| e.into_inner());
+        let previous = items.insert(name.to_string(), item);
+        if let Err(e) = self.persist(&items) {
+            // Keep memory consistent with what is on disk
+            match previous {
+                Some(previous) => items.insert(name.to_string(), previous),
+                None => items.remove(name),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Store `item` under `name` unless an item is already stored there,
+    /// returning whether it was stored.
+    pub fn insert_new(&self, name: &str, item: T) -> Result<bool, StoreError> {
+        validate_name(name)?;
+
+        let mut items = self.items.write().unwrap_or_else(|e| e.into_inner());
+        if items.contains_key(name) {
+            return Ok(false);
+        }
+        items.insert(name.to_string(), item);
+        if let Err(e) = self.persist(&items) {
+            items.remove(name);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Remove and return the item stored under `name`.
+    pub fn remove(&self, name: &str) -> Result<T, StoreError> {
+        let mut items = self.items.write().unwrap_or_else(|e| e.into_inner());
+        let item = items
+            .remove(name)
+            .ok_or_else(|| StoreError::NotFound(name.to_string()))?;
+        if let Err(e) = self.persist(&items) {
+            items.insert(name.to_string(), item);
+            return Err(e);
+        }
+        Ok(item)
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<String, T>> {
+        self.items.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write all items to disk via a temporary file so a crash never
+    /// leaves a truncated store behind.
+    fn persist(&self, items: &BTreeMap<String, T>) -> Result<(), StoreError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let storage_err =
+            |e: std::io::Error| StoreError::Storage(format!("{}: {}", path.display(), e));
+
+        let json =
+            serde_json::to_string_pretty(items).map_err(|e| StoreError::Storage(e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json).map_err(storage_err)?;
+        std::fs::rename(&tmp, path).map_err(storage_err)
+    }
+}
+
+/// Check that `name` is safe to use as a key, URL segment, and file name.
+pub fn validate_name(name: &str) -> Result<(), StoreError> {
+    let valid = (1..=MAX_NAME_LEN).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_persists_across_reopen() {
+        let path = std::env::temp_dir().join(format!("sf-store-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = NamedStore::<u32>::open(&path).unwrap();
+        assert!(store.is_empty());
+        store.insert("es-hourly", 1).unwrap();
+        store.insert("cl_daily", 2).unwrap();
+        assert!(!store.insert_new("cl_daily", 4).unwrap());
+        assert!(store.insert_new("nq-weekly", 5).unwrap());
+        assert_eq!(store.get("cl_daily").unwrap(), 2);
+        assert_eq!(store.remove("nq-weekly").unwrap(), 5);
+        assert!(matches!(
+            store.insert("bad name", 3),
+            Err(StoreError::InvalidName(_))
+        ));
+        assert_eq!(store.remove("cl_daily").unwrap(), 2);
+
+        let reopened = NamedStore::<u32>::open(&path).unwrap();
+        assert_eq!(reopened.list(), vec![("es-hourly".to_string(), 1)]);
+        assert!(matches!(
+            reopened.get("missing"),
+            Err(StoreError::NotFound(_))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
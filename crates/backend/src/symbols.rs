@@ -0,0 +1,342 @@
+//! Symbol checks run before a historical request is fetched.
+//!
+//! A typo such as `ESS.FUT` would otherwise go all the way upstream, cost a
+//! round trip (or a billed request) and fail with the provider's message.
+//! Instead the request's symbols are resolved first with
+//! [`MarketDataService::unresolved_symbols`], and any that don't resolve
+//! are rejected with a 400 naming them and the known symbols closest to
+//! each. Resolutions are cached per symbol, symbology and day range for
+//! `SYMBOL_CACHE_TTL_SECS` (a day by default), so repeated requests don't
+//! resolve again.
+
+use crate::service::{MarketDataService, ServiceError};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use shared::ticks::{symbol_root, TickTable};
+use shared::{HistoricalRequest, SymbolType, UnknownSymbol, UnknownSymbolsResponse};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most suggestions given for an unknown symbol.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Cached resolutions kept before expired ones are pruned.
+const PRUNE_AT: usize = 10_000;
+
+/// Symbols in a request that don't resolve.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{}", describe(.0))]
+pub struct UnknownSymbols(pub Vec<UnknownSymbol>);
+
+impl IntoResponse for UnknownSymbols {
+    fn into_response(self) -> Response {
+        let status = StatusCode::BAD_REQUEST;
+        let body = UnknownSymbolsResponse {
+            error: self.to_string(),
+            code: status.as_u16(),
+            symbols: self.0,
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+fn describe(unknown: &[UnknownSymbol]) -> String {
+    let symbols: Vec<String> = unknown
+        .iter()
+        .map(|u| match u.suggestions.as_slice() {
+            [] => u.symbol.clone(),
+            suggestions => format!("{} (did you mean {}?)", u.symbol, suggestions.join(", ")),
+        })
+        .collect();
+    let noun = if unknown.len() == 1 {
+        "symbol"
+    } else {
+        "symbols"
+    };
+    format!("Unknown {}: {}", noun, symbols.join("; "))
+}
+
+/// What a resolution is cached under: the same symbol can resolve in one
+/// dataset or period and not another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    stype_in: SymbolType,
+    dataset: Option<String>,
+    symbol: String,
+    start_day: String,
+    end_day: String,
+}
+
+/// UTC day of an RFC3339 timestamp, or the timestamp if it doesn't parse.
+fn day(ts: &str) -> String {
+    DateTime::parse_from_rfc3339(ts).map_or_else(
+        |_| ts.to_string(),
+        |t| t.with_timezone(&Utc).date_naive().to_string(),
+    )
+}
+
+/// Checks request symbols against the provider, caching what resolves.
+pub struct SymbolResolver {
+    ticks: Arc<TickTable>,
+    ttl: Duration,
+    /// Whether each symbol resolved, and when that was found
+    cache: Mutex<HashMap<CacheKey, (bool, Instant)>>,
+}
+
+impl SymbolResolver {
+    pub fn new(ticks: Arc<TickTable>, ttl: Duration) -> Self {
+        Self {
+            ticks,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key(req: &HistoricalRequest, symbol: &str) -> CacheKey {
+        CacheKey {
+            stype_in: req.stype_in,
+            dataset: req.dataset.clone(),
+            symbol: symbol.to_string(),
+            start_day: day(&req.start_rfc3339),
+            end_day: day(&req.end_rfc3339),
+        }
+    }
+
+    /// `req`'s symbols that don't resolve, each with suggestions. Only
+    /// symbols without a cached resolution are sent to `service`.
+    pub async fn unknown(
+        &self,
+        service: &dyn MarketDataService,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<UnknownSymbol>, ServiceError> {
+        let now = Instant::now();
+        let mut resolved = HashMap::new();
+        let mut uncached = Vec::new();
+        {
+            let cache = self.cache.lock().unwrap();
+            for symbol in &req.symbols {
+                match cache.get(&Self::key(req, symbol)) {
+                    Some((ok, at)) if now.duration_since(*at) < self.ttl => {
+                        resolved.insert(symbol.clone(), *ok);
+                    }
+                    _ => uncached.push(symbol.clone()),
+                }
+            }
+        }
+
+        if !uncached.is_empty() {
+            let lookup = HistoricalRequest {
+                symbols: uncached.clone(),
+                ..req.clone()
+            };
+            let unresolved = service.unresolved_symbols(&lookup).await?;
+            let mut cache = self.cache.lock().unwrap();
+            if cache.len() >= PRUNE_AT {
+                cache.retain(|_, (_, at)| now.duration_since(*at) < self.ttl);
+            }
+            for symbol in uncached {
+                let ok = !unresolved.contains(&symbol);
+                cache.insert(Self::key(req, &symbol), (ok, now));
+                resolved.insert(symbol, ok);
+            }
+        }
+
+        Ok(req
+            .symbols
+            .iter()
+            .filter(|symbol| resolved.get(*symbol) == Some(&false))
+            .map(|symbol| UnknownSymbol {
+                symbol: symbol.clone(),
+                suggestions: self.suggest(symbol, req.stype_in),
+            })
+            .collect())
+    }
+
+    /// Known symbols closest to `symbol`: the configured futures roots in
+    /// the form `symbol` was written in (`ES.FUT`, `ES.c.0`, `ESZ4`), and
+    /// symbols that have resolved before.
+    fn suggest(&self, symbol: &str, stype_in: SymbolType) -> Vec<String> {
+        // Suffixes are written as upstream spells them: `.FUT`, `.c.0`
+        let suffix = &symbol[symbol_root(symbol).len()..];
+        let suffix = match stype_in {
+            SymbolType::InstrumentId => return Vec::new(),
+            SymbolType::Continuous => suffix.to_ascii_lowercase(),
+            _ => suffix.to_ascii_uppercase(),
+        };
+        let mut candidates: Vec<String> = self
+            .ticks
+            .entries()
+            .into_iter()
+            .map(|entry| format!("{}{}", entry.root, suffix))
+            .collect();
+        candidates.extend(
+            self.cache
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(key, (ok, _))| *ok && key.stype_in == stype_in)
+                .map(|(key, _)| key.symbol.clone()),
+        );
+
+        let wanted = symbol.to_ascii_uppercase();
+        // Allow about one typo per four characters
+        let max_distance = (wanted.len() / 4).max(1);
+        let mut scored: Vec<(usize, String)> = candidates
+            .into_iter()
+            .filter(|candidate| candidate != symbol)
+            .map(|candidate| {
+                let distance = edit_distance(&wanted, &candidate.to_ascii_uppercase());
+                (distance, candidate)
+            })
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+        scored.sort();
+        scored.dedup_by(|a, b| a.1 == b.1);
+        scored
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+}
+
+/// Levenshtein distance between two strings, by character.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_service::MockService;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn request(symbols: &[&str], stype_in: SymbolType) -> HistoricalRequest {
+        HistoricalRequest {
+            symbols: symbols.iter().map(ToString::to_string).collect(),
+            schema: "trades".to_string(),
+            stype_in,
+            start_rfc3339: "2024-01-02T14:30:00Z".to_string(),
+            end_rfc3339: "2024-01-02T15:30:00Z".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("ES.FUT", "ES.FUT"), 0);
+        assert_eq!(edit_distance("ESS.FUT", "ES.FUT"), 1);
+        assert_eq!(edit_distance("NQ", "ES"), 2);
+        assert_eq!(edit_distance("", "ES"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_symbols_suggested() {
+        let resolver = SymbolResolver::new(
+            Arc::new(TickTable::with_defaults()),
+            Duration::from_secs(60),
+        );
+        let service = MockService::new();
+
+        let req = request(
+            &["ES.FUT", "ESS.FUT", "es.fut", "QQQQQ.FUT"],
+            SymbolType::Parent,
+        );
+        let unknown = resolver.unknown(&service, &req).await.unwrap();
+        assert_eq!(unknown.len(), 3);
+        assert_eq!(unknown[0].symbol, "ESS.FUT");
+        assert_eq!(unknown[0].suggestions, vec!["ES.FUT"]);
+        // Case mismatches rank first
+        assert_eq!(unknown[1].suggestions[0], "ES.FUT");
+        assert!(unknown[2].suggestions.is_empty());
+        let message = UnknownSymbols(unknown).to_string();
+        assert!(message.starts_with("Unknown symbols: ESS.FUT (did you mean ES.FUT?)"));
+
+        // Suggestions keep the symbology the symbol was written in
+        let req = request(&["NQQ.c.0"], SymbolType::Continuous);
+        let unknown = resolver.unknown(&service, &req).await.unwrap();
+        assert_eq!(unknown[0].suggestions, vec!["NQ.c.0"]);
+
+        assert!(resolver
+            .unknown(&service, &request(&["4916"], SymbolType::InstrumentId))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Counts how many symbols it's asked to resolve, and serves no data.
+    struct Counting(AtomicUsize);
+
+    #[async_trait]
+    impl MarketDataService for Counting {
+        async fn get_historical(
+            &self,
+            _req: &HistoricalRequest,
+        ) -> Result<shared::HistoricalResponse, ServiceError> {
+            Err(ServiceError::NotConfigured("historical".to_string()))
+        }
+
+        async fn subscribe_live(
+            &self,
+            _symbols: Vec<String>,
+            _schema: String,
+            _stype_in: SymbolType,
+        ) -> Result<crate::service::LiveStream, ServiceError> {
+            Err(ServiceError::NotConfigured("live".to_string()))
+        }
+
+        async fn unresolved_symbols(
+            &self,
+            req: &HistoricalRequest,
+        ) -> Result<Vec<String>, ServiceError> {
+            self.0.fetch_add(req.symbols.len(), Ordering::SeqCst);
+            Ok(Vec::new())
+        }
+
+        fn name(&self) -> &'static str {
+            "Counting"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolutions_cached() {
+        let resolver = SymbolResolver::new(
+            Arc::new(TickTable::with_defaults()),
+            Duration::from_secs(60),
+        );
+        let service = Counting(AtomicUsize::new(0));
+
+        let req = request(&["ES.FUT", "NQ.FUT"], SymbolType::Parent);
+        resolver.unknown(&service, &req).await.unwrap();
+        resolver.unknown(&service, &req).await.unwrap();
+        assert_eq!(service.0.load(Ordering::SeqCst), 2);
+
+        // Another day resolves again
+        let later = HistoricalRequest {
+            start_rfc3339: "2024-03-01T14:30:00Z".to_string(),
+            ..request(&["ES.FUT"], SymbolType::Parent)
+        };
+        resolver.unknown(&service, &later).await.unwrap();
+        assert_eq!(service.0.load(Ordering::SeqCst), 3);
+
+        let expired = SymbolResolver::new(Arc::new(TickTable::with_defaults()), Duration::ZERO);
+        expired.unknown(&service, &req).await.unwrap();
+        expired.unknown(&service, &req).await.unwrap();
+        assert_eq!(service.0.load(Ordering::SeqCst), 7);
+    }
+}
@@ -0,0 +1,283 @@
+//! Log output and OpenTelemetry span export.
+//!
+//! Logs always go to stdout, filtered by `RUST_LOG`, as human-readable text
+//! or, with `LOG_FORMAT=json`, one JSON object per line carrying the
+//! event's fields and those of its enclosing spans (`request_id`, `route`,
+//! `symbols`, `duration_ms`, ...) at the top level. When an OTLP endpoint
+//! is configured and the `otel` feature is compiled in, the same spans are
+//! also sent over OTLP/HTTP (protobuf) to a collector such as Jaeger or
+//! Tempo. A historical request is traced as
+//! `request` (HTTP handler) → `historical` → `provider` → `decode`, with
+//! its symbols, schema, record count and cache status as span attributes.
+
+use axum::extract::MatchedPath;
+use axum::http::{HeaderValue, Request, Response};
+use axum::middleware::Next;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{info, info_span, Event, Span, Subscriber};
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry};
+
+/// Filter used when `RUST_LOG` is unset.
+const DEFAULT_FILTER: &str = "backend=info,tower_http=info";
+
+#[cfg(feature = "otel")]
+type Provider = opentelemetry_sdk::trace::SdkTracerProvider;
+#[cfg(not(feature = "otel"))]
+type Provider = std::convert::Infallible;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Log line format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("'{}': expected text or json", other)),
+        }
+    }
+}
+
+/// Error type for span export.
+#[derive(Debug, thiserror::Error)]
+#[error("Span export: {0}")]
+pub struct TelemetryError(pub String);
+
+/// Where spans are exported.
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// Collector base URL, e.g. `http://localhost:4318`; spans are posted
+    /// to `/v1/traces` under it
+    pub endpoint: String,
+    /// `service.name` resource attribute
+    pub service_name: String,
+}
+
+/// Installed tracing; [`Telemetry::shutdown`] flushes exported spans.
+pub struct Telemetry {
+    provider: Option<Provider>,
+}
+
+impl Telemetry {
+    /// Whether spans are being exported.
+    pub fn is_exporting(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// Export any buffered spans and stop exporting.
+    pub fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("Failed to flush spans: {}", e);
+            }
+        }
+    }
+}
+
+fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_FILTER.into())
+}
+
+/// Install the global subscriber, logging in `format` and exporting spans
+/// to `otlp` if given. An exporter that can't be set up is logged and
+/// leaves only log output.
+pub fn init(format: LogFormat, otlp: Option<&OtlpConfig>) -> Telemetry {
+    let (export, failure) = match otlp.map(exporter).transpose() {
+        Ok(export) => (export, None),
+        Err(e) => (None, Some(e)),
+    };
+    let (layer, provider) = export.unzip();
+
+    let output: BoxedLayer = match format {
+        LogFormat::Text => Box::new(tracing_subscriber::fmt::layer().with_filter(env_filter())),
+        LogFormat::Json => Box::new(
+            tracing_subscriber::fmt::layer()
+                .fmt_fields(JsonFields::new())
+                .event_format(FlatJson)
+                .with_filter(env_filter()),
+        ),
+    };
+    tracing_subscriber::registry()
+        .with(layer.into_iter().chain([output]).collect::<Vec<_>>())
+        .init();
+
+    if let Some(e) = failure {
+        tracing::error!("{}", e);
+    }
+    Telemetry { provider }
+}
+
+/// Header carrying a request's id.
+pub const REQUEST_ID: &str = "x-request-id";
+
+/// Middleware giving a request without an `X-Request-Id` header a random
+/// one, and echoing it in the response so the request can be referred to,
+/// e.g. to cancel it.
+pub async fn request_id(
+    mut request: axum::extract::Request,
+    next: Next,
+) -> axum::response::Response {
+    let id = match request.headers().get(REQUEST_ID) {
+        Some(id) => id.clone(),
+        None => {
+            let id = HeaderValue::from_str(&format!("{:016x}", rand::random::<u64>()))
+                .expect("hex is a valid header value");
+            request.headers_mut().insert(REQUEST_ID, id.clone());
+            id
+        }
+    };
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(REQUEST_ID, id);
+    response
+}
+
+/// Span of one HTTP request: its `request_id` (the `X-Request-Id` header,
+/// or a new random id), method and `route` (the matched path pattern).
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(request.uri().path(), MatchedPath::as_str);
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+    info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        route = %route
+    )
+}
+
+/// Log a served request with its status and `duration_ms`.
+pub fn log_response<B>(response: &Response<B>, latency: Duration, _span: &Span) {
+    info!(
+        status = response.status().as_u16(),
+        duration_ms = latency.as_millis() as u64,
+        "Served request"
+    );
+}
+
+/// JSON lines with span fields merged in, outermost span first, so every
+/// line of a request carries its `request_id` and `route`.
+struct FlatJson;
+
+impl<S, N> FormatEvent<S, N> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Micros, true)
+                .into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+        for span in ctx.event_scope().into_iter().flat_map(|s| s.from_root()) {
+            line.insert("span".to_string(), span.name().into());
+            let extensions = span.extensions();
+            let fields = extensions
+                .get::<FormattedFields<N>>()
+                .and_then(|f| serde_json::from_str::<Map<String, Value>>(f).ok());
+            line.extend(fields.unwrap_or_default());
+        }
+        event.record(&mut JsonVisitor(&mut line));
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(feature = "otel")]
+fn exporter(config: &OtlpConfig) -> Result<(BoxedLayer, Provider), TelemetryError> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!(
+            "{}/v1/traces",
+            config.endpoint.trim_end_matches('/')
+        ))
+        .build()
+        .map_err(|e| TelemetryError(format!("{}: {}", config.endpoint, e)))?;
+    let resource = opentelemetry_sdk::Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .build();
+    let provider = Provider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("backend"))
+        .with_filter(env_filter());
+    Ok((Box::new(layer), provider))
+}
+
+#[cfg(not(feature = "otel"))]
+fn exporter(_config: &OtlpConfig) -> Result<(BoxedLayer, Provider), TelemetryError> {
+    Err(TelemetryError(
+        "OTLP support is not compiled in; rebuild with `--features otel`".to_string(),
+    ))
+}
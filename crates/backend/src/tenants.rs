@@ -0,0 +1,123 @@
+//! Tenants: separate teams served by one deployment.
+//!
+//! Each tenant listed in the tenants file (`TENANTS_PATH`) gets its own copy
+//! of the API under `/t/{tenant}/api/...` and `/t/{tenant}/ws/...`. Nothing
+//! is shared with other tenants or with the default API at `/api/...`:
+//! saved queries, scheduled jobs and their output, webhooks, users, usage,
+//! the query log and secrets (so the DataBento key too) live under the
+//! tenant's own directory, and its Redis cache entries and live channels are
+//! namespaced. A tenants file looks like:
+//!
+//! ```json
+//! [
+//!   { "name": "research", "admin_token": "...", "usage_quotas": "cost_usd=50" },
+//!   { "name": "desk", "databento_per_user": true, "max_ranges": "trades=1d" }
+//! ]
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Error type for loading tenants.
+#[derive(Debug, thiserror::Error)]
+pub enum TenantError {
+    #[error("Failed to read tenants file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid tenants file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Invalid tenant name '{0}': use letters, digits, '-' and '_'")]
+    InvalidName(String),
+    #[error("Tenant '{0}' is listed twice")]
+    Duplicate(String),
+}
+
+/// One tenant and the settings it overrides.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantConfig {
+    /// Path segment the tenant is served under, e.g. `research`
+    pub name: String,
+    /// Bearer token for the tenant's user management; unset disables it
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Monthly per-key quotas, as in `USAGE_QUOTAS`
+    #[serde(default)]
+    pub usage_quotas: Option<String>,
+    /// Per-schema range limit overrides, as in `MAX_RANGES`
+    #[serde(default)]
+    pub max_ranges: Option<String>,
+    /// Use DataBento without a tenant key, serving only users who
+    /// registered their own
+    #[serde(default)]
+    pub databento_per_user: bool,
+}
+
+impl TenantConfig {
+    /// Directory the tenant's state lives in, under `root`.
+    pub fn dir(&self, root: &Path) -> PathBuf {
+        root.join(&self.name)
+    }
+
+    /// Prefix for the tenant's keys in shared stores such as Redis.
+    pub fn namespace(&self) -> String {
+        format!("tenant:{}:", self.name)
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Parse and check a tenants file's contents.
+pub fn parse(json: &str) -> Result<Vec<TenantConfig>, TenantError> {
+    let tenants: Vec<TenantConfig> = serde_json::from_str(json)?;
+    let mut seen = HashSet::new();
+    for tenant in &tenants {
+        if !valid_name(&tenant.name) {
+            return Err(TenantError::InvalidName(tenant.name.clone()));
+        }
+        if !seen.insert(tenant.name.as_str()) {
+            return Err(TenantError::Duplicate(tenant.name.clone()));
+        }
+    }
+    Ok(tenants)
+}
+
+/// Load the tenants file at `path`.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<TenantConfig>, TenantError> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tenants() {
+        let tenants = parse(
+            r#"[{"name": "research", "usage_quotas": "cost_usd=50"},
+                {"name": "desk", "databento_per_user": true}]"#,
+        )
+        .unwrap();
+        assert_eq!(tenants.len(), 2);
+        assert_eq!(tenants[0].usage_quotas.as_deref(), Some("cost_usd=50"));
+        assert!(tenants[1].databento_per_user);
+        assert_eq!(
+            tenants[1].dir(Path::new("tenants")),
+            Path::new("tenants/desk")
+        );
+        assert_eq!(tenants[1].namespace(), "tenant:desk:");
+
+        assert!(matches!(
+            parse(r#"[{"name": "../etc"}]"#),
+            Err(TenantError::InvalidName(_))
+        ));
+        assert!(matches!(
+            parse(r#"[{"name": "desk"}, {"name": "desk"}]"#),
+            Err(TenantError::Duplicate(_))
+        ));
+    }
+}
@@ -0,0 +1,366 @@
+//! DBN files uploaded for viewing.
+//!
+//! Many DataBento users have batch downloads on disk. `POST /api/uploads`
+//! takes one such `.dbn` or `.dbn.zst` file, decodes it (see
+//! [`crate::databento_service::decode_file`]) and holds its records in
+//! memory for a while under the caller's API key. Each upload is served as
+//! a provider of its own: historical requests against it are answered from
+//! the file, with bars and CVD derived from its trades the way any provider
+//! derives them.
+
+use crate::service::{
+    historical_bars, historical_cvd, LiveStream, MarketDataService, ServiceError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use shared::aggregate::{aggregate_trades, resample_bars, NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use shared::uploads::UploadInfo;
+use shared::{HistoricalRequest, HistoricalResponse, Schema, SymbolType};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Largest DBN file accepted.
+pub const MAX_UPLOAD_BYTES: usize = 256 * 1024 * 1024;
+
+/// Most records decoded from one file, which are all held in memory.
+pub const MAX_UPLOAD_RECORDS: u64 = 10_000_000;
+
+/// Uploads kept per API key; a new one past this drops the oldest.
+pub const MAX_UPLOADS_PER_KEY: usize = 5;
+
+/// How often expired uploads are dropped.
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Error type for uploads.
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("Invalid DBN file: {0}")]
+    Decode(String),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("No upload '{0}'")]
+    NotFound(String),
+}
+
+/// Records decoded from a DBN file.
+#[derive(Debug, Clone)]
+pub struct DecodedFile {
+    pub dataset: String,
+    /// Time-ordered records in the file's schema
+    pub response: HistoricalResponse,
+}
+
+/// One upload's records, served as a market data provider.
+pub struct UploadService {
+    info: UploadInfo,
+    data: HistoricalResponse,
+}
+
+impl UploadService {
+    pub fn info(&self) -> &UploadInfo {
+        &self.info
+    }
+
+    /// The records of `req`'s symbols (all of them if it names none) in its
+    /// range.
+    fn select(&self, req: &HistoricalRequest) -> Result<HistoricalResponse, ServiceError> {
+        let time = |ts: &str, field: &str| -> Result<u64, ServiceError> {
+            DateTime::parse_from_rfc3339(ts)
+                .ok()
+                .and_then(|t| t.timestamp_nanos_opt())
+                .map(|ns| ns.max(0) as u64)
+                .ok_or_else(|| {
+                    ServiceError::InvalidTimeFormat(format!("{}: invalid time '{}'", field, ts))
+                })
+        };
+        let start = time(&req.start_rfc3339, "start_rfc3339")?;
+        let end = time(&req.end_rfc3339, "end_rfc3339")?;
+        let mut selected = self.data.clone();
+        selected.retain(|symbol, ts| {
+            (req.symbols.is_empty() || req.symbols.iter().any(|s| s == symbol))
+                && (start..end).contains(&ts)
+        });
+        Ok(selected)
+    }
+}
+
+#[async_trait]
+impl MarketDataService for UploadService {
+    /// Serves the file's own schema, OHLCV bars from trades or 1s bars, and
+    /// CVD or tick/volume/dollar bars from trades. Trades decoded from a
+    /// book feed keep their quotes only when `quote_at_trade` asks for them.
+    async fn get_historical(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<HistoricalResponse, ServiceError> {
+        let schema: Schema = req
+            .schema
+            .parse()
+            .map_err(|e: String| ServiceError::InvalidSchema(e))?;
+        let held = self.data.schema();
+        let unavailable = || {
+            ServiceError::InvalidSchema(format!(
+                "Upload '{}' holds {} and can't serve {}",
+                self.info.id,
+                held.as_str(),
+                schema.as_str()
+            ))
+        };
+        let mut response = match (&held, &schema) {
+            (Schema::Trades, Schema::Cvd1S | Schema::Cvd1M) => {
+                return historical_cvd(self, req, schema).await
+            }
+            (Schema::Trades, Schema::Bars(spec)) => return historical_bars(self, req, *spec).await,
+            (held, wanted) if held == wanted => self.select(req)?,
+            (Schema::Trades, Schema::Ohlcv1S | Schema::Ohlcv1M) => {
+                let HistoricalResponse::Trades { data } = self.select(req)? else {
+                    return Err(unavailable());
+                };
+                let interval_ns = match schema {
+                    Schema::Ohlcv1S => NANOS_PER_SECOND,
+                    _ => NANOS_PER_MINUTE,
+                };
+                HistoricalResponse::from_bars(&schema, aggregate_trades(&data, interval_ns))
+                    .ok_or_else(unavailable)?
+            }
+            (Schema::Ohlcv1S, Schema::Ohlcv1M) => {
+                let HistoricalResponse::Ohlcv1S { data } = self.select(req)? else {
+                    return Err(unavailable());
+                };
+                HistoricalResponse::Ohlcv1M {
+                    data: resample_bars(&data, NANOS_PER_MINUTE),
+                }
+            }
+            _ => return Err(unavailable()),
+        };
+        if let HistoricalResponse::Trades { data } = &mut response {
+            if !req.quote_at_trade {
+                data.iter_mut().for_each(|t| t.quote = None);
+            }
+        }
+        response.apply_limits(req.limit, req.limit_per_symbol);
+        Ok(response)
+    }
+
+    async fn subscribe_live(
+        &self,
+        _symbols: Vec<String>,
+        _schema: String,
+        _stype_in: SymbolType,
+    ) -> Result<LiveStream, ServiceError> {
+        Err(ServiceError::NotConfigured(
+            "An uploaded file has no live data".to_string(),
+        ))
+    }
+
+    async fn unresolved_symbols(
+        &self,
+        req: &HistoricalRequest,
+    ) -> Result<Vec<String>, ServiceError> {
+        Ok(req
+            .symbols
+            .iter()
+            .filter(|s| !self.info.symbols.contains(s))
+            .cloned()
+            .collect())
+    }
+
+    fn name(&self) -> &'static str {
+        "upload"
+    }
+}
+
+/// An upload and when it's dropped.
+struct Held {
+    service: Arc<UploadService>,
+    expires: DateTime<Utc>,
+}
+
+/// Uploads by API key.
+pub struct Uploads {
+    ttl: Duration,
+    uploads: Mutex<HashMap<String, Vec<Held>>>,
+}
+
+impl Uploads {
+    /// Uploads kept for `ttl` after they're made.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            uploads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn uploads(&self) -> MutexGuard<'_, HashMap<String, Vec<Held>>> {
+        self.uploads.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hold `file`, decoded from `bytes` of `file_name`, for `key_id`.
+    pub fn insert(
+        &self,
+        key_id: &str,
+        file_name: &str,
+        bytes: u64,
+        file: DecodedFile,
+        now: DateTime<Utc>,
+    ) -> UploadInfo {
+        let expires = now + chrono::Duration::from_std(self.ttl).unwrap_or(chrono::Duration::MAX);
+        let symbols = file.response.records_by_symbol().into_keys().collect();
+        let span = file.response.time_span();
+        let info = UploadInfo {
+            id: format!("{:012x}", rand::random::<u64>() >> 16),
+            file_name: file_name.to_string(),
+            dataset: file.dataset,
+            schema: file.response.schema().as_str().into_owned(),
+            symbols,
+            records: file.response.len() as u64,
+            bytes,
+            start_unix_ns: span.map(|(start, _)| start),
+            end_unix_ns: span.map(|(_, end)| end),
+            expires_rfc3339: expires.to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+        let mut uploads = self.uploads();
+        let held = uploads.entry(key_id.to_string()).or_default();
+        if held.len() >= MAX_UPLOADS_PER_KEY {
+            held.remove(0);
+        }
+        held.push(Held {
+            service: Arc::new(UploadService {
+                info: info.clone(),
+                data: file.response,
+            }),
+            expires,
+        });
+        info
+    }
+
+    /// `key_id`'s uploads, oldest first.
+    pub fn list(&self, key_id: &str) -> Vec<UploadInfo> {
+        self.uploads()
+            .get(key_id)
+            .map(|held| held.iter().map(|h| h.service.info.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// `key_id`'s upload `id`, to query.
+    pub fn get(&self, key_id: &str, id: &str) -> Result<Arc<UploadService>, UploadError> {
+        self.uploads()
+            .get(key_id)
+            .and_then(|held| held.iter().find(|h| h.service.info.id == id))
+            .map(|h| h.service.clone())
+            .ok_or_else(|| UploadError::NotFound(id.to_string()))
+    }
+
+    /// Drop `key_id`'s upload `id`.
+    pub fn remove(&self, key_id: &str, id: &str) -> Result<UploadInfo, UploadError> {
+        let mut uploads = self.uploads();
+        let held = uploads
+            .get_mut(key_id)
+            .ok_or_else(|| UploadError::NotFound(id.to_string()))?;
+        let index = held
+            .iter()
+            .position(|h| h.service.info.id == id)
+            .ok_or_else(|| UploadError::NotFound(id.to_string()))?;
+        Ok(held.remove(index).service.info.clone())
+    }
+
+    /// Drop uploads expired at `now`.
+    pub fn expire(&self, now: DateTime<Utc>) {
+        let mut uploads = self.uploads();
+        for held in uploads.values_mut() {
+            held.retain(|h| h.expires > now);
+        }
+        uploads.retain(|_, held| !held.is_empty());
+    }
+
+    /// Drop expired uploads every minute.
+    pub fn spawn_expiry(self: &Arc<Self>) -> JoinHandle<()> {
+        let uploads = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(EXPIRY_INTERVAL);
+            loop {
+                ticker.tick().await;
+                uploads.expire(Utc::now());
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::TradeRecord;
+
+    fn trade(ts: u64, symbol: &str) -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: symbol.to_string(),
+            price_i64: 4_500_000_000_000,
+            size_u32: 1,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_service() {
+        let uploads = Uploads::new(Duration::from_secs(60));
+        let now = Utc::now();
+        let minute = 1_704_205_800_000_000_000;
+        let file = DecodedFile {
+            dataset: "GLBX.MDP3".to_string(),
+            response: HistoricalResponse::Trades {
+                data: (0..120)
+                    .map(|i| trade(minute + i * NANOS_PER_SECOND, "ESH4"))
+                    .chain([trade(minute, "NQH4")])
+                    .collect(),
+            },
+        };
+        let info = uploads.insert("key", "trades.dbn.zst", 1024, file, now);
+        assert_eq!(info.symbols, ["ESH4", "NQH4"]);
+        assert_eq!(info.records, 121);
+        assert_eq!(uploads.list("key").len(), 1);
+        assert!(uploads.list("other").is_empty());
+        assert!(uploads.get("other", &info.id).is_err());
+
+        let service = uploads.get("key", &info.id).unwrap();
+        let req = |schema: &str| HistoricalRequest {
+            symbols: vec!["ESH4".to_string()],
+            schema: schema.to_string(),
+            start_rfc3339: "2024-01-02T14:30:00Z".to_string(),
+            end_rfc3339: "2024-01-02T14:31:00Z".to_string(),
+            limit: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            service.get_historical(&req("trades")).await.unwrap().len(),
+            60
+        );
+        let bars = service.get_historical(&req("ohlcv-1m")).await.unwrap();
+        assert_eq!(bars.len(), 1);
+        let cvd = service.get_historical(&req("cvd-1s")).await.unwrap();
+        assert!(matches!(cvd, HistoricalResponse::Cvd1S { .. }));
+        let both = HistoricalRequest {
+            symbols: vec!["ESH4".to_string(), "CLH4".to_string()],
+            ..req("trades")
+        };
+        assert_eq!(service.unresolved_symbols(&both).await.unwrap(), ["CLH4"]);
+
+        // Uploads expire, and a key holds a bounded number
+        uploads.expire(now + chrono::Duration::seconds(61));
+        assert!(uploads.list("key").is_empty());
+        for _ in 0..=MAX_UPLOADS_PER_KEY {
+            let file = DecodedFile {
+                dataset: String::new(),
+                response: HistoricalResponse::Trades { data: Vec::new() },
+            };
+            uploads.insert("key", "empty.dbn", 0, file, now);
+        }
+        let held = uploads.list("key");
+        assert_eq!(held.len(), MAX_UPLOADS_PER_KEY);
+        uploads.remove("key", &held[0].id).unwrap();
+        assert!(uploads.remove("key", &held[0].id).is_err());
+    }
+}
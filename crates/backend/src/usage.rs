@@ -0,0 +1,354 @@
+//! Per-API-key usage accounting and monthly quotas.
+//!
+//! Callers are identified by the key sent in `X-API-Key` (or the `api_key`
+//! query parameter, for WebSockets); requests without one share the
+//! `anonymous` account. Counters reset at the start of each calendar month
+//! (UTC) and are flushed to a JSON file periodically, so a restart loses at
+//! most one flush interval of live message counts.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::usage::{UsageQuotas, UsageReport};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Account used for requests that carry no API key.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// How often dirty counters are written to disk.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A monthly limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    RecordsServed,
+    LiveMessages,
+    CostUsd,
+}
+
+impl std::fmt::Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Quota::RecordsServed => "records_served",
+            Quota::LiveMessages => "live_messages",
+            Quota::CostUsd => "cost_usd",
+        })
+    }
+}
+
+/// Error type for usage accounting.
+#[derive(Debug, thiserror::Error)]
+pub enum UsageError {
+    #[error("Monthly {0} quota exhausted")]
+    QuotaExceeded(Quota),
+    #[error("Invalid usage quotas: {0}")]
+    InvalidQuotas(String),
+    #[error("Failed to persist usage: {0}")]
+    Storage(String),
+}
+
+/// Parse a quota spec such as `records_served=1000000,cost_usd=50`.
+/// Omitted limits are unlimited.
+pub fn parse_quotas(spec: &str) -> Result<UsageQuotas, UsageError> {
+    let mut quotas = UsageQuotas::default();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, value) = entry
+            .split_once('=')
+            .ok_or_else(|| UsageError::InvalidQuotas(format!("'{}' is not name=value", entry)))?;
+        let value = value.trim();
+        let invalid = || UsageError::InvalidQuotas(format!("bad value in '{}'", entry));
+        match name.trim() {
+            "records_served" => quotas.records_served = Some(value.parse().map_err(|_| invalid())?),
+            "live_messages" => quotas.live_messages = Some(value.parse().map_err(|_| invalid())?),
+            "cost_usd" => quotas.cost_usd = Some(value.parse().map_err(|_| invalid())?),
+            other => {
+                return Err(UsageError::InvalidQuotas(format!(
+                    "unknown quota '{}': expected records_served, live_messages or cost_usd",
+                    other
+                )))
+            }
+        }
+    }
+    Ok(quotas)
+}
+
+/// Stable, non-secret account id for an API key.
+pub fn key_id(api_key: Option<&str>) -> String {
+    match api_key.filter(|k| !k.is_empty()) {
+        Some(key) => hex::encode(&Sha256::digest(key.as_bytes())[..8]),
+        None => ANONYMOUS.to_string(),
+    }
+}
+
+/// The calendar month counters cover, e.g. "2024-07".
+fn current_period() -> String {
+    Utc::now().format("%Y-%m").to_string()
+}
+
+/// One account's counters for one period.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Counters {
+    period: String,
+    requests: u64,
+    records_served: u64,
+    live_messages: u64,
+    estimated_cost_usd: f64,
+}
+
+/// Usage counters for every API key, checked against shared quotas.
+pub struct UsageTracker {
+    path: Option<PathBuf>,
+    quotas: UsageQuotas,
+    accounts: Mutex<HashMap<String, Counters>>,
+    dirty: AtomicBool,
+}
+
+impl UsageTracker {
+    /// A tracker that is not persisted.
+    pub fn in_memory(quotas: UsageQuotas) -> Self {
+        Self {
+            path: None,
+            quotas,
+            accounts: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Open the tracker at `path`, loading any counters saved there.
+    pub fn open(path: impl Into<PathBuf>, quotas: UsageQuotas) -> Result<Self, UsageError> {
+        let path = path.into();
+        let accounts = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| UsageError::Storage(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(UsageError::Storage(format!("{}: {}", path.display(), e))),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            quotas,
+            accounts: Mutex::new(accounts),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub fn quotas(&self) -> &UsageQuotas {
+        &self.quotas
+    }
+
+    fn accounts(&self) -> MutexGuard<'_, HashMap<String, Counters>> {
+        self.accounts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run `f` on the current period's counters for `key_id`.
+    fn with_counters<T>(&self, key_id: &str, f: impl FnOnce(&mut Counters) -> T) -> T {
+        let period = current_period();
+        let mut accounts = self.accounts();
+        let counters = accounts.entry(key_id.to_string()).or_default();
+        if counters.period != period {
+            *counters = Counters {
+                period,
+                ..Default::default()
+            };
+        }
+        f(counters)
+    }
+
+    /// Whether a historical request estimated at `cost_usd` may proceed.
+    pub fn check_historical(&self, key_id: &str, cost_usd: f64) -> Result<(), UsageError> {
+        self.with_counters(key_id, |c| {
+            if self
+                .quotas
+                .records_served
+                .is_some_and(|q| c.records_served >= q)
+            {
+                return Err(UsageError::QuotaExceeded(Quota::RecordsServed));
+            }
+            if self
+                .quotas
+                .cost_usd
+                .is_some_and(|q| c.estimated_cost_usd + cost_usd > q)
+            {
+                return Err(UsageError::QuotaExceeded(Quota::CostUsd));
+            }
+            Ok(())
+        })
+    }
+
+    /// Count a served historical request.
+    pub fn record_historical(&self, key_id: &str, records: u64, cost_usd: f64) {
+        self.with_counters(key_id, |c| {
+            c.requests += 1;
+            c.records_served += records;
+            c.estimated_cost_usd += cost_usd;
+        });
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a live subscription may start.
+    pub fn check_live(&self, key_id: &str) -> Result<(), UsageError> {
+        self.with_counters(key_id, |c| {
+            if self
+                .quotas
+                .live_messages
+                .is_some_and(|q| c.live_messages >= q)
+            {
+                return Err(UsageError::QuotaExceeded(Quota::LiveMessages));
+            }
+            Ok(())
+        })
+    }
+
+    /// Count delivered live messages; fails once the quota is used up.
+    pub fn record_live(&self, key_id: &str, messages: u64) -> Result<(), UsageError> {
+        self.with_counters(key_id, |c| c.live_messages += messages);
+        self.dirty.store(true, Ordering::Relaxed);
+        self.check_live(key_id)
+    }
+
+    /// Usage by `key_id` this month.
+    pub fn report(&self, key_id: &str) -> UsageReport {
+        let counters = self.with_counters(key_id, |c| c.clone());
+        UsageReport {
+            key_id: key_id.to_string(),
+            period: counters.period,
+            requests: counters.requests,
+            records_served: counters.records_served,
+            live_messages: counters.live_messages,
+            estimated_cost_usd: counters.estimated_cost_usd,
+            quotas: self.quotas.clone(),
+        }
+    }
+
+    /// Write counters to disk if anything changed since the last flush.
+    pub fn flush(&self) -> Result<(), UsageError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let json = serde_json::to_string_pretty(&*self.accounts())
+            .map_err(|e| UsageError::Storage(e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .map_err(|e| {
+                self.dirty.store(true, Ordering::Relaxed);
+                UsageError::Storage(format!("{}: {}", path.display(), e))
+            })
+    }
+
+    /// Flush every [`FLUSH_INTERVAL`] in the background.
+    pub fn spawn_flusher(self: &Arc<Self>) -> JoinHandle<()> {
+        let tracker = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = tracker.flush() {
+                    warn!("{}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quotas() -> UsageQuotas {
+        parse_quotas("records_served=100, live_messages=10, cost_usd=1.5").unwrap()
+    }
+
+    #[test]
+    fn test_parse_quotas() {
+        let q = quotas();
+        assert_eq!(q.records_served, Some(100));
+        assert_eq!(q.live_messages, Some(10));
+        assert_eq!(q.cost_usd, Some(1.5));
+        assert_eq!(parse_quotas("").unwrap(), UsageQuotas::default());
+        assert!(parse_quotas("bytes=5").is_err());
+        assert!(parse_quotas("records_served").is_err());
+        assert!(parse_quotas("live_messages=-1").is_err());
+    }
+
+    #[test]
+    fn test_key_id_hides_key() {
+        let id = key_id(Some("db-secret"));
+        assert_eq!(id.len(), 16);
+        assert!(!id.contains("secret"));
+        assert_eq!(id, key_id(Some("db-secret")));
+        assert_eq!(key_id(None), ANONYMOUS);
+        assert_eq!(key_id(Some("")), ANONYMOUS);
+    }
+
+    #[test]
+    fn test_quotas_are_enforced_per_key() {
+        let tracker = UsageTracker::in_memory(quotas());
+
+        tracker.check_historical("a", 1.0).unwrap();
+        tracker.record_historical("a", 100, 1.0);
+        assert!(matches!(
+            tracker.check_historical("a", 0.0),
+            Err(UsageError::QuotaExceeded(Quota::RecordsServed))
+        ));
+        // Other keys have their own allowance
+        tracker.check_historical("b", 1.0).unwrap();
+        assert!(matches!(
+            tracker.check_historical("b", 2.0),
+            Err(UsageError::QuotaExceeded(Quota::CostUsd))
+        ));
+
+        tracker.record_live("a", 9).unwrap();
+        assert!(matches!(
+            tracker.record_live("a", 1),
+            Err(UsageError::QuotaExceeded(Quota::LiveMessages))
+        ));
+        assert!(tracker.check_live("a").is_err());
+
+        let report = tracker.report("a");
+        assert_eq!(report.requests, 1);
+        assert_eq!(report.records_served, 100);
+        assert_eq!(report.live_messages, 10);
+        assert_eq!(report.period, current_period());
+    }
+
+    #[test]
+    fn test_counters_reset_each_month() {
+        let tracker = UsageTracker::in_memory(quotas());
+        tracker.accounts().insert(
+            "a".to_string(),
+            Counters {
+                period: "2000-01".to_string(),
+                records_served: 500,
+                ..Default::default()
+            },
+        );
+        tracker.check_historical("a", 0.0).unwrap();
+        assert_eq!(tracker.report("a").records_served, 0);
+    }
+
+    #[test]
+    fn test_flush_and_reopen() {
+        let dir = std::env::temp_dir().join(format!("sf-usage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("usage.json");
+
+        let tracker = UsageTracker::open(&path, UsageQuotas::default()).unwrap();
+        tracker.record_historical("a", 42, 0.25);
+        tracker.flush().unwrap();
+
+        let reopened = UsageTracker::open(&path, UsageQuotas::default()).unwrap();
+        assert_eq!(reopened.report("a").records_served, 42);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+//! User accounts with per-user DataBento keys.
+//!
+//! An administrator (holding `ADMIN_TOKEN`) creates users; each gets an API
+//! token, returned once, which it sends as `X-API-Key`. Only a SHA-256 hash
+//! of the token is stored. Users may register their own DataBento key,
+//! which is sealed with the server's master key before being written to
+//! disk, and the provider uses it for that user's requests.
+
+use crate::crypto::{CryptoError, Sealer};
+use crate::store::{NamedStore, StoreError};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::users::{UserInfo, UserToken};
+
+/// Prefix of issued API tokens, to make them recognizable in configs.
+const TOKEN_PREFIX: &str = "sf_";
+
+/// Error type for user operations.
+#[derive(Debug, thiserror::Error)]
+pub enum UserError {
+    #[error("User: {0}")]
+    Store(#[from] StoreError),
+    #[error("User already exists: {0}")]
+    Exists(String),
+    #[error("Missing or invalid credentials")]
+    Unauthorized,
+    #[error("User management is disabled: set ADMIN_TOKEN")]
+    AdminDisabled,
+    #[error("Invalid DataBento key: {0}")]
+    InvalidKey(String),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// A user as persisted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    /// Hex SHA-256 of the user's API token
+    token_sha256: String,
+    /// DataBento key sealed with the master key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    databento_key: Option<String>,
+    created_at: String,
+}
+
+/// Users keyed by name.
+pub type UserStore = NamedStore<UserRecord>;
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Compare without short-circuiting, so timing doesn't reveal how much of
+/// a token matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// User accounts and their sealed provider keys.
+pub struct UserDirectory {
+    users: UserStore,
+    sealer: Sealer,
+    admin_token: Option<String>,
+}
+
+impl UserDirectory {
+    pub fn new(users: UserStore, sealer: Sealer, admin_token: Option<String>) -> Self {
+        Self {
+            users,
+            sealer,
+            admin_token: admin_token.filter(|t| !t.is_empty()),
+        }
+    }
+
+    /// Number of users.
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// Check an `Authorization: Bearer` token against `ADMIN_TOKEN`.
+    pub fn check_admin(&self, token: Option<&str>) -> Result<(), UserError> {
+        let expected = self.admin_token.as_ref().ok_or(UserError::AdminDisabled)?;
+        match token {
+            Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+            _ => Err(UserError::Unauthorized),
+        }
+    }
+
+    /// Create a user and return its API token.
+    pub fn create(&self, name: &str) -> Result<UserToken, UserError> {
+        let token = format!(
+            "{}{}",
+            TOKEN_PREFIX,
+            hex::encode(rand::random::<[u8; 24]>())
+        );
+        let record = UserRecord {
+            token_sha256: token_hash(&token),
+            databento_key: None,
+            created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        };
+        if !self.users.insert_new(name, record)? {
+            return Err(UserError::Exists(name.to_string()));
+        }
+        Ok(UserToken {
+            name: name.to_string(),
+            token,
+        })
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), UserError> {
+        self.users.remove(name)?;
+        Ok(())
+    }
+
+    /// Users, ordered by name.
+    pub fn list(&self) -> Vec<UserInfo> {
+        self.users
+            .list()
+            .into_iter()
+            .map(|(name, record)| info(name, &record))
+            .collect()
+    }
+
+    pub fn info(&self, name: &str) -> Result<UserInfo, UserError> {
+        Ok(info(name.to_string(), &self.users.get(name)?))
+    }
+
+    /// Name of the user holding `token`, if any.
+    pub fn authenticate(&self, token: &str) -> Option<String> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return None;
+        }
+        let hash = token_hash(token);
+        self.users
+            .list()
+            .into_iter()
+            .find(|(_, record)| constant_time_eq(record.token_sha256.as_bytes(), hash.as_bytes()))
+            .map(|(name, _)| name)
+    }
+
+    /// Register `name`'s own DataBento key, replacing any previous one.
+    pub fn set_databento_key(&self, name: &str, api_key: &str) -> Result<(), UserError> {
+        let api_key = api_key.trim();
+        if api_key.is_empty() || api_key.chars().any(char::is_whitespace) {
+            return Err(UserError::InvalidKey(
+                "must be non-empty with no whitespace".to_string(),
+            ));
+        }
+        let record = self.users.get(name)?;
+        self.users.insert(
+            name,
+            UserRecord {
+                databento_key: Some(self.sealer.seal(api_key)),
+                ..record
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_databento_key(&self, name: &str) -> Result<(), UserError> {
+        let record = self.users.get(name)?;
+        self.users.insert(
+            name,
+            UserRecord {
+                databento_key: None,
+                ..record
+            },
+        )?;
+        Ok(())
+    }
+
+    /// `name`'s decrypted DataBento key, if registered.
+    pub fn databento_key(&self, name: &str) -> Result<Option<String>, UserError> {
+        match self.users.get(name)?.databento_key {
+            Some(sealed) => Ok(Some(self.sealer.open(&sealed)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn info(name: String, record: &UserRecord) -> UserInfo {
+    UserInfo {
+        name,
+        has_databento_key: record.databento_key.is_some(),
+        created_at: record.created_at.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory() -> UserDirectory {
+        UserDirectory::new(
+            UserStore::in_memory(),
+            Sealer::ephemeral(),
+            Some("admin".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_token_authenticates_its_user() {
+        let users = directory();
+        let alice = users.create("alice").unwrap();
+        let bob = users.create("bob").unwrap();
+        assert!(alice.token.starts_with(TOKEN_PREFIX));
+        assert_eq!(users.authenticate(&alice.token).as_deref(), Some("alice"));
+        assert_eq!(users.authenticate(&bob.token).as_deref(), Some("bob"));
+        assert_eq!(users.authenticate("sf_wrong"), None);
+        assert!(matches!(users.create("alice"), Err(UserError::Exists(_))));
+
+        users.remove("bob").unwrap();
+        assert_eq!(users.authenticate(&bob.token), None);
+    }
+
+    #[test]
+    fn test_databento_key_is_sealed() {
+        let users = directory();
+        users.create("alice").unwrap();
+        assert_eq!(users.databento_key("alice").unwrap(), None);
+
+        users.set_databento_key("alice", " db-alice-key ").unwrap();
+        assert_eq!(
+            users.databento_key("alice").unwrap().as_deref(),
+            Some("db-alice-key")
+        );
+        assert!(users.info("alice").unwrap().has_databento_key);
+        let stored = serde_json::to_string(&users.users.get("alice").unwrap()).unwrap();
+        assert!(!stored.contains("db-alice-key"));
+
+        assert!(users.set_databento_key("alice", "").is_err());
+        users.clear_databento_key("alice").unwrap();
+        assert!(!users.info("alice").unwrap().has_databento_key);
+    }
+
+    #[test]
+    fn test_admin_check() {
+        let users = directory();
+        assert!(users.check_admin(Some("admin")).is_ok());
+        assert!(matches!(
+            users.check_admin(Some("guess")),
+            Err(UserError::Unauthorized)
+        ));
+        assert!(matches!(
+            users.check_admin(None),
+            Err(UserError::Unauthorized)
+        ));
+
+        let disabled = UserDirectory::new(UserStore::in_memory(), Sealer::ephemeral(), None);
+        assert!(matches!(
+            disabled.check_admin(Some("admin")),
+            Err(UserError::AdminDisabled)
+        ));
+    }
+}
@@ -0,0 +1,346 @@
+//! API versions.
+//!
+//! The REST API is served under `/api/v1/...` and `/api/v2/...`. Handlers
+//! are shared; where a version's format differs, the handler reshapes its
+//! response with [`ApiVersion`], so a breaking change ships as a new
+//! version while older ones keep being served. v2 moves the metadata of
+//! historical responses under `meta` ([`shared::HistoricalEnvelope`]).
+//!
+//! Unversioned `/api/...` requests are routed to the version named by their
+//! `Api-Version` header, else to [`ApiVersion::DEFAULT`]. A version can be
+//! deprecated with a sunset date in `API_SUNSET`, e.g.:
+//!
+//! ```text
+//! API_SUNSET="v1=2025-06-30"
+//! ```
+//!
+//! Responses of a deprecated version carry `Deprecation`, `Sunset` and a
+//! `Link` to the same route in the latest version; from the sunset on, the
+//! version answers 410 Gone.
+
+use axum::extract::{OriginalUri, Request, State};
+use axum::http::{HeaderValue, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use shared::wire::ApiVersionInfo;
+use shared::{ErrorResponse, ResponseMeta};
+use std::collections::BTreeMap;
+
+/// Request header naming the version an unversioned request wants.
+pub const VERSION_HEADER: &str = "api-version";
+
+/// A version of the REST API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ApiVersion {
+    V1,
+    /// Historical responses carry their metadata under `meta`, and
+    /// multi-symbol requests serve the symbols that work
+    V2,
+}
+
+impl ApiVersion {
+    /// Every version served, oldest first.
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    /// Version unversioned requests get without an `Api-Version` header.
+    pub const DEFAULT: ApiVersion = ApiVersion::V1;
+
+    pub const LATEST: ApiVersion = ApiVersion::V2;
+
+    pub fn number(self) -> u32 {
+        match self {
+            ApiVersion::V1 => 1,
+            ApiVersion::V2 => 2,
+        }
+    }
+
+    /// Route prefix, e.g. `/api/v2`.
+    pub fn prefix(self) -> String {
+        format!("/api/v{}", self.number())
+    }
+
+    /// Parse `2` or `v2`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let number = s.trim();
+        let number = number.strip_prefix(['v', 'V']).unwrap_or(number);
+        Self::ALL
+            .into_iter()
+            .find(|version| number == version.number().to_string())
+    }
+
+    /// Whether multi-symbol historical requests serve the symbols that work
+    /// when others fail, listing the failures in `meta`.
+    pub fn serves_partial(self) -> bool {
+        self >= ApiVersion::V2
+    }
+
+    /// A served historical response (records, projected records or a
+    /// count) as this version serves it, with `meta` describing records.
+    pub fn historical<T: Serialize>(self, served: &T, meta: Option<ResponseMeta>) -> Value {
+        let body = serde_json::to_value(served).unwrap_or(Value::Null);
+        match (self, meta) {
+            (ApiVersion::V2, Some(meta)) => envelope(body, meta),
+            _ => body,
+        }
+    }
+}
+
+/// Metadata fields v1 serves beside the records and v2 under `meta`.
+const META_FIELDS: [&str; 3] = ["duplicates_dropped", "transform", "resolution"];
+
+/// A v1 historical response body reshaped into v2's.
+fn envelope(body: Value, meta: ResponseMeta) -> Value {
+    let Value::Object(mut fields) = body else {
+        return body;
+    };
+    for name in META_FIELDS {
+        fields.remove(name);
+    }
+    let meta = serde_json::to_value(meta).unwrap_or(Value::Null);
+    fields.insert("meta".to_string(), meta);
+    Value::Object(fields)
+}
+
+/// Error type for parsing `API_SUNSET`.
+#[derive(Debug, thiserror::Error)]
+pub enum SunsetError {
+    #[error("Expected version=date, got '{0}'")]
+    Syntax(String),
+    #[error("Unknown API version '{0}'")]
+    UnknownVersion(String),
+    #[error("Invalid sunset date '{0}': use YYYY-MM-DD or RFC 3339")]
+    InvalidDate(String),
+}
+
+/// Sunset dates of deprecated versions.
+#[derive(Debug, Clone, Default)]
+pub struct Deprecations(BTreeMap<ApiVersion, DateTime<Utc>>);
+
+impl Deprecations {
+    /// Parse `v1=2025-06-30;...`.
+    pub fn parse(spec: &str) -> Result<Self, SunsetError> {
+        let mut sunsets = BTreeMap::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (version, date) = entry
+                .split_once('=')
+                .ok_or_else(|| SunsetError::Syntax(entry.to_string()))?;
+            let version = ApiVersion::parse(version)
+                .ok_or_else(|| SunsetError::UnknownVersion(version.trim().to_string()))?;
+            sunsets.insert(version, parse_date(date.trim())?);
+        }
+        Ok(Self(sunsets))
+    }
+
+    /// When `version` stops being served, if it's deprecated.
+    pub fn sunset(&self, version: ApiVersion) -> Option<DateTime<Utc>> {
+        self.0.get(&version).copied()
+    }
+
+    /// Every version served and its deprecation status.
+    pub fn versions(&self) -> Vec<ApiVersionInfo> {
+        ApiVersion::ALL
+            .into_iter()
+            .map(|version| {
+                let sunset = self.sunset(version);
+                ApiVersionInfo {
+                    version: version.number(),
+                    prefix: version.prefix(),
+                    deprecated: sunset.is_some(),
+                    sunset: sunset.map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                }
+            })
+            .collect()
+    }
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, SunsetError> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(date) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|day| day.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| SunsetError::InvalidDate(date.to_string()))
+}
+
+/// How one version's routes are served: its number and, once deprecated,
+/// its sunset.
+#[derive(Debug, Clone, Copy)]
+pub struct VersionPolicy {
+    pub version: ApiVersion,
+    pub sunset: Option<DateTime<Utc>>,
+}
+
+/// Middleware for one version's routes: names the version in the response
+/// and, when it's deprecated, adds the deprecation headers or answers 410
+/// Gone once the sunset has passed.
+pub async fn deprecation(
+    State(policy): State<VersionPolicy>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let successor = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| successor(&uri.0, policy.version));
+    let mut response = match policy.sunset {
+        Some(sunset) if Utc::now() >= sunset => {
+            let status = StatusCode::GONE;
+            let body = ErrorResponse {
+                error: format!(
+                    "API v{} was retired on {}; use {}",
+                    policy.version.number(),
+                    sunset.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    ApiVersion::LATEST.prefix()
+                ),
+                code: status.as_u16(),
+            };
+            (status, Json(body)).into_response()
+        }
+        _ => next.run(request).await,
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(VERSION_HEADER, policy.version.number().into());
+    if let Some(sunset) = policy.sunset {
+        headers.insert("deprecation", HeaderValue::from_static("true"));
+        let http_date = sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = HeaderValue::from_str(&http_date) {
+            headers.insert("sunset", value);
+        }
+        let link = successor.map(|path| format!("<{}>; rel=\"successor-version\"", path));
+        if let Some(value) = link.and_then(|link| HeaderValue::from_str(&link).ok()) {
+            headers.insert("link", value);
+        }
+    }
+    response
+}
+
+/// `uri`'s path in the latest version.
+fn successor(uri: &Uri, version: ApiVersion) -> String {
+    uri.path().replacen(
+        &format!("{}/", version.prefix()),
+        &format!("{}/", ApiVersion::LATEST.prefix()),
+        1,
+    )
+}
+
+/// Middleware run before routing: sends an unversioned `/api/...` request
+/// (also under `/t/{tenant}`) to the version its `Api-Version` header
+/// names, else to [`ApiVersion::DEFAULT`].
+pub async fn negotiate(mut request: Request, next: Next) -> Response {
+    let requested = request.headers().get(VERSION_HEADER);
+    let version = match requested {
+        Some(value) => value.to_str().ok().and_then(ApiVersion::parse),
+        None => Some(ApiVersion::DEFAULT),
+    };
+    let Some(uri) = versioned_uri(request.uri(), version.unwrap_or(ApiVersion::DEFAULT)) else {
+        return next.run(request).await;
+    };
+    if version.is_none() {
+        let status = StatusCode::BAD_REQUEST;
+        let body = ErrorResponse {
+            error: format!(
+                "Unknown Api-Version '{}'; served versions are 1 to {}",
+                requested.and_then(|v| v.to_str().ok()).unwrap_or_default(),
+                ApiVersion::LATEST.number()
+            ),
+            code: status.as_u16(),
+        };
+        return (status, Json(body)).into_response();
+    }
+    request.extensions_mut().insert(OriginalUri(uri.clone()));
+    *request.uri_mut() = uri;
+    next.run(request).await
+}
+
+/// `uri` routed to `version`, or `None` when it isn't an unversioned API
+/// path.
+fn versioned_uri(uri: &Uri, version: ApiVersion) -> Option<Uri> {
+    let path = uri.path();
+    let api = match path.strip_prefix("/t/") {
+        Some(rest) => path.len() - rest.len() + rest.find('/')?,
+        None => 0,
+    };
+    let rest = path[api..].strip_prefix("/api/")?;
+    let first = rest.split('/').next().unwrap_or_default();
+    if first.starts_with('v') && first[1..].parse::<u32>().is_ok() {
+        return None;
+    }
+    let mut versioned = format!("{}{}/{}", &path[..api], version.prefix(), rest);
+    if let Some(query) = uri.query() {
+        versioned = format!("{}?{}", versioned, query);
+    }
+    versioned.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::{HistoricalEnvelope, HistoricalResponse, NormalizedResponse, OhlcvRecord};
+
+    #[test]
+    fn test_versioned_uri() {
+        let v2 = |uri: &str| versioned_uri(&uri.parse().unwrap(), ApiVersion::V2);
+        assert_eq!(
+            v2("/api/historical?x=1").unwrap().to_string(),
+            "/api/v2/historical?x=1"
+        );
+        assert_eq!(
+            v2("/t/desk/api/queries/es/run").unwrap().to_string(),
+            "/t/desk/api/v2/queries/es/run"
+        );
+        assert!(v2("/api/v1/historical").is_none());
+        assert!(v2("/t/desk/api/v2/health").is_none());
+        assert!(v2("/ws/live").is_none());
+        assert!(v2("/t/desk/ws/live").is_none());
+    }
+
+    #[test]
+    fn test_parse_deprecations() {
+        let deprecations = Deprecations::parse("v1=2025-06-30").unwrap();
+        let versions = deprecations.versions();
+        assert!(versions[0].deprecated);
+        assert_eq!(versions[0].sunset.as_deref(), Some("2025-06-30T00:00:00Z"));
+        assert!(!versions[1].deprecated);
+
+        assert!(matches!(
+            Deprecations::parse("v9=2025-06-30"),
+            Err(SunsetError::UnknownVersion(_))
+        ));
+        assert!(matches!(
+            Deprecations::parse("v1=soon"),
+            Err(SunsetError::InvalidDate(_))
+        ));
+    }
+
+    #[test]
+    fn test_v2_envelope() {
+        let bar = OhlcvRecord {
+            ts_event_unix_ns: 1,
+            symbol: "ES.FUT".to_string(),
+            open_i64: 1,
+            high_i64: 2,
+            low_i64: 1,
+            close_i64: 2,
+            volume_u64: 10,
+        };
+        let served = NormalizedResponse {
+            transform: Some("heikin-ashi".to_string()),
+            ..HistoricalResponse::Ohlcv1M { data: vec![bar] }.into()
+        };
+        let meta = || Some(ResponseMeta::from(&served));
+        let body = ApiVersion::V2.historical(&served, meta());
+        let expected = serde_json::to_value(HistoricalEnvelope::from(served.clone())).unwrap();
+        assert_eq!(body, expected);
+        assert_eq!(body["meta"]["records"], 1);
+        assert_eq!(body["meta"]["transform"], "heikin-ashi");
+        assert_eq!(
+            ApiVersion::V1.historical(&served, meta()),
+            serde_json::to_value(&served).unwrap()
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! `sfctl` - query the Market Data Viewer backend from the terminal.
+//!
+//! Speaks the same REST and WebSocket API as the web frontend, which makes it
+//! handy for scripting data pulls and debugging without a browser.
+
+mod output;
+
+use chrono::{SecondsFormat, Utc};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use client::{HistoricalClient, LiveClient, Subscription};
+use futures::StreamExt;
+use output::{Format, LiveWriter};
+use shared::price::{format_price, PricesAs};
+use shared::timeexpr::{self, parse_lookback};
+use shared::{HistoricalRequest, LiveMessage, RecordOrder, SymbolType};
+use std::io::Write;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(name = "sfctl", version, about = "Query the market data backend")]
+struct Cli {
+    /// Backend base URL
+    #[arg(
+        long,
+        global = true,
+        env = "SFCTL_SERVER",
+        default_value = "http://127.0.0.1:3001"
+    )]
+    server: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that the backend is reachable
+    Health,
+    /// Fetch historical trades or bars
+    Historical(HistoricalArgs),
+    /// Stream live data until interrupted
+    Live(LiveArgs),
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("range").required(true).args(["last", "start"])))]
+struct HistoricalArgs {
+    /// Comma-separated symbols (e.g. ES.FUT,CL.FUT)
+    #[arg(long, value_delimiter = ',', required = true)]
+    symbols: Vec<String>,
+    /// Data schema: trades, ohlcv-1s, ohlcv-1m, cvd-1s, cvd-1m, or bars such
+    /// as tickbar-500, volbar-1000, dollarbar-5000000
+    #[arg(long, default_value = "trades")]
+    schema: String,
+    /// Symbol type input: parent, continuous, raw_symbol or instrument_id
+    #[arg(long, default_value = "parent")]
+    stype_in: SymbolType,
+    /// Look back this far from now (e.g. 90s, 30m, 2h, 1d)
+    #[arg(long, conflicts_with_all = ["start", "end"])]
+    last: Option<String>,
+    /// Start time: RFC3339 or relative (e.g. now-6h, "today 09:30 America/New_York")
+    #[arg(long)]
+    start: Option<String>,
+    /// End time: RFC3339 or relative; defaults to now
+    #[arg(long, requires = "start")]
+    end: Option<String>,
+    /// Maximum number of records to return
+    #[arg(long, default_value_t = 1000)]
+    limit: u32,
+    /// Maximum number of records per symbol, sharing --limit evenly
+    #[arg(long)]
+    limit_per_symbol: Option<u32>,
+    /// Record order: time (interleaved) or symbol (grouped per symbol)
+    #[arg(long, default_value = "time")]
+    order: RecordOrder,
+    /// Serve the finest resolution that fits the range in --limit records
+    #[arg(long)]
+    auto_resolution: bool,
+    /// Dataset to query (e.g. XNAS.ITCH); routed by symbol when omitted
+    #[arg(long)]
+    dataset: Option<String>,
+    /// Tag each trade with the prevailing bid/ask (trades schema only)
+    #[arg(long)]
+    quote_at_trade: bool,
+    /// Print how many records match, ignoring --limit, instead of fetching them
+    #[arg(long)]
+    count_only: bool,
+    /// Upstream revision to serve, e.g. GLBX.MDP3@2024-03-15 (from a v2 response's meta)
+    #[arg(long)]
+    revision: Option<String>,
+    /// Transfer records with short field names (smaller; printed the same)
+    #[arg(long)]
+    compact: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(Args)]
+struct LiveArgs {
+    /// Comma-separated symbols (e.g. ES.FUT,CL.FUT)
+    #[arg(long, value_delimiter = ',', default_value = "ES.FUT")]
+    symbols: Vec<String>,
+    /// Data schema: trades, ohlcv-1s, ohlcv-1m, mbp-10 or mbo (depth and
+    /// books are only written with --format json)
+    #[arg(long, default_value = "trades")]
+    schema: String,
+    /// Symbol type input: parent, continuous, raw_symbol or instrument_id
+    #[arg(long, default_value = "parent")]
+    stype_in: SymbolType,
+    /// Exit after this many data messages
+    #[arg(long)]
+    count: Option<usize>,
+    /// Also report order-flow imbalance over these windows (e.g. 5s,1m)
+    #[arg(long, value_delimiter = ',')]
+    imbalance: Vec<String>,
+    /// Also report contract rolls, comparing volume over this window (e.g. 15m)
+    #[arg(long)]
+    roll_window: Option<String>,
+    /// Receive only closed bars of this width built from trades (e.g. 1m)
+    #[arg(long)]
+    bar_feed: Option<String>,
+    /// Also report economic calendar releases, if the server has a calendar
+    #[arg(long)]
+    calendar: bool,
+    /// Completed bars an ohlcv-1s or ohlcv-1m stream starts with
+    #[arg(long)]
+    snapshot_bars: Option<usize>,
+    /// Transfer trades and bars with short field names (smaller; printed the same)
+    #[arg(long)]
+    compact: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Health => health(&cli.server).await,
+        Command::Historical(args) => historical(&cli.server, args).await,
+        Command::Live(args) => live(&cli.server, args).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// GET /api/health
+async fn health(server: &str) -> Result<()> {
+    println!("{}", HistoricalClient::new(server)?.health().await?);
+    Ok(())
+}
+
+/// POST /api/historical and print the records.
+async fn historical(server: &str, args: HistoricalArgs) -> Result<()> {
+    let now = Utc::now();
+    let (start, end) = match (&args.last, &args.start) {
+        (Some(last), _) => (
+            now.checked_sub_signed(parse_lookback(last)?)
+                .ok_or_else(|| format!("--last {} is out of range", last))?,
+            now,
+        ),
+        (None, Some(start)) => (
+            timeexpr::resolve(start, now)?,
+            timeexpr::resolve(args.end.as_deref().unwrap_or("now"), now)?,
+        ),
+        _ => return Err("either --last or --start is required".into()),
+    };
+
+    let req = HistoricalRequest {
+        symbols: args.symbols,
+        schema: args.schema,
+        stype_in: args.stype_in,
+        start_rfc3339: start.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        end_rfc3339: end.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        limit: args.limit,
+        limit_per_symbol: args.limit_per_symbol,
+        dataset: args.dataset,
+        quote_at_trade: args.quote_at_trade,
+        count_only: false,
+        fields: Vec::new(),
+        align: None,
+        transform: None,
+        order: args.order,
+        compact: args.compact,
+        prices_as: PricesAs::Number,
+        auto_resolution: args.auto_resolution,
+        revision: args.revision,
+    };
+
+    let client = HistoricalClient::new(server)?;
+    if args.count_only {
+        println!("{}", client.count(&req).await?);
+        return Ok(());
+    }
+    let data = client.fetch(&req).await?;
+    let mut stdout = std::io::stdout().lock();
+    output::write_historical(&mut stdout, &data, args.format)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Connect to /ws/live and print messages as they arrive.
+async fn live(server: &str, args: LiveArgs) -> Result<()> {
+    let sub = Subscription {
+        symbols: args.symbols,
+        schema: args.schema,
+        stype_in: args.stype_in,
+        imbalance_windows: args.imbalance,
+        roll_window: args.roll_window,
+        bar_feed: args.bar_feed,
+        calendar: args.calendar,
+        snapshot_bars: args.snapshot_bars,
+        compact: args.compact,
+    };
+    let mut stream = LiveClient::new(server)?.subscribe(&sub).await?;
+
+    let mut writer = LiveWriter::new(args.format);
+    let mut stdout = std::io::stdout().lock();
+    let mut received = 0;
+
+    while let Some(msg) = stream.next().await {
+        match &msg {
+            LiveMessage::Connected {
+                symbols, schema, ..
+            } => {
+                eprintln!("connected: {} ({})", symbols.join(","), schema);
+            }
+            LiveMessage::SymbolMapping { .. } if args.format == Format::Json => {
+                writer.write(&mut stdout, &msg)?;
+                stdout.flush()?;
+            }
+            LiveMessage::SymbolMapping {
+                instrument_id,
+                stype_in_symbol,
+                stype_out_symbol,
+            } => eprintln!(
+                "{} -> {} (instrument {})",
+                stype_in_symbol, stype_out_symbol, instrument_id
+            ),
+            LiveMessage::Error { message } => return Err(message.clone().into()),
+            // Metrics go alongside the data and don't count towards --count
+            LiveMessage::Metric { .. } if args.format == Format::Json => {
+                writer.write(&mut stdout, &msg)?;
+                stdout.flush()?;
+            }
+            LiveMessage::Metric {
+                symbol,
+                name,
+                value,
+                window,
+            } => eprintln!("{} {} [{}]: {:+.3}", symbol, name, window, value),
+            LiveMessage::RollAlert { .. } if args.format == Format::Json => {
+                writer.write(&mut stdout, &msg)?;
+                stdout.flush()?;
+            }
+            LiveMessage::RollAlert {
+                root,
+                from_symbol,
+                to_symbol,
+                from_volume,
+                to_volume,
+                window,
+                ..
+            } => eprintln!(
+                "roll {}: {} -> {} ({} vs {} over {})",
+                root, from_symbol, to_symbol, to_volume, from_volume, window
+            ),
+            LiveMessage::ProviderStatus(_) if args.format == Format::Json => {
+                writer.write(&mut stdout, &msg)?;
+                stdout.flush()?;
+            }
+            LiveMessage::ProviderStatus(change) => eprintln!(
+                "provider {} is {:?}: {}",
+                change.provider, change.health, change.reason
+            ),
+            LiveMessage::CalendarEvent(_) if args.format == Format::Json => {
+                writer.write(&mut stdout, &msg)?;
+                stdout.flush()?;
+            }
+            LiveMessage::CalendarEvent(alert) => {
+                eprintln!("calendar {:?}: {}", alert.stage, alert.event.title)
+            }
+            // Paper trading events for this API key
+            LiveMessage::PaperOrder(_)
+            | LiveMessage::PaperFill(_)
+            | LiveMessage::PaperPosition(_)
+                if args.format == Format::Json =>
+            {
+                writer.write(&mut stdout, &msg)?;
+                stdout.flush()?;
+            }
+            LiveMessage::PaperOrder(order) => eprintln!(
+                "paper order {}: {:?} {} {} {:?}",
+                order.order_id, order.side, order.qty, order.symbol, order.status
+            ),
+            LiveMessage::PaperFill(fill) => eprintln!(
+                "paper fill {}: {:?} {} {} @ {}",
+                fill.order_id,
+                fill.side,
+                fill.qty,
+                fill.symbol,
+                format_price(fill.price_i64)
+            ),
+            LiveMessage::PaperPosition(position) => eprintln!(
+                "paper position {}: {} @ {} (realized {}, unrealized {})",
+                position.symbol,
+                position.qty,
+                format_price(position.avg_price_i64),
+                format_price(position.realized_pnl_i64),
+                format_price(position.unrealized_pnl_i64)
+            ),
+            _ => {
+                writer.write(&mut stdout, &msg)?;
+                stdout.flush()?;
+                received += 1;
+                if args.count.is_some_and(|n| received >= n) {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lookback() {
+        assert_eq!(
+            parse_lookback("90s").unwrap(),
+            chrono::Duration::seconds(90)
+        );
+        assert_eq!(parse_lookback("2h").unwrap(), chrono::Duration::hours(2));
+        assert_eq!(parse_lookback("1d").unwrap(), chrono::Duration::days(1));
+        assert!(parse_lookback("2").is_err());
+        assert!(parse_lookback("h").is_err());
+        assert!(parse_lookback("2w").is_err());
+    }
+
+    #[test]
+    fn test_cli_requires_time_range() {
+        let parsed = Cli::try_parse_from(["sfctl", "historical", "--symbols", "ES.FUT"]);
+        assert!(parsed.is_err());
+
+        let parsed = Cli::try_parse_from([
+            "sfctl",
+            "historical",
+            "--symbols",
+            "ES.FUT,CL.FUT",
+            "--last",
+            "2h",
+        ])
+        .unwrap();
+        match parsed.command {
+            Command::Historical(args) => assert_eq!(args.symbols, vec!["ES.FUT", "CL.FUT"]),
+            _ => panic!("Expected historical command"),
+        }
+    }
+}
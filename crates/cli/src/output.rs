@@ -0,0 +1,223 @@
+//! Rendering of API records as tables, CSV, or JSON.
+
+use chrono::{DateTime, SecondsFormat};
+use clap::ValueEnum;
+use shared::price::format_price;
+use shared::{HistoricalResponse, LiveMessage, OhlcvRecord, TradeRecord, TradeSide};
+use std::io::{self, Write};
+
+/// Output format for records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for reading in a terminal
+    Table,
+    /// Comma-separated values with a header row
+    Csv,
+    /// JSON (one object per line for live streams)
+    Json,
+}
+
+const TRADE_COLUMNS: [&str; 4] = ["ts_event", "symbol", "price", "size"];
+const QUOTED_TRADE_COLUMNS: [&str; 7] =
+    ["ts_event", "symbol", "price", "size", "bid", "ask", "side"];
+const OHLCV_COLUMNS: [&str; 7] = [
+    "ts_event", "symbol", "open", "high", "low", "close", "volume",
+];
+
+/// Write a historical response in the requested format.
+pub fn write_historical(
+    w: &mut impl Write,
+    resp: &HistoricalResponse,
+    format: Format,
+) -> io::Result<()> {
+    if format == Format::Json {
+        serde_json::to_writer_pretty(&mut *w, resp)?;
+        return writeln!(w);
+    }
+
+    match resp {
+        HistoricalResponse::Trades { data } => {
+            let quoted = data.iter().any(|t| t.quote.is_some());
+            let columns: &[&str] = if quoted {
+                &QUOTED_TRADE_COLUMNS
+            } else {
+                &TRADE_COLUMNS
+            };
+            write_row(w, format, columns)?;
+            for trade in data {
+                let mut row = trade_row(trade);
+                if quoted {
+                    row.extend(quote_cells(trade));
+                }
+                write_row(w, format, &row)?;
+            }
+        }
+        HistoricalResponse::Ohlcv1S { data }
+        | HistoricalResponse::Ohlcv1M { data }
+        | HistoricalResponse::Cvd1S { data }
+        | HistoricalResponse::Cvd1M { data }
+        | HistoricalResponse::Bars { data, .. } => {
+            write_row(w, format, &OHLCV_COLUMNS)?;
+            for bar in data {
+                write_row(w, format, &ohlcv_row(bar))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes live messages, emitting a header before the first row.
+pub struct LiveWriter {
+    format: Format,
+    header_written: bool,
+}
+
+impl LiveWriter {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            header_written: false,
+        }
+    }
+
+    /// Write one live data message. Control messages are ignored.
+    pub fn write(&mut self, w: &mut impl Write, msg: &LiveMessage) -> io::Result<()> {
+        if self.format == Format::Json {
+            serde_json::to_writer(&mut *w, msg)?;
+            return writeln!(w);
+        }
+
+        let (columns, row): (&[&str], Vec<String>) = match msg {
+            LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+            } => (
+                &TRADE_COLUMNS,
+                vec![
+                    format_ts(*ts_event_unix_ns),
+                    symbol.clone(),
+                    format_price(*price_i64),
+                    size_u32.to_string(),
+                ],
+            ),
+            LiveMessage::Ohlcv {
+                ts_event_unix_ns,
+                symbol,
+                open_i64,
+                high_i64,
+                low_i64,
+                close_i64,
+                volume_u64,
+                ..
+            } => (
+                &OHLCV_COLUMNS,
+                vec![
+                    format_ts(*ts_event_unix_ns),
+                    symbol.clone(),
+                    format_price(*open_i64),
+                    format_price(*high_i64),
+                    format_price(*low_i64),
+                    format_price(*close_i64),
+                    volume_u64.to_string(),
+                ],
+            ),
+            _ => return Ok(()),
+        };
+
+        if !self.header_written {
+            write_row(w, self.format, columns)?;
+            self.header_written = true;
+        }
+        write_row(w, self.format, &row)
+    }
+}
+
+fn trade_row(trade: &TradeRecord) -> Vec<String> {
+    vec![
+        format_ts(trade.ts_event_unix_ns),
+        trade.symbol.clone(),
+        format_price(trade.price_i64),
+        trade.size_u32.to_string(),
+    ]
+}
+
+fn quote_cells(trade: &TradeRecord) -> Vec<String> {
+    match &trade.quote {
+        Some(q) => vec![
+            format_price(q.bid_px_i64),
+            format_price(q.ask_px_i64),
+            match q.side {
+                TradeSide::Bid => "bid",
+                TradeSide::Ask => "ask",
+                TradeSide::Mid => "mid",
+            }
+            .to_string(),
+        ],
+        None => vec![String::new(); 3],
+    }
+}
+
+fn ohlcv_row(bar: &OhlcvRecord) -> Vec<String> {
+    vec![
+        format_ts(bar.ts_event_unix_ns),
+        bar.symbol.clone(),
+        format_price(bar.open_i64),
+        format_price(bar.high_i64),
+        format_price(bar.low_i64),
+        format_price(bar.close_i64),
+        bar.volume_u64.to_string(),
+    ]
+}
+
+fn write_row<S: AsRef<str>>(w: &mut impl Write, format: Format, cells: &[S]) -> io::Result<()> {
+    match format {
+        Format::Csv => {
+            let line: Vec<&str> = cells.iter().map(|c| c.as_ref()).collect();
+            writeln!(w, "{}", line.join(","))
+        }
+        _ => {
+            // Timestamp column is fixed width; the rest fit in 12 chars
+            let mut cells = cells.iter();
+            if let Some(ts) = cells.next() {
+                write!(w, "{:<31}", ts.as_ref())?;
+            }
+            for cell in cells {
+                write!(w, " {:>12}", cell.as_ref())?;
+            }
+            writeln!(w)
+        }
+    }
+}
+
+/// Format nanoseconds since the UNIX epoch as RFC3339.
+fn format_ts(ns: u64) -> String {
+    DateTime::from_timestamp_nanos(ns as i64).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_historical_csv() {
+        let resp = HistoricalResponse::Trades {
+            data: vec![TradeRecord {
+                ts_event_unix_ns: 1_704_067_200_000_000_000,
+                symbol: "ES.FUT".to_string(),
+                price_i64: 4_750_500_000_000,
+                size_u32: 3,
+                quote: None,
+                sequence_u32: None,
+            }],
+        };
+
+        let mut out = Vec::new();
+        write_historical(&mut out, &resp, Format::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ts_event,symbol,price,size\n2024-01-01T00:00:00Z,ES.FUT,4750.50,3\n"
+        );
+    }
+}
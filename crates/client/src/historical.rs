@@ -0,0 +1,125 @@
+//! Client for the REST endpoints.
+
+use crate::{parse_base_url, ClientError};
+use reqwest::Url;
+use shared::price::PricesAs;
+use shared::{compact, ErrorResponse, HistoricalRequest, HistoricalResponse, RecordCount};
+
+/// Client for `/api/historical` and other REST endpoints.
+#[derive(Debug, Clone)]
+pub struct HistoricalClient {
+    http: reqwest::Client,
+    base_url: Url,
+}
+
+impl HistoricalClient {
+    /// Create a client for the server at `base_url` (e.g. `http://127.0.0.1:3001`).
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Create a client that reuses an existing `reqwest::Client`, e.g. one
+    /// configured with timeouts or default headers.
+    pub fn with_http_client(base_url: &str, http: reqwest::Client) -> Result<Self, ClientError> {
+        Ok(Self {
+            http,
+            base_url: parse_base_url(base_url)?,
+        })
+    }
+
+    /// GET /api/health - returns the server's health string.
+    pub async fn health(&self) -> Result<String, ClientError> {
+        let resp = self.http.get(self.endpoint("api/health")?).send().await?;
+        Ok(Self::check(resp).await?.text().await?)
+    }
+
+    /// POST /api/historical - fetch historical trades or bars. Always asks
+    /// for whole records with fixed-point prices: `req.fields` and
+    /// `req.prices_as` are ignored. Records asked for `compact` are expanded
+    /// back as they're read.
+    pub async fn fetch(&self, req: &HistoricalRequest) -> Result<HistoricalResponse, ClientError> {
+        let req = HistoricalRequest {
+            fields: Vec::new(),
+            prices_as: PricesAs::Number,
+            ..req.clone()
+        };
+        let resp = self
+            .http
+            .post(self.endpoint("api/historical")?)
+            .json(&req)
+            .send()
+            .await?;
+        let resp = Self::check(resp).await?;
+        if !req.compact {
+            return Ok(resp.json().await?);
+        }
+        let mut body: serde_json::Value = resp.json().await?;
+        compact::expand_response(&mut body);
+        serde_json::from_value(body).map_err(|e| ClientError::Decode(e.to_string()))
+    }
+
+    /// POST /api/historical with `count_only` - the number of records `req`
+    /// matches, ignoring its limit.
+    pub async fn count(&self, req: &HistoricalRequest) -> Result<u64, ClientError> {
+        let req = HistoricalRequest {
+            count_only: true,
+            ..req.clone()
+        };
+        let resp = self
+            .http
+            .post(self.endpoint("api/historical")?)
+            .json(&req)
+            .send()
+            .await?;
+        let count: RecordCount = Self::check(resp).await?.json().await?;
+        Ok(count.count)
+    }
+
+    /// `path`, relative to the base URL and so under any prefix it has.
+    fn endpoint(&self, path: &str) -> Result<Url, ClientError> {
+        self.base_url
+            .join(path)
+            .map_err(|e| ClientError::InvalidUrl(e.to_string()))
+    }
+
+    /// Turn non-2xx responses into `ClientError::Api`, using the server's
+    /// `ErrorResponse` body when present.
+    async fn check(resp: reqwest::Response) -> Result<reqwest::Response, ClientError> {
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+
+        Err(match resp.json::<ErrorResponse>().await {
+            Ok(body) => ClientError::Api {
+                code: body.code,
+                message: body.error,
+            },
+            Err(_) => ClientError::Api {
+                code: status.as_u16(),
+                message: status.to_string(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_keeps_base_path() {
+        for base in ["http://example.com/t/x/", "http://example.com/t/x"] {
+            let client = HistoricalClient::new(base).unwrap();
+            assert_eq!(
+                client.endpoint("api/historical").unwrap().as_str(),
+                "http://example.com/t/x/api/historical"
+            );
+        }
+        let client = HistoricalClient::new("http://127.0.0.1:3001").unwrap();
+        assert_eq!(
+            client.endpoint("api/health").unwrap().as_str(),
+            "http://127.0.0.1:3001/api/health"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Typed async client for the Market Data Viewer API.
+//!
+//! Wraps the REST and WebSocket endpoints using the request and response
+//! types from `shared`, so other Rust programs can consume the server
+//! without hand-rolling HTTP calls.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), client::ClientError> {
+//! use client::{HistoricalClient, LiveClient, Subscription};
+//! use futures::StreamExt;
+//!
+//! let historical = HistoricalClient::new("http://127.0.0.1:3001")?;
+//! let live = LiveClient::new("http://127.0.0.1:3001")?;
+//!
+//! let mut stream = live.subscribe(&Subscription::new(["ES.FUT"], "trades")).await?;
+//! while let Some(msg) = stream.next().await {
+//!     println!("{:?}", msg);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod historical;
+mod live;
+
+pub use historical::HistoricalClient;
+pub use live::{LiveClient, Subscription};
+
+/// Error type for client operations.
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("Server error ({code}): {message}")]
+    Api { code: u16, message: String },
+    #[error("Failed to decode response: {0}")]
+    Decode(String),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ClientError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        ClientError::WebSocket(Box::new(e))
+    }
+}
+
+/// Parse and validate a server base URL such as `http://127.0.0.1:3001`
+/// or `https://example.com/t/acme/`. Its path is given a trailing `/` so
+/// endpoints join under it rather than replace it.
+fn parse_base_url(base_url: &str) -> Result<reqwest::Url, ClientError> {
+    let mut url = reqwest::Url::parse(base_url)
+        .map_err(|e| ClientError::InvalidUrl(format!("{}: {}", base_url, e)))?;
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        other => Err(ClientError::InvalidUrl(format!(
+            "{}: unsupported scheme '{}', expected http or https",
+            base_url, other
+        ))),
+    }
+}
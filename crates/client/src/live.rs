@@ -0,0 +1,289 @@
+//! Client for the live WebSocket endpoint.
+
+use crate::{parse_base_url, ClientError};
+use futures::{Stream, StreamExt};
+use reqwest::Url;
+use shared::{compact, fragment::Reassembler, LiveMessage, SymbolType};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// Parameters for a live subscription.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    /// Symbols to subscribe to (e.g., ["ES.FUT"])
+    pub symbols: Vec<String>,
+    /// Data schema: "trades", "ohlcv-1s", or "ohlcv-1m"
+    pub schema: String,
+    /// Symbol type input
+    pub stype_in: SymbolType,
+    /// Order-flow imbalance windows (e.g., ["5s", "1m"]); metrics are only
+    /// sent when non-empty
+    pub imbalance_windows: Vec<String>,
+    /// Window for contract roll detection (e.g., "15m"); roll alerts are
+    /// only sent when set
+    pub roll_window: Option<String>,
+    /// Width of closed bars to receive instead of trades (e.g., "1m")
+    pub bar_feed: Option<String>,
+    /// Whether to receive economic calendar alerts
+    pub calendar: bool,
+    /// Completed bars an `ohlcv-1s` or `ohlcv-1m` subscription starts with;
+    /// the server's default when unset
+    pub snapshot_bars: Option<usize>,
+    /// Whether trades and bars are sent with short field names; they're
+    /// decoded the same either way
+    pub compact: bool,
+}
+
+impl Subscription {
+    /// Subscribe to `symbols` using parent symbology.
+    pub fn new<S: Into<String>>(
+        symbols: impl IntoIterator<Item = S>,
+        schema: impl Into<String>,
+    ) -> Self {
+        Self {
+            symbols: symbols.into_iter().map(Into::into).collect(),
+            schema: schema.into(),
+            stype_in: SymbolType::Parent,
+            imbalance_windows: Vec::new(),
+            roll_window: None,
+            bar_feed: None,
+            calendar: false,
+            snapshot_bars: None,
+            compact: false,
+        }
+    }
+
+    /// Also receive `LiveMessage::Metric` order-flow imbalance updates over
+    /// each window.
+    pub fn with_imbalance_windows<S: Into<String>>(
+        mut self,
+        windows: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.imbalance_windows = windows.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Also receive `LiveMessage::RollAlert` when a different contract
+    /// month leads volume over `window`.
+    pub fn with_roll_alerts(mut self, window: impl Into<String>) -> Self {
+        self.roll_window = Some(window.into());
+        self
+    }
+
+    /// Receive closed `LiveMessage::Ohlcv` bars `interval` wide, built by
+    /// the server from the trades, instead of the trades themselves.
+    pub fn with_bar_feed(mut self, interval: impl Into<String>) -> Self {
+        self.bar_feed = Some(interval.into());
+        self
+    }
+
+    /// Also receive `LiveMessage::CalendarEvent` alerts before and at
+    /// economic releases, if the server has a calendar configured.
+    pub fn with_calendar(mut self) -> Self {
+        self.calendar = true;
+        self
+    }
+
+    /// Start with at most `bars` completed bars before the bar in progress.
+    /// Each connection starts with a snapshot, so a client that reconnects
+    /// gets back the bars it missed, up to `bars`.
+    pub fn with_snapshot_bars(mut self, bars: usize) -> Self {
+        self.snapshot_bars = Some(bars);
+        self
+    }
+
+    /// Have trades and bars sent with short field names, which makes
+    /// tick-heavy streams about 40% smaller.
+    pub fn with_compact(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+}
+
+/// Client for `/ws/live`.
+#[derive(Debug, Clone)]
+pub struct LiveClient {
+    base_url: Url,
+}
+
+impl LiveClient {
+    /// Create a client for the server at `base_url` (e.g. `http://127.0.0.1:3001`).
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        Ok(Self {
+            base_url: parse_base_url(base_url)?,
+        })
+    }
+
+    /// Open a live subscription.
+    ///
+    /// Connection failures are returned as errors. Once connected, transport
+    /// or decoding failures are delivered in-band as `LiveMessage::Error`,
+    /// after which the stream ends - the same contract the server uses.
+    /// Fragmented messages are reassembled before being yielded.
+    pub async fn subscribe(
+        &self,
+        sub: &Subscription,
+    ) -> Result<impl Stream<Item = LiveMessage> + Send + Unpin + 'static, ClientError> {
+        let (mut socket, _) = connect_async(self.live_url(sub)?.as_str()).await?;
+        let parse: fn(&str) -> serde_json::Result<LiveMessage> = match sub.compact {
+            true => compact::live_from_str,
+            false => |json| serde_json::from_str(json),
+        };
+
+        Ok(Box::pin(async_stream::stream! {
+            let mut reassembler = Reassembler::new();
+
+            // Pings are answered by tungstenite itself while reading
+            while let Some(frame) = socket.next().await {
+                let text = match frame {
+                    Ok(Message::Text(text)) => text,
+                    Ok(Message::Close(_)) => break,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        yield LiveMessage::Error {
+                            message: format!("WebSocket error: {}", e),
+                        };
+                        break;
+                    }
+                };
+
+                match decode(&mut reassembler, &text, parse) {
+                    Ok(Some(msg)) => yield msg,
+                    Ok(None) => continue,
+                    Err(message) => {
+                        yield LiveMessage::Error { message };
+                        break;
+                    }
+                }
+            }
+
+            let _ = socket.close(None).await;
+        }))
+    }
+
+    /// Build the `ws://` / `wss://` URL for a subscription.
+    fn live_url(&self, sub: &Subscription) -> Result<Url, ClientError> {
+        let mut url = self
+            .base_url
+            .join("ws/live")
+            .map_err(|e| ClientError::InvalidUrl(e.to_string()))?;
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|_| ClientError::InvalidUrl(format!("cannot use {} scheme", scheme)))?;
+        url.query_pairs_mut()
+            .append_pair("symbols", &sub.symbols.join(","))
+            .append_pair("schema", &sub.schema)
+            .append_pair("stype_in", sub.stype_in.as_str());
+        if !sub.imbalance_windows.is_empty() {
+            url.query_pairs_mut()
+                .append_pair("imbalance_windows", &sub.imbalance_windows.join(","));
+        }
+        if let Some(window) = &sub.roll_window {
+            url.query_pairs_mut().append_pair("roll_window", window);
+        }
+        if let Some(interval) = &sub.bar_feed {
+            url.query_pairs_mut().append_pair("bar_feed", interval);
+        }
+        if sub.calendar {
+            url.query_pairs_mut().append_pair("calendar", "true");
+        }
+        if let Some(bars) = sub.snapshot_bars {
+            url.query_pairs_mut()
+                .append_pair("snapshot_bars", &bars.to_string());
+        }
+        if sub.compact {
+            url.query_pairs_mut().append_pair("compact", "true");
+        }
+        Ok(url)
+    }
+}
+
+/// Decode a text frame with `parse`, buffering fragments until their
+/// message is complete.
+fn decode(
+    reassembler: &mut Reassembler,
+    text: &str,
+    parse: fn(&str) -> serde_json::Result<LiveMessage>,
+) -> Result<Option<LiveMessage>, String> {
+    let decode_err = |e: serde_json::Error| format!("Failed to decode message: {}", e);
+
+    match parse(text).map_err(decode_err)? {
+        LiveMessage::Fragment {
+            id,
+            part,
+            total,
+            data,
+        } => match reassembler.push(id, part, total, &data)? {
+            Some(json) => parse(&json).map(Some).map_err(decode_err),
+            None => Ok(None),
+        },
+        // A type from a newer server; skipped, as older clients should
+        LiveMessage::Unknown => Ok(None),
+        msg => Ok(Some(msg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live_url() {
+        let client = LiveClient::new("https://example.com:8443").unwrap();
+        let url = client
+            .live_url(&Subscription::new(["ES.FUT", "CL.FUT"], "trades"))
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "wss://example.com:8443/ws/live?symbols=ES.FUT%2CCL.FUT&schema=trades&stype_in=parent"
+        );
+    }
+
+    #[test]
+    fn test_live_url_keeps_base_path() {
+        let client = LiveClient::new("http://example.com/t/x/").unwrap();
+        let url = client
+            .live_url(&Subscription::new(["ES.FUT"], "trades"))
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "ws://example.com/t/x/ws/live?symbols=ES.FUT&schema=trades&stype_in=parent"
+        );
+    }
+
+    #[test]
+    fn test_live_url_with_imbalance_windows() {
+        let client = LiveClient::new("http://127.0.0.1:3001").unwrap();
+        let sub = Subscription::new(["ES.FUT"], "trades").with_imbalance_windows(["5s", "1m"]);
+        assert!(client
+            .live_url(&sub)
+            .unwrap()
+            .as_str()
+            .ends_with("&imbalance_windows=5s%2C1m"));
+    }
+
+    #[test]
+    fn test_decode_reassembles_fragments() {
+        let msg = LiveMessage::Error {
+            message: "x".repeat(2_000),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+
+        let mut reassembler = Reassembler::new();
+        let mut decoded = Vec::new();
+        for part in shared::fragment::split(&json, 0, 512) {
+            let frame = serde_json::to_string(&part).unwrap();
+            decoded.extend(
+                decode(&mut reassembler, &frame, |json| serde_json::from_str(json)).unwrap(),
+            );
+        }
+
+        assert_eq!(decoded.len(), 1);
+        assert!(matches!(&decoded[0], LiveMessage::Error { message } if message.len() == 2_000));
+    }
+
+    #[test]
+    fn test_rejects_non_http_base_url() {
+        assert!(LiveClient::new("ftp://example.com").is_err());
+        assert!(LiveClient::new("not a url").is_err());
+    }
+}
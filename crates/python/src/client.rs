@@ -0,0 +1,247 @@
+//! Blocking Python wrappers around the async Rust client.
+
+use crate::records::{PyHistoricalData, PyMetric, PyOhlcvRecord, PyTradeRecord};
+use crate::ClientError;
+use client::{HistoricalClient, LiveClient, Subscription};
+use futures::{Stream, StreamExt};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use shared::price::PricesAs;
+use shared::{HistoricalRequest, LiveMessage, RecordOrder, SymbolType};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+type BoxedLiveStream = Pin<Box<dyn Stream<Item = LiveMessage> + Send>>;
+
+fn client_error(e: impl std::fmt::Display) -> PyErr {
+    ClientError::new_err(e.to_string())
+}
+
+/// Client for a running Market Data Viewer server.
+///
+/// Calls block the calling thread but release the GIL while waiting.
+#[pyclass(name = "Client", module = "signalfoundry", frozen)]
+pub struct PyClient {
+    historical: HistoricalClient,
+    live: LiveClient,
+    runtime: Arc<Runtime>,
+}
+
+#[pymethods]
+impl PyClient {
+    #[new]
+    #[pyo3(signature = (base_url = "http://127.0.0.1:3001"))]
+    fn new(base_url: &str) -> PyResult<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(client_error)?;
+
+        Ok(Self {
+            historical: HistoricalClient::new(base_url).map_err(client_error)?,
+            live: LiveClient::new(base_url).map_err(client_error)?,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Check that the server is reachable.
+    fn health(&self, py: Python<'_>) -> PyResult<String> {
+        py.detach(|| self.runtime.block_on(self.historical.health()))
+            .map_err(client_error)
+    }
+
+    /// Fetch historical trades or bars.
+    #[pyo3(signature = (symbols, schema, start, end, limit = 1000, stype_in = "parent", dataset = None, quote_at_trade = false, limit_per_symbol = None, order = "time", auto_resolution = false, revision = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn historical(
+        &self,
+        py: Python<'_>,
+        symbols: Vec<String>,
+        schema: &str,
+        start: &str,
+        end: &str,
+        limit: u32,
+        stype_in: &str,
+        dataset: Option<String>,
+        quote_at_trade: bool,
+        limit_per_symbol: Option<u32>,
+        order: &str,
+        auto_resolution: bool,
+        revision: Option<String>,
+    ) -> PyResult<PyHistoricalData> {
+        let order: RecordOrder = order.parse().map_err(PyValueError::new_err)?;
+        let stype_in: SymbolType = stype_in.parse().map_err(PyValueError::new_err)?;
+        let req = HistoricalRequest {
+            symbols,
+            schema: schema.to_string(),
+            stype_in,
+            start_rfc3339: start.to_string(),
+            end_rfc3339: end.to_string(),
+            limit,
+            limit_per_symbol,
+            dataset,
+            quote_at_trade,
+            count_only: false,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            order,
+            compact: false,
+            prices_as: PricesAs::Number,
+            auto_resolution,
+            revision,
+        };
+
+        let resp = py
+            .detach(|| self.runtime.block_on(self.historical.fetch(&req)))
+            .map_err(client_error)?;
+        Ok(resp.into())
+    }
+
+    /// Subscribe to live data. Iterate the result to receive records.
+    ///
+    /// With `imbalance_windows` (e.g. `["5s", "1m"]`), Metric objects with
+    /// the rolling order-flow imbalance are interleaved with the trades.
+    /// With `bar_feed` (e.g. `"1m"`), closed OhlcvRecord bars of that width
+    /// are yielded instead of trades.
+    #[pyo3(signature = (symbols, schema = "trades", stype_in = "parent", imbalance_windows = Vec::new(), bar_feed = None))]
+    fn live(
+        &self,
+        py: Python<'_>,
+        symbols: Vec<String>,
+        schema: &str,
+        stype_in: &str,
+        imbalance_windows: Vec<String>,
+        bar_feed: Option<String>,
+    ) -> PyResult<PyLiveSubscription> {
+        let stype_in: SymbolType = stype_in.parse().map_err(PyValueError::new_err)?;
+        let sub = Subscription {
+            symbols,
+            schema: schema.to_string(),
+            stype_in,
+            imbalance_windows,
+            roll_window: None,
+            bar_feed,
+            calendar: false,
+            snapshot_bars: None,
+            compact: false,
+        };
+
+        let stream = py
+            .detach(|| self.runtime.block_on(self.live.subscribe(&sub)))
+            .map_err(client_error)?;
+
+        Ok(PyLiveSubscription {
+            stream: Mutex::new(Box::pin(stream)),
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+/// An open live subscription.
+///
+/// Iterating yields TradeRecord, OhlcvRecord, or Metric objects; server errors are
+/// raised as ClientError and the iterator stops when the server closes.
+#[pyclass(name = "LiveSubscription", module = "signalfoundry", frozen)]
+pub struct PyLiveSubscription {
+    stream: Mutex<BoxedLiveStream>,
+    runtime: Arc<Runtime>,
+}
+
+#[pymethods]
+impl PyLiveSubscription {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        loop {
+            let msg = py.detach(|| {
+                let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+                self.runtime.block_on(stream.next())
+            });
+
+            let obj = match msg {
+                None => return Ok(None),
+                Some(LiveMessage::Trade {
+                    ts_event_unix_ns,
+                    symbol,
+                    price_i64,
+                    size_u32,
+                }) => Py::new(
+                    py,
+                    PyTradeRecord {
+                        ts_event_unix_ns,
+                        symbol,
+                        price_i64,
+                        size_u32,
+                        side: None,
+                    },
+                )?
+                .into_any(),
+                Some(LiveMessage::Ohlcv {
+                    ts_event_unix_ns,
+                    symbol,
+                    open_i64,
+                    high_i64,
+                    low_i64,
+                    close_i64,
+                    volume_u64,
+                    ..
+                }) => Py::new(
+                    py,
+                    PyOhlcvRecord {
+                        ts_event_unix_ns,
+                        symbol,
+                        open_i64,
+                        high_i64,
+                        low_i64,
+                        close_i64,
+                        volume_u64,
+                    },
+                )?
+                .into_any(),
+                Some(LiveMessage::Metric {
+                    symbol,
+                    name,
+                    value,
+                    window,
+                }) => Py::new(
+                    py,
+                    PyMetric {
+                        symbol,
+                        name,
+                        value,
+                        window,
+                    },
+                )?
+                .into_any(),
+                Some(LiveMessage::Error { message }) => return Err(ClientError::new_err(message)),
+                Some(
+                    LiveMessage::Connected { .. }
+                    | LiveMessage::SymbolMapping { .. }
+                    | LiveMessage::RollAlert { .. }
+                    | LiveMessage::Fragment { .. }
+                    | LiveMessage::PaperOrder(_)
+                    | LiveMessage::PaperFill(_)
+                    | LiveMessage::PaperPosition(_)
+                    | LiveMessage::PositionPnl(_)
+                    | LiveMessage::CalendarEvent(_)
+                    | LiveMessage::ProviderStatus(_)
+                    | LiveMessage::ProfileDelta(_)
+                    | LiveMessage::DaySummary(_)
+                    | LiveMessage::Depth(_)
+                    | LiveMessage::DepthSnapshot(_)
+                    | LiveMessage::DepthDiff(_)
+                    | LiveMessage::Mbo(_)
+                    | LiveMessage::RateAdjusted { .. }
+                    | LiveMessage::TradeBatch { .. }
+                    | LiveMessage::Unknown,
+                ) => continue,
+            };
+            return Ok(Some(obj));
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Python bindings for the Market Data Viewer client and record types.
+//!
+//! Built with maturin as the `signalfoundry` extension module:
+//!
+//! ```text
+//! cd crates/python && maturin develop --release
+//! ```
+//!
+//! ```python
+//! import signalfoundry as sf
+//!
+//! client = sf.Client("http://127.0.0.1:3001")
+//! data = client.historical(["ES.FUT"], "ohlcv-1m",
+//!                          "2024-01-02T14:30:00Z", "2024-01-02T21:00:00Z")
+//! df = data.to_pandas()
+//! ```
+
+mod client;
+mod records;
+
+use pyo3::prelude::*;
+
+pyo3::create_exception!(
+    signalfoundry,
+    ClientError,
+    pyo3::exceptions::PyException,
+    "Raised when a request to the server fails."
+);
+
+#[pymodule]
+fn signalfoundry(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ClientError", m.py().get_type::<ClientError>())?;
+    m.add_class::<client::PyClient>()?;
+    m.add_class::<client::PyLiveSubscription>()?;
+    m.add_class::<records::PyTradeRecord>()?;
+    m.add_class::<records::PyOhlcvRecord>()?;
+    m.add_class::<records::PyMetric>()?;
+    m.add_class::<records::PyHistoricalData>()?;
+    Ok(())
+}
@@ -0,0 +1,271 @@
+//! Python views of the shared record types, with numpy/pandas conversion.
+
+use numpy::IntoPyArray;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use shared::price::to_f64;
+use shared::{HistoricalResponse, OhlcvRecord, TradeRecord, TradeSide};
+
+/// A single trade.
+#[pyclass(name = "TradeRecord", module = "signalfoundry", frozen, get_all)]
+pub struct PyTradeRecord {
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    pub price_i64: i64,
+    pub size_u32: u32,
+    /// "bid", "ask" or "mid" when fetched with `quote_at_trade`
+    pub side: Option<String>,
+}
+
+#[pymethods]
+impl PyTradeRecord {
+    /// Trade price as a float.
+    #[getter]
+    fn price(&self) -> f64 {
+        to_f64(self.price_i64)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "TradeRecord(ts_event_unix_ns={}, symbol='{}', price={}, size={})",
+            self.ts_event_unix_ns,
+            self.symbol,
+            self.price(),
+            self.size_u32
+        )
+    }
+}
+
+impl From<TradeRecord> for PyTradeRecord {
+    fn from(t: TradeRecord) -> Self {
+        Self {
+            ts_event_unix_ns: t.ts_event_unix_ns,
+            symbol: t.symbol,
+            price_i64: t.price_i64,
+            size_u32: t.size_u32,
+            side: t.quote.map(|q| side_str(q.side).to_string()),
+        }
+    }
+}
+
+/// A single OHLCV bar.
+#[pyclass(name = "OhlcvRecord", module = "signalfoundry", frozen, get_all)]
+pub struct PyOhlcvRecord {
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    pub open_i64: i64,
+    pub high_i64: i64,
+    pub low_i64: i64,
+    pub close_i64: i64,
+    pub volume_u64: u64,
+}
+
+#[pymethods]
+impl PyOhlcvRecord {
+    #[getter]
+    fn open(&self) -> f64 {
+        to_f64(self.open_i64)
+    }
+
+    #[getter]
+    fn high(&self) -> f64 {
+        to_f64(self.high_i64)
+    }
+
+    #[getter]
+    fn low(&self) -> f64 {
+        to_f64(self.low_i64)
+    }
+
+    #[getter]
+    fn close(&self) -> f64 {
+        to_f64(self.close_i64)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "OhlcvRecord(ts_event_unix_ns={}, symbol='{}', open={}, high={}, low={}, close={}, volume={})",
+            self.ts_event_unix_ns,
+            self.symbol,
+            self.open(),
+            self.high(),
+            self.low(),
+            self.close(),
+            self.volume_u64
+        )
+    }
+}
+
+impl From<OhlcvRecord> for PyOhlcvRecord {
+    fn from(b: OhlcvRecord) -> Self {
+        Self {
+            ts_event_unix_ns: b.ts_event_unix_ns,
+            symbol: b.symbol,
+            open_i64: b.open_i64,
+            high_i64: b.high_i64,
+            low_i64: b.low_i64,
+            close_i64: b.close_i64,
+            volume_u64: b.volume_u64,
+        }
+    }
+}
+
+/// A live analytic update of `symbol`, e.g. order-flow imbalance over
+/// `window`.
+#[pyclass(name = "Metric", module = "signalfoundry", frozen, get_all)]
+pub struct PyMetric {
+    pub symbol: String,
+    pub name: String,
+    pub value: f64,
+    pub window: String,
+}
+
+#[pymethods]
+impl PyMetric {
+    fn __repr__(&self) -> String {
+        format!(
+            "Metric(symbol='{}', name='{}', window='{}', value={})",
+            self.symbol, self.name, self.window, self.value
+        )
+    }
+}
+
+/// Result of a historical query.
+///
+/// Records stay in Rust until requested, so converting straight to numpy or
+/// pandas never materializes per-record Python objects.
+#[pyclass(name = "HistoricalData", module = "signalfoundry", frozen)]
+pub struct PyHistoricalData {
+    schema: String,
+    trades: Vec<TradeRecord>,
+    bars: Vec<OhlcvRecord>,
+}
+
+impl From<HistoricalResponse> for PyHistoricalData {
+    fn from(resp: HistoricalResponse) -> Self {
+        let schema = resp.schema().to_string();
+        match resp {
+            HistoricalResponse::Trades { data } => Self {
+                schema,
+                trades: data,
+                bars: Vec::new(),
+            },
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => Self {
+                schema,
+                trades: Vec::new(),
+                bars: data,
+            },
+        }
+    }
+}
+
+#[pymethods]
+impl PyHistoricalData {
+    /// Schema of the records ("trades", "ohlcv-1m", "tickbar-500", ...).
+    ///
+    /// CVD schemas use the OHLCV layout; their OHLC values are cumulative
+    /// buy-minus-sell volume.
+    #[getter]
+    fn schema(&self) -> &str {
+        &self.schema
+    }
+
+    fn __len__(&self) -> usize {
+        self.trades.len() + self.bars.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "HistoricalData(schema='{}', records={})",
+            self.schema,
+            self.__len__()
+        )
+    }
+
+    /// Records as a list of TradeRecord or OhlcvRecord objects.
+    fn records<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        if self.schema == "trades" {
+            let items = self
+                .trades
+                .iter()
+                .map(|t| Bound::new(py, PyTradeRecord::from(t.clone())))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items)
+        } else {
+            let items = self
+                .bars
+                .iter()
+                .map(|b| Bound::new(py, PyOhlcvRecord::from(b.clone())))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items)
+        }
+    }
+
+    /// Column arrays keyed by field name.
+    ///
+    /// `ts_event` is uint64 nanoseconds since the UNIX epoch, prices are
+    /// float64, sizes/volumes are unsigned integers, and `symbol` is a list.
+    fn to_numpy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let columns = PyDict::new(py);
+
+        if self.schema == "trades" {
+            let t = &self.trades;
+            columns.set_item("ts_event", col(t, |r| r.ts_event_unix_ns).into_pyarray(py))?;
+            columns.set_item("symbol", col(t, |r| r.symbol.clone()))?;
+            columns.set_item("price", col(t, |r| to_f64(r.price_i64)).into_pyarray(py))?;
+            columns.set_item("size", col(t, |r| r.size_u32).into_pyarray(py))?;
+            if t.iter().any(|r| r.quote.is_some()) {
+                // NaN / None where no BBO was available
+                let px = |p: fn(&shared::QuoteAtTrade) -> i64| {
+                    move |r: &TradeRecord| r.quote.as_ref().map_or(f64::NAN, |q| to_f64(p(q)))
+                };
+                columns.set_item("bid", col(t, px(|q| q.bid_px_i64)).into_pyarray(py))?;
+                columns.set_item("ask", col(t, px(|q| q.ask_px_i64)).into_pyarray(py))?;
+                columns.set_item("side", col(t, |r| r.quote.map(|q| side_str(q.side))))?;
+            }
+        } else {
+            let b = &self.bars;
+            columns.set_item("ts_event", col(b, |r| r.ts_event_unix_ns).into_pyarray(py))?;
+            columns.set_item("symbol", col(b, |r| r.symbol.clone()))?;
+            columns.set_item("open", col(b, |r| to_f64(r.open_i64)).into_pyarray(py))?;
+            columns.set_item("high", col(b, |r| to_f64(r.high_i64)).into_pyarray(py))?;
+            columns.set_item("low", col(b, |r| to_f64(r.low_i64)).into_pyarray(py))?;
+            columns.set_item("close", col(b, |r| to_f64(r.close_i64)).into_pyarray(py))?;
+            columns.set_item("volume", col(b, |r| r.volume_u64).into_pyarray(py))?;
+        }
+
+        Ok(columns)
+    }
+
+    /// A pandas DataFrame indexed by a UTC `ts_event` DatetimeIndex.
+    ///
+    /// Requires pandas to be installed.
+    fn to_pandas<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let pd = py.import("pandas")?;
+        let df = pd.call_method1("DataFrame", (self.to_numpy(py)?,))?;
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("unit", "ns")?;
+        kwargs.set_item("utc", true)?;
+        let ts = pd.call_method("to_datetime", (df.get_item("ts_event")?,), Some(&kwargs))?;
+        df.set_item("ts_event", ts)?;
+
+        df.call_method1("set_index", ("ts_event",))
+    }
+}
+
+fn side_str(side: TradeSide) -> &'static str {
+    match side {
+        TradeSide::Bid => "bid",
+        TradeSide::Ask => "ask",
+        TradeSide::Mid => "mid",
+    }
+}
+
+fn col<R, T>(records: &[R], f: impl Fn(&R) -> T) -> Vec<T> {
+    records.iter().map(f).collect()
+}
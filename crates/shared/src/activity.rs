@@ -0,0 +1,359 @@
+//! Order activity by price level, from market-by-order (MBO) events.
+//!
+//! [`OrderActivity`] follows every order of each instrument through a
+//! replay and counts, per price, the orders added, cancelled and traded,
+//! with how long orders rested before leaving the book. Like an
+//! [`OrderBook`](crate::book::OrderBook) it must start from the provider's
+//! snapshot, so orders resting before the range are known; only events
+//! from the range's start are counted.
+//!
+//! Venues take filled orders off the book with a cancel, in the same event
+//! as the trade. A cancel is counted as one only if the order had no fill
+//! and nothing traded at its price in that event; the rest are filled
+//! orders leaving.
+
+use crate::book::{MboAction, MboRecord};
+use crate::SymbolType;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// A request for a symbol's order activity over a range, from
+/// `POST /api/book/activity`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OrderActivityRequest {
+    pub symbol: String,
+    #[serde(default)]
+    pub stype_in: SymbolType,
+    /// Times as a historical request's
+    pub start_rfc3339: String,
+    pub end_rfc3339: String,
+}
+
+/// What orders did, at one price or over a whole book.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ActivityCounts {
+    pub adds_u64: u64,
+    /// Orders cancelled, in part or whole, and not filled
+    pub cancels_u64: u64,
+    pub trades_u64: u64,
+    pub traded_volume_u64: u64,
+    /// Orders that left the book, cancelled or filled
+    pub departures_u64: u64,
+    /// Mean time from add to leaving the book of the orders that left
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_resting_ns_u64: Option<u64>,
+    /// Cancels per trade; left out when nothing traded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancel_to_trade: Option<f64>,
+}
+
+/// One price's activity.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct LevelActivity {
+    pub price_i64: i64,
+    #[serde(flatten)]
+    pub counts: ActivityCounts,
+}
+
+/// One instrument's activity.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InstrumentActivity {
+    pub symbol: String,
+    pub totals: ActivityCounts,
+    /// By price, lowest first
+    pub levels: Vec<LevelActivity>,
+}
+
+/// A symbol's order activity over a range.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OrderActivityReport {
+    /// As requested
+    pub symbol: String,
+    pub start_unix_ns: u64,
+    pub end_unix_ns: u64,
+    /// Events replayed, from the day's snapshot
+    pub events_u64: u64,
+    /// By symbol
+    pub instruments: Vec<InstrumentActivity>,
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    counts: ActivityCounts,
+    resting_ns: u128,
+}
+
+impl Tally {
+    fn finish(&self) -> ActivityCounts {
+        let counts = self.counts;
+        ActivityCounts {
+            avg_resting_ns_u64: (counts.departures_u64 > 0)
+                .then(|| (self.resting_ns / u128::from(counts.departures_u64)) as u64),
+            cancel_to_trade: (counts.trades_u64 > 0)
+                .then(|| counts.cancels_u64 as f64 / counts.trades_u64 as f64),
+            ..counts
+        }
+    }
+
+    fn merge(&mut self, other: &Tally) {
+        let (a, b) = (&mut self.counts, &other.counts);
+        a.adds_u64 += b.adds_u64;
+        a.cancels_u64 += b.cancels_u64;
+        a.trades_u64 += b.trades_u64;
+        a.traded_volume_u64 += b.traded_volume_u64;
+        a.departures_u64 += b.departures_u64;
+        self.resting_ns += other.resting_ns;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Resting {
+    added_unix_ns: u64,
+    price_i64: i64,
+    size_u32: u32,
+}
+
+#[derive(Debug, Default)]
+struct Instrument {
+    orders: HashMap<u64, Resting>,
+    /// Orders with a fill since their last cancel
+    filled: HashSet<u64>,
+    /// The venue event being applied, and the prices it traded at
+    event_unix_ns: u64,
+    traded: Vec<i64>,
+    levels: BTreeMap<i64, Tally>,
+}
+
+/// Order activity by instrument and price, built from a replay.
+#[derive(Debug, Default)]
+pub struct OrderActivity {
+    from_unix_ns: u64,
+    instruments: HashMap<String, Instrument>,
+    events: u64,
+}
+
+impl OrderActivity {
+    /// Activity counted from `from_unix_ns`.
+    pub fn new(from_unix_ns: u64) -> Self {
+        Self {
+            from_unix_ns,
+            ..Default::default()
+        }
+    }
+
+    /// Apply one event.
+    pub fn apply(&mut self, record: &MboRecord) {
+        self.events += 1;
+        let counting = record.ts_event_unix_ns >= self.from_unix_ns;
+        let instrument = self.instruments.entry(record.symbol.clone()).or_default();
+        if record.ts_event_unix_ns != instrument.event_unix_ns {
+            instrument.event_unix_ns = record.ts_event_unix_ns;
+            instrument.traded.clear();
+        }
+        let Instrument {
+            orders,
+            filled,
+            traded,
+            levels,
+            ..
+        } = instrument;
+
+        match record.action {
+            MboAction::Add => {
+                orders.insert(
+                    record.order_id_u64,
+                    Resting {
+                        added_unix_ns: record.ts_event_unix_ns,
+                        price_i64: record.price_i64,
+                        size_u32: record.size_u32,
+                    },
+                );
+                if counting {
+                    levels.entry(record.price_i64).or_default().counts.adds_u64 += 1;
+                }
+            }
+            // Keeps its add time: a moved order has rested since then
+            MboAction::Modify => {
+                let resting = orders.entry(record.order_id_u64).or_insert(Resting {
+                    added_unix_ns: record.ts_event_unix_ns,
+                    price_i64: record.price_i64,
+                    size_u32: record.size_u32,
+                });
+                resting.price_i64 = record.price_i64;
+                resting.size_u32 = record.size_u32;
+            }
+            MboAction::Cancel => {
+                let was_filled = filled.remove(&record.order_id_u64);
+                let Some(resting) = orders.get(&record.order_id_u64).copied() else {
+                    if counting && !traded.contains(&record.price_i64) {
+                        levels
+                            .entry(record.price_i64)
+                            .or_default()
+                            .counts
+                            .cancels_u64 += 1;
+                    }
+                    return;
+                };
+                let whole = record.size_u32 >= resting.size_u32;
+                if whole {
+                    orders.remove(&record.order_id_u64);
+                } else if let Some(left) = orders.get_mut(&record.order_id_u64) {
+                    left.size_u32 -= record.size_u32;
+                }
+                if !counting {
+                    return;
+                }
+                let tally = levels.entry(resting.price_i64).or_default();
+                if !was_filled && !traded.contains(&resting.price_i64) {
+                    tally.counts.cancels_u64 += 1;
+                }
+                if whole {
+                    tally.counts.departures_u64 += 1;
+                    tally.resting_ns += u128::from(
+                        record
+                            .ts_event_unix_ns
+                            .saturating_sub(resting.added_unix_ns),
+                    );
+                }
+            }
+            MboAction::Trade => {
+                traded.push(record.price_i64);
+                if counting {
+                    let tally = levels.entry(record.price_i64).or_default();
+                    tally.counts.trades_u64 += 1;
+                    tally.counts.traded_volume_u64 += u64::from(record.size_u32);
+                }
+            }
+            MboAction::Fill => {
+                filled.insert(record.order_id_u64);
+            }
+            MboAction::Clear => {
+                orders.clear();
+                filled.clear();
+            }
+            MboAction::None => {}
+        }
+    }
+
+    /// Events applied so far.
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// The activity so far, labelled `symbol` as requested, by symbol.
+    pub fn report(
+        &self,
+        symbol: &str,
+        start_unix_ns: u64,
+        end_unix_ns: u64,
+    ) -> OrderActivityReport {
+        let mut instruments: Vec<InstrumentActivity> = self
+            .instruments
+            .iter()
+            .map(|(symbol, instrument)| {
+                let mut totals = Tally::default();
+                let levels = instrument
+                    .levels
+                    .iter()
+                    .map(|(price_i64, tally)| {
+                        totals.merge(tally);
+                        LevelActivity {
+                            price_i64: *price_i64,
+                            counts: tally.finish(),
+                        }
+                    })
+                    .collect();
+                InstrumentActivity {
+                    symbol: symbol.clone(),
+                    totals: totals.finish(),
+                    levels,
+                }
+            })
+            .collect();
+        instruments.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        OrderActivityReport {
+            symbol: symbol.to_string(),
+            start_unix_ns,
+            end_unix_ns,
+            events_u64: self.events,
+            instruments,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::BookSide;
+
+    fn event(ts: u64, action: MboAction, order_id_u64: u64, price: i64, size: u32) -> MboRecord {
+        MboRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ESZ4".to_string(),
+            order_id_u64,
+            price_i64: price,
+            size_u32: size,
+            action,
+            side: BookSide::Bid,
+            sequence_u32: 0,
+            last: true,
+            maybe_bad_book: false,
+        }
+    }
+
+    #[test]
+    fn test_counts_by_level() {
+        use MboAction::{Add, Cancel, Clear, Fill, Trade};
+
+        // Counting starts at 10; order 1 rests from before it
+        let mut activity = OrderActivity::new(10);
+        for record in [
+            event(0, Clear, 0, 0, 0),
+            event(5, Add, 1, 100, 5),
+            event(12, Add, 2, 100, 3),
+            event(14, Add, 3, 99, 4),
+            // A partial cancel, then a trade whose cancel is a fill
+            event(20, Cancel, 1, 100, 2),
+            event(30, Trade, 0, 100, 3),
+            event(30, Cancel, 1, 100, 3),
+            // A fill named by the venue, then a true cancel
+            event(40, Fill, 2, 100, 3),
+            event(41, Cancel, 2, 100, 3),
+            event(50, Cancel, 3, 99, 4),
+        ] {
+            activity.apply(&record);
+        }
+
+        let report = activity.report("ES.FUT", 10, 60);
+        assert_eq!(report.events_u64, 10);
+        let instrument = &report.instruments[0];
+        let at = |price: i64| {
+            instrument
+                .levels
+                .iter()
+                .find(|level| level.price_i64 == price)
+                .unwrap()
+                .counts
+        };
+        let top = at(100);
+        assert_eq!(
+            (
+                top.adds_u64,
+                top.cancels_u64,
+                top.trades_u64,
+                top.departures_u64
+            ),
+            (1, 1, 1, 2)
+        );
+        // Orders 1 and 2 rested 25 and 29
+        assert_eq!(top.avg_resting_ns_u64, Some(27));
+        assert_eq!(top.cancel_to_trade, Some(1.0));
+        let below = at(99);
+        assert_eq!((below.cancels_u64, below.cancel_to_trade), (1, None));
+
+        let totals = instrument.totals;
+        assert_eq!((totals.adds_u64, totals.cancels_u64), (2, 2));
+        assert_eq!(totals.avg_resting_ns_u64, Some((25 + 29 + 36) / 3));
+        assert_eq!(totals.cancel_to_trade, Some(2.0));
+    }
+}
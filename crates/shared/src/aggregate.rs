@@ -0,0 +1,1080 @@
+//! Bar aggregation and downsampling.
+//!
+//! These helpers turn trade prints into OHLCV bars (of price, or of
+//! cumulative volume delta) and shrink long bar series down to a point
+//! budget. They are pure functions over the shared
+//! record types so the backend, benches, and any other consumer produce
+//! identical bars.
+
+use crate::price::PRICE_SCALE;
+use crate::sessions::{self, SessionSpec};
+use crate::timeexpr::{parse_lookback, parse_time_of_day};
+use crate::{OhlcvRecord, TradeRecord, TradeSide};
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Nanoseconds in one second.
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Nanoseconds in one minute.
+pub const NANOS_PER_MINUTE: u64 = 60 * NANOS_PER_SECOND;
+
+/// Where time bars start.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BarAlign {
+    /// Bars open on whole multiples of the interval since the UNIX epoch,
+    /// so a 1h bar always opens on the hour (UTC)
+    #[default]
+    Epoch,
+    /// Bars open at the session open, a wall-clock time in a time zone,
+    /// and every interval after it. Each session open starts a new bar, so
+    /// CME's 08:30 CT open is never split mid-bar.
+    Session { open: NaiveTime, timezone: Tz },
+    /// Epoch-anchored bars shifted later by this many nanoseconds
+    Offset(u64),
+}
+
+impl BarAlign {
+    /// Parse `epoch`, `session 08:30 America/Chicago` (time zone defaults
+    /// to UTC), `session cme` (a market's session open, see
+    /// [`SessionSpec`]), or `offset 30m`.
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let invalid = |why: &str| {
+            format!(
+                "invalid align '{}': {} (expected 'epoch', 'session 08:30 America/Chicago', or 'offset 30m')",
+                spec, why
+            )
+        };
+        let words: Vec<&str> = spec.split_whitespace().collect();
+        match words.as_slice() {
+            ["epoch"] => Ok(BarAlign::Epoch),
+            ["session", market] if parse_time_of_day(market).is_err() => {
+                let spec = SessionSpec::parse(market).map_err(|e| invalid(&e))?;
+                Ok(BarAlign::Session {
+                    open: spec.open,
+                    timezone: spec.timezone,
+                })
+            }
+            ["session", open, zone @ ..] if zone.len() <= 1 => Ok(BarAlign::Session {
+                open: parse_time_of_day(open).map_err(|e| invalid(&e))?,
+                timezone: match zone.first() {
+                    Some(zone) => zone
+                        .parse()
+                        .map_err(|_| invalid(&format!("unknown time zone '{}'", zone)))?,
+                    None => Tz::UTC,
+                },
+            }),
+            ["offset", offset] => {
+                let offset = parse_lookback(offset).map_err(|e| invalid(&e))?;
+                let offset_ns = offset
+                    .num_nanoseconds()
+                    .and_then(|ns| u64::try_from(ns).ok())
+                    .ok_or_else(|| invalid("offset out of range"))?;
+                Ok(BarAlign::Offset(offset_ns))
+            }
+            _ => Err(invalid("unrecognized alignment")),
+        }
+    }
+}
+
+impl std::str::FromStr for BarAlign {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_spec(s)
+    }
+}
+
+/// Maps timestamps to the start of the bar containing them.
+#[derive(Debug, Clone)]
+pub struct BarBuckets {
+    interval_ns: u64,
+    align: BarAlign,
+    /// Last session looked up, as `[open, next open)`
+    session: Option<(u64, u64)>,
+}
+
+impl BarBuckets {
+    pub fn new(interval_ns: u64, align: BarAlign) -> Self {
+        assert!(interval_ns > 0, "bar interval must be non-zero");
+        Self {
+            interval_ns,
+            align,
+            session: None,
+        }
+    }
+
+    /// Bar width in nanoseconds.
+    pub fn interval_ns(&self) -> u64 {
+        self.interval_ns
+    }
+
+    /// Open time of the bar containing `ts_ns`.
+    pub fn start(&mut self, ts_ns: u64) -> u64 {
+        let anchor = match self.align {
+            BarAlign::Epoch => 0,
+            BarAlign::Offset(offset_ns) => offset_ns % self.interval_ns,
+            BarAlign::Session { open, timezone } => {
+                if self
+                    .session
+                    .is_none_or(|(start, end)| ts_ns < start || ts_ns >= end)
+                {
+                    self.session = session_containing(ts_ns, open, timezone);
+                }
+                self.session.map_or(0, |(start, _)| start)
+            }
+        };
+        if ts_ns < anchor {
+            // Before the anchor: count back from it (saturating at the epoch)
+            let back = (anchor - ts_ns).div_ceil(self.interval_ns) * self.interval_ns;
+            return anchor.saturating_sub(back);
+        }
+        ts_ns - (ts_ns - anchor) % self.interval_ns
+    }
+}
+
+/// The session `[open, next open)` containing `ts_ns`.
+fn session_containing(ts_ns: u64, open: NaiveTime, tz: Tz) -> Option<(u64, u64)> {
+    let date = DateTime::from_timestamp_nanos(i64::try_from(ts_ns).ok()?)
+        .with_timezone(&tz)
+        .date_naive();
+    for day in [date, date.pred_opt()?] {
+        let start = session_open(day, open, tz)?;
+        if start <= ts_ns {
+            return Some((start, session_open(day.succ_opt()?, open, tz)?));
+        }
+    }
+    None
+}
+
+/// The session open on `day`, past the gap if a DST change skips it.
+fn session_open(day: NaiveDate, open: NaiveTime, tz: Tz) -> Option<u64> {
+    let time = sessions::to_utc(day.and_time(open), tz)?;
+    u64::try_from(time.timestamp_nanos_opt()?).ok()
+}
+
+/// Incrementally builds time bars from a stream of trades.
+///
+/// Bars are anchored to the UNIX epoch unless built with
+/// [`BarBuilder::aligned`], so a 1m bar always opens on a whole minute. One
+/// bar is kept in progress per symbol; feeding a trade that falls into a
+/// later bucket closes the previous bar and returns it.
+#[derive(Debug, Clone)]
+pub struct BarBuilder {
+    buckets: BarBuckets,
+    bars: HashMap<String, OhlcvRecord>,
+}
+
+impl BarBuilder {
+    /// Create a builder producing epoch-anchored bars `interval_ns` wide.
+    pub fn new(interval_ns: u64) -> Self {
+        Self::aligned(interval_ns, BarAlign::Epoch)
+    }
+
+    /// Create a builder producing bars `interval_ns` wide, starting where
+    /// `align` puts them.
+    pub fn aligned(interval_ns: u64, align: BarAlign) -> Self {
+        Self {
+            buckets: BarBuckets::new(interval_ns, align),
+            bars: HashMap::new(),
+        }
+    }
+
+    /// Bar width in nanoseconds.
+    pub fn interval_ns(&self) -> u64 {
+        self.buckets.interval_ns()
+    }
+
+    /// Feed a trade into the builder.
+    ///
+    /// Returns the completed bar for the trade's symbol if this trade opened a
+    /// new bar. Trades older than the bar in progress are folded into it
+    /// rather than reopening a closed bar.
+    pub fn push(&mut self, trade: &TradeRecord) -> Option<OhlcvRecord> {
+        let bucket = self.buckets.start(trade.ts_event_unix_ns);
+
+        match self.bars.get_mut(&trade.symbol) {
+            Some(bar) if bucket <= bar.ts_event_unix_ns => {
+                bar.high_i64 = bar.high_i64.max(trade.price_i64);
+                bar.low_i64 = bar.low_i64.min(trade.price_i64);
+                bar.close_i64 = trade.price_i64;
+                bar.volume_u64 += trade.size_u32 as u64;
+                None
+            }
+            Some(bar) => Some(std::mem::replace(bar, Self::open_bar(bucket, trade))),
+            None => {
+                self.bars
+                    .insert(trade.symbol.clone(), Self::open_bar(bucket, trade));
+                None
+            }
+        }
+    }
+
+    /// The bar currently in progress for `symbol`, if any.
+    pub fn current(&self, symbol: &str) -> Option<&OhlcvRecord> {
+        self.bars.get(symbol)
+    }
+
+    /// Close and return every bar in progress, ordered by open time.
+    pub fn flush(&mut self) -> Vec<OhlcvRecord> {
+        let mut bars: Vec<OhlcvRecord> = self.bars.drain().map(|(_, bar)| bar).collect();
+        bars.sort_by(|a, b| {
+            a.ts_event_unix_ns
+                .cmp(&b.ts_event_unix_ns)
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        bars
+    }
+
+    fn open_bar(bucket: u64, trade: &TradeRecord) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: bucket,
+            symbol: trade.symbol.clone(),
+            open_i64: trade.price_i64,
+            high_i64: trade.price_i64,
+            low_i64: trade.price_i64,
+            close_i64: trade.price_i64,
+            volume_u64: trade.size_u32 as u64,
+        }
+    }
+}
+
+/// Builds time bars from a live trade stream and hands them out only once
+/// closed, in time order across symbols, so a consumer such as a backtest
+/// sees the same sequence of bars however the trades were batched.
+///
+/// Every symbol's bar closes together: the first trade in a later bar closes
+/// all bars in progress. A trade older than the current bar is dropped, as
+/// its bar has already been handed out.
+#[derive(Debug, Clone)]
+pub struct ClosedBarFeed {
+    builder: BarBuilder,
+    current: Option<u64>,
+    dropped: u64,
+}
+
+impl ClosedBarFeed {
+    pub fn new(interval_ns: u64, align: BarAlign) -> Self {
+        Self {
+            builder: BarBuilder::aligned(interval_ns, align),
+            current: None,
+            dropped: 0,
+        }
+    }
+
+    /// Feed a trade, returning the bars it closed, ordered by symbol.
+    pub fn push(&mut self, trade: &TradeRecord) -> Vec<OhlcvRecord> {
+        let bucket = self.builder.buckets.start(trade.ts_event_unix_ns);
+        let closed = match self.current {
+            Some(current) if bucket < current => {
+                self.dropped += 1;
+                return Vec::new();
+            }
+            Some(current) if bucket > current => self.builder.flush(),
+            _ => Vec::new(),
+        };
+        self.current = Some(bucket);
+        self.builder.push(trade);
+        closed
+    }
+
+    /// Close and return the bars in progress, e.g. when the stream ends.
+    pub fn flush(&mut self) -> Vec<OhlcvRecord> {
+        self.builder.flush()
+    }
+
+    /// Trades dropped for arriving after their bar closed.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// Closed bars of several widths from one stream. The finest width is built
+/// from trades, or comes built from an upstream bar subscription, and each
+/// coarser width is rolled up from the finest's closed bars, so one
+/// subscription feeds them all. Bars close as in [`ClosedBarFeed`]: a bar
+/// of the next bucket closes every symbol's bar of that width.
+#[derive(Debug, Clone)]
+pub struct BarFanout {
+    /// Builds the finest width from trades; `None` when bars come built
+    trades: Option<(u64, ClosedBarFeed)>,
+    /// Coarser widths, narrowest first
+    coarser: Vec<BarRollup>,
+}
+
+impl BarFanout {
+    /// Bars of each of `intervals_ns`, the finest built from trades. Every
+    /// width must be a whole multiple of the finest.
+    pub fn from_trades(intervals_ns: &[u64], align: BarAlign) -> Result<Self, String> {
+        let finest = intervals_ns
+            .iter()
+            .copied()
+            .min()
+            .ok_or("no bar widths given")?;
+        Ok(Self {
+            trades: Some((finest, ClosedBarFeed::new(finest, align))),
+            coarser: Self::rollups(finest, intervals_ns, align)?,
+        })
+    }
+
+    /// Bars of each of `intervals_ns` wider than `source_ns`, rolled up from
+    /// upstream bars `source_ns` wide. Every width must be a whole multiple
+    /// of `source_ns`.
+    pub fn from_bars(
+        source_ns: u64,
+        intervals_ns: &[u64],
+        align: BarAlign,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            trades: None,
+            coarser: Self::rollups(source_ns, intervals_ns, align)?,
+        })
+    }
+
+    fn rollups(
+        finest_ns: u64,
+        intervals_ns: &[u64],
+        align: BarAlign,
+    ) -> Result<Vec<BarRollup>, String> {
+        if let Some(bad) = intervals_ns
+            .iter()
+            .find(|ns| **ns < finest_ns || **ns % finest_ns != 0)
+        {
+            let width = |ns: u64| match ns % NANOS_PER_SECOND {
+                0 => format!("{}s", ns / NANOS_PER_SECOND),
+                _ => format!("{}ns", ns),
+            };
+            return Err(format!(
+                "{} bars can't be built from {} bars: each width must be a whole multiple of the finest",
+                width(*bad),
+                width(finest_ns)
+            ));
+        }
+        let mut widths: Vec<u64> = intervals_ns
+            .iter()
+            .copied()
+            .filter(|ns| *ns > finest_ns)
+            .collect();
+        widths.sort_unstable();
+        widths.dedup();
+        Ok(widths
+            .into_iter()
+            .map(|ns| BarRollup {
+                buckets: BarBuckets::new(ns, align),
+                current: None,
+                bars: HashMap::new(),
+            })
+            .collect())
+    }
+
+    /// Feed a trade, returning the bars it closed with their widths: the
+    /// finest first, then each coarser width's. Ignored when bars come
+    /// built.
+    pub fn push_trade(&mut self, trade: &TradeRecord) -> Vec<(u64, OhlcvRecord)> {
+        let Some((finest_ns, feed)) = self.trades.as_mut() else {
+            return Vec::new();
+        };
+        let finest_ns = *finest_ns;
+        let closed = feed.push(trade);
+        self.roll_up(finest_ns, closed)
+    }
+
+    /// Feed a closed bar of the finest width, returning the coarser bars it
+    /// closed with their widths.
+    pub fn push_bar(&mut self, bar: &OhlcvRecord) -> Vec<(u64, OhlcvRecord)> {
+        let mut out = Vec::new();
+        for rollup in &mut self.coarser {
+            let width = rollup.buckets.interval_ns();
+            out.extend(rollup.push(bar).into_iter().map(|bar| (width, bar)));
+        }
+        out
+    }
+
+    /// Close and return the bars in progress, e.g. when the stream ends.
+    pub fn flush(&mut self) -> Vec<(u64, OhlcvRecord)> {
+        let mut out = match self.trades.as_mut() {
+            Some((finest_ns, feed)) => {
+                let finest_ns = *finest_ns;
+                let closed = feed.flush();
+                self.roll_up(finest_ns, closed)
+            }
+            None => Vec::new(),
+        };
+        for rollup in &mut self.coarser {
+            let width = rollup.buckets.interval_ns();
+            out.extend(rollup.flush().into_iter().map(|bar| (width, bar)));
+        }
+        out
+    }
+
+    /// Trades dropped for arriving after their bar closed.
+    pub fn dropped(&self) -> u64 {
+        self.trades.as_ref().map_or(0, |(_, feed)| feed.dropped())
+    }
+
+    /// `closed` finest bars followed by the coarser bars they close.
+    fn roll_up(&mut self, finest_ns: u64, closed: Vec<OhlcvRecord>) -> Vec<(u64, OhlcvRecord)> {
+        let coarser: Vec<_> = closed.iter().flat_map(|bar| self.push_bar(bar)).collect();
+        closed
+            .into_iter()
+            .map(|bar| (finest_ns, bar))
+            .chain(coarser)
+            .collect()
+    }
+}
+
+/// One coarser width of a [`BarFanout`], merging the finest bars.
+#[derive(Debug, Clone)]
+struct BarRollup {
+    buckets: BarBuckets,
+    current: Option<u64>,
+    bars: HashMap<String, OhlcvRecord>,
+}
+
+impl BarRollup {
+    fn push(&mut self, bar: &OhlcvRecord) -> Vec<OhlcvRecord> {
+        let bucket = self.buckets.start(bar.ts_event_unix_ns);
+        let closed = match self.current {
+            // A bar for a bucket already closed can't reopen it
+            Some(current) if bucket < current => return Vec::new(),
+            Some(current) if bucket > current => self.flush(),
+            _ => Vec::new(),
+        };
+        self.current = Some(bucket);
+        match self.bars.get_mut(&bar.symbol) {
+            Some(open) => {
+                let merged = merge_bars(&[&*open, bar]);
+                *open = merged;
+            }
+            None => {
+                self.bars.insert(
+                    bar.symbol.clone(),
+                    OhlcvRecord {
+                        ts_event_unix_ns: bucket,
+                        ..bar.clone()
+                    },
+                );
+            }
+        }
+        closed
+    }
+
+    fn flush(&mut self) -> Vec<OhlcvRecord> {
+        let mut bars: Vec<OhlcvRecord> = self.bars.drain().map(|(_, bar)| bar).collect();
+        sort_bars(&mut bars);
+        bars
+    }
+}
+
+/// Aggregate trades into epoch-anchored OHLCV bars `interval_ns` wide.
+///
+/// Trades should be in timestamp order per symbol. The result is ordered by
+/// bar open time, then symbol.
+pub fn aggregate_trades(trades: &[TradeRecord], interval_ns: u64) -> Vec<OhlcvRecord> {
+    aggregate_trades_aligned(trades, interval_ns, BarAlign::Epoch)
+}
+
+/// [`aggregate_trades`] with bars starting where `align` puts them.
+pub fn aggregate_trades_aligned(
+    trades: &[TradeRecord],
+    interval_ns: u64,
+    align: BarAlign,
+) -> Vec<OhlcvRecord> {
+    let mut builder = BarBuilder::aligned(interval_ns, align);
+    let mut bars: Vec<OhlcvRecord> = trades.iter().filter_map(|t| builder.push(t)).collect();
+    bars.extend(builder.flush());
+    bars.sort_by(|a, b| {
+        a.ts_event_unix_ns
+            .cmp(&b.ts_event_unix_ns)
+            .then_with(|| a.symbol.cmp(&b.symbol))
+    });
+    bars
+}
+
+/// What fills a [`BarSpec`] bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BarKind {
+    /// A bar every `threshold` trades
+    Tick,
+    /// A bar every `threshold` contracts or shares
+    Volume,
+    /// A bar every `threshold` dollars of notional: price times size, with
+    /// no contract multiplier
+    Dollar,
+}
+
+impl BarKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            BarKind::Tick => "tickbar",
+            BarKind::Volume => "volbar",
+            BarKind::Dollar => "dollarbar",
+        }
+    }
+}
+
+/// Bars that close on trading activity rather than time, named like a
+/// schema: `tickbar-500`, `volbar-1000`, `dollarbar-5000000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BarSpec {
+    pub kind: BarKind,
+    pub threshold: u64,
+}
+
+impl fmt::Display for BarSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.kind.prefix(), self.threshold)
+    }
+}
+
+impl std::str::FromStr for BarSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid bar type '{}': expected tickbar-N, volbar-N, or dollarbar-N",
+                s
+            )
+        };
+        let (prefix, threshold) = s.split_once('-').ok_or_else(invalid)?;
+        let kind = [BarKind::Tick, BarKind::Volume, BarKind::Dollar]
+            .into_iter()
+            .find(|kind| kind.prefix() == prefix)
+            .ok_or_else(invalid)?;
+        let threshold = threshold
+            .parse()
+            .ok()
+            .filter(|&n: &u64| n > 0)
+            .ok_or_else(invalid)?;
+        Ok(Self { kind, threshold })
+    }
+}
+
+/// Incrementally builds [`BarSpec`] bars from a stream of trades.
+///
+/// A bar closes on the trade that takes it to its threshold, so volume and
+/// dollar bars can overshoot by part of one trade rather than splitting it.
+/// Each bar is stamped with its first trade's time. One bar is kept in
+/// progress per symbol.
+#[derive(Debug, Clone)]
+pub struct ThresholdBarBuilder {
+    spec: BarSpec,
+    /// Threshold in progress units (dollars are fixed-point)
+    target: u128,
+    bars: HashMap<String, (OhlcvRecord, u128)>,
+}
+
+impl ThresholdBarBuilder {
+    pub fn new(spec: BarSpec) -> Self {
+        let target = match spec.kind {
+            BarKind::Dollar => spec.threshold as u128 * PRICE_SCALE as u128,
+            BarKind::Tick | BarKind::Volume => spec.threshold as u128,
+        };
+        Self {
+            spec,
+            target,
+            bars: HashMap::new(),
+        }
+    }
+
+    pub fn spec(&self) -> BarSpec {
+        self.spec
+    }
+
+    /// Feed a trade into the builder. Returns the bar it completed, if any.
+    pub fn push(&mut self, trade: &TradeRecord) -> Option<OhlcvRecord> {
+        let step = match self.spec.kind {
+            BarKind::Tick => 1,
+            BarKind::Volume => trade.size_u32 as u128,
+            BarKind::Dollar => trade.price_i64.unsigned_abs() as u128 * trade.size_u32 as u128,
+        };
+        let filled = match self.bars.get_mut(&trade.symbol) {
+            Some((bar, filled)) => {
+                bar.high_i64 = bar.high_i64.max(trade.price_i64);
+                bar.low_i64 = bar.low_i64.min(trade.price_i64);
+                bar.close_i64 = trade.price_i64;
+                bar.volume_u64 += trade.size_u32 as u64;
+                *filled += step;
+                *filled
+            }
+            None => {
+                let bar = BarBuilder::open_bar(trade.ts_event_unix_ns, trade);
+                self.bars.insert(trade.symbol.clone(), (bar, step));
+                step
+            }
+        };
+        if filled >= self.target {
+            return self.bars.remove(&trade.symbol).map(|(bar, _)| bar);
+        }
+        None
+    }
+
+    /// Return every bar in progress, short of its threshold, ordered by open
+    /// time.
+    pub fn flush(&mut self) -> Vec<OhlcvRecord> {
+        let mut bars: Vec<OhlcvRecord> = self.bars.drain().map(|(_, (bar, _))| bar).collect();
+        sort_bars(&mut bars);
+        bars
+    }
+}
+
+/// Aggregate trades into [`BarSpec`] bars. Trades should be in timestamp
+/// order per symbol. Each symbol's last bar may be short of the threshold.
+pub fn threshold_bars(trades: &[TradeRecord], spec: BarSpec) -> Vec<OhlcvRecord> {
+    let mut builder = ThresholdBarBuilder::new(spec);
+    let mut bars: Vec<OhlcvRecord> = trades.iter().filter_map(|t| builder.push(t)).collect();
+    bars.extend(builder.flush());
+    sort_bars(&mut bars);
+    bars
+}
+
+/// Order bars by open time, then symbol.
+fn sort_bars(bars: &mut [OhlcvRecord]) {
+    bars.sort_by(|a, b| {
+        a.ts_event_unix_ns
+            .cmp(&b.ts_event_unix_ns)
+            .then_with(|| a.symbol.cmp(&b.symbol))
+    });
+}
+
+/// Signed size of a trade: positive when it lifted the offer, negative when
+/// it hit the bid, and zero when it printed inside the spread or carries no
+/// quote.
+pub fn signed_volume(trade: &TradeRecord) -> i64 {
+    match trade.quote.map(|q| q.side) {
+        Some(TradeSide::Ask) => trade.size_u32 as i64,
+        Some(TradeSide::Bid) => -(trade.size_u32 as i64),
+        Some(TradeSide::Mid) | None => 0,
+    }
+}
+
+/// Build cumulative volume delta (CVD) bars `interval_ns` wide.
+///
+/// The running buy-minus-sell volume starts at zero for each symbol and
+/// carries across bars: each bar opens at the previous bar's close and its
+/// high/low/close track the running total. Values are scaled by
+/// [`PRICE_SCALE`] so the series reads like any other OHLCV price series;
+/// `volume_u64` is the bar's total traded volume.
+///
+/// Trades should be in timestamp order and side-tagged via `quote`.
+pub fn cumulative_delta(
+    trades: &[TradeRecord],
+    interval_ns: u64,
+    align: BarAlign,
+) -> Vec<OhlcvRecord> {
+    let mut buckets = BarBuckets::new(interval_ns, align);
+    let mut totals: HashMap<&str, i64> = HashMap::new();
+    let mut open: HashMap<&str, OhlcvRecord> = HashMap::new();
+    let mut bars = Vec::new();
+
+    for trade in trades {
+        let bucket = buckets.start(trade.ts_event_unix_ns);
+        let total = totals.entry(&trade.symbol).or_insert(0);
+        let before = total.saturating_mul(PRICE_SCALE);
+        *total += signed_volume(trade);
+        let after = total.saturating_mul(PRICE_SCALE);
+
+        match open.get_mut(trade.symbol.as_str()) {
+            Some(bar) if bucket <= bar.ts_event_unix_ns => {
+                bar.high_i64 = bar.high_i64.max(after);
+                bar.low_i64 = bar.low_i64.min(after);
+                bar.close_i64 = after;
+                bar.volume_u64 += trade.size_u32 as u64;
+            }
+            slot => {
+                let bar = OhlcvRecord {
+                    ts_event_unix_ns: bucket,
+                    symbol: trade.symbol.clone(),
+                    open_i64: before,
+                    high_i64: before.max(after),
+                    low_i64: before.min(after),
+                    close_i64: after,
+                    volume_u64: trade.size_u32 as u64,
+                };
+                match slot {
+                    Some(prev) => bars.push(std::mem::replace(prev, bar)),
+                    None => {
+                        open.insert(&trade.symbol, bar);
+                    }
+                }
+            }
+        }
+    }
+
+    bars.extend(open.into_values());
+    bars.sort_by(|a, b| {
+        a.ts_event_unix_ns
+            .cmp(&b.ts_event_unix_ns)
+            .then_with(|| a.symbol.cmp(&b.symbol))
+    });
+    bars
+}
+
+/// Merge consecutive bars so that each symbol has at most `max_points` bars.
+///
+/// Every merged bar takes the open of its first input bar, the close of its
+/// last, the extreme high/low, and the summed volume, so the shape of the
+/// series is preserved at a coarser resolution. Series already within budget
+/// are returned unchanged.
+pub fn downsample_bars(bars: &[OhlcvRecord], max_points: usize) -> Vec<OhlcvRecord> {
+    if max_points == 0 {
+        return Vec::new();
+    }
+
+    let mut by_symbol: Vec<(&str, Vec<&OhlcvRecord>)> = Vec::new();
+    for bar in bars {
+        match by_symbol.iter_mut().find(|(s, _)| *s == bar.symbol) {
+            Some((_, series)) => series.push(bar),
+            None => by_symbol.push((&bar.symbol, vec![bar])),
+        }
+    }
+
+    let mut out = Vec::with_capacity(bars.len().min(max_points * by_symbol.len()));
+    for (_, series) in by_symbol {
+        let group = series.len().div_ceil(max_points);
+        for chunk in series.chunks(group) {
+            out.push(merge_bars(chunk));
+        }
+    }
+
+    out.sort_by(|a, b| {
+        a.ts_event_unix_ns
+            .cmp(&b.ts_event_unix_ns)
+            .then_with(|| a.symbol.cmp(&b.symbol))
+    });
+    out
+}
+
+/// Merge bars into wider bars of `interval_ns`, starting on whole multiples
+/// of it, per symbol. Bars must be time-ordered.
+pub fn resample_bars(bars: &[OhlcvRecord], interval_ns: u64) -> Vec<OhlcvRecord> {
+    // symbol -> (bucket start, bars in it)
+    let mut pending: HashMap<&str, (u64, Vec<&OhlcvRecord>)> = HashMap::new();
+    let mut out = Vec::new();
+    for bar in bars {
+        let start = bar.ts_event_unix_ns - bar.ts_event_unix_ns % interval_ns;
+        let (bucket, chunk) = pending
+            .entry(bar.symbol.as_str())
+            .or_insert((start, Vec::new()));
+        if *bucket != start && !chunk.is_empty() {
+            out.push(OhlcvRecord {
+                ts_event_unix_ns: *bucket,
+                ..merge_bars(chunk)
+            });
+            chunk.clear();
+        }
+        *bucket = start;
+        chunk.push(bar);
+    }
+    out.extend(
+        pending
+            .into_values()
+            .filter(|(_, chunk)| !chunk.is_empty())
+            .map(|(bucket, chunk)| OhlcvRecord {
+                ts_event_unix_ns: bucket,
+                ..merge_bars(&chunk)
+            }),
+    );
+    sort_bars(&mut out);
+    out
+}
+
+/// Merge a non-empty run of bars into one.
+fn merge_bars(chunk: &[&OhlcvRecord]) -> OhlcvRecord {
+    let first = chunk[0];
+    let last = chunk[chunk.len() - 1];
+    OhlcvRecord {
+        ts_event_unix_ns: first.ts_event_unix_ns,
+        symbol: first.symbol.clone(),
+        open_i64: first.open_i64,
+        high_i64: chunk
+            .iter()
+            .map(|b| b.high_i64)
+            .max()
+            .unwrap_or(first.high_i64),
+        low_i64: chunk
+            .iter()
+            .map(|b| b.low_i64)
+            .min()
+            .unwrap_or(first.low_i64),
+        close_i64: last.close_i64,
+        volume_u64: chunk.iter().map(|b| b.volume_u64).sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QuoteAtTrade;
+    use std::str::FromStr;
+
+    fn trade(ts: u64, price: i64, size: u32) -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ES.FUT".to_string(),
+            price_i64: price,
+            size_u32: size,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_trades_into_bars() {
+        let trades = vec![
+            trade(0, 100, 1),
+            trade(10 * NANOS_PER_SECOND, 105, 2),
+            trade(30 * NANOS_PER_SECOND, 95, 3),
+            trade(NANOS_PER_MINUTE, 101, 4),
+        ];
+
+        let bars = aggregate_trades(&trades, NANOS_PER_MINUTE);
+        assert_eq!(bars.len(), 2);
+
+        assert_eq!(bars[0].ts_event_unix_ns, 0);
+        assert_eq!(bars[0].open_i64, 100);
+        assert_eq!(bars[0].high_i64, 105);
+        assert_eq!(bars[0].low_i64, 95);
+        assert_eq!(bars[0].close_i64, 95);
+        assert_eq!(bars[0].volume_u64, 6);
+
+        assert_eq!(bars[1].ts_event_unix_ns, NANOS_PER_MINUTE);
+        assert_eq!(bars[1].volume_u64, 4);
+    }
+
+    #[test]
+    fn test_bar_builder_returns_closed_bar() {
+        let mut builder = BarBuilder::new(NANOS_PER_SECOND);
+        assert!(builder.push(&trade(100, 10, 1)).is_none());
+        assert!(builder.push(&trade(200, 12, 1)).is_none());
+
+        let closed = builder.push(&trade(NANOS_PER_SECOND + 1, 11, 1)).unwrap();
+        assert_eq!(closed.close_i64, 12);
+        assert_eq!(builder.current("ES.FUT").unwrap().open_i64, 11);
+    }
+
+    #[test]
+    fn test_session_aligned_bars() {
+        const HOUR: u64 = 60 * NANOS_PER_MINUTE;
+        // 2024-07-01 08:30 CDT = 13:30 UTC
+        let open = 1_719_840_600 * NANOS_PER_SECOND;
+        let align: BarAlign = "session 08:30 America/Chicago".parse().unwrap();
+        let mut buckets = BarBuckets::new(HOUR, align);
+
+        assert_eq!(buckets.start(open), open);
+        assert_eq!(buckets.start(open + 90 * NANOS_PER_MINUTE), open + HOUR);
+        // Before the open, bars count from the previous day's session
+        assert_eq!(buckets.start(open - NANOS_PER_MINUTE), open - HOUR);
+        // The next session restarts the grid even though 24h isn't a
+        // multiple of the bar width
+        let mut buckets = BarBuckets::new(7 * HOUR, align);
+        assert_eq!(buckets.start(open + 24 * HOUR + 1), open + 24 * HOUR);
+
+        let epoch = BarBuckets::new(HOUR, BarAlign::Epoch).start(open);
+        assert_eq!(epoch, open - 30 * NANOS_PER_MINUTE);
+        let mut shifted = BarBuckets::new(HOUR, "offset 30m".parse().unwrap());
+        assert_eq!(shifted.start(open + 1), open);
+
+        assert_eq!(
+            "session cme".parse::<BarAlign>().unwrap(),
+            "session 17:00 America/Chicago".parse().unwrap()
+        );
+        assert!("session 8h".parse::<BarAlign>().is_err());
+        assert!("midnight".parse::<BarAlign>().is_err());
+    }
+
+    #[test]
+    fn test_threshold_bars() {
+        let trades: Vec<_> = (0..7)
+            .map(|i| trade(i * NANOS_PER_SECOND, 100 + i as i64, 2))
+            .collect();
+
+        let ticks = threshold_bars(&trades, "tickbar-3".parse().unwrap());
+        assert_eq!(ticks.len(), 3);
+        assert_eq!((ticks[0].open_i64, ticks[0].close_i64), (100, 102));
+        assert_eq!(ticks[1].ts_event_unix_ns, 3 * NANOS_PER_SECOND);
+        // The last bar is short of its threshold
+        assert_eq!(ticks[2].volume_u64, 2);
+
+        // 5 contracts closes on the third trade, overshooting to 6
+        let volume = threshold_bars(&trades, "volbar-5".parse().unwrap());
+        assert_eq!(volume[0].volume_u64, 6);
+
+        let priced = |price: f64, size| TradeRecord {
+            price_i64: crate::price::from_f64(price),
+            ..trade(0, 0, size)
+        };
+        let notional = [priced(4_000.0, 1), priced(4_000.0, 2), priced(4_000.0, 1)];
+        let dollars = threshold_bars(&notional, "dollarbar-10000".parse().unwrap());
+        assert_eq!(dollars.len(), 2);
+        assert_eq!(dollars[0].volume_u64, 3);
+
+        assert_eq!(
+            BarSpec::from_str("volbar-5").unwrap().to_string(),
+            "volbar-5"
+        );
+        assert!(BarSpec::from_str("tickbar-0").is_err());
+        assert!(BarSpec::from_str("rangebar-5").is_err());
+    }
+
+    #[test]
+    fn test_cumulative_delta_carries_across_bars() {
+        let tagged = |ts, size, side| TradeRecord {
+            quote: Some(QuoteAtTrade {
+                bid_px_i64: 99,
+                ask_px_i64: 101,
+                side,
+            }),
+            ..trade(ts, 100, size)
+        };
+        let trades = vec![
+            tagged(0, 5, TradeSide::Ask),
+            tagged(NANOS_PER_SECOND, 8, TradeSide::Bid),
+            tagged(2 * NANOS_PER_SECOND, 4, TradeSide::Mid),
+            tagged(NANOS_PER_MINUTE, 10, TradeSide::Ask),
+            trade(NANOS_PER_MINUTE + 1, 100, 7),
+        ];
+
+        let bars = cumulative_delta(&trades, NANOS_PER_MINUTE, BarAlign::Epoch);
+        assert_eq!(bars.len(), 2);
+
+        let scaled = |v: i64| v * PRICE_SCALE;
+        assert_eq!(bars[0].open_i64, 0);
+        assert_eq!(bars[0].high_i64, scaled(5));
+        assert_eq!(bars[0].low_i64, scaled(-3));
+        assert_eq!(bars[0].close_i64, scaled(-3));
+        assert_eq!(bars[0].volume_u64, 17);
+
+        assert_eq!(bars[1].open_i64, scaled(-3));
+        assert_eq!(bars[1].close_i64, scaled(7));
+        assert_eq!(bars[1].volume_u64, 17);
+    }
+
+    #[test]
+    fn test_downsample_bars_preserves_shape() {
+        let trades: Vec<_> = (0..10)
+            .map(|i| trade(i * NANOS_PER_SECOND, 100 + i as i64, 1))
+            .collect();
+        let bars = aggregate_trades(&trades, NANOS_PER_SECOND);
+
+        let down = downsample_bars(&bars, 3);
+        assert_eq!(down.len(), 3);
+        assert_eq!(down[0].open_i64, 100);
+        assert_eq!(down[2].close_i64, 109);
+        assert_eq!(down.iter().map(|b| b.volume_u64).sum::<u64>(), 10);
+
+        assert_eq!(downsample_bars(&bars, 100).len(), 10);
+    }
+
+    #[test]
+    fn test_resample_bars() {
+        let trades: Vec<_> = (0..150)
+            .map(|i| trade(i * NANOS_PER_SECOND, 100 + i as i64, 1))
+            .collect();
+        let seconds = aggregate_trades(&trades, NANOS_PER_SECOND);
+
+        let minutes = resample_bars(&seconds, NANOS_PER_MINUTE);
+        assert_eq!(minutes.len(), 3);
+        assert_eq!(minutes[1].ts_event_unix_ns, NANOS_PER_MINUTE);
+        assert_eq!((minutes[1].open_i64, minutes[1].close_i64), (160, 219));
+        assert_eq!(minutes[2].volume_u64, 30);
+        assert_eq!(minutes, aggregate_trades(&trades, NANOS_PER_MINUTE));
+    }
+
+    #[test]
+    fn test_closed_bar_feed_orders_across_symbols() {
+        let at = |symbol: &str, ts: u64, price: i64| TradeRecord {
+            symbol: symbol.to_string(),
+            ..trade(ts, price, 1)
+        };
+        let mut feed = ClosedBarFeed::new(NANOS_PER_MINUTE, BarAlign::Epoch);
+        assert!(feed
+            .push(&at("NQ.FUT", 5 * NANOS_PER_SECOND, 200))
+            .is_empty());
+        assert!(feed
+            .push(&at("ES.FUT", 10 * NANOS_PER_SECOND, 100))
+            .is_empty());
+
+        // The first trade of the next minute closes both symbols' bars
+        let closed = feed.push(&at("ES.FUT", 61 * NANOS_PER_SECOND, 101));
+        let symbols: Vec<_> = closed.iter().map(|b| b.symbol.as_str()).collect();
+        assert_eq!(symbols, ["ES.FUT", "NQ.FUT"]);
+        assert!(closed.iter().all(|b| b.ts_event_unix_ns == 0));
+
+        // A late trade for the closed minute is dropped, not reopened
+        assert!(feed
+            .push(&at("NQ.FUT", 59 * NANOS_PER_SECOND, 199))
+            .is_empty());
+        assert_eq!(feed.dropped(), 1);
+        let rest = feed.flush();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].ts_event_unix_ns, NANOS_PER_MINUTE);
+    }
+
+    #[test]
+    fn test_bar_fanout_rolls_up_the_finest_width() {
+        let widths = [NANOS_PER_SECOND, NANOS_PER_MINUTE, 5 * NANOS_PER_MINUTE];
+        let mut fanout = BarFanout::from_trades(&widths, BarAlign::Epoch).unwrap();
+        let mut bars = Vec::new();
+        for second in [0, 30, 59, 60, 299, 300] {
+            let price = 100 + second as i64;
+            bars.extend(fanout.push_trade(&trade(second * NANOS_PER_SECOND, price, 1)));
+        }
+        bars.extend(fanout.flush());
+
+        let of = |width: u64| -> Vec<(u64, i64, u64)> {
+            bars.iter()
+                .filter(|(w, _)| *w == width)
+                .map(|(_, b)| {
+                    (
+                        b.ts_event_unix_ns / NANOS_PER_SECOND,
+                        b.close_i64,
+                        b.volume_u64,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(of(NANOS_PER_SECOND).len(), 6);
+        assert_eq!(
+            of(NANOS_PER_MINUTE),
+            [(0, 159, 3), (60, 160, 1), (240, 399, 1), (300, 400, 1)]
+        );
+        assert_eq!(of(5 * NANOS_PER_MINUTE), [(0, 399, 5), (300, 400, 1)]);
+        // A 1m bar closes once a 1s bar of the next minute has
+        let first_minute = bars
+            .iter()
+            .position(|(w, _)| *w == NANOS_PER_MINUTE)
+            .unwrap();
+        assert_eq!(bars[first_minute - 1].1.ts_event_unix_ns, NANOS_PER_MINUTE);
+
+        // Upstream bars only feed the coarser widths
+        let mut fanout =
+            BarFanout::from_bars(NANOS_PER_SECOND, &widths[1..], BarAlign::Epoch).unwrap();
+        let bar = |second: u64| OhlcvRecord {
+            ts_event_unix_ns: second * NANOS_PER_SECOND,
+            symbol: "ES.FUT".to_string(),
+            open_i64: 1,
+            high_i64: 2,
+            low_i64: 1,
+            close_i64: 2,
+            volume_u64: 1,
+        };
+        assert!(fanout.push_bar(&bar(0)).is_empty());
+        assert!(fanout.push_trade(&trade(1, 1, 1)).is_empty());
+        let closed = fanout.push_bar(&bar(60));
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].0, closed[0].1.volume_u64), (NANOS_PER_MINUTE, 1));
+
+        assert!(BarFanout::from_trades(
+            &[NANOS_PER_MINUTE, 90 * NANOS_PER_SECOND],
+            BarAlign::Epoch
+        )
+        .is_err());
+        assert!(
+            BarFanout::from_bars(NANOS_PER_MINUTE, &[NANOS_PER_SECOND], BarAlign::Epoch).is_err()
+        );
+    }
+}
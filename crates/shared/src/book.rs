@@ -0,0 +1,507 @@
+//! Limit order books rebuilt from market-by-order (MBO) events.
+//!
+//! An [`OrderBook`] keeps every resting order of one instrument and the
+//! price levels they make up, applying each [`MboRecord`] as the venue
+//! reported it: adds, cancels (partial or whole), modifies and clears.
+//! Trades and fills leave the book alone; the venue follows a fill with the
+//! cancel or modify that takes the size off. A [`BookSet`] holds one book
+//! per instrument, for feeds of several.
+//!
+//! A book is only as good as the events it has seen, so it must start from
+//! a clear: the provider's snapshot at the start of the day, or of a live
+//! session. Events for orders the book doesn't know are counted in
+//! [`OrderBook::unknown_orders`] rather than failing.
+//!
+//! A [`BookSampler`] takes the books at a fixed cadence as events are
+//! replayed through it, for `POST /api/book/sample`.
+
+use crate::depth::DepthLevel;
+use crate::SymbolType;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// What an MBO event did.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MboAction {
+    /// A new order rests in the book
+    Add,
+    /// An order's size shrank by the event's, or it left the book
+    Cancel,
+    /// An order moved to the event's price and size
+    Modify,
+    /// Every order left the book
+    Clear,
+    /// An aggressing order traded
+    Trade,
+    /// A resting order was filled
+    Fill,
+    /// Nothing changed, e.g. a record carrying only flags
+    None,
+}
+
+/// Side of the book an order rests on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSide {
+    Bid,
+    Ask,
+    None,
+}
+
+/// One MBO event.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MboRecord {
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    pub order_id_u64: u64,
+    pub price_i64: i64,
+    pub size_u32: u32,
+    pub action: MboAction,
+    pub side: BookSide,
+    /// The venue's message sequence number
+    pub sequence_u32: u32,
+    /// The last record of a venue event: the book is consistent only after
+    /// one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub last: bool,
+    /// The provider may have missed events for this book
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub maybe_bad_book: bool,
+}
+
+/// One price level of one side.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BookLevel {
+    pub price_i64: i64,
+    pub size_u64: u64,
+    /// Orders resting at the price
+    pub orders_u32: u32,
+}
+
+/// The top of one instrument's book.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BookView {
+    pub symbol: String,
+    /// The last event applied
+    pub ts_event_unix_ns: u64,
+    /// Best (highest) first
+    pub bids: Vec<BookLevel>,
+    /// Best (lowest) first
+    pub asks: Vec<BookLevel>,
+    /// Orders resting on both sides, at every level
+    pub orders_u64: u64,
+}
+
+/// The books a symbol's instruments had at a time, from `GET /api/book`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BookResponse {
+    /// As requested, e.g. a parent symbol whose contracts each have a book
+    pub symbol: String,
+    pub at_unix_ns: u64,
+    /// Events replayed since the day's snapshot
+    pub events_u64: u64,
+    /// By symbol
+    pub books: Vec<BookView>,
+}
+
+/// A request for a symbol's books every `interval` over a range, from
+/// `POST /api/book/sample`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BookSampleRequest {
+    pub symbol: String,
+    #[serde(default)]
+    pub stype_in: SymbolType,
+    /// The first sample's time; times as a historical request's
+    pub start_rfc3339: String,
+    /// The last sample is at or before this
+    pub end_rfc3339: String,
+    /// Between samples, e.g. "1s" or "5m" (default: "1s")
+    #[serde(default = "default_sample_interval")]
+    pub interval: String,
+    /// Levels per side (default 10)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+}
+
+fn default_sample_interval() -> String {
+    "1s".to_string()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Order {
+    side: BookSide,
+    price_i64: i64,
+    size_u32: u32,
+}
+
+/// One instrument's book.
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    orders: HashMap<u64, Order>,
+    bids: BTreeMap<i64, BookLevel>,
+    asks: BTreeMap<i64, BookLevel>,
+    ts_event_unix_ns: u64,
+    unknown_orders: u64,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply one event.
+    pub fn apply(&mut self, record: &MboRecord) {
+        self.ts_event_unix_ns = record.ts_event_unix_ns;
+        match record.action {
+            MboAction::Add => self.add(record.order_id_u64, order(record)),
+            MboAction::Cancel => {
+                let Some(resting) = self.remove(record.order_id_u64) else {
+                    self.unknown_orders += 1;
+                    return;
+                };
+                if let Some(size_u32) = resting.size_u32.checked_sub(record.size_u32) {
+                    if size_u32 > 0 {
+                        self.add(
+                            record.order_id_u64,
+                            Order {
+                                size_u32,
+                                ..resting
+                            },
+                        );
+                    }
+                }
+            }
+            MboAction::Modify => {
+                // A modify for an order the book missed adds it, as the
+                // provider advises
+                if self.remove(record.order_id_u64).is_none() {
+                    self.unknown_orders += 1;
+                }
+                self.add(record.order_id_u64, order(record));
+            }
+            MboAction::Clear => {
+                self.orders.clear();
+                self.bids.clear();
+                self.asks.clear();
+            }
+            MboAction::Trade | MboAction::Fill | MboAction::None => {}
+        }
+    }
+
+    fn levels(&mut self, side: BookSide) -> Option<&mut BTreeMap<i64, BookLevel>> {
+        match side {
+            BookSide::Bid => Some(&mut self.bids),
+            BookSide::Ask => Some(&mut self.asks),
+            BookSide::None => None,
+        }
+    }
+
+    fn add(&mut self, order_id: u64, order: Order) {
+        if order.size_u32 == 0 {
+            return;
+        }
+        let Some(levels) = self.levels(order.side) else {
+            return;
+        };
+        let level = levels.entry(order.price_i64).or_insert(BookLevel {
+            price_i64: order.price_i64,
+            ..Default::default()
+        });
+        level.size_u64 += u64::from(order.size_u32);
+        level.orders_u32 += 1;
+        if let Some(replaced) = self.orders.insert(order_id, order) {
+            self.take(replaced);
+        }
+    }
+
+    fn remove(&mut self, order_id: u64) -> Option<Order> {
+        let order = self.orders.remove(&order_id)?;
+        self.take(order);
+        Some(order)
+    }
+
+    /// Take `order` off its level.
+    fn take(&mut self, order: Order) {
+        let Some(levels) = self.levels(order.side) else {
+            return;
+        };
+        if let Some(level) = levels.get_mut(&order.price_i64) {
+            level.size_u64 = level.size_u64.saturating_sub(u64::from(order.size_u32));
+            level.orders_u32 = level.orders_u32.saturating_sub(1);
+            if level.orders_u32 == 0 {
+                levels.remove(&order.price_i64);
+            }
+        }
+    }
+
+    /// The best `depth` levels of each side.
+    pub fn view(&self, symbol: &str, depth: usize) -> BookView {
+        BookView {
+            symbol: symbol.to_string(),
+            ts_event_unix_ns: self.ts_event_unix_ns,
+            bids: self.bids.values().rev().take(depth).copied().collect(),
+            asks: self.asks.values().take(depth).copied().collect(),
+            orders_u64: self.orders.len() as u64,
+        }
+    }
+
+    /// The best `depth` levels of each side side by side, as an mbp-10
+    /// book has them.
+    pub fn depth_levels(&self, depth: usize) -> Vec<DepthLevel> {
+        let mut bids = self.bids.values().rev();
+        let mut asks = self.asks.values();
+        (0..depth)
+            .map_while(|_| {
+                let (bid, ask) = (bids.next(), asks.next());
+                (bid.is_some() || ask.is_some()).then(|| {
+                    let (bid, ask) = (
+                        bid.copied().unwrap_or_default(),
+                        ask.copied().unwrap_or_default(),
+                    );
+                    DepthLevel {
+                        bid_px_i64: bid.price_i64,
+                        ask_px_i64: ask.price_i64,
+                        bid_sz_u32: bid.size_u64.min(u64::from(u32::MAX)) as u32,
+                        ask_sz_u32: ask.size_u64.min(u64::from(u32::MAX)) as u32,
+                        bid_ct_u32: bid.orders_u32,
+                        ask_ct_u32: ask.orders_u32,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Events for orders the book didn't know: cancels dropped and modifies
+    /// taken as adds. Nonzero means it missed some.
+    pub fn unknown_orders(&self) -> u64 {
+        self.unknown_orders
+    }
+}
+
+fn order(record: &MboRecord) -> Order {
+    Order {
+        side: record.side,
+        price_i64: record.price_i64,
+        size_u32: record.size_u32,
+    }
+}
+
+/// Books by instrument.
+#[derive(Debug, Default)]
+pub struct BookSet {
+    books: HashMap<String, OrderBook>,
+    events: u64,
+}
+
+impl BookSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply one event to its instrument's book, returning the book.
+    pub fn apply(&mut self, record: &MboRecord) -> &OrderBook {
+        self.events += 1;
+        let book = self.books.entry(record.symbol.clone()).or_default();
+        book.apply(record);
+        book
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(symbol)
+    }
+
+    /// The best `depth` levels of every book, by symbol.
+    pub fn views(&self, depth: usize) -> Vec<BookView> {
+        let mut views: Vec<BookView> = self
+            .books
+            .iter()
+            .map(|(symbol, book)| book.view(symbol, depth))
+            .collect();
+        views.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        views
+    }
+
+    /// Events applied so far.
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+}
+
+/// Books taken every `interval_ns` from `start_unix_ns` to `end_unix_ns`
+/// as events are replayed through it. A sample at a time has every event
+/// up to and including that time.
+#[derive(Debug)]
+pub struct BookSampler {
+    symbol: String,
+    books: BookSet,
+    next_unix_ns: u64,
+    end_unix_ns: u64,
+    interval_ns: u64,
+    depth: usize,
+}
+
+impl BookSampler {
+    /// Samples labelled `symbol`, as requested, `depth` levels deep.
+    pub fn new(
+        symbol: &str,
+        start_unix_ns: u64,
+        end_unix_ns: u64,
+        interval_ns: u64,
+        depth: usize,
+    ) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            books: BookSet::new(),
+            next_unix_ns: start_unix_ns,
+            end_unix_ns,
+            interval_ns: interval_ns.max(1),
+            depth,
+        }
+    }
+
+    /// Apply one event, returning the samples due before it.
+    pub fn apply(&mut self, record: &MboRecord) -> Vec<BookResponse> {
+        let samples = self.take_until(record.ts_event_unix_ns.saturating_sub(1));
+        self.books.apply(record);
+        samples
+    }
+
+    /// The samples left once the events run out.
+    pub fn finish(&mut self) -> Vec<BookResponse> {
+        self.take_until(self.end_unix_ns)
+    }
+
+    /// Events applied so far.
+    pub fn events(&self) -> u64 {
+        self.books.events()
+    }
+
+    fn take_until(&mut self, until_unix_ns: u64) -> Vec<BookResponse> {
+        let mut samples = Vec::new();
+        while self.next_unix_ns <= until_unix_ns.min(self.end_unix_ns) {
+            samples.push(BookResponse {
+                symbol: self.symbol.clone(),
+                at_unix_ns: self.next_unix_ns,
+                events_u64: self.books.events(),
+                books: self.books.views(self.depth),
+            });
+            self.next_unix_ns = self.next_unix_ns.saturating_add(self.interval_ns);
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(
+        action: MboAction,
+        order_id_u64: u64,
+        side: BookSide,
+        price: i64,
+        size: u32,
+    ) -> MboRecord {
+        MboRecord {
+            ts_event_unix_ns: order_id_u64,
+            symbol: "ESZ4".to_string(),
+            order_id_u64,
+            price_i64: price,
+            size_u32: size,
+            action,
+            side,
+            sequence_u32: 0,
+            last: true,
+            maybe_bad_book: false,
+        }
+    }
+
+    #[test]
+    fn test_order_book() {
+        use BookSide::{Ask, Bid};
+        use MboAction::*;
+
+        let mut books = BookSet::new();
+        for record in [
+            event(Add, 1, Bid, 100, 5),
+            event(Add, 2, Bid, 100, 3),
+            event(Add, 3, Bid, 99, 7),
+            event(Add, 4, Ask, 101, 2),
+            // Partial cancel, then a fill that leaves the book alone
+            event(Cancel, 1, Bid, 100, 2),
+            event(Fill, 4, Ask, 101, 1),
+            // Moves to a new price
+            event(Modify, 3, Bid, 98, 7),
+            // Unknown orders: a cancel is dropped, a modify adds
+            event(Cancel, 9, Ask, 102, 1),
+            event(Modify, 10, Ask, 103, 4),
+        ] {
+            books.apply(&record);
+        }
+
+        let book = books.get("ESZ4").unwrap();
+        let view = book.view("ESZ4", 10);
+        assert_eq!(
+            view.bids,
+            vec![
+                BookLevel {
+                    price_i64: 100,
+                    size_u64: 6,
+                    orders_u32: 2
+                },
+                BookLevel {
+                    price_i64: 98,
+                    size_u64: 7,
+                    orders_u32: 1
+                },
+            ]
+        );
+        assert_eq!(
+            view.asks
+                .iter()
+                .map(|level| level.price_i64)
+                .collect::<Vec<_>>(),
+            vec![101, 103]
+        );
+        assert_eq!((view.orders_u64, book.unknown_orders()), (5, 2));
+        assert_eq!(book.view("ESZ4", 1).bids.len(), 1);
+
+        let levels = book.depth_levels(10);
+        assert_eq!(levels.len(), 2);
+        assert_eq!((levels[0].bid_sz_u32, levels[0].bid_ct_u32), (6, 2));
+        assert_eq!((levels[1].ask_px_i64, levels[1].ask_sz_u32), (103, 4));
+
+        // A cancel of the whole size empties the level
+        books.apply(&event(Cancel, 3, Bid, 98, 7));
+        assert_eq!(books.get("ESZ4").unwrap().view("ESZ4", 10).bids.len(), 1);
+        books.apply(&event(Clear, 0, BookSide::None, 0, 0));
+        let view = books.views(10).remove(0);
+        assert!(view.bids.is_empty() && view.asks.is_empty());
+        assert_eq!((view.orders_u64, books.events()), (0, 11));
+    }
+
+    #[test]
+    fn test_sampler_takes_books_at_each_interval() {
+        use BookSide::Bid;
+        use MboAction::Add;
+
+        // Samples at 10, 20 and 30; the events at 5 and 20 are in the
+        // samples at their times, and none come before the start
+        let mut sampler = BookSampler::new("ES.FUT", 10, 35, 10, 5);
+        assert!(sampler.apply(&event(Add, 5, Bid, 100, 1)).is_empty());
+        let samples = sampler.apply(&event(Add, 20, Bid, 99, 2));
+        assert_eq!(samples.len(), 1);
+        assert_eq!((samples[0].at_unix_ns, samples[0].events_u64), (10, 1));
+        assert_eq!(samples[0].books[0].bids.len(), 1);
+
+        let samples = sampler.finish();
+        assert_eq!(
+            samples.iter().map(|s| s.at_unix_ns).collect::<Vec<_>>(),
+            vec![20, 30]
+        );
+        assert_eq!(samples[0].books[0].bids.len(), 2);
+        assert_eq!(samples[0].symbol, "ES.FUT");
+        assert!(sampler.finish().is_empty());
+    }
+}
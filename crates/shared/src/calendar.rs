@@ -0,0 +1,262 @@
+//! Economic calendar events, for chart annotations and live alerts.
+//!
+//! A calendar is loaded from an iCalendar (`.ics`) feed or a JSON array of
+//! `{"time": "<RFC3339>", "title": "CPI", "country": "US", "impact": "high"}`
+//! entries. Live sockets that ask for it get a `calendar_event` message a
+//! lead time before each release and another at the release itself.
+
+use crate::sessions;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// A scheduled release.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CalendarEvent {
+    /// Release time
+    pub ts_event_unix_ns: u64,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Expected market impact, as given by the source (e.g. "high")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impact: Option<String>,
+}
+
+/// Which alert a `calendar_event` message is.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CalendarStage {
+    /// The release is due within the lead time
+    Upcoming,
+    /// The release time has arrived
+    Released,
+}
+
+/// A live alert for a calendar event.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CalendarAlert {
+    pub stage: CalendarStage,
+    #[serde(flatten)]
+    pub event: CalendarEvent,
+}
+
+/// One entry of a JSON calendar.
+#[derive(Deserialize)]
+struct JsonEntry {
+    time: String,
+    title: String,
+    #[serde(default)]
+    country: Option<String>,
+    #[serde(default)]
+    impact: Option<String>,
+}
+
+fn unix_ns(time: DateTime<Utc>) -> Option<u64> {
+    time.timestamp_nanos_opt()
+        .and_then(|ns| u64::try_from(ns).ok())
+}
+
+/// Parse a calendar in either format, sorted by release time.
+pub fn parse(text: &str) -> Result<Vec<CalendarEvent>, String> {
+    let mut events = if text.trim_start().starts_with("BEGIN:VCALENDAR") {
+        parse_ics(text)?
+    } else {
+        parse_json(text)?
+    };
+    events.sort_by_key(|e| e.ts_event_unix_ns);
+    Ok(events)
+}
+
+fn parse_json(text: &str) -> Result<Vec<CalendarEvent>, String> {
+    let entries: Vec<JsonEntry> =
+        serde_json::from_str(text).map_err(|e| format!("bad JSON calendar: {}", e))?;
+    entries
+        .into_iter()
+        .map(|entry| {
+            let ts_event_unix_ns = DateTime::parse_from_rfc3339(&entry.time)
+                .ok()
+                .and_then(|t| unix_ns(t.with_timezone(&Utc)))
+                .ok_or_else(|| format!("'{}': bad time '{}'", entry.title, entry.time))?;
+            Ok(CalendarEvent {
+                ts_event_unix_ns,
+                title: entry.title,
+                country: entry.country,
+                impact: entry.impact,
+            })
+        })
+        .collect()
+}
+
+/// Events of an iCalendar feed: each `VEVENT`'s `DTSTART` and `SUMMARY`.
+/// Start times may be UTC, carry a `TZID`, or be whole dates (midnight
+/// UTC); floating times are read as UTC.
+fn parse_ics(text: &str) -> Result<Vec<CalendarEvent>, String> {
+    // Unfold continuation lines, which start with a space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<(Option<u64>, Option<String>)> = None;
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = name.split(';');
+        let property = params.next().unwrap_or_default().to_ascii_uppercase();
+        match (property.as_str(), &mut current) {
+            ("BEGIN", _) if value.eq_ignore_ascii_case("VEVENT") => current = Some((None, None)),
+            ("END", Some((start, summary))) if value.eq_ignore_ascii_case("VEVENT") => {
+                let title = summary.take().unwrap_or_default();
+                let ts_event_unix_ns =
+                    start.ok_or_else(|| format!("event '{}' has no DTSTART", title))?;
+                events.push(CalendarEvent {
+                    ts_event_unix_ns,
+                    title,
+                    country: None,
+                    impact: None,
+                });
+                current = None;
+            }
+            ("DTSTART", Some((start, _))) => {
+                let tzid = params.find_map(|p| p.strip_prefix("TZID="));
+                *start = Some(ics_time(value, tzid)?);
+            }
+            ("SUMMARY", Some((_, summary))) => *summary = Some(ics_text(value)),
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+fn ics_time(value: &str, tzid: Option<&str>) -> Result<u64, String> {
+    let invalid = || format!("bad DTSTART '{}'", value);
+    let utc = if let Some(utc) = value.strip_suffix('Z') {
+        NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .map_err(|_| invalid())?
+            .and_utc()
+    } else if let Ok(local) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        match tzid {
+            Some(name) => {
+                let tz: Tz = name
+                    .trim_matches('"')
+                    .parse()
+                    .map_err(|_| format!("unknown TZID '{}'", name))?;
+                sessions::to_utc(local, tz).ok_or_else(invalid)?
+            }
+            None => local.and_utc(),
+        }
+    } else {
+        NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map_err(|_| invalid())?
+            .and_hms_opt(0, 0, 0)
+            .ok_or_else(invalid)?
+            .and_utc()
+    };
+    unix_ns(utc).ok_or_else(invalid)
+}
+
+/// Undo iCalendar text escaping.
+fn ics_text(value: &str) -> String {
+    value
+        .replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+/// Alerts falling due in `(after_ns, until_ns]` for time-ordered `events`,
+/// in the order they fall due: `upcoming` at `lead_ns` before each release
+/// and `released` at it.
+pub fn due_alerts(
+    events: &[CalendarEvent],
+    after_ns: u64,
+    until_ns: u64,
+    lead_ns: u64,
+) -> Vec<CalendarAlert> {
+    let mut due: Vec<(u64, CalendarAlert)> = Vec::new();
+    // Only releases up to a lead time past the window can be due in it
+    let from = events.partition_point(|e| e.ts_event_unix_ns <= after_ns);
+    let to = events.partition_point(|e| e.ts_event_unix_ns <= until_ns.saturating_add(lead_ns));
+    for event in &events[from..to.max(from)] {
+        let stages = [
+            (
+                event.ts_event_unix_ns.saturating_sub(lead_ns),
+                CalendarStage::Upcoming,
+            ),
+            (event.ts_event_unix_ns, CalendarStage::Released),
+        ];
+        for (at, stage) in stages {
+            if at > after_ns && at <= until_ns && (lead_ns > 0 || stage == CalendarStage::Released)
+            {
+                due.push((
+                    at,
+                    CalendarAlert {
+                        stage,
+                        event: event.clone(),
+                    },
+                ));
+            }
+        }
+    }
+    due.sort_by_key(|(at, _)| *at);
+    due.into_iter().map(|(_, alert)| alert).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;TZID=America/New_York:20240711T083000\r\nSUMMARY:Consumer Price\r\n  Index\\, YoY\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nDTSTART:20240731T180000Z\r\nSUMMARY:FOMC\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let events = parse(ics).unwrap();
+        assert_eq!(events.len(), 2);
+        // 08:30 EDT
+        assert_eq!(
+            events[0].ts_event_unix_ns,
+            unix_ns("2024-07-11T12:30:00Z".parse().unwrap()).unwrap()
+        );
+        assert_eq!(events[0].title, "Consumer Price Index, YoY");
+
+        let json =
+            r#"[{"time":"2024-07-31T18:00:00Z","title":"FOMC","country":"US","impact":"high"}]"#;
+        let events_json = parse(json).unwrap();
+        assert_eq!(events_json[0].ts_event_unix_ns, events[1].ts_event_unix_ns);
+        assert_eq!(events_json[0].impact.as_deref(), Some("high"));
+        assert!(parse(r#"[{"time":"soon","title":"NFP"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_due_alerts() {
+        let event = |ts| CalendarEvent {
+            ts_event_unix_ns: ts,
+            title: format!("event at {}", ts),
+            country: None,
+            impact: None,
+        };
+        let events = [event(100), event(130)];
+        let stages = |after, until| -> Vec<(u64, CalendarStage)> {
+            due_alerts(&events, after, until, 50)
+                .into_iter()
+                .map(|a| (a.event.ts_event_unix_ns, a.stage))
+                .collect()
+        };
+        assert_eq!(
+            stages(0, 100),
+            [
+                (100, CalendarStage::Upcoming),
+                (130, CalendarStage::Upcoming),
+                (100, CalendarStage::Released)
+            ]
+        );
+        assert_eq!(stages(100, 200), [(130, CalendarStage::Released)]);
+        assert!(stages(200, 300).is_empty());
+    }
+}
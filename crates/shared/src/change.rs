@@ -0,0 +1,85 @@
+//! Net and percent change against the previous session's close.
+//!
+//! The close is the last minute bar of the session: for a parent symbol,
+//! of the contract that traded the most, so `ES.FUT` changes against its
+//! front month rather than whichever contract printed last.
+
+use crate::OhlcvRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How far a price is from the previous session's close.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct DayChange {
+    pub previous_close_i64: i64,
+    pub net_change_i64: i64,
+    /// Net change against the previous close, in percent
+    pub change_pct: f64,
+}
+
+impl DayChange {
+    /// Change of `price_i64` against `previous_close_i64`.
+    pub fn new(previous_close_i64: i64, price_i64: i64) -> Self {
+        let net_change_i64 = price_i64 - previous_close_i64;
+        let change_pct = match previous_close_i64 {
+            0 => 0.0,
+            close => net_change_i64 as f64 / close as f64 * 100.0,
+        };
+        Self {
+            previous_close_i64,
+            net_change_i64,
+            change_pct,
+        }
+    }
+}
+
+/// The close of a session from its minute bars, or `None` if nothing
+/// traded: the latest bar of the symbol with the most volume, the first by
+/// name on a tie.
+pub fn session_close(bars: &[OhlcvRecord]) -> Option<i64> {
+    let mut volumes: HashMap<&str, u64> = HashMap::new();
+    for bar in bars {
+        *volumes.entry(&bar.symbol).or_default() += bar.volume_u64;
+    }
+    let (symbol, _) = volumes
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))?;
+    bars.iter()
+        .filter(|bar| bar.symbol == symbol)
+        .max_by_key(|bar| bar.ts_event_unix_ns)
+        .map(|bar| bar.close_i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(ts: u64, symbol: &str, close_i64: i64, volume_u64: u64) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: ts,
+            symbol: symbol.to_string(),
+            open_i64: close_i64,
+            high_i64: close_i64,
+            low_i64: close_i64,
+            close_i64,
+            volume_u64,
+        }
+    }
+
+    #[test]
+    fn test_session_close() {
+        assert_eq!(session_close(&[]), None);
+        // The back month printed last, but the front month's close counts
+        let bars = [
+            bar(2, "ESZ4", 5_000, 10),
+            bar(1, "ESZ4", 4_990, 10),
+            bar(3, "ESH5", 5_050, 1),
+        ];
+        assert_eq!(session_close(&bars), Some(5_000));
+
+        let change = DayChange::new(5_000, 5_050);
+        assert_eq!(change.net_change_i64, 50);
+        assert_eq!(change.change_pct, 1.0);
+        assert_eq!(DayChange::new(0, 5).change_pct, 0.0);
+    }
+}
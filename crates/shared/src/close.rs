@@ -0,0 +1,85 @@
+//! WebSocket close codes for live subscriptions.
+//!
+//! When the server ends a live socket because of an error it sends the
+//! `LiveMessage::Error` as before, then a close frame whose code says what
+//! kind of error it was, so a client can decide whether to reconnect
+//! without parsing the message. Error codes are 4000 plus the HTTP status
+//! the same error gets from the REST API: 4400 for bad parameters, 4401
+//! when the data provider refuses the credentials, 4403 when the key isn't
+//! entitled to the data, 4429 when the live quota runs out and 4502 when
+//! the provider is down or its stream fails. A stream that simply ends is
+//! closed with 1000.
+
+/// The subscription ended without an error.
+pub const NORMAL: u16 = 1000;
+
+/// The subscription's parameters were rejected.
+pub const BAD_PARAMS: u16 = 4400;
+
+/// The data provider refused the credentials, or none are configured.
+pub const AUTH_FAILED: u16 = 4401;
+
+/// The key isn't entitled to a symbol's data.
+pub const NOT_ENTITLED: u16 = 4403;
+
+/// The key's live message quota is used up.
+pub const QUOTA_EXCEEDED: u16 = 4429;
+
+/// The data provider is unreachable or its stream failed.
+pub const UPSTREAM_DOWN: u16 = 4502;
+
+/// Most bytes a close frame's reason may hold.
+pub const MAX_REASON_BYTES: usize = 123;
+
+/// The close code for an error reported over HTTP with `status`.
+pub fn from_status(status: u16) -> u16 {
+    match status {
+        400..=599 => 4000 + status,
+        _ => NORMAL,
+    }
+}
+
+/// Whether reconnecting with the same parameters may succeed. Codes
+/// 4400-4499 mean the subscription itself was refused and will be again,
+/// at least until the quota resets or the credentials change.
+pub fn retryable(code: u16) -> bool {
+    !(4400..4500).contains(&code)
+}
+
+/// `message` cut to fit a close frame's reason, on a character boundary.
+pub fn reason(message: &str) -> &str {
+    if message.len() <= MAX_REASON_BYTES {
+        return message;
+    }
+    let mut end = MAX_REASON_BYTES;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    &message[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(from_status(400), BAD_PARAMS);
+        assert_eq!(from_status(401), AUTH_FAILED);
+        assert_eq!(from_status(403), NOT_ENTITLED);
+        assert_eq!(from_status(429), QUOTA_EXCEEDED);
+        assert_eq!(from_status(502), UPSTREAM_DOWN);
+        assert_eq!(from_status(200), NORMAL);
+
+        assert!(retryable(UPSTREAM_DOWN));
+        assert!(retryable(NORMAL));
+        assert!(!retryable(BAD_PARAMS));
+        assert!(!retryable(AUTH_FAILED));
+        assert!(!retryable(NOT_ENTITLED));
+        assert!(!retryable(QUOTA_EXCEEDED));
+
+        assert_eq!(reason("Stream error"), "Stream error");
+        let long = "é".repeat(100);
+        assert_eq!(reason(&long).len(), 122);
+    }
+}
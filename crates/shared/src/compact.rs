@@ -0,0 +1,204 @@
+//! Compact field names for record-heavy payloads.
+//!
+//! Asked for with `compact: true` on a historical request or `compact=true`
+//! on a live subscription, trade and bar records are sent with the short
+//! field names of [`FIELDS`] (`t`, `s`, `p`, `q`, ...) instead of
+//! `ts_event_unix_ns`, `symbol`, `price_i64`, `size_u32`, which makes tick
+//! data about 40% smaller. Only records are renamed: the fields of a
+//! historical response's `data`, of live `trade` and `ohlcv` messages and
+//! of a `trade_batch`'s `trades`.
+//! Everything else, including the `type` tag, keeps its name. Verbose names
+//! stay the default.
+
+use crate::LiveMessage;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Record field names and their compact forms.
+pub const FIELDS: &[(&str, &str)] = &[
+    ("ts_event_unix_ns", "t"),
+    ("symbol", "s"),
+    ("price_i64", "p"),
+    ("size_u32", "q"),
+    ("sequence_u32", "n"),
+    ("open_i64", "o"),
+    ("high_i64", "h"),
+    ("low_i64", "l"),
+    ("close_i64", "c"),
+    ("volume_u64", "v"),
+];
+
+/// The compact name of a record field, if it has one.
+pub fn short_name(field: &str) -> Option<&'static str> {
+    FIELDS
+        .iter()
+        .find(|(long, _)| *long == field)
+        .map(|(_, short)| *short)
+}
+
+/// The record field a compact name stands for.
+pub fn long_name(short: &str) -> Option<&'static str> {
+    FIELDS
+        .iter()
+        .find(|(_, s)| *s == short)
+        .map(|(long, _)| *long)
+}
+
+fn rename(record: &mut Value, name: fn(&str) -> Option<&'static str>) {
+    let Value::Object(fields) = record else {
+        return;
+    };
+    *fields = std::mem::take(fields)
+        .into_iter()
+        .map(|(key, value)| match name(&key) {
+            Some(renamed) => (renamed.to_string(), value),
+            None => (key, value),
+        })
+        .collect::<Map<String, Value>>();
+}
+
+/// Rename a record's fields to their compact names.
+pub fn compact_record(record: &mut Value) {
+    rename(record, short_name);
+}
+
+/// Rename a compact record's fields back to their full names.
+pub fn expand_record(record: &mut Value) {
+    rename(record, long_name);
+}
+
+fn each_record(body: &mut Value, f: fn(&mut Value)) {
+    if let Some(Value::Array(records)) = body.get_mut("data") {
+        records.iter_mut().for_each(f);
+    }
+}
+
+/// Compact the records of a historical response body, in place.
+pub fn compact_response(body: &mut Value) {
+    each_record(body, compact_record);
+}
+
+/// Expand the records of a compact historical response body, in place.
+pub fn expand_response(body: &mut Value) {
+    each_record(body, expand_record);
+}
+
+/// `value`'s JSON with its records compacted: the `data` of a historical
+/// response, or the whole of a record-like live message.
+pub fn to_string<T: Serialize>(value: &T) -> serde_json::Result<String> {
+    let mut json = serde_json::to_value(value)?;
+    compact_response(&mut json);
+    serde_json::to_string(&json)
+}
+
+/// Apply `f` to each record of a serialized live message: the message
+/// itself for a trade or bar, and each of a trade batch's `trades`.
+pub fn each_live_record(msg: &mut Value, f: impl FnMut(&mut Value)) {
+    match msg.get("type").and_then(Value::as_str) {
+        Some("trade" | "ohlcv") => std::iter::once(msg).for_each(f),
+        Some("trade_batch") => {
+            if let Some(Value::Array(trades)) = msg.get_mut("trades") {
+                trades.iter_mut().for_each(f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A live message's JSON, with trade and bar fields compacted.
+pub fn live_to_string(msg: &LiveMessage) -> serde_json::Result<String> {
+    match msg {
+        LiveMessage::Trade { .. } | LiveMessage::TradeBatch { .. } | LiveMessage::Ohlcv { .. } => {
+            let mut json = serde_json::to_value(msg)?;
+            each_live_record(&mut json, compact_record);
+            serde_json::to_string(&json)
+        }
+        _ => serde_json::to_string(msg),
+    }
+}
+
+/// Read a live message sent compact.
+pub fn live_from_str(json: &str) -> serde_json::Result<LiveMessage> {
+    let mut value: Value = serde_json::from_str(json)?;
+    each_live_record(&mut value, expand_record);
+    serde_json::from_value(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HistoricalResponse, TradeRecord};
+
+    fn trade(i: u64) -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: 1_717_248_600_000_000_000 + i * 1_000_000,
+            symbol: "ES.FUT".to_string(),
+            price_i64: 5_300_250_000_000 + i as i64 * 250_000_000,
+            size_u32: 1 + (i % 5) as u32,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let response = HistoricalResponse::Trades {
+            data: (0..100).map(trade).collect(),
+        };
+        let verbose = serde_json::to_string(&response).unwrap();
+        let compact = to_string(&response).unwrap();
+        assert!(compact.starts_with(r#"{"data":[{"p":5300250000000,"q":1,"s":"ES.FUT","t":"#));
+        // About 40% smaller
+        assert!(
+            compact.len() * 10 < verbose.len() * 7,
+            "{} vs {}",
+            compact.len(),
+            verbose.len()
+        );
+
+        let mut body: Value = serde_json::from_str(&compact).unwrap();
+        expand_response(&mut body);
+        let back: HistoricalResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), verbose);
+
+        let msg = LiveMessage::Ohlcv {
+            ts_event_unix_ns: 1,
+            symbol: "ES.FUT".to_string(),
+            open_i64: 2,
+            high_i64: 3,
+            low_i64: 1,
+            close_i64: 2,
+            volume_u64: 9,
+            bar_closed: true,
+            interval: None,
+        };
+        let json = live_to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"bar_closed":true,"c":2,"h":3,"l":1,"o":2,"s":"ES.FUT","t":1,"type":"ohlcv","v":9}"#
+        );
+        let back = live_from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_string(&back).unwrap(),
+            serde_json::to_string(&msg).unwrap()
+        );
+        let batch = LiveMessage::TradeBatch {
+            trades: vec![trade(0), trade(1)],
+        };
+        let json = live_to_string(&batch).unwrap();
+        assert!(json.starts_with(r#"{"trades":[{"p":5300250000000,"q":1,"s":"ES.FUT","t":"#));
+        let LiveMessage::TradeBatch { trades } = live_from_str(&json).unwrap() else {
+            panic!("expected a trade batch");
+        };
+        assert_eq!(trades[1].size_u32, 2);
+
+        // Messages other than records are left alone
+        let error = LiveMessage::Error {
+            message: "x".to_string(),
+        };
+        assert_eq!(
+            live_to_string(&error).unwrap(),
+            serde_json::to_string(&error).unwrap()
+        );
+    }
+}
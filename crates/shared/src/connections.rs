@@ -0,0 +1,30 @@
+//! Introspection of open live sessions.
+
+use crate::SymbolType;
+use serde::{Deserialize, Serialize};
+
+/// A subscribed symbol's current instrument, as last reported upstream.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SymbolMapping {
+    pub instrument_id: u32,
+    /// The symbol as subscribed, e.g. "ES.FUT"
+    pub stype_in_symbol: String,
+    /// The instrument's raw symbol, e.g. "ESZ4"
+    pub stype_out_symbol: String,
+    /// RFC3339 time the mapping was received
+    pub updated_at: String,
+}
+
+/// Response to `GET /api/connections/{id}/symbols`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionSymbols {
+    pub id: u64,
+    /// Symbols as subscribed
+    pub symbols: Vec<String>,
+    pub schema: String,
+    pub stype_in: SymbolType,
+    /// RFC3339 time the session opened
+    pub connected_at: String,
+    /// Current mappings, ordered by instrument ID
+    pub mappings: Vec<SymbolMapping>,
+}
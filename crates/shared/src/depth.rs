@@ -0,0 +1,281 @@
+//! Order book depth as snapshots and diffs.
+//!
+//! The provider sends a symbol's top [`DEPTH_LEVELS`] levels after every
+//! book update ([`DepthUpdate`]). Forwarded as they come, a client joining
+//! mid-stream can't tell a missed update from a quiet book, so a
+//! [`DepthBook`] turns them into a [`DepthSnapshot`] of the whole book when
+//! a symbol first updates and after a gap, and a [`DepthDiff`] of the
+//! levels that changed otherwise.
+//!
+//! Each symbol's messages are numbered `sequence_u64`, one apart with no
+//! holes, snapshots included. A client applies a diff only when it follows
+//! the last message it applied for the symbol; on a hole it drops the book
+//! and waits for the next snapshot.
+//!
+//! The venue's sequence numbers on the updates are checked too: one seen
+//! again is a duplicate and dropped, and one that goes backwards means the
+//! provider restarted or replayed, so the symbol starts over with a
+//! snapshot. So does an update the provider flags as maybe from a bad
+//! book, and every symbol after [`DepthBook::invalidate`].
+
+use crate::LiveMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Levels per side in an mbp-10 book.
+pub const DEPTH_LEVELS: usize = 10;
+
+/// One level of both sides of a book. An empty side is all 0.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DepthLevel {
+    pub bid_px_i64: i64,
+    pub ask_px_i64: i64,
+    pub bid_sz_u32: u32,
+    pub ask_sz_u32: u32,
+    /// Orders at the bid price
+    pub bid_ct_u32: u32,
+    /// Orders at the ask price
+    pub ask_ct_u32: u32,
+}
+
+/// A symbol's top levels after one book update, as the provider sends it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DepthUpdate {
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    /// The venue's message sequence number
+    pub sequence_u32: u32,
+    /// The provider may have missed updates to this book
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub maybe_bad_book: bool,
+    /// Best first
+    pub levels: Vec<DepthLevel>,
+}
+
+/// Why a snapshot was sent.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotReason {
+    /// The symbol's first update on this subscription
+    Subscribe,
+    /// Updates may have been missed; replace the book
+    Gap,
+}
+
+/// A symbol's whole book.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DepthSnapshot {
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    pub sequence_u64: u64,
+    pub reason: SnapshotReason,
+    /// Best first
+    pub levels: Vec<DepthLevel>,
+}
+
+/// A level of a diff, replacing the one at `index`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LevelChange {
+    /// 0 is the best level
+    pub index: u8,
+    pub level: DepthLevel,
+}
+
+/// The levels of a symbol's book that changed since its previous message.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DepthDiff {
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    /// One more than the symbol's previous snapshot or diff
+    pub sequence_u64: u64,
+    pub changes: Vec<LevelChange>,
+}
+
+/// What was last sent of one symbol's book.
+struct Book {
+    sequence_u64: u64,
+    venue_sequence: u32,
+    levels: Vec<DepthLevel>,
+    /// Snapshot on the next update
+    stale: bool,
+}
+
+/// Turns one subscription's depth updates into snapshots and diffs.
+#[derive(Default)]
+pub struct DepthBook {
+    books: HashMap<String, Book>,
+    duplicates: u64,
+}
+
+impl DepthBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The message to send for `update`: a snapshot if it's the symbol's
+    /// first or follows a gap, else a diff. `None` for a duplicate, or an
+    /// update that changed none of the levels sent.
+    pub fn push(&mut self, update: DepthUpdate) -> Option<LiveMessage> {
+        let Some(book) = self.books.get_mut(&update.symbol) else {
+            return Some(self.snapshot(update, 1, SnapshotReason::Subscribe));
+        };
+        if update.sequence_u32 == book.venue_sequence {
+            self.duplicates += 1;
+            return None;
+        }
+        let sequence_u64 = book.sequence_u64 + 1;
+        if book.stale || update.maybe_bad_book || update.sequence_u32 < book.venue_sequence {
+            return Some(self.snapshot(update, sequence_u64, SnapshotReason::Gap));
+        }
+
+        let depth = book.levels.len().max(update.levels.len());
+        let changes: Vec<LevelChange> = (0..depth)
+            .filter_map(|i| {
+                let level = update.levels.get(i).copied().unwrap_or_default();
+                (book.levels.get(i).copied().unwrap_or_default() != level).then_some(LevelChange {
+                    index: i as u8,
+                    level,
+                })
+            })
+            .collect();
+        book.venue_sequence = update.sequence_u32;
+        book.levels = update.levels;
+        if changes.is_empty() {
+            return None;
+        }
+        book.sequence_u64 = sequence_u64;
+        Some(LiveMessage::DepthDiff(DepthDiff {
+            ts_event_unix_ns: update.ts_event_unix_ns,
+            symbol: update.symbol,
+            sequence_u64,
+            changes,
+        }))
+    }
+
+    fn snapshot(
+        &mut self,
+        update: DepthUpdate,
+        sequence_u64: u64,
+        reason: SnapshotReason,
+    ) -> LiveMessage {
+        self.books.insert(
+            update.symbol.clone(),
+            Book {
+                sequence_u64,
+                venue_sequence: update.sequence_u32,
+                levels: update.levels.clone(),
+                stale: false,
+            },
+        );
+        LiveMessage::DepthSnapshot(DepthSnapshot {
+            ts_event_unix_ns: update.ts_event_unix_ns,
+            symbol: update.symbol,
+            sequence_u64,
+            reason,
+            levels: update.levels,
+        })
+    }
+
+    /// Snapshot every symbol on its next update, for when updates may have
+    /// been lost upstream.
+    pub fn invalidate(&mut self) {
+        for book in self.books.values_mut() {
+            book.stale = true;
+        }
+    }
+
+    /// Duplicate updates dropped so far.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(sequence_u32: u32, bid_sizes: &[u32]) -> DepthUpdate {
+        DepthUpdate {
+            ts_event_unix_ns: u64::from(sequence_u32),
+            symbol: "ESZ4".to_string(),
+            sequence_u32,
+            maybe_bad_book: false,
+            levels: bid_sizes
+                .iter()
+                .enumerate()
+                .map(|(i, size)| DepthLevel {
+                    bid_px_i64: 100 - i as i64,
+                    ask_px_i64: 101 + i as i64,
+                    bid_sz_u32: *size,
+                    ask_sz_u32: 1,
+                    bid_ct_u32: 1,
+                    ask_ct_u32: 1,
+                })
+                .collect(),
+        }
+    }
+
+    fn sequence(msg: &LiveMessage) -> u64 {
+        match msg {
+            LiveMessage::DepthSnapshot(snapshot) => snapshot.sequence_u64,
+            LiveMessage::DepthDiff(diff) => diff.sequence_u64,
+            other => panic!("Expected depth, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_snapshots_and_diffs() {
+        let mut book = DepthBook::new();
+        let LiveMessage::DepthSnapshot(first) = book.push(update(10, &[5, 3])).unwrap() else {
+            panic!("expected a snapshot");
+        };
+        assert_eq!(
+            (first.sequence_u64, first.reason),
+            (1, SnapshotReason::Subscribe)
+        );
+
+        // Only the level that changed, then the one the book lost
+        let LiveMessage::DepthDiff(diff) = book.push(update(12, &[5, 4])).unwrap() else {
+            panic!("expected a diff");
+        };
+        assert_eq!(diff.sequence_u64, 2);
+        assert_eq!(diff.changes.len(), 1);
+        assert_eq!(
+            (diff.changes[0].index, diff.changes[0].level.bid_sz_u32),
+            (1, 4)
+        );
+        let LiveMessage::DepthDiff(diff) = book.push(update(13, &[5])).unwrap() else {
+            panic!("expected a diff");
+        };
+        assert_eq!(diff.changes[0].level, DepthLevel::default());
+
+        // A duplicate, and an update to nothing sent, aren't numbered
+        assert!(book.push(update(13, &[7])).is_none());
+        assert_eq!(book.duplicates(), 1);
+        assert!(book.push(update(14, &[5])).is_none());
+
+        // Going backwards, a flagged book and invalidation all snapshot
+        let msg = book.push(update(2, &[5])).unwrap();
+        assert!(matches!(
+            msg,
+            LiveMessage::DepthSnapshot(DepthSnapshot {
+                reason: SnapshotReason::Gap,
+                ..
+            })
+        ));
+        assert_eq!(sequence(&msg), 4);
+        let flagged = DepthUpdate {
+            maybe_bad_book: true,
+            ..update(3, &[5])
+        };
+        assert!(matches!(
+            book.push(flagged),
+            Some(LiveMessage::DepthSnapshot(_))
+        ));
+        book.invalidate();
+        let msg = book.push(update(4, &[6])).unwrap();
+        assert!(matches!(msg, LiveMessage::DepthSnapshot(_)));
+        assert_eq!(sequence(&msg), 6);
+        assert_eq!(sequence(&book.push(update(5, &[7])).unwrap()), 7);
+    }
+}
@@ -0,0 +1,400 @@
+//! Comparing one historical range as served by two sources.
+//!
+//! For `POST /api/diff`, which fetches the same request from two
+//! [`DiffSource`]s, such as the provider and the server's own recording, or
+//! two of the provider's datasets, to check a new source against a trusted
+//! one. [`diff`] pairs each symbol's records by timestamp, trades sharing a
+//! timestamp in the order they came, and reports the records only one side
+//! has and the pairs whose prices or sizes differ.
+
+use crate::{HistoricalRequest, HistoricalResponse, OhlcvRecord, TradeRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Most differences listed in a report; the counts cover all of them.
+pub const MAX_DIFFERENCES: usize = 1_000;
+
+/// Where one side of a diff is fetched from.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiffSource {
+    /// The provider, bypassing the historical cache
+    Provider {
+        /// Dataset in place of the request's
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dataset: Option<String>,
+    },
+    /// The historical cache alone; a request not cached fails
+    Cache,
+    /// The server's recording of the symbols' live data
+    Recording,
+}
+
+/// Request body for `POST /api/diff`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffRequest {
+    /// Fetched from each source alike; trades or time bars
+    pub request: HistoricalRequest,
+    /// The source checked against
+    pub left: DiffSource,
+    pub right: DiffSource,
+}
+
+/// How a record differs between the sources.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DifferenceKind {
+    /// Only the left source has the record
+    MissingRight,
+    /// Only the right source has the record
+    MissingLeft,
+    /// Both have it, with some fields differing
+    Mismatch,
+}
+
+/// A record as one source served it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DiffRecord {
+    Trade(TradeRecord),
+    Bar(OhlcvRecord),
+}
+
+impl DiffRecord {
+    fn symbol(&self) -> &str {
+        match self {
+            DiffRecord::Trade(trade) => &trade.symbol,
+            DiffRecord::Bar(bar) => &bar.symbol,
+        }
+    }
+
+    fn ts_event_unix_ns(&self) -> u64 {
+        match self {
+            DiffRecord::Trade(trade) => trade.ts_event_unix_ns,
+            DiffRecord::Bar(bar) => bar.ts_event_unix_ns,
+        }
+    }
+
+    /// Fields that differ from `other`'s, and whether a price and a size
+    /// are among them.
+    fn compare(&self, other: &DiffRecord) -> (Vec<&'static str>, bool, bool) {
+        let (prices, sizes) = match (self, other) {
+            (DiffRecord::Trade(a), DiffRecord::Trade(b)) => (
+                vec![("price_i64", a.price_i64 != b.price_i64)],
+                vec![("size_u32", a.size_u32 != b.size_u32)],
+            ),
+            (DiffRecord::Bar(a), DiffRecord::Bar(b)) => (
+                vec![
+                    ("open_i64", a.open_i64 != b.open_i64),
+                    ("high_i64", a.high_i64 != b.high_i64),
+                    ("low_i64", a.low_i64 != b.low_i64),
+                    ("close_i64", a.close_i64 != b.close_i64),
+                ],
+                vec![("volume_u64", a.volume_u64 != b.volume_u64)],
+            ),
+            // Both sides are checked to be one schema first
+            _ => (Vec::new(), Vec::new()),
+        };
+        let differing = |fields: &[(&'static str, bool)]| -> Vec<&'static str> {
+            fields
+                .iter()
+                .filter_map(|(name, differs)| differs.then_some(*name))
+                .collect()
+        };
+        let (prices, sizes) = (differing(&prices), differing(&sizes));
+        let (price, size) = (!prices.is_empty(), !sizes.is_empty());
+        (prices.into_iter().chain(sizes).collect(), price, size)
+    }
+}
+
+/// One record that differs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordDifference {
+    pub kind: DifferenceKind,
+    pub symbol: String,
+    pub ts_event_unix_ns: u64,
+    /// Fields that differ, for a mismatch
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Option<DiffRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<DiffRecord>,
+}
+
+/// How the sources compare, for one symbol or all of them.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DiffCounts {
+    pub left_records_u64: u64,
+    pub right_records_u64: u64,
+    /// Right's records less left's
+    pub count_delta_i64: i64,
+    /// Records both have, identical
+    pub matched_u64: u64,
+    pub missing_left_u64: u64,
+    pub missing_right_u64: u64,
+    /// Pairs with a price that differs
+    pub price_mismatches_u64: u64,
+    /// Pairs with a size or volume that differs
+    pub size_mismatches_u64: u64,
+}
+
+impl DiffCounts {
+    fn merge(&mut self, other: &DiffCounts) {
+        self.left_records_u64 += other.left_records_u64;
+        self.right_records_u64 += other.right_records_u64;
+        self.count_delta_i64 += other.count_delta_i64;
+        self.matched_u64 += other.matched_u64;
+        self.missing_left_u64 += other.missing_left_u64;
+        self.missing_right_u64 += other.missing_right_u64;
+        self.price_mismatches_u64 += other.price_mismatches_u64;
+        self.size_mismatches_u64 += other.size_mismatches_u64;
+    }
+}
+
+/// One symbol's comparison.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SymbolDiff {
+    pub symbol: String,
+    #[serde(flatten)]
+    pub counts: DiffCounts,
+}
+
+/// Response to `POST /api/diff`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub schema: String,
+    pub left: DiffSource,
+    pub right: DiffSource,
+    /// True when the sources served the same records
+    pub identical: bool,
+    pub totals: DiffCounts,
+    /// By symbol
+    pub symbols: Vec<SymbolDiff>,
+    /// In time order by symbol, at most [`MAX_DIFFERENCES`]
+    pub differences: Vec<RecordDifference>,
+    /// True when more differences were found than are listed
+    pub truncated: bool,
+    /// True when a source reached the request's limits, so the records past
+    /// them weren't compared
+    pub limited: bool,
+}
+
+/// Records of `response`, by symbol, each in time order.
+fn by_symbol(response: HistoricalResponse) -> BTreeMap<String, Vec<DiffRecord>> {
+    let records: Vec<DiffRecord> = match response {
+        HistoricalResponse::Trades { data } => data.into_iter().map(DiffRecord::Trade).collect(),
+        HistoricalResponse::Ohlcv1S { data }
+        | HistoricalResponse::Ohlcv1M { data }
+        | HistoricalResponse::Cvd1S { data }
+        | HistoricalResponse::Cvd1M { data }
+        | HistoricalResponse::Bars { data, .. } => data.into_iter().map(DiffRecord::Bar).collect(),
+    };
+    let mut symbols: BTreeMap<String, Vec<DiffRecord>> = BTreeMap::new();
+    for record in records {
+        symbols
+            .entry(record.symbol().to_string())
+            .or_default()
+            .push(record);
+    }
+    for records in symbols.values_mut() {
+        // Stable, so trades sharing a timestamp keep the order they came in
+        records.sort_by_key(DiffRecord::ts_event_unix_ns);
+    }
+    symbols
+}
+
+/// Compare `left` and `right`, as served by the sources of `req`. `limited`
+/// says whether either reached the request's limits. Fails if the sources
+/// served different schemas.
+pub fn diff(
+    req: &DiffRequest,
+    left: HistoricalResponse,
+    right: HistoricalResponse,
+    limited: bool,
+) -> Result<DiffReport, String> {
+    let schema = left.schema();
+    if right.schema() != schema {
+        return Err(format!(
+            "The sources served different schemas: {} and {}",
+            schema.as_str(),
+            right.schema().as_str()
+        ));
+    }
+    let (mut left, mut right) = (by_symbol(left), by_symbol(right));
+    let mut names: Vec<String> = left.keys().chain(right.keys()).cloned().collect();
+    names.sort();
+    names.dedup();
+
+    let mut totals = DiffCounts::default();
+    let mut symbols = Vec::with_capacity(names.len());
+    let mut differences = Vec::new();
+    let mut found = 0usize;
+    let mut note = |difference: RecordDifference| {
+        found += 1;
+        if differences.len() < MAX_DIFFERENCES {
+            differences.push(difference);
+        }
+    };
+    for symbol in names {
+        let lhs = left.remove(&symbol).unwrap_or_default();
+        let rhs = right.remove(&symbol).unwrap_or_default();
+        let mut counts = DiffCounts {
+            left_records_u64: lhs.len() as u64,
+            right_records_u64: rhs.len() as u64,
+            count_delta_i64: rhs.len() as i64 - lhs.len() as i64,
+            ..Default::default()
+        };
+        let (mut lhs, mut rhs) = (lhs.into_iter().peekable(), rhs.into_iter().peekable());
+        loop {
+            let ts = match (lhs.peek(), rhs.peek()) {
+                (Some(a), Some(b)) => a.ts_event_unix_ns().min(b.ts_event_unix_ns()),
+                (Some(a), None) => a.ts_event_unix_ns(),
+                (None, Some(b)) => b.ts_event_unix_ns(),
+                (None, None) => break,
+            };
+            // Records at `ts` pair up in order; extras are missing
+            let at = |records: &mut std::iter::Peekable<std::vec::IntoIter<DiffRecord>>| {
+                std::iter::from_fn(|| records.next_if(|r| r.ts_event_unix_ns() == ts))
+                    .collect::<Vec<_>>()
+            };
+            let (lefts, rights) = (at(&mut lhs), at(&mut rhs));
+            let mut rights = rights.into_iter();
+            for a in lefts {
+                let Some(b) = rights.next() else {
+                    counts.missing_right_u64 += 1;
+                    note(RecordDifference {
+                        kind: DifferenceKind::MissingRight,
+                        symbol: symbol.clone(),
+                        ts_event_unix_ns: ts,
+                        fields: Vec::new(),
+                        left: Some(a),
+                        right: None,
+                    });
+                    continue;
+                };
+                let (fields, price, size) = a.compare(&b);
+                if fields.is_empty() {
+                    counts.matched_u64 += 1;
+                    continue;
+                }
+                counts.price_mismatches_u64 += u64::from(price);
+                counts.size_mismatches_u64 += u64::from(size);
+                note(RecordDifference {
+                    kind: DifferenceKind::Mismatch,
+                    symbol: symbol.clone(),
+                    ts_event_unix_ns: ts,
+                    fields: fields.into_iter().map(str::to_string).collect(),
+                    left: Some(a),
+                    right: Some(b),
+                });
+            }
+            for b in rights {
+                counts.missing_left_u64 += 1;
+                note(RecordDifference {
+                    kind: DifferenceKind::MissingLeft,
+                    symbol: symbol.clone(),
+                    ts_event_unix_ns: ts,
+                    fields: Vec::new(),
+                    left: None,
+                    right: Some(b),
+                });
+            }
+        }
+        totals.merge(&counts);
+        symbols.push(SymbolDiff { symbol, counts });
+    }
+
+    Ok(DiffReport {
+        schema: schema.as_str().to_string(),
+        left: req.left.clone(),
+        right: req.right.clone(),
+        identical: found == 0,
+        totals,
+        symbols,
+        truncated: found > differences.len(),
+        differences,
+        limited,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(symbol: &str, ts: u64, price_i64: i64, size_u32: u32) -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: symbol.to_string(),
+            price_i64,
+            size_u32,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    fn request() -> DiffRequest {
+        DiffRequest {
+            request: HistoricalRequest::default(),
+            left: DiffSource::Provider { dataset: None },
+            right: DiffSource::Recording,
+        }
+    }
+
+    #[test]
+    fn test_diff_trades() {
+        let left = HistoricalResponse::Trades {
+            data: vec![
+                trade("ESZ4", 10, 100, 1),
+                trade("ESZ4", 20, 101, 2),
+                trade("ESZ4", 20, 101, 3),
+                trade("NQZ4", 10, 200, 1),
+            ],
+        };
+        let right = HistoricalResponse::Trades {
+            data: vec![
+                trade("ESZ4", 20, 101, 2),
+                trade("ESZ4", 10, 100, 1),
+                trade("ESZ4", 30, 102, 1),
+                trade("NQZ4", 10, 201, 4),
+            ],
+        };
+        let report = diff(&request(), left, right, false).unwrap();
+
+        assert!(!report.identical);
+        let es = &report.symbols[0].counts;
+        assert_eq!(
+            (es.matched_u64, es.missing_right_u64, es.missing_left_u64),
+            (2, 1, 1)
+        );
+        assert_eq!(es.count_delta_i64, 0);
+        let nq = &report.symbols[1].counts;
+        assert_eq!((nq.price_mismatches_u64, nq.size_mismatches_u64), (1, 1));
+        assert_eq!(report.totals.matched_u64, 2);
+
+        // The second trade at 20 has no partner; the one at 30 only right
+        let kinds: Vec<(DifferenceKind, u64)> = report
+            .differences
+            .iter()
+            .map(|d| (d.kind, d.ts_event_unix_ns))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (DifferenceKind::MissingRight, 20),
+                (DifferenceKind::MissingLeft, 30),
+                (DifferenceKind::Mismatch, 10),
+            ]
+        );
+        assert_eq!(report.differences[2].fields, ["price_i64", "size_u32"]);
+    }
+
+    #[test]
+    fn test_schemas_must_match() {
+        let trades = HistoricalResponse::Trades { data: Vec::new() };
+        let bars = HistoricalResponse::Ohlcv1M { data: Vec::new() };
+        assert!(diff(&request(), trades.clone(), bars, false).is_err());
+        let report = diff(&request(), trades.clone(), trades, false).unwrap();
+        assert!(report.identical && report.symbols.is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+//! Rolling order-flow analytics over live trades.
+//!
+//! Live trades carry no quote, so aggressor side is inferred with the tick
+//! rule: a trade above the previous price for its symbol is a buy, below is
+//! a sell, and an unchanged price keeps the previous direction.
+
+use crate::aggregate::{NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use crate::LiveMessage;
+use std::collections::{HashMap, VecDeque};
+
+/// Metric name used for order-flow imbalance updates.
+pub const IMBALANCE_METRIC: &str = "order_flow_imbalance";
+
+/// Volume is summed into buckets this wide; windows resolve to this.
+const BUCKET_NS: u64 = 100_000_000;
+
+/// Minimum event time between two rounds of metric updates.
+const EMIT_INTERVAL_NS: u64 = 250_000_000;
+
+/// Longest window accepted, bounding memory per subscription.
+pub const MAX_WINDOW_NS: u64 = 60 * NANOS_PER_MINUTE;
+
+/// Parse a window like `500ms`, `5s`, `1m`, or `1h` into nanoseconds.
+pub fn parse_window(s: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid window '{}': expected e.g. 500ms, 5s, 1m", s);
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+
+    let unit_ns = match unit {
+        "ms" => 1_000_000,
+        "s" => NANOS_PER_SECOND,
+        "m" => NANOS_PER_MINUTE,
+        "h" => 60 * NANOS_PER_MINUTE,
+        _ => return Err(invalid()),
+    };
+    let window = amount.saturating_mul(unit_ns);
+    if !(BUCKET_NS..=MAX_WINDOW_NS).contains(&window) {
+        return Err(format!("Window '{}' must be between 100ms and 1h", s));
+    }
+    Ok(window)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    start_ns: u64,
+    buy: u64,
+    sell: u64,
+}
+
+/// One symbol's classified volume and tick-rule state.
+#[derive(Debug, Clone, Default)]
+struct SymbolFlow {
+    buckets: VecDeque<Bucket>,
+    /// Last price and inferred direction (+1 buy, -1 sell)
+    last: Option<(i64, i8)>,
+    next_emit_ns: u64,
+}
+
+impl SymbolFlow {
+    /// Imbalance over the `width` ending with the bucket at `now_bucket`.
+    fn imbalance(&self, now_bucket: u64, width: u64) -> f64 {
+        let (buy, sell) = self
+            .buckets
+            .iter()
+            .rev()
+            .take_while(|b| b.start_ns + width > now_bucket)
+            .fold((0u64, 0u64), |(buy, sell), b| (buy + b.buy, sell + b.sell));
+        let total = buy + sell;
+        if total == 0 {
+            0.0
+        } else {
+            (buy as f64 - sell as f64) / total as f64
+        }
+    }
+}
+
+/// Rolling buy/sell volume imbalance over one or more windows, per symbol.
+///
+/// The imbalance is `(buy - sell) / (buy + sell)`, ranging from -1 (all
+/// selling) to 1 (all buying), and 0 when the window has no classified
+/// volume.
+#[derive(Debug, Clone)]
+pub struct ImbalanceTracker {
+    /// Window labels as configured, with their widths
+    windows: Vec<(String, u64)>,
+    symbols: HashMap<String, SymbolFlow>,
+}
+
+impl ImbalanceTracker {
+    /// Track the given `(label, width_ns)` windows.
+    pub fn new(windows: Vec<(String, u64)>) -> Self {
+        Self {
+            windows,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Build a tracker from a comma-separated list such as `5s,1m`.
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let windows = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|w| !w.is_empty())
+            .map(|w| parse_window(w).map(|ns| (w.to_string(), ns)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if windows.is_empty() {
+            return Err("No imbalance windows given".to_string());
+        }
+        Ok(Self::new(windows))
+    }
+
+    /// Record a trade. Returns one `Metric` per window for `symbol` when
+    /// its update is due.
+    pub fn push(&mut self, ts_ns: u64, symbol: &str, price: i64, size: u32) -> Vec<LiveMessage> {
+        let longest = self.windows.iter().map(|(_, w)| *w).max().unwrap_or(0);
+        let flow = self.symbols.entry(symbol.to_string()).or_default();
+        let direction = match flow.last {
+            Some((prev, dir)) if price == prev => dir,
+            Some((prev, _)) if price > prev => 1,
+            Some(_) => -1,
+            None => 0,
+        };
+        flow.last = Some((price, direction));
+
+        let start_ns = ts_ns - ts_ns % BUCKET_NS;
+        if flow.buckets.back().is_none_or(|b| b.start_ns < start_ns) {
+            flow.buckets.push_back(Bucket {
+                start_ns,
+                buy: 0,
+                sell: 0,
+            });
+        }
+        // Late trades land in the newest bucket rather than reopening old ones
+        if let Some(bucket) = flow.buckets.back_mut() {
+            match direction {
+                1 => bucket.buy += size as u64,
+                -1 => bucket.sell += size as u64,
+                _ => {}
+            }
+        }
+
+        while flow
+            .buckets
+            .front()
+            .is_some_and(|b| b.start_ns + longest <= start_ns)
+        {
+            flow.buckets.pop_front();
+        }
+
+        if ts_ns < flow.next_emit_ns {
+            return Vec::new();
+        }
+        flow.next_emit_ns = ts_ns + EMIT_INTERVAL_NS;
+        let flow = &*flow;
+        self.windows
+            .iter()
+            .map(|(label, width)| LiveMessage::Metric {
+                symbol: symbol.to_string(),
+                name: IMBALANCE_METRIC.to_string(),
+                value: flow.imbalance(start_ns, *width),
+                window: label.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(metrics: &[LiveMessage]) -> Vec<f64> {
+        metrics
+            .iter()
+            .map(|m| match m {
+                LiveMessage::Metric { value, .. } => *value,
+                _ => panic!("Expected metric"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("500ms").unwrap(), 500_000_000);
+        assert_eq!(parse_window("5s").unwrap(), 5 * NANOS_PER_SECOND);
+        assert_eq!(parse_window("1m").unwrap(), NANOS_PER_MINUTE);
+        assert!(parse_window("10ms").is_err());
+        assert!(parse_window("2d").is_err());
+        assert!(parse_window("s").is_err());
+    }
+
+    #[test]
+    fn test_imbalance_rolls_off_old_volume() {
+        let mut tracker = ImbalanceTracker::from_spec("1s,10s").unwrap();
+        let s = NANOS_PER_SECOND;
+
+        // First print has no previous price and is unclassified
+        assert_eq!(values(&tracker.push(0, "ES", 100, 5)), vec![0.0, 0.0]);
+        // Uptick buy, then an unchanged price keeps the buy direction
+        assert!(tracker.push(s / 10, "ES", 101, 10).is_empty());
+        assert!(tracker.push(s / 5, "ES", 101, 10).is_empty());
+        assert_eq!(values(&tracker.push(s / 2, "ES", 100, 20)), vec![0.0, 0.0]);
+
+        // Five seconds later the 1s window only sees this downtick sell
+        let metrics = tracker.push(5 * s, "ES", 99, 10);
+        assert_eq!(values(&metrics), vec![-1.0, -0.2]);
+        match &metrics[1] {
+            LiveMessage::Metric { name, window, .. } => {
+                assert_eq!(name, IMBALANCE_METRIC);
+                assert_eq!(window, "10s");
+            }
+            _ => panic!("Expected metric"),
+        }
+    }
+
+    #[test]
+    fn test_imbalance_per_symbol() {
+        let mut tracker = ImbalanceTracker::from_spec("10s").unwrap();
+        let s = NANOS_PER_SECOND;
+
+        tracker.push(0, "ES", 100, 5);
+        tracker.push(0, "NQ", 200, 5);
+        // ES upticks while NQ downticks; neither sees the other's volume
+        tracker.push(s, "ES", 101, 10);
+        tracker.push(s, "NQ", 199, 30);
+        let es = tracker.push(2 * s, "ES", 101, 10);
+        let nq = tracker.push(2 * s, "NQ", 199, 10);
+        assert_eq!(values(&es), vec![1.0]);
+        assert_eq!(values(&nq), vec![-1.0]);
+        assert!(matches!(&nq[0], LiveMessage::Metric { symbol, .. } if symbol == "NQ"));
+    }
+}
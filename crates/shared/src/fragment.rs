@@ -0,0 +1,206 @@
+//! Splitting oversized WebSocket payloads into `LiveMessage::Fragment` parts.
+//!
+//! Some proxies drop connections carrying frames above ~1MB. The server
+//! serializes each message as usual and, if the JSON is too large, sends it
+//! as several fragment messages whose `data` fields concatenate back to the
+//! original JSON. Clients feed fragments to a [`Reassembler`].
+
+use crate::LiveMessage;
+use std::collections::HashMap;
+
+/// Bytes reserved for the fragment envelope around each `data` chunk.
+const ENVELOPE_OVERHEAD: usize = 128;
+
+/// Smallest frame budget `split` accepts.
+pub const MIN_FRAME_BYTES: usize = 2 * ENVELOPE_OVERHEAD;
+
+/// Most messages a [`Reassembler`] holds partly received; starting another
+/// drops the one that has gone longest without a part.
+const MAX_PENDING: usize = 16;
+
+/// Fragments received after a message's latest part at which it is dropped
+/// as abandoned. The server sends each message's parts back to back.
+const STALE_AFTER: u64 = 1_024;
+
+/// Split serialized JSON into fragments whose own JSON encoding fits in
+/// `max_frame_bytes`.
+///
+/// Chunks are cut on character boundaries and sized by their escaped length,
+/// since quotes in the payload grow when embedded in the `data` string.
+pub fn split(json: &str, id: u64, max_frame_bytes: usize) -> Vec<LiveMessage> {
+    let budget = max_frame_bytes.max(MIN_FRAME_BYTES) - ENVELOPE_OVERHEAD;
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut escaped_len = 0;
+    for (i, c) in json.char_indices() {
+        let len = escaped_char_len(c);
+        if escaped_len + len > budget {
+            chunks.push(&json[start..i]);
+            start = i;
+            escaped_len = 0;
+        }
+        escaped_len += len;
+    }
+    chunks.push(&json[start..]);
+
+    let total = chunks.len() as u32;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(part, data)| LiveMessage::Fragment {
+            id,
+            part: part as u32,
+            total,
+            data: data.to_string(),
+        })
+        .collect()
+}
+
+/// Length of `c` once escaped inside a JSON string.
+fn escaped_char_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{08}' | '\u{0c}' => 2,
+        c if (c as u32) < 0x20 => 6,
+        c => c.len_utf8(),
+    }
+}
+
+/// Collects fragments and yields the original JSON once complete.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    pending: HashMap<u64, Pending>,
+    /// Fragments pushed so far
+    received: u64,
+}
+
+/// A message partly received.
+#[derive(Debug)]
+struct Pending {
+    next: u32,
+    json: String,
+    /// Value of `received` when its latest part arrived
+    last_part: u64,
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a fragment. Returns the reassembled JSON when `part` completes
+    /// message `id`, or an error if parts arrive out of order. Messages
+    /// abandoned part way are dropped (see [`MAX_PENDING`] and
+    /// [`STALE_AFTER`]), so their remaining parts are out of order too.
+    pub fn push(
+        &mut self,
+        id: u64,
+        part: u32,
+        total: u32,
+        data: &str,
+    ) -> Result<Option<String>, String> {
+        self.received += 1;
+        let received = self.received;
+        self.pending
+            .retain(|_, pending| received - pending.last_part <= STALE_AFTER);
+        let starts = part == 0 && !self.pending.contains_key(&id);
+        if starts && self.pending.len() >= MAX_PENDING {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, pending)| pending.last_part)
+                .map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+
+        let pending = self.pending.entry(id).or_insert(Pending {
+            next: 0,
+            json: String::new(),
+            last_part: received,
+        });
+        if part != pending.next || part >= total {
+            let expected = pending.next;
+            self.pending.remove(&id);
+            return Err(format!(
+                "fragment {} of message {} out of order (expected part {} of {})",
+                part, id, expected, total
+            ));
+        }
+
+        pending.json.push_str(data);
+        pending.next += 1;
+        pending.last_part = received;
+        if pending.next < total {
+            return Ok(None);
+        }
+        Ok(self.pending.remove(&id).map(|pending| pending.json))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_respects_frame_budget_and_round_trips() {
+        let msg = LiveMessage::Error {
+            message: "quoted \"text\" with ünïcödé and \\ slashes ".repeat(200),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+
+        let parts = split(&json, 7, 1024);
+        assert!(parts.len() > 1);
+
+        let mut reassembler = Reassembler::new();
+        let mut out = None;
+        for part in &parts {
+            let frame = serde_json::to_string(part).unwrap();
+            assert!(frame.len() <= 1024, "frame of {} bytes", frame.len());
+
+            if let LiveMessage::Fragment {
+                id,
+                part,
+                total,
+                data,
+            } = part
+            {
+                out = reassembler.push(*id, *part, *total, data).unwrap();
+            }
+        }
+        assert_eq!(out.unwrap(), json);
+    }
+
+    #[test]
+    fn test_small_payload_is_single_fragment() {
+        let parts = split("{}", 1, 1024);
+        assert_eq!(parts.len(), 1);
+    }
+
+    #[test]
+    fn test_reassembler_rejects_out_of_order_parts() {
+        let mut reassembler = Reassembler::new();
+        assert!(reassembler.push(1, 1, 2, "b").is_err());
+        assert_eq!(reassembler.push(1, 0, 2, "a").unwrap(), None);
+        assert_eq!(reassembler.push(1, 1, 2, "b").unwrap().unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_reassembler_drops_abandoned_messages() {
+        let mut reassembler = Reassembler::new();
+        for id in 0..=MAX_PENDING as u64 {
+            assert_eq!(reassembler.push(id, 0, 2, "a").unwrap(), None);
+        }
+        assert_eq!(reassembler.pending.len(), MAX_PENDING);
+        // The first was dropped to make room for the last
+        assert!(reassembler.push(0, 1, 2, "b").is_err());
+        assert_eq!(reassembler.push(1, 1, 2, "b").unwrap().unwrap(), "ab");
+
+        for id in 100..100 + STALE_AFTER {
+            assert_eq!(reassembler.push(id, 0, 1, "{}").unwrap().unwrap(), "{}");
+        }
+        assert!(reassembler.push(2, 1, 2, "b").is_err());
+        assert!(reassembler.pending.is_empty());
+    }
+}
@@ -0,0 +1,465 @@
+//! Importing third-party data files.
+//!
+//! A CSV or Parquet file is read as a table of text cells, then its columns
+//! are mapped by an [`ImportSpec`] onto the records of a trades or OHLCV
+//! schema under a symbol of the user's choosing.
+
+use crate::price::{parse_price, PRICE_SCALE};
+use crate::recordings::RecordingPartition;
+use crate::{HistoricalResponse, OhlcvRecord, Schema, TradeRecord};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Row errors kept in an import report; the rest are only counted.
+pub const MAX_REPORTED_ERRORS: usize = 10;
+
+/// File format of an import.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ImportFormat {
+    /// Format implied by a file name's extension.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" | "txt" => Some(ImportFormat::Csv),
+            "parquet" | "pq" => Some(ImportFormat::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// How timestamps are written.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    /// Integers by their magnitude (seconds to nanoseconds since the
+    /// epoch), otherwise RFC3339, `2024-01-02 14:30:00[.f]` (UTC) or
+    /// `2024-01-02`
+    #[default]
+    Auto,
+    Rfc3339,
+    UnixS,
+    UnixMs,
+    UnixUs,
+    UnixNs,
+}
+
+/// Column of the file holding each record field. Only the fields of the
+/// imported schema are used.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub ts_event: String,
+    pub price: String,
+    pub size: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            ts_event: "ts_event".to_string(),
+            price: "price".to_string(),
+            size: "size".to_string(),
+            open: "open".to_string(),
+            high: "high".to_string(),
+            low: "low".to_string(),
+            close: "close".to_string(),
+            volume: "volume".to_string(),
+        }
+    }
+}
+
+/// What to import, and how to read it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImportSpec {
+    /// Symbol the records are stored under
+    pub symbol: String,
+    /// `trades`, `ohlcv-1s` or `ohlcv-1m`
+    pub schema: String,
+    /// File on the server, relative to its import directory; uploads
+    /// send the file instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Defaults to the file name's extension
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<ImportFormat>,
+    #[serde(default)]
+    pub columns: ColumnMapping,
+    #[serde(default)]
+    pub time_format: TimeFormat,
+    /// Prices are already fixed-point integers scaled by 1e9 rather than
+    /// decimals
+    #[serde(default)]
+    pub fixed_point: bool,
+    /// CSV field separator (default: ',')
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<char>,
+}
+
+impl ImportSpec {
+    /// The schema, if it's one that can be imported.
+    pub fn parsed_schema(&self) -> Result<Schema, String> {
+        match self.schema.parse::<Schema>()? {
+            schema @ (Schema::Trades | Schema::Ohlcv1S | Schema::Ohlcv1M) => Ok(schema),
+            _ => Err(format!(
+                "Schema '{}' can't be imported: expected trades, ohlcv-1s or ohlcv-1m",
+                self.schema
+            )),
+        }
+    }
+}
+
+/// Outcome of an import.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImportReport {
+    pub symbol: String,
+    pub schema: String,
+    /// Records stored
+    pub records: u64,
+    /// Rows that couldn't be read
+    pub skipped: u64,
+    /// Why the first rows were skipped, as `row N: ...`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// Partitions written, one per UTC day
+    pub partitions: Vec<RecordingPartition>,
+}
+
+/// A file's columns and rows as text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn column(&self, name: &str) -> Result<usize, String> {
+        self.columns.iter().position(|c| c == name).ok_or_else(|| {
+            format!(
+                "No column '{}'; the file has {}",
+                name,
+                self.columns.join(", ")
+            )
+        })
+    }
+}
+
+/// Split CSV text into its header and rows. Fields may be quoted, with
+/// `""` for a quote inside one; blank lines are skipped.
+pub fn parse_csv(text: &str, delimiter: char) -> Result<Table, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(c),
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|r| !(r.len() == 1 && r[0].trim().is_empty()));
+
+    let mut records = records.into_iter();
+    let columns: Vec<String> = records
+        .next()
+        .ok_or("The file is empty")?
+        .into_iter()
+        .map(|c| c.trim().to_string())
+        .collect();
+    Ok(Table {
+        columns,
+        rows: records.collect(),
+    })
+}
+
+/// Nanoseconds since the epoch of a timestamp cell.
+pub fn parse_time(cell: &str, format: TimeFormat) -> Result<u64, String> {
+    let cell = cell.trim();
+    let invalid = || format!("invalid time '{}'", cell);
+    let scaled = |scale: u64| -> Result<u64, String> {
+        cell.parse::<u64>()
+            .ok()
+            .and_then(|v| v.checked_mul(scale))
+            .ok_or_else(invalid)
+    };
+    let rfc3339 = || -> Option<u64> {
+        let ns = DateTime::parse_from_rfc3339(cell)
+            .ok()?
+            .timestamp_nanos_opt()?;
+        u64::try_from(ns).ok()
+    };
+    match format {
+        TimeFormat::UnixS => scaled(1_000_000_000),
+        TimeFormat::UnixMs => scaled(1_000_000),
+        TimeFormat::UnixUs => scaled(1_000),
+        TimeFormat::UnixNs => scaled(1),
+        TimeFormat::Rfc3339 => rfc3339().ok_or_else(invalid),
+        TimeFormat::Auto => {
+            if cell.bytes().all(|b| b.is_ascii_digit()) && !cell.is_empty() {
+                // Nanoseconds since 1973, microseconds since 1970-04,
+                // milliseconds since 2001-09; seconds otherwise
+                return match cell.len() {
+                    18.. => scaled(1),
+                    15..=17 => scaled(1_000),
+                    13..=14 => scaled(1_000_000),
+                    _ => scaled(1_000_000_000),
+                };
+            }
+            let naive = NaiveDateTime::parse_from_str(cell, "%Y-%m-%d %H:%M:%S%.f")
+                .or_else(|_| NaiveDateTime::parse_from_str(cell, "%Y-%m-%dT%H:%M:%S%.f"))
+                .ok()
+                .or_else(|| {
+                    NaiveDate::parse_from_str(cell, "%Y-%m-%d")
+                        .ok()?
+                        .and_hms_opt(0, 0, 0)
+                })
+                .and_then(|t| t.and_utc().timestamp_nanos_opt())
+                .and_then(|ns| u64::try_from(ns).ok());
+            rfc3339().or(naive).ok_or_else(invalid)
+        }
+    }
+}
+
+/// Records read from a table, and the rows that couldn't be.
+#[derive(Debug, Clone)]
+pub struct Imported {
+    /// Time-ordered records of the spec's schema and symbol
+    pub response: HistoricalResponse,
+    pub skipped: u64,
+    /// The first [`MAX_REPORTED_ERRORS`] row errors
+    pub errors: Vec<String>,
+}
+
+/// Map `table`'s rows onto records as `spec` says. A missing column fails
+/// the import; a row that doesn't read is skipped.
+pub fn to_records(table: &Table, spec: &ImportSpec) -> Result<Imported, String> {
+    let schema = spec.parsed_schema()?;
+    if spec.symbol.trim().is_empty() {
+        return Err("A symbol to store the records under is required".to_string());
+    }
+    let columns = &spec.columns;
+    let ts = table.column(&columns.ts_event)?;
+    let price = |row: &[String], column: usize| -> Result<i64, String> {
+        let cell = row.get(column).map(|c| c.trim()).unwrap_or_default();
+        let parsed = if spec.fixed_point {
+            cell.parse().ok()
+        } else {
+            parse_price(cell)
+        };
+        parsed.ok_or_else(|| format!("invalid price '{}'", cell))
+    };
+    let integer = |row: &[String], column: usize| -> Result<u64, String> {
+        let cell = row.get(column).map(|c| c.trim()).unwrap_or_default();
+        // Sizes written as decimals, e.g. 3.0, are taken when whole
+        cell.parse::<u64>()
+            .ok()
+            .or_else(|| {
+                let value = parse_price(cell)?;
+                (value >= 0 && value % PRICE_SCALE == 0).then_some((value / PRICE_SCALE) as u64)
+            })
+            .ok_or_else(|| format!("invalid size '{}'", cell))
+    };
+    let time = |row: &[String]| -> Result<u64, String> {
+        parse_time(row.get(ts).map_or("", |c| c.as_str()), spec.time_format)
+    };
+
+    let mut skipped = 0;
+    let mut errors = Vec::new();
+    let mut skip = |index: usize, error: String| {
+        skipped += 1;
+        if errors.len() < MAX_REPORTED_ERRORS {
+            // Row 1 is the header
+            errors.push(format!("row {}: {}", index + 2, error));
+        }
+    };
+    let mut response = match schema {
+        Schema::Trades => {
+            let (price_col, size_col) =
+                (table.column(&columns.price)?, table.column(&columns.size)?);
+            let mut data = Vec::with_capacity(table.rows.len());
+            for (i, row) in table.rows.iter().enumerate() {
+                let record = (|| {
+                    let size = integer(row, size_col)?;
+                    Ok::<_, String>(TradeRecord {
+                        ts_event_unix_ns: time(row)?,
+                        symbol: spec.symbol.clone(),
+                        price_i64: price(row, price_col)?,
+                        size_u32: u32::try_from(size)
+                            .map_err(|_| format!("size {} too large", size))?,
+                        quote: None,
+                        sequence_u32: None,
+                    })
+                })();
+                match record {
+                    Ok(record) => data.push(record),
+                    Err(e) => skip(i, e),
+                }
+            }
+            HistoricalResponse::Trades { data }
+        }
+        _ => {
+            let [open, high, low, close, volume] = [
+                &columns.open,
+                &columns.high,
+                &columns.low,
+                &columns.close,
+                &columns.volume,
+            ]
+            .map(|name| table.column(name));
+            let (open, high, low, close, volume) = (open?, high?, low?, close?, volume?);
+            let mut data = Vec::with_capacity(table.rows.len());
+            for (i, row) in table.rows.iter().enumerate() {
+                let record = (|| {
+                    Ok::<_, String>(OhlcvRecord {
+                        ts_event_unix_ns: time(row)?,
+                        symbol: spec.symbol.clone(),
+                        open_i64: price(row, open)?,
+                        high_i64: price(row, high)?,
+                        low_i64: price(row, low)?,
+                        close_i64: price(row, close)?,
+                        volume_u64: integer(row, volume)?,
+                    })
+                })();
+                match record {
+                    Ok(record) => data.push(record),
+                    Err(e) => skip(i, e),
+                }
+            }
+            HistoricalResponse::from_bars(&schema, data).expect("bar schema")
+        }
+    };
+    response.sort_by_time();
+    Ok(Imported {
+        response,
+        skipped,
+        errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(schema: &str) -> ImportSpec {
+        ImportSpec {
+            symbol: "MYDATA".to_string(),
+            schema: schema.to_string(),
+            path: None,
+            format: None,
+            columns: ColumnMapping::default(),
+            time_format: TimeFormat::Auto,
+            fixed_point: false,
+            delimiter: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let table = parse_csv(
+            "\u{feff}time,note,px\r\n1,\"a, \"\"quoted\"\" note\",2.5\n\n2,,3",
+            ',',
+        )
+        .unwrap();
+        assert_eq!(table.columns, ["time", "note", "px"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[0][1], "a, \"quoted\" note");
+        assert_eq!(table.rows[1], ["2", "", "3"]);
+        assert!(parse_csv("a\n\"open", ',').is_err());
+        assert!(parse_csv("", ',').is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let ns = 1_704_205_800_000_000_000;
+        for cell in [
+            "1704205800",
+            "1704205800000",
+            "1704205800000000",
+            "1704205800000000000",
+            "2024-01-02T14:30:00Z",
+            "2024-01-02T09:30:00-05:00",
+            "2024-01-02 14:30:00",
+            "2024-01-02 14:30:00.000",
+        ] {
+            assert_eq!(parse_time(cell, TimeFormat::Auto), Ok(ns), "{}", cell);
+        }
+        assert_eq!(
+            parse_time("2024-01-02", TimeFormat::Auto),
+            Ok(1_704_153_600_000_000_000)
+        );
+        assert_eq!(parse_time("1704205800000", TimeFormat::UnixMs), Ok(ns));
+        assert!(parse_time("1704205800", TimeFormat::Rfc3339).is_err());
+        assert!(parse_time("yesterday", TimeFormat::Auto).is_err());
+    }
+
+    #[test]
+    fn test_to_records() {
+        let table = parse_csv(
+            "when,last,qty\n2024-01-02T14:30:01Z,4500.25,3\n2024-01-02T14:30:00Z,4500,1.0\nbad,1,1\n",
+            ',',
+        )
+        .unwrap();
+        let mut trades = spec("trades");
+        trades.columns.ts_event = "when".to_string();
+        trades.columns.price = "last".to_string();
+        trades.columns.size = "qty".to_string();
+        let imported = to_records(&table, &trades).unwrap();
+        assert_eq!(imported.skipped, 1);
+        assert_eq!(imported.errors, ["row 4: invalid time 'bad'"]);
+        let HistoricalResponse::Trades { data } = imported.response else {
+            panic!("expected trades");
+        };
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].price_i64, 4_500_000_000_000);
+        assert_eq!(data[1].price_i64, 4_500_250_000_000);
+        assert_eq!(data[1].size_u32, 3);
+        assert_eq!(data[0].symbol, "MYDATA");
+
+        let bars = parse_csv(
+            "ts_event,open,high,low,close,volume\n1704205800,1,2,0.5,1.5,10",
+            ',',
+        )
+        .unwrap();
+        let imported = to_records(&bars, &spec("ohlcv-1m")).unwrap();
+        assert_eq!(imported.response.len(), 1);
+        assert!(matches!(
+            imported.response,
+            HistoricalResponse::Ohlcv1M { .. }
+        ));
+
+        // A missing column or schema that can't be imported fails it all
+        let err = to_records(&bars, &spec("trades")).unwrap_err();
+        assert!(err.contains("No column 'price'"), "{}", err);
+        assert!(to_records(&bars, &spec("cvd-1m")).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Integrity checks over fetched historical data.
+//!
+//! [`check`] looks for the usual signs of bad or missing data: timestamps
+//! going backwards, repeated records, bars whose high and low don't bracket
+//! their open and close, and missing bars between a symbol's first and last.
+//! Records are checked per symbol in the order they were received.
+
+use crate::aggregate::{NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use crate::{HistoricalResponse, OhlcvRecord, TradeRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Most issues listed in a report; the counts cover all of them.
+pub const MAX_ISSUES: usize = 1_000;
+
+/// A kind of integrity problem.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A record is timestamped before the one preceding it
+    NonMonotonic,
+    /// A bar repeats an earlier bar's timestamp, or a trade repeats the
+    /// previous trade exactly
+    Duplicate,
+    /// A bar's high/low don't bracket its open and close
+    InvalidOhlc,
+    /// Bars are missing between two consecutive bars
+    Gap,
+}
+
+/// One problem found in the data.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IntegrityIssue {
+    pub kind: IssueKind,
+    pub symbol: String,
+    /// Timestamp of the offending record (for gaps, the bar before the gap)
+    pub ts_event_unix_ns: u64,
+    pub detail: String,
+}
+
+/// Coverage of one symbol.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SymbolCoverage {
+    pub symbol: String,
+    pub records: usize,
+    pub first_ts_unix_ns: u64,
+    pub last_ts_unix_ns: u64,
+    /// Bars a gapless series from first to last would have (time bar
+    /// schemas only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_bars: Option<u64>,
+    /// `expected_bars` less the distinct bars received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missing_bars: Option<u64>,
+}
+
+/// Response to `POST /api/validate`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IntegrityReport {
+    pub schema: String,
+    pub records: usize,
+    /// True when no issues were found
+    pub passed: bool,
+    /// Number of issues of each kind
+    pub counts: BTreeMap<IssueKind, usize>,
+    /// Issues in the order found, at most [`MAX_ISSUES`]
+    pub issues: Vec<IntegrityIssue>,
+    /// True when more issues were found than are listed
+    pub truncated: bool,
+    pub symbols: Vec<SymbolCoverage>,
+}
+
+/// Collects issues and per-symbol coverage while scanning.
+#[derive(Default)]
+struct Scan {
+    counts: BTreeMap<IssueKind, usize>,
+    issues: Vec<IntegrityIssue>,
+    coverage: BTreeMap<String, SymbolCoverage>,
+}
+
+impl Scan {
+    fn issue(&mut self, kind: IssueKind, symbol: &str, ts: u64, detail: String) {
+        *self.counts.entry(kind).or_default() += 1;
+        if self.issues.len() < MAX_ISSUES {
+            self.issues.push(IntegrityIssue {
+                kind,
+                symbol: symbol.to_string(),
+                ts_event_unix_ns: ts,
+                detail,
+            });
+        }
+    }
+
+    fn cover(&mut self, symbol: &str, ts: u64) {
+        let coverage = self
+            .coverage
+            .entry(symbol.to_string())
+            .or_insert_with(|| SymbolCoverage {
+                symbol: symbol.to_string(),
+                records: 0,
+                first_ts_unix_ns: ts,
+                last_ts_unix_ns: ts,
+                expected_bars: None,
+                missing_bars: None,
+            });
+        coverage.records += 1;
+        coverage.first_ts_unix_ns = coverage.first_ts_unix_ns.min(ts);
+        coverage.last_ts_unix_ns = coverage.last_ts_unix_ns.max(ts);
+    }
+
+    fn into_report(self, schema: &str, records: usize) -> IntegrityReport {
+        let found: usize = self.counts.values().sum();
+        IntegrityReport {
+            schema: schema.to_string(),
+            records,
+            passed: found == 0,
+            truncated: found > self.issues.len(),
+            counts: self.counts,
+            issues: self.issues,
+            symbols: self.coverage.into_values().collect(),
+        }
+    }
+}
+
+/// Run every check that applies to `response`'s schema.
+pub fn check(response: &HistoricalResponse) -> IntegrityReport {
+    let schema = response.schema();
+    let mut scan = Scan::default();
+    match response {
+        HistoricalResponse::Trades { data } => check_trades(&mut scan, data),
+        HistoricalResponse::Ohlcv1S { data } | HistoricalResponse::Cvd1S { data } => {
+            check_bars(&mut scan, data, Some(NANOS_PER_SECOND))
+        }
+        HistoricalResponse::Ohlcv1M { data } | HistoricalResponse::Cvd1M { data } => {
+            check_bars(&mut scan, data, Some(NANOS_PER_MINUTE))
+        }
+        // Activity bars have no fixed spacing to find gaps in
+        HistoricalResponse::Bars { data, .. } => check_bars(&mut scan, data, None),
+    }
+    scan.into_report(&schema.as_str(), response.len())
+}
+
+fn check_trades(scan: &mut Scan, trades: &[TradeRecord]) {
+    let mut previous: HashMap<&str, &TradeRecord> = HashMap::new();
+    for trade in trades {
+        let ts = trade.ts_event_unix_ns;
+        if let Some(prev) = previous.insert(&trade.symbol, trade) {
+            if ts < prev.ts_event_unix_ns {
+                scan.issue(
+                    IssueKind::NonMonotonic,
+                    &trade.symbol,
+                    ts,
+                    format!("{}ns before the previous trade", prev.ts_event_unix_ns - ts),
+                );
+            } else if ts == prev.ts_event_unix_ns
+                && trade.price_i64 == prev.price_i64
+                && trade.size_u32 == prev.size_u32
+            {
+                scan.issue(
+                    IssueKind::Duplicate,
+                    &trade.symbol,
+                    ts,
+                    "same time, price and size as the previous trade".to_string(),
+                );
+            }
+        }
+        scan.cover(&trade.symbol, ts);
+    }
+}
+
+fn check_bars(scan: &mut Scan, bars: &[OhlcvRecord], interval_ns: Option<u64>) {
+    let mut previous: HashMap<&str, u64> = HashMap::new();
+    let mut seen: HashMap<&str, HashSet<u64>> = HashMap::new();
+    for bar in bars {
+        let ts = bar.ts_event_unix_ns;
+        let symbol = bar.symbol.as_str();
+
+        if !seen.entry(symbol).or_default().insert(ts) {
+            scan.issue(
+                IssueKind::Duplicate,
+                symbol,
+                ts,
+                "bar timestamp already seen".to_string(),
+            );
+        }
+        if let Some(prev) = previous.insert(symbol, ts) {
+            if ts < prev {
+                scan.issue(
+                    IssueKind::NonMonotonic,
+                    symbol,
+                    ts,
+                    format!("{}ns before the previous bar", prev - ts),
+                );
+            } else if let Some(interval_ns) = interval_ns.filter(|&i| ts - prev > i) {
+                let missing = (ts - prev) / interval_ns - 1;
+                scan.issue(
+                    IssueKind::Gap,
+                    symbol,
+                    prev,
+                    format!("{} bar(s) missing before {}", missing, ts),
+                );
+            }
+        }
+        if bar.low_i64 > bar.high_i64
+            || bar.high_i64 < bar.open_i64.max(bar.close_i64)
+            || bar.low_i64 > bar.open_i64.min(bar.close_i64)
+        {
+            scan.issue(
+                IssueKind::InvalidOhlc,
+                symbol,
+                ts,
+                format!(
+                    "open {} high {} low {} close {}",
+                    bar.open_i64, bar.high_i64, bar.low_i64, bar.close_i64
+                ),
+            );
+        }
+        scan.cover(symbol, ts);
+    }
+
+    let Some(interval_ns) = interval_ns else {
+        return;
+    };
+    for coverage in scan.coverage.values_mut() {
+        let expected = (coverage.last_ts_unix_ns - coverage.first_ts_unix_ns) / interval_ns + 1;
+        let distinct = seen.get(coverage.symbol.as_str()).map_or(0, HashSet::len) as u64;
+        coverage.expected_bars = Some(expected);
+        coverage.missing_bars = Some(expected.saturating_sub(distinct));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(ts_min: u64, open: i64, high: i64, low: i64, close: i64) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: ts_min * NANOS_PER_MINUTE,
+            symbol: "ES.FUT".to_string(),
+            open_i64: open,
+            high_i64: high,
+            low_i64: low,
+            close_i64: close,
+            volume_u64: 10,
+        }
+    }
+
+    fn trade(ts: u64, price: i64) -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ES.FUT".to_string(),
+            price_i64: price,
+            size_u32: 1,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    #[test]
+    fn test_clean_bars_pass() {
+        let data = (0..5).map(|m| bar(m, 10, 12, 9, 11)).collect();
+        let report = check(&HistoricalResponse::Ohlcv1M { data });
+        assert!(report.passed, "{:?}", report);
+        assert_eq!(report.symbols[0].expected_bars, Some(5));
+        assert_eq!(report.symbols[0].missing_bars, Some(0));
+    }
+
+    #[test]
+    fn test_bar_issues() {
+        let data = vec![
+            bar(0, 10, 12, 9, 11),
+            bar(1, 10, 9, 8, 11),  // high below close
+            bar(4, 10, 12, 9, 11), // 2 bars missing
+            bar(4, 10, 12, 9, 11), // duplicate
+            bar(3, 10, 12, 9, 11), // backwards
+        ];
+        let report = check(&HistoricalResponse::Ohlcv1M { data });
+        assert!(!report.passed);
+        assert_eq!(report.counts[&IssueKind::InvalidOhlc], 1);
+        assert_eq!(report.counts[&IssueKind::Gap], 1);
+        assert_eq!(report.counts[&IssueKind::Duplicate], 1);
+        assert_eq!(report.counts[&IssueKind::NonMonotonic], 1);
+        assert!(report.issues[1].detail.starts_with("2 bar(s) missing"));
+        // Minutes 0-4 with 0, 1, 3 and 4 present
+        assert_eq!(report.symbols[0].missing_bars, Some(1));
+    }
+
+    #[test]
+    fn test_trade_issues() {
+        let data = vec![trade(100, 5), trade(100, 5), trade(90, 5), trade(95, 6)];
+        let report = check(&HistoricalResponse::Trades { data });
+        assert_eq!(report.counts[&IssueKind::Duplicate], 1);
+        assert_eq!(report.counts[&IssueKind::NonMonotonic], 1);
+        assert!(report.symbols[0].expected_bars.is_none());
+        assert_eq!(report.symbols[0].first_ts_unix_ns, 90);
+    }
+}
@@ -0,0 +1,150 @@
+//! Types for scheduled recurring data pulls.
+
+use crate::HistoricalRequest;
+use serde::{Deserialize, Serialize};
+
+/// File format written by a scheduled job.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Parquet,
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// File extension for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Time range a job pulls, resolved when the job runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobRange {
+    /// From midnight in the job's timezone until the run time
+    #[default]
+    Today,
+    /// A lookback ending at the run time, e.g. `{"last": "2h"}`
+    Last(String),
+}
+
+/// A recurring historical data pull.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSpec {
+    /// Letters, digits, '-' and '_' (max 64 chars)
+    pub name: String,
+    /// Cron expression with a seconds field, e.g. "0 0 17 * * Mon-Fri"
+    pub cron: String,
+    /// IANA timezone for `cron` and `today` (e.g. "America/Chicago")
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Request template; its start and end are replaced by `range`
+    pub request: HistoricalRequest,
+    #[serde(default)]
+    pub range: JobRange,
+    /// Output directory, relative to the server's data directory.
+    /// Defaults to the job name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<String>,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+/// Outcome of a job run.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Succeeded,
+    Failed,
+    /// Stopped through `DELETE /api/jobs/:name/run` or by removing the job
+    Cancelled,
+}
+
+/// Record of a single job run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRun {
+    pub job: String,
+    /// Run start time in RFC3339 format
+    pub started_at: String,
+    /// Run end time in RFC3339 format
+    pub finished_at: String,
+    pub status: JobStatus,
+    /// Records written
+    pub records: usize,
+    /// File written, relative to the data directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Manifest written beside the output, relative to the data directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How a job run's output was made, saved beside it as
+/// `<output>.manifest.json` so the file can be reproduced and checked later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobManifest {
+    pub job: String,
+    /// Run start time in RFC3339 format
+    pub started_at: String,
+    /// The request as run, with the start and end its range resolved to
+    pub request: HistoricalRequest,
+    /// Provider the data came from, e.g. "databento"
+    pub provider: String,
+    /// Version of the server that wrote the file
+    pub server_version: String,
+    /// Version of the record fields written (see `GET /api/version`)
+    pub wire_version: u32,
+    pub format: ExportFormat,
+    /// File written, relative to the data directory
+    pub output: String,
+    pub records: usize,
+    /// Size of the file written
+    pub bytes: u64,
+    /// SHA-256 of the file written, hex-encoded
+    pub sha256: String,
+    /// SHA-256 of the records as JSON, hex-encoded. Unlike `sha256` it
+    /// doesn't depend on the format, so a rerun of the request can be
+    /// compared whatever it's written as.
+    pub records_sha256: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_spec_defaults() {
+        let spec: JobSpec = serde_json::from_str(
+            r#"{
+                "name": "es-close",
+                "cron": "0 0 17 * * Mon-Fri",
+                "request": {
+                    "symbols": ["ES.FUT"],
+                    "schema": "ohlcv-1m",
+                    "start_rfc3339": "",
+                    "end_rfc3339": ""
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(spec.timezone, "UTC");
+        assert_eq!(spec.range, JobRange::Today);
+        assert_eq!(spec.format, ExportFormat::Parquet);
+
+        let range: JobRange = serde_json::from_str(r#"{"last":"2h"}"#).unwrap();
+        assert_eq!(range, JobRange::Last("2h".to_string()));
+    }
+}
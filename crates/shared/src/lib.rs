@@ -3,25 +3,106 @@
 //! These types are used by both the backend and can be serialized to JSON
 //! for the frontend.
 
-use serde::{Deserialize, Serialize};
+pub mod activity;
+pub mod aggregate;
+pub mod book;
+pub mod calendar;
+pub mod change;
+pub mod close;
+pub mod compact;
+pub mod connections;
+pub mod depth;
+pub mod diff;
+pub mod flow;
+pub mod fragment;
+pub mod imports;
+pub mod integrity;
+pub mod jobs;
+pub mod metrics;
+pub mod paper;
+pub mod positions;
+pub mod price;
+pub mod profile;
+pub mod projection;
+pub mod querylog;
+pub mod recordings;
+pub mod roll;
+pub mod rollups;
+pub mod scenarios;
+pub mod sessions;
+pub mod stats;
+pub mod status;
+pub mod storage;
+pub mod summary;
+pub mod tape;
+pub mod tca;
+pub mod ticks;
+pub mod timeexpr;
+pub mod transform;
+pub mod uploads;
+pub mod usage;
+pub mod users;
+pub mod validation;
+pub mod webhooks;
+pub mod wire;
 
-/// Supported schema types for market data queries.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case")]
+use aggregate::BarSpec;
+use price::PricesAs;
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Supported schema types for market data queries. Serialized as its
+/// name, e.g. `"ohlcv-1m"` or `"tickbar-500"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Schema {
     Trades,
-    #[serde(rename = "ohlcv-1s")]
     Ohlcv1S,
-    #[serde(rename = "ohlcv-1m")]
     Ohlcv1M,
+    /// Cumulative volume delta bars, derived from side-tagged trades
+    Cvd1S,
+    Cvd1M,
+    /// Tick, volume or dollar bars, derived from trades
+    Bars(BarSpec),
+    /// The top ten book levels after each update; live only, sent as
+    /// snapshots and diffs (see [`depth`])
+    Mbp10,
+    /// Every order event; books are rebuilt from them (see [`book`]) and
+    /// sent live like mbp-10, or as of a time by `GET /api/book`
+    Mbo,
 }
 
 impl Schema {
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> Cow<'static, str> {
+        match self {
+            Schema::Trades => "trades".into(),
+            Schema::Ohlcv1S => "ohlcv-1s".into(),
+            Schema::Ohlcv1M => "ohlcv-1m".into(),
+            Schema::Cvd1S => "cvd-1s".into(),
+            Schema::Cvd1M => "cvd-1m".into(),
+            Schema::Bars(spec) => spec.to_string().into(),
+            Schema::Mbp10 => "mbp-10".into(),
+            Schema::Mbo => "mbo".into(),
+        }
+    }
+
+    /// Bar type for derived tick, volume and dollar bar schemas.
+    pub fn bar_spec(&self) -> Option<BarSpec> {
+        match self {
+            Schema::Bars(spec) => Some(*spec),
+            _ => None,
+        }
+    }
+
+    /// Bar width for derived CVD schemas, `None` for upstream schemas.
+    pub fn cvd_interval_ns(&self) -> Option<u64> {
         match self {
-            Schema::Trades => "trades",
-            Schema::Ohlcv1S => "ohlcv-1s",
-            Schema::Ohlcv1M => "ohlcv-1m",
+            Schema::Cvd1S => Some(aggregate::NANOS_PER_SECOND),
+            Schema::Cvd1M => Some(aggregate::NANOS_PER_MINUTE),
+            _ => None,
         }
     }
 }
@@ -34,24 +115,161 @@ impl std::str::FromStr for Schema {
             "trades" => Ok(Schema::Trades),
             "ohlcv-1s" => Ok(Schema::Ohlcv1S),
             "ohlcv-1m" => Ok(Schema::Ohlcv1M),
+            "cvd-1s" => Ok(Schema::Cvd1S),
+            "cvd-1m" => Ok(Schema::Cvd1M),
+            "mbp-10" => Ok(Schema::Mbp10),
+            "mbo" => Ok(Schema::Mbo),
+            _ => s.parse().map(Schema::Bars).map_err(|_| {
+                format!(
+                    "Invalid schema: {}. Expected: trades, ohlcv-1s, ohlcv-1m, cvd-1s, cvd-1m, \
+                     tickbar-N, volbar-N, dollarbar-N, mbp-10, or mbo",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_str())
+    }
+}
+
+impl Serialize for Schema {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Schema {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = Cow::<str>::deserialize(deserializer)?;
+        name.parse().map_err(de::Error::custom)
+    }
+}
+
+/// How request symbols are interpreted upstream (DataBento's `stype_in`).
+/// Written as DataBento spells it, e.g. `raw_symbol`; the kebab-case
+/// spellings (`raw-symbol`, `instrument-id`) are read too.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolType {
+    /// Product groups, e.g. "ES.FUT"
+    #[default]
+    Parent,
+    /// Continuous contracts, e.g. "ES.c.0"
+    Continuous,
+    /// Venue symbols, e.g. "ESZ4"
+    RawSymbol,
+    /// Numeric instrument IDs, e.g. "4916"; records are labelled with the
+    /// ID itself rather than a resolved symbol
+    InstrumentId,
+}
+
+impl SymbolType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SymbolType::Parent => "parent",
+            SymbolType::Continuous => "continuous",
+            SymbolType::RawSymbol => "raw_symbol",
+            SymbolType::InstrumentId => "instrument_id",
+        }
+    }
+
+    /// Check that `symbols` are well-formed for this symbol type: instrument
+    /// IDs must be unsigned 32-bit integers.
+    pub fn validate(&self, symbols: &[String]) -> Result<(), String> {
+        if *self == SymbolType::InstrumentId {
+            if let Some(bad) = symbols.iter().find(|s| s.parse::<u32>().is_err()) {
+                return Err(format!(
+                    "Invalid instrument ID '{}': expected an unsigned integer",
+                    bad
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for SymbolType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parent" => Ok(SymbolType::Parent),
+            "continuous" => Ok(SymbolType::Continuous),
+            "raw_symbol" | "raw-symbol" => Ok(SymbolType::RawSymbol),
+            "instrument_id" | "instrument-id" => Ok(SymbolType::InstrumentId),
             _ => Err(format!(
-                "Invalid schema: {}. Expected: trades, ohlcv-1s, or ohlcv-1m",
+                "Invalid stype_in: {}. Expected: parent, continuous, raw_symbol, or instrument_id",
                 s
             )),
         }
     }
 }
 
+impl fmt::Display for SymbolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SymbolType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = Cow::<str>::deserialize(deserializer)?;
+        name.parse().map_err(de::Error::custom)
+    }
+}
+
+/// How the records of a multi-symbol response are ordered.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordOrder {
+    /// Interleaved chronologically
+    #[default]
+    Time,
+    /// Grouped by symbol, in symbol order, each group chronological
+    Symbol,
+}
+
+impl RecordOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordOrder::Time => "time",
+            RecordOrder::Symbol => "symbol",
+        }
+    }
+
+    fn is_time(&self) -> bool {
+        *self == RecordOrder::Time
+    }
+}
+
+impl std::str::FromStr for RecordOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "time" => Ok(RecordOrder::Time),
+            "symbol" => Ok(RecordOrder::Symbol),
+            _ => Err(format!("Invalid order: {}. Expected: time or symbol", s)),
+        }
+    }
+}
+
 /// Request for historical market data.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoricalRequest {
     /// Symbols to query (e.g., ["ES.FUT", "CL.FUT"])
     pub symbols: Vec<String>,
-    /// Data schema: "trades", "ohlcv-1s", or "ohlcv-1m"
+    /// Data schema: "trades", "ohlcv-1s", "ohlcv-1m", "cvd-1s", "cvd-1m", or
+    /// derived bars such as "tickbar-500" (see [`Schema`])
     pub schema: String,
-    /// Symbol type input (e.g., "parent", "raw_symbol")
-    #[serde(default = "default_stype_in")]
-    pub stype_in: String,
+    /// Symbol type input: "parent" (default), "continuous", "raw_symbol",
+    /// or "instrument_id"
+    #[serde(default)]
+    pub stype_in: SymbolType,
     /// Start time in RFC3339 format
     pub start_rfc3339: String,
     /// End time in RFC3339 format
@@ -59,16 +277,109 @@ pub struct HistoricalRequest {
     /// Maximum number of records to return
     #[serde(default = "default_limit")]
     pub limit: u32,
+    /// Maximum number of records to return per symbol, as labelled in the
+    /// records. `limit` still caps the total and is shared evenly between
+    /// symbols when it binds, so one busy symbol can't crowd out the rest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_per_symbol: Option<u32>,
+    /// Upstream dataset (e.g., "GLBX.MDP3"); routed from the symbols when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<String>,
+    /// Join each trade with the prevailing BBO (trades schema only, needs mbp-1 data)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quote_at_trade: bool,
+    /// Return only the number of matching records (see [`RecordCount`])
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub count_only: bool,
+    /// Record fields to return, e.g. `["ts_event", "close", "volume"]`; all
+    /// fields when empty (see [`projection`])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// Where bars built by the server start: `epoch` (default),
+    /// `session 08:30 America/Chicago`, or `offset 30m` (see
+    /// [`aggregate::BarAlign`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub align: Option<String>,
+    /// Transform bar responses into `heikin-ashi` candles or `renko-N`
+    /// bricks of N ticks (see [`transform::Transform`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    /// Interleave records by `time` (default) or group them by `symbol`
+    #[serde(default, skip_serializing_if = "RecordOrder::is_time")]
+    pub order: RecordOrder,
+    /// Send records with short field names (`t`, `s`, `p`, `q`, ...; see
+    /// [`compact`])
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compact: bool,
+    /// Write prices and volumes as `number` (default), `string` or
+    /// `decimal` (see [`price::PricesAs`])
+    #[serde(default, skip_serializing_if = "PricesAs::is_number")]
+    pub prices_as: PricesAs,
+    /// Serve the finest of trades, `ohlcv-1s`, `ohlcv-1m` and hourly bars
+    /// (starting from `schema`) that fits the range in `limit` records; the
+    /// choice is reported as [`NormalizedResponse::resolution`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_resolution: bool,
+    /// Upstream revision to serve, as reported in a response's
+    /// `meta.revision` (see [`DataVersion::revision`]): from the cache if it
+    /// was cached at that revision, else only if the provider hasn't
+    /// restated the range since. Providers that don't version their data
+    /// ignore it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+impl Default for HistoricalRequest {
+    fn default() -> Self {
+        Self {
+            symbols: Vec::new(),
+            schema: Schema::Trades.as_str().to_string(),
+            stype_in: SymbolType::default(),
+            start_rfc3339: String::new(),
+            end_rfc3339: String::new(),
+            limit: default_limit(),
+            limit_per_symbol: None,
+            dataset: None,
+            quote_at_trade: false,
+            count_only: false,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            order: RecordOrder::Time,
+            compact: false,
+            prices_as: PricesAs::Number,
+            auto_resolution: false,
+            revision: None,
+        }
+    }
 }
 
-fn default_stype_in() -> String {
-    "parent".to_string()
+impl HistoricalRequest {
+    /// The request's bar alignment, epoch-anchored when unset.
+    pub fn bar_align(&self) -> Result<aggregate::BarAlign, String> {
+        self.align
+            .as_deref()
+            .map_or(Ok(aggregate::BarAlign::Epoch), str::parse)
+    }
+
+    /// The request's bar transform, if any.
+    pub fn bar_transform(&self) -> Result<Option<transform::Transform>, String> {
+        self.transform.as_deref().map(str::parse).transpose()
+    }
 }
 
 fn default_limit() -> u32 {
     1000
 }
 
+/// A historical request template saved under a name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedQuery {
+    /// Letters, digits, '-' and '_' (max 64 chars)
+    pub name: String,
+    pub request: HistoricalRequest,
+}
+
 /// A single trade record.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeRecord {
@@ -80,10 +391,57 @@ pub struct TradeRecord {
     pub price_i64: i64,
     /// Trade size
     pub size_u32: u32,
+    /// Prevailing BBO, present when requested with `quote_at_trade`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<QuoteAtTrade>,
+    /// Venue sequence number, when the provider supplies one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_u32: Option<u32>,
+}
+
+/// Where a trade printed relative to the prevailing best bid/ask.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    /// At or below the bid (seller-initiated)
+    Bid,
+    /// At or above the ask (buyer-initiated)
+    Ask,
+    /// Strictly inside the spread
+    Mid,
+}
+
+/// Best bid/ask prevailing when a trade printed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuoteAtTrade {
+    /// Best bid price as fixed-point integer
+    pub bid_px_i64: i64,
+    /// Best ask price as fixed-point integer
+    pub ask_px_i64: i64,
+    /// Side of the book the trade printed at
+    pub side: TradeSide,
+}
+
+impl QuoteAtTrade {
+    /// Tag a trade at `price` against the given BBO.
+    pub fn classify(price: i64, bid_px_i64: i64, ask_px_i64: i64) -> Self {
+        let side = if price <= bid_px_i64 {
+            TradeSide::Bid
+        } else if price >= ask_px_i64 {
+            TradeSide::Ask
+        } else {
+            TradeSide::Mid
+        };
+        Self {
+            bid_px_i64,
+            ask_px_i64,
+            side,
+        }
+    }
 }
 
 /// A single OHLCV bar record.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct OhlcvRecord {
     /// Timestamp in nanoseconds since Unix epoch (bar open time)
     pub ts_event_unix_ns: u64,
@@ -115,19 +473,596 @@ pub struct OhlcvResponse {
     pub data: Vec<OhlcvRecord>,
 }
 
+/// Response to a `count_only` historical request: how many records the
+/// request matches, regardless of its `limit`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecordCount {
+    pub schema: String,
+    pub count: u64,
+}
+
 /// Unified historical response that can contain either trades or OHLCV data.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "schema")]
+///
+/// Serialized as `{"schema": "...", "data": [...]}`, with the schema named
+/// as in the request.
+#[derive(Debug, Clone)]
 pub enum HistoricalResponse {
-    #[serde(rename = "trades")]
-    Trades { data: Vec<TradeRecord> },
-    #[serde(rename = "ohlcv-1s")]
-    Ohlcv1S { data: Vec<OhlcvRecord> },
-    #[serde(rename = "ohlcv-1m")]
-    Ohlcv1M { data: Vec<OhlcvRecord> },
+    Trades {
+        data: Vec<TradeRecord>,
+    },
+    Ohlcv1S {
+        data: Vec<OhlcvRecord>,
+    },
+    Ohlcv1M {
+        data: Vec<OhlcvRecord>,
+    },
+    /// CVD bars; see [`aggregate::cumulative_delta`] for the value scale
+    Cvd1S {
+        data: Vec<OhlcvRecord>,
+    },
+    Cvd1M {
+        data: Vec<OhlcvRecord>,
+    },
+    /// Tick, volume or dollar bars; see [`aggregate::threshold_bars`]
+    Bars {
+        spec: BarSpec,
+        data: Vec<OhlcvRecord>,
+    },
+}
+
+impl HistoricalResponse {
+    /// An empty response for `schema`.
+    pub fn empty(schema: &Schema) -> Self {
+        Self::from_bars(schema, Vec::new())
+            .unwrap_or(HistoricalResponse::Trades { data: Vec::new() })
+    }
+
+    /// A response of bar `schema` holding `data`, or `None` for trades and
+    /// depth.
+    pub fn from_bars(schema: &Schema, data: Vec<OhlcvRecord>) -> Option<Self> {
+        Some(match schema {
+            Schema::Trades | Schema::Mbp10 | Schema::Mbo => return None,
+            Schema::Ohlcv1S => HistoricalResponse::Ohlcv1S { data },
+            Schema::Ohlcv1M => HistoricalResponse::Ohlcv1M { data },
+            Schema::Cvd1S => HistoricalResponse::Cvd1S { data },
+            Schema::Cvd1M => HistoricalResponse::Cvd1M { data },
+            Schema::Bars(spec) => HistoricalResponse::Bars { spec: *spec, data },
+        })
+    }
+
+    /// Schema of the records in this response.
+    pub fn schema(&self) -> Schema {
+        match self {
+            HistoricalResponse::Trades { .. } => Schema::Trades,
+            HistoricalResponse::Ohlcv1S { .. } => Schema::Ohlcv1S,
+            HistoricalResponse::Ohlcv1M { .. } => Schema::Ohlcv1M,
+            HistoricalResponse::Cvd1S { .. } => Schema::Cvd1S,
+            HistoricalResponse::Cvd1M { .. } => Schema::Cvd1M,
+            HistoricalResponse::Bars { spec, .. } => Schema::Bars(*spec),
+        }
+    }
+
+    /// Number of records.
+    pub fn len(&self) -> usize {
+        match self {
+            HistoricalResponse::Trades { data } => data.len(),
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Move the records of `other` into this response.
+    /// Records of a different schema are ignored.
+    pub fn append(&mut self, other: HistoricalResponse) {
+        match (self, other) {
+            (HistoricalResponse::Trades { data }, HistoricalResponse::Trades { data: more }) => {
+                data.extend(more)
+            }
+            (HistoricalResponse::Ohlcv1S { data }, HistoricalResponse::Ohlcv1S { data: more })
+            | (HistoricalResponse::Ohlcv1M { data }, HistoricalResponse::Ohlcv1M { data: more })
+            | (HistoricalResponse::Cvd1S { data }, HistoricalResponse::Cvd1S { data: more })
+            | (HistoricalResponse::Cvd1M { data }, HistoricalResponse::Cvd1M { data: more }) => {
+                data.extend(more)
+            }
+            (
+                HistoricalResponse::Bars { spec, data },
+                HistoricalResponse::Bars {
+                    spec: other,
+                    data: more,
+                },
+            ) if *spec == other => data.extend(more),
+            _ => {}
+        }
+    }
+
+    /// Stable-sort records by event timestamp.
+    pub fn sort_by_time(&mut self) {
+        match self {
+            HistoricalResponse::Trades { data } => data.sort_by_key(|r| r.ts_event_unix_ns),
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => data.sort_by_key(|r| r.ts_event_unix_ns),
+        }
+    }
+
+    /// Keep the records for which `keep(symbol, ts_event_unix_ns)` holds.
+    pub fn retain(&mut self, keep: impl Fn(&str, u64) -> bool) {
+        match self {
+            HistoricalResponse::Trades { data } => {
+                data.retain(|r| keep(&r.symbol, r.ts_event_unix_ns))
+            }
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => {
+                data.retain(|r| keep(&r.symbol, r.ts_event_unix_ns))
+            }
+        }
+    }
+
+    /// Keep at most `len` records.
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            HistoricalResponse::Trades { data } => data.truncate(len),
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => data.truncate(len),
+        }
+    }
+
+    /// Put time-ordered records in `order`. Grouping by symbol is stable,
+    /// so each symbol's records stay in time order.
+    pub fn order_by(&mut self, order: RecordOrder) {
+        if order == RecordOrder::Time {
+            return;
+        }
+        match self {
+            HistoricalResponse::Trades { data } => data.sort_by(|a, b| a.symbol.cmp(&b.symbol)),
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => data.sort_by(|a, b| a.symbol.cmp(&b.symbol)),
+        }
+    }
+
+    /// Keep at most `limit` records, and at most `per_symbol` of each
+    /// symbol if given. Records should be in time order; the earliest of
+    /// each symbol are kept, with `limit` divided by [`fair_shares`].
+    pub fn apply_limits(&mut self, limit: u32, per_symbol: Option<u32>) {
+        match self {
+            HistoricalResponse::Trades { data } => {
+                limit_fairly(data, |r| &r.symbol, limit, per_symbol)
+            }
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => {
+                limit_fairly(data, |r| &r.symbol, limit, per_symbol)
+            }
+        }
+    }
+
+    /// Number of records of each symbol.
+    pub fn records_by_symbol(&self) -> BTreeMap<String, usize> {
+        fn count<'a>(symbols: impl Iterator<Item = &'a String>) -> BTreeMap<String, usize> {
+            let mut counts = BTreeMap::new();
+            for symbol in symbols {
+                *counts.entry(symbol.clone()).or_insert(0) += 1;
+            }
+            counts
+        }
+        match self {
+            HistoricalResponse::Trades { data } => count(data.iter().map(|r| &r.symbol)),
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => count(data.iter().map(|r| &r.symbol)),
+        }
+    }
+
+    /// Earliest and latest timestamps of a time-ordered response.
+    pub fn time_span(&self) -> Option<(u64, u64)> {
+        let (first, last) = match self {
+            HistoricalResponse::Trades { data } => (
+                data.first()?.ts_event_unix_ns,
+                data.last()?.ts_event_unix_ns,
+            ),
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => (
+                data.first()?.ts_event_unix_ns,
+                data.last()?.ts_event_unix_ns,
+            ),
+        };
+        Some((first, last))
+    }
+
+    /// Whether this response, as fetched under `limit` and `per_symbol`,
+    /// reached either, so more records may exist than it holds.
+    pub fn reached_limits(&self, limit: u32, per_symbol: Option<u32>) -> bool {
+        self.len() >= limit as usize
+            || per_symbol.is_some_and(|per_symbol| {
+                self.records_by_symbol()
+                    .values()
+                    .any(|&count| count >= per_symbol as usize)
+            })
+    }
+
+    /// The records of a bar response, or `None` for trades.
+    pub fn bars_mut(&mut self) -> Option<&mut Vec<OhlcvRecord>> {
+        match self {
+            HistoricalResponse::Trades { .. } => None,
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => Some(data),
+        }
+    }
+
+    /// Sort records by event time and drop exact duplicates, returning how
+    /// many were dropped. Trades sharing a timestamp are ordered by sequence
+    /// number; the sort is stable, so records are otherwise kept in the
+    /// order they were fetched.
+    ///
+    /// A trade duplicates an earlier one with the same timestamp, symbol,
+    /// price, size and sequence number. Trades without a sequence number are
+    /// never dropped, since an identical trade can be a genuine second fill.
+    /// A bar duplicates an earlier bar identical in every field.
+    pub fn normalize(&mut self) -> usize {
+        match self {
+            HistoricalResponse::Trades { data } => {
+                data.sort_by_key(|r| (r.ts_event_unix_ns, r.sequence_u32));
+                dedup_by_time(
+                    data,
+                    |r| r.ts_event_unix_ns,
+                    |a, b| {
+                        a.sequence_u32.is_some()
+                            && a.sequence_u32 == b.sequence_u32
+                            && a.symbol == b.symbol
+                            && a.price_i64 == b.price_i64
+                            && a.size_u32 == b.size_u32
+                    },
+                )
+            }
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => {
+                data.sort_by_key(|r| r.ts_event_unix_ns);
+                dedup_by_time(data, |r| r.ts_event_unix_ns, |a, b| a == b)
+            }
+        }
+    }
+}
+
+impl Serialize for HistoricalResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("schema", &self.schema())?;
+        match self {
+            HistoricalResponse::Trades { data } => map.serialize_entry("data", data)?,
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => map.serialize_entry("data", data)?,
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for HistoricalResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ResponseVisitor)
+    }
+}
+
+/// Reads `data` with the record type `schema` names. `schema` normally
+/// comes first; `data` seen before it is buffered.
+struct ResponseVisitor;
+
+impl<'de> Visitor<'de> for ResponseVisitor {
+    type Value = HistoricalResponse;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a historical response with schema and data")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut schema: Option<Schema> = None;
+        let mut response = None;
+        let mut early_data: Option<serde_json::Value> = None;
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            match (key.as_ref(), &schema) {
+                ("schema", _) => schema = Some(map.next_value()?),
+                ("data", Some(Schema::Trades)) => {
+                    response = Some(HistoricalResponse::Trades {
+                        data: map.next_value()?,
+                    })
+                }
+                ("data", Some(schema)) => {
+                    response = HistoricalResponse::from_bars(schema, map.next_value()?)
+                }
+                ("data", None) => early_data = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+
+        let schema = schema.ok_or_else(|| de::Error::missing_field("schema"))?;
+        if let Some(response) = response {
+            return Ok(response);
+        }
+        let data = early_data.ok_or_else(|| de::Error::missing_field("data"))?;
+        let response = match &schema {
+            Schema::Trades => HistoricalResponse::Trades {
+                data: serde_json::from_value(data).map_err(de::Error::custom)?,
+            },
+            _ => HistoricalResponse::from_bars(
+                &schema,
+                serde_json::from_value(data).map_err(de::Error::custom)?,
+            )
+            .ok_or_else(|| de::Error::custom("bars for a trades schema"))?,
+        };
+        Ok(response)
+    }
+}
+
+/// Split `total` between parties with `available` items each, evenly
+/// except that a party never gets more than it has; what a small party
+/// can't use goes to the others. Shares are returned in `available`'s
+/// order and sum to `total` or everything available, whichever is less.
+pub fn fair_shares(available: &[usize], total: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..available.len()).collect();
+    order.sort_by_key(|&i| available[i]);
+    let mut shares = vec![0; available.len()];
+    let mut remaining = total;
+    for (done, &i) in order.iter().enumerate() {
+        shares[i] = available[i].min(remaining / (order.len() - done));
+        remaining -= shares[i];
+    }
+    shares
+}
+
+/// Keep the first records of `data` within `limit` overall and
+/// `per_symbol` for each symbol, sharing `limit` fairly between symbols.
+fn limit_fairly<T>(
+    data: &mut Vec<T>,
+    symbol: impl Fn(&T) -> &String,
+    limit: u32,
+    per_symbol: Option<u32>,
+) {
+    let Some(per_symbol) = per_symbol else {
+        data.truncate(limit as usize);
+        return;
+    };
+    let mut index: std::collections::HashMap<String, usize> = Default::default();
+    let mut available: Vec<usize> = Vec::new();
+    for record in data.iter() {
+        let next = index.len();
+        let i = *index.entry(symbol(record).clone()).or_insert(next);
+        if i == available.len() {
+            available.push(0);
+        }
+        available[i] += 1;
+    }
+    for count in &mut available {
+        *count = (*count).min(per_symbol as usize);
+    }
+    let shares = fair_shares(&available, limit as usize);
+    let mut kept = vec![0; shares.len()];
+    data.retain(|record| {
+        let i = index[symbol(record)];
+        kept[i] += 1;
+        kept[i] <= shares[i]
+    });
+}
+
+/// Drop records of time-sorted `data` that `same` matches against an
+/// earlier record with the same timestamp. Returns the number dropped.
+fn dedup_by_time<T>(
+    data: &mut Vec<T>,
+    ts: impl Fn(&T) -> u64,
+    same: impl Fn(&T, &T) -> bool,
+) -> usize {
+    let before = data.len();
+    let mut kept: Vec<T> = Vec::with_capacity(before);
+    // Start of the run of kept records sharing the current timestamp
+    let mut run_start = 0;
+    for record in data.drain(..) {
+        if kept.last().is_some_and(|last| ts(last) != ts(&record)) {
+            run_start = kept.len();
+        }
+        if !kept[run_start..].iter().any(|k| same(k, &record)) {
+            kept.push(record);
+        }
+    }
+    *data = kept;
+    before - data.len()
+}
+
+/// A historical response as served by the API: normalized with
+/// [`HistoricalResponse::normalize`], noting how many duplicates that dropped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizedResponse {
+    #[serde(flatten)]
+    pub response: HistoricalResponse,
+    /// Duplicate records removed while assembling the response
+    #[serde(default)]
+    pub duplicates_dropped: usize,
+    /// Transform applied to the bars, e.g. "renko-4"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    /// Resolution `auto_resolution` picked: a schema, or `ohlcv-1h` for
+    /// minute bars merged into hourly ones (served under `ohlcv-1m`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    /// True when the range held more minute bars than are merged into
+    /// hourly ones, so the hourly bars cover only its start
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub limited: bool,
+}
+
+impl From<HistoricalResponse> for NormalizedResponse {
+    fn from(mut response: HistoricalResponse) -> Self {
+        let duplicates_dropped = response.normalize();
+        Self {
+            response,
+            duplicates_dropped,
+            transform: None,
+            resolution: None,
+            limited: false,
+        }
+    }
+}
+
+/// Where the records of a historical response came from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DataSource {
+    /// The shared historical cache
+    Cache,
+    /// The market data provider
+    Provider,
+}
+
+impl From<querylog::CacheStatus> for DataSource {
+    fn from(status: querylog::CacheStatus) -> Self {
+        match status {
+            querylog::CacheStatus::Hit => DataSource::Cache,
+            querylog::CacheStatus::Miss | querylog::CacheStatus::Bypass => DataSource::Provider,
+        }
+    }
+}
+
+/// When a provider last modified one dataset's data over a request's
+/// range. It changes when the provider restates any of that data.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DataVersion {
+    /// Dataset, e.g. "GLBX.MDP3"
+    pub dataset: String,
+    /// Date of the latest upstream modification, YYYY-MM-DD
+    pub last_modified: String,
+}
+
+impl DataVersion {
+    /// The revision `versions` identify together, e.g.
+    /// `GLBX.MDP3@2024-03-15,XNAS.ITCH@2024-03-12`; `None` when the provider
+    /// reports none.
+    pub fn revision(versions: &[DataVersion]) -> Option<String> {
+        let mut parts: Vec<String> = versions
+            .iter()
+            .map(|v| format!("{}@{}", v.dataset, v.last_modified))
+            .collect();
+        parts.sort();
+        (!parts.is_empty()).then(|| parts.join(","))
+    }
+}
+
+/// What a historical response holds and how it was served, under `meta`
+/// in API v2.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResponseMeta {
+    /// Records in `data`
+    pub records: usize,
+    /// Records in `data` per symbol
+    #[serde(default)]
+    pub records_by_symbol: BTreeMap<String, usize>,
+    /// Whether `limit` or `limit_per_symbol` cut the records short; false
+    /// means the range holds no more
+    #[serde(default)]
+    pub truncated: bool,
+    /// Symbols that couldn't be served; the others are in `data`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbol_errors: Vec<SymbolError>,
+    /// Duplicate records removed while assembling the response
+    #[serde(default)]
+    pub duplicates_dropped: usize,
+    /// Transform applied to the bars, e.g. "renko-4"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    /// Resolution `auto_resolution` picked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    /// True when hourly bars cover only the start of the range (see
+    /// [`NormalizedResponse::limited`])
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub limited: bool,
+    /// The request as served: relative times resolved, a future end
+    /// clamped and the schema `auto_resolution` picked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<HistoricalRequest>,
+    /// Time taken to serve the request
+    #[serde(default)]
+    pub elapsed_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<DataSource>,
+    /// Upstream version of each dataset the records came from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_versions: Vec<DataVersion>,
+    /// Revision served, to pin in a later request's `revision`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+impl From<&NormalizedResponse> for ResponseMeta {
+    fn from(served: &NormalizedResponse) -> Self {
+        Self {
+            records: served.response.len(),
+            records_by_symbol: served.response.records_by_symbol(),
+            duplicates_dropped: served.duplicates_dropped,
+            transform: served.transform.clone(),
+            resolution: served.resolution.clone(),
+            limited: served.limited,
+            ..Default::default()
+        }
+    }
+}
+
+/// A historical response as served by API v2: `{"schema", "data", "meta"}`,
+/// where v1 ([`NormalizedResponse`]) puts the metadata beside the records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalEnvelope {
+    #[serde(flatten)]
+    pub response: HistoricalResponse,
+    pub meta: ResponseMeta,
+}
+
+impl From<NormalizedResponse> for HistoricalEnvelope {
+    fn from(served: NormalizedResponse) -> Self {
+        Self {
+            meta: ResponseMeta::from(&served),
+            response: served.response,
+        }
+    }
 }
 
 /// Message sent over WebSocket for live data.
+///
+/// New message types may be added in later wire versions; a build that
+/// doesn't know a type reads it as [`LiveMessage::Unknown`] rather than
+/// failing, and should skip it. Fields added to existing types are
+/// optional, so older builds read newer messages by ignoring them.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum LiveMessage {
@@ -138,6 +1073,10 @@ pub enum LiveMessage {
         price_i64: i64,
         size_u32: u32,
     },
+    /// Trades that arrived within a few milliseconds of each other, in
+    /// order, on subscriptions with `trade_batch_ms` set.
+    #[serde(rename = "trade_batch")]
+    TradeBatch { trades: Vec<TradeRecord> },
     #[serde(rename = "ohlcv")]
     Ohlcv {
         ts_event_unix_ns: u64,
@@ -147,14 +1086,154 @@ pub enum LiveMessage {
         low_i64: i64,
         close_i64: i64,
         volume_u64: u64,
+        /// Set on bars known to have closed: those from a `bar_feed` stream,
+        /// which only sends a bar once it has closed, and the completed bars
+        /// a subscription's snapshot starts with
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        bar_closed: bool,
+        /// Bar width, e.g. "5m", on the bars of a `bar_feed` with several
+        /// widths or derived from a bar subscription: the channel a client
+        /// sorts them into
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval: Option<String>,
     },
     #[serde(rename = "error")]
     Error { message: String },
+    /// A derived live analytic of `symbol`, e.g. order-flow imbalance over
+    /// `window`.
+    #[serde(rename = "metric")]
+    Metric {
+        symbol: String,
+        name: String,
+        value: f64,
+        window: String,
+    },
+    /// A different contract month now leads its root by volume over
+    /// `window`, e.g. ESZ4 to ESH5 around a quarterly roll.
+    #[serde(rename = "roll_alert")]
+    RollAlert {
+        ts_event_unix_ns: u64,
+        /// Contract root, e.g. "ES"
+        root: String,
+        from_symbol: String,
+        to_symbol: String,
+        from_volume: u64,
+        to_volume: u64,
+        window: String,
+    },
     #[serde(rename = "connected")]
     Connected {
         symbols: Vec<String>,
         schema: String,
+        /// Server-assigned session ID, for `GET /api/connections/{id}/symbols`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        connection_id: Option<u64>,
     },
+    /// The provider resolved a subscribed symbol to a concrete instrument,
+    /// e.g. ES.FUT to ESZ4. Sent as a subscription starts and again when a
+    /// contract rolls.
+    #[serde(rename = "symbol_mapping")]
+    SymbolMapping {
+        instrument_id: u32,
+        /// The symbol as subscribed, e.g. "ES.FUT"
+        stype_in_symbol: String,
+        /// The instrument's raw symbol, e.g. "ESZ4"
+        stype_out_symbol: String,
+    },
+    /// A paper order was accepted, filled or cancelled.
+    #[serde(rename = "paper_order")]
+    PaperOrder(paper::PaperOrder),
+    /// A paper order was filled against a live trade.
+    #[serde(rename = "paper_fill")]
+    PaperFill(paper::PaperFill),
+    /// A paper position after a fill, or marked at a later trade.
+    #[serde(rename = "paper_position")]
+    PaperPosition(paper::PaperPosition),
+    /// A registered position marked at the latest trade in its symbol.
+    #[serde(rename = "position_pnl")]
+    PositionPnl(positions::PositionPnl),
+    /// An economic calendar release is coming up, or has arrived.
+    #[serde(rename = "calendar_event")]
+    CalendarEvent(calendar::CalendarAlert),
+    /// The upstream provider's health changed.
+    #[serde(rename = "provider_status")]
+    ProviderStatus(status::StatusChange),
+    /// Levels of a symbol's developing volume profile that traded.
+    #[serde(rename = "profile_delta")]
+    ProfileDelta(profile::ProfileDelta),
+    /// A symbol's session open, high, low, volume and VWAP so far.
+    #[serde(rename = "day_summary")]
+    DaySummary(summary::DaySummary),
+    /// A symbol's book after one update, as the provider sends it.
+    /// Subscriptions get the snapshots and diffs built from these instead.
+    #[serde(rename = "depth")]
+    Depth(depth::DepthUpdate),
+    /// A symbol's whole book, when it first updates and after a gap.
+    #[serde(rename = "depth_snapshot")]
+    DepthSnapshot(depth::DepthSnapshot),
+    /// Levels of a symbol's book that changed since its last snapshot or
+    /// diff.
+    #[serde(rename = "depth_diff")]
+    DepthDiff(depth::DepthDiff),
+    /// One order event, as the provider sends it. Subscriptions get the
+    /// snapshots and diffs of the books rebuilt from these instead.
+    #[serde(rename = "mbo")]
+    Mbo(book::MboRecord),
+    /// The server changed how far it conflates this connection's messages,
+    /// as an `adaptive` client fell behind or caught up.
+    #[serde(rename = "rate_adjusted")]
+    RateAdjusted {
+        /// Trades, bars, day summaries and metrics are now sent at most
+        /// once per symbol this often; 0 sends every one
+        conflation_ms: u64,
+        previous_ms: u64,
+        /// How long messages were waiting to be sent when it changed
+        lag_ms: u64,
+    },
+    /// One part of a message too large for a single frame.
+    /// Concatenating `data` of parts 0..total yields the original JSON.
+    #[serde(rename = "fragment")]
+    Fragment {
+        id: u64,
+        part: u32,
+        total: u32,
+        data: String,
+    },
+    /// A message of a type this build doesn't know, from a newer server.
+    /// Never sent.
+    #[serde(rename = "unknown", other)]
+    Unknown,
+}
+
+impl LiveMessage {
+    /// The message's `type` tag, e.g. "trade".
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LiveMessage::Trade { .. } => "trade",
+            LiveMessage::TradeBatch { .. } => "trade_batch",
+            LiveMessage::Ohlcv { .. } => "ohlcv",
+            LiveMessage::Error { .. } => "error",
+            LiveMessage::Metric { .. } => "metric",
+            LiveMessage::RollAlert { .. } => "roll_alert",
+            LiveMessage::Connected { .. } => "connected",
+            LiveMessage::SymbolMapping { .. } => "symbol_mapping",
+            LiveMessage::PaperOrder(_) => "paper_order",
+            LiveMessage::PaperFill(_) => "paper_fill",
+            LiveMessage::PaperPosition(_) => "paper_position",
+            LiveMessage::PositionPnl(_) => "position_pnl",
+            LiveMessage::CalendarEvent(_) => "calendar_event",
+            LiveMessage::ProviderStatus(_) => "provider_status",
+            LiveMessage::ProfileDelta(_) => "profile_delta",
+            LiveMessage::DaySummary(_) => "day_summary",
+            LiveMessage::Depth(_) => "depth",
+            LiveMessage::DepthSnapshot(_) => "depth_snapshot",
+            LiveMessage::DepthDiff(_) => "depth_diff",
+            LiveMessage::Mbo(_) => "mbo",
+            LiveMessage::RateAdjusted { .. } => "rate_adjusted",
+            LiveMessage::Fragment { .. } => "fragment",
+            LiveMessage::Unknown => "unknown",
+        }
+    }
 }
 
 /// Error response for API errors.
@@ -164,6 +1243,108 @@ pub struct ErrorResponse {
     pub code: u16,
 }
 
+/// A symbol left out of a multi-symbol response, with the error a request
+/// for it alone gets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolError {
+    pub symbol: String,
+    pub error: String,
+    pub code: u16,
+}
+
+/// Error for a historical range longer than its schema allows, with what to
+/// do instead: request `suggested_schema`, or when there is none, pull the
+/// range with a scheduled job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RangeErrorResponse {
+    pub error: String,
+    pub code: u16,
+    pub schema: String,
+    pub requested_secs: i64,
+    pub max_secs: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggested_schema: Option<String>,
+    pub suggest_job: bool,
+}
+
+/// A symbol that doesn't resolve for a request's range and symbology, with
+/// known symbols close to it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UnknownSymbol {
+    pub symbol: String,
+    #[serde(default)]
+    pub suggestions: Vec<String>,
+}
+
+/// Error for a request naming symbols that don't resolve, checked before
+/// anything is fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnknownSymbolsResponse {
+    pub error: String,
+    pub code: u16,
+    pub symbols: Vec<UnknownSymbol>,
+}
+
+/// Outcome of one request in a batch (`POST /api/historical/batch`): what
+/// `/api/historical` returns for it, or its error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchResult<T = serde_json::Value> {
+    Ok(T),
+    Error(ErrorResponse),
+}
+
+/// Request for several schemas of the same symbols and range in one
+/// response (`POST /api/historical/combined`), e.g. `ohlcv-1m` for a chart
+/// and `trades` for the tape. It is a [`HistoricalRequest`] with `schemas`
+/// in place of `schema`; every other field applies to each schema.
+#[derive(Debug, Clone, Serialize)]
+pub struct CombinedRequest {
+    pub schemas: Vec<String>,
+    #[serde(flatten)]
+    pub request: HistoricalRequest,
+}
+
+impl CombinedRequest {
+    /// One request per distinct schema, in the order first listed.
+    pub fn requests(&self) -> Vec<HistoricalRequest> {
+        let mut schemas: Vec<&String> = Vec::new();
+        for schema in &self.schemas {
+            if !schemas.contains(&schema) {
+                schemas.push(schema);
+            }
+        }
+        schemas
+            .into_iter()
+            .map(|schema| HistoricalRequest {
+                schema: schema.clone(),
+                ..self.request.clone()
+            })
+            .collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for CombinedRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = serde_json::Map::deserialize(deserializer)?;
+        let schemas = fields
+            .remove("schemas")
+            .ok_or_else(|| de::Error::missing_field("schemas"))?;
+        let schemas = serde_json::from_value(schemas).map_err(de::Error::custom)?;
+        // Each schema is filled in per request
+        fields
+            .entry("schema")
+            .or_insert_with(|| serde_json::Value::String(String::new()));
+        let request =
+            serde_json::from_value(serde_json::Value::Object(fields)).map_err(de::Error::custom)?;
+        Ok(Self { schemas, request })
+    }
+}
+
+/// Response to a [`CombinedRequest`]: each schema's result, keyed by schema.
+pub type CombinedResponse<T = serde_json::Value> =
+    std::collections::BTreeMap<String, BatchResult<T>>;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -173,9 +1354,51 @@ mod tests {
         assert_eq!("trades".parse::<Schema>().unwrap(), Schema::Trades);
         assert_eq!("ohlcv-1s".parse::<Schema>().unwrap(), Schema::Ohlcv1S);
         assert_eq!("ohlcv-1m".parse::<Schema>().unwrap(), Schema::Ohlcv1M);
+        assert_eq!("cvd-1m".parse::<Schema>().unwrap(), Schema::Cvd1M);
         assert!("invalid".parse::<Schema>().is_err());
     }
 
+    #[test]
+    fn test_symbol_type() {
+        for stype in [
+            SymbolType::Parent,
+            SymbolType::Continuous,
+            SymbolType::RawSymbol,
+            SymbolType::InstrumentId,
+        ] {
+            assert_eq!(stype.as_str().parse::<SymbolType>().unwrap(), stype);
+        }
+        assert!("smart".parse::<SymbolType>().is_err());
+        assert_eq!("raw-symbol".parse(), Ok(SymbolType::RawSymbol));
+        assert_eq!(SymbolType::InstrumentId.to_string(), "instrument_id");
+
+        // Read from request bodies, defaulting to parent
+        let req: HistoricalRequest = serde_json::from_str(
+            r#"{"symbols":["4916"],"schema":"trades","stype_in":"instrument-id",
+                "start_rfc3339":"","end_rfc3339":""}"#,
+        )
+        .unwrap();
+        assert_eq!(req.stype_in, SymbolType::InstrumentId);
+        assert_eq!(
+            serde_json::to_value(&req).unwrap()["stype_in"],
+            "instrument_id"
+        );
+        let err = serde_json::from_str::<HistoricalRequest>(
+            r#"{"symbols":[],"schema":"trades","stype_in":"smart","start_rfc3339":"","end_rfc3339":""}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid stype_in: smart"));
+
+        let ids = |ids: &[&str]| ids.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(SymbolType::InstrumentId
+            .validate(&ids(&["4916", "183748"]))
+            .is_ok());
+        assert!(SymbolType::InstrumentId
+            .validate(&ids(&["4916", "ES.FUT"]))
+            .is_err());
+        assert!(SymbolType::Parent.validate(&ids(&["ES.FUT"])).is_ok());
+    }
+
     #[test]
     fn test_schema_as_str() {
         assert_eq!(Schema::Trades.as_str(), "trades");
@@ -188,16 +1411,61 @@ mod tests {
         let req = HistoricalRequest {
             symbols: vec!["ES.FUT".to_string()],
             schema: "trades".to_string(),
-            stype_in: "parent".to_string(),
+            stype_in: SymbolType::Parent,
             start_rfc3339: "2022-06-10T14:30:00Z".to_string(),
             end_rfc3339: "2022-06-10T14:40:00Z".to_string(),
             limit: 1000,
+            limit_per_symbol: None,
+            dataset: None,
+            quote_at_trade: false,
+            count_only: false,
+            fields: Vec::new(),
+            align: None,
+            transform: None,
+            order: RecordOrder::Time,
+            compact: false,
+            prices_as: PricesAs::Number,
+            auto_resolution: false,
+            revision: None,
         };
         let json = serde_json::to_string(&req).unwrap();
         assert!(json.contains("ES.FUT"));
         assert!(json.contains("trades"));
     }
 
+    #[test]
+    fn test_data_revision() {
+        let version = |dataset: &str, last_modified: &str| DataVersion {
+            dataset: dataset.to_string(),
+            last_modified: last_modified.to_string(),
+        };
+        assert_eq!(DataVersion::revision(&[]), None);
+        let versions = [
+            version("XNAS.ITCH", "2024-03-12"),
+            version("GLBX.MDP3", "2024-03-15"),
+        ];
+        assert_eq!(
+            DataVersion::revision(&versions).unwrap(),
+            "GLBX.MDP3@2024-03-15,XNAS.ITCH@2024-03-12"
+        );
+    }
+
+    #[test]
+    fn test_combined_request() {
+        let json = r#"{"symbols":["ES.FUT"],"schemas":["ohlcv-1m","trades","ohlcv-1m"],
+            "start_rfc3339":"2024-01-01T00:00:00Z","end_rfc3339":"2024-01-01T01:00:00Z","limit":50}"#;
+        let combined: CombinedRequest = serde_json::from_str(json).unwrap();
+        let requests = combined.requests();
+        let schemas: Vec<&str> = requests.iter().map(|r| r.schema.as_str()).collect();
+        assert_eq!(schemas, ["ohlcv-1m", "trades"]);
+        assert!(requests
+            .iter()
+            .all(|r| r.limit == 50 && r.symbols == ["ES.FUT"]));
+
+        let missing = r#"{"symbols":["ES.FUT"],"start_rfc3339":"","end_rfc3339":""}"#;
+        assert!(serde_json::from_str::<CombinedRequest>(missing).is_err());
+    }
+
     #[test]
     fn test_live_message_serialization() {
         let msg = LiveMessage::Trade {
@@ -218,9 +1486,204 @@ mod tests {
                 symbol: "ES.FUT".to_string(),
                 price_i64: 4_500_000_000_000,
                 size_u32: 10,
+                quote: None,
+                sequence_u32: None,
             }],
         };
         let json = serde_json::to_string(&resp).unwrap();
         assert!(json.contains("\"schema\":\"trades\""));
+        assert!(!json.contains("quote"));
+    }
+
+    #[test]
+    fn test_bar_schema_round_trip() {
+        let schema: Schema = "dollarbar-5000000".parse().unwrap();
+        assert_eq!(schema.as_str(), "dollarbar-5000000");
+        assert!("tickbar-x".parse::<Schema>().is_err());
+
+        let bar = OhlcvRecord {
+            ts_event_unix_ns: 1,
+            symbol: "ES.FUT".to_string(),
+            open_i64: 1,
+            high_i64: 2,
+            low_i64: 1,
+            close_i64: 2,
+            volume_u64: 3,
+        };
+        let resp = HistoricalResponse::from_bars(&schema, vec![bar]).unwrap();
+        let json = serde_json::to_string(&NormalizedResponse::from(resp)).unwrap();
+        assert!(json.starts_with(r#"{"schema":"dollarbar-5000000","data":[{"#));
+
+        let parsed: NormalizedResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.response.schema(), schema);
+        assert_eq!(parsed.response.len(), 1);
+
+        // Data ahead of the schema still parses
+        let reordered = r#"{"data":[],"schema":"trades"}"#;
+        let parsed: HistoricalResponse = serde_json::from_str(reordered).unwrap();
+        assert_eq!(parsed.schema(), Schema::Trades);
+    }
+
+    #[test]
+    fn test_limit_per_symbol_is_fair() {
+        assert_eq!(fair_shares(&[100, 2, 100], 10), [4, 2, 4]);
+        assert_eq!(fair_shares(&[3, 1], 10), [3, 1]);
+        assert_eq!(fair_shares(&[5, 5, 5], 7), [2, 2, 3]);
+
+        let trade = |ts, symbol: &str| TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: symbol.to_string(),
+            price_i64: 4_500_000_000_000,
+            size_u32: 1,
+            quote: None,
+            sequence_u32: None,
+        };
+        // A busy ES would take every record under a global limit
+        let data: Vec<_> = (0..20)
+            .map(|ts| trade(ts, if ts % 10 == 9 { "NQ" } else { "ES" }))
+            .collect();
+        let symbols = |limit, per_symbol| {
+            let mut resp = HistoricalResponse::Trades { data: data.clone() };
+            resp.apply_limits(limit, per_symbol);
+            let HistoricalResponse::Trades { data } = resp else {
+                unreachable!()
+            };
+            data.into_iter().map(|t| t.symbol).collect::<Vec<_>>()
+        };
+        assert_eq!(symbols(4, None), ["ES"; 4]);
+        assert_eq!(symbols(4, Some(3)), ["ES", "ES", "NQ", "NQ"]);
+        assert_eq!(symbols(100, Some(3)), ["ES", "ES", "ES", "NQ", "NQ"]);
+    }
+
+    #[test]
+    fn test_reached_limits() {
+        let trade = |symbol: &str| TradeRecord {
+            ts_event_unix_ns: 1,
+            symbol: symbol.to_string(),
+            price_i64: 4_500_000_000_000,
+            size_u32: 1,
+            quote: None,
+            sequence_u32: None,
+        };
+        let resp = HistoricalResponse::Trades {
+            data: vec![trade("ES"), trade("ES"), trade("NQ")],
+        };
+        assert_eq!(
+            resp.records_by_symbol(),
+            BTreeMap::from([("ES".to_string(), 2), ("NQ".to_string(), 1)])
+        );
+        assert!(resp.reached_limits(3, None));
+        assert!(!resp.reached_limits(4, None));
+        assert!(resp.reached_limits(100, Some(2)));
+        assert!(!resp.reached_limits(100, Some(3)));
+    }
+
+    #[test]
+    fn test_order_by_symbol() {
+        let bar = |ts, symbol: &str| OhlcvRecord {
+            ts_event_unix_ns: ts,
+            symbol: symbol.to_string(),
+            open_i64: 0,
+            high_i64: 0,
+            low_i64: 0,
+            close_i64: 0,
+            volume_u64: 0,
+        };
+        let data = vec![bar(1, "NQ"), bar(1, "ES"), bar(2, "NQ"), bar(2, "ES")];
+        let order = |order| {
+            let mut resp = HistoricalResponse::Ohlcv1M { data: data.clone() };
+            resp.order_by(order);
+            let HistoricalResponse::Ohlcv1M { data } = resp else {
+                unreachable!()
+            };
+            data.into_iter()
+                .map(|b| (b.symbol, b.ts_event_unix_ns))
+                .collect::<Vec<_>>()
+        };
+        let pairs = |p: &[(&str, u64)]| -> Vec<(String, u64)> {
+            p.iter().map(|(s, t)| (s.to_string(), *t)).collect()
+        };
+        assert_eq!(
+            order(RecordOrder::Time),
+            pairs(&[("NQ", 1), ("ES", 1), ("NQ", 2), ("ES", 2)])
+        );
+        assert_eq!(
+            order(RecordOrder::Symbol),
+            pairs(&[("ES", 1), ("ES", 2), ("NQ", 1), ("NQ", 2)])
+        );
+
+        let req: HistoricalRequest = serde_json::from_str(
+            r#"{"symbols":["ES.FUT"],"schema":"trades","start_rfc3339":"","end_rfc3339":"","order":"symbol"}"#,
+        )
+        .unwrap();
+        assert_eq!(req.order, RecordOrder::Symbol);
+        let json = serde_json::to_string(&HistoricalRequest::default()).unwrap();
+        assert!(!json.contains("order"));
+    }
+
+    #[test]
+    fn test_normalize_sorts_and_drops_duplicates() {
+        let trade = |ts, sequence, size| TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ES.FUT".to_string(),
+            price_i64: 4_500_000_000_000,
+            size_u32: size,
+            quote: None,
+            sequence_u32: sequence,
+        };
+        // Two fetches overlapping at ts 20
+        let mut resp = HistoricalResponse::Trades {
+            data: vec![
+                trade(20, Some(7), 1),
+                trade(30, Some(9), 1),
+                trade(10, Some(5), 2),
+                trade(20, Some(6), 1),
+                trade(20, Some(7), 1),
+                trade(40, None, 1),
+                trade(40, None, 1),
+            ],
+        };
+        let served = NormalizedResponse::from(resp.clone());
+        assert_eq!(served.duplicates_dropped, 1);
+        let json = serde_json::to_string(&served).unwrap();
+        assert!(json.contains("\"schema\":\"trades\""));
+        assert!(json.contains("\"duplicates_dropped\":1"));
+
+        assert_eq!(resp.normalize(), 1);
+        let HistoricalResponse::Trades { data } = &resp else {
+            unreachable!()
+        };
+        let order: Vec<_> = data
+            .iter()
+            .map(|t| (t.ts_event_unix_ns, t.sequence_u32))
+            .collect();
+        // Unsequenced repeats are kept as possible genuine fills
+        assert_eq!(
+            order,
+            [
+                (10, Some(5)),
+                (20, Some(6)),
+                (20, Some(7)),
+                (30, Some(9)),
+                (40, None),
+                (40, None)
+            ]
+        );
+        // Already normalized
+        assert_eq!(resp.normalize(), 0);
+    }
+
+    #[test]
+    fn test_quote_at_trade_classification() {
+        let (bid, ask) = (4_500_000_000_000, 4_500_250_000_000);
+        assert_eq!(QuoteAtTrade::classify(bid, bid, ask).side, TradeSide::Bid);
+        assert_eq!(QuoteAtTrade::classify(ask, bid, ask).side, TradeSide::Ask);
+        assert_eq!(
+            QuoteAtTrade::classify(bid + 125_000_000, bid, ask).side,
+            TradeSide::Mid
+        );
+
+        let json = serde_json::to_string(&QuoteAtTrade::classify(ask, bid, ask)).unwrap();
+        assert!(json.contains("\"side\":\"ask\""));
     }
 }
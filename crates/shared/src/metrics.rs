@@ -0,0 +1,109 @@
+//! Server metrics returned by `GET /api/metrics`.
+
+use serde::{Deserialize, Serialize};
+
+/// Response to `GET /api/metrics`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerMetrics {
+    pub clock_skew: ClockSkewStats,
+    #[serde(default)]
+    pub historical_queue: HistoricalQueueStats,
+    /// DataBento live gateways, in the order they're tried; empty unless
+    /// `LIVE_GATEWAYS` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gateways: Vec<GatewayStats>,
+    /// Latency of each stage of the live pipeline, without the histograms
+    /// (see `GET /api/debug/latency`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pipeline: Vec<StageLatency>,
+}
+
+/// How data timestamps compare with the server's clock.
+///
+/// Skew is a record's event time minus server time when it was handled, so
+/// live data normally has a small negative skew (its latency). A positive
+/// skew means the data claims to be from the future: the provider's or the
+/// server's clock is off.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ClockSkewStats {
+    /// Live trades and bars sampled
+    pub live_samples: u64,
+    /// Skew of the most recent sample, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_skew_ms: Option<i64>,
+    /// Largest skew seen (furthest ahead), in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_skew_ms: Option<i64>,
+    /// Samples ahead of server time by more than `warn_threshold_ms`
+    pub ahead_samples: u64,
+    pub warn_threshold_ms: u64,
+    /// Historical requests whose end was in the future and was moved to now
+    pub requests_clamped: u64,
+    /// Historical requests rejected for starting in the future or ending
+    /// too far ahead
+    pub requests_rejected: u64,
+}
+
+/// How one live gateway has served.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GatewayStats {
+    pub name: String,
+    /// Address template, with `{dataset}` standing for each dataset's host
+    pub address: String,
+    /// Connect time measured at startup; unset if it couldn't be reached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    /// Live sessions it has served
+    pub sessions: u64,
+    /// Of those, sessions open now
+    pub active_sessions: u64,
+    /// Failed connections and sessions ended by an error
+    pub failures: u64,
+    /// When it last failed, RFC 3339
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_failure_at: Option<String>,
+}
+
+/// The queue upstream historical fetches wait in.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct HistoricalQueueStats {
+    /// Most fetches run at once
+    pub max_concurrent: usize,
+    /// Fetches running now
+    pub running: usize,
+    /// Fetches waiting for a slot
+    pub waiting: usize,
+    /// API keys with fetches waiting
+    pub waiting_keys: usize,
+    /// Fetches that have had to wait since startup
+    pub queued_total: u64,
+}
+
+/// How long one stage of the live pipeline takes per message.
+///
+/// The stages, in order: `decode` turns a provider record into a message,
+/// `transform` runs a subscription's bars, books and other transforms,
+/// `serialize` writes the message as JSON and `send` writes it to the
+/// socket. Percentiles are bucket bounds, so they're upper estimates.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StageLatency {
+    pub stage: String,
+    /// Messages timed since startup or the last reset
+    pub samples: u64,
+    pub mean_us: f64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+    /// Samples taking at most each bound, counted cumulatively; samples
+    /// over the last bound are only in `samples`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buckets: Vec<LatencyBucket>,
+}
+
+/// One bucket of a [`StageLatency`] histogram.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LatencyBucket {
+    pub le_us: u64,
+    pub count: u64,
+}
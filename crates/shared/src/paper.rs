@@ -0,0 +1,352 @@
+//! Paper trading: simulated orders filled against live trades.
+//!
+//! A [`PaperAccount`] holds one API key's orders and positions. Market
+//! orders fill at the next trade in their symbol, moved against the trader
+//! by a slippage amount; limit orders fill once a trade reaches their
+//! limit, at the trade price or the limit, whichever is better. Fills, order updates and positions are sent to the
+//! account's live sockets as `paper_*` [`LiveMessage`]s.
+//!
+//! Prices and PnL are fixed-point at the price scale (1e9). PnL is price
+//! difference times quantity, without a contract multiplier.
+
+use crate::LiveMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Marks of an unchanged position are sent at most this often per symbol.
+const MARK_INTERVAL_NS: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl OrderSide {
+    fn sign(self) -> i64 {
+        match self {
+            OrderSide::Buy => 1,
+            OrderSide::Sell => -1,
+        }
+    }
+}
+
+/// A paper order as submitted, by `POST /api/paper/orders` or a
+/// `submit_order` WebSocket message.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NewPaperOrder {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub qty: u32,
+    /// Limit price; a market order when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price_i64: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperOrderStatus {
+    Open,
+    Filled,
+    Cancelled,
+}
+
+/// A paper order and its state.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaperOrder {
+    pub order_id: u64,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub qty: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price_i64: Option<i64>,
+    pub status: PaperOrderStatus,
+    /// Submission time (RFC3339)
+    pub submitted_at: String,
+}
+
+/// A simulated execution.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaperFill {
+    pub order_id: u64,
+    /// Time of the trade the order filled against
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub qty: u32,
+    /// Fill price, including slippage
+    pub price_i64: i64,
+}
+
+/// A net position in one symbol, marked at the last trade.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PaperPosition {
+    pub symbol: String,
+    /// Net quantity: positive long, negative short
+    pub qty: i64,
+    /// Average entry price of the open quantity (0 when flat)
+    pub avg_price_i64: i64,
+    /// Last trade price in the symbol
+    pub last_price_i64: i64,
+    /// PnL locked in by closing trades
+    pub realized_pnl_i64: i64,
+    /// PnL of the open quantity at the last price
+    pub unrealized_pnl_i64: i64,
+}
+
+impl PaperPosition {
+    /// Apply a fill of signed quantity `qty` at `price`.
+    fn apply(&mut self, qty: i64, price: i64) {
+        if self.qty == 0 || self.qty.signum() == qty.signum() {
+            let total = self.qty.abs() + qty.abs();
+            self.avg_price_i64 = ((self.avg_price_i64 as i128 * self.qty.abs() as i128
+                + price as i128 * qty.abs() as i128)
+                / total as i128) as i64;
+        } else {
+            let closed = qty.abs().min(self.qty.abs());
+            self.realized_pnl_i64 += closed * (price - self.avg_price_i64) * self.qty.signum();
+            if qty.abs() > self.qty.abs() {
+                // Flipped through flat: the remainder opens at this price
+                self.avg_price_i64 = price;
+            }
+        }
+        self.qty += qty;
+        if self.qty == 0 {
+            self.avg_price_i64 = 0;
+        }
+        self.mark(price);
+    }
+
+    fn mark(&mut self, price: i64) {
+        self.last_price_i64 = price;
+        self.unrealized_pnl_i64 = self.qty * (price - self.avg_price_i64);
+    }
+}
+
+/// Paper trading commands a client may send over `/ws/live`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaperCommand {
+    SubmitOrder(NewPaperOrder),
+    CancelOrder { order_id: u64 },
+}
+
+/// One account's paper orders and positions.
+#[derive(Debug, Clone, Default)]
+pub struct PaperAccount {
+    next_id: u64,
+    orders: BTreeMap<u64, PaperOrder>,
+    positions: BTreeMap<String, PaperPosition>,
+    /// When each symbol's position was last sent as a mark
+    last_marked: HashMap<String, u64>,
+}
+
+impl PaperAccount {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept an order, returning it as opened.
+    pub fn submit(&mut self, order: NewPaperOrder, now: &str) -> Result<PaperOrder, String> {
+        if order.symbol.trim().is_empty() {
+            return Err("order symbol is empty".to_string());
+        }
+        if order.qty == 0 {
+            return Err("order qty must be positive".to_string());
+        }
+        if order.limit_price_i64.is_some_and(|price| price <= 0) {
+            return Err("limit price must be positive".to_string());
+        }
+        self.next_id += 1;
+        let opened = PaperOrder {
+            order_id: self.next_id,
+            symbol: order.symbol,
+            side: order.side,
+            qty: order.qty,
+            limit_price_i64: order.limit_price_i64,
+            status: PaperOrderStatus::Open,
+            submitted_at: now.to_string(),
+        };
+        self.orders.insert(opened.order_id, opened.clone());
+        Ok(opened)
+    }
+
+    /// Cancel an open order, returning it as cancelled.
+    pub fn cancel(&mut self, order_id: u64) -> Result<PaperOrder, String> {
+        let order = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or_else(|| format!("no paper order {}", order_id))?;
+        if order.status != PaperOrderStatus::Open {
+            return Err(format!(
+                "paper order {} is already {:?}",
+                order_id, order.status
+            ));
+        }
+        order.status = PaperOrderStatus::Cancelled;
+        Ok(order.clone())
+    }
+
+    /// Order `order_id`, if it exists.
+    pub fn order(&self, order_id: u64) -> Option<&PaperOrder> {
+        self.orders.get(&order_id)
+    }
+
+    /// Every order, oldest first.
+    pub fn orders(&self) -> Vec<PaperOrder> {
+        self.orders.values().cloned().collect()
+    }
+
+    /// Every position, by symbol, including flat ones with realized PnL.
+    pub fn positions(&self) -> Vec<PaperPosition> {
+        self.positions.values().cloned().collect()
+    }
+
+    /// Fill open orders in `symbol` against a trade at `price`, with market
+    /// orders paying `slippage_i64`. Returns the messages to send: each
+    /// filled order, its fill and the updated position, or a throttled mark
+    /// of an existing position.
+    pub fn on_trade(
+        &mut self,
+        ts_event_unix_ns: u64,
+        symbol: &str,
+        price: i64,
+        slippage_i64: i64,
+    ) -> Vec<LiveMessage> {
+        let mut messages = Vec::new();
+        for order in self.orders.values_mut() {
+            if order.status != PaperOrderStatus::Open || order.symbol != symbol {
+                continue;
+            }
+            let fill_price = match (order.limit_price_i64, order.side) {
+                (None, side) => price + side.sign() * slippage_i64,
+                (Some(limit), OrderSide::Buy) if price <= limit => price,
+                (Some(limit), OrderSide::Sell) if price >= limit => price,
+                _ => continue,
+            };
+            order.status = PaperOrderStatus::Filled;
+            self.positions
+                .entry(symbol.to_string())
+                .or_insert_with(|| PaperPosition {
+                    symbol: symbol.to_string(),
+                    ..Default::default()
+                })
+                .apply(order.side.sign() * order.qty as i64, fill_price);
+            messages.push(LiveMessage::PaperOrder(order.clone()));
+            messages.push(LiveMessage::PaperFill(PaperFill {
+                order_id: order.order_id,
+                ts_event_unix_ns,
+                symbol: symbol.to_string(),
+                side: order.side,
+                qty: order.qty,
+                price_i64: fill_price,
+            }));
+        }
+
+        let Some(position) = self.positions.get_mut(symbol) else {
+            return messages;
+        };
+        position.mark(price);
+        let last = self.last_marked.entry(symbol.to_string()).or_insert(0);
+        if messages.is_empty() && (position.qty == 0 || ts_event_unix_ns < *last + MARK_INTERVAL_NS)
+        {
+            return messages;
+        }
+        *last = ts_event_unix_ns;
+        messages.push(LiveMessage::PaperPosition(position.clone()));
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(side: OrderSide, qty: u32, limit: Option<i64>) -> NewPaperOrder {
+        NewPaperOrder {
+            symbol: "ES.FUT".to_string(),
+            side,
+            qty,
+            limit_price_i64: limit,
+        }
+    }
+
+    fn position(messages: &[LiveMessage]) -> Option<&PaperPosition> {
+        messages.iter().find_map(|m| match m {
+            LiveMessage::PaperPosition(p) => Some(p),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_market_and_limit_fills() {
+        let mut account = PaperAccount::new();
+        account
+            .submit(order(OrderSide::Buy, 2, None), "now")
+            .unwrap();
+        let limit = account
+            .submit(order(OrderSide::Sell, 3, Some(110)), "now")
+            .unwrap();
+
+        // The market buy fills at the trade plus slippage; the limit waits
+        let messages = account.on_trade(1, "ES.FUT", 100, 1);
+        let fill = messages.iter().find_map(|m| match m {
+            LiveMessage::PaperFill(fill) => Some(fill),
+            _ => None,
+        });
+        assert_eq!(
+            fill,
+            Some(&PaperFill {
+                order_id: 1,
+                ts_event_unix_ns: 1,
+                symbol: "ES.FUT".to_string(),
+                side: OrderSide::Buy,
+                qty: 2,
+                price_i64: 101,
+            })
+        );
+        assert_eq!(position(&messages).unwrap().qty, 2);
+
+        // Selling 3 at 111 closes the long for +20 and opens a short of 1
+        let messages = account.on_trade(2, "ES.FUT", 111, 1);
+        let p = position(&messages).unwrap();
+        assert_eq!((p.qty, p.avg_price_i64, p.realized_pnl_i64), (-1, 111, 20));
+        assert_eq!(p.unrealized_pnl_i64, 0);
+        assert_eq!(
+            account.orders()[limit.order_id as usize - 1].status,
+            PaperOrderStatus::Filled
+        );
+
+        // Marks without fills are throttled
+        assert!(account.on_trade(3, "ES.FUT", 105, 1).is_empty());
+        let marked = account.on_trade(2_000_000_002, "ES.FUT", 105, 1);
+        assert_eq!(position(&marked).unwrap().unrealized_pnl_i64, 6);
+    }
+
+    #[test]
+    fn test_cancel_and_commands() {
+        let mut account = PaperAccount::new();
+        let opened = account
+            .submit(order(OrderSide::Buy, 1, Some(90)), "now")
+            .unwrap();
+        assert!(account
+            .submit(order(OrderSide::Buy, 0, None), "now")
+            .is_err());
+        assert_eq!(
+            account.cancel(opened.order_id).unwrap().status,
+            PaperOrderStatus::Cancelled
+        );
+        assert!(account.cancel(opened.order_id).is_err());
+        assert!(account.on_trade(1, "ES.FUT", 80, 0).is_empty());
+
+        let command: PaperCommand = serde_json::from_str(
+            r#"{"type":"submit_order","symbol":"ES.FUT","side":"buy","qty":1}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            command,
+            PaperCommand::SubmitOrder(order(OrderSide::Buy, 1, None))
+        );
+    }
+}
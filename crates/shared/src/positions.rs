@@ -0,0 +1,112 @@
+//! Mark-to-market of positions held outside the server.
+//!
+//! Clients register what they hold with `POST /api/positions`, then open
+//! `/ws/positions` to have each position marked at the latest trade in its
+//! symbol and sent as a `position_pnl` [`LiveMessage`].
+
+use crate::LiveMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A position as registered by a client.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Position {
+    pub symbol: String,
+    /// Net quantity: positive long, negative short. Registering 0 removes
+    /// the symbol's position.
+    pub qty: i64,
+    /// Average entry price (fixed-point, 1e9 scale)
+    pub entry_price_i64: i64,
+}
+
+impl Position {
+    /// PnL of this position at `price`: price difference times quantity,
+    /// without a contract multiplier.
+    pub fn pnl_at(&self, price: i64) -> i64 {
+        self.qty * (price - self.entry_price_i64)
+    }
+}
+
+/// A position marked at a trade.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PositionPnl {
+    /// Time of the trade the position was marked at
+    pub ts_event_unix_ns: u64,
+    pub symbol: String,
+    pub qty: i64,
+    pub entry_price_i64: i64,
+    pub last_price_i64: i64,
+    pub pnl_i64: i64,
+}
+
+/// Turns trades into throttled `position_pnl` messages.
+#[derive(Debug, Clone)]
+pub struct PnlMarker {
+    interval_ns: u64,
+    /// When each symbol was last marked
+    last_sent: HashMap<String, u64>,
+}
+
+impl PnlMarker {
+    /// Mark each symbol at most once per `interval_ns` of trade time; 0
+    /// marks on every trade.
+    pub fn new(interval_ns: u64) -> Self {
+        Self {
+            interval_ns,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Mark `position` at a trade, unless its symbol was marked less than
+    /// an interval ago.
+    pub fn mark(
+        &mut self,
+        position: &Position,
+        ts_event_unix_ns: u64,
+        price_i64: i64,
+    ) -> Option<LiveMessage> {
+        if let Some(&last) = self.last_sent.get(&position.symbol) {
+            if ts_event_unix_ns < last.saturating_add(self.interval_ns) {
+                return None;
+            }
+        }
+        self.last_sent
+            .insert(position.symbol.clone(), ts_event_unix_ns);
+        Some(LiveMessage::PositionPnl(PositionPnl {
+            ts_event_unix_ns,
+            symbol: position.symbol.clone(),
+            qty: position.qty,
+            entry_price_i64: position.entry_price_i64,
+            last_price_i64: price_i64,
+            pnl_i64: position.pnl_at(price_i64),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marks_are_throttled_per_symbol() {
+        let short = Position {
+            symbol: "ES.FUT".to_string(),
+            qty: -2,
+            entry_price_i64: 100,
+        };
+        let mut marker = PnlMarker::new(10);
+
+        let Some(LiveMessage::PositionPnl(pnl)) = marker.mark(&short, 5, 97) else {
+            panic!("first trade should be marked");
+        };
+        assert_eq!((pnl.last_price_i64, pnl.pnl_i64), (97, 6));
+        assert!(marker.mark(&short, 14, 99).is_none());
+        assert!(marker.mark(&short, 15, 99).is_some());
+
+        let nq = Position {
+            symbol: "NQ.FUT".to_string(),
+            ..short
+        };
+        assert!(marker.mark(&nq, 16, 99).is_some());
+    }
+}
@@ -0,0 +1,308 @@
+//! Fixed-point price helpers.
+//!
+//! Prices travel as `i64` scaled by 1e9 (DataBento's convention), so
+//! 5000.25 is `5_000_250_000_000`. These helpers convert to and from floats,
+//! snap prices to an instrument's tick size, and render them for display.
+//! [`PricesAs`] writes them as strings or decimals instead, for clients
+//! that parse JSON numbers as doubles.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Scale factor of fixed-point prices.
+pub const PRICE_SCALE: i64 = 1_000_000_000;
+
+/// Convert a fixed-point price to a float.
+pub fn to_f64(price_i64: i64) -> f64 {
+    price_i64 as f64 / PRICE_SCALE as f64
+}
+
+/// Convert a float price to fixed-point, rounding to the nearest unit.
+pub fn from_f64(price: f64) -> i64 {
+    (price * PRICE_SCALE as f64).round() as i64
+}
+
+/// Format a fixed-point price as a decimal string.
+///
+/// Trailing zeros are trimmed but at least two decimal places are kept, so
+/// `5_000_250_000_000` renders as `5000.25` and `5_000_000_000_000` as
+/// `5000.00`. No precision is lost, unlike going through `f64`.
+pub fn format_price(price_i64: i64) -> String {
+    let sign = if price_i64 < 0 { "-" } else { "" };
+    let abs = price_i64.unsigned_abs();
+    let frac = format!("{:09}", abs % PRICE_SCALE as u64);
+    let frac = frac.trim_end_matches('0');
+    format!("{}{}.{:0<2}", sign, abs / PRICE_SCALE as u64, frac)
+}
+
+/// Parse a decimal price such as `5000.25` or `-1.5` to fixed-point,
+/// exactly rather than through `f64`. Digits past the ninth decimal are
+/// truncated; `None` for anything else or a price out of range.
+pub fn parse_price(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (whole, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && frac.is_empty()
+        || !whole
+            .chars()
+            .chain(frac.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole: i64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let frac: i64 = format!("{:0<9}", &frac[..frac.len().min(9)]).parse().ok()?;
+    let abs = whole.checked_mul(PRICE_SCALE)?.checked_add(frac)?;
+    Some(if negative { -abs } else { abs })
+}
+
+/// Round a fixed-point price to the nearest multiple of `tick_i64`, with
+/// halves rounded away from zero. A non-positive tick leaves the price as is.
+pub fn round_to_tick(price_i64: i64, tick_i64: i64) -> i64 {
+    if tick_i64 <= 0 {
+        return price_i64;
+    }
+    let rem = price_i64.rem_euclid(tick_i64);
+    let down = price_i64 - rem;
+    let round_up = if price_i64 >= 0 {
+        rem * 2 >= tick_i64
+    } else {
+        rem * 2 > tick_i64
+    };
+    if round_up {
+        down + tick_i64
+    } else {
+        down
+    }
+}
+
+/// Decimal places needed to show every multiple of `tick_i64` exactly:
+/// 2 for 0.25, 6 for 1/64 (0.015625), 0 for whole-point ticks.
+pub fn tick_decimals(tick_i64: i64) -> usize {
+    let frac = format!("{:09}", tick_i64.unsigned_abs() % PRICE_SCALE as u64);
+    frac.trim_end_matches('0').len()
+}
+
+/// Format a fixed-point price rounded to `tick_i64`, with exactly as many
+/// decimals as the tick needs: an ES price of 4500.123456789 with a 0.25
+/// tick renders as `4500.00`. A non-positive tick falls back to
+/// [`format_price`].
+pub fn format_price_to_tick(price_i64: i64, tick_i64: i64) -> String {
+    if tick_i64 <= 0 {
+        return format_price(price_i64);
+    }
+    let rounded = round_to_tick(price_i64, tick_i64);
+    let decimals = tick_decimals(tick_i64);
+    let sign = if rounded < 0 { "-" } else { "" };
+    let abs = rounded.unsigned_abs();
+    let whole = abs / PRICE_SCALE as u64;
+    if decimals == 0 {
+        return format!("{}{}", sign, whole);
+    }
+    let frac = format!("{:09}", abs % PRICE_SCALE as u64);
+    format!("{}{}.{}", sign, whole, &frac[..decimals])
+}
+
+/// Record fields holding fixed-point prices.
+pub const PRICE_FIELDS: &[&str] = &[
+    "price_i64",
+    "open_i64",
+    "high_i64",
+    "low_i64",
+    "close_i64",
+    "bid_px_i64",
+    "ask_px_i64",
+];
+
+/// Record fields holding volumes.
+pub const VOLUME_FIELDS: &[&str] = &["volume_u64"];
+
+/// How a response writes prices and volumes. JavaScript parses JSON numbers
+/// as doubles, which are only exact up to 2^53: a fixed-point price of 9
+/// million or more, or a volume past that, comes out rounded.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PricesAs {
+    /// Fixed-point integers, e.g. `5300250000000`
+    #[default]
+    Number,
+    /// Fixed-point integers as strings, e.g. `"5300250000000"`; exact
+    String,
+    /// Decimal floats, e.g. `5300.25`; as exact as a double
+    Decimal,
+}
+
+impl PricesAs {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PricesAs::Number => "number",
+            PricesAs::String => "string",
+            PricesAs::Decimal => "decimal",
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        *self == PricesAs::Number
+    }
+
+    /// Rewrite the prices and volumes of one serialized record, including
+    /// those of a trade's `quote`.
+    pub fn apply_record(&self, record: &mut Value) {
+        if self.is_number() {
+            return;
+        }
+        let Value::Object(fields) = record else {
+            return;
+        };
+        for (name, value) in fields.iter_mut() {
+            if name == "quote" {
+                self.apply_record(value);
+            } else if PRICE_FIELDS.contains(&name.as_str()) {
+                if let Some(price) = value.as_i64() {
+                    *value = match self {
+                        PricesAs::Decimal => Value::from(to_f64(price)),
+                        _ => Value::String(price.to_string()),
+                    };
+                }
+            } else if VOLUME_FIELDS.contains(&name.as_str()) {
+                if let Some(volume) = value.as_u64() {
+                    *value = match self {
+                        PricesAs::Decimal => Value::from(volume as f64),
+                        _ => Value::String(volume.to_string()),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Rewrite the records of a historical response body.
+    pub fn apply_response(&self, body: &mut Value) {
+        if let Some(Value::Array(records)) = body.get_mut("data") {
+            for record in records {
+                self.apply_record(record);
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for PricesAs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "number" => Ok(PricesAs::Number),
+            "string" => Ok(PricesAs::String),
+            "decimal" => Ok(PricesAs::Decimal),
+            _ => Err(format!(
+                "Unknown prices_as '{}': expected number, string or decimal",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prices_as() {
+        let record = || {
+            serde_json::json!({
+                "ts_event_unix_ns": 1_717_248_600_000_000_000u64,
+                "symbol": "ES.FUT",
+                "price_i64": 9_007_199_254_740_993i64,
+                "size_u32": 3,
+                "quote": { "bid_px_i64": 5_300_000_000_000i64, "ask_px_i64": 5_300_250_000_000i64, "side": "ask" },
+            })
+        };
+
+        let mut exact = record();
+        PricesAs::String.apply_record(&mut exact);
+        assert_eq!(exact["price_i64"], "9007199254740993");
+        assert_eq!(exact["quote"]["ask_px_i64"], "5300250000000");
+        assert_eq!(exact["size_u32"], 3);
+        assert_eq!(exact["ts_event_unix_ns"], 1_717_248_600_000_000_000u64);
+
+        let mut decimal = serde_json::json!({ "data": [record()] });
+        PricesAs::Decimal.apply_response(&mut decimal);
+        assert_eq!(decimal["data"][0]["quote"]["bid_px_i64"], 5300.0);
+
+        let mut bar = serde_json::json!({ "close_i64": 5_300_250_000_000i64, "volume_u64": 1250 });
+        PricesAs::Decimal.apply_record(&mut bar);
+        assert_eq!(
+            bar,
+            serde_json::json!({ "close_i64": 5300.25, "volume_u64": 1250.0 })
+        );
+        let mut unchanged = record();
+        PricesAs::Number.apply_record(&mut unchanged);
+        assert_eq!(unchanged, record());
+
+        assert_eq!("decimal".parse::<PricesAs>(), Ok(PricesAs::Decimal));
+        assert!("float".parse::<PricesAs>().is_err());
+    }
+
+    #[test]
+    fn test_format_price() {
+        assert_eq!(format_price(5_000_250_000_000), "5000.25");
+        assert_eq!(format_price(5_000_000_000_000), "5000.00");
+        assert_eq!(format_price(1_000_000_001), "1.000000001");
+        assert_eq!(format_price(-1_500_000_000), "-1.50");
+    }
+
+    #[test]
+    fn test_parse_price() {
+        assert_eq!(parse_price("5000.25"), Some(5_000_250_000_000));
+        assert_eq!(parse_price(" 5000 "), Some(5_000_000_000_000));
+        assert_eq!(parse_price("-1.5"), Some(-1_500_000_000));
+        assert_eq!(parse_price(".000000001"), Some(1));
+        assert_eq!(parse_price("1.0000000019"), Some(1_000_000_001));
+        assert_eq!(parse_price("1e3"), None);
+        assert_eq!(parse_price("."), None);
+        assert_eq!(parse_price(""), None);
+        assert_eq!(parse_price("99999999999"), None);
+    }
+
+    #[test]
+    fn test_round_to_tick() {
+        let quarter = 250_000_000;
+        assert_eq!(round_to_tick(4_500_123_456_789, quarter), 4_500_000_000_000);
+        assert_eq!(round_to_tick(4_500_125_000_000, quarter), 4_500_250_000_000);
+        assert_eq!(round_to_tick(4_500_250_000_000, quarter), 4_500_250_000_000);
+        assert_eq!(round_to_tick(-1_125_000_000, quarter), -1_250_000_000);
+        assert_eq!(round_to_tick(-1_100_000_000, quarter), -1_000_000_000);
+        assert_eq!(round_to_tick(123, 0), 123);
+    }
+
+    #[test]
+    fn test_format_price_to_tick() {
+        assert_eq!(
+            format_price_to_tick(4_500_123_456_789, 250_000_000),
+            "4500.00"
+        );
+        assert_eq!(
+            format_price_to_tick(110_515_625_000, 15_625_000),
+            "110.515625"
+        );
+        assert_eq!(format_price_to_tick(75_123_000_000, 10_000_000), "75.12");
+        assert_eq!(
+            format_price_to_tick(39_001_400_000_000, PRICE_SCALE),
+            "39001"
+        );
+        assert_eq!(format_price_to_tick(-1_100_000_000, 250_000_000), "-1.00");
+        assert_eq!(tick_decimals(5_000), 6);
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        assert_eq!(from_f64(4500.25), 4_500_250_000_000);
+        assert_eq!(to_f64(4_500_250_000_000), 4500.25);
+    }
+}
@@ -0,0 +1,187 @@
+//! Developing volume profiles: volume traded at each price over the
+//! current session.
+//!
+//! A [`DevelopingProfile`] counts one symbol's live trades by price from
+//! when it was started until its session closes. Its state is served whole
+//! as a [`VolumeProfile`], and what changed since it was last asked is
+//! sent as a `profile_delta` [`LiveMessage`](crate::LiveMessage): the new
+//! totals of the levels that traded, so a chart adds them onto the
+//! snapshot it started from.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Volume traded at one price.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProfileLevel {
+    pub price_i64: i64,
+    pub volume_u64: u64,
+}
+
+/// A symbol's volume profile so far this session.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VolumeProfile {
+    pub symbol: String,
+    /// The session's trading day, YYYY-MM-DD
+    pub trading_day: String,
+    /// First trade counted: the profile misses anything earlier in the
+    /// session
+    pub from_unix_ns: u64,
+    /// Latest trade counted
+    pub last_unix_ns: u64,
+    pub volume_u64: u64,
+    /// Point of control: the price with the most volume, the lower on a tie
+    pub poc_i64: Option<i64>,
+    /// Levels by price, lowest first
+    pub levels: Vec<ProfileLevel>,
+}
+
+/// Levels of a profile that changed since the last delta.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProfileDelta {
+    pub symbol: String,
+    pub trading_day: String,
+    /// Latest trade counted
+    pub ts_event_unix_ns: u64,
+    /// The profile started over, e.g. for a new session: drop the levels
+    /// held before applying these
+    pub reset: bool,
+    /// New totals of the levels that traded, lowest price first
+    pub levels: Vec<ProfileLevel>,
+}
+
+/// One symbol's profile as it's built from trades.
+#[derive(Debug, Clone)]
+pub struct DevelopingProfile {
+    symbol: String,
+    trading_day: String,
+    from_unix_ns: u64,
+    last_unix_ns: u64,
+    levels: BTreeMap<i64, u64>,
+    /// Prices traded since the last delta
+    changed: BTreeSet<i64>,
+    /// Whether the next delta starts the profile
+    reset: bool,
+}
+
+impl DevelopingProfile {
+    /// An empty profile of `symbol` for the session of `trading_day`.
+    pub fn new(symbol: &str, trading_day: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            trading_day: trading_day.to_string(),
+            from_unix_ns: 0,
+            last_unix_ns: 0,
+            levels: BTreeMap::new(),
+            changed: BTreeSet::new(),
+            reset: true,
+        }
+    }
+
+    /// Count a trade.
+    pub fn add(&mut self, ts_event_unix_ns: u64, price_i64: i64, size_u32: u32) {
+        if self.levels.is_empty() {
+            self.from_unix_ns = ts_event_unix_ns;
+        }
+        self.last_unix_ns = self.last_unix_ns.max(ts_event_unix_ns);
+        *self.levels.entry(price_i64).or_default() += u64::from(size_u32);
+        self.changed.insert(price_i64);
+    }
+
+    /// The profile so far.
+    pub fn snapshot(&self) -> VolumeProfile {
+        let poc_i64 = self
+            .levels
+            .iter()
+            // max_by_key takes the last maximum, so walk down from the top
+            .rev()
+            .max_by_key(|(_, volume)| **volume)
+            .map(|(price, _)| *price);
+        VolumeProfile {
+            symbol: self.symbol.clone(),
+            trading_day: self.trading_day.clone(),
+            from_unix_ns: self.from_unix_ns,
+            last_unix_ns: self.last_unix_ns,
+            volume_u64: self.levels.values().sum(),
+            poc_i64,
+            levels: self
+                .levels
+                .iter()
+                .map(|(price, volume)| ProfileLevel {
+                    price_i64: *price,
+                    volume_u64: *volume,
+                })
+                .collect(),
+        }
+    }
+
+    /// The levels changed since the last delta, if any.
+    pub fn take_delta(&mut self) -> Option<ProfileDelta> {
+        if self.changed.is_empty() {
+            return None;
+        }
+        let levels = std::mem::take(&mut self.changed)
+            .into_iter()
+            .map(|price| ProfileLevel {
+                price_i64: price,
+                volume_u64: self.levels[&price],
+            })
+            .collect();
+        Some(ProfileDelta {
+            symbol: self.symbol.clone(),
+            trading_day: self.trading_day.clone(),
+            ts_event_unix_ns: self.last_unix_ns,
+            reset: std::mem::take(&mut self.reset),
+            levels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price_i64: i64, volume_u64: u64) -> ProfileLevel {
+        ProfileLevel {
+            price_i64,
+            volume_u64,
+        }
+    }
+
+    #[test]
+    fn test_developing_profile() {
+        let mut profile = DevelopingProfile::new("ES.FUT", "2024-06-03");
+        assert!(profile.take_delta().is_none());
+        profile.add(10, 100, 2);
+        profile.add(11, 101, 5);
+        profile.add(12, 100, 3);
+
+        let snapshot = profile.snapshot();
+        assert_eq!(snapshot.levels, vec![level(100, 5), level(101, 5)]);
+        assert_eq!(
+            (
+                snapshot.from_unix_ns,
+                snapshot.last_unix_ns,
+                snapshot.volume_u64
+            ),
+            (10, 12, 10)
+        );
+        // A tie goes to the lower price
+        assert_eq!(snapshot.poc_i64, Some(100));
+
+        let delta = profile.take_delta().unwrap();
+        assert!(delta.reset);
+        assert_eq!(delta.levels, vec![level(100, 5), level(101, 5)]);
+
+        // Later deltas carry only the levels traded since, at their totals
+        profile.add(13, 101, 1);
+        let delta = profile.take_delta().unwrap();
+        assert!(!delta.reset);
+        assert_eq!(
+            (delta.ts_event_unix_ns, delta.levels),
+            (13, vec![level(101, 6)])
+        );
+        assert_eq!(profile.snapshot().poc_i64, Some(101));
+        assert!(profile.take_delta().is_none());
+    }
+}
@@ -0,0 +1,362 @@
+//! Field selection for historical responses.
+//!
+//! A request's `fields` (e.g. `["ts_event", "close", "volume"]`) picks which
+//! record fields are serialized, so a client that only charts closes doesn't
+//! download full OHLCV bars. Fields are named as in the records, or without
+//! their type suffix (`close` for `close_i64`). [`ProjectedResponse`] writes
+//! the selected fields straight from the records, without building
+//! intermediate values.
+//!
+//! Live subscriptions select fields the same way, for trades and bars both,
+//! with a [`LiveProjection`].
+
+use crate::{HistoricalResponse, NormalizedResponse, OhlcvRecord, Schema, TradeRecord};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+/// Fields of [`TradeRecord`], in serialization order.
+pub const TRADE_FIELDS: &[&str] = &[
+    "ts_event_unix_ns",
+    "symbol",
+    "price_i64",
+    "size_u32",
+    "quote",
+    "sequence_u32",
+];
+
+/// Fields of [`OhlcvRecord`], in serialization order.
+pub const OHLCV_FIELDS: &[&str] = &[
+    "ts_event_unix_ns",
+    "symbol",
+    "open_i64",
+    "high_i64",
+    "low_i64",
+    "close_i64",
+    "volume_u64",
+];
+
+/// Type suffixes that may be left off a field name.
+const SUFFIXES: &[&str] = &["_unix_ns", "_i64", "_u64", "_u32"];
+
+/// The record fields to serialize, in record order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Projection {
+    fields: Vec<&'static str>,
+}
+
+impl Projection {
+    /// Resolve `fields` against the records of `schema`. Returns `None` when
+    /// no fields are given, meaning whole records.
+    pub fn new(schema: &Schema, fields: &[String]) -> Result<Option<Self>, String> {
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        let known = match schema {
+            Schema::Trades => TRADE_FIELDS,
+            _ => OHLCV_FIELDS,
+        };
+        let mut selected = vec![false; known.len()];
+        for field in fields {
+            let field = field.trim();
+            let index = known
+                .iter()
+                .position(|name| names(name, field))
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown field '{}' for {}. Expected: {}",
+                        field,
+                        schema.as_str(),
+                        known.join(", ")
+                    )
+                })?;
+            selected[index] = true;
+        }
+        Ok(Some(Self {
+            fields: known
+                .iter()
+                .zip(selected)
+                .filter_map(|(name, keep)| keep.then_some(*name))
+                .collect(),
+        }))
+    }
+
+    /// Selected field names, e.g. `["ts_event_unix_ns", "close_i64"]`.
+    pub fn fields(&self) -> &[&'static str] {
+        &self.fields
+    }
+}
+
+/// The fields kept of each trade and bar on a live stream, from a
+/// subscription's `fields`.
+///
+/// Names resolve as for a [`Projection`], against trades and bars alike, so
+/// `ts_event,price,close` keeps the time and price of trades and the time
+/// and close of bars. Records of a kind none of the names are fields of are
+/// sent whole, and what isn't a record field, such as a message's `type` or
+/// a bar's `interval`, is always kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveProjection {
+    trades: Option<Vec<&'static str>>,
+    bars: Option<Vec<&'static str>>,
+}
+
+impl LiveProjection {
+    /// Resolve `fields`. Returns `None` when no fields are given, meaning
+    /// whole records.
+    pub fn new(fields: &[String]) -> Result<Option<Self>, String> {
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        let known = || TRADE_FIELDS.iter().chain(OHLCV_FIELDS);
+        if let Some(field) = fields
+            .iter()
+            .find(|field| !known().any(|name| names(name, field.trim())))
+        {
+            let expected: Vec<&str> = TRADE_FIELDS
+                .iter()
+                .chain(
+                    OHLCV_FIELDS
+                        .iter()
+                        .filter(|name| !TRADE_FIELDS.contains(name)),
+                )
+                .copied()
+                .collect();
+            return Err(format!(
+                "Unknown field '{}'. Expected: {}",
+                field.trim(),
+                expected.join(", ")
+            ));
+        }
+        let select = |known: &[&'static str]| {
+            let selected: Vec<&'static str> = known
+                .iter()
+                .filter(|name| fields.iter().any(|field| names(name, field.trim())))
+                .copied()
+                .collect();
+            (!selected.is_empty()).then_some(selected)
+        };
+        Ok(Some(Self {
+            trades: select(TRADE_FIELDS),
+            bars: select(OHLCV_FIELDS),
+        }))
+    }
+
+    /// Drop the fields not selected from `record`, a trade or bar as it's
+    /// serialized in a live message.
+    pub fn apply(&self, record: &mut Value) {
+        let Some(record) = record.as_object_mut() else {
+            return;
+        };
+        let (known, selected) = match record.contains_key("close_i64") {
+            true => (OHLCV_FIELDS, &self.bars),
+            false => (TRADE_FIELDS, &self.trades),
+        };
+        if let Some(selected) = selected {
+            record.retain(|name, _| {
+                !known.contains(&name.as_str()) || selected.contains(&name.as_str())
+            });
+        }
+    }
+}
+
+/// Whether `field`, as a client wrote it, names the record field `name`.
+fn names(name: &str, field: &str) -> bool {
+    name == field || short_name(name) == field
+}
+
+fn short_name(name: &str) -> &str {
+    SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name)
+}
+
+/// A normalized response that serializes only the projected fields of each
+/// record. Its JSON has the layout of [`NormalizedResponse`] with the other
+/// fields left out of every record.
+pub struct ProjectedResponse {
+    pub response: NormalizedResponse,
+    pub projection: Projection,
+}
+
+impl Serialize for ProjectedResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields = self.projection.fields();
+        let transform = &self.response.transform;
+        let mut map = serializer.serialize_map(Some(3 + transform.is_some() as usize))?;
+        map.serialize_entry("schema", &self.response.response.schema())?;
+        match &self.response.response {
+            HistoricalResponse::Trades { data } => {
+                map.serialize_entry("data", &Records { data, fields })?
+            }
+            HistoricalResponse::Ohlcv1S { data }
+            | HistoricalResponse::Ohlcv1M { data }
+            | HistoricalResponse::Cvd1S { data }
+            | HistoricalResponse::Cvd1M { data }
+            | HistoricalResponse::Bars { data, .. } => {
+                map.serialize_entry("data", &Records { data, fields })?
+            }
+        }
+        map.serialize_entry("duplicates_dropped", &self.response.duplicates_dropped)?;
+        if let Some(transform) = transform {
+            map.serialize_entry("transform", transform)?;
+        }
+        map.end()
+    }
+}
+
+/// Records that can write one named field into a map.
+trait Project {
+    fn write_field<M: SerializeMap>(&self, name: &str, map: &mut M) -> Result<(), M::Error>;
+}
+
+impl Project for TradeRecord {
+    fn write_field<M: SerializeMap>(&self, name: &str, map: &mut M) -> Result<(), M::Error> {
+        match name {
+            "ts_event_unix_ns" => map.serialize_entry(name, &self.ts_event_unix_ns),
+            "symbol" => map.serialize_entry(name, &self.symbol),
+            "price_i64" => map.serialize_entry(name, &self.price_i64),
+            "size_u32" => map.serialize_entry(name, &self.size_u32),
+            "quote" => map.serialize_entry(name, &self.quote),
+            "sequence_u32" => map.serialize_entry(name, &self.sequence_u32),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Project for OhlcvRecord {
+    fn write_field<M: SerializeMap>(&self, name: &str, map: &mut M) -> Result<(), M::Error> {
+        match name {
+            "ts_event_unix_ns" => map.serialize_entry(name, &self.ts_event_unix_ns),
+            "symbol" => map.serialize_entry(name, &self.symbol),
+            "open_i64" => map.serialize_entry(name, &self.open_i64),
+            "high_i64" => map.serialize_entry(name, &self.high_i64),
+            "low_i64" => map.serialize_entry(name, &self.low_i64),
+            "close_i64" => map.serialize_entry(name, &self.close_i64),
+            "volume_u64" => map.serialize_entry(name, &self.volume_u64),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Records<'a, R> {
+    data: &'a [R],
+    fields: &'a [&'static str],
+}
+
+impl<R: Project> Serialize for Records<'_, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.data.len()))?;
+        for record in self.data {
+            seq.serialize_element(&Record {
+                record,
+                fields: self.fields,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+struct Record<'a, R> {
+    record: &'a R,
+    fields: &'a [&'static str],
+}
+
+impl<R: Project> Serialize for Record<'_, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len()))?;
+        for field in self.fields {
+            self.record.write_field(field, &mut map)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LiveMessage;
+
+    fn bars() -> NormalizedResponse {
+        HistoricalResponse::Ohlcv1M {
+            data: vec![OhlcvRecord {
+                ts_event_unix_ns: 60_000_000_000,
+                symbol: "ES.FUT".to_string(),
+                open_i64: 1,
+                high_i64: 4,
+                low_i64: 0,
+                close_i64: 3,
+                volume_u64: 12,
+            }],
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_short_and_full_names() {
+        let fields = [
+            "volume".to_string(),
+            "ts_event".to_string(),
+            "close_i64".to_string(),
+        ];
+        let projection = Projection::new(&Schema::Ohlcv1M, &fields).unwrap().unwrap();
+        // Record order, not request order
+        assert_eq!(
+            projection.fields(),
+            ["ts_event_unix_ns", "close_i64", "volume_u64"]
+        );
+
+        assert!(Projection::new(&Schema::Trades, &[]).unwrap().is_none());
+        assert!(Projection::new(&Schema::Trades, &["close".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_live_projection() {
+        let fields = ["ts_event".to_string(), "price".to_string()];
+        let projection = LiveProjection::new(&fields).unwrap().unwrap();
+        let trade = LiveMessage::Trade {
+            ts_event_unix_ns: 1,
+            symbol: "ESZ4".to_string(),
+            price_i64: 2,
+            size_u32: 3,
+        };
+        let mut json = serde_json::to_value(&trade).unwrap();
+        projection.apply(&mut json);
+        assert_eq!(
+            json.to_string(),
+            r#"{"price_i64":2,"ts_event_unix_ns":1,"type":"trade"}"#
+        );
+
+        // Only the time is a bar field; the channel stays
+        let mut bar = serde_json::json!({
+            "type": "ohlcv",
+            "ts_event_unix_ns": 60,
+            "close_i64": 3,
+            "volume_u64": 12,
+            "interval": "5m",
+        });
+        projection.apply(&mut bar);
+        assert_eq!(
+            bar.to_string(),
+            r#"{"interval":"5m","ts_event_unix_ns":60,"type":"ohlcv"}"#
+        );
+
+        assert!(LiveProjection::new(&[]).unwrap().is_none());
+        let error = LiveProjection::new(&["bid".to_string()]).unwrap_err();
+        assert!(error.ends_with("sequence_u32, open_i64, high_i64, low_i64, close_i64, volume_u64"));
+    }
+
+    #[test]
+    fn test_projected_json() {
+        let fields = ["ts_event".to_string(), "close".to_string()];
+        let projected = ProjectedResponse {
+            response: bars(),
+            projection: Projection::new(&Schema::Ohlcv1M, &fields).unwrap().unwrap(),
+        };
+        assert_eq!(
+            serde_json::to_string(&projected).unwrap(),
+            r#"{"schema":"ohlcv-1m","data":[{"ts_event_unix_ns":60000000000,"close_i64":3}],"duplicates_dropped":0}"#
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Historical query log entries, returned by `GET /api/admin/queries`.
+
+use serde::{Deserialize, Serialize};
+
+/// Whether a query was answered from the shared historical cache.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    /// No cache is configured or the range hasn't settled
+    Bypass,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+            CacheStatus::Bypass => "bypass",
+        }
+    }
+}
+
+/// One historical query as served.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryRecord {
+    pub id: u64,
+    /// When the query was received (RFC3339)
+    pub at: String,
+    /// Usage account of the caller, as in `GET /api/usage`
+    pub key_id: String,
+    pub symbols: Vec<String>,
+    pub schema: String,
+    pub start_rfc3339: String,
+    pub end_rfc3339: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<String>,
+    /// Time to fetch the records, including the cost estimate
+    pub duration_ms: u64,
+    /// Records returned
+    pub records: u64,
+    pub cache: CacheStatus,
+    /// Estimated upstream cost; zero for cache hits
+    pub cost_usd: f64,
+    /// Why the fetch failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Order of `GET /api/admin/queries` results.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuerySort {
+    /// Newest first
+    #[default]
+    Recent,
+    /// Longest duration first
+    Slowest,
+    /// Highest upstream cost first
+    Costliest,
+}
+
+/// Query parameters of `GET /api/admin/queries`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryLogFilter {
+    #[serde(default)]
+    pub sort: QuerySort,
+    /// Only queries by this usage account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Only queries that took at least this long
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_duration_ms: Option<u64>,
+    /// Most entries returned (default 50)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
@@ -0,0 +1,64 @@
+//! Recorded live data: the partitions it's stored in and compaction
+//! reports.
+//!
+//! Live trades and bars are recorded to one partition per schema, UTC day
+//! and symbol. A day's partitions start as NDJSON files appended to as
+//! messages arrive, and once the day is over are compacted into Parquet.
+
+use serde::{Deserialize, Serialize};
+
+/// How a partition is stored.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionFormat {
+    /// Live messages as JSON lines, still being written
+    Ndjson,
+    /// Compressed columns of the schema's historical records
+    Parquet,
+}
+
+/// One schema's recording of one symbol over one UTC day.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecordingPartition {
+    pub schema: String,
+    pub symbol: String,
+    /// UTC day recorded, e.g. "2024-01-02"
+    pub day: String,
+    pub format: PartitionFormat,
+    /// File path relative to the recordings directory
+    pub path: String,
+    pub bytes: u64,
+    /// Records held; known once compacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records: Option<u64>,
+    /// Earliest record's timestamp; known once compacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_unix_ns: Option<u64>,
+    /// Latest record's timestamp; known once compacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_unix_ns: Option<u64>,
+}
+
+impl RecordingPartition {
+    /// Whether the partition may hold records in `[start, end)`.
+    pub fn overlaps(&self, start_ns: u64, end_ns: u64) -> bool {
+        match (self.start_unix_ns, self.end_unix_ns) {
+            (Some(first), Some(last)) => first < end_ns && start_ns <= last,
+            _ => true,
+        }
+    }
+}
+
+/// Outcome of a compaction run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CompactionReport {
+    /// Partitions written to Parquet
+    pub compacted: Vec<RecordingPartition>,
+    /// Size of the NDJSON files they replaced
+    pub bytes_before: u64,
+    /// Size of the Parquet files written
+    pub bytes_after: u64,
+    /// Files that couldn't be compacted, with why; they're retried next run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<String>,
+}
@@ -0,0 +1,262 @@
+//! Contract roll detection.
+//!
+//! A parent subscription such as `ES.FUT` carries trades for every listed
+//! contract month (`ESZ4`, `ESH5`, ...). Liquidity moves from the expiring
+//! contract to the next one around each roll date, so the contract with the
+//! most recent volume is the one worth charting. [`RollTracker`] watches
+//! rolling volume per contract and reports when the leader changes.
+
+use crate::aggregate::NANOS_PER_SECOND;
+use crate::ticks::symbol_root;
+use crate::LiveMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Volume is summed into buckets this wide.
+const BUCKET_NS: u64 = NANOS_PER_SECOND;
+
+/// Volume traded in one contract.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContractVolume {
+    pub symbol: String,
+    pub volume: u64,
+}
+
+/// Response to `GET /api/front-month/{symbol}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrontMonth {
+    /// Symbol as requested, e.g. "ES.FUT"
+    pub parent: String,
+    /// Contract with the most volume, e.g. "ESZ4"
+    pub front: String,
+    /// Lookback the volumes cover, e.g. "1d"
+    pub window: String,
+    /// Outright contracts by volume, highest first
+    pub contracts: Vec<ContractVolume>,
+}
+
+/// Root of an outright futures contract such as `ESZ4`, or `None` for
+/// spreads (`ESZ4-ESH5`) and parent or continuous symbols.
+pub fn contract_root(symbol: &str) -> Option<&str> {
+    if symbol.contains(['-', ':', '.', ' ']) {
+        return None;
+    }
+    let root = symbol_root(symbol);
+    (root.len() < symbol.len()).then_some(root)
+}
+
+/// Sum `(symbol, volume)` pairs per outright contract, highest first.
+/// Spreads and non-contract symbols are skipped.
+pub fn rank_contracts<'a>(
+    volumes: impl IntoIterator<Item = (&'a str, u64)>,
+) -> Vec<ContractVolume> {
+    let mut totals: HashMap<&str, u64> = HashMap::new();
+    for (symbol, volume) in volumes {
+        if contract_root(symbol).is_some() {
+            *totals.entry(symbol).or_default() += volume;
+        }
+    }
+    let mut ranked: Vec<ContractVolume> = totals
+        .into_iter()
+        .map(|(symbol, volume)| ContractVolume {
+            symbol: symbol.to_string(),
+            volume,
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.volume.cmp(&a.volume).then(a.symbol.cmp(&b.symbol)));
+    ranked
+}
+
+#[derive(Debug, Default)]
+struct RootVolume {
+    /// Per-second volume by contract, oldest first
+    buckets: VecDeque<(u64, HashMap<String, u64>)>,
+    totals: HashMap<String, u64>,
+    front: Option<String>,
+    /// Event time of the first trade seen for this root
+    first_ns: u64,
+}
+
+impl RootVolume {
+    fn add(&mut self, start_ns: u64, symbol: &str, size: u64) {
+        if self.buckets.back().is_none_or(|(b, _)| *b < start_ns) {
+            self.buckets.push_back((start_ns, HashMap::new()));
+        }
+        // Late trades land in the newest bucket rather than reopening old ones
+        if let Some((_, volumes)) = self.buckets.back_mut() {
+            *volumes.entry(symbol.to_string()).or_default() += size;
+        }
+        *self.totals.entry(symbol.to_string()).or_default() += size;
+    }
+
+    fn expire(&mut self, start_ns: u64, window_ns: u64) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|(b, _)| b + window_ns <= start_ns)
+        {
+            let Some((_, volumes)) = self.buckets.pop_front() else {
+                break;
+            };
+            for (symbol, volume) in volumes {
+                if let Some(total) = self.totals.get_mut(&symbol) {
+                    *total -= volume;
+                    if *total == 0 {
+                        self.totals.remove(&symbol);
+                    }
+                }
+            }
+        }
+    }
+
+    fn volume(&self, symbol: &str) -> u64 {
+        self.totals.get(symbol).copied().unwrap_or(0)
+    }
+
+    /// Contract with the most volume; the current front wins ties.
+    fn leader(&self) -> Option<&str> {
+        let front_volume = self.front.as_deref().map_or(0, |f| self.volume(f));
+        self.totals
+            .iter()
+            .filter(|(_, &v)| v > front_volume)
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(s, _)| s.as_str())
+            .or(self.front.as_deref())
+    }
+}
+
+/// Watches rolling volume per contract month and emits a
+/// [`LiveMessage::RollAlert`] when a different contract takes the lead.
+///
+/// Until a root has been seen for a full window the leader is tracked
+/// silently, so a session's first trades don't raise spurious alerts.
+#[derive(Debug)]
+pub struct RollTracker {
+    /// Window label as configured, e.g. "15m"
+    window: String,
+    window_ns: u64,
+    roots: HashMap<String, RootVolume>,
+}
+
+impl RollTracker {
+    pub fn new(window: &str, window_ns: u64) -> Self {
+        Self {
+            window: window.to_string(),
+            window_ns: window_ns.max(BUCKET_NS),
+            roots: HashMap::new(),
+        }
+    }
+
+    /// Build a tracker from a window such as `15m` (100ms to 1h).
+    pub fn from_spec(window: &str) -> Result<Self, String> {
+        let window = window.trim();
+        Ok(Self::new(window, crate::flow::parse_window(window)?))
+    }
+
+    /// Current leader for `root`, if any trades have been seen.
+    pub fn front(&self, root: &str) -> Option<&str> {
+        self.roots.get(root)?.front.as_deref()
+    }
+
+    /// Record a trade. Returns a `RollAlert` if the leading contract for
+    /// the trade's root changed.
+    pub fn push(&mut self, ts_ns: u64, symbol: &str, size: u32) -> Option<LiveMessage> {
+        let root = contract_root(symbol)?;
+        let state = self
+            .roots
+            .entry(root.to_string())
+            .or_insert_with(|| RootVolume {
+                first_ns: ts_ns,
+                ..Default::default()
+            });
+
+        let start_ns = ts_ns - ts_ns % BUCKET_NS;
+        state.add(start_ns, symbol, size as u64);
+        state.expire(start_ns, self.window_ns);
+
+        let leader = state.leader()?.to_string();
+        let previous = state.front.replace(leader.clone());
+        let warmed_up = ts_ns >= state.first_ns.saturating_add(self.window_ns);
+        match previous {
+            Some(from) if from != leader && warmed_up => Some(LiveMessage::RollAlert {
+                ts_event_unix_ns: ts_ns,
+                root: root.to_string(),
+                from_volume: state.volume(&from),
+                to_volume: state.volume(&leader),
+                from_symbol: from,
+                to_symbol: leader,
+                window: self.window.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: u64 = 60 * NANOS_PER_SECOND;
+
+    #[test]
+    fn test_contract_root() {
+        assert_eq!(contract_root("ESZ4"), Some("ES"));
+        assert_eq!(contract_root("MNQH25"), Some("MNQ"));
+        assert_eq!(contract_root("ESZ4-ESH5"), None);
+        assert_eq!(contract_root("ES.FUT"), None);
+        assert_eq!(contract_root("AAPL"), None);
+    }
+
+    #[test]
+    fn test_rank_contracts() {
+        let ranked = rank_contracts([
+            ("ESZ4", 100),
+            ("ESH5", 300),
+            ("ESZ4-ESH5", 1_000),
+            ("ESZ4", 50),
+        ]);
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].symbol, "ESH5");
+        assert_eq!(ranked[1].volume, 150);
+    }
+
+    #[test]
+    fn test_roll_alert_when_leader_changes() {
+        let mut tracker = RollTracker::new("5m", 5 * MIN);
+        let t0 = 1_700_000_000 * NANOS_PER_SECOND;
+
+        // Warm-up: ESH5 overtaking early is not reported
+        assert!(tracker.push(t0, "ESZ4", 10).is_none());
+        assert!(tracker.push(t0 + MIN, "ESH5", 20).is_none());
+        assert_eq!(tracker.front("ES"), Some("ESH5"));
+        assert!(tracker.push(t0 + 2 * MIN, "ESZ4", 30).is_none());
+        assert_eq!(tracker.front("ES"), Some("ESZ4"));
+
+        // After a full window, a change of leader alerts
+        assert!(tracker.push(t0 + 6 * MIN, "ESZ4", 5).is_none());
+        let alert = tracker.push(t0 + 6 * MIN, "ESH5", 100).unwrap();
+        match alert {
+            LiveMessage::RollAlert {
+                root,
+                from_symbol,
+                to_symbol,
+                from_volume,
+                to_volume,
+                ..
+            } => {
+                assert_eq!(root, "ES");
+                assert_eq!(from_symbol, "ESZ4");
+                assert_eq!(to_symbol, "ESH5");
+                // ESZ4's t0 trade has left the 5m window
+                assert_eq!(from_volume, 35);
+                assert_eq!(to_volume, 100);
+            }
+            other => panic!("expected roll alert, got {:?}", other),
+        }
+
+        // Spreads and other roots don't disturb ES
+        assert!(tracker.push(t0 + 7 * MIN, "ESZ4-ESH5", 10_000).is_none());
+        assert!(tracker.push(t0 + 7 * MIN, "NQH5", 1).is_none());
+        assert_eq!(tracker.front("ES"), Some("ESH5"));
+    }
+}
@@ -0,0 +1,282 @@
+//! Rolling per-symbol bars kept as data passes through the server.
+//!
+//! A [`RollupSeries`] holds the latest bars of one symbol at one width,
+//! with the span of time it is known to be complete over: every bar in
+//! that span is held, and a bucket without one had no trades. Fetches and
+//! live trades only extend a series when they're known to cover whole
+//! buckets, so a range cut by a limit or a subscription joined mid-bar
+//! never passes for a full bar.
+
+use crate::change::DayChange;
+use crate::OhlcvRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The latest bars of one symbol at one width.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RollupSeries {
+    pub symbol: String,
+    /// Bar width in nanoseconds
+    pub interval_ns: u64,
+    /// `[from, to)` in which every bar is held; bars outside it aren't kept
+    pub covered: Option<(u64, u64)>,
+    /// Bars in time order
+    pub bars: VecDeque<OhlcvRecord>,
+}
+
+impl RollupSeries {
+    pub fn new(symbol: &str, interval_ns: u64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            interval_ns,
+            covered: None,
+            bars: VecDeque::new(),
+        }
+    }
+
+    /// Record `bars` as every bar from `from` to `to`, replacing what was
+    /// held there. Spans touching the covered one extend it and a later
+    /// disjoint span replaces it; an earlier disjoint span is ignored, as
+    /// the series only keeps one unbroken run. At most `max_bars` are kept,
+    /// dropping the oldest.
+    pub fn insert(&mut self, bars: &[OhlcvRecord], from: u64, to: u64, max_bars: usize) {
+        if from > to {
+            return;
+        }
+        let covered = match self.covered {
+            None => (from, to),
+            Some((start, end)) if from <= end && start <= to => (start.min(from), end.max(to)),
+            Some((_, end)) if from > end => {
+                self.bars.clear();
+                (from, to)
+            }
+            Some(_) => return,
+        };
+        self.bars
+            .retain(|bar| bar.ts_event_unix_ns < from || bar.ts_event_unix_ns >= to);
+        self.bars.extend(
+            bars.iter()
+                .filter(|bar| bar.symbol == self.symbol)
+                .filter(|bar| (from..to).contains(&bar.ts_event_unix_ns))
+                .filter(|bar| bar.ts_event_unix_ns % self.interval_ns == 0)
+                .cloned(),
+        );
+        self.bars
+            .make_contiguous()
+            .sort_by_key(|bar| bar.ts_event_unix_ns);
+
+        let mut covered = covered;
+        while self.bars.len() > max_bars {
+            if let Some(dropped) = self.bars.pop_front() {
+                covered.0 = dropped.ts_event_unix_ns + self.interval_ns;
+            }
+        }
+        self.covered = Some(covered);
+    }
+
+    /// The bars of the `count` buckets before `end`, if the series covers
+    /// all of them.
+    pub fn recent(&self, end: u64, count: usize) -> Option<Vec<OhlcvRecord>> {
+        let start = end.checked_sub(self.interval_ns.checked_mul(count as u64)?)?;
+        let (from, to) = self.covered?;
+        if start < from || end > to {
+            return None;
+        }
+        Some(self.between(start, end))
+    }
+
+    /// Held bars opening in `[start, end)`.
+    pub fn between(&self, start: u64, end: u64) -> Vec<OhlcvRecord> {
+        self.bars
+            .iter()
+            .filter(|bar| (start..end).contains(&bar.ts_event_unix_ns))
+            .cloned()
+            .collect()
+    }
+
+    /// The latest `count` bars.
+    pub fn last(&self, count: usize) -> Vec<OhlcvRecord> {
+        let skip = self.bars.len().saturating_sub(count);
+        self.bars.iter().skip(skip).cloned().collect()
+    }
+
+    /// How the symbol moved over the bars opening at or after `since`.
+    pub fn mover(&self, since: u64) -> Option<Mover> {
+        let bars = self.between(since, u64::MAX);
+        let (first, last) = (bars.first()?, bars.last()?);
+        let change_pct = match first.open_i64 {
+            0 => 0.0,
+            open => (last.close_i64 - open) as f64 / open as f64 * 100.0,
+        };
+        Some(Mover {
+            symbol: self.symbol.clone(),
+            since_unix_ns: first.ts_event_unix_ns,
+            open_i64: first.open_i64,
+            close_i64: last.close_i64,
+            change_pct,
+            high_i64: bars.iter().map(|b| b.high_i64).max()?,
+            low_i64: bars.iter().map(|b| b.low_i64).min()?,
+            volume_u64: bars.iter().map(|b| b.volume_u64).sum(),
+            day_change: None,
+        })
+    }
+}
+
+/// Response of `GET /api/rollups/:symbol`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RollupBars {
+    pub symbol: String,
+    /// Bar width, e.g. "5m"
+    pub interval: String,
+    /// Start of the span every bar is held for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub covered_from_unix_ns: Option<u64>,
+    /// End of that span, exclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub covered_to_unix_ns: Option<u64>,
+    pub bars: Vec<OhlcvRecord>,
+    /// The latest bar's close against the previous session's close
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day_change: Option<DayChange>,
+}
+
+/// How one symbol moved over a leaderboard's window.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Mover {
+    pub symbol: String,
+    /// Open time of the first bar in the window
+    pub since_unix_ns: u64,
+    pub open_i64: i64,
+    pub close_i64: i64,
+    /// Close against open, in percent
+    pub change_pct: f64,
+    pub high_i64: i64,
+    pub low_i64: i64,
+    pub volume_u64: u64,
+    /// The close against the previous session's close
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day_change: Option<DayChange>,
+}
+
+/// What a leaderboard ranks by.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardRank {
+    /// Biggest moves either way
+    #[default]
+    Change,
+    Volume,
+}
+
+/// Rank `movers` by `rank`, keeping the top `limit`.
+pub fn leaderboard(mut movers: Vec<Mover>, rank: LeaderboardRank, limit: usize) -> Vec<Mover> {
+    match rank {
+        LeaderboardRank::Change => {
+            movers.sort_by(|a, b| b.change_pct.abs().total_cmp(&a.change_pct.abs()))
+        }
+        LeaderboardRank::Volume => movers.sort_by_key(|m| std::cmp::Reverse(m.volume_u64)),
+    }
+    movers.truncate(limit);
+    movers
+}
+
+/// Response of `GET /api/leaderboard`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Leaderboard {
+    /// Bar width ranked over, e.g. "5m"
+    pub interval: String,
+    /// Window start
+    pub since_unix_ns: u64,
+    pub rank: LeaderboardRank,
+    pub movers: Vec<Mover>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: u64 = 60_000_000_000;
+
+    fn bar(minute: u64, open: i64, close: i64, volume: u64) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: minute * MIN,
+            symbol: "ES.FUT".to_string(),
+            open_i64: open,
+            high_i64: open.max(close),
+            low_i64: open.min(close),
+            close_i64: close,
+            volume_u64: volume,
+        }
+    }
+
+    #[test]
+    fn test_series_coverage() {
+        let mut series = RollupSeries::new("ES.FUT", MIN);
+        series.insert(
+            &[bar(10, 100, 101, 5), bar(12, 101, 102, 5)],
+            10 * MIN,
+            13 * MIN,
+            100,
+        );
+        assert_eq!(series.covered, Some((10 * MIN, 13 * MIN)));
+        // Minute 11 had no trades, so three minutes are still covered
+        assert_eq!(series.recent(13 * MIN, 3).unwrap().len(), 2);
+        assert!(series.recent(14 * MIN, 3).is_none());
+
+        // An adjacent span extends, replacing bars it covers
+        series.insert(
+            &[bar(12, 101, 103, 7), bar(13, 103, 104, 1)],
+            12 * MIN,
+            14 * MIN,
+            100,
+        );
+        assert_eq!(series.covered, Some((10 * MIN, 14 * MIN)));
+        assert_eq!(series.last(2)[0].volume_u64, 7);
+
+        // An earlier disjoint span is ignored; a later one starts over
+        series.insert(&[bar(1, 1, 1, 1)], MIN, 2 * MIN, 100);
+        assert_eq!(series.bars.len(), 3);
+        series.insert(&[bar(20, 100, 99, 1)], 20 * MIN, 21 * MIN, 100);
+        assert_eq!(series.covered, Some((20 * MIN, 21 * MIN)));
+        assert_eq!(series.bars.len(), 1);
+
+        // Dropping old bars moves the covered start past them
+        series.insert(
+            &[bar(21, 99, 98, 1), bar(22, 98, 97, 1)],
+            21 * MIN,
+            23 * MIN,
+            2,
+        );
+        assert_eq!(series.covered, Some((21 * MIN, 23 * MIN)));
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let mut es = RollupSeries::new("ES.FUT", MIN);
+        es.insert(
+            &[bar(0, 100, 101, 10), bar(1, 101, 104, 10)],
+            0,
+            2 * MIN,
+            10,
+        );
+        let mut nq = RollupSeries::new("NQ.FUT", MIN);
+        let nq_bar = |minute, open, close| OhlcvRecord {
+            symbol: "NQ.FUT".to_string(),
+            ..bar(minute, open, close, 50)
+        };
+        nq.insert(&[nq_bar(0, 200, 198), nq_bar(1, 198, 190)], 0, 2 * MIN, 10);
+
+        let movers = vec![es.mover(0).unwrap(), nq.mover(0).unwrap()];
+        assert_eq!(movers[0].change_pct, 4.0);
+        assert_eq!(movers[0].high_i64, 104);
+        let by_change = leaderboard(movers.clone(), LeaderboardRank::Change, 10);
+        assert_eq!(by_change[0].symbol, "NQ.FUT");
+        assert_eq!(by_change[0].change_pct, -5.0);
+        let by_volume = leaderboard(movers, LeaderboardRank::Volume, 1);
+        assert_eq!(by_volume.len(), 1);
+        assert_eq!(by_volume[0].volume_u64, 100);
+
+        assert_eq!(es.mover(MIN).unwrap().open_i64, 101);
+        assert!(es.mover(5 * MIN).is_none());
+    }
+}
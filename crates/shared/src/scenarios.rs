@@ -0,0 +1,274 @@
+//! Scripted market regimes for mock mode.
+//!
+//! A [`Scenario`] bends how mock prices move over a window: a volatility
+//! spike that fades, a flash crash that recovers, or a trend day whose
+//! move holds once made. Scenarios are scheduled with
+//! `POST /api/mock/scenarios` and shape the mock's live and historical
+//! data alike, so alerts, anomaly detection and charts can be shown under
+//! stress on demand.
+
+use crate::timeexpr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Share of a flash crash spent falling; the rest is the recovery.
+const CRASH_SHARE: f64 = 0.2;
+
+/// How much faster prices move while a flash crash runs.
+const CRASH_VOLATILITY: f64 = 3.0;
+
+/// A kind of scripted regime.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScenarioKind {
+    /// Moves `magnitude` times larger at the start, fading back to normal
+    VolatilitySpike,
+    /// Prices fall by the `magnitude` fraction over the first fifth, then
+    /// climb back by the end
+    FlashCrash,
+    /// Prices drift by the `magnitude` fraction (negative for down) over
+    /// the window and stay there
+    TrendDay,
+}
+
+impl ScenarioKind {
+    fn default_duration_secs(&self) -> u64 {
+        match self {
+            ScenarioKind::VolatilitySpike => 30 * 60,
+            ScenarioKind::FlashCrash => 20 * 60,
+            ScenarioKind::TrendDay => 390 * 60,
+        }
+    }
+
+    fn default_magnitude(&self) -> f64 {
+        match self {
+            ScenarioKind::VolatilitySpike => 5.0,
+            ScenarioKind::FlashCrash => 0.05,
+            ScenarioKind::TrendDay => 0.02,
+        }
+    }
+}
+
+/// A scenario to schedule, from `POST /api/mock/scenarios`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScenarioRequest {
+    pub kind: ScenarioKind,
+    /// When it starts: RFC3339 or a time expression such as
+    /// `today 13:30 America/New_York` or `now+5m`
+    #[serde(default = "default_at")]
+    pub at: String,
+    /// Defaults to 30 minutes for a spike, 20 for a crash, 6.5 hours for a
+    /// trend day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
+    /// Defaults to 5x for a spike, 5% for a crash, +2% for a trend day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub magnitude: Option<f64>,
+    /// Symbols affected, as subscribed or requested; every symbol when
+    /// empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<String>,
+}
+
+fn default_at() -> String {
+    "now".to_string()
+}
+
+/// A scheduled scenario.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Scenario {
+    pub id: u64,
+    pub kind: ScenarioKind,
+    pub start_unix_ns: u64,
+    pub end_unix_ns: u64,
+    pub magnitude: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<String>,
+}
+
+/// How scenarios bend prices at a moment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regime {
+    /// Multiplies the size of price moves
+    pub volatility: f64,
+    /// Moves prices by this fraction of themselves
+    pub shift: f64,
+}
+
+impl Default for Regime {
+    fn default() -> Self {
+        Self {
+            volatility: 1.0,
+            shift: 0.0,
+        }
+    }
+}
+
+impl Regime {
+    /// This regime with `other` on top: volatilities compound and shifts
+    /// add.
+    pub fn and(self, other: Regime) -> Regime {
+        Regime {
+            volatility: self.volatility * other.volatility,
+            shift: self.shift + other.shift,
+        }
+    }
+}
+
+impl ScenarioRequest {
+    /// The scenario this schedules as `id`, its start resolved against
+    /// `now`.
+    pub fn schedule(&self, id: u64, now: DateTime<Utc>) -> Result<Scenario, String> {
+        let start = timeexpr::resolve(&self.at, now)?;
+        let duration_secs = self
+            .duration_secs
+            .unwrap_or_else(|| self.kind.default_duration_secs());
+        if duration_secs == 0 {
+            return Err("duration_secs must be positive".to_string());
+        }
+        let magnitude = self
+            .magnitude
+            .unwrap_or_else(|| self.kind.default_magnitude());
+        let valid = match self.kind {
+            ScenarioKind::VolatilitySpike => (1.0..=100.0).contains(&magnitude),
+            ScenarioKind::FlashCrash => magnitude > 0.0 && magnitude < 1.0,
+            ScenarioKind::TrendDay => magnitude > -1.0 && magnitude < 1.0,
+        };
+        if !valid {
+            return Err(format!(
+                "magnitude {} out of range: a volatility_spike takes 1 to 100, a flash_crash \
+                 a fraction between 0 and 1, a trend_day a fraction between -1 and 1",
+                magnitude
+            ));
+        }
+        let start_unix_ns = start
+            .timestamp_nanos_opt()
+            .filter(|ns| *ns >= 0)
+            .ok_or_else(|| format!("start {} out of range", start))?
+            as u64;
+        Ok(Scenario {
+            id,
+            kind: self.kind,
+            start_unix_ns,
+            end_unix_ns: start_unix_ns.saturating_add(duration_secs.saturating_mul(1_000_000_000)),
+            magnitude,
+            symbols: self.symbols.clone(),
+        })
+    }
+}
+
+impl Scenario {
+    /// Whether the scenario moves `symbol`.
+    pub fn applies_to(&self, symbol: &str) -> bool {
+        self.symbols.is_empty() || self.symbols.iter().any(|s| s == symbol)
+    }
+
+    /// How the scenario bends prices at `ts_unix_ns`.
+    pub fn regime_at(&self, ts_unix_ns: u64) -> Regime {
+        if ts_unix_ns < self.start_unix_ns {
+            return Regime::default();
+        }
+        let span = self.end_unix_ns.saturating_sub(self.start_unix_ns).max(1);
+        let done = (ts_unix_ns - self.start_unix_ns) as f64 / span as f64;
+        let m = self.magnitude;
+        match self.kind {
+            ScenarioKind::VolatilitySpike if done < 1.0 => Regime {
+                volatility: 1.0 + (m - 1.0) * (1.0 - done),
+                shift: 0.0,
+            },
+            ScenarioKind::FlashCrash if done < 1.0 => Regime {
+                volatility: CRASH_VOLATILITY,
+                shift: match done < CRASH_SHARE {
+                    true => -m * done / CRASH_SHARE,
+                    false => -m * (1.0 - done) / (1.0 - CRASH_SHARE),
+                },
+            },
+            ScenarioKind::TrendDay => Regime {
+                volatility: 1.0,
+                shift: m * done.min(1.0),
+            },
+            _ => Regime::default(),
+        }
+    }
+}
+
+/// The regime `scenarios` make for `symbol` at `ts_unix_ns`.
+pub fn regime_at(scenarios: &[Scenario], symbol: &str, ts_unix_ns: u64) -> Regime {
+    scenarios
+        .iter()
+        .filter(|scenario| scenario.applies_to(symbol))
+        .fold(Regime::default(), |regime, scenario| {
+            regime.and(scenario.regime_at(ts_unix_ns))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60_000_000_000;
+
+    #[test]
+    fn test_schedule() {
+        let now = DateTime::parse_from_rfc3339("2024-06-04T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let req = ScenarioRequest {
+            kind: ScenarioKind::VolatilitySpike,
+            at: "today 13:30 UTC".to_string(),
+            duration_secs: None,
+            magnitude: None,
+            symbols: Vec::new(),
+        };
+        let spike = req.schedule(1, now).unwrap();
+        assert_eq!(spike.start_unix_ns, 1_717_507_800_000_000_000);
+        assert_eq!(spike.end_unix_ns - spike.start_unix_ns, 30 * MINUTE);
+        assert_eq!(spike.magnitude, 5.0);
+
+        let crash = ScenarioRequest {
+            kind: ScenarioKind::FlashCrash,
+            magnitude: Some(1.5),
+            ..req.clone()
+        };
+        assert!(crash.schedule(2, now).is_err());
+        let never = ScenarioRequest {
+            duration_secs: Some(0),
+            ..req
+        };
+        assert!(never.schedule(3, now).is_err());
+    }
+
+    #[test]
+    fn test_regimes() {
+        let scenario = |kind, magnitude| Scenario {
+            id: 1,
+            kind,
+            start_unix_ns: 0,
+            end_unix_ns: 10 * MINUTE,
+            magnitude,
+            symbols: vec!["ES.FUT".to_string()],
+        };
+
+        // A spike fades to nothing by its end
+        let spike = scenario(ScenarioKind::VolatilitySpike, 5.0);
+        assert_eq!(spike.regime_at(0).volatility, 5.0);
+        assert_eq!(spike.regime_at(5 * MINUTE).volatility, 3.0);
+        assert_eq!(spike.regime_at(10 * MINUTE), Regime::default());
+
+        // A crash bottoms out at a fifth of the way, then recovers
+        let crash = scenario(ScenarioKind::FlashCrash, 0.05);
+        assert!((crash.regime_at(2 * MINUTE).shift + 0.05).abs() < 1e-9);
+        assert!((crash.regime_at(6 * MINUTE).shift + 0.025).abs() < 1e-9);
+        assert_eq!(crash.regime_at(10 * MINUTE), Regime::default());
+
+        // A trend holds its move afterwards
+        let trend = scenario(ScenarioKind::TrendDay, -0.02);
+        assert_eq!(trend.regime_at(5 * MINUTE).shift, -0.01);
+        assert_eq!(trend.regime_at(60 * MINUTE).shift, -0.02);
+
+        let all = [spike, trend];
+        let regime = regime_at(&all, "ES.FUT", 5 * MINUTE);
+        assert_eq!((regime.volatility, regime.shift), (3.0, -0.01));
+        assert_eq!(regime_at(&all, "NQ.FUT", 5 * MINUTE), Regime::default());
+    }
+}
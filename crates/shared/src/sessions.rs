@@ -0,0 +1,312 @@
+//! Exchange trading sessions, and DST-safe conversion of exchange-local
+//! wall-clock times to UTC.
+//!
+//! A session is written `OPEN-CLOSE ZONE`, e.g. `17:00-16:00
+//! America/Chicago` for CME Globex, or by market name (`cme`, `cme-rth`,
+//! `nyse`, `eurex`). A close at or before the open runs overnight into the
+//! next day. Sessions are named by their trading day, the day they close
+//! on, and trade Monday to Friday, so CME's Monday session opens Sunday at
+//! 17:00 CT. Times are converted with the offset in force on the day, so
+//! the 17:00 CT open is 23:00 UTC in winter and 22:00 UTC in summer.
+
+use crate::timeexpr::parse_time_of_day;
+use chrono::{
+    DateTime, Datelike, Days, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone,
+    Utc, Weekday,
+};
+use chrono_tz::Tz;
+use std::fmt;
+
+/// Sessions known by market name.
+const MARKETS: &[(&str, &str)] = &[
+    ("cme", "17:00-16:00 America/Chicago"),
+    ("cme-rth", "08:30-15:15 America/Chicago"),
+    ("nyse", "09:30-16:00 America/New_York"),
+    ("eurex", "08:00-22:00 Europe/Berlin"),
+];
+
+/// Longest run of days without a session looked across (a weekend plus
+/// margin).
+const MAX_GAP_DAYS: u64 = 7;
+
+/// The UTC time of a wall-clock time in `tz`. A time repeated when clocks
+/// go back is taken at its first occurrence; a time skipped when they go
+/// forward is read with the offset before the change, so it lands as far
+/// after the gap as it was into it (02:30 becomes 03:30 on a one-hour
+/// change).
+pub fn to_utc(local: NaiveDateTime, tz: Tz) -> Option<DateTime<Utc>> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => {
+            Some(time.with_timezone(&Utc))
+        }
+        LocalResult::None => {
+            let before = tz
+                .from_local_datetime(&(local - chrono::Duration::days(1)))
+                .earliest()?;
+            let offset = chrono::Duration::seconds(before.offset().fix().local_minus_utc().into());
+            Some((local - offset).and_utc())
+        }
+    }
+}
+
+/// One trading day's session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    /// The day the session closes on
+    pub trading_day: NaiveDate,
+    pub open: DateTime<Utc>,
+    pub close: DateTime<Utc>,
+}
+
+impl Session {
+    /// Whether `time` falls in `[open, close)`.
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.open <= time && time < self.close
+    }
+}
+
+/// Daily session hours in an exchange's time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionSpec {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    pub timezone: Tz,
+}
+
+impl SessionSpec {
+    /// Parse a market name (`cme`) or `OPEN-CLOSE [ZONE]`, e.g.
+    /// `17:00-16:00 America/Chicago`; the zone defaults to UTC.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if let Some((_, hours)) = MARKETS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(spec))
+        {
+            return Self::parse(hours);
+        }
+        let invalid = |why: String| {
+            format!(
+                "invalid session '{}': {} (expected a market such as 'cme' or '17:00-16:00 America/Chicago')",
+                spec, why
+            )
+        };
+        let mut words = spec.split_whitespace();
+        let (open, close) = words
+            .next()
+            .and_then(|hours| hours.split_once('-'))
+            .ok_or_else(|| invalid("expected OPEN-CLOSE hours".to_string()))?;
+        let timezone = match words.next() {
+            Some(zone) => zone
+                .parse()
+                .map_err(|_| invalid(format!("unknown time zone '{}'", zone)))?,
+            None => Tz::UTC,
+        };
+        if let Some(extra) = words.next() {
+            return Err(invalid(format!("unexpected '{}'", extra)));
+        }
+        Ok(Self {
+            open: parse_time_of_day(open).map_err(invalid)?,
+            close: parse_time_of_day(close).map_err(invalid)?,
+            timezone,
+        })
+    }
+
+    /// Whether sessions open the day before they close.
+    pub fn overnight(&self) -> bool {
+        self.close <= self.open
+    }
+
+    /// The session of `trading_day`, or `None` on a weekend.
+    pub fn session(&self, trading_day: NaiveDate) -> Option<Session> {
+        if matches!(trading_day.weekday(), Weekday::Sat | Weekday::Sun) {
+            return None;
+        }
+        let open_day = match self.overnight() {
+            true => trading_day.pred_opt()?,
+            false => trading_day,
+        };
+        Some(Session {
+            trading_day,
+            open: to_utc(open_day.and_time(self.open), self.timezone)?,
+            close: to_utc(trading_day.and_time(self.close), self.timezone)?,
+        })
+    }
+
+    /// Local date of `time` in the exchange's time zone.
+    fn local_date(&self, time: DateTime<Utc>) -> NaiveDate {
+        time.with_timezone(&self.timezone).date_naive()
+    }
+
+    /// The session open at `time`, if any.
+    pub fn containing(&self, time: DateTime<Utc>) -> Option<Session> {
+        let date = self.local_date(time);
+        [Some(date), date.succ_opt()]
+            .into_iter()
+            .flatten()
+            .filter_map(|day| self.session(day))
+            .find(|session| session.contains(time))
+    }
+
+    /// The last session to open at or before `time`: the one in progress,
+    /// or the one before if the market is closed.
+    pub fn latest(&self, time: DateTime<Utc>) -> Option<Session> {
+        let mut day = self.local_date(time).succ_opt()?;
+        for _ in 0..=MAX_GAP_DAYS + 1 {
+            if let Some(session) = self.session(day).filter(|s| s.open <= time) {
+                return Some(session);
+            }
+            day = day.pred_opt()?;
+        }
+        None
+    }
+
+    /// The session before `session`.
+    pub fn previous(&self, session: &Session) -> Option<Session> {
+        let mut day = session.trading_day;
+        for _ in 0..MAX_GAP_DAYS {
+            day = day.pred_opt()?;
+            if let Some(session) = self.session(day) {
+                return Some(session);
+            }
+        }
+        None
+    }
+
+    /// Sessions overlapping `[start, end)`, in order.
+    pub fn sessions(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Session> {
+        let (Some(mut day), Some(last)) = (
+            self.local_date(start).pred_opt(),
+            self.local_date(end).checked_add_days(Days::new(1)),
+        ) else {
+            return Vec::new();
+        };
+        let mut sessions = Vec::new();
+        while day <= last {
+            if let Some(session) = self.session(day) {
+                if session.open < end && start < session.close {
+                    sessions.push(session);
+                }
+            }
+            match day.succ_opt() {
+                Some(next) => day = next,
+                None => break,
+            }
+        }
+        sessions
+    }
+
+    /// The parts of `[start, end)` the market is open, in order.
+    pub fn open_spans(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        self.sessions(start, end)
+            .into_iter()
+            .map(|s| (s.open.max(start), s.close.min(end)))
+            .collect()
+    }
+}
+
+impl std::str::FromStr for SessionSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for SessionSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{} {}",
+            self.open.format("%H:%M"),
+            self.close.format("%H:%M"),
+            self.timezone
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn local(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_to_utc_across_dst() {
+        let chicago: Tz = "America/Chicago".parse().unwrap();
+        assert_eq!(
+            to_utc(local("2024-01-15 17:00"), chicago),
+            Some(at("2024-01-15T23:00:00Z"))
+        );
+        assert_eq!(
+            to_utc(local("2024-07-15 17:00"), chicago),
+            Some(at("2024-07-15T22:00:00Z"))
+        );
+        // 02:30 doesn't happen on 2024-03-10; read as CST it's 03:30 CDT
+        assert_eq!(
+            to_utc(local("2024-03-10 02:30"), chicago),
+            Some(at("2024-03-10T08:30:00Z"))
+        );
+        // 01:30 happens twice on 2024-11-03; the first is CDT
+        assert_eq!(
+            to_utc(local("2024-11-03 01:30"), chicago),
+            Some(at("2024-11-03T06:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_cme_sessions() {
+        let cme = SessionSpec::parse("cme").unwrap();
+        assert_eq!(cme.to_string(), "17:00-16:00 America/Chicago");
+        assert!(cme.overnight());
+
+        // Monday 2024-03-11's session opens Sunday, the day DST starts
+        let monday = NaiveDate::from_ymd_opt(2024, 3, 11).unwrap();
+        let session = cme.session(monday).unwrap();
+        assert_eq!(session.open, at("2024-03-10T22:00:00Z"));
+        assert_eq!(session.close, at("2024-03-11T21:00:00Z"));
+        // Friday's closed an hour later in UTC, before the change
+        let friday = cme.previous(&session).unwrap();
+        assert_eq!(
+            friday.trading_day,
+            NaiveDate::from_ymd_opt(2024, 3, 8).unwrap()
+        );
+        assert_eq!(friday.close, at("2024-03-08T22:00:00Z"));
+
+        assert_eq!(cme.containing(at("2024-03-10T23:00:00Z")), Some(session));
+        // The daily break and the weekend are closed
+        assert_eq!(cme.containing(at("2024-03-11T21:30:00Z")), None);
+        assert_eq!(cme.containing(at("2024-03-09T12:00:00Z")), None);
+        assert_eq!(cme.latest(at("2024-03-09T12:00:00Z")), Some(friday));
+        assert_eq!(cme.latest(at("2024-03-11T12:00:00Z")), Some(session));
+
+        let spans = cme.open_spans(at("2024-03-08T20:00:00Z"), at("2024-03-11T00:00:00Z"));
+        assert_eq!(
+            spans,
+            vec![
+                (at("2024-03-08T20:00:00Z"), at("2024-03-08T22:00:00Z")),
+                (at("2024-03-10T22:00:00Z"), at("2024-03-11T00:00:00Z")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_session() {
+        let rth = SessionSpec::parse("09:30-16:00 America/New_York").unwrap();
+        assert!(!rth.overnight());
+        assert_eq!(SessionSpec::parse("NYSE").unwrap(), rth);
+        assert_eq!(SessionSpec::parse("00:00-00:00").unwrap().timezone, Tz::UTC);
+        assert!(SessionSpec::parse("lse").is_err());
+        assert!(SessionSpec::parse("09:30 America/New_York").is_err());
+        assert!(SessionSpec::parse("09:30-16:00 Mars/Olympus").is_err());
+    }
+}
@@ -0,0 +1,605 @@
+//! Statistics computed over a range of bars on the server, for the
+//! `/api/stats/*` endpoints.
+//!
+//! These reduce months of minute bars to a few numbers per symbol, so they
+//! run next to the backend's cache rather than shipping the bars to the
+//! client first.
+
+use crate::aggregate::{resample_bars, NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use crate::timeexpr::parse_lookback;
+use crate::{HistoricalRequest, OhlcvRecord, Schema, SymbolType};
+use chrono::{DateTime, NaiveDate, Timelike};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const SECONDS_PER_DAY: u32 = 86_400;
+
+/// The symbols and time range a statistics request covers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsRange {
+    /// Symbols to query (e.g., ["ES.c.0", "NQ.c.0"])
+    pub symbols: Vec<String>,
+    /// Symbol type input, as in [`HistoricalRequest`]
+    #[serde(default)]
+    pub stype_in: SymbolType,
+    /// Start time (RFC3339 or a relative expression)
+    pub start_rfc3339: String,
+    /// End time (RFC3339 or a relative expression)
+    pub end_rfc3339: String,
+    /// Dataset override, as in [`HistoricalRequest`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<String>,
+}
+
+impl StatsRange {
+    /// A request for the range's `schema` bars, up to `limit` of them.
+    pub fn bars_request(&self, schema: Schema, limit: u32) -> HistoricalRequest {
+        HistoricalRequest {
+            symbols: self.symbols.clone(),
+            schema: schema.as_str().into_owned(),
+            stype_in: self.stype_in,
+            start_rfc3339: self.start_rfc3339.clone(),
+            end_rfc3339: self.end_rfc3339.clone(),
+            limit,
+            dataset: self.dataset.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Request body for `POST /api/stats/intraday-profile`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntradayProfileRequest {
+    #[serde(flatten)]
+    pub range: StatsRange,
+    /// Bucket width, whole minutes dividing a day (e.g. "30m", "1h")
+    #[serde(default = "default_bucket")]
+    pub bucket: String,
+    /// IANA time zone buckets and sessions are read in (default UTC)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+fn default_bucket() -> String {
+    "30m".to_string()
+}
+
+fn default_interval() -> String {
+    "1m".to_string()
+}
+
+/// Parse a bar interval of whole minutes, e.g. "5m" or "1d", into
+/// nanoseconds.
+pub fn parse_bar_interval(interval: &str) -> Result<u64, String> {
+    let nanos = parse_lookback(interval)?
+        .num_nanoseconds()
+        .and_then(|ns| u64::try_from(ns).ok())
+        .unwrap_or(0);
+    if nanos == 0 || nanos % NANOS_PER_MINUTE != 0 {
+        return Err(format!(
+            "interval '{}' must be whole minutes (e.g. 1m, 1h, 1d)",
+            interval
+        ));
+    }
+    Ok(nanos)
+}
+
+/// Bars of one symbol from time-ordered `bars`, by symbol.
+pub(crate) fn by_symbol(bars: &[OhlcvRecord]) -> BTreeMap<&str, Vec<&OhlcvRecord>> {
+    let mut series: BTreeMap<&str, Vec<&OhlcvRecord>> = BTreeMap::new();
+    for bar in bars {
+        series.entry(bar.symbol.as_str()).or_default().push(bar);
+    }
+    series
+}
+
+impl IntradayProfileRequest {
+    /// The bucket width in seconds and the time zone, checked.
+    pub fn buckets(&self) -> Result<(u32, Tz), String> {
+        let width = parse_lookback(&self.bucket)?.num_seconds();
+        if width < 60 || width % 60 != 0 || SECONDS_PER_DAY as i64 % width != 0 {
+            return Err(format!(
+                "bucket '{}' must be whole minutes dividing a day (e.g. 5m, 30m, 1h)",
+                self.bucket
+            ));
+        }
+        let tz = match &self.timezone {
+            Some(name) => name
+                .parse()
+                .map_err(|_| format!("unknown time zone '{}'", name))?,
+            None => Tz::UTC,
+        };
+        Ok((width as u32, tz))
+    }
+}
+
+/// Averages for one time-of-day bucket.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProfileBucket {
+    /// Local wall-clock start of the bucket, e.g. "09:30"
+    pub start: String,
+    /// Sessions with at least one bar in the bucket
+    pub sessions: usize,
+    /// Mean volume traded in the bucket per session
+    pub avg_volume: f64,
+    /// Mean realized volatility in the bucket per session: the square root
+    /// of the summed squared log returns of its bars (0.001 is 0.1%)
+    pub avg_volatility: f64,
+}
+
+/// The average intraday profile of one symbol.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IntradayProfile {
+    pub symbol: String,
+    /// Time zone the buckets are in
+    pub timezone: String,
+    /// Bucket width in seconds
+    pub bucket_secs: u32,
+    /// Sessions (local calendar days) with any bars
+    pub sessions: usize,
+    /// Buckets with any bars, in time-of-day order
+    pub buckets: Vec<ProfileBucket>,
+}
+
+/// Totals for one bucket of one session.
+#[derive(Default)]
+struct BucketSums {
+    volume: u64,
+    squared_returns: f64,
+}
+
+/// Average intraday profiles of time-ordered minute `bars`, one per
+/// symbol, with `bucket_secs`-wide buckets of local time in `tz`. A session
+/// is a local calendar day; the first bar of each session has no return, so
+/// overnight gaps don't count towards volatility.
+pub fn intraday_profiles(bars: &[OhlcvRecord], bucket_secs: u32, tz: Tz) -> Vec<IntradayProfile> {
+    // symbol -> (session, bucket index) -> sums
+    let mut sums: BTreeMap<&str, HashMap<(NaiveDate, u32), BucketSums>> = BTreeMap::new();
+    // symbol -> (session, close) of the previous bar
+    let mut previous: HashMap<&str, (NaiveDate, i64)> = HashMap::new();
+
+    for bar in bars {
+        let secs = (bar.ts_event_unix_ns / NANOS_PER_SECOND) as i64;
+        let Some(utc) = DateTime::from_timestamp(secs, 0) else {
+            continue;
+        };
+        let local = utc.with_timezone(&tz);
+        let session = local.date_naive();
+        let bucket = local.num_seconds_from_midnight() / bucket_secs;
+
+        let cell = sums
+            .entry(bar.symbol.as_str())
+            .or_default()
+            .entry((session, bucket))
+            .or_default();
+        cell.volume += bar.volume_u64;
+        if let Some(&(prev_session, prev_close)) = previous.get(bar.symbol.as_str()) {
+            if prev_session == session && prev_close > 0 && bar.close_i64 > 0 {
+                let r = (bar.close_i64 as f64 / prev_close as f64).ln();
+                cell.squared_returns += r * r;
+            }
+        }
+        previous.insert(bar.symbol.as_str(), (session, bar.close_i64));
+    }
+
+    sums.into_iter()
+        .map(|(symbol, cells)| {
+            let sessions = cells
+                .keys()
+                .map(|(session, _)| *session)
+                .collect::<HashSet<_>>()
+                .len();
+            // bucket -> (sessions, volume, summed volatility)
+            let mut totals: BTreeMap<u32, (usize, u64, f64)> = BTreeMap::new();
+            for ((_, bucket), cell) in cells {
+                let total = totals.entry(bucket).or_default();
+                total.0 += 1;
+                total.1 += cell.volume;
+                total.2 += cell.squared_returns.sqrt();
+            }
+            IntradayProfile {
+                symbol: symbol.to_string(),
+                timezone: tz.name().to_string(),
+                bucket_secs,
+                sessions,
+                buckets: totals
+                    .into_iter()
+                    .map(|(bucket, (count, volume, volatility))| {
+                        let start = bucket * bucket_secs;
+                        ProfileBucket {
+                            start: format!("{:02}:{:02}", start / 3600, start % 3600 / 60),
+                            sessions: count,
+                            avg_volume: volume as f64 / count as f64,
+                            avg_volatility: volatility / count as f64,
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// How [`returns`] estimates volatility from bars.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VolEstimator {
+    /// Sample standard deviation of close-to-close log returns
+    #[default]
+    CloseToClose,
+    /// Parkinson's high-low range estimator
+    Parkinson,
+    /// Garman-Klass: high-low range plus open-to-close move
+    GarmanKlass,
+}
+
+/// Request body for `POST /api/stats/returns`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReturnsRequest {
+    #[serde(flatten)]
+    pub range: StatsRange,
+    /// Interval returns are measured over, whole minutes (default "1m")
+    #[serde(default = "default_interval")]
+    pub interval: String,
+    #[serde(default)]
+    pub estimator: VolEstimator,
+}
+
+/// Returns and risk of one symbol over a range.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SymbolReturns {
+    pub symbol: String,
+    /// Bars the statistics are computed from
+    pub bars: usize,
+    /// Log return from the first bar's close to the last's
+    pub total_log_return: f64,
+    /// Mean close-to-close log return per bar
+    pub mean_log_return: f64,
+    /// Volatility per bar interval, by the report's estimator (0.001 is
+    /// 0.1%); multiply by the square root of bars per year to annualize
+    pub volatility: f64,
+    /// Largest fall in close from a prior peak, as a fraction of the peak
+    pub max_drawdown: f64,
+}
+
+/// Response to `POST /api/stats/returns`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReturnsReport {
+    /// Bar interval in seconds
+    pub interval_secs: u64,
+    pub estimator: VolEstimator,
+    pub symbols: Vec<SymbolReturns>,
+}
+
+/// Returns and volatility per symbol of time-ordered `bars`. Bars with
+/// non-positive prices are skipped.
+pub fn returns(bars: &[OhlcvRecord], estimator: VolEstimator) -> Vec<SymbolReturns> {
+    by_symbol(bars)
+        .into_iter()
+        .map(|(symbol, series)| {
+            let series: Vec<_> = series
+                .into_iter()
+                .filter(|b| b.open_i64 > 0 && b.low_i64 > 0 && b.close_i64 > 0)
+                .collect();
+            let log_returns: Vec<f64> = series
+                .windows(2)
+                .map(|pair| ln_ratio(pair[1].close_i64, pair[0].close_i64))
+                .collect();
+            let total_log_return = log_returns.iter().sum::<f64>();
+            let mean_log_return = match log_returns.len() {
+                0 => 0.0,
+                n => total_log_return / n as f64,
+            };
+
+            let variance = match estimator {
+                VolEstimator::CloseToClose => match log_returns.len() {
+                    0 | 1 => 0.0,
+                    n => {
+                        log_returns
+                            .iter()
+                            .map(|r| (r - mean_log_return).powi(2))
+                            .sum::<f64>()
+                            / (n - 1) as f64
+                    }
+                },
+                VolEstimator::Parkinson => {
+                    mean(
+                        series
+                            .iter()
+                            .map(|b| ln_ratio(b.high_i64, b.low_i64).powi(2)),
+                    ) / (4.0 * std::f64::consts::LN_2)
+                }
+                VolEstimator::GarmanKlass => mean(series.iter().map(|b| {
+                    0.5 * ln_ratio(b.high_i64, b.low_i64).powi(2)
+                        - (2.0 * std::f64::consts::LN_2 - 1.0)
+                            * ln_ratio(b.close_i64, b.open_i64).powi(2)
+                })),
+            };
+
+            SymbolReturns {
+                symbol: symbol.to_string(),
+                bars: series.len(),
+                total_log_return,
+                mean_log_return,
+                volatility: variance.max(0.0).sqrt(),
+                max_drawdown: max_drawdown(series.iter().map(|b| b.close_i64)),
+            }
+        })
+        .collect()
+}
+
+fn ln_ratio(a: i64, b: i64) -> f64 {
+    (a as f64 / b as f64).ln()
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, n) = values.fold((0.0, 0usize), |(sum, n), v| (sum + v, n + 1));
+    if n == 0 {
+        0.0
+    } else {
+        sum / n as f64
+    }
+}
+
+/// Largest fall from a running peak of `prices`, as a fraction of the peak.
+fn max_drawdown(prices: impl Iterator<Item = i64>) -> f64 {
+    let mut peak = 0;
+    let mut worst: f64 = 0.0;
+    for price in prices {
+        peak = peak.max(price);
+        if peak > 0 {
+            worst = worst.max((peak - price) as f64 / peak as f64);
+        }
+    }
+    worst
+}
+
+/// How [`correlation`] treats a bar one symbol has and another lacks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingBars {
+    /// Skip returns that span a missing bar
+    #[default]
+    Drop,
+    /// Carry the last close forward, so a missing bar is a zero return
+    FillForward,
+}
+
+/// Request body for `POST /api/stats/correlation`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorrelationRequest {
+    #[serde(flatten)]
+    pub range: StatsRange,
+    /// Interval returns are measured over, whole minutes (default "1m")
+    #[serde(default = "default_interval")]
+    pub interval: String,
+    #[serde(default)]
+    pub missing: MissingBars,
+}
+
+/// Response to `POST /api/stats/correlation`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CorrelationMatrix {
+    /// Bar interval in seconds
+    pub interval_secs: u64,
+    pub missing: MissingBars,
+    /// Symbols in matrix order
+    pub symbols: Vec<String>,
+    /// Pearson correlation of log returns for each pair of symbols; null
+    /// when a pair has fewer than two returns in common or one doesn't move
+    pub matrix: Vec<Vec<Option<f64>>>,
+    /// Returns each pair has in common
+    pub observations: Vec<Vec<usize>>,
+}
+
+/// Pairwise correlation of log returns between the symbols of
+/// time-ordered `bars`, merged into bars of `interval_ns`. Returns are
+/// aligned on the timeline of every bar start seen for any symbol.
+pub fn correlation(
+    bars: &[OhlcvRecord],
+    interval_ns: u64,
+    missing: MissingBars,
+) -> CorrelationMatrix {
+    let bars = &resample_bars(bars, interval_ns);
+    let mut timeline: Vec<u64> = bars.iter().map(|b| b.ts_event_unix_ns).collect();
+    timeline.sort_unstable();
+    timeline.dedup();
+
+    // Per symbol, the log return into each timeline step, if known
+    let series = by_symbol(bars);
+    let symbols: Vec<String> = series.keys().map(|s| s.to_string()).collect();
+    let returns: Vec<Vec<Option<f64>>> = series
+        .values()
+        .map(|bars| {
+            let mut closes = vec![None; timeline.len()];
+            for bar in bars.iter().filter(|b| b.close_i64 > 0) {
+                if let Ok(step) = timeline.binary_search(&bar.ts_event_unix_ns) {
+                    closes[step] = Some(bar.close_i64);
+                }
+            }
+            if missing == MissingBars::FillForward {
+                for step in 1..closes.len() {
+                    if closes[step].is_none() {
+                        closes[step] = closes[step - 1];
+                    }
+                }
+            }
+            std::iter::once(None)
+                .chain(closes.windows(2).map(|pair| match (pair[0], pair[1]) {
+                    (Some(prev), Some(close)) => Some(ln_ratio(close, prev)),
+                    _ => None,
+                }))
+                .collect()
+        })
+        .collect();
+
+    let n = symbols.len();
+    let mut matrix = vec![vec![None; n]; n];
+    let mut observations = vec![vec![0; n]; n];
+    for i in 0..n {
+        for j in i..n {
+            let pairs: Vec<(f64, f64)> = returns[i]
+                .iter()
+                .zip(&returns[j])
+                .filter_map(|(a, b)| Some(((*a)?, (*b)?)))
+                .collect();
+            let r = pearson(&pairs);
+            matrix[i][j] = r;
+            matrix[j][i] = r;
+            observations[i][j] = pairs.len();
+            observations[j][i] = pairs.len();
+        }
+    }
+    CorrelationMatrix {
+        interval_secs: interval_ns / NANOS_PER_SECOND,
+        missing,
+        symbols,
+        matrix,
+        observations,
+    }
+}
+
+/// Pearson correlation of `pairs`, or `None` with fewer than two pairs or
+/// no variance on either side.
+fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|(a, _)| a).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|(_, b)| b).sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (a, b) in pairs {
+        cov += (a - mean_a) * (b - mean_b);
+        var_a += (a - mean_a).powi(2);
+        var_b += (b - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return None;
+    }
+    Some((cov / (var_a * var_b).sqrt()).clamp(-1.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(ts_secs: u64, close: i64, volume: u64) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: ts_secs * NANOS_PER_SECOND,
+            symbol: "ES.c.0".to_string(),
+            open_i64: close,
+            high_i64: close,
+            low_i64: close,
+            close_i64: close,
+            volume_u64: volume,
+        }
+    }
+
+    #[test]
+    fn test_intraday_profile() {
+        // 2024-07-01 and 2024-07-02, 13:30 UTC (09:30 New York)
+        let day1 = 1_719_840_600;
+        let day2 = day1 + SECONDS_PER_DAY as u64;
+        let bars = [
+            bar(day1, 100, 10),
+            bar(day1 + 60, 110, 30),
+            bar(day1 + 1800, 110, 5),
+            bar(day2, 100, 20),
+            bar(day2 + 60, 100, 40),
+        ];
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let profiles = intraday_profiles(&bars, 1800, tz);
+        assert_eq!(profiles.len(), 1);
+        let profile = &profiles[0];
+        assert_eq!(profile.sessions, 2);
+
+        let open = &profile.buckets[0];
+        assert_eq!(open.start, "09:30");
+        assert_eq!(open.sessions, 2);
+        assert_eq!(open.avg_volume, 50.0);
+        // One 10% move on day 1, none on day 2
+        assert!((open.avg_volatility - (1.1f64).ln() / 2.0).abs() < 1e-12);
+
+        let next = &profile.buckets[1];
+        assert_eq!((next.start.as_str(), next.sessions), ("10:00", 1));
+        assert_eq!(next.avg_volatility, 0.0);
+    }
+
+    #[test]
+    fn test_bucket_must_divide_day() {
+        let req = |bucket: &str| IntradayProfileRequest {
+            range: StatsRange {
+                symbols: vec!["ES.c.0".to_string()],
+                stype_in: SymbolType::Continuous,
+                start_rfc3339: "now-30d".to_string(),
+                end_rfc3339: "now".to_string(),
+                dataset: None,
+            },
+            bucket: bucket.to_string(),
+            timezone: None,
+        };
+        assert_eq!(req("1h").buckets().unwrap().0, 3600);
+        assert!(req("7m").buckets().is_err());
+        assert!(req("90s").buckets().is_err());
+    }
+
+    #[test]
+    fn test_returns_and_drawdown() {
+        let bars = [
+            bar(0, 100, 1),
+            bar(60, 120, 1),
+            bar(120, 90, 1),
+            bar(180, 110, 1),
+        ];
+        let report = returns(&bars, VolEstimator::CloseToClose);
+        let r = &report[0];
+        assert_eq!(r.bars, 4);
+        assert!((r.total_log_return - (1.1f64).ln()).abs() < 1e-12);
+        // From the 120 peak down to 90
+        assert_eq!(r.max_drawdown, 0.25);
+        let rets = [(1.2f64).ln(), (0.75f64).ln(), (110.0f64 / 90.0).ln()];
+        let m = rets.iter().sum::<f64>() / 3.0;
+        let var = rets.iter().map(|r| (r - m).powi(2)).sum::<f64>() / 2.0;
+        assert!((r.volatility - var.sqrt()).abs() < 1e-12);
+
+        // Flat bars have no range
+        let flat = returns(&bars, VolEstimator::Parkinson);
+        assert_eq!(flat[0].volatility, 0.0);
+        let ranged = OhlcvRecord {
+            high_i64: 110,
+            low_i64: 100,
+            ..bar(0, 105, 1)
+        };
+        let parkinson = returns(&[ranged], VolEstimator::Parkinson);
+        let expected = ((1.1f64).ln().powi(2) / (4.0 * std::f64::consts::LN_2)).sqrt();
+        assert!((parkinson[0].volatility - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_correlation_with_missing_bars() {
+        let with_symbol = |symbol: &str, ts: u64, close: i64| OhlcvRecord {
+            symbol: symbol.to_string(),
+            ..bar(ts, close, 1)
+        };
+        let mut bars = Vec::new();
+        for (step, close) in [100, 101, 99, 102, 103].into_iter().enumerate() {
+            let ts = step as u64 * 60;
+            bars.push(with_symbol("ES.c.0", ts, close));
+            // NQ moves with ES, twice as far, but has no bar at 120s
+            if ts != 120 {
+                bars.push(with_symbol("NQ.c.0", ts, 200 + 2 * (close - 100)));
+            }
+        }
+
+        let dropped = correlation(&bars, NANOS_PER_MINUTE, MissingBars::Drop);
+        assert_eq!(dropped.symbols, ["ES.c.0", "NQ.c.0"]);
+        assert_eq!(dropped.observations, [[4, 2], [2, 2]]);
+        assert_eq!(dropped.matrix[0][0], Some(1.0));
+        assert!(dropped.matrix[0][1].unwrap() > 0.99);
+
+        let filled = correlation(&bars, NANOS_PER_MINUTE, MissingBars::FillForward);
+        assert_eq!(filled.observations[0][1], 4);
+        assert!(filled.matrix[1][0].unwrap() < 0.99);
+    }
+}
@@ -0,0 +1,64 @@
+//! Provider health, returned by `GET /api/status` and pushed to live
+//! sockets as `provider_status` messages when it changes.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderHealth {
+    Up,
+    /// Serving, but live data has gone quiet or many requests fail
+    Degraded,
+    /// Repeated upstream failures
+    Down,
+}
+
+/// Current health of one upstream provider.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProviderStatus {
+    pub provider: String,
+    pub health: ProviderHealth,
+    /// Why the provider is in this state
+    pub reason: String,
+    /// When it entered this state (RFC3339)
+    pub since: String,
+    /// Upstream requests in the error-rate window, and how many failed
+    pub recent_requests: u64,
+    pub recent_failures: u64,
+    /// Open live streams
+    pub live_streams: usize,
+    /// Last live message or subscription (RFC3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_live_at: Option<String>,
+}
+
+/// A period a provider spent degraded or down.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Incident {
+    pub id: u64,
+    pub provider: String,
+    pub health: ProviderHealth,
+    pub reason: String,
+    pub started_at: String,
+    /// When the provider left this state; open incidents have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<String>,
+}
+
+/// Response to `GET /api/status`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatusReport {
+    pub providers: Vec<ProviderStatus>,
+    /// Recent incidents, newest first
+    pub incidents: Vec<Incident>,
+}
+
+/// A provider health change, as sent to live sockets.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StatusChange {
+    pub provider: String,
+    pub health: ProviderHealth,
+    pub reason: String,
+    /// When the change was detected (RFC3339)
+    pub at: String,
+}
@@ -0,0 +1,40 @@
+//! Local disk usage and retention types.
+
+use serde::{Deserialize, Serialize};
+
+/// Limits on what a subsystem keeps on disk. Unset limits are unlimited.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// Files whose data is older than this are deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+}
+
+/// Disk used by one subsystem.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SubsystemUsage {
+    /// e.g. "recordings"
+    pub name: String,
+    /// Directory or files it keeps, as configured
+    pub path: String,
+    pub bytes: u64,
+    pub files: u64,
+    /// Time of the oldest data kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oldest_unix_ns: Option<u64>,
+    /// Absent for subsystems that are never evicted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+    /// Files deleted by retention since the server started
+    pub evicted_files: u64,
+    pub evicted_bytes: u64,
+}
+
+/// Response of `GET /api/admin/storage`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StorageReport {
+    pub total_bytes: u64,
+    pub subsystems: Vec<SubsystemUsage>,
+}
@@ -0,0 +1,116 @@
+//! Day summaries: a symbol's open, high, low, last, volume and VWAP over
+//! the current session, so quote boards needn't rebuild them from trades.
+
+use crate::change::DayChange;
+use serde::{Deserialize, Serialize};
+
+/// A symbol's session so far.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DaySummary {
+    pub symbol: String,
+    /// The session's trading day, YYYY-MM-DD
+    pub trading_day: String,
+    /// First trade counted: open, high and low miss anything earlier in
+    /// the session
+    pub from_unix_ns: u64,
+    /// Latest trade counted
+    pub last_unix_ns: u64,
+    pub open_i64: i64,
+    pub high_i64: i64,
+    pub low_i64: i64,
+    pub last_i64: i64,
+    pub volume_u64: u64,
+    /// Volume-weighted average price
+    pub vwap_i64: i64,
+    pub trades_u64: u64,
+    /// The last price against the previous session's close, once known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day_change: Option<DayChange>,
+}
+
+/// One symbol's day summary as it's built from trades.
+#[derive(Debug, Clone)]
+pub struct DaySummaryBuilder {
+    summary: Option<DaySummary>,
+    symbol: String,
+    trading_day: String,
+    /// Sum of price times size, for the VWAP
+    notional: i128,
+}
+
+impl DaySummaryBuilder {
+    /// An empty summary of `symbol` for the session of `trading_day`.
+    pub fn new(symbol: &str, trading_day: &str) -> Self {
+        Self {
+            summary: None,
+            symbol: symbol.to_string(),
+            trading_day: trading_day.to_string(),
+            notional: 0,
+        }
+    }
+
+    /// Count a trade. Trades are taken in arrival order, so the last
+    /// price is the latest trade's even if one arrives out of time order.
+    pub fn add(&mut self, ts_event_unix_ns: u64, price_i64: i64, size_u32: u32) {
+        self.notional += i128::from(price_i64) * i128::from(size_u32);
+        let summary = self.summary.get_or_insert_with(|| DaySummary {
+            symbol: self.symbol.clone(),
+            trading_day: self.trading_day.clone(),
+            from_unix_ns: ts_event_unix_ns,
+            last_unix_ns: ts_event_unix_ns,
+            open_i64: price_i64,
+            high_i64: price_i64,
+            low_i64: price_i64,
+            last_i64: price_i64,
+            volume_u64: 0,
+            vwap_i64: price_i64,
+            trades_u64: 0,
+            day_change: None,
+        });
+        summary.last_unix_ns = summary.last_unix_ns.max(ts_event_unix_ns);
+        summary.high_i64 = summary.high_i64.max(price_i64);
+        summary.low_i64 = summary.low_i64.min(price_i64);
+        summary.last_i64 = price_i64;
+        summary.volume_u64 += u64::from(size_u32);
+        summary.trades_u64 += 1;
+        if summary.volume_u64 > 0 {
+            summary.vwap_i64 = (self.notional / i128::from(summary.volume_u64)) as i64;
+        }
+    }
+
+    /// The summary so far, once a trade was counted.
+    pub fn summary(&self) -> Option<&DaySummary> {
+        self.summary.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day_summary() {
+        let mut builder = DaySummaryBuilder::new("ES.FUT", "2024-06-03");
+        assert!(builder.summary().is_none());
+        builder.add(10, 100, 1);
+        builder.add(11, 104, 3);
+        builder.add(12, 98, 0);
+
+        let summary = builder.summary().unwrap();
+        assert_eq!(
+            (summary.open_i64, summary.high_i64, summary.low_i64),
+            (100, 104, 98)
+        );
+        assert_eq!((summary.last_i64, summary.volume_u64), (98, 4));
+        // (100 * 1 + 104 * 3) / 4
+        assert_eq!(summary.vwap_i64, 103);
+        assert_eq!(
+            (
+                summary.from_unix_ns,
+                summary.last_unix_ns,
+                summary.trades_u64
+            ),
+            (10, 12, 3)
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! The trade tape ("time & sales"): a symbol's latest trades, newest first.
+//!
+//! [`select`] cuts the trades read for `GET /api/tape` down to what the
+//! panel shows. Clients poll with the previous response's
+//! `latest_unix_ns` as `since_ns`, so each poll only carries the trades
+//! printed since.
+
+use crate::{DataSource, TradeRecord};
+use serde::{Deserialize, Serialize};
+
+/// Where a tape's trades were read from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TapeSource {
+    /// The server's own live recording of the symbol
+    Recording,
+    /// The shared historical cache
+    Cache,
+    /// The market data provider
+    Provider,
+}
+
+impl From<DataSource> for TapeSource {
+    fn from(source: DataSource) -> Self {
+        match source {
+            DataSource::Cache => TapeSource::Cache,
+            DataSource::Provider => TapeSource::Provider,
+        }
+    }
+}
+
+/// Response of `GET /api/tape`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tape {
+    pub symbol: String,
+    pub source: TapeSource,
+    /// Trades of at least the requested size, newest first
+    pub trades: Vec<TradeRecord>,
+    /// Newest trade time read, whatever its size; pass it as `since_ns` to
+    /// poll for later trades. The `since_ns` asked for when nothing was read.
+    pub latest_unix_ns: Option<u64>,
+    /// Trades that matched but were left out past `limit`, all older than
+    /// the oldest one sent
+    pub skipped: usize,
+    /// Whether more trades printed after `latest_unix_ns` than one read
+    /// takes; polling again catches up
+    pub more: bool,
+}
+
+/// The tape of `trades`, in time order: those after `since_ns` of at least
+/// `min_size`, the newest `limit` of them, newest first. Also returns how
+/// many matched but were left out.
+pub fn select(
+    trades: Vec<TradeRecord>,
+    min_size: u32,
+    since_ns: Option<u64>,
+    limit: usize,
+) -> (Vec<TradeRecord>, usize) {
+    let mut selected: Vec<TradeRecord> = trades
+        .into_iter()
+        .filter(|trade| since_ns.is_none_or(|since| trade.ts_event_unix_ns > since))
+        .filter(|trade| trade.size_u32 >= min_size)
+        .collect();
+    let skipped = selected.len().saturating_sub(limit);
+    selected.drain(..skipped);
+    selected.reverse();
+    (selected, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(ts: u64, size: u32) -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ES.FUT".to_string(),
+            price_i64: 5_000_000_000_000,
+            size_u32: size,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    fn times(trades: &[TradeRecord]) -> Vec<u64> {
+        trades.iter().map(|t| t.ts_event_unix_ns).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let trades = vec![
+            trade(1, 5),
+            trade(2, 50),
+            trade(3, 1),
+            trade(4, 80),
+            trade(5, 20),
+        ];
+
+        let (tape, skipped) = select(trades.clone(), 0, None, 10);
+        assert_eq!((times(&tape), skipped), (vec![5, 4, 3, 2, 1], 0));
+
+        // Block trades only, newest kept when the limit binds
+        let (tape, skipped) = select(trades.clone(), 20, None, 2);
+        assert_eq!((times(&tape), skipped), (vec![5, 4], 1));
+
+        // Polling: strictly after since_ns
+        let (tape, skipped) = select(trades.clone(), 0, Some(3), 10);
+        assert_eq!((times(&tape), skipped), (vec![5, 4], 0));
+        let (tape, _) = select(trades, 0, Some(5), 10);
+        assert!(tape.is_empty());
+    }
+}
@@ -0,0 +1,388 @@
+//! Transaction cost analysis: fills benchmarked against market prices.
+//!
+//! For `POST /api/tca`. Each fill is compared with three benchmarks built
+//! from minute bars of its symbol:
+//!
+//! - arrival: the close of the last bar that ended at or before the fill
+//! - VWAP: the volume-weighted typical price of the bars within half the
+//!   VWAP window either side of the fill
+//! - close: the last close of the fill's local calendar day
+//!
+//! Slippage is signed so that a positive value is a cost: paying above the
+//! benchmark on a buy, or selling below it.
+
+use crate::aggregate::{NANOS_PER_MINUTE, NANOS_PER_SECOND};
+use crate::paper::OrderSide;
+use crate::stats::{by_symbol, parse_bar_interval, StatsRange};
+use crate::{OhlcvRecord, SymbolType};
+use chrono::{DateTime, SecondsFormat, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Most fills one request may analyze.
+pub const MAX_FILLS: usize = 10_000;
+
+/// One execution to analyze.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TcaFill {
+    /// Execution time (RFC3339)
+    pub ts_rfc3339: String,
+    pub symbol: String,
+    pub side: OrderSide,
+    /// Execution price (fixed-point, 1e9 scale)
+    pub price_i64: i64,
+    pub qty: u32,
+}
+
+/// Request body for `POST /api/tca`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcaRequest {
+    pub fills: Vec<TcaFill>,
+    /// Symbol type of the fills' symbols, as in [`crate::HistoricalRequest`]
+    #[serde(default)]
+    pub stype_in: SymbolType,
+    /// Dataset override, as in [`crate::HistoricalRequest`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<String>,
+    /// Width of the VWAP window centred on each fill, whole minutes
+    #[serde(default = "default_vwap_window")]
+    pub vwap_window: String,
+    /// IANA time zone whose calendar day sets the close (default UTC)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+fn default_vwap_window() -> String {
+    "5m".to_string()
+}
+
+/// A value per benchmark; `None` where the benchmark had no data.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct Benchmarks<T> {
+    pub arrival: Option<T>,
+    pub vwap: Option<T>,
+    pub close: Option<T>,
+}
+
+impl<T> Benchmarks<T> {
+    fn map<U>(&self, mut f: impl FnMut(&T) -> Option<U>) -> Benchmarks<U> {
+        Benchmarks {
+            arrival: self.arrival.as_ref().and_then(&mut f),
+            vwap: self.vwap.as_ref().and_then(&mut f),
+            close: self.close.as_ref().and_then(&mut f),
+        }
+    }
+}
+
+/// A fill with its benchmarks and slippage against each.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FillTca {
+    #[serde(flatten)]
+    pub fill: TcaFill,
+    /// Benchmark prices (fixed-point)
+    pub benchmarks: Benchmarks<i64>,
+    /// Slippage in basis points of each benchmark
+    pub slippage_bps: Benchmarks<f64>,
+    /// Slippage times quantity (fixed-point, no contract multiplier)
+    pub cost_i64: Benchmarks<i64>,
+}
+
+/// Totals across all fills.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TcaSummary {
+    pub fills: usize,
+    pub qty: u64,
+    /// Quantity-weighted mean slippage over the fills with each benchmark
+    pub slippage_bps: Benchmarks<f64>,
+    /// Summed cost over the fills with each benchmark
+    pub cost_i64: Benchmarks<i64>,
+}
+
+/// Response of `POST /api/tca`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TcaReport {
+    pub vwap_window_secs: u64,
+    pub timezone: String,
+    pub fills: Vec<FillTca>,
+    pub summary: TcaSummary,
+}
+
+/// A checked request: fill times parsed, and the bars needed to cover them.
+#[derive(Debug, Clone)]
+pub struct TcaPlan {
+    /// Minute bars to fetch
+    pub range: StatsRange,
+    /// Fills with their times in Unix nanoseconds
+    fills: Vec<(u64, TcaFill)>,
+    window_ns: u64,
+    tz: Tz,
+}
+
+/// Start and end (exclusive) of the local calendar day containing `ts_ns`.
+fn local_day(ts_ns: u64, tz: Tz) -> Option<(u64, u64)> {
+    let local = DateTime::from_timestamp_nanos(ts_ns as i64).with_timezone(&tz);
+    let midnight = |date: chrono::NaiveDate| {
+        tz.from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?
+            .timestamp_nanos_opt()
+            .and_then(|ns| u64::try_from(ns).ok())
+    };
+    let date = local.date_naive();
+    Some((midnight(date)?, midnight(date.succ_opt()?)?))
+}
+
+fn rfc3339(ts_ns: u64) -> String {
+    DateTime::from_timestamp_nanos(ts_ns as i64).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+impl TcaRequest {
+    /// Check the request and work out the bars it needs.
+    pub fn plan(self) -> Result<TcaPlan, String> {
+        if self.fills.is_empty() {
+            return Err("no fills given".to_string());
+        }
+        if self.fills.len() > MAX_FILLS {
+            return Err(format!("at most {} fills per request", MAX_FILLS));
+        }
+        let window_ns = parse_bar_interval(&self.vwap_window)?;
+        let tz = match &self.timezone {
+            Some(name) => name
+                .parse()
+                .map_err(|_| format!("unknown time zone '{}'", name))?,
+            None => Tz::UTC,
+        };
+
+        let mut fills = Vec::with_capacity(self.fills.len());
+        for (i, fill) in self.fills.into_iter().enumerate() {
+            let ts_ns = DateTime::parse_from_rfc3339(&fill.ts_rfc3339)
+                .ok()
+                .and_then(|t| t.timestamp_nanos_opt())
+                .and_then(|ns| u64::try_from(ns).ok())
+                .ok_or_else(|| format!("fill {}: bad time '{}'", i, fill.ts_rfc3339))?;
+            if fill.symbol.trim().is_empty() || fill.qty == 0 || fill.price_i64 <= 0 {
+                return Err(format!(
+                    "fill {}: needs a symbol, a positive qty and a positive price",
+                    i
+                ));
+            }
+            fills.push((ts_ns, fill));
+        }
+
+        let half_window = window_ns / 2;
+        let mut start = u64::MAX;
+        let mut end = 0;
+        for (ts_ns, fill) in &fills {
+            let (_, day_end) = local_day(*ts_ns, tz)
+                .ok_or_else(|| format!("fill time {} is out of range", fill.ts_rfc3339))?;
+            start = start.min(ts_ns.saturating_sub(half_window + NANOS_PER_MINUTE));
+            end = end.max(day_end).max(ts_ns + half_window);
+        }
+        let symbols: BTreeSet<&str> = fills.iter().map(|(_, f)| f.symbol.as_str()).collect();
+        let range = StatsRange {
+            symbols: symbols.into_iter().map(str::to_string).collect(),
+            stype_in: self.stype_in,
+            start_rfc3339: rfc3339(start),
+            end_rfc3339: rfc3339(end),
+            dataset: self.dataset,
+        };
+
+        Ok(TcaPlan {
+            range,
+            fills,
+            window_ns,
+            tz,
+        })
+    }
+}
+
+impl TcaPlan {
+    /// Number of fills in the plan.
+    pub fn len(&self) -> usize {
+        self.fills.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fills.is_empty()
+    }
+
+    /// Benchmark every fill against time-ordered minute `bars` of the range.
+    pub fn analyze(self, bars: &[OhlcvRecord]) -> TcaReport {
+        let series = by_symbol(bars);
+        let half_window = self.window_ns / 2;
+
+        let fills: Vec<FillTca> = self
+            .fills
+            .into_iter()
+            .map(|(ts_ns, fill)| {
+                let bars = series
+                    .get(fill.symbol.as_str())
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let benchmarks = Benchmarks {
+                    arrival: arrival(bars, ts_ns),
+                    vwap: vwap(bars, ts_ns.saturating_sub(half_window), ts_ns + half_window),
+                    close: local_day(ts_ns, self.tz)
+                        .and_then(|(start, end)| close(bars, start, end)),
+                };
+                let sign = match fill.side {
+                    OrderSide::Buy => 1,
+                    OrderSide::Sell => -1,
+                };
+                let slippage = |benchmark: &i64| sign * (fill.price_i64 - benchmark);
+                FillTca {
+                    slippage_bps: benchmarks
+                        .map(|b| (*b > 0).then(|| slippage(b) as f64 / *b as f64 * 10_000.0)),
+                    cost_i64: benchmarks.map(|b| Some(slippage(b) * fill.qty as i64)),
+                    benchmarks,
+                    fill,
+                }
+            })
+            .collect();
+
+        let summary = summarize(&fills);
+        TcaReport {
+            vwap_window_secs: self.window_ns / NANOS_PER_SECOND,
+            timezone: self.tz.name().to_string(),
+            fills,
+            summary,
+        }
+    }
+}
+
+/// Close of the last bar ending at or before `ts_ns`.
+fn arrival(bars: &[&OhlcvRecord], ts_ns: u64) -> Option<i64> {
+    let ended = bars.partition_point(|b| b.ts_event_unix_ns + NANOS_PER_MINUTE <= ts_ns);
+    ended.checked_sub(1).map(|i| bars[i].close_i64)
+}
+
+/// Volume-weighted typical price of the bars starting in `[start, end)`.
+fn vwap(bars: &[&OhlcvRecord], start: u64, end: u64) -> Option<i64> {
+    let from = bars.partition_point(|b| b.ts_event_unix_ns < start);
+    let to = bars.partition_point(|b| b.ts_event_unix_ns < end);
+    let (notional, volume) =
+        bars[from..to.max(from)]
+            .iter()
+            .fold((0f64, 0u64), |(notional, volume), b| {
+                let typical = (b.high_i64 + b.low_i64 + b.close_i64) as f64 / 3.0;
+                (
+                    notional + typical * b.volume_u64 as f64,
+                    volume + b.volume_u64,
+                )
+            });
+    (volume > 0).then(|| (notional / volume as f64).round() as i64)
+}
+
+/// Close of the last bar starting in `[start, end)`.
+fn close(bars: &[&OhlcvRecord], start: u64, end: u64) -> Option<i64> {
+    let to = bars.partition_point(|b| b.ts_event_unix_ns < end);
+    to.checked_sub(1)
+        .map(|i| bars[i])
+        .filter(|b| b.ts_event_unix_ns >= start)
+        .map(|b| b.close_i64)
+}
+
+fn summarize(fills: &[FillTca]) -> TcaSummary {
+    let mut bps = Benchmarks::<(f64, u64)>::default();
+    let mut cost = Benchmarks::<i64>::default();
+    for fill in fills {
+        let qty = fill.fill.qty as u64;
+        let add = |total: &mut Option<(f64, u64)>, value: Option<f64>| {
+            if let Some(value) = value {
+                let (sum, weight) = total.get_or_insert((0.0, 0));
+                *sum += value * qty as f64;
+                *weight += qty;
+            }
+        };
+        add(&mut bps.arrival, fill.slippage_bps.arrival);
+        add(&mut bps.vwap, fill.slippage_bps.vwap);
+        add(&mut bps.close, fill.slippage_bps.close);
+        for (total, value) in [
+            (&mut cost.arrival, fill.cost_i64.arrival),
+            (&mut cost.vwap, fill.cost_i64.vwap),
+            (&mut cost.close, fill.cost_i64.close),
+        ] {
+            if let Some(value) = value {
+                *total.get_or_insert(0) += value;
+            }
+        }
+    }
+    TcaSummary {
+        fills: fills.len(),
+        qty: fills.iter().map(|f| f.fill.qty as u64).sum(),
+        slippage_bps: bps.map(|&(sum, weight)| (weight > 0).then(|| sum / weight as f64)),
+        cost_i64: cost,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn bar(minute: u64, close: i64, volume: u64) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: minute * NANOS_PER_MINUTE,
+            symbol: "ES.FUT".to_string(),
+            open_i64: close,
+            high_i64: close,
+            low_i64: close,
+            close_i64: close,
+            volume_u64: volume,
+        }
+    }
+
+    fn fill(minute_secs: i64, side: OrderSide, price: i64) -> TcaFill {
+        TcaFill {
+            ts_rfc3339: (DateTime::UNIX_EPOCH + Duration::seconds(minute_secs)).to_rfc3339(),
+            symbol: "ES.FUT".to_string(),
+            side,
+            price_i64: price,
+            qty: 2,
+        }
+    }
+
+    #[test]
+    fn test_benchmarks_and_slippage() {
+        let request = TcaRequest {
+            fills: vec![
+                // 00:02:30, within the third bar
+                fill(150, OrderSide::Buy, 10_100),
+                fill(150, OrderSide::Sell, 10_100),
+            ],
+            stype_in: SymbolType::Parent,
+            dataset: None,
+            vwap_window: "4m".to_string(),
+            timezone: None,
+        };
+        let plan = request.plan().unwrap();
+        // Half a window and the arrival bar before the fill, clamped to 0
+        assert_eq!(plan.range.start_rfc3339, "1970-01-01T00:00:00Z");
+        assert_eq!(plan.range.end_rfc3339, "1970-01-02T00:00:00Z");
+
+        let bars = [
+            bar(0, 9_000, 1),
+            bar(1, 10_000, 1),
+            bar(2, 10_200, 1),
+            bar(3, 10_300, 2),
+            bar(9, 11_000, 1),
+        ];
+        let report = plan.analyze(&bars);
+        let buy = &report.fills[0];
+        assert_eq!(
+            buy.benchmarks,
+            Benchmarks {
+                arrival: Some(10_000),
+                // Bars starting 00:00:30 to 00:04:30: minutes 1, 2 and 3
+                vwap: Some(10_200),
+                close: Some(11_000),
+            }
+        );
+        assert_eq!(buy.slippage_bps.arrival, Some(100.0));
+        assert_eq!(buy.cost_i64.close, Some(-1_800));
+        // The sell is the mirror image, so the two cancel out
+        assert_eq!(report.fills[1].cost_i64.arrival, Some(-200));
+        assert_eq!(report.summary.slippage_bps.arrival, Some(0.0));
+        assert_eq!(report.summary.qty, 4);
+    }
+}
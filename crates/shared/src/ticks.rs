@@ -0,0 +1,199 @@
+//! Minimum price increments per instrument.
+//!
+//! Prices are stored at 1e9 precision, but an instrument only trades at
+//! multiples of its tick: ES in quarters, ZN in 64ths. A [`TickTable`]
+//! maps symbol roots to ticks so prices can be rounded and displayed at the
+//! instrument's own precision (see [`crate::price::format_price_to_tick`]).
+
+use crate::price::{self, PRICE_SCALE};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Ticks for common CME futures, as (root, tick in points).
+const BUILTIN_TICKS: &[(&str, f64)] = &[
+    ("ES", 0.25),
+    ("MES", 0.25),
+    ("NQ", 0.25),
+    ("MNQ", 0.25),
+    ("YM", 1.0),
+    ("MYM", 1.0),
+    ("RTY", 0.1),
+    ("M2K", 0.1),
+    ("CL", 0.01),
+    ("MCL", 0.01),
+    ("NG", 0.001),
+    ("GC", 0.1),
+    ("MGC", 0.1),
+    ("SI", 0.005),
+    ("HG", 0.0005),
+    ("ZN", 0.015625),
+    ("ZF", 0.0078125),
+    ("ZT", 0.00390625),
+    ("ZB", 0.03125),
+    ("6E", 0.00005),
+    ("6J", 0.0000005),
+];
+
+/// A tick size as returned by `GET /api/ticks`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TickSize {
+    /// Symbol root, e.g. "ES"
+    pub root: String,
+    /// Tick in points, e.g. 0.25
+    pub tick: f64,
+    /// Tick as a fixed-point price (1e9 scale)
+    pub tick_i64: i64,
+    /// Decimal places prices are shown with
+    pub decimals: usize,
+}
+
+/// The root of a symbol: "ES" for `ES.FUT`, `ES.c.0` and `ESZ4`.
+/// Symbols that don't look like futures contracts are returned unchanged.
+pub fn symbol_root(symbol: &str) -> &str {
+    let symbol = symbol.trim();
+    // Parent (ES.FUT) and continuous (ES.c.0) symbology
+    if let Some((root, _)) = symbol.split_once('.') {
+        return root;
+    }
+    // Raw contract symbols: root + month code + 1-2 digit year
+    let bytes = symbol.as_bytes();
+    let digits = bytes
+        .iter()
+        .rev()
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    if (1..=2).contains(&digits) && bytes.len() > digits + 1 {
+        let month = bytes[bytes.len() - digits - 1];
+        if b"FGHJKMNQUVXZ".contains(&month) {
+            return &symbol[..bytes.len() - digits - 1];
+        }
+    }
+    symbol
+}
+
+/// Tick sizes keyed by symbol root, with an optional fallback.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TickTable {
+    ticks: BTreeMap<String, i64>,
+    fallback: Option<i64>,
+}
+
+impl TickTable {
+    /// A table preloaded with ticks for common CME futures.
+    pub fn with_defaults() -> Self {
+        let mut table = Self::default();
+        for (root, tick) in BUILTIN_TICKS {
+            table.ticks.insert(root.to_string(), price::from_f64(*tick));
+        }
+        table
+    }
+
+    /// Set the tick for `root` (e.g. from an instrument definition's
+    /// minimum price increment). Non-positive ticks are ignored.
+    pub fn insert(&mut self, root: &str, tick_i64: i64) {
+        if tick_i64 > 0 {
+            self.ticks.insert(root.to_ascii_uppercase(), tick_i64);
+        }
+    }
+
+    /// Apply a spec like `ES=0.25;ZN=0.015625;*=0.01`, where `*` sets the
+    /// tick for symbols not otherwise listed.
+    pub fn apply_spec(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec
+            .split([';', ','])
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let (root, tick) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected ROOT=TICK, got {:?}", entry))?;
+            let tick: f64 = tick
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid tick {:?} for {}", tick.trim(), root.trim()))?;
+            let tick_i64 = price::from_f64(tick);
+            if tick_i64 <= 0 {
+                return Err(format!("tick for {} must be positive", root.trim()));
+            }
+            match root.trim() {
+                "*" => self.fallback = Some(tick_i64),
+                "" => return Err(format!("missing symbol in {:?}", entry)),
+                root => self.insert(root, tick_i64),
+            }
+        }
+        Ok(())
+    }
+
+    /// Tick for `symbol` as a fixed-point price, if known.
+    pub fn tick_size(&self, symbol: &str) -> Option<i64> {
+        let root = symbol_root(symbol).to_ascii_uppercase();
+        self.ticks.get(&root).copied().or(self.fallback)
+    }
+
+    /// Format `price_i64` at `symbol`'s tick precision, or with
+    /// [`price::format_price`] if its tick is unknown.
+    pub fn format_price(&self, symbol: &str, price_i64: i64) -> String {
+        match self.tick_size(symbol) {
+            Some(tick) => price::format_price_to_tick(price_i64, tick),
+            None => price::format_price(price_i64),
+        }
+    }
+
+    /// Every configured tick, ordered by root.
+    pub fn entries(&self) -> Vec<TickSize> {
+        self.ticks
+            .iter()
+            .map(|(root, &tick_i64)| tick_entry(root, tick_i64))
+            .collect()
+    }
+
+    /// The tick entry that applies to `symbol`, if any.
+    pub fn entry(&self, symbol: &str) -> Option<TickSize> {
+        self.tick_size(symbol)
+            .map(|tick_i64| tick_entry(symbol_root(symbol), tick_i64))
+    }
+}
+
+fn tick_entry(root: &str, tick_i64: i64) -> TickSize {
+    TickSize {
+        root: root.to_ascii_uppercase(),
+        tick: tick_i64 as f64 / PRICE_SCALE as f64,
+        tick_i64,
+        decimals: price::tick_decimals(tick_i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_root() {
+        assert_eq!(symbol_root("ES.FUT"), "ES");
+        assert_eq!(symbol_root("ES.c.0"), "ES");
+        assert_eq!(symbol_root("ESZ4"), "ES");
+        assert_eq!(symbol_root("MNQH25"), "MNQ");
+        assert_eq!(symbol_root("6EM4"), "6E");
+        assert_eq!(symbol_root("AAPL"), "AAPL");
+        assert_eq!(symbol_root("F"), "F");
+    }
+
+    #[test]
+    fn test_table_lookup_and_spec() {
+        let mut table = TickTable::with_defaults();
+        assert_eq!(table.tick_size("ES.FUT"), Some(250_000_000));
+        assert_eq!(table.tick_size("ZNU4"), Some(15_625_000));
+        assert_eq!(table.tick_size("AAPL"), None);
+        assert_eq!(table.format_price("ES.FUT", 4_500_123_456_789), "4500.00");
+        assert_eq!(table.format_price("AAPL", 190_123_456_789), "190.123456789");
+
+        table.apply_spec("ES=0.5; *=0.01").unwrap();
+        assert_eq!(table.tick_size("ESZ4"), Some(500_000_000));
+        assert_eq!(table.format_price("AAPL", 190_123_456_789), "190.12");
+        assert_eq!(table.entry("ZN.FUT").unwrap().decimals, 6);
+
+        assert!(table.apply_spec("ES").is_err());
+        assert!(table.apply_spec("ES=abc").is_err());
+        assert!(table.apply_spec("ES=0").is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Relative time expressions for historical requests.
+//!
+//! Wherever a request takes an RFC3339 time it also accepts:
+//!
+//! - `now`, optionally with offsets: `now-6h`, `now-1d+30m`
+//! - `today` or `yesterday`, optionally with a wall-clock time and a time
+//!   zone (UTC by default): `today 09:30 America/New_York`,
+//!   `yesterday 17:00:00 America/Chicago`, and offsets as a last word:
+//!   `today 09:30 America/New_York -15m`
+//! - `session open` or `session close` of a market (see
+//!   [`SessionSpec`]), the one in progress or else the last; `session-N`
+//!   counts N sessions back, up to 366: `session open cme`, `session-1 close cme`,
+//!   `session open 09:30-16:00 America/New_York +5m`
+//!
+//! Expressions are resolved against the server's clock, so a saved query
+//! or a script can say "the last six hours" without doing the date math.
+
+use crate::sessions::{self, SessionSpec};
+use crate::HistoricalRequest;
+use chrono::{DateTime, Days, NaiveTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+
+/// Furthest back `session-N` may count.
+const MAX_SESSIONS_BACK: u32 = 366;
+
+/// Parse a lookback like `90s`, `30m`, `2h`, or `1d`.
+pub fn parse_lookback(s: &str) -> Result<chrono::Duration, String> {
+    let invalid = || format!("invalid duration '{}': expected e.g. 90s, 30m, 2h, 1d", s);
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = s.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+
+    let duration = match unit {
+        "s" => chrono::Duration::try_seconds(amount),
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        _ => return Err(invalid()),
+    };
+    duration.ok_or_else(|| format!("duration '{}' out of range", s))
+}
+
+/// Parse a wall-clock time like `09:30` or `17:00:00`.
+pub fn parse_time_of_day(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .map_err(|_| format!("bad time of day '{}'", s))
+}
+
+/// Resolve an RFC3339 time or a relative expression against `now`.
+pub fn resolve(expr: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let expr = expr.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(expr) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let invalid = |why: String| {
+        format!(
+            "invalid time '{}': {} (expected RFC3339, 'now-6h', 'today 09:30 America/New_York', or 'session open cme')",
+            expr, why
+        )
+    };
+
+    if let Some(offsets) = expr.strip_prefix("now") {
+        return apply_offsets(now, offsets).map_err(invalid);
+    }
+
+    if expr.starts_with("session") {
+        return resolve_session(expr, now).map_err(invalid);
+    }
+
+    let mut words = expr.split_whitespace().peekable();
+    let days_back = match words.next() {
+        Some("today") => 0,
+        Some("yesterday") => 1,
+        _ => return Err(invalid("unrecognized expression".to_string())),
+    };
+    let time = match words.next_if(|w| w.contains(':')) {
+        Some(word) => parse_time_of_day(word).map_err(invalid)?,
+        None => NaiveTime::MIN,
+    };
+    let tz: Tz = match words.next_if(|w| !w.starts_with(['+', '-'])) {
+        Some(word) => word
+            .parse()
+            .map_err(|_| invalid(format!("unknown time zone '{}'", word)))?,
+        None => Tz::UTC,
+    };
+    let offsets = words.next().unwrap_or("");
+    if let Some(extra) = words.next() {
+        return Err(invalid(format!("unexpected '{}'", extra)));
+    }
+
+    let date = now
+        .with_timezone(&tz)
+        .date_naive()
+        .checked_sub_days(Days::new(days_back))
+        .ok_or_else(|| invalid("date out of range".to_string()))?;
+    let time = sessions::to_utc(date.and_time(time), tz)
+        .ok_or_else(|| invalid("date out of range".to_string()))?;
+    apply_offsets(time, offsets).map_err(invalid)
+}
+
+/// Resolve `session[-N] open|close MARKET [offsets]`.
+fn resolve_session(expr: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let mut words = expr.split_whitespace().peekable();
+    let back: u32 = match words.next().and_then(|w| w.strip_prefix("session")) {
+        Some("") => 0,
+        Some(back) => back
+            .strip_prefix('-')
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| format!("expected 'session' or 'session-N', got 'session{}'", back))?,
+        None => unreachable!("checked by the caller"),
+    };
+    if back > MAX_SESSIONS_BACK {
+        return Err(format!(
+            "session-{} is too far back: at most session-{}",
+            back, MAX_SESSIONS_BACK
+        ));
+    }
+    let close = match words.next() {
+        Some("open") => false,
+        Some("close") => true,
+        _ => return Err("expected 'open' or 'close' after 'session'".to_string()),
+    };
+    let mut market = Vec::new();
+    while let Some(word) = words.next_if(|w| !w.starts_with(['+', '-'])) {
+        market.push(word);
+    }
+    if market.is_empty() {
+        return Err("missing market, e.g. 'cme'".to_string());
+    }
+    let spec = SessionSpec::parse(&market.join(" "))?;
+    let offsets = words.next().unwrap_or("");
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected '{}'", extra));
+    }
+
+    let mut session = spec
+        .latest(now)
+        .ok_or_else(|| "no session found".to_string())?;
+    for _ in 0..back {
+        session = spec
+            .previous(&session)
+            .ok_or_else(|| "no session found".to_string())?;
+    }
+    let time = if close { session.close } else { session.open };
+    apply_offsets(time, offsets)
+}
+
+/// Apply offsets such as `-6h+30m` to `time`.
+fn apply_offsets(mut time: DateTime<Utc>, mut offsets: &str) -> Result<DateTime<Utc>, String> {
+    while !offsets.is_empty() {
+        let sign = offsets.chars().next().unwrap_or_default();
+        if sign != '+' && sign != '-' {
+            return Err(format!("expected '+' or '-' before '{}'", offsets));
+        }
+        let rest = &offsets[1..];
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let duration = parse_lookback(&rest[..end])?;
+        time = if sign == '+' {
+            time.checked_add_signed(duration)
+        } else {
+            time.checked_sub_signed(duration)
+        }
+        .ok_or_else(|| format!("offset '{}' out of range", offsets))?;
+        offsets = &rest[end..];
+    }
+    Ok(time)
+}
+
+/// Replace relative expressions in `req`'s start and end with RFC3339
+/// times. Times that are already RFC3339 are left as written.
+pub fn resolve_request(req: &mut HistoricalRequest, now: DateTime<Utc>) -> Result<(), String> {
+    for field in [&mut req.start_rfc3339, &mut req.end_rfc3339] {
+        if DateTime::parse_from_rfc3339(field).is_err() {
+            *field = resolve(field, now)?.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_lookback_out_of_range() {
+        assert_eq!(parse_lookback("2h").unwrap(), chrono::Duration::hours(2));
+        assert!(parse_lookback("200000000000d").is_err());
+        assert!(parse_lookback("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn test_now_offsets() {
+        let now = at("2024-07-01T12:00:00Z");
+        assert_eq!(resolve("now", now).unwrap(), now);
+        assert_eq!(resolve("now-6h", now).unwrap(), at("2024-07-01T06:00:00Z"));
+        assert_eq!(
+            resolve("now-1d+30m", now).unwrap(),
+            at("2024-06-30T12:30:00Z")
+        );
+        assert!(resolve("now-6", now).is_err());
+        assert!(resolve("now 6h", now).is_err());
+        assert!(resolve("now-100000000d", now).is_err());
+        assert!(resolve("today -100000000d", now).is_err());
+    }
+
+    #[test]
+    fn test_today_in_zone() {
+        // 02:00 UTC on the 2nd is still the 1st in New York (EDT, UTC-4)
+        let now = at("2024-07-02T02:00:00Z");
+        assert_eq!(
+            resolve("today 09:30 America/New_York", now).unwrap(),
+            at("2024-07-01T13:30:00Z")
+        );
+        assert_eq!(resolve("today", now).unwrap(), at("2024-07-02T00:00:00Z"));
+        assert_eq!(
+            resolve("yesterday 17:00 America/Chicago -15m", now).unwrap(),
+            at("2024-06-30T21:45:00Z")
+        );
+        assert!(resolve("today 25:00", now).is_err());
+        assert!(resolve("today Mars/Olympus", now).is_err());
+    }
+
+    #[test]
+    fn test_session_expressions() {
+        // Saturday: the last CME session was Friday's
+        let now = at("2024-03-09T12:00:00Z");
+        assert_eq!(
+            resolve("session open cme", now).unwrap(),
+            at("2024-03-07T23:00:00Z")
+        );
+        assert_eq!(
+            resolve("session close cme -15m", now).unwrap(),
+            at("2024-03-08T21:45:00Z")
+        );
+        // Monday's session opens after the DST change, an hour earlier in
+        // UTC, and Friday's is one back from it
+        let now = at("2024-03-11T12:00:00Z");
+        assert_eq!(
+            resolve("session open cme", now).unwrap(),
+            at("2024-03-10T22:00:00Z")
+        );
+        assert_eq!(
+            resolve("session-1 close cme", now).unwrap(),
+            at("2024-03-08T22:00:00Z")
+        );
+        assert_eq!(
+            resolve("session open 09:30-16:00 America/New_York +5m", now).unwrap(),
+            at("2024-03-08T14:35:00Z")
+        );
+        assert!(resolve("session cme", now).is_err());
+        assert!(resolve("session open", now).is_err());
+        assert!(resolve("session-x open cme", now).is_err());
+        assert!(resolve("session-366 open cme", now).is_ok());
+        assert!(resolve("session-4000000000 open cme", now).is_err());
+    }
+
+    #[test]
+    fn test_resolve_request_keeps_rfc3339() {
+        let mut req = HistoricalRequest {
+            start_rfc3339: "now-1h".to_string(),
+            end_rfc3339: "2024-07-01T12:00:00.5Z".to_string(),
+            ..Default::default()
+        };
+        resolve_request(&mut req, at("2024-07-01T12:00:00Z")).unwrap();
+        assert_eq!(req.start_rfc3339, "2024-07-01T11:00:00Z");
+        assert_eq!(req.end_rfc3339, "2024-07-01T12:00:00.5Z");
+    }
+}
@@ -0,0 +1,218 @@
+//! Derived bar transforms applied to an OHLCV series before it is served.
+//!
+//! - `heikin-ashi`: Heikin-Ashi candles, one per source bar
+//! - `renko-N`: Renko bricks of `N` ticks, built from source bar closes
+//!
+//! Both return [`OhlcvRecord`]s, so a transformed series charts like any
+//! other bar response. Each symbol is transformed separately.
+
+use crate::ticks::TickTable;
+use crate::OhlcvRecord;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A transform requested with a historical request's `transform`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Heikin-Ashi candles
+    HeikinAshi,
+    /// Renko bricks, sized in ticks of each symbol
+    Renko { brick_ticks: u32 },
+}
+
+impl std::str::FromStr for Transform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            format!(
+                "Invalid transform: {}. Expected: heikin-ashi or renko-N (brick size in ticks)",
+                s
+            )
+        };
+        if s == "heikin-ashi" {
+            return Ok(Transform::HeikinAshi);
+        }
+        let brick_ticks: u32 = s
+            .strip_prefix("renko-")
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(invalid)?;
+        if brick_ticks == 0 {
+            return Err(invalid());
+        }
+        Ok(Transform::Renko { brick_ticks })
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::HeikinAshi => f.write_str("heikin-ashi"),
+            Transform::Renko { brick_ticks } => write!(f, "renko-{}", brick_ticks),
+        }
+    }
+}
+
+impl Transform {
+    /// Transform time-ordered `bars`. Renko bricks are sized with `ticks`;
+    /// a symbol without a known tick is an error.
+    pub fn apply(
+        &self,
+        bars: &[OhlcvRecord],
+        ticks: &TickTable,
+    ) -> Result<Vec<OhlcvRecord>, String> {
+        match *self {
+            Transform::HeikinAshi => Ok(heikin_ashi(bars)),
+            Transform::Renko { brick_ticks } => {
+                let mut bricks = HashMap::new();
+                for bar in bars {
+                    if !bricks.contains_key(&bar.symbol) {
+                        let tick = ticks.tick_size(&bar.symbol).ok_or_else(|| {
+                            format!(
+                                "No tick size known for {}; set one with TICK_SIZES",
+                                bar.symbol
+                            )
+                        })?;
+                        bricks.insert(bar.symbol.clone(), tick * brick_ticks as i64);
+                    }
+                }
+                Ok(renko(bars, |symbol| bricks[symbol]))
+            }
+        }
+    }
+}
+
+/// Heikin-Ashi candles for time-ordered `bars`, one per bar. The first
+/// candle of each symbol opens at the midpoint of its bar's open and close.
+pub fn heikin_ashi(bars: &[OhlcvRecord]) -> Vec<OhlcvRecord> {
+    // Previous candle's (open, close), per symbol
+    let mut previous: HashMap<&str, (i64, i64)> = HashMap::new();
+    bars.iter()
+        .map(|bar| {
+            let close = (bar.open_i64 + bar.high_i64 + bar.low_i64 + bar.close_i64) / 4;
+            let open = match previous.get(bar.symbol.as_str()) {
+                Some(&(prev_open, prev_close)) => (prev_open + prev_close) / 2,
+                None => (bar.open_i64 + bar.close_i64) / 2,
+            };
+            previous.insert(bar.symbol.as_str(), (open, close));
+            OhlcvRecord {
+                ts_event_unix_ns: bar.ts_event_unix_ns,
+                symbol: bar.symbol.clone(),
+                open_i64: open,
+                high_i64: bar.high_i64.max(open).max(close),
+                low_i64: bar.low_i64.min(open).min(close),
+                close_i64: close,
+                volume_u64: bar.volume_u64,
+            }
+        })
+        .collect()
+}
+
+/// Renko state for one symbol: the range of the last brick, and the volume
+/// traded since it closed.
+struct RenkoState {
+    low: i64,
+    high: i64,
+    volume: u64,
+}
+
+/// Renko bricks for time-ordered `bars`, with each symbol's brick size in
+/// fixed-point price from `brick_of`. The first close, rounded down to a
+/// brick, anchors the series; a new brick is laid whenever a close moves a
+/// whole brick beyond the last one's range, so a reversal takes two bricks.
+/// Bricks are stamped with the bar that completed them and carry the volume
+/// traded since the previous brick.
+pub fn renko(bars: &[OhlcvRecord], brick_of: impl Fn(&str) -> i64) -> Vec<OhlcvRecord> {
+    let mut states: HashMap<&str, RenkoState> = HashMap::new();
+    let mut bricks = Vec::new();
+    for bar in bars {
+        let brick = brick_of(&bar.symbol);
+        let state = states.entry(bar.symbol.as_str()).or_insert_with(|| {
+            let anchor = bar.close_i64.div_euclid(brick) * brick;
+            RenkoState {
+                low: anchor,
+                high: anchor,
+                volume: 0,
+            }
+        });
+        state.volume += bar.volume_u64;
+
+        let mut lay = |open: i64, close: i64, state: &mut RenkoState| {
+            bricks.push(OhlcvRecord {
+                ts_event_unix_ns: bar.ts_event_unix_ns,
+                symbol: bar.symbol.clone(),
+                open_i64: open,
+                high_i64: open.max(close),
+                low_i64: open.min(close),
+                close_i64: close,
+                volume_u64: std::mem::take(&mut state.volume),
+            });
+            state.low = open.min(close);
+            state.high = open.max(close);
+        };
+        while bar.close_i64 >= state.high + brick {
+            let open = state.high;
+            lay(open, open + brick, state);
+        }
+        while bar.close_i64 <= state.low - brick {
+            let open = state.low;
+            lay(open, open - brick, state);
+        }
+    }
+    bricks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(ts: u64, open: i64, high: i64, low: i64, close: i64) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: ts,
+            symbol: "ES.FUT".to_string(),
+            open_i64: open,
+            high_i64: high,
+            low_i64: low,
+            close_i64: close,
+            volume_u64: 10,
+        }
+    }
+
+    #[test]
+    fn test_heikin_ashi() {
+        let candles = heikin_ashi(&[bar(1, 10, 20, 8, 18), bar(2, 18, 22, 16, 20)]);
+        assert_eq!(
+            (candles[0].open_i64, candles[0].close_i64),
+            (14, 14) // (10 + 18) / 2, (10 + 20 + 8 + 18) / 4
+        );
+        assert_eq!(candles[1].open_i64, 14);
+        assert_eq!(candles[1].close_i64, 19);
+        assert_eq!((candles[1].high_i64, candles[1].low_i64), (22, 14));
+    }
+
+    #[test]
+    fn test_renko_bricks() {
+        let bars = [
+            bar(1, 0, 0, 0, 103), // anchors at 100
+            bar(2, 0, 0, 0, 125), // up to 110, 120
+            bar(3, 0, 0, 0, 112), // within a brick of the range
+            bar(4, 0, 0, 0, 99),  // reversal: down from 110 to 100
+        ];
+        let bricks = renko(&bars, |_| 10);
+        let spans: Vec<_> = bricks
+            .iter()
+            .map(|b| (b.ts_event_unix_ns, b.open_i64, b.close_i64))
+            .collect();
+        assert_eq!(spans, [(2, 100, 110), (2, 110, 120), (4, 110, 100)]);
+        // The first brick takes the volume of bars 1 and 2, the reversal
+        // that of bars 3 and 4
+        assert_eq!(bricks[0].volume_u64, 20);
+        assert_eq!(bricks[1].volume_u64, 0);
+        assert_eq!(bricks[2].volume_u64, 20);
+
+        assert_eq!("renko-4".parse(), Ok(Transform::Renko { brick_ticks: 4 }));
+        assert!("renko-0".parse::<Transform>().is_err());
+        assert_eq!(Transform::HeikinAshi.to_string(), "heikin-ashi");
+    }
+}
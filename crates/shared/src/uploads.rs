@@ -0,0 +1,26 @@
+//! Uploaded DBN files held for viewing.
+
+use serde::{Deserialize, Serialize};
+
+/// A decoded DBN file, served as its own provider to the API key that
+/// uploaded it until it expires.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UploadInfo {
+    /// Identifies the upload in `/api/uploads/{id}/...`
+    pub id: String,
+    pub file_name: String,
+    /// Dataset named in the file's metadata, e.g. "GLBX.MDP3"
+    pub dataset: String,
+    /// Schema of the decoded records: `trades`, `ohlcv-1s` or `ohlcv-1m`
+    pub schema: String,
+    pub symbols: Vec<String>,
+    pub records: u64,
+    /// Size of the uploaded file
+    pub bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_unix_ns: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_unix_ns: Option<u64>,
+    /// When the upload is dropped, e.g. "2024-01-02T15:30:00Z"
+    pub expires_rfc3339: String,
+}
@@ -0,0 +1,33 @@
+//! Per-key usage accounting types.
+
+use serde::{Deserialize, Serialize};
+
+/// Monthly limits applied to each API key. Unset limits are unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UsageQuotas {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records_served: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub live_messages: Option<u64>,
+    /// Estimated upstream cost in USD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+/// Usage by one API key in the current calendar month (UTC).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    /// Stable, non-secret identifier derived from the API key
+    pub key_id: String,
+    /// Month the counters cover, e.g. "2024-07"
+    pub period: String,
+    /// Historical requests made
+    pub requests: u64,
+    /// Historical records returned
+    pub records_served: u64,
+    /// Live data messages delivered
+    pub live_messages: u64,
+    /// Upstream cost estimated before each historical request
+    pub estimated_cost_usd: f64,
+    pub quotas: UsageQuotas,
+}
@@ -0,0 +1,33 @@
+//! User accounts for multi-user deployments.
+
+use serde::{Deserialize, Serialize};
+
+/// A user as shown to clients. Tokens and DataBento keys are never
+/// returned after they are set.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UserInfo {
+    pub name: String,
+    /// Whether the user has registered their own DataBento API key
+    pub has_databento_key: bool,
+    /// RFC3339 creation time
+    pub created_at: String,
+}
+
+/// Request body for `POST /api/users`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewUser {
+    pub name: String,
+}
+
+/// Response to `POST /api/users`: the user's API token, shown only once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserToken {
+    pub name: String,
+    pub token: String,
+}
+
+/// Request body for `PUT /api/me/databento-key`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabentoKey {
+    pub api_key: String,
+}
@@ -0,0 +1,243 @@
+//! Dry-run checks of historical requests.
+//!
+//! [`check`] runs every check a request would meet on its way to the
+//! provider, without fetching anything, and collects all the problems it
+//! finds instead of stopping at the first. Query forms use it to flag each
+//! bad field at once and to show the request as it would be served: symbols
+//! trimmed and deduplicated, relative times resolved.
+
+use crate::projection::Projection;
+use crate::{timeexpr, HistoricalRequest, Schema};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// One problem with a request.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// Request field at fault, e.g. `schema` or `end_rfc3339`
+    pub field: String,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Symbols a request fetches from one upstream dataset.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DatasetRoute {
+    pub dataset: String,
+    pub symbols: Vec<String>,
+}
+
+/// Result of validating a request without serving it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestValidation {
+    /// Whether the request would be accepted
+    pub valid: bool,
+    /// The request as it would be served; omitted when invalid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<HistoricalRequest>,
+    /// Upstream datasets the symbols are fetched from, when the provider
+    /// routes by dataset
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub datasets: Vec<DatasetRoute>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ValidationIssue>,
+}
+
+impl RequestValidation {
+    /// A validation of `request` reporting `errors`, keeping the request
+    /// only if there are none.
+    pub fn new(
+        request: HistoricalRequest,
+        datasets: Vec<DatasetRoute>,
+        errors: Vec<ValidationIssue>,
+    ) -> Self {
+        let valid = errors.is_empty();
+        Self {
+            valid,
+            request: valid.then_some(request),
+            datasets: if valid { datasets } else { Vec::new() },
+            errors,
+        }
+    }
+}
+
+/// Symbols trimmed of whitespace, without blanks or repeats, in their
+/// original order.
+pub fn normalize_symbols(symbols: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    symbols
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty() && seen.insert(*s))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Normalize `req` and check it as of `now`, returning the normalized
+/// request and every problem found. Server policy that depends on
+/// configuration (clock limits, dataset routing) is left to the caller.
+pub fn check(
+    req: &HistoricalRequest,
+    now: DateTime<Utc>,
+) -> (HistoricalRequest, Vec<ValidationIssue>) {
+    let mut req = HistoricalRequest {
+        symbols: normalize_symbols(&req.symbols),
+        ..req.clone()
+    };
+    let mut errors = Vec::new();
+
+    if req.symbols.is_empty() {
+        errors.push(ValidationIssue::new(
+            "symbols",
+            "at least one symbol is required",
+        ));
+    }
+    if let Err(e) = req.stype_in.validate(&req.symbols) {
+        errors.push(ValidationIssue::new("symbols", e));
+    }
+
+    for (field, value) in [
+        ("start_rfc3339", &mut req.start_rfc3339),
+        ("end_rfc3339", &mut req.end_rfc3339),
+    ] {
+        if DateTime::parse_from_rfc3339(value).is_ok() {
+            continue;
+        }
+        match timeexpr::resolve(value, now) {
+            Ok(time) => *value = time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            Err(e) => errors.push(ValidationIssue::new(field, e)),
+        }
+    }
+    let parse = |s: &str| DateTime::parse_from_rfc3339(s).ok();
+    if let (Some(start), Some(end)) = (parse(&req.start_rfc3339), parse(&req.end_rfc3339)) {
+        if start >= end {
+            errors.push(ValidationIssue::new(
+                "end_rfc3339",
+                format!(
+                    "end {} is not after start {}",
+                    req.end_rfc3339, req.start_rfc3339
+                ),
+            ));
+        }
+    }
+
+    if req.limit == 0 {
+        errors.push(ValidationIssue::new("limit", "limit must be at least 1"));
+    }
+    if req.limit_per_symbol == Some(0) {
+        errors.push(ValidationIssue::new(
+            "limit_per_symbol",
+            "limit_per_symbol must be at least 1",
+        ));
+    }
+
+    let schema = match req.schema.parse::<Schema>() {
+        Ok(schema) => Some(schema),
+        Err(e) => {
+            errors.push(ValidationIssue::new("schema", e));
+            None
+        }
+    };
+    if let Some(schema) = &schema {
+        match schema {
+            Schema::Mbp10 => errors.push(ValidationIssue::new(
+                "schema",
+                "mbp-10 is only streamed live; historical requests take trades or bars",
+            )),
+            Schema::Mbo => errors.push(ValidationIssue::new(
+                "schema",
+                "mbo is served as books by GET /api/book and live subscriptions",
+            )),
+            _ => {}
+        }
+        if let Err(e) = Projection::new(schema, &req.fields) {
+            errors.push(ValidationIssue::new("fields", e));
+        }
+        if req.auto_resolution
+            && ![Schema::Trades, Schema::Ohlcv1S, Schema::Ohlcv1M].contains(schema)
+        {
+            errors.push(ValidationIssue::new(
+                "auto_resolution",
+                "auto_resolution starts from trades, ohlcv-1s or ohlcv-1m",
+            ));
+        }
+        if req.quote_at_trade && *schema != Schema::Trades && schema.cvd_interval_ns().is_none() {
+            errors.push(ValidationIssue::new(
+                "quote_at_trade",
+                "quote_at_trade requires the trades schema",
+            ));
+        }
+    }
+    match req.bar_transform() {
+        Ok(Some(_)) if schema == Some(Schema::Trades) => errors.push(ValidationIssue::new(
+            "transform",
+            "transform applies to bar schemas, not trades",
+        )),
+        Ok(_) => {}
+        Err(e) => errors.push(ValidationIssue::new("transform", e)),
+    }
+    if let Err(e) = req.bar_align() {
+        errors.push(ValidationIssue::new("align", e));
+    }
+
+    (req, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SymbolType;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-07-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_normalizes_valid_request() {
+        let req = HistoricalRequest {
+            symbols: vec![" ES.FUT".to_string(), "ES.FUT".to_string(), "".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            start_rfc3339: "now-6h".to_string(),
+            end_rfc3339: "2024-07-01T11:00:00Z".to_string(),
+            fields: vec!["close".to_string()],
+            ..Default::default()
+        };
+        let (normalized, errors) = check(&req, now());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(normalized.symbols, ["ES.FUT"]);
+        assert_eq!(normalized.start_rfc3339, "2024-07-01T06:00:00Z");
+        assert_eq!(normalized.end_rfc3339, "2024-07-01T11:00:00Z");
+    }
+
+    #[test]
+    fn test_collects_every_error() {
+        let req = HistoricalRequest {
+            symbols: vec!["ESZ4".to_string()],
+            schema: "trades".to_string(),
+            stype_in: SymbolType::InstrumentId,
+            start_rfc3339: "now-1h".to_string(),
+            end_rfc3339: "yesterday-ish".to_string(),
+            limit: 0,
+            fields: vec!["close".to_string()],
+            transform: Some("heikin-ashi".to_string()),
+            ..Default::default()
+        };
+        let (_, errors) = check(&req, now());
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            ["symbols", "end_rfc3339", "limit", "fields", "transform"]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Types for outbound webhook notifications.
+
+use crate::jobs::{JobRun, JobStatus};
+use serde::{Deserialize, Serialize};
+
+/// Categories of events a webhook can subscribe to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    JobSucceeded,
+    JobFailed,
+    Alert,
+    ProviderOutage,
+    ProviderRecovered,
+}
+
+/// Body shape posted to a webhook URL.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// The [`WebhookEvent`] as JSON
+    #[default]
+    Json,
+    /// `{"text": summary}` for Slack incoming webhooks
+    Slack,
+    /// `{"content": summary}` for Discord webhooks
+    Discord,
+}
+
+/// A registered webhook endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    /// Letters, digits, '-' and '_' (max 64 chars)
+    pub name: String,
+    /// http(s) URL events are POSTed to
+    pub url: String,
+    /// HMAC-SHA256 key used to sign each delivery
+    pub secret: String,
+    /// Events to deliver; empty means all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventKind>,
+    #[serde(default)]
+    pub format: PayloadFormat,
+}
+
+impl Webhook {
+    /// Whether this webhook wants events of `kind`.
+    pub fn accepts(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// An event delivered to webhooks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A scheduled job finished, successfully or not
+    JobCompleted { run: JobRun },
+    /// An alert condition triggered
+    Alert { name: String, message: String },
+    /// Upstream requests to a provider keep failing
+    ProviderOutage {
+        provider: String,
+        consecutive_failures: u32,
+        error: String,
+    },
+    /// A provider that was in outage answered successfully again
+    ProviderRecovered { provider: String },
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            // A cancelled run didn't write its output either
+            WebhookEvent::JobCompleted { run } if run.status != JobStatus::Succeeded => {
+                EventKind::JobFailed
+            }
+            WebhookEvent::JobCompleted { .. } => EventKind::JobSucceeded,
+            WebhookEvent::Alert { .. } => EventKind::Alert,
+            WebhookEvent::ProviderOutage { .. } => EventKind::ProviderOutage,
+            WebhookEvent::ProviderRecovered { .. } => EventKind::ProviderRecovered,
+        }
+    }
+
+    /// One-line human-readable description, used for chat payloads.
+    pub fn summary(&self) -> String {
+        match self {
+            WebhookEvent::JobCompleted { run } => match (&run.status, &run.error) {
+                (JobStatus::Failed, Some(error)) => format!("Job {} failed: {}", run.job, error),
+                (JobStatus::Failed, None) => format!("Job {} failed", run.job),
+                (JobStatus::Cancelled, _) => format!("Job {} was cancelled", run.job),
+                (JobStatus::Succeeded, _) => format!(
+                    "Job {} succeeded: {} records written to {}",
+                    run.job,
+                    run.records,
+                    run.output.as_deref().unwrap_or("-")
+                ),
+            },
+            WebhookEvent::Alert { name, message } => format!("Alert {}: {}", name, message),
+            WebhookEvent::ProviderOutage {
+                provider,
+                consecutive_failures,
+                error,
+            } => format!(
+                "Provider {} is down ({} consecutive failures): {}",
+                provider, consecutive_failures, error
+            ),
+            WebhookEvent::ProviderRecovered { provider } => {
+                format!("Provider {} recovered", provider)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_kind_and_filter() {
+        let run = JobRun {
+            job: "es-close".to_string(),
+            started_at: "2024-07-01T22:00:00.000Z".to_string(),
+            finished_at: "2024-07-01T22:00:01.000Z".to_string(),
+            status: JobStatus::Failed,
+            records: 0,
+            output: None,
+            manifest: None,
+            error: Some("API error: timeout".to_string()),
+        };
+        let event = WebhookEvent::JobCompleted { run };
+        assert_eq!(event.kind(), EventKind::JobFailed);
+        assert_eq!(event.summary(), "Job es-close failed: API error: timeout");
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "job_completed");
+        assert_eq!(json["run"]["status"], "failed");
+
+        let hook: Webhook = serde_json::from_str(
+            r#"{"name":"ops","url":"https://example.com/hook","secret":"s","events":["job_failed"]}"#,
+        )
+        .unwrap();
+        assert_eq!(hook.format, PayloadFormat::Json);
+        assert!(hook.accepts(EventKind::JobFailed));
+        assert!(!hook.accepts(EventKind::JobSucceeded));
+    }
+}
@@ -0,0 +1,1235 @@
+//! Wire format versioning.
+//!
+//! [`WIRE_VERSION`] numbers the JSON the API speaks and [`CHANGELOG`] says
+//! what each version changed; both are served at `GET /api/version` so
+//! clients can tell what to expect. Golden fixtures in
+//! `fixtures/wire.json` pin the JSON of the historical and live API: every
+//! request, response, error and live message variant. The tests here fail
+//! when serialization drifts from them.
+//!
+//! For an intended change, bump [`WIRE_VERSION`], say what changed in
+//! [`CHANGELOG`], then regenerate the fixtures:
+//!
+//! ```text
+//! UPDATE_WIRE_FIXTURES=1 cargo test -p shared wire
+//! ```
+//!
+//! Regenerating refuses to overwrite changed fixtures without a new
+//! version, so a format change can't slip in with a fixture update alone.
+//!
+//! [`FEATURES`] names what each version made possible, so a client can
+//! check [`supports`] with the server's `wire_version` before relying on
+//! something, and [`LIVE_MESSAGES`] says when each live message type was
+//! introduced. Live subscriptions take the client's `wire_version`, and the
+//! server leaves out message types newer than it.
+
+use serde::{Deserialize, Serialize};
+
+/// Version of the API's JSON wire format.
+pub const WIRE_VERSION: u32 = 26;
+
+/// What each wire format version changed, oldest first.
+pub const CHANGELOG: &[(u32, &[&str])] = &[
+    (
+        1,
+        &[
+            "First versioned format: historical requests and responses, errors \
+             and live messages as pinned by the wire fixtures",
+        ],
+    ),
+    (
+        2,
+        &[
+            "API v2 at /api/v2: historical responses move duplicates_dropped, \
+             transform and resolution under meta, with a record count",
+            "GET /api/version lists the API versions in api_versions, with \
+             deprecation and sunset",
+        ],
+    ),
+    (
+        3,
+        &[
+            "v2 historical meta adds records_by_symbol, truncated, the served \
+             query, elapsed_ms and source (cache or provider)",
+        ],
+    ),
+    (
+        4,
+        &[
+            "v2 historical responses serve the symbols that work when others \
+             fail, listing the failures in meta.symbol_errors",
+        ],
+    ),
+    (
+        5,
+        &[
+            "Historical requests naming symbols that don't resolve are rejected \
+             up front with an unknown-symbols error listing suggestions",
+        ],
+    ),
+    (
+        6,
+        &[
+            "v2 historical meta adds data_versions (the provider's last \
+             modification per dataset) and the revision served; historical \
+             requests take a revision to pin",
+        ],
+    ),
+    (
+        7,
+        &[
+            "Historical requests and live subscriptions take compact to send \
+             records with short field names (t, s, p, q, o, h, l, c, v, n)",
+        ],
+    ),
+    (
+        8,
+        &[
+            "Historical requests and live subscriptions take prices_as to write \
+             prices and volumes as strings or decimals instead of numbers",
+        ],
+    ),
+    (
+        9,
+        &[
+            "Live message types a client doesn't know are read as unknown and \
+             skipped rather than failing; live subscriptions take wire_version \
+             to leave out types newer than the client",
+            "GET /api/version lists features with the wire version each \
+             arrived in",
+        ],
+    ),
+    (
+        10,
+        &[
+            "stype_in also takes the kebab-case raw-symbol and instrument-id; \
+             an unknown stype_in is rejected as the request body is read",
+        ],
+    ),
+    (
+        11,
+        &[
+            "Live subscriptions take profile to receive profile_delta messages \
+             with the levels of each symbol's developing volume profile that \
+             traded",
+        ],
+    ),
+    (
+        12,
+        &[
+            "Live subscriptions take day_summary to receive day_summary \
+             messages with each symbol's session open, high, low, volume and \
+             VWAP",
+        ],
+    ),
+    (
+        13,
+        &["Day summaries, rollup bars and leaderboard movers carry \
+             day_change: the net and percent change against the previous \
+             session's close"],
+    ),
+    (
+        14,
+        &[
+            "bar_feed takes several widths, and an ohlcv-1s or ohlcv-1m \
+             subscription takes coarser ones; their bars carry interval, the \
+             width each belongs to",
+        ],
+    ),
+    (
+        15,
+        &[
+            "Live subscriptions take schema mbp-10 for book depth, sent as \
+             depth_snapshot messages on subscribe and after gaps and \
+             depth_diff messages otherwise, numbered by sequence_u64",
+        ],
+    ),
+    (
+        16,
+        &[
+            "GET /api/book rebuilds a symbol's order books at a time from \
+             market-by-order events",
+            "Live subscriptions take schema mbo, whose books are sent as \
+             depth_snapshot and depth_diff messages book_depth levels deep",
+        ],
+    ),
+    (
+        17,
+        &[
+            "POST /api/book/sample streams a symbol's books at a fixed cadence \
+             over a range as NDJSON",
+        ],
+    ),
+    (
+        18,
+        &[
+            "POST /api/book/activity reports adds, cancels, trades, resting \
+             time and cancel-to-trade ratios per price level over a range",
+        ],
+    ),
+    (
+        19,
+        &[
+            "GET /api/metrics has pipeline, the live pipeline's latency by \
+             stage, and GET /api/debug/latency its histograms",
+        ],
+    ),
+    (
+        20,
+        &[
+            "Live subscriptions take adaptive=true to have trades, bars, day \
+             summaries and metrics conflated while the client falls behind, \
+             announced by rate_adjusted messages",
+        ],
+    ),
+    (
+        21,
+        &[
+            "Live subscriptions take trade_batch_ms to have trades arriving \
+             within that many milliseconds sent together as a trade_batch",
+        ],
+    ),
+    (
+        22,
+        &[
+            "Live subscriptions take fields to send only the named fields of \
+             each trade and bar",
+        ],
+    ),
+    (
+        23,
+        &["POST /api/diff compares a range as served by two sources, \
+             listing missing records and price and size mismatches"],
+    ),
+    (
+        24,
+        &[
+            "/api/mock/scenarios schedules volatility spikes, flash crashes \
+             and trend days in mock data",
+        ],
+    ),
+    (
+        25,
+        &[
+            "Historical responses set limited when auto_resolution's hourly \
+             bars cover only the start of the range",
+        ],
+    ),
+    (
+        26,
+        &[
+            "Live metric messages name their symbol, and order-flow imbalance \
+             is tracked per symbol",
+        ],
+    ),
+];
+
+/// Named capabilities and the wire version that introduced each.
+pub const FEATURES: &[(&str, u32)] = &[
+    ("api_v2", 2),
+    ("historical_meta", 3),
+    ("partial_symbols", 4),
+    ("unknown_symbols", 5),
+    ("revisions", 6),
+    ("compact", 7),
+    ("prices_as", 8),
+    ("unknown_live_messages", 9),
+    ("live_wire_version", 9),
+    ("volume_profile", 11),
+    ("day_summary", 12),
+    ("day_change", 13),
+    ("bar_channels", 14),
+    ("depth", 15),
+    ("order_book", 16),
+    ("book_sample", 17),
+    ("order_activity", 18),
+    ("pipeline_latency", 19),
+    ("adaptive_rate", 20),
+    ("trade_batch", 21),
+    ("live_fields", 22),
+    ("historical_diff", 23),
+    ("mock_scenarios", 24),
+    ("resolution_limited", 25),
+    ("metric_symbol", 26),
+];
+
+/// Live message types and the wire version that introduced each; types
+/// added later are listed with their version.
+pub const LIVE_MESSAGES: &[(&str, u32)] = &[
+    ("trade", 1),
+    ("ohlcv", 1),
+    ("error", 1),
+    ("metric", 1),
+    ("roll_alert", 1),
+    ("connected", 1),
+    ("symbol_mapping", 1),
+    ("paper_order", 1),
+    ("paper_fill", 1),
+    ("paper_position", 1),
+    ("position_pnl", 1),
+    ("calendar_event", 1),
+    ("provider_status", 1),
+    ("fragment", 1),
+    ("profile_delta", 11),
+    ("day_summary", 12),
+    ("depth", 15),
+    ("depth_snapshot", 15),
+    ("depth_diff", 15),
+    ("mbo", 16),
+    ("rate_adjusted", 20),
+    ("trade_batch", 21),
+];
+
+/// Whether a server speaking `wire_version` has `feature`. Unknown
+/// features are unsupported.
+pub fn supports(wire_version: u32, feature: &str) -> bool {
+    FEATURES
+        .iter()
+        .any(|(name, since)| *name == feature && *since <= wire_version)
+}
+
+/// Whether a client speaking `wire_version` knows live messages of
+/// `type_name`. Errors are always sent, whatever the version.
+pub fn knows_live_message(wire_version: u32, type_name: &str) -> bool {
+    type_name == "error"
+        || LIVE_MESSAGES
+            .iter()
+            .any(|(name, since)| *name == type_name && *since <= wire_version)
+}
+
+/// A named capability and the wire version it arrived in.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WireFeature {
+    pub name: String,
+    pub since: u32,
+}
+
+/// Changes made in one wire format version.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WireChange {
+    pub version: u32,
+    pub changes: Vec<String>,
+}
+
+/// One API version and its deprecation status.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ApiVersionInfo {
+    pub version: u32,
+    /// Route prefix, e.g. "/api/v2"
+    pub prefix: String,
+    pub deprecated: bool,
+    /// When the version stops being served (RFC 3339), if scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sunset: Option<String>,
+}
+
+/// Response of `GET /api/version`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// Server build version, e.g. "0.1.0"
+    pub server_version: String,
+    pub wire_version: u32,
+    /// Every wire format version, oldest first
+    pub changelog: Vec<WireChange>,
+    /// API versions served, oldest first
+    pub api_versions: Vec<ApiVersionInfo>,
+    /// Features and the wire version each arrived in; missing from servers
+    /// before wire version 9, for which [`supports`] works it out
+    #[serde(default)]
+    pub features: Vec<WireFeature>,
+}
+
+impl VersionInfo {
+    /// Version info for a server built as `server_version`, serving
+    /// `api_versions`.
+    pub fn new(server_version: &str, api_versions: Vec<ApiVersionInfo>) -> Self {
+        Self {
+            server_version: server_version.to_string(),
+            api_versions,
+            wire_version: WIRE_VERSION,
+            changelog: CHANGELOG
+                .iter()
+                .map(|(version, changes)| WireChange {
+                    version: *version,
+                    changes: changes.iter().map(|c| c.to_string()).collect(),
+                })
+                .collect(),
+            features: FEATURES
+                .iter()
+                .map(|(name, since)| WireFeature {
+                    name: name.to_string(),
+                    since: *since,
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether this server has `feature`.
+    pub fn supports(&self, feature: &str) -> bool {
+        supports(self.wire_version, feature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::{
+        ActivityCounts, InstrumentActivity, LevelActivity, OrderActivityReport,
+        OrderActivityRequest,
+    };
+    use crate::book::{
+        BookLevel, BookResponse, BookSampleRequest, BookSide, BookView, MboAction, MboRecord,
+    };
+    use crate::calendar::{CalendarAlert, CalendarEvent, CalendarStage};
+    use crate::change::DayChange;
+    use crate::depth::{
+        DepthDiff, DepthLevel, DepthSnapshot, DepthUpdate, LevelChange, SnapshotReason,
+    };
+    use crate::diff::{
+        DiffCounts, DiffRecord, DiffReport, DiffRequest, DiffSource, DifferenceKind,
+        RecordDifference, SymbolDiff,
+    };
+    use crate::metrics::{LatencyBucket, StageLatency};
+    use crate::paper::{
+        NewPaperOrder, OrderSide, PaperCommand, PaperFill, PaperOrder, PaperOrderStatus,
+        PaperPosition,
+    };
+    use crate::positions::PositionPnl;
+    use crate::price::PricesAs;
+    use crate::profile::{ProfileDelta, ProfileLevel};
+    use crate::scenarios::{Scenario, ScenarioKind, ScenarioRequest};
+    use crate::status::{ProviderHealth, StatusChange};
+    use crate::summary::DaySummary;
+    use crate::validation::{DatasetRoute, RequestValidation, ValidationIssue};
+    use crate::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wire.json");
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Fixtures {
+        wire_version: u32,
+        cases: BTreeMap<String, Value>,
+    }
+
+    fn depth_level() -> DepthLevel {
+        DepthLevel {
+            bid_px_i64: 5_300_000_000_000,
+            ask_px_i64: 5_300_250_000_000,
+            bid_sz_u32: 42,
+            ask_sz_u32: 17,
+            bid_ct_u32: 9,
+            ask_ct_u32: 4,
+        }
+    }
+
+    /// `value`'s JSON, checked to read back into the same JSON.
+    fn case<T: Serialize + DeserializeOwned>(name: &str, value: T) -> (String, Value) {
+        let json = serde_json::to_value(&value).unwrap();
+        let back: T = serde_json::from_value(json.clone())
+            .unwrap_or_else(|e| panic!("{} doesn't deserialize: {}", name, e));
+        assert_eq!(serde_json::to_value(&back).unwrap(), json, "{}", name);
+        (name.to_string(), json)
+    }
+
+    fn trade() -> TradeRecord {
+        TradeRecord {
+            ts_event_unix_ns: 1_717_248_600_000_000_000,
+            symbol: "ES.FUT".to_string(),
+            price_i64: 5_300_250_000_000,
+            size_u32: 3,
+            quote: None,
+            sequence_u32: None,
+        }
+    }
+
+    fn bar() -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: 1_717_248_600_000_000_000,
+            symbol: "ES.FUT".to_string(),
+            open_i64: 5_300_000_000_000,
+            high_i64: 5_302_500_000_000,
+            low_i64: 5_299_750_000_000,
+            close_i64: 5_301_250_000_000,
+            volume_u64: 1_250,
+        }
+    }
+
+    fn request() -> HistoricalRequest {
+        HistoricalRequest {
+            symbols: vec!["ES.FUT".to_string()],
+            schema: "ohlcv-1m".to_string(),
+            start_rfc3339: "2024-06-01T13:30:00Z".to_string(),
+            end_rfc3339: "2024-06-01T20:00:00Z".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn error() -> ErrorResponse {
+        ErrorResponse {
+            error: "Invalid schema: ticks".to_string(),
+            code: 400,
+        }
+    }
+
+    fn cases() -> BTreeMap<String, Value> {
+        let quoted = TradeRecord {
+            quote: Some(QuoteAtTrade::classify(
+                5_300_250_000_000,
+                5_300_000_000_000,
+                5_300_250_000_000,
+            )),
+            sequence_u32: Some(42),
+            ..trade()
+        };
+        let tick_bars = "tickbar-500".parse::<Schema>().unwrap();
+        let live_bar = |bar_closed, interval: Option<&str>| LiveMessage::Ohlcv {
+            ts_event_unix_ns: 1_717_248_600_000_000_000,
+            symbol: "ES.FUT".to_string(),
+            open_i64: 5_300_000_000_000,
+            high_i64: 5_302_500_000_000,
+            low_i64: 5_299_750_000_000,
+            close_i64: 5_301_250_000_000,
+            volume_u64: 1_250,
+            bar_closed,
+            interval: interval.map(str::to_string),
+        };
+        let new_order = NewPaperOrder {
+            symbol: "ES.FUT".to_string(),
+            side: OrderSide::Buy,
+            qty: 2,
+            limit_price_i64: Some(5_300_000_000_000),
+        };
+
+        [
+            // Requests
+            case("request/historical_minimal", request()),
+            case(
+                "request/historical_full",
+                HistoricalRequest {
+                    symbols: vec!["ES.FUT".to_string(), "NQ.FUT".to_string()],
+                    schema: "trades".to_string(),
+                    stype_in: SymbolType::Parent,
+                    start_rfc3339: "now-6h".to_string(),
+                    end_rfc3339: "now".to_string(),
+                    limit: 500,
+                    limit_per_symbol: Some(250),
+                    dataset: Some("GLBX.MDP3".to_string()),
+                    quote_at_trade: true,
+                    count_only: false,
+                    fields: vec!["price".to_string(), "size".to_string()],
+                    align: Some("session".to_string()),
+                    transform: None,
+                    order: RecordOrder::Symbol,
+                    compact: true,
+                    prices_as: PricesAs::String,
+                    auto_resolution: true,
+                    revision: Some("GLBX.MDP3@2024-05-31".to_string()),
+                },
+            ),
+            case(
+                "request/combined",
+                CombinedRequest {
+                    schemas: vec!["ohlcv-1m".to_string(), "trades".to_string()],
+                    request: HistoricalRequest {
+                        schema: String::new(),
+                        ..request()
+                    },
+                },
+            ),
+            case(
+                "request/saved_query",
+                SavedQuery {
+                    name: "es-session".to_string(),
+                    request: request(),
+                },
+            ),
+            case(
+                "request/book_sample",
+                BookSampleRequest {
+                    symbol: "ESZ4".to_string(),
+                    stype_in: SymbolType::RawSymbol,
+                    start_rfc3339: "2024-06-04T13:30:00Z".to_string(),
+                    end_rfc3339: "2024-06-04T14:30:00Z".to_string(),
+                    interval: "1s".to_string(),
+                    depth: Some(5),
+                },
+            ),
+            case(
+                "request/order_activity",
+                OrderActivityRequest {
+                    symbol: "ES.FUT".to_string(),
+                    stype_in: SymbolType::Parent,
+                    start_rfc3339: "2024-06-04T13:30:00Z".to_string(),
+                    end_rfc3339: "2024-06-04T14:30:00Z".to_string(),
+                },
+            ),
+            case(
+                "request/diff",
+                DiffRequest {
+                    request: request(),
+                    left: DiffSource::Provider { dataset: None },
+                    right: DiffSource::Recording,
+                },
+            ),
+            case(
+                "request/mock_scenario",
+                ScenarioRequest {
+                    kind: ScenarioKind::FlashCrash,
+                    at: "today 13:30 America/New_York".to_string(),
+                    duration_secs: Some(900),
+                    magnitude: Some(0.04),
+                    symbols: vec!["ES.FUT".to_string()],
+                },
+            ),
+            case(
+                "request/paper_submit_order",
+                PaperCommand::SubmitOrder(new_order),
+            ),
+            case(
+                "request/paper_cancel_order",
+                PaperCommand::CancelOrder { order_id: 7 },
+            ),
+            // Historical responses
+            case(
+                "response/trades",
+                HistoricalResponse::Trades {
+                    data: vec![trade(), quoted],
+                },
+            ),
+            case(
+                "response/ohlcv_1m_compact",
+                serde_json::from_str::<Value>(
+                    &crate::compact::to_string(&HistoricalResponse::Ohlcv1M { data: vec![bar()] })
+                        .unwrap(),
+                )
+                .unwrap(),
+            ),
+            case("response/ohlcv_1m_decimal_prices", {
+                let mut body =
+                    serde_json::to_value(HistoricalResponse::Ohlcv1M { data: vec![bar()] })
+                        .unwrap();
+                PricesAs::Decimal.apply_response(&mut body);
+                body
+            }),
+            case(
+                "response/ohlcv_1s",
+                HistoricalResponse::Ohlcv1S { data: vec![bar()] },
+            ),
+            case(
+                "response/ohlcv_1m",
+                HistoricalResponse::Ohlcv1M { data: vec![bar()] },
+            ),
+            case(
+                "response/cvd_1s",
+                HistoricalResponse::Cvd1S { data: vec![bar()] },
+            ),
+            case(
+                "response/cvd_1m",
+                HistoricalResponse::Cvd1M { data: vec![bar()] },
+            ),
+            case(
+                "response/tick_bars",
+                HistoricalResponse::from_bars(&tick_bars, vec![bar()]).unwrap(),
+            ),
+            case(
+                "response/normalized",
+                NormalizedResponse {
+                    response: HistoricalResponse::Ohlcv1M { data: vec![bar()] },
+                    duplicates_dropped: 1,
+                    transform: Some("heikin-ashi".to_string()),
+                    resolution: Some("ohlcv-1h".to_string()),
+                    limited: true,
+                },
+            ),
+            case(
+                "response/count",
+                RecordCount {
+                    schema: "trades".to_string(),
+                    count: 125_000,
+                },
+            ),
+            case(
+                "response/batch",
+                vec![
+                    BatchResult::Ok(serde_json::json!({ "schema": "trades", "data": [] })),
+                    BatchResult::Error(error()),
+                ],
+            ),
+            case(
+                "response/validation_valid",
+                RequestValidation::new(
+                    request(),
+                    vec![DatasetRoute {
+                        dataset: "GLBX.MDP3".to_string(),
+                        symbols: vec!["ES.FUT".to_string()],
+                    }],
+                    Vec::new(),
+                ),
+            ),
+            case(
+                "response/validation_invalid",
+                RequestValidation::new(
+                    request(),
+                    Vec::new(),
+                    vec![ValidationIssue::new("limit", "limit must be at least 1")],
+                ),
+            ),
+            case(
+                "response/historical_v2",
+                HistoricalEnvelope {
+                    response: HistoricalResponse::Ohlcv1M { data: vec![bar()] },
+                    meta: ResponseMeta {
+                        records: 1,
+                        records_by_symbol: [("ES.FUT".to_string(), 1)].into(),
+                        truncated: true,
+                        symbol_errors: vec![SymbolError {
+                            symbol: "ZZ.FUT".to_string(),
+                            error: "Invalid symbol: ZZ.FUT doesn't resolve".to_string(),
+                            code: 400,
+                        }],
+                        duplicates_dropped: 1,
+                        transform: None,
+                        resolution: Some("ohlcv-1m".to_string()),
+                        limited: false,
+                        query: Some(HistoricalRequest {
+                            limit: 1,
+                            auto_resolution: true,
+                            ..request()
+                        }),
+                        elapsed_ms: 42,
+                        source: Some(DataSource::Cache),
+                        data_versions: vec![DataVersion {
+                            dataset: "GLBX.MDP3".to_string(),
+                            last_modified: "2024-05-31".to_string(),
+                        }],
+                        revision: Some("GLBX.MDP3@2024-05-31".to_string()),
+                    },
+                },
+            ),
+            case(
+                "response/version",
+                VersionInfo::new(
+                    "0.1.0",
+                    vec![
+                        ApiVersionInfo {
+                            version: 1,
+                            prefix: "/api/v1".to_string(),
+                            deprecated: true,
+                            sunset: Some("2025-06-30T00:00:00Z".to_string()),
+                        },
+                        ApiVersionInfo {
+                            version: 2,
+                            prefix: "/api/v2".to_string(),
+                            deprecated: false,
+                            sunset: None,
+                        },
+                    ],
+                ),
+            ),
+            case(
+                "response/book",
+                BookResponse {
+                    symbol: "ESZ4".to_string(),
+                    at_unix_ns: 1_717_511_400_000_000_000,
+                    events_u64: 2_418_007,
+                    books: vec![BookView {
+                        symbol: "ESZ4".to_string(),
+                        ts_event_unix_ns: 1_717_511_399_998_112_000,
+                        bids: vec![BookLevel {
+                            price_i64: 5_300_000_000_000,
+                            size_u64: 42,
+                            orders_u32: 9,
+                        }],
+                        asks: vec![BookLevel {
+                            price_i64: 5_300_250_000_000,
+                            size_u64: 17,
+                            orders_u32: 4,
+                        }],
+                        orders_u64: 3_112,
+                    }],
+                },
+            ),
+            case("response/order_activity", {
+                let counts = ActivityCounts {
+                    adds_u64: 1_204,
+                    cancels_u64: 1_013,
+                    trades_u64: 88,
+                    traded_volume_u64: 412,
+                    departures_u64: 1_150,
+                    avg_resting_ns_u64: Some(2_310_000_000),
+                    cancel_to_trade: Some(11.5),
+                };
+                OrderActivityReport {
+                    symbol: "ES.FUT".to_string(),
+                    start_unix_ns: 1_717_507_800_000_000_000,
+                    end_unix_ns: 1_717_511_400_000_000_000,
+                    events_u64: 2_418_007,
+                    instruments: vec![InstrumentActivity {
+                        symbol: "ESZ4".to_string(),
+                        totals: counts,
+                        levels: vec![LevelActivity {
+                            price_i64: 5_300_000_000_000,
+                            counts,
+                        }],
+                    }],
+                }
+            }),
+            case("response/diff", {
+                let counts = DiffCounts {
+                    left_records_u64: 390,
+                    right_records_u64: 389,
+                    count_delta_i64: -1,
+                    matched_u64: 388,
+                    missing_left_u64: 0,
+                    missing_right_u64: 1,
+                    price_mismatches_u64: 0,
+                    size_mismatches_u64: 1,
+                };
+                DiffReport {
+                    schema: "ohlcv-1m".to_string(),
+                    left: DiffSource::Provider { dataset: None },
+                    right: DiffSource::Recording,
+                    identical: false,
+                    totals: counts,
+                    symbols: vec![SymbolDiff {
+                        symbol: "ES.FUT".to_string(),
+                        counts,
+                    }],
+                    differences: vec![
+                        RecordDifference {
+                            kind: DifferenceKind::Mismatch,
+                            symbol: "ES.FUT".to_string(),
+                            ts_event_unix_ns: bar().ts_event_unix_ns,
+                            fields: vec!["volume_u64".to_string()],
+                            left: Some(DiffRecord::Bar(bar())),
+                            right: Some(DiffRecord::Bar(OhlcvRecord {
+                                volume_u64: 1_248,
+                                ..bar()
+                            })),
+                        },
+                        RecordDifference {
+                            kind: DifferenceKind::MissingRight,
+                            symbol: "ES.FUT".to_string(),
+                            ts_event_unix_ns: 1_717_272_000_000_000_000,
+                            fields: Vec::new(),
+                            left: Some(DiffRecord::Bar(OhlcvRecord {
+                                ts_event_unix_ns: 1_717_272_000_000_000_000,
+                                ..bar()
+                            })),
+                            right: None,
+                        },
+                    ],
+                    truncated: false,
+                    limited: false,
+                }
+            }),
+            case(
+                "response/mock_scenario",
+                Scenario {
+                    id: 1,
+                    kind: ScenarioKind::FlashCrash,
+                    start_unix_ns: 1_717_522_200_000_000_000,
+                    end_unix_ns: 1_717_523_100_000_000_000,
+                    magnitude: 0.04,
+                    symbols: vec!["ES.FUT".to_string()],
+                },
+            ),
+            case(
+                "response/debug_latency",
+                vec![StageLatency {
+                    stage: "send".to_string(),
+                    samples: 48_210,
+                    mean_us: 61.5,
+                    p50_us: 50,
+                    p90_us: 100,
+                    p99_us: 1_000,
+                    max_us: 18_442,
+                    buckets: vec![
+                        LatencyBucket {
+                            le_us: 50,
+                            count: 30_118,
+                        },
+                        LatencyBucket {
+                            le_us: 100,
+                            count: 47_902,
+                        },
+                    ],
+                }],
+            ),
+            // Errors
+            case("error/basic", error()),
+            case(
+                "error/range",
+                RangeErrorResponse {
+                    error: "Range of 10d exceeds the 3d allowed for trades; \
+                            request ohlcv-1s instead or split the range"
+                        .to_string(),
+                    code: 400,
+                    schema: "trades".to_string(),
+                    requested_secs: 864_000,
+                    max_secs: 259_200,
+                    suggested_schema: Some("ohlcv-1s".to_string()),
+                    suggest_job: false,
+                },
+            ),
+            case(
+                "error/unknown_symbols",
+                UnknownSymbolsResponse {
+                    error: "Unknown symbol ESS.FUT (did you mean ES.FUT?)".to_string(),
+                    code: 400,
+                    symbols: vec![UnknownSymbol {
+                        symbol: "ESS.FUT".to_string(),
+                        suggestions: vec!["ES.FUT".to_string()],
+                    }],
+                },
+            ),
+            // Live messages
+            case(
+                "live/connected",
+                LiveMessage::Connected {
+                    symbols: vec!["ES.FUT".to_string()],
+                    schema: "trades".to_string(),
+                    connection_id: Some(3),
+                },
+            ),
+            case(
+                "live/trade",
+                LiveMessage::Trade {
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    symbol: "ES.FUT".to_string(),
+                    price_i64: 5_300_250_000_000,
+                    size_u32: 3,
+                },
+            ),
+            case(
+                "live/trade_compact",
+                serde_json::from_str::<Value>(
+                    &crate::compact::live_to_string(&LiveMessage::Trade {
+                        ts_event_unix_ns: 1_717_248_600_000_000_000,
+                        symbol: "ES.FUT".to_string(),
+                        price_i64: 5_300_250_000_000,
+                        size_u32: 3,
+                    })
+                    .unwrap(),
+                )
+                .unwrap(),
+            ),
+            case(
+                "live/trade_batch",
+                LiveMessage::TradeBatch {
+                    trades: vec![
+                        trade(),
+                        TradeRecord {
+                            ts_event_unix_ns: 1_717_248_600_000_400_000,
+                            size_u32: 1,
+                            ..trade()
+                        },
+                    ],
+                },
+            ),
+            case("live/ohlcv", live_bar(false, None)),
+            case("live/ohlcv_closed", live_bar(true, None)),
+            case("live/ohlcv_channel", live_bar(true, Some("5m"))),
+            case(
+                "live/error",
+                LiveMessage::Error {
+                    message: "Unknown schema: ticks".to_string(),
+                },
+            ),
+            case(
+                "live/metric",
+                LiveMessage::Metric {
+                    symbol: "ES.FUT".to_string(),
+                    name: "order_flow_imbalance".to_string(),
+                    value: 0.25,
+                    window: "5s".to_string(),
+                },
+            ),
+            case(
+                "live/roll_alert",
+                LiveMessage::RollAlert {
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    root: "ES".to_string(),
+                    from_symbol: "ESM4".to_string(),
+                    to_symbol: "ESU4".to_string(),
+                    from_volume: 5_120,
+                    to_volume: 6_433,
+                    window: "15m".to_string(),
+                },
+            ),
+            case(
+                "live/symbol_mapping",
+                LiveMessage::SymbolMapping {
+                    instrument_id: 4_916,
+                    stype_in_symbol: "ES.FUT".to_string(),
+                    stype_out_symbol: "ESM4".to_string(),
+                },
+            ),
+            case(
+                "live/paper_order",
+                LiveMessage::PaperOrder(PaperOrder {
+                    order_id: 7,
+                    symbol: "ES.FUT".to_string(),
+                    side: OrderSide::Buy,
+                    qty: 2,
+                    limit_price_i64: Some(5_300_000_000_000),
+                    status: PaperOrderStatus::Open,
+                    submitted_at: "2024-06-01T13:30:00Z".to_string(),
+                }),
+            ),
+            case(
+                "live/paper_fill",
+                LiveMessage::PaperFill(PaperFill {
+                    order_id: 7,
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    symbol: "ES.FUT".to_string(),
+                    side: OrderSide::Buy,
+                    qty: 2,
+                    price_i64: 5_300_000_000_000,
+                }),
+            ),
+            case(
+                "live/paper_position",
+                LiveMessage::PaperPosition(PaperPosition {
+                    symbol: "ES.FUT".to_string(),
+                    qty: 2,
+                    avg_price_i64: 5_300_000_000_000,
+                    last_price_i64: 5_301_000_000_000,
+                    realized_pnl_i64: 0,
+                    unrealized_pnl_i64: 2_000_000_000,
+                }),
+            ),
+            case(
+                "live/position_pnl",
+                LiveMessage::PositionPnl(PositionPnl {
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    symbol: "ES.FUT".to_string(),
+                    qty: -1,
+                    entry_price_i64: 5_302_000_000_000,
+                    last_price_i64: 5_301_000_000_000,
+                    pnl_i64: 1_000_000_000,
+                }),
+            ),
+            case(
+                "live/calendar_event",
+                LiveMessage::CalendarEvent(CalendarAlert {
+                    stage: CalendarStage::Upcoming,
+                    event: CalendarEvent {
+                        ts_event_unix_ns: 1_717_248_600_000_000_000,
+                        title: "Nonfarm Payrolls".to_string(),
+                        country: Some("US".to_string()),
+                        impact: Some("high".to_string()),
+                    },
+                }),
+            ),
+            case(
+                "live/provider_status",
+                LiveMessage::ProviderStatus(StatusChange {
+                    provider: "DataBento".to_string(),
+                    health: ProviderHealth::Degraded,
+                    reason: "No live data for 60s".to_string(),
+                    at: "2024-06-01T13:30:00Z".to_string(),
+                }),
+            ),
+            case(
+                "live/profile_delta",
+                LiveMessage::ProfileDelta(ProfileDelta {
+                    symbol: "ES.FUT".to_string(),
+                    trading_day: "2024-06-03".to_string(),
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    reset: false,
+                    levels: vec![ProfileLevel {
+                        price_i64: 5_300_250_000_000,
+                        volume_u64: 1_250,
+                    }],
+                }),
+            ),
+            case(
+                "live/day_summary",
+                LiveMessage::DaySummary(DaySummary {
+                    symbol: "ES.FUT".to_string(),
+                    trading_day: "2024-06-03".to_string(),
+                    from_unix_ns: 1_717_365_600_000_000_000,
+                    last_unix_ns: 1_717_423_200_000_000_000,
+                    open_i64: 5_290_000_000_000,
+                    high_i64: 5_312_500_000_000,
+                    low_i64: 5_281_250_000_000,
+                    last_i64: 5_300_250_000_000,
+                    volume_u64: 1_250_000,
+                    vwap_i64: 5_297_125_000_000,
+                    trades_u64: 412_000,
+                    day_change: Some(DayChange::new(5_280_000_000_000, 5_300_250_000_000)),
+                }),
+            ),
+            case(
+                "live/depth",
+                LiveMessage::Depth(DepthUpdate {
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    symbol: "ESZ4".to_string(),
+                    sequence_u32: 81_024,
+                    maybe_bad_book: false,
+                    levels: vec![depth_level()],
+                }),
+            ),
+            case(
+                "live/depth_snapshot",
+                LiveMessage::DepthSnapshot(DepthSnapshot {
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    symbol: "ESZ4".to_string(),
+                    sequence_u64: 1,
+                    reason: SnapshotReason::Subscribe,
+                    levels: vec![depth_level()],
+                }),
+            ),
+            case(
+                "live/depth_diff",
+                LiveMessage::DepthDiff(DepthDiff {
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    symbol: "ESZ4".to_string(),
+                    sequence_u64: 2,
+                    changes: vec![LevelChange {
+                        index: 0,
+                        level: depth_level(),
+                    }],
+                }),
+            ),
+            case(
+                "live/mbo",
+                LiveMessage::Mbo(MboRecord {
+                    ts_event_unix_ns: 1_717_248_600_000_000_000,
+                    symbol: "ESZ4".to_string(),
+                    order_id_u64: 6_412_091_033,
+                    price_i64: 5_300_000_000_000,
+                    size_u32: 3,
+                    action: MboAction::Add,
+                    side: BookSide::Bid,
+                    sequence_u32: 81_025,
+                    last: true,
+                    maybe_bad_book: false,
+                }),
+            ),
+            case(
+                "live/rate_adjusted",
+                LiveMessage::RateAdjusted {
+                    conflation_ms: 100,
+                    previous_ms: 0,
+                    lag_ms: 412,
+                },
+            ),
+            case(
+                "live/fragment",
+                LiveMessage::Fragment {
+                    id: 1,
+                    part: 0,
+                    total: 2,
+                    data: r#"{"type":"conn"#.to_string(),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_wire_format_matches_fixtures() {
+        let actual = cases();
+        let stored = std::fs::read_to_string(FIXTURES)
+            .ok()
+            .map(|json| serde_json::from_str::<Fixtures>(&json).unwrap());
+
+        if std::env::var_os("UPDATE_WIRE_FIXTURES").is_some() {
+            if let Some(stored) = &stored {
+                assert!(
+                    stored.cases == actual || stored.wire_version < WIRE_VERSION,
+                    "The wire format changed: bump WIRE_VERSION and add a CHANGELOG \
+                     entry before updating the fixtures"
+                );
+            }
+            let fixtures = Fixtures {
+                wire_version: WIRE_VERSION,
+                cases: actual,
+            };
+            let json = serde_json::to_string_pretty(&fixtures).unwrap();
+            std::fs::write(FIXTURES, json + "\n").unwrap();
+            return;
+        }
+
+        let stored = stored.expect("No wire fixtures; run with UPDATE_WIRE_FIXTURES=1");
+        assert_eq!(
+            stored.wire_version, WIRE_VERSION,
+            "Wire fixtures are for another version; run with UPDATE_WIRE_FIXTURES=1"
+        );
+        for (name, json) in &actual {
+            let expected = stored.cases.get(name).unwrap_or_else(|| {
+                panic!("No fixture for {}; run with UPDATE_WIRE_FIXTURES=1", name)
+            });
+            assert_eq!(
+                json, expected,
+                "The wire format of {} changed; if intended, bump WIRE_VERSION, \
+                 add a CHANGELOG entry and run with UPDATE_WIRE_FIXTURES=1",
+                name
+            );
+        }
+        for name in stored.cases.keys() {
+            assert!(actual.contains_key(name), "Fixture {} has no case", name);
+        }
+    }
+
+    #[test]
+    fn test_changelog_covers_every_version() {
+        let versions: Vec<u32> = CHANGELOG.iter().map(|(version, _)| *version).collect();
+        assert_eq!(versions, (1..=WIRE_VERSION).collect::<Vec<_>>());
+        let info = VersionInfo::new("0.1.0", Vec::new());
+        assert_eq!(info.changelog.len(), CHANGELOG.len());
+        assert!(FEATURES.iter().all(|(_, since)| *since <= WIRE_VERSION));
+    }
+
+    #[test]
+    fn test_unknown_live_messages() {
+        // A type from a newer server, with fields this build doesn't know
+        let msg: LiveMessage =
+            serde_json::from_str(r#"{"type":"halt","symbol":"ES.FUT","reason":"limit"}"#).unwrap();
+        assert!(matches!(msg, LiveMessage::Unknown));
+        // New fields on a known type are ignored
+        let msg: LiveMessage = serde_json::from_str(
+            r#"{"type":"error","message":"Stream error","retry_after_ms":500}"#,
+        )
+        .unwrap();
+        assert!(matches!(msg, LiveMessage::Error { .. }));
+        assert!(crate::compact::live_from_str(r#"{"type":"halt"}"#).is_ok());
+
+        let every_type: Vec<&str> = LIVE_MESSAGES.iter().map(|(name, _)| *name).collect();
+        for (name, json) in cases() {
+            if let Some(type_name) = json.get("type").and_then(Value::as_str) {
+                if name.starts_with("live/") {
+                    assert!(
+                        every_type.contains(&type_name),
+                        "{} isn't listed",
+                        type_name
+                    );
+                }
+            }
+        }
+        assert!(knows_live_message(1, "trade"));
+        assert!(!knows_live_message(WIRE_VERSION, "halt"));
+        assert!(knows_live_message(0, "error"));
+    }
+
+    #[test]
+    fn test_supports() {
+        assert!(supports(8, "prices_as"));
+        assert!(!supports(7, "prices_as"));
+        assert!(!supports(WIRE_VERSION, "telepathy"));
+        // An older server's version info has no features
+        let old: VersionInfo = serde_json::from_str(
+            r#"{"server_version":"0.1.0","wire_version":7,"changelog":[],"api_versions":[]}"#,
+        )
+        .unwrap();
+        assert!(old.features.is_empty());
+        assert!(old.supports("compact"));
+        assert!(!old.supports("prices_as"));
+    }
+}
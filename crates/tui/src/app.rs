@@ -0,0 +1,240 @@
+//! Viewer state, updated from live messages and historical responses.
+
+use shared::{LiveMessage, OhlcvRecord, TradeRecord};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Latest prices for one subscribed symbol.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quote {
+    /// Contract the symbol resolved to, e.g. ESZ4 for ES.FUT
+    pub contract: Option<String>,
+    pub last_i64: Option<i64>,
+    /// Price the change is measured from: the first trade seen
+    pub reference_i64: Option<i64>,
+    pub volume_u64: u64,
+    pub trades: u64,
+}
+
+impl Quote {
+    /// Change since the reference price.
+    pub fn change_i64(&self) -> Option<i64> {
+        Some(self.last_i64? - self.reference_i64?)
+    }
+}
+
+/// Everything the viewer shows.
+#[derive(Debug, Clone)]
+pub struct App {
+    pub symbols: Vec<String>,
+    /// Index into `symbols` of the symbol the candles are for
+    pub selected: usize,
+    pub quotes: BTreeMap<String, Quote>,
+    /// Bars of the selected symbol, oldest first
+    pub candles: Vec<OhlcvRecord>,
+    /// Width of each candle
+    pub interval_ns: u64,
+    /// Most recent trades, newest first
+    pub tape: VecDeque<TradeRecord>,
+    tape_len: usize,
+    /// Contract to the subscribed symbol it resolved from; live trades are
+    /// labelled with the contract
+    subscribed: HashMap<String, String>,
+    /// Connection state or the last error, for the status bar
+    pub status: String,
+}
+
+impl App {
+    pub fn new(symbols: Vec<String>, interval_ns: u64, tape_len: usize) -> Self {
+        let quotes = symbols
+            .iter()
+            .map(|symbol| (symbol.clone(), Quote::default()))
+            .collect();
+        Self {
+            symbols,
+            selected: 0,
+            quotes,
+            candles: Vec::new(),
+            interval_ns,
+            tape: VecDeque::with_capacity(tape_len),
+            tape_len,
+            subscribed: HashMap::new(),
+            status: "connecting".to_string(),
+        }
+    }
+
+    /// The symbol the candles are for.
+    pub fn selected_symbol(&self) -> &str {
+        &self.symbols[self.selected]
+    }
+
+    /// Select the next symbol, or the previous with `back`. The candles
+    /// are cleared until the new symbol's are loaded.
+    pub fn cycle(&mut self, back: bool) {
+        let n = self.symbols.len();
+        self.selected = if back {
+            (self.selected + n - 1) % n
+        } else {
+            (self.selected + 1) % n
+        };
+        self.candles.clear();
+    }
+
+    /// The subscribed symbol a live record labelled `symbol` belongs to.
+    fn subscribed<'a>(&'a self, symbol: &'a str) -> &'a str {
+        self.subscribed
+            .get(symbol)
+            .map(String::as_str)
+            .unwrap_or(symbol)
+    }
+
+    /// Replace the candles with those loaded for the selected symbol.
+    pub fn set_candles(&mut self, candles: Vec<OhlcvRecord>) {
+        self.candles = candles;
+    }
+
+    /// Apply a live message.
+    pub fn apply(&mut self, msg: LiveMessage) {
+        match msg {
+            LiveMessage::Trade {
+                ts_event_unix_ns,
+                symbol,
+                price_i64,
+                size_u32,
+            } => {
+                let subscribed = self.subscribed(&symbol).to_string();
+                let quote = self.quotes.entry(subscribed.clone()).or_default();
+                quote.last_i64 = Some(price_i64);
+                quote.reference_i64.get_or_insert(price_i64);
+                quote.volume_u64 += u64::from(size_u32);
+                quote.trades += 1;
+                if subscribed == self.selected_symbol() {
+                    self.extend_candles(ts_event_unix_ns, price_i64, size_u32);
+                }
+
+                self.tape.push_front(TradeRecord {
+                    ts_event_unix_ns,
+                    symbol,
+                    price_i64,
+                    size_u32,
+                    quote: None,
+                    sequence_u32: None,
+                });
+                self.tape.truncate(self.tape_len);
+            }
+            LiveMessage::SymbolMapping {
+                stype_in_symbol,
+                stype_out_symbol,
+                ..
+            } => {
+                if let Some(quote) = self.quotes.get_mut(&stype_in_symbol) {
+                    quote.contract = Some(stype_out_symbol.clone());
+                }
+                self.subscribed.insert(stype_out_symbol, stype_in_symbol);
+            }
+            LiveMessage::Connected {
+                symbols, schema, ..
+            } => {
+                self.status = format!("connected: {} ({})", symbols.join(","), schema);
+            }
+            LiveMessage::Error { message } => self.status = format!("error: {}", message),
+            _ => {}
+        }
+    }
+
+    /// Fold a trade into the last candle, or start the next one.
+    fn extend_candles(&mut self, ts_event_unix_ns: u64, price_i64: i64, size_u32: u32) {
+        let open_ns = ts_event_unix_ns - ts_event_unix_ns % self.interval_ns.max(1);
+        match self.candles.last_mut() {
+            Some(bar) if bar.ts_event_unix_ns == open_ns => {
+                bar.high_i64 = bar.high_i64.max(price_i64);
+                bar.low_i64 = bar.low_i64.min(price_i64);
+                bar.close_i64 = price_i64;
+                bar.volume_u64 += u64::from(size_u32);
+            }
+            Some(bar) if bar.ts_event_unix_ns > open_ns => {}
+            _ => self.candles.push(OhlcvRecord {
+                ts_event_unix_ns: open_ns,
+                symbol: self.selected_symbol().to_string(),
+                open_i64: price_i64,
+                high_i64: price_i64,
+                low_i64: price_i64,
+                close_i64: price_i64,
+                volume_u64: u64::from(size_u32),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE_NS: u64 = 60_000_000_000;
+
+    fn trade(ts: u64, symbol: &str, price: i64) -> LiveMessage {
+        LiveMessage::Trade {
+            ts_event_unix_ns: ts,
+            symbol: symbol.to_string(),
+            price_i64: price,
+            size_u32: 2,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut app = App::new(
+            vec!["ES.FUT".to_string(), "CL.FUT".to_string()],
+            MINUTE_NS,
+            2,
+        );
+        app.set_candles(vec![OhlcvRecord {
+            ts_event_unix_ns: 0,
+            symbol: "ESZ4".to_string(),
+            open_i64: 100,
+            high_i64: 110,
+            low_i64: 90,
+            close_i64: 105,
+            volume_u64: 10,
+        }]);
+        app.apply(LiveMessage::SymbolMapping {
+            instrument_id: 1,
+            stype_in_symbol: "ES.FUT".to_string(),
+            stype_out_symbol: "ESZ4".to_string(),
+        });
+
+        // Trades are labelled with the contract but quoted by the symbol
+        app.apply(trade(30_000_000_000, "ESZ4", 120));
+        app.apply(trade(MINUTE_NS + 1, "ESZ4", 115));
+        app.apply(trade(MINUTE_NS + 2, "CL.FUT", 7_000));
+        let es = &app.quotes["ES.FUT"];
+        assert_eq!(es.contract.as_deref(), Some("ESZ4"));
+        assert_eq!(
+            (es.last_i64, es.change_i64(), es.volume_u64),
+            (Some(115), Some(-5), 4)
+        );
+        assert_eq!(app.quotes["CL.FUT"].last_i64, Some(7_000));
+
+        // The selected symbol's trades extend its candles
+        assert_eq!(app.candles.len(), 2);
+        assert_eq!(
+            (
+                app.candles[0].high_i64,
+                app.candles[0].close_i64,
+                app.candles[0].volume_u64
+            ),
+            (120, 120, 12)
+        );
+        assert_eq!(app.candles[1].ts_event_unix_ns, MINUTE_NS);
+        assert_eq!(app.candles[1].open_i64, 115);
+
+        // The tape keeps the newest trades
+        let tape: Vec<&str> = app.tape.iter().map(|t| t.symbol.as_str()).collect();
+        assert_eq!(tape, ["CL.FUT", "ESZ4"]);
+
+        app.cycle(false);
+        assert_eq!(app.selected_symbol(), "CL.FUT");
+        assert!(app.candles.is_empty());
+        app.cycle(true);
+        assert_eq!(app.selected_symbol(), "ES.FUT");
+    }
+}
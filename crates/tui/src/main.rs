@@ -0,0 +1,207 @@
+//! `sftui` - watch the Market Data Viewer backend from a terminal.
+//!
+//! Shows live prices, candles of the selected symbol loaded from
+//! `/api/historical` and kept current from the live trades, and a rolling
+//! trade tape. Handy on headless servers and for demos without the web
+//! frontend.
+
+mod app;
+mod ui;
+
+use app::App;
+use chrono::{SecondsFormat, Utc};
+use clap::Parser;
+use client::{HistoricalClient, LiveClient, Subscription};
+use futures::StreamExt;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::DefaultTerminal;
+use shared::timeexpr::parse_lookback;
+use shared::{HistoricalRequest, HistoricalResponse, LiveMessage, OhlcvRecord};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How often the screen is redrawn and keys are read.
+const FRAME: Duration = Duration::from_millis(100);
+
+/// Wait before resubscribing after the live connection drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+/// Most candles loaded for the selected symbol.
+const CANDLE_LIMIT: u32 = 1000;
+
+#[derive(Parser)]
+#[command(name = "sftui", version, about = "Terminal market data viewer")]
+struct Args {
+    /// Backend base URL
+    #[arg(long, env = "SFCTL_SERVER", default_value = "http://127.0.0.1:3001")]
+    server: String,
+    /// Comma-separated symbols (e.g. ES.FUT,CL.FUT)
+    #[arg(long, value_delimiter = ',', default_value = "ES.FUT")]
+    symbols: Vec<String>,
+    /// Candle schema: ohlcv-1s or ohlcv-1m
+    #[arg(long, default_value = "ohlcv-1m")]
+    candles: String,
+    /// How far back the candles go (e.g. 90s, 30m, 2h, 1d)
+    #[arg(long, default_value = "2h")]
+    last: String,
+    /// Trades kept on the tape
+    #[arg(long, default_value_t = 200)]
+    tape: usize,
+}
+
+/// Something for the viewer from a background task.
+enum Update {
+    Live(LiveMessage),
+    Status(String),
+    Candles {
+        symbol: String,
+        result: Result<Vec<OhlcvRecord>, String>,
+    },
+}
+
+/// Loads candles for a symbol in the background.
+#[derive(Clone)]
+struct Loader {
+    client: HistoricalClient,
+    schema: String,
+    lookback: chrono::Duration,
+    updates: mpsc::UnboundedSender<Update>,
+}
+
+impl Loader {
+    fn load(&self, symbol: &str) {
+        let loader = self.clone();
+        let symbol = symbol.to_string();
+        tokio::spawn(async move {
+            let now = Utc::now();
+            let Some(start) = now.checked_sub_signed(loader.lookback) else {
+                let result = Err("--last is out of range".to_string());
+                let _ = loader.updates.send(Update::Candles { symbol, result });
+                return;
+            };
+            let req = HistoricalRequest {
+                symbols: vec![symbol.clone()],
+                schema: loader.schema.clone(),
+                start_rfc3339: start.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                end_rfc3339: now.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                limit: CANDLE_LIMIT,
+                ..Default::default()
+            };
+            let result = match loader.client.fetch(&req).await {
+                Ok(HistoricalResponse::Ohlcv1S { data } | HistoricalResponse::Ohlcv1M { data }) => {
+                    Ok(data)
+                }
+                Ok(other) => Err(format!("expected bars, got {}", other.schema().as_str())),
+                Err(e) => Err(e.to_string()),
+            };
+            let _ = loader.updates.send(Update::Candles { symbol, result });
+        });
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = start(Args::parse()).await {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn start(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let interval_ns = match args.candles.as_str() {
+        "ohlcv-1s" => 1_000_000_000,
+        "ohlcv-1m" => 60_000_000_000,
+        other => {
+            return Err(format!("--candles must be ohlcv-1s or ohlcv-1m, not {}", other).into())
+        }
+    };
+    let lookback = parse_lookback(&args.last)?;
+    if Utc::now().checked_sub_signed(lookback).is_none() {
+        return Err(format!("--last {} is out of range", args.last).into());
+    }
+    let (updates, mut received) = mpsc::unbounded_channel();
+    let loader = Loader {
+        client: HistoricalClient::new(&args.server)?,
+        schema: args.candles,
+        lookback,
+        updates: updates.clone(),
+    };
+    let live = LiveClient::new(&args.server)?;
+    let sub = Subscription::new(args.symbols.iter().cloned(), "trades");
+    tokio::spawn(async move {
+        loop {
+            match live.subscribe(&sub).await {
+                Ok(mut stream) => {
+                    while let Some(msg) = stream.next().await {
+                        if updates.send(Update::Live(msg)).is_err() {
+                            return;
+                        }
+                    }
+                    let _ = updates.send(Update::Status("disconnected".to_string()));
+                }
+                Err(e) => {
+                    let _ = updates.send(Update::Status(format!("disconnected: {}", e)));
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+
+    let mut app = App::new(args.symbols, interval_ns, args.tape);
+    loader.load(app.selected_symbol());
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut app, &mut received, &loader).await;
+    ratatui::restore();
+    Ok(result?)
+}
+
+/// Redraw and handle keys until the user quits.
+async fn run(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    received: &mut mpsc::UnboundedReceiver<Update>,
+    loader: &Loader,
+) -> std::io::Result<()> {
+    let mut frames = tokio::time::interval(FRAME);
+    loop {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+        frames.tick().await;
+
+        while let Ok(update) = received.try_recv() {
+            match update {
+                Update::Live(msg) => app.apply(msg),
+                Update::Status(status) => app.status = status,
+                Update::Candles { symbol, result } if symbol == app.selected_symbol() => {
+                    match result {
+                        Ok(candles) => app.set_candles(candles),
+                        Err(e) => app.status = format!("candles: {}", e),
+                    }
+                }
+                // Loaded for a symbol since deselected
+                Update::Candles { .. } => {}
+            }
+        }
+
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Tab | KeyCode::BackTab => {
+                    app.cycle(key.code == KeyCode::BackTab);
+                    loader.load(app.selected_symbol());
+                }
+                KeyCode::Char('r') => loader.load(app.selected_symbol()),
+                _ => {}
+            }
+        }
+    }
+}
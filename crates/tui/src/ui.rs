@@ -0,0 +1,229 @@
+//! Rendering: the quote table, candlestick pane, trade tape and status bar.
+
+use crate::app::App;
+use chrono::DateTime;
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table, Widget};
+use ratatui::Frame;
+use shared::price::format_price;
+use shared::OhlcvRecord;
+
+const UP: Color = Color::Green;
+const DOWN: Color = Color::Red;
+
+/// Draw the whole viewer.
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [quotes, middle, status] = Layout::vertical([
+        Constraint::Length(app.symbols.len() as u16 + 3),
+        Constraint::Min(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [candles, tape] =
+        Layout::horizontal([Constraint::Min(20), Constraint::Length(42)]).areas(middle);
+
+    frame.render_widget(quote_table(app), quotes);
+    frame.render_widget(candle_pane(app), candles);
+    frame.render_widget(trade_tape(app), tape);
+    frame.render_widget(
+        Paragraph::new(format!(
+            " {}  |  tab/shift-tab: symbol  r: reload  q: quit",
+            app.status
+        ))
+        .style(Style::new().add_modifier(Modifier::REVERSED)),
+        status,
+    );
+}
+
+fn signed_price(change: i64) -> String {
+    let sign = if change < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_price(change.abs()))
+}
+
+fn direction(change: i64) -> Style {
+    match change {
+        c if c > 0 => Style::new().fg(UP),
+        c if c < 0 => Style::new().fg(DOWN),
+        _ => Style::new(),
+    }
+}
+
+fn quote_table(app: &App) -> Table<'_> {
+    let rows = app.symbols.iter().enumerate().map(|(i, symbol)| {
+        let quote = app.quotes.get(symbol).cloned().unwrap_or_default();
+        let change = quote.change_i64();
+        let mut style = change.map(direction).unwrap_or_default();
+        if i == app.selected {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        Row::new([
+            symbol.clone(),
+            quote.contract.unwrap_or_default(),
+            quote.last_i64.map(format_price).unwrap_or_default(),
+            change.map(signed_price).unwrap_or_default(),
+            quote.volume_u64.to_string(),
+            quote.trades.to_string(),
+        ])
+        .style(style)
+    });
+    Table::new(
+        rows,
+        [
+            Constraint::Length(12),
+            Constraint::Length(10),
+            Constraint::Length(14),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(10),
+        ],
+    )
+    .header(
+        Row::new(["Symbol", "Contract", "Last", "Change", "Volume", "Trades"])
+            .style(Style::new().add_modifier(Modifier::UNDERLINED)),
+    )
+    .block(Block::bordered().title(" Prices "))
+}
+
+fn candle_pane(app: &App) -> impl Widget + '_ {
+    let interval = match app.interval_ns / 1_000_000_000 {
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    };
+    let title = match price_range(&app.candles) {
+        Some((low, high)) => format!(
+            " {} {}  {} - {} ",
+            app.selected_symbol(),
+            interval,
+            format_price(low),
+            format_price(high)
+        ),
+        None => format!(" {} {}  loading ", app.selected_symbol(), interval),
+    };
+    Candles {
+        candles: &app.candles,
+    }
+    .block(Block::bordered().title(title))
+}
+
+fn trade_tape(app: &App) -> List<'_> {
+    let items = app.tape.iter().map(|trade| {
+        let time = DateTime::from_timestamp_nanos(trade.ts_event_unix_ns as i64)
+            .format("%H:%M:%S%.3f")
+            .to_string();
+        ListItem::new(Line::from(vec![
+            Span::raw(format!("{} ", time)),
+            Span::styled(format!("{:<8}", trade.symbol), Style::new().fg(Color::Cyan)),
+            Span::raw(format!(
+                "{:>11} x{}",
+                format_price(trade.price_i64),
+                trade.size_u32
+            )),
+        ]))
+    });
+    List::new(items).block(Block::bordered().title(" Trades "))
+}
+
+/// Lowest low and highest high of `candles`.
+fn price_range(candles: &[OhlcvRecord]) -> Option<(i64, i64)> {
+    let low = candles.iter().map(|bar| bar.low_i64).min()?;
+    let high = candles.iter().map(|bar| bar.high_i64).max()?;
+    Some((low, high))
+}
+
+/// A candlestick chart, one column per bar, the newest at the right.
+struct Candles<'a> {
+    candles: &'a [OhlcvRecord],
+}
+
+impl<'a> Candles<'a> {
+    fn block(self, block: Block<'a>) -> impl Widget + 'a {
+        Bordered { block, inner: self }
+    }
+}
+
+impl Widget for Candles<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        let shown = &self.candles[self.candles.len().saturating_sub(area.width as usize)..];
+        let Some((low, high)) = price_range(shown) else {
+            return;
+        };
+        let rows = i128::from(area.height - 1);
+        let span = i128::from(high - low).max(1);
+        let row = |price: i64| area.y + ((i128::from(high - price) * rows) / span) as u16;
+
+        for (x, bar) in (area.x..).zip(shown) {
+            let style = Style::new().fg(if bar.close_i64 >= bar.open_i64 {
+                UP
+            } else {
+                DOWN
+            });
+            for y in row(bar.high_i64)..=row(bar.low_i64) {
+                buf[(x, y)].set_symbol("│").set_style(style);
+            }
+            let top = row(bar.open_i64.max(bar.close_i64));
+            let bottom = row(bar.open_i64.min(bar.close_i64));
+            for y in top..=bottom {
+                buf[(x, y)].set_symbol("┃").set_style(style);
+            }
+        }
+    }
+}
+
+/// A widget drawn inside a block.
+struct Bordered<'a, W> {
+    block: Block<'a>,
+    inner: W,
+}
+
+impl<W: Widget> Widget for Bordered<'_, W> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let inner = self.block.inner(area);
+        self.block.render(area, buf);
+        self.inner.render(inner, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(open: i64, high: i64, low: i64, close: i64) -> OhlcvRecord {
+        OhlcvRecord {
+            ts_event_unix_ns: 0,
+            symbol: "ES.FUT".to_string(),
+            open_i64: open,
+            high_i64: high,
+            low_i64: low,
+            close_i64: close,
+            volume_u64: 1,
+        }
+    }
+
+    #[test]
+    fn test_candles() {
+        let candles = [
+            bar(10, 10, 10, 10),
+            bar(40, 40, 0, 20),
+            bar(20, 40, 20, 30),
+            bar(30, 40, 10, 40),
+        ];
+        let area = Rect::new(0, 0, 3, 5);
+        let mut buf = Buffer::empty(area);
+        Candles { candles: &candles }.render(area, &mut buf);
+
+        // Only the newest three fit; each row is 10 wide
+        let rows: Vec<String> = (0..5)
+            .map(|y| (0..3).map(|x| buf[(x, y)].symbol()).collect())
+            .collect();
+        assert_eq!(rows, ["┃│┃", "┃┃┃", "┃┃│", "│ │", "│  "]);
+        assert_eq!(buf[(0, 0)].fg, DOWN);
+        assert_eq!(buf[(1, 0)].fg, UP);
+    }
+}
@@ -0,0 +1,127 @@
+//! wasm-bindgen exports of the shared crate for the browser frontend.
+//!
+//! Built with `wasm-pack build crates/wasm --target web`. The frontend then
+//! parses messages, formats prices, and builds bars with the exact same code
+//! as the backend.
+//!
+//! 64-bit integers (timestamps, fixed-point prices, volumes) cross the
+//! boundary as JS `BigInt`s, since nanosecond timestamps do not fit in a
+//! `Number` without losing precision.
+
+use serde::Serialize;
+use shared::aggregate::{aggregate_trades_aligned, BarAlign, BarBuilder};
+use shared::{price, LiveMessage, OhlcvRecord, TradeRecord};
+use wasm_bindgen::prelude::*;
+
+/// Convert a Rust value to JS, mapping 64-bit integers to `BigInt`.
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    let serializer =
+        serde_wasm_bindgen::Serializer::new().serialize_large_number_types_as_bigints(true);
+    value
+        .serialize(&serializer)
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Parse a WebSocket text frame into a LiveMessage object.
+#[wasm_bindgen(js_name = parseLiveMessage)]
+pub fn parse_live_message(json: &str) -> Result<JsValue, JsError> {
+    let msg: LiveMessage = serde_json::from_str(json)?;
+    to_js(&msg)
+}
+
+/// Convert a fixed-point price to a float.
+#[wasm_bindgen(js_name = priceToNumber)]
+pub fn price_to_number(price_i64: i64) -> f64 {
+    price::to_f64(price_i64)
+}
+
+/// Convert a float price to fixed-point.
+#[wasm_bindgen(js_name = priceFromNumber)]
+pub fn price_from_number(value: f64) -> i64 {
+    price::from_f64(value)
+}
+
+/// Format a fixed-point price as a decimal string without float rounding.
+#[wasm_bindgen(js_name = formatPrice)]
+pub fn format_price(price_i64: i64) -> String {
+    price::format_price(price_i64)
+}
+
+/// Round a fixed-point price to the nearest multiple of a tick.
+#[wasm_bindgen(js_name = roundToTick)]
+pub fn round_to_tick(price_i64: i64, tick_i64: i64) -> i64 {
+    price::round_to_tick(price_i64, tick_i64)
+}
+
+/// Format a fixed-point price at a tick's precision.
+#[wasm_bindgen(js_name = formatPriceToTick)]
+pub fn format_price_to_tick(price_i64: i64, tick_i64: i64) -> String {
+    price::format_price_to_tick(price_i64, tick_i64)
+}
+
+/// Parse an optional alignment spec such as `session 08:30 America/Chicago`.
+fn parse_align(align: Option<String>) -> Result<BarAlign, JsError> {
+    align
+        .as_deref()
+        .map_or(Ok(BarAlign::Epoch), str::parse)
+        .map_err(|e: String| JsError::new(&e))
+}
+
+/// Aggregate an array of TradeRecord objects into OHLCV bars, optionally
+/// aligned (`epoch`, `session 08:30 America/Chicago`, `offset 30m`).
+#[wasm_bindgen(js_name = aggregateTrades)]
+pub fn aggregate_trades_js(
+    trades: JsValue,
+    interval_ns: u64,
+    align: Option<String>,
+) -> Result<JsValue, JsError> {
+    if interval_ns == 0 {
+        return Err(JsError::new("interval_ns must be non-zero"));
+    }
+    let align = parse_align(align)?;
+    let trades: Vec<TradeRecord> = serde_wasm_bindgen::from_value(trades)?;
+    to_js(&aggregate_trades_aligned(&trades, interval_ns, align))
+}
+
+/// Incremental bar builder for live trade streams.
+#[wasm_bindgen(js_name = BarBuilder)]
+pub struct WasmBarBuilder {
+    inner: BarBuilder,
+}
+
+#[wasm_bindgen(js_class = BarBuilder)]
+impl WasmBarBuilder {
+    /// Create a builder producing bars `interval_ns` wide, optionally
+    /// aligned as in `aggregateTrades`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(interval_ns: u64, align: Option<String>) -> Result<WasmBarBuilder, JsError> {
+        if interval_ns == 0 {
+            return Err(JsError::new("interval_ns must be non-zero"));
+        }
+        Ok(Self {
+            inner: BarBuilder::aligned(interval_ns, parse_align(align)?),
+        })
+    }
+
+    /// Feed a TradeRecord; returns the completed bar or `undefined`.
+    pub fn push(&mut self, trade: JsValue) -> Result<JsValue, JsError> {
+        let trade: TradeRecord = serde_wasm_bindgen::from_value(trade)?;
+        match self.inner.push(&trade) {
+            Some(bar) => to_js(&bar),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// The bar in progress for `symbol`, or `undefined`.
+    pub fn current(&self, symbol: &str) -> Result<JsValue, JsError> {
+        match self.inner.current(symbol) {
+            Some(bar) => to_js::<OhlcvRecord>(bar),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// Close and return every bar in progress.
+    pub fn flush(&mut self) -> Result<JsValue, JsError> {
+        to_js(&self.inner.flush())
+    }
+}
@@ -0,0 +1,345 @@
+//! A terminal ticker for the Market Data Viewer, built on the `client`
+//! crate.
+//!
+//! [`Ticker`] keeps a `/ws/live` subscription open across disconnects,
+//! reconnecting with backoff, and [`BarBook`] keeps each symbol's recent
+//! bars. Every connection to an `ohlcv-1s` or `ohlcv-1m` stream starts with
+//! a snapshot of completed bars, so merging it into the book fills in the
+//! bars missed while disconnected: that's how the ticker resumes.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), client::ClientError> {
+//! use client::Subscription;
+//! use futures::StreamExt;
+//! use ticker::{BarBook, Event, Ticker};
+//!
+//! let sub = Subscription::new(["ES.FUT"], "ohlcv-1m").with_snapshot_bars(60);
+//! let mut events = Ticker::new("http://127.0.0.1:3001", sub)?.events();
+//! let mut book = BarBook::new(60);
+//! while let Some(event) = events.next().await {
+//!     if let Event::Message(msg) = event {
+//!         if book.apply(&msg) {
+//!             println!("{}", book.line());
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use client::{ClientError, LiveClient, Subscription};
+use futures::{Stream, StreamExt};
+use shared::price::format_price;
+use shared::LiveMessage;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Something that happened on a [`Ticker`]'s subscription.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A message from the server.
+    Message(LiveMessage),
+    /// The connection was lost, or couldn't be made; the next attempt
+    /// starts after `retry_in`.
+    Disconnected { reason: String, retry_in: Duration },
+    /// The server refused the subscription, e.g. for an unknown schema.
+    /// No more events follow.
+    Failed { reason: String },
+}
+
+/// Delays between reconnect attempts.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// Delay before the first attempt after a disconnect
+    pub initial: Duration,
+    /// Longest delay, however many attempts have failed
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A live subscription that reconnects when its connection drops.
+#[derive(Debug, Clone)]
+pub struct Ticker {
+    client: LiveClient,
+    sub: Subscription,
+    backoff: Backoff,
+}
+
+impl Ticker {
+    /// Subscribe to `sub` on the server at `server` (e.g.
+    /// `http://127.0.0.1:3001`).
+    pub fn new(server: &str, sub: Subscription) -> Result<Self, ClientError> {
+        Ok(Self {
+            client: LiveClient::new(server)?,
+            sub,
+            backoff: Backoff::default(),
+        })
+    }
+
+    /// Wait between reconnect attempts as `backoff` says.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// The subscription's events, forever unless the server refuses it.
+    ///
+    /// The delay before reconnecting doubles with each failed attempt, up
+    /// to the maximum, and starts over once a connection is confirmed with
+    /// `connected`. An `error` before `connected` means the server refused
+    /// the subscription, which reconnecting won't fix; one after it is
+    /// passed on, and if the stream then ends the ticker reconnects.
+    pub fn events(self) -> impl Stream<Item = Event> + Send + Unpin + 'static {
+        Box::pin(async_stream::stream! {
+            let mut delay = self.backoff.initial;
+            loop {
+                let reason = match self.client.subscribe(&self.sub).await {
+                    Err(e) => e.to_string(),
+                    Ok(mut stream) => {
+                        let mut connected = false;
+                        let mut reason = "connection closed".to_string();
+                        while let Some(msg) = stream.next().await {
+                            match &msg {
+                                LiveMessage::Error { message } if !connected => {
+                                    yield Event::Failed { reason: message.clone() };
+                                    return;
+                                }
+                                LiveMessage::Error { message } => reason = message.clone(),
+                                LiveMessage::Connected { .. } => {
+                                    connected = true;
+                                    delay = self.backoff.initial;
+                                }
+                                _ => {}
+                            }
+                            yield Event::Message(msg);
+                        }
+                        reason
+                    }
+                };
+                yield Event::Disconnected { reason, retry_in: delay };
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(self.backoff.max);
+            }
+        })
+    }
+}
+
+/// A bar as a [`BarBook`] keeps it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bar {
+    pub open_i64: i64,
+    pub high_i64: i64,
+    pub low_i64: i64,
+    pub close_i64: i64,
+    pub volume_u64: u64,
+    /// Whether the server said the bar had closed
+    pub closed: bool,
+}
+
+/// Each symbol's most recent bars, keyed by open time, and its last trade.
+///
+/// A bar replaces the one with the same open time, so the bars a
+/// reconnection's snapshot repeats aren't counted twice, except that a
+/// closed bar isn't replaced by one still in progress.
+#[derive(Debug, Clone, Default)]
+pub struct BarBook {
+    capacity: usize,
+    bars: BTreeMap<String, BTreeMap<u64, Bar>>,
+    last_trades: HashMap<String, i64>,
+    /// Subscribed symbol to the contract it resolved to, e.g. ES.FUT to ESZ4
+    contracts: HashMap<String, String>,
+    connections: u32,
+}
+
+impl BarBook {
+    /// A book keeping at most `capacity` bars per symbol.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    /// Apply a message, returning whether the ticker line changed.
+    pub fn apply(&mut self, msg: &LiveMessage) -> bool {
+        match msg {
+            LiveMessage::Ohlcv {
+                ts_event_unix_ns,
+                symbol,
+                open_i64,
+                high_i64,
+                low_i64,
+                close_i64,
+                volume_u64,
+                bar_closed,
+                ..
+            } => {
+                let bars = self.bars.entry(symbol.clone()).or_default();
+                if !bar_closed && bars.get(ts_event_unix_ns).is_some_and(|bar| bar.closed) {
+                    return false;
+                }
+                bars.insert(
+                    *ts_event_unix_ns,
+                    Bar {
+                        open_i64: *open_i64,
+                        high_i64: *high_i64,
+                        low_i64: *low_i64,
+                        close_i64: *close_i64,
+                        volume_u64: *volume_u64,
+                        closed: *bar_closed,
+                    },
+                );
+                while bars.len() > self.capacity {
+                    bars.pop_first();
+                }
+                true
+            }
+            LiveMessage::Trade {
+                symbol, price_i64, ..
+            } => {
+                self.last_trades.insert(symbol.clone(), *price_i64);
+                true
+            }
+            LiveMessage::SymbolMapping {
+                stype_in_symbol,
+                stype_out_symbol,
+                ..
+            } => {
+                self.contracts
+                    .insert(stype_in_symbol.clone(), stype_out_symbol.clone());
+                true
+            }
+            LiveMessage::Connected { .. } => {
+                self.connections += 1;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Bars held for `symbol`, oldest first.
+    pub fn bars(&self, symbol: &str) -> impl Iterator<Item = (u64, &Bar)> {
+        self.bars
+            .get(symbol)
+            .into_iter()
+            .flat_map(|bars| bars.iter().map(|(ts, bar)| (*ts, bar)))
+    }
+
+    /// The latest price of `symbol`: its last trade, or else the close of
+    /// its latest bar.
+    pub fn last(&self, symbol: &str) -> Option<i64> {
+        self.last_trades.get(symbol).copied().or_else(|| {
+            self.bars
+                .get(symbol)
+                .and_then(|bars| bars.last_key_value())
+                .map(|(_, bar)| bar.close_i64)
+        })
+    }
+
+    /// How many times the subscription has connected.
+    pub fn connections(&self) -> u32 {
+        self.connections
+    }
+
+    /// Symbols with a price, in order.
+    pub fn symbols(&self) -> Vec<&str> {
+        let mut symbols: Vec<&str> = self
+            .bars
+            .keys()
+            .chain(self.last_trades.keys())
+            .map(String::as_str)
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        symbols
+    }
+
+    /// One line with each symbol's contract, latest price and change since
+    /// the open of its oldest bar held, e.g.
+    /// `ES.FUT (ESZ4) 4501.25 +3.50 [60 bars]`.
+    pub fn line(&self) -> String {
+        self.symbols()
+            .into_iter()
+            .filter_map(|symbol| {
+                let last = self.last(symbol)?;
+                let mut item = symbol.to_string();
+                if let Some(contract) = self.contracts.get(symbol) {
+                    item.push_str(&format!(" ({})", contract));
+                }
+                item.push_str(&format!(" {}", format_price(last)));
+                if let Some((_, first)) = self.bars(symbol).next() {
+                    let change = last - first.open_i64;
+                    let sign = if change < 0 { "-" } else { "+" };
+                    item.push_str(&format!(
+                        " {}{} [{} bars]",
+                        sign,
+                        format_price(change.abs()),
+                        self.bars(symbol).count()
+                    ));
+                }
+                Some(item)
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(ts: u64, close: i64, closed: bool) -> LiveMessage {
+        LiveMessage::Ohlcv {
+            ts_event_unix_ns: ts,
+            symbol: "ES.FUT".to_string(),
+            open_i64: 4_500_000_000_000,
+            high_i64: close.max(4_500_000_000_000),
+            low_i64: close.min(4_500_000_000_000),
+            close_i64: close,
+            volume_u64: 10,
+            bar_closed: closed,
+            interval: None,
+        }
+    }
+
+    #[test]
+    fn test_bar_book() {
+        let mut book = BarBook::new(2);
+        assert_eq!(book.line(), "");
+
+        assert!(book.apply(&bar(60, 4_501_000_000_000, false)));
+        assert!(book.apply(&bar(60, 4_502_000_000_000, true)));
+        // A closed bar isn't replaced by the bar in progress
+        assert!(!book.apply(&bar(60, 4_499_000_000_000, false)));
+        assert!(book.apply(&bar(0, 4_500_000_000_000, true)));
+        assert!(book.apply(&bar(120, 4_503_500_000_000, false)));
+
+        // Only the newest two are kept
+        let held: Vec<u64> = book.bars("ES.FUT").map(|(ts, _)| ts).collect();
+        assert_eq!(held, [60, 120]);
+        assert_eq!(book.last("ES.FUT"), Some(4_503_500_000_000));
+
+        book.apply(&LiveMessage::SymbolMapping {
+            instrument_id: 1,
+            stype_in_symbol: "ES.FUT".to_string(),
+            stype_out_symbol: "ESZ4".to_string(),
+        });
+        assert_eq!(book.line(), "ES.FUT (ESZ4) 4503.50 +3.50 [2 bars]");
+
+        book.apply(&LiveMessage::Trade {
+            ts_event_unix_ns: 130,
+            symbol: "ES.FUT".to_string(),
+            price_i64: 4_499_000_000_000,
+            size_u32: 1,
+        });
+        assert_eq!(book.line(), "ES.FUT (ESZ4) 4499.00 -1.00 [2 bars]");
+    }
+}
@@ -0,0 +1,101 @@
+//! `ticker` - a live terminal ticker that survives disconnects.
+//!
+//! Subscribes to bars on `/ws/live`, keeps the latest of them per symbol
+//! and rewrites one status line as they change. When the connection drops
+//! it reconnects with backoff, and the snapshot each connection starts with
+//! fills in the bars missed meanwhile. With `--count` it exits after that
+//! many updates, which makes it a smoke test for a running server.
+
+use clap::Parser;
+use client::Subscription;
+use futures::StreamExt;
+use std::io::{IsTerminal, Write};
+use std::time::Duration;
+use ticker::{Backoff, BarBook, Event, Ticker};
+
+#[derive(Parser)]
+#[command(name = "ticker", version, about = "Live terminal ticker")]
+struct Args {
+    /// Backend base URL
+    #[arg(long, env = "SFCTL_SERVER", default_value = "http://127.0.0.1:3001")]
+    server: String,
+    /// Comma-separated symbols (e.g. ES.FUT,CL.FUT)
+    #[arg(long, value_delimiter = ',', default_value = "ES.FUT")]
+    symbols: Vec<String>,
+    /// Data schema: ohlcv-1s, ohlcv-1m, or trades for prices without bars
+    #[arg(long, default_value = "ohlcv-1m")]
+    schema: String,
+    /// Bars to keep per symbol, and to ask for when (re)connecting
+    #[arg(long, default_value_t = 60)]
+    bars: usize,
+    /// Exit after this many updates
+    #[arg(long)]
+    count: Option<usize>,
+    /// Longest wait between reconnect attempts, in seconds
+    #[arg(long, default_value_t = 30)]
+    max_backoff: u64,
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(Args::parse()).await {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let sub = Subscription::new(args.symbols, args.schema).with_snapshot_bars(args.bars);
+    let backoff = Backoff {
+        max: Duration::from_secs(args.max_backoff),
+        ..Backoff::default()
+    };
+    let mut events = Ticker::new(&args.server, sub)?
+        .with_backoff(backoff)
+        .events();
+
+    // On a terminal the line is rewritten in place; piped, each update is
+    // its own line
+    let interactive = std::io::stdout().is_terminal();
+    let mut stdout = std::io::stdout().lock();
+    let mut book = BarBook::new(args.bars);
+    let mut updates = 0;
+
+    while let Some(event) = events.next().await {
+        let msg = match event {
+            Event::Message(msg) => msg,
+            Event::Disconnected { reason, retry_in } => {
+                if interactive {
+                    writeln!(stdout)?;
+                }
+                eprintln!("disconnected: {}; retrying in {:?}", reason, retry_in);
+                continue;
+            }
+            Event::Failed { reason } => return Err(reason.into()),
+        };
+        if let shared::LiveMessage::Error { message } = &msg {
+            eprintln!("server error: {}", message);
+        }
+        if matches!(msg, shared::LiveMessage::Connected { .. }) && book.connections() > 0 {
+            eprintln!("reconnected; resuming from the snapshot");
+        }
+        if !book.apply(&msg) {
+            continue;
+        }
+        if interactive {
+            write!(stdout, "\r\x1b[2K{}", book.line())?;
+        } else {
+            writeln!(stdout, "{}", book.line())?;
+        }
+        stdout.flush()?;
+
+        updates += 1;
+        if args.count.is_some_and(|count| updates >= count) {
+            if interactive {
+                writeln!(stdout)?;
+            }
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//! The ticker against a stand-in `/ws/live` that drops each connection
+//! after a few bars, as a server restart or network blip would.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use client::Subscription;
+use futures::StreamExt;
+use shared::LiveMessage;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use ticker::{Backoff, BarBook, Event, Ticker};
+
+const MINUTE_NS: u64 = 60_000_000_000;
+
+fn bar(minute: u64, close: i64, closed: bool) -> LiveMessage {
+    LiveMessage::Ohlcv {
+        ts_event_unix_ns: minute * MINUTE_NS,
+        symbol: "ES.FUT".to_string(),
+        open_i64: 4_500_000_000_000,
+        high_i64: close.max(4_500_000_000_000),
+        low_i64: close.min(4_500_000_000_000),
+        close_i64: close,
+        volume_u64: 10,
+        bar_closed: closed,
+        interval: None,
+    }
+}
+
+/// The first connection sends the bar for minute 0 and minute 1's bar in
+/// progress; the second starts with a snapshot repeating minute 0, minute
+/// 1 now closed and minute 2 in progress. Both then close. A subscription
+/// to any schema but `ohlcv-1m` is refused.
+async fn live(
+    ws: WebSocketUpgrade,
+    Query(params): Query<HashMap<String, String>>,
+    State(connections): State<Arc<AtomicUsize>>,
+) -> Response {
+    let connection = connections.fetch_add(1, Ordering::SeqCst);
+    ws.on_upgrade(move |mut socket: WebSocket| async move {
+        let messages = if params.get("schema").map(String::as_str) != Some("ohlcv-1m") {
+            vec![LiveMessage::Error {
+                message: "Unknown schema".to_string(),
+            }]
+        } else {
+            let connected = LiveMessage::Connected {
+                symbols: vec!["ES.FUT".to_string()],
+                schema: "ohlcv-1m".to_string(),
+                connection_id: Some(connection as u64),
+            };
+            match connection {
+                0 => vec![
+                    connected,
+                    bar(0, 4_501_000_000_000, true),
+                    bar(1, 4_502_000_000_000, false),
+                ],
+                _ => vec![
+                    connected,
+                    bar(0, 4_501_000_000_000, true),
+                    bar(1, 4_502_500_000_000, true),
+                    bar(2, 4_503_000_000_000, false),
+                ],
+            }
+        };
+        for msg in messages {
+            let text = serde_json::to_string(&msg).unwrap();
+            if socket.send(Message::Text(text)).await.is_err() {
+                return;
+            }
+        }
+        let _ = socket.close().await;
+    })
+}
+
+/// Serve the stand-in on an ephemeral port, returning its base URL.
+async fn serve() -> String {
+    let app = Router::new()
+        .route("/ws/live", get(live))
+        .with_state(Arc::new(AtomicUsize::new(0)));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+fn backoff() -> Backoff {
+    Backoff {
+        initial: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+    }
+}
+
+#[tokio::test]
+async fn test_resumes_after_reconnect() {
+    let server = serve().await;
+    let sub = Subscription::new(["ES.FUT"], "ohlcv-1m").with_snapshot_bars(10);
+    let mut events = Ticker::new(&server, sub)
+        .unwrap()
+        .with_backoff(backoff())
+        .events();
+
+    let mut book = BarBook::new(10);
+    let mut disconnects = 0;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while let Some(event) = events.next().await {
+            match event {
+                Event::Message(msg) => {
+                    book.apply(&msg);
+                }
+                Event::Disconnected { .. } if book.connections() == 2 => break,
+                Event::Disconnected { .. } => disconnects += 1,
+                Event::Failed { reason } => panic!("subscription refused: {}", reason),
+            }
+        }
+    })
+    .await
+    .expect("the ticker should reconnect");
+
+    assert_eq!(book.connections(), 2);
+    assert_eq!(disconnects, 1);
+
+    // The snapshot filled in minute 1's close without repeating minute 0
+    let bars: Vec<(u64, i64, bool)> = book
+        .bars("ES.FUT")
+        .map(|(ts, bar)| (ts / MINUTE_NS, bar.close_i64, bar.closed))
+        .collect();
+    assert_eq!(
+        bars,
+        [
+            (0, 4_501_000_000_000, true),
+            (1, 4_502_500_000_000, true),
+            (2, 4_503_000_000_000, false),
+        ]
+    );
+    assert_eq!(book.line(), "ES.FUT 4503.00 +3.00 [3 bars]");
+}
+
+#[tokio::test]
+async fn test_refused_subscription_ends() {
+    let server = serve().await;
+    let sub = Subscription::new(["ES.FUT"], "bogus");
+    let events: Vec<Event> = tokio::time::timeout(
+        Duration::from_secs(10),
+        Ticker::new(&server, sub)
+            .unwrap()
+            .with_backoff(backoff())
+            .events()
+            .collect(),
+    )
+    .await
+    .expect("a refused subscription shouldn't be retried");
+
+    assert!(matches!(
+        events.as_slice(),
+        [Event::Failed { reason }] if reason == "Unknown schema"
+    ));
+}
@@ -11,7 +11,8 @@ import {
   OhlcvRecord,
 } from './api';
 
-type Schema = 'trades' | 'ohlcv-1s' | 'ohlcv-1m';
+type Schema = 'trades' | 'ohlcv-1s' | 'ohlcv-1m' | 'cvd-1s' | 'cvd-1m';
+type BarSchema = Exclude<Schema, 'trades'>;
 
 function App() {
   const [loading, setLoading] = useState(false);
@@ -60,11 +61,11 @@ function App() {
 
   // Determine what to show in the main display area
   const showTradeTape = currentSchema === 'trades';
-  const showChart = currentSchema === 'ohlcv-1s' || currentSchema === 'ohlcv-1m';
+  const showChart = currentSchema !== 'trades';
 
   const historicalTrades = historicalData?.schema === 'trades' ? historicalData.data : [];
   const historicalOhlcv =
-    historicalData?.schema === 'ohlcv-1s' || historicalData?.schema === 'ohlcv-1m'
+    historicalData && historicalData.schema !== 'trades'
       ? (historicalData.data as OhlcvRecord[])
       : [];
 
@@ -132,7 +133,7 @@ function App() {
               {showChart && historicalOhlcv.length > 0 && (
                 <HistoricalChart
                   data={historicalOhlcv}
-                  schema={currentSchema as 'ohlcv-1s' | 'ohlcv-1m'}
+                  schema={currentSchema as BarSchema}
                 />
               )}
 
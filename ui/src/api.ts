@@ -5,11 +5,45 @@
 // Types matching backend shared crate
 export interface HistoricalRequest {
   symbols: string[];
-  schema: 'trades' | 'ohlcv-1s' | 'ohlcv-1m';
-  stype_in?: string;
+  schema:
+    | 'trades'
+    | 'ohlcv-1s'
+    | 'ohlcv-1m'
+    | 'cvd-1s'
+    | 'cvd-1m'
+    | `tickbar-${number}`
+    | `volbar-${number}`
+    | `dollarbar-${number}`;
+  stype_in?: SymbolType;
   start_rfc3339: string;
   end_rfc3339: string;
   limit?: number;
+  // Cap each symbol's records; `limit` is then shared evenly between symbols
+  limit_per_symbol?: number;
+  dataset?: string;
+  quote_at_trade?: boolean;
+  // Record fields to return, e.g. ['ts_event', 'close']; records are partial when set
+  fields?: string[];
+  // Bar alignment for server-built bars: 'epoch', 'session 08:30 America/Chicago', 'session cme', 'offset 30m'
+  align?: string;
+  // Reshape bars server-side: 'heikin-ashi' or 'renko-N' (bricks of N ticks)
+  transform?: string;
+  // Interleave records by time (default) or group them by symbol
+  order?: 'time' | 'symbol';
+  // Serve the finest resolution that fits the range in `limit` records
+  auto_resolution?: boolean;
+  // Upstream revision to serve, e.g. 'GLBX.MDP3@2024-05-31' (from a v2 response's meta.revision)
+  revision?: string;
+  // Send records with short field names (t, s, p, q, ...); the types below describe verbose records
+  compact?: boolean;
+  // Write prices and volumes as fixed-point numbers (default), exact strings, or decimal floats
+  prices_as?: 'number' | 'string' | 'decimal';
+}
+
+export interface QuoteAtTrade {
+  bid_px_i64: number;
+  ask_px_i64: number;
+  side: 'bid' | 'ask' | 'mid';
 }
 
 export interface TradeRecord {
@@ -17,6 +51,8 @@ export interface TradeRecord {
   symbol: string;
   price_i64: number;
   size_u32: number;
+  quote?: QuoteAtTrade;
+  sequence_u32?: number;
 }
 
 export interface OhlcvRecord {
@@ -29,16 +65,384 @@ export interface OhlcvRecord {
   volume_u64: number;
 }
 
-export type HistoricalResponse =
+export type SymbolType = 'parent' | 'continuous' | 'raw_symbol' | 'instrument_id';
+
+export type HistoricalResponse = (
   | { schema: 'trades'; data: TradeRecord[] }
   | { schema: 'ohlcv-1s'; data: OhlcvRecord[] }
-  | { schema: 'ohlcv-1m'; data: OhlcvRecord[] };
+  | { schema: 'ohlcv-1m'; data: OhlcvRecord[] }
+  // Cumulative volume delta in OHLCV layout (values scaled like prices)
+  | { schema: 'cvd-1s'; data: OhlcvRecord[] }
+  | { schema: 'cvd-1m'; data: OhlcvRecord[] }
+  // Tick, volume and dollar bars, closed when a threshold is reached
+  | { schema: `tickbar-${number}` | `volbar-${number}` | `dollarbar-${number}`; data: OhlcvRecord[] }
+) & {
+  // Duplicate records the server removed while assembling the response
+  duplicates_dropped?: number;
+  // Transform the bars were reshaped with, e.g. 'renko-4'
+  transform?: string;
+  // Resolution auto_resolution picked; 'ohlcv-1h' bars come as ohlcv-1m
+  resolution?: string;
+  // Set when the hourly bars cover only the start of the range
+  limited?: boolean;
+};
 
 export type LiveMessage =
   | { type: 'trade'; ts_event_unix_ns: number; symbol: string; price_i64: number; size_u32: number }
-  | { type: 'ohlcv'; ts_event_unix_ns: number; symbol: string; open_i64: number; high_i64: number; low_i64: number; close_i64: number; volume_u64: number }
+  | { type: 'trade_batch'; trades: TradeRecord[] }
+  | { type: 'ohlcv'; ts_event_unix_ns: number; symbol: string; open_i64: number; high_i64: number; low_i64: number; close_i64: number; volume_u64: number; bar_closed?: boolean; interval?: string }
+  | { type: 'metric'; symbol: string; name: string; value: number; window: string }
   | { type: 'error'; message: string }
-  | { type: 'connected'; symbols: string[]; schema: string };
+  | { type: 'symbol_mapping'; instrument_id: number; stype_in_symbol: string; stype_out_symbol: string }
+  | { type: 'roll_alert'; ts_event_unix_ns: number; root: string; from_symbol: string; to_symbol: string; from_volume: number; to_volume: number; window: string }
+  | { type: 'connected'; symbols: string[]; schema: string; connection_id?: number }
+  | ({ type: 'paper_order' } & PaperOrder)
+  | { type: 'paper_fill'; order_id: number; ts_event_unix_ns: number; symbol: string; side: OrderSide; qty: number; price_i64: number }
+  | ({ type: 'paper_position' } & PaperPosition)
+  | { type: 'position_pnl'; ts_event_unix_ns: number; symbol: string; qty: number; entry_price_i64: number; last_price_i64: number; pnl_i64: number }
+  | ({ type: 'calendar_event'; stage: 'upcoming' | 'released' } & CalendarEvent)
+  | { type: 'provider_status'; provider: string; health: 'up' | 'degraded' | 'down'; reason: string; at: string }
+  | { type: 'profile_delta'; symbol: string; trading_day: string; ts_event_unix_ns: number; reset: boolean; levels: ProfileLevel[] }
+  | ({ type: 'day_summary' } & DaySummary)
+  | { type: 'depth_snapshot'; ts_event_unix_ns: number; symbol: string; sequence_u64: number; reason: 'subscribe' | 'gap'; levels: DepthLevel[] }
+  | { type: 'depth_diff'; ts_event_unix_ns: number; symbol: string; sequence_u64: number; changes: { index: number; level: DepthLevel }[] }
+  | { type: 'rate_adjusted'; conflation_ms: number; previous_ms: number; lag_ms: number };
+
+export interface ValidationIssue {
+  field: string;
+  message: string;
+}
+
+export interface RequestValidation {
+  valid: boolean;
+  // The request as the server would serve it; only when valid
+  request?: HistoricalRequest;
+  datasets?: { dataset: string; symbols: string[] }[];
+  errors?: ValidationIssue[];
+}
+
+export interface CalendarEvent {
+  ts_event_unix_ns: number;
+  title: string;
+  country?: string;
+  impact?: string;
+}
+
+export interface ProfileLevel {
+  price_i64: number;
+  volume_u64: number;
+}
+
+// A symbol's developing volume profile this session, from /api/profile
+export interface VolumeProfile {
+  symbol: string;
+  trading_day: string;
+  from_unix_ns: number;
+  last_unix_ns: number;
+  volume_u64: number;
+  poc_i64: number | null;
+  levels: ProfileLevel[];
+}
+
+// A price against the previous session's close
+export interface DayChange {
+  previous_close_i64: number;
+  net_change_i64: number;
+  change_pct: number;
+}
+
+// A symbol's open, high, low, volume and VWAP this session, from /api/day-summary
+export interface DaySummary {
+  symbol: string;
+  trading_day: string;
+  from_unix_ns: number;
+  last_unix_ns: number;
+  open_i64: number;
+  high_i64: number;
+  low_i64: number;
+  last_i64: number;
+  volume_u64: number;
+  vwap_i64: number;
+  trades_u64: number;
+  // Left out until the previous close is known
+  day_change?: DayChange;
+}
+
+// One level of both sides of an mbp-10 book; an empty side is all zeros
+export interface DepthLevel {
+  bid_px_i64: number;
+  ask_px_i64: number;
+  bid_sz_u32: number;
+  ask_sz_u32: number;
+  bid_ct_u32: number;
+  ask_ct_u32: number;
+}
+
+// A symbol's book as built from depth_snapshot and depth_diff messages
+export interface DepthBook {
+  sequence_u64: number;
+  levels: DepthLevel[];
+}
+
+// Apply a depth message to `books`. A diff that doesn't follow the last
+// message applied for its symbol drops the book until the next snapshot;
+// returns whether the symbol's book is usable.
+export function applyDepth(
+  books: Map<string, DepthBook>,
+  msg: Extract<LiveMessage, { type: 'depth_snapshot' | 'depth_diff' }>,
+): boolean {
+  if (msg.type === 'depth_snapshot') {
+    books.set(msg.symbol, { sequence_u64: msg.sequence_u64, levels: [...msg.levels] });
+    return true;
+  }
+  const book = books.get(msg.symbol);
+  if (!book || msg.sequence_u64 !== book.sequence_u64 + 1) {
+    books.delete(msg.symbol);
+    return false;
+  }
+  for (const { index, level } of msg.changes) {
+    book.levels[index] = level;
+  }
+  book.sequence_u64 = msg.sequence_u64;
+  return true;
+}
+
+// One price level of a book rebuilt from market-by-order data
+export interface BookLevel {
+  price_i64: number;
+  size_u64: number;
+  orders_u32: number;
+}
+
+export interface BookView {
+  symbol: string;
+  ts_event_unix_ns: number;
+  // Best first
+  bids: BookLevel[];
+  asks: BookLevel[];
+  orders_u64: number;
+}
+
+// The books of a symbol's instruments at a time, from /api/book
+export interface BookResponse {
+  symbol: string;
+  at_unix_ns: number;
+  events_u64: number;
+  books: BookView[];
+}
+
+export interface ActivityCounts {
+  adds_u64: number;
+  cancels_u64: number;
+  trades_u64: number;
+  traded_volume_u64: number;
+  departures_u64: number;
+  avg_resting_ns_u64?: number;
+  // Left out when nothing traded
+  cancel_to_trade?: number;
+}
+
+export interface LevelActivity extends ActivityCounts {
+  price_i64: number;
+}
+
+// Order activity by price level, from /api/book/activity
+export interface OrderActivityReport {
+  symbol: string;
+  start_unix_ns: number;
+  end_unix_ns: number;
+  events_u64: number;
+  instruments: { symbol: string; totals: ActivityCounts; levels: LevelActivity[] }[];
+}
+
+// Where one side of a diff is fetched from
+export type DiffSource =
+  | { kind: 'provider'; dataset?: string }
+  | { kind: 'cache' }
+  | { kind: 'recording' };
+
+export interface DiffCounts {
+  left_records_u64: number;
+  right_records_u64: number;
+  count_delta_i64: number;
+  matched_u64: number;
+  missing_left_u64: number;
+  missing_right_u64: number;
+  price_mismatches_u64: number;
+  size_mismatches_u64: number;
+}
+
+export interface RecordDifference {
+  kind: 'missing_right' | 'missing_left' | 'mismatch';
+  symbol: string;
+  ts_event_unix_ns: number;
+  fields?: string[];
+  left?: TradeRecord | OhlcvRecord;
+  right?: TradeRecord | OhlcvRecord;
+}
+
+// One range as served by two sources, compared, from /api/diff
+export interface DiffReport {
+  schema: string;
+  left: DiffSource;
+  right: DiffSource;
+  identical: boolean;
+  totals: DiffCounts;
+  symbols: (DiffCounts & { symbol: string })[];
+  differences: RecordDifference[];
+  truncated: boolean;
+  limited: boolean;
+}
+
+export type ScenarioKind = 'volatility_spike' | 'flash_crash' | 'trend_day';
+
+// A regime to script into mock data, for POST /api/mock/scenarios
+export interface ScenarioRequest {
+  kind: ScenarioKind;
+  // RFC3339 or a time expression such as 'today 13:30 America/New_York'; defaults to now
+  at?: string;
+  duration_secs?: number;
+  // Volatility multiple for a spike; fraction of price for a crash or trend
+  magnitude?: number;
+  // Every symbol when left out
+  symbols?: string[];
+}
+
+// A scheduled mock scenario
+export interface Scenario {
+  id: number;
+  kind: ScenarioKind;
+  start_unix_ns: number;
+  end_unix_ns: number;
+  magnitude: number;
+  symbols?: string[];
+}
+
+// Bars kept for a symbol on ingest, from /api/rollups/:symbol
+export interface RollupBars {
+  symbol: string;
+  interval: string;
+  // Span every bar is held for; a bucket without a bar had no trades
+  covered_from_unix_ns?: number;
+  covered_to_unix_ns?: number;
+  bars: OhlcvRecord[];
+  day_change?: DayChange;
+}
+
+export interface Mover {
+  symbol: string;
+  since_unix_ns: number;
+  open_i64: number;
+  close_i64: number;
+  change_pct: number;
+  high_i64: number;
+  low_i64: number;
+  volume_u64: number;
+  day_change?: DayChange;
+}
+
+export type LeaderboardRank = 'change' | 'volume';
+
+export interface Leaderboard {
+  interval: string;
+  since_unix_ns: number;
+  rank: LeaderboardRank;
+  movers: Mover[];
+}
+
+// A symbol's latest trades, newest first, from /api/tape
+export interface Tape {
+  symbol: string;
+  source: 'recording' | 'cache' | 'provider';
+  trades: TradeRecord[];
+  // Pass as sinceNs to poll for later trades
+  latest_unix_ns: number | null;
+  skipped: number;
+  more: boolean;
+}
+
+// A day of one symbol's recorded live data, from /api/recordings
+export interface RecordingPartition {
+  schema: string;
+  symbol: string;
+  day: string;
+  format: 'ndjson' | 'parquet';
+  path: string;
+  bytes: number;
+  // Known once compacted to Parquet
+  records?: number;
+  start_unix_ns?: number;
+  end_unix_ns?: number;
+}
+
+// How /api/import maps a file's columns onto records
+export interface ImportSpec {
+  symbol: string;
+  schema: 'trades' | 'ohlcv-1s' | 'ohlcv-1m';
+  columns?: Partial<Record<
+    'ts_event' | 'price' | 'size' | 'open' | 'high' | 'low' | 'close' | 'volume',
+    string
+  >>;
+  time_format?: 'auto' | 'rfc3339' | 'unix_s' | 'unix_ms' | 'unix_us' | 'unix_ns';
+  fixed_point?: boolean;
+  format?: 'csv' | 'parquet';
+  delimiter?: string;
+}
+
+export interface ImportReport {
+  symbol: string;
+  schema: string;
+  records: number;
+  skipped: number;
+  errors?: string[];
+  partitions: RecordingPartition[];
+}
+
+// A DBN file uploaded for viewing, from /api/uploads
+export interface UploadInfo {
+  id: string;
+  file_name: string;
+  dataset: string;
+  schema: string;
+  symbols: string[];
+  records: number;
+  bytes: number;
+  start_unix_ns?: number;
+  end_unix_ns?: number;
+  expires_rfc3339: string;
+}
+
+export type OrderSide = 'buy' | 'sell';
+
+export interface PaperOrder {
+  order_id: number;
+  symbol: string;
+  side: OrderSide;
+  qty: number;
+  // Absent for market orders
+  limit_price_i64?: number;
+  status: 'open' | 'filled' | 'cancelled';
+  submitted_at: string;
+}
+
+export interface PaperPosition {
+  symbol: string;
+  qty: number;
+  avg_price_i64: number;
+  last_price_i64: number;
+  realized_pnl_i64: number;
+  unrealized_pnl_i64: number;
+}
+
+// Part of a message too large for one WebSocket frame; concatenating `data`
+// of parts 0..total yields the original message JSON
+interface FragmentMessage {
+  type: 'fragment';
+  id: number;
+  part: number;
+  total: number;
+  data: string;
+}
 
 // Price conversion utilities
 // DataBento uses fixed-point 1e-9 format
@@ -70,17 +474,66 @@ export function formatTimestampFull(tsNs: number): string {
   return date.toISOString();
 }
 
+export interface WireChange {
+  version: number;
+  changes: string[];
+}
+
+export interface ApiVersionInfo {
+  version: number;
+  prefix: string;
+  deprecated: boolean;
+  // RFC 3339; the version answers 410 Gone from then on
+  sunset?: string;
+}
+
+// A named capability and the wire version it arrived in
+export interface WireFeature {
+  name: string;
+  since: number;
+}
+
+// Server and wire format version, from /api/version
+export interface VersionInfo {
+  server_version: string;
+  wire_version: number;
+  changelog: WireChange[];
+  api_versions: ApiVersionInfo[];
+  // Missing from servers before wire version 9
+  features?: WireFeature[];
+}
+
+// Wire format version this UI was written against; live message types
+// added after it aren't sent to it
+export const WIRE_VERSION = 26;
+
+// Most live messages held partly reassembled from fragments
+const MAX_PENDING_FRAGMENTED = 16;
+
+// Tenant the UI talks to, served under /t/<tenant>; unset uses the default API
+const BASE = import.meta.env.VITE_TENANT ? `/t/${import.meta.env.VITE_TENANT}` : '';
+
 // API functions
 export async function fetchHealth(): Promise<string> {
-  const response = await fetch('/api/health');
+  const response = await fetch(`${BASE}/api/health`);
   return response.text();
 }
 
-export async function fetchHistorical(request: HistoricalRequest): Promise<HistoricalResponse> {
-  const response = await fetch('/api/historical', {
+export async function fetchVersion(): Promise<VersionInfo> {
+  const response = await fetch(`${BASE}/api/version`);
+  return response.json();
+}
+
+// Pass a requestId to be able to cancel the fetch with cancelRequest
+export async function fetchHistorical(
+  request: HistoricalRequest,
+  requestId?: string
+): Promise<HistoricalResponse> {
+  const response = await fetch(`${BASE}/api/historical`, {
     method: 'POST',
     headers: {
       'Content-Type': 'application/json',
+      ...(requestId ? { 'X-Request-Id': requestId } : {}),
     },
     body: JSON.stringify(request),
   });
@@ -93,28 +546,453 @@ export async function fetchHistorical(request: HistoricalRequest): Promise<Histo
   return response.json();
 }
 
+// Stop a request still in flight; it fails with status 499
+export async function cancelRequest(requestId: string): Promise<void> {
+  const response = await fetch(`${BASE}/api/requests/${encodeURIComponent(requestId)}`, {
+    method: 'DELETE',
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to cancel request');
+  }
+}
+
+// One result per request of a batch, in order
+export type BatchResult =
+  | { ok: HistoricalResponse }
+  | { error: { error: string; code: number } };
+
+export async function fetchHistoricalBatch(requests: HistoricalRequest[]): Promise<BatchResult[]> {
+  const response = await fetch(`${BASE}/api/historical/batch`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify(requests),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch historical batch');
+  }
+
+  return response.json();
+}
+
+// Several schemas of the same symbols and range, keyed by schema
+export async function fetchHistoricalCombined(
+  request: Omit<HistoricalRequest, 'schema'> & { schemas: HistoricalRequest['schema'][] }
+): Promise<Record<string, BatchResult>> {
+  const response = await fetch(`${BASE}/api/historical/combined`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch combined historical data');
+  }
+
+  return response.json();
+}
+
+// Check a request without fetching it, e.g. while a query form is edited
+export async function validateHistorical(request: HistoricalRequest): Promise<RequestValidation> {
+  const response = await fetch(`${BASE}/api/historical/validate`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to validate request');
+  }
+
+  return response.json();
+}
+
+export async function fetchVolumeProfile(symbol: string): Promise<VolumeProfile> {
+  const response = await fetch(`${BASE}/api/profile/${encodeURIComponent(symbol)}`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch volume profile');
+  }
+
+  return response.json();
+}
+
+// Day summaries of `symbols`, or of every symbol with one when empty
+export async function fetchDaySummaries(symbols: string[] = []): Promise<DaySummary[]> {
+  const params = new URLSearchParams();
+  if (symbols.length > 0) {
+    params.set('symbols', symbols.join(','));
+  }
+  const response = await fetch(`${BASE}/api/day-summary?${params}`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch day summaries');
+  }
+
+  return response.json();
+}
+
+// Economic calendar releases in a range such as 'today..now+7d'
+export async function fetchCalendar(range: string): Promise<CalendarEvent[]> {
+  const response = await fetch(`${BASE}/api/calendar?${new URLSearchParams({ range })}`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch calendar');
+  }
+
+  return response.json();
+}
+
+// The latest kept bars of a symbol, e.g. fetchRollups('ES.FUT', '1m', 500)
+export async function fetchRollups(
+  symbol: string,
+  interval?: string,
+  bars?: number
+): Promise<RollupBars> {
+  const params = new URLSearchParams();
+  if (interval) params.set('interval', interval);
+  if (bars !== undefined) params.set('bars', String(bars));
+  const response = await fetch(`${BASE}/api/rollups/${encodeURIComponent(symbol)}?${params}`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch rollups');
+  }
+
+  return response.json();
+}
+
+// Symbols that moved most over a window such as '1h'
+export async function fetchLeaderboard(
+  window: string,
+  rank: LeaderboardRank = 'change',
+  limit = 10
+): Promise<Leaderboard> {
+  const params = new URLSearchParams({ window, rank, limit: String(limit) });
+  const response = await fetch(`${BASE}/api/leaderboard?${params}`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch leaderboard');
+  }
+
+  return response.json();
+}
+
+export async function fetchTape(
+  symbol: string,
+  minSize = 0,
+  sinceNs?: number | null,
+  limit = 100
+): Promise<Tape> {
+  const params = new URLSearchParams({
+    symbol,
+    min_size: String(minSize),
+    limit: String(limit),
+  });
+  if (sinceNs != null) {
+    params.set('since_ns', String(sinceNs));
+  }
+  const response = await fetch(`${BASE}/api/tape?${params}`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch tape');
+  }
+
+  return response.json();
+}
+
+// `symbol`'s books at `at` (RFC 3339 or a time expression), `depth`
+// levels a side
+export async function fetchBook(symbol: string, at = 'now', depth = 10): Promise<BookResponse> {
+  const params = new URLSearchParams({ symbol, at, depth: String(depth) });
+  const response = await fetch(`${BASE}/api/book?${params}`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch book');
+  }
+
+  return response.json();
+}
+
+export interface BookSampleRequest {
+  symbol: string;
+  stype_in?: SymbolType;
+  start_rfc3339: string;
+  end_rfc3339: string;
+  // e.g. '1s' or '5m' (default '1s')
+  interval?: string;
+  depth?: number;
+}
+
+// `request`'s book samples, passed to `onSample` as the NDJSON stream
+// arrives. A replay that fails part way rejects after the samples sent.
+export async function fetchBookSamples(
+  request: BookSampleRequest,
+  onSample: (sample: BookResponse) => void
+): Promise<void> {
+  const response = await fetch(`${BASE}/api/book/sample`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok || !response.body) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to sample books');
+  }
+
+  const reader = response.body.pipeThrough(new TextDecoderStream()).getReader();
+  let buffered = '';
+  for (;;) {
+    const { done, value } = await reader.read();
+    buffered += value ?? '';
+    const lines = buffered.split('\n');
+    buffered = done ? '' : lines.pop() ?? '';
+    for (const line of lines.filter((line) => line.trim())) {
+      const parsed = JSON.parse(line) as BookResponse | { error: string; code: number };
+      if ('error' in parsed) {
+        throw new Error(parsed.error);
+      }
+      onSample(parsed);
+    }
+    if (done) {
+      return;
+    }
+  }
+}
+
+export async function fetchOrderActivity(
+  request: Omit<BookSampleRequest, 'interval' | 'depth'>
+): Promise<OrderActivityReport> {
+  const response = await fetch(`${BASE}/api/book/activity`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch order activity');
+  }
+
+  return response.json();
+}
+
+export async function fetchDiff(
+  request: HistoricalRequest,
+  left: DiffSource,
+  right: DiffSource
+): Promise<DiffReport> {
+  const response = await fetch(`${BASE}/api/diff`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ request, left, right }),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to diff sources');
+  }
+
+  return response.json();
+}
+
+// Scheduled mock scenarios; fails outside mock mode
+export async function fetchScenarios(): Promise<Scenario[]> {
+  const response = await fetch(`${BASE}/api/mock/scenarios`);
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch scenarios');
+  }
+
+  return response.json();
+}
+
+export async function scheduleScenario(request: ScenarioRequest): Promise<Scenario> {
+  const response = await fetch(`${BASE}/api/mock/scenarios`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to schedule scenario');
+  }
+
+  return response.json();
+}
+
+// Remove one scenario, or every scenario when no id is given
+export async function removeScenarios(id?: number): Promise<void> {
+  const path = id === undefined ? '' : `/${id}`;
+  const response = await fetch(`${BASE}/api/mock/scenarios${path}`, {
+    method: 'DELETE',
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to remove scenarios');
+  }
+}
+
+// Recorded data for a historical request, read from disk
+export async function fetchRecorded(request: HistoricalRequest): Promise<HistoricalResponse> {
+  const response = await fetch(`${BASE}/api/recordings/query`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to query recordings');
+  }
+
+  return response.json();
+}
+
+// Upload a CSV or Parquet file into the local store
+export async function importFile(file: File, spec: ImportSpec): Promise<ImportReport> {
+  const body = new FormData();
+  body.append('spec', JSON.stringify(spec));
+  body.append('file', file);
+  const response = await fetch(`${BASE}/api/import`, { method: 'POST', body });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to import file');
+  }
+
+  return response.json();
+}
+
+// Upload a .dbn or .dbn.zst file to view
+export async function uploadDbn(file: File): Promise<UploadInfo> {
+  const body = new FormData();
+  body.append('file', file);
+  const response = await fetch(`${BASE}/api/uploads`, { method: 'POST', body });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to upload file');
+  }
+
+  return response.json();
+}
+
+// Historical data served from an uploaded file
+export async function fetchUploaded(id: string, request: HistoricalRequest): Promise<HistoricalResponse> {
+  const response = await fetch(`${BASE}/api/uploads/${encodeURIComponent(id)}/historical`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify(request),
+  });
+
+  if (!response.ok) {
+    const error = await response.json();
+    throw new Error(error.error || 'Failed to fetch uploaded data');
+  }
+
+  return response.json();
+}
+
+// Close codes the server ends a live socket with after an error
+export const CLOSE_CODES = {
+  NORMAL: 1000,
+  BAD_PARAMS: 4400,
+  AUTH_FAILED: 4401,
+  NOT_ENTITLED: 4403,
+  QUOTA_EXCEEDED: 4429,
+  UPSTREAM_DOWN: 4502,
+} as const;
+
+// Whether reconnecting with the same parameters may succeed; 4400-4499
+// mean the subscription was refused and would be again
+export function isRetryableClose(code: number): boolean {
+  return code < 4400 || code >= 4500;
+}
+
 // WebSocket connection for live data
 export function connectLive(
   symbols: string[],
   schema: string,
   onMessage: (msg: LiveMessage) => void,
   onError: (error: Event) => void,
-  onClose: () => void
+  onClose: (event: CloseEvent) => void,
+  profile = false,
+  daySummary = false,
+  // Levels a side of an mbo subscription's books
+  bookDepth?: number,
+  // Have the server conflate trades and bars while this client falls behind
+  adaptive = true
 ): WebSocket {
   const params = new URLSearchParams({
     symbols: symbols.join(','),
     schema,
+    wire_version: String(WIRE_VERSION),
   });
+  if (profile) {
+    params.set('profile', 'true');
+  }
+  if (daySummary) {
+    params.set('day_summary', 'true');
+  }
+  if (bookDepth != null) {
+    params.set('book_depth', String(bookDepth));
+  }
+  if (adaptive) {
+    params.set('adaptive', 'true');
+  }
 
   const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-  const wsUrl = `${protocol}//${window.location.host}/ws/live?${params}`;
+  const wsUrl = `${protocol}//${window.location.host}${BASE}/ws/live?${params}`;
 
   const ws = new WebSocket(wsUrl);
+  const fragments = new Map<number, string[]>();
 
   ws.onmessage = (event) => {
     try {
-      const msg = JSON.parse(event.data) as LiveMessage;
-      onMessage(msg);
+      const msg = JSON.parse(event.data) as LiveMessage | FragmentMessage;
+      if (msg.type !== 'fragment') {
+        onMessage(msg);
+        return;
+      }
+
+      // Hold at most MAX_PENDING_FRAGMENTED messages, dropping the oldest
+      if (!fragments.has(msg.id) && fragments.size >= MAX_PENDING_FRAGMENTED) {
+        fragments.delete(fragments.keys().next().value as number);
+      }
+      const parts = fragments.get(msg.id) ?? [];
+      parts.push(msg.data);
+      if (parts.length < msg.total) {
+        fragments.set(msg.id, parts);
+        return;
+      }
+      fragments.delete(msg.id);
+      onMessage(JSON.parse(parts.join('')) as LiveMessage);
     } catch (e) {
       console.error('Failed to parse WebSocket message:', e);
     }
@@ -5,7 +5,7 @@ import { OhlcvRecord, formatPriceNumber } from '../api';
 
 interface HistoricalChartProps {
   data: OhlcvRecord[];
-  schema: 'ohlcv-1s' | 'ohlcv-1m';
+  schema: 'ohlcv-1s' | 'ohlcv-1m' | 'cvd-1s' | 'cvd-1m';
 }
 
 export function HistoricalChart({ data, schema }: HistoricalChartProps) {
@@ -33,7 +33,7 @@ export function HistoricalChart({ data, schema }: HistoricalChartProps) {
       timeScale: {
         borderColor: '#374151',
         timeVisible: true,
-        secondsVisible: schema === 'ohlcv-1s',
+        secondsVisible: schema.endsWith('-1s'),
       },
       width: containerRef.current.clientWidth,
       height: 400,
@@ -92,7 +92,8 @@ export function HistoricalChart({ data, schema }: HistoricalChartProps) {
   }
 
   const symbol = data[0]?.symbol || 'Unknown';
-  const schemaLabel = schema === 'ohlcv-1s' ? '1-Second' : '1-Minute';
+  const schemaLabel =
+    (schema.startsWith('cvd') ? 'CVD ' : '') + (schema.endsWith('-1s') ? '1-Second' : '1-Minute');
 
   return (
     <div className="bg-gray-800 rounded-lg overflow-hidden">
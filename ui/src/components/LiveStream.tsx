@@ -12,10 +12,12 @@ export function LiveStream({ symbols, schema, onTrade, onDisconnect }: LiveStrea
   const wsRef = useRef<WebSocket | null>(null);
   const [status, setStatus] = useState<'connecting' | 'connected' | 'error'>('connecting');
   const [messageCount, setMessageCount] = useState(0);
+  const [conflationMs, setConflationMs] = useState(0);
 
   useEffect(() => {
     setStatus('connecting');
     setMessageCount(0);
+    setConflationMs(0);
 
     const handleMessage = (msg: LiveMessage) => {
       if (msg.type === 'connected') {
@@ -28,6 +30,8 @@ export function LiveStream({ symbols, schema, onTrade, onDisconnect }: LiveStrea
           price_i64: msg.price_i64,
           size_u32: msg.size_u32,
         });
+      } else if (msg.type === 'rate_adjusted') {
+        setConflationMs(msg.conflation_ms);
       } else if (msg.type === 'error') {
         console.error('WebSocket error message:', msg.message);
         setStatus('error');
@@ -74,7 +78,9 @@ export function LiveStream({ symbols, schema, onTrade, onDisconnect }: LiveStrea
           <div className={`w-2 h-2 rounded-full ${statusColor} animate-pulse`} />
           <span className="text-sm text-gray-300">{statusText}</span>
         </div>
-        <span className="text-sm text-gray-400">{messageCount} msgs</span>
+        <span className="text-sm text-gray-400">
+          {messageCount} msgs{conflationMs > 0 && ` (conflated ${conflationMs}ms)`}
+        </span>
       </div>
 
       <div className="mt-2 text-xs text-gray-500">
@@ -1,7 +1,7 @@
 import { useState, FormEvent } from 'react';
 import { HistoricalRequest } from '../api';
 
-type Schema = 'trades' | 'ohlcv-1s' | 'ohlcv-1m';
+type Schema = 'trades' | 'ohlcv-1s' | 'ohlcv-1m' | 'cvd-1s' | 'cvd-1m';
 
 interface SymbolFormProps {
   onFetchHistorical: (request: HistoricalRequest) => void;
@@ -120,6 +120,8 @@ export function SymbolForm({
           <option value="trades">Trades</option>
           <option value="ohlcv-1s">OHLCV 1-Second</option>
           <option value="ohlcv-1m">OHLCV 1-Minute</option>
+          <option value="cvd-1s">CVD 1-Second</option>
+          <option value="cvd-1m">CVD 1-Minute</option>
         </select>
       </div>
 
@@ -1 +1,6 @@
 /// <reference types="vite/client" />
+
+interface ImportMetaEnv {
+  // Tenant to talk to (served under /t/<tenant>); unset uses the default API
+  readonly VITE_TENANT?: string;
+}
@@ -15,6 +15,12 @@ export default defineConfig({
         target: 'ws://127.0.0.1:3001',
         ws: true,
       },
+      // Tenant APIs (VITE_TENANT), REST and WebSocket alike
+      '^/t/': {
+        target: 'http://127.0.0.1:3001',
+        changeOrigin: true,
+        ws: true,
+      },
     },
   },
 })